@@ -1,10 +1,38 @@
 use clap::Parser;
+use cmap::compat::evmap as evmap_compat;
 use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
     fmt::Debug,
     hash::Hash,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
     time::{Duration, Instant},
 };
 
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 struct NotClone(i32);
 
@@ -31,6 +59,124 @@ enum Args {
         #[clap(value_enum)]
         mode: Mode,
     },
+
+    BulkLoad {
+        #[clap(long, default_value_t = 1_000_000)]
+        count: u32,
+    },
+
+    ReaderClone {
+        #[clap(long, default_value_t = 1_000_000)]
+        iterations: u32,
+    },
+
+    Sharded {
+        #[clap(long, default_value_t = 8)]
+        threads: u32,
+        #[clap(long, default_value_t = 100_000)]
+        count: u32,
+    },
+
+    SliceWindows {
+        #[clap(long, default_value_t = 100_000)]
+        iterations: u32,
+        #[clap(long, default_value_t = 64)]
+        windows: usize,
+        #[clap(long, default_value_t = 256)]
+        window_size: usize,
+    },
+
+    Padding {
+        #[clap(long, default_value_t = 4)]
+        readers: u32,
+        #[clap(long, default_value_t = 1.0)]
+        timeout: f32,
+    },
+
+    SwapNoReaders {
+        #[clap(long, default_value_t = 10_000_000)]
+        iterations: u32,
+    },
+
+    PostSwapWarm {
+        #[clap(long, default_value_t = 16)]
+        megabytes: usize,
+        #[clap(long, default_value_t = 4)]
+        readers: u32,
+        #[clap(long, default_value_t = 200)]
+        iterations: u32,
+    },
+
+    ReaderLatency {
+        #[clap(long, default_value_t = 4)]
+        readers: u32,
+        #[clap(long, default_value_t = 100_000)]
+        swaps_per_sec: u32,
+        #[clap(long, default_value_t = 10_000)]
+        reads_per_sec: u32,
+        #[clap(long, default_value_t = 1.0)]
+        timeout: f32,
+    },
+
+    WaitBudget {
+        #[clap(long, default_value_t = 2)]
+        readers: u32,
+        #[clap(long, default_value_t = 10_000)]
+        iterations: u32,
+        #[clap(long, default_value_t = 5)]
+        hold_us: u64,
+    },
+
+    WaitStrategies {
+        #[clap(long, default_value_t = 2)]
+        readers: u32,
+        #[clap(long, default_value_t = 2_000)]
+        iterations: u32,
+    },
+
+    Watch {
+        #[clap(long, default_value_t = 1_000_000)]
+        count: u32,
+        #[clap(long, default_value_t = 1000)]
+        watchers: u32,
+        #[clap(long, default_value_t = 100)]
+        publish_every: u32,
+    },
+
+    LazyStartup {
+        #[clap(long, default_value_t = 1_000_000)]
+        count: u32,
+    },
+
+    IntMap {
+        #[clap(long, default_value_t = 100_000)]
+        count: u32,
+        #[clap(long, default_value_t = 10)]
+        rounds: u32,
+    },
+
+    DiffSync {
+        #[clap(long, default_value_t = 1_000_000)]
+        count: u32,
+        #[clap(long, default_value_t = 100)]
+        changes: u32,
+        #[clap(long, default_value_t = 1000)]
+        rounds: u32,
+    },
+
+    SharedValue {
+        #[clap(long, default_value_t = 10_000)]
+        count: u32,
+        #[clap(long, default_value_t = 4096)]
+        size: usize,
+    },
+
+    BoxedOps {
+        #[clap(long, default_value_t = 100_000)]
+        count: u32,
+        #[clap(long, default_value_t = 10)]
+        rounds: u32,
+    },
 }
 
 #[derive(Debug, Clone, Copy, clap::ArgEnum)]
@@ -38,6 +184,44 @@ enum Args {
 enum Mode {
     CMap,
     EVMap,
+    /// the evmap arm, run against `cmap::compat::evmap`
+    EVMapCompat,
+}
+
+/// the `ev-map` arm of `run-with-config`, where `$evmap` is either evmap or
+/// `cmap::compat::evmap`, which only differ in the import
+macro_rules! run_evmap {
+    ($evmap:ident, $reader_count:expr, $write_count:expr, $timeout:expr) => {{
+        let (mut map, reader) = $evmap::new();
+
+        map.publish();
+
+        for _ in 0..$reader_count {
+            let reader = reader.clone();
+
+            std::thread::spawn(move || {
+                reader.enter();
+            });
+        }
+
+        let timeout = Duration::from_secs_f32($timeout);
+        let end = Instant::now() + timeout;
+        let mut iter: u64 = 0;
+        loop {
+            iter += 1;
+            for i in 0..$write_count {
+                map.insert(i, i);
+            }
+            map.purge();
+
+            map.publish();
+            if end <= Instant::now() {
+                break;
+            }
+        }
+
+        (iter, map, reader)
+    }};
 }
 
 fn parse() -> Args {
@@ -64,7 +248,7 @@ fn main() {
                 let write_count_s = write_count.to_string();
                 for reader_count in min_readers..=max_readers.unwrap_or(min_readers) {
                     let reader_count = reader_count.to_string();
-                    for mode in ["c-map", "ev-map"] {
+                    for mode in ["c-map", "ev-map", "ev-map-compat"] {
                         eprint!("run reader_count={reader_count}, write_count={write_count_s}, mode={mode}");
                         let output = std::process::Command::new(&program)
                             .args([
@@ -131,35 +315,788 @@ fn main() {
             timeout,
             mode: Mode::EVMap,
         } => {
-            let (mut map, reader) = evmap::new();
+            let (iter, _, _) = run_evmap!(evmap, reader_count, write_count, timeout);
+            print!("{}", iter);
+        }
+        Args::RunWithConfig {
+            reader_count,
+            write_count,
+            timeout,
+            mode: Mode::EVMapCompat,
+        } => {
+            let (iter, mut map, reader) =
+                run_evmap!(evmap_compat, reader_count, write_count, timeout);
+            assert_compat_matches_multimap(&mut map, &reader, write_count);
+            print!("{}", iter);
+        }
+        Args::BulkLoad { count } => {
+            let items = (0..count).map(|i| (i, i)).collect::<Vec<_>>();
 
+            let start = Instant::now();
+            let mut map = cmap::CBTreeMap::<_, _>::new();
+            for &(key, value) in &items {
+                map.insert(key, value);
+            }
+            map.force_publish();
+            map.force_publish();
+            println!("per-insert\t{:?}", start.elapsed());
+
+            let start = Instant::now();
+            let mut map = cmap::CBTreeMap::<_, _>::new();
+            map.bulk_load_sorted(items.clone());
+            map.force_publish();
+            map.force_publish();
+            println!("bulk-op\t{:?}", start.elapsed());
+
+            let start = Instant::now();
+            let map = cmap::CBTreeMap::<_, _>::from_sorted_iter(items);
+            println!("constructor\t{:?}", start.elapsed());
+            drop(map);
+        }
+        Args::ReaderClone { iterations } => {
+            let mut map = cmap::CMap::<u32, u32>::new();
+            map.insert(0, 0);
             map.publish();
 
-            for _ in 0..reader_count {
-                let reader = reader.clone();
+            let mut parent = map.reader();
 
-                std::thread::spawn(move || {
-                    reader.enter();
-                });
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+            let start = Instant::now();
+            for _ in 0..iterations {
+                let guard = parent.load();
+                std::hint::black_box(&*guard);
+            }
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+            println!("single-reader\t{:?}\t{allocations}", start.elapsed());
+
+            let mut child = parent.clone();
+
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+            let start = Instant::now();
+            for _ in 0..iterations {
+                let a = parent.load();
+                let b = child.load();
+                std::hint::black_box((&*a, &*b));
+            }
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+            println!("parent-and-child\t{:?}\t{allocations}", start.elapsed());
+        }
+        Args::Sharded { threads, count } => {
+            let map = Mutex::new(cmap::CMap::<u32, u32>::new());
+            let start = Instant::now();
+            insert_from_threads(threads, count, |key| map.lock().unwrap().insert(key, key));
+            println!("single-insert\t{:?}", start.elapsed());
+            let mut map = map.into_inner().unwrap();
+            map.publish();
+            map.insert(0, 0);
+            let start = Instant::now();
+            map.publish();
+            println!("single-publish\t{:?}", start.elapsed());
+
+            // each shard has its own lock, so the threads write to the shards concurrently
+            let map = cmap::ShardedCMap::<u32, u32>::new();
+            let start = Instant::now();
+            insert_from_threads(threads, count, |key| map.insert(key, key));
+            println!("sharded-insert\t{:?}", start.elapsed());
+            map.publish();
+            map.insert(0, 0);
+            let start = Instant::now();
+            map.publish();
+            println!("sharded-publish\t{:?}", start.elapsed());
+        }
+        Args::SliceWindows {
+            iterations,
+            windows,
+            window_size,
+        } => {
+            let len = windows * window_size;
+            let shared = dbuf::ptrs::alloc::Owned::<dbuf::strategy::TrackingStrategy, _>::new(
+                dbuf::raw::Shared::from_raw_parts(
+                    Default::default(),
+                    dbuf::raw::RawDBuf::new(vec![1.0f32; len], vec![1.0f32; len]),
+                ),
+            );
+            let writer = dbuf::raw::Writer::new(shared);
+            let mut reader = writer.reader();
+
+            // each window needs a new guard, since `map` consumes it
+            let start = Instant::now();
+            for _ in 0..iterations {
+                for window in 0..windows {
+                    let range = window * window_size..(window + 1) * window_size;
+                    let guard = reader.get().map(|buffer| &buffer[range]);
+                    std::hint::black_box(guard.iter().sum::<f32>());
+                }
             }
+            println!("map-per-window\t{:?}", start.elapsed());
 
+            let start = Instant::now();
+            for _ in 0..iterations {
+                let guard = reader.get().map(Vec::as_slice);
+                for window in guard.chunks(window_size) {
+                    std::hint::black_box(window.iter().sum::<f32>());
+                }
+            }
+            println!("borrowed-chunks\t{:?}", start.elapsed());
+
+            let start = Instant::now();
+            for _ in 0..iterations {
+                let guard = reader.get().map(Vec::as_slice);
+                for window in 0..windows {
+                    let range = window * window_size..(window + 1) * window_size;
+                    let window = guard.get(range).unwrap();
+                    std::hint::black_box(window.iter().sum::<f32>());
+                }
+            }
+            println!("borrowed-get\t{:?}", start.elapsed());
+        }
+        Args::Padding { readers, timeout } => {
             let timeout = Duration::from_secs_f32(timeout);
-            let end = Instant::now() + timeout;
-            let mut iter: u64 = 0;
-            loop {
-                iter += 1;
-                for i in 0..write_count {
+            let reads = read_with_concurrent_writer::<dbuf::raw::RawDBuf<u64>>(readers, timeout);
+            println!("unpadded\t{reads}");
+            let reads =
+                read_with_concurrent_writer::<dbuf::raw::PaddedRawDBuf<u64>>(readers, timeout);
+            println!("padded\t{reads}");
+        }
+        Args::SwapNoReaders { iterations } => {
+            let mut writer = dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::<
+                dbuf::strategy::HazardStrategy,
+                _,
+            >::from_buffers(0u64, 0));
+            let start = Instant::now();
+            for _ in 0..iterations {
+                writer.swap_buffers();
+            }
+            println!("hazard\t{:?}", start.elapsed());
+
+            let mut writer = dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::<
+                dbuf::strategy::TrackingStrategy,
+                _,
+            >::from_buffers(0u64, 0));
+            let start = Instant::now();
+            for _ in 0..iterations {
+                writer.swap_buffers();
+            }
+            println!("tracking\t{:?}", start.elapsed());
+        }
+        Args::PostSwapWarm {
+            megabytes,
+            readers,
+            iterations,
+        } => {
+            for warm in [false, true] {
+                let (swap, write) =
+                    first_write_after_swap(megabytes << 20, readers, iterations, warm);
+                let name = if warm { "warm" } else { "cold" };
+                println!(
+                    "{name}\tswap {:?}\tfirst write {:?}\ttotal {:?}",
+                    swap / iterations,
+                    write / iterations,
+                    (swap + write) / iterations
+                );
+            }
+        }
+        Args::ReaderLatency {
+            readers,
+            swaps_per_sec,
+            reads_per_sec,
+            timeout,
+        } => {
+            let mut latencies = read_latency_with_swapping_writer(
+                readers,
+                Duration::from_secs(1) / swaps_per_sec,
+                Duration::from_secs(1) / reads_per_sec,
+                Duration::from_secs_f32(timeout),
+            );
+            latencies.sort_unstable();
+            let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+            println!(
+                "hazard	reads {}	p50 {:?}	p99 {:?}	max {:?}",
+                latencies.len(),
+                percentile(50),
+                percentile(99),
+                percentile(100),
+            );
+        }
+        Args::WaitBudget {
+            readers,
+            iterations,
+            hold_us,
+        } => {
+            // park immediately, the default budget, and spin until the backoff saturates
+            for budget in [0, dbuf::wait::AdaptiveWait::DEFAULT_SPIN_BUDGET, 16] {
+                let (mut latencies, pauses) = swap_latency_with_wait_budget(
+                    budget,
+                    readers,
+                    iterations,
+                    Duration::from_micros(hold_us),
+                );
+                latencies.sort_unstable();
+                let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+                println!(
+                    "budget {budget}	p50 {:?}	p99 {:?}	pauses {}",
+                    percentile(50),
+                    percentile(99),
+                    pauses / u64::from(iterations),
+                );
+            }
+        }
+        Args::WaitStrategies {
+            readers,
+            iterations,
+        } => {
+            // an instant get, a short critical section, and a reader which does real work
+            for hold in [
+                Duration::ZERO,
+                Duration::from_micros(1),
+                Duration::from_millis(1),
+            ] {
+                // a millisecond hold makes each swap take at least a millisecond
+                let iterations = if hold >= Duration::from_millis(1) {
+                    iterations.min(200)
+                } else {
+                    iterations
+                };
+                println!("hold {hold:?}");
+                report_publish_latency(
+                    "spin",
+                    publish_latency_with_strategy::<dbuf::profiles::LowLatencyWriter>(
+                        readers, iterations, hold,
+                    ),
+                );
+                report_publish_latency(
+                    "yield",
+                    publish_latency_with_strategy::<dbuf::profiles::BriefReads>(
+                        readers, iterations, hold,
+                    ),
+                );
+                report_publish_latency(
+                    "adaptive",
+                    publish_latency_with_strategy::<
+                        dbuf::strategy::HazardStrategy<dbuf::wait::AdaptiveWait>,
+                    >(readers, iterations, hold),
+                );
+                report_publish_latency(
+                    "park",
+                    publish_latency_with_strategy::<
+                        dbuf::strategy::HazardStrategy<dbuf::wait::ThreadParker>,
+                    >(readers, iterations, hold),
+                );
+            }
+        }
+        Args::Watch {
+            count,
+            watchers,
+            publish_every,
+        } => {
+            for watchers in [0, watchers] {
+                let mut map = cmap::CMap::<u32, u32>::new();
+                // none of the watched keys are inserted
+                let _watchers = (0..watchers)
+                    .map(|i| map.watch_key(count + i))
+                    .collect::<Vec<_>>();
+
+                let start = Instant::now();
+                for i in 0..count {
                     map.insert(i, i);
+                    if i % publish_every == 0 {
+                        map.publish();
+                    }
                 }
-                map.purge();
+                map.publish();
+                println!("{watchers} watchers\t{:?}", start.elapsed());
+            }
+        }
+        Args::LazyStartup { count } => {
+            let initial = (0..count).map(|i| (i, i)).collect::<HashMap<u32, u32>>();
+
+            for lazy in [false, true] {
+                let input = initial.clone();
+                let bytes = LIVE_BYTES.load(Ordering::Relaxed);
+                let start = Instant::now();
+                let mut map = if lazy {
+                    cmap::CMap::<u32, u32>::new_lazy(input)
+                } else {
+                    cmap::CMap::<u32, u32>::from_maps(input.clone(), input)
+                };
+                let startup = start.elapsed();
+                let bytes = LIVE_BYTES.load(Ordering::Relaxed) - bytes;
 
+                // the lazy map pays for the copy here instead
+                let start = Instant::now();
+                map.insert(count, count);
                 map.publish();
-                if end <= Instant::now() {
-                    break;
+                let first_publish = start.elapsed();
+
+                println!(
+                    "{}\tstartup {startup:?}\tstartup bytes {}\tfirst publish {first_publish:?}",
+                    if lazy { "lazy" } else { "eager" },
+                    human_format::Formatter::new()
+                        .with_units("B")
+                        .format(bytes as f64),
+                );
+            }
+        }
+        Args::IntMap { count, rounds } => {
+            let start = Instant::now();
+            let mut map = cmap::CMap::<u32, u32>::new();
+            for _ in 0..rounds {
+                for i in 0..count {
+                    map.insert(i, i);
                 }
+                map.publish();
             }
+            println!("cmap-insert\t{:?}", start.elapsed());
 
-            print!("{}", iter);
+            let start = Instant::now();
+            let mut int_map = cmap::CIntMap::<u32>::new();
+            for _ in 0..rounds {
+                for i in 0..count {
+                    int_map.insert(i, i);
+                }
+                int_map.publish();
+            }
+            println!("intmap-insert\t{:?}", start.elapsed());
+
+            let mut reader = map.reader();
+            let start = Instant::now();
+            for _ in 0..rounds {
+                for i in 0..count {
+                    std::hint::black_box(reader.get(&i).map(|value| *value));
+                }
+            }
+            println!("cmap-get\t{:?}", start.elapsed());
+
+            let mut reader = int_map.reader();
+            let start = Instant::now();
+            for _ in 0..rounds {
+                for i in 0..count {
+                    std::hint::black_box(reader.get(i).map(|value| *value));
+                }
+            }
+            println!("intmap-get\t{:?}", start.elapsed());
         }
+        Args::DiffSync {
+            count,
+            changes,
+            rounds,
+        } => {
+            // each round updates `changes` keys spread over the map, then publishes
+            let key = |round: u32, i: u32| {
+                round
+                    .wrapping_mul(changes)
+                    .wrapping_add(i)
+                    .wrapping_mul(7919)
+                    % count
+            };
+
+            let mut map = cmap::CMap::<u32, u32>::new();
+            map.bulk_insert((0..count).map(|i| (i, i)).collect());
+            map.publish();
+            let mut reader = map.reader();
+            let start = Instant::now();
+            for round in 0..rounds {
+                for i in 0..changes {
+                    map.insert(key(round, i), round);
+                }
+                map.publish();
+                std::hint::black_box(reader.get(&key(round, 0)).map(|value| *value));
+            }
+            println!("cmap\t{:?}", start.elapsed());
+
+            let mut map = cmap::CMapDiffSync::<u32, u32>::new();
+            for i in 0..count {
+                map.insert(i, i);
+            }
+            map.publish();
+            let mut reader = map.reader();
+            let start = Instant::now();
+            for round in 0..rounds {
+                for i in 0..changes {
+                    map.insert(key(round, i), round);
+                }
+                map.publish();
+                std::hint::black_box(reader.get(&key(round, 0)).map(|value| *value));
+            }
+            println!("diff-sync\t{:?}", start.elapsed());
+        }
+        Args::SharedValue { count, size } => {
+            fn run<V: cmap::split::Split>(count: u32, make: impl Fn(u32) -> V) {
+                let bytes = LIVE_BYTES.load(Ordering::Relaxed);
+                let mut map = cmap::CMap::<u32, V>::new();
+                for i in 0..count {
+                    map.insert(i, make(i));
+                }
+                let start = Instant::now();
+                map.publish();
+                let publish = start.elapsed();
+
+                // the second publish puts the values in the other map
+                let start = Instant::now();
+                map.force_publish();
+                let second_publish = start.elapsed();
+                let bytes = LIVE_BYTES.load(Ordering::Relaxed) - bytes;
+
+                println!(
+                    "{}\tpublish {publish:?}\tsecond publish {second_publish:?}\tbytes {}",
+                    std::any::type_name::<V>(),
+                    human_format::Formatter::new()
+                        .with_units("B")
+                        .format(bytes as f64),
+                );
+            }
+
+            run(count, |i| vec![i as u8; size]);
+            run(count, |i| cmap::SharedValue::new(vec![i as u8; size]));
+        }
+        Args::BoxedOps { count, rounds } => {
+            // the maps have different types, since the op log's storage is a type parameter
+            macro_rules! run {
+                ($name:literal, $map:expr) => {{
+                    let mut map = $map;
+                    let mut push = Duration::ZERO;
+                    let mut publish = Duration::ZERO;
+                    for round in 0..rounds {
+                        let start = Instant::now();
+                        for i in 0..count {
+                            // a 256 byte value makes each operation at least as large
+                            map.insert(i, [round as u8; 256]);
+                        }
+                        push += start.elapsed();
+
+                        let start = Instant::now();
+                        map.publish();
+                        publish += start.elapsed();
+                    }
+                    println!(
+                        "{}	push {:?}/op	publish {:?}",
+                        $name,
+                        push / (count * rounds),
+                        publish / rounds,
+                    );
+                }};
+            }
+
+            run!("inline", cmap::CMap::<u32, [u8; 256]>::new());
+            run!(
+                "boxed",
+                cmap::CMap::<u32, [u8; 256]>::new().with_boxed_ops()
+            );
+        }
+    }
+}
+
+/// run the same writes through the evmap shim and a `CMultiMap`, and check that readers see the same maps
+fn assert_compat_matches_multimap(
+    map: &mut evmap_compat::WriteHandle<u32, u32>,
+    reader: &evmap_compat::ReadHandle<u32, u32>,
+    write_count: u32,
+) {
+    fn sorted(mut entries: Vec<(u32, Vec<u32>)>) -> Vec<(u32, Vec<u32>)> {
+        entries.iter_mut().for_each(|(_, values)| values.sort());
+        entries.sort();
+        entries
     }
+
+    let mut native = cmap::CMultiMap::<u32, u32>::new();
+    let mut native_reader = native.reader();
+    map.purge();
+    native.purge();
+
+    for round in 0..3 {
+        for i in 0..write_count {
+            map.insert(i % 64, i + round);
+            native.insert(i % 64, i + round);
+        }
+        for i in (0..write_count).step_by(3) {
+            map.remove_value(i % 64, i);
+            native.remove(i % 64, i);
+        }
+        map.remove_entry(round);
+        native.clear(round);
+        map.publish();
+        native.force_publish();
+
+        let compat = reader.map_into(|&key, values| (key, values.iter().copied().collect()));
+        let expected = native_reader
+            .load()
+            .iter()
+            .map(|(&key, values)| (key, values.iter().copied().collect()))
+            .collect();
+        assert_eq!(sorted(compat), sorted(expected));
+    }
+}
+
+fn insert_from_threads(threads: u32, count: u32, insert: impl Fn(u32) + Sync) {
+    std::thread::scope(|s| {
+        for thread in 0..threads {
+            let insert = &insert;
+            s.spawn(move || {
+                for i in 0..count {
+                    insert(thread * count + i)
+                }
+            });
+        }
+    });
+}
+
+/// time the swaps, and the first pass of writes over the writer buffer after each swap,
+/// while readers keep reading the whole reader buffer on other cores
+fn first_write_after_swap(
+    size: usize,
+    readers: u32,
+    iterations: u32,
+    warm: bool,
+) -> (Duration, Duration) {
+    let mut writer =
+        dbuf::delayed::DelayedWriter::new(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::<
+            dbuf::strategy::HazardStrategy,
+            _,
+        >::from_buffers(
+            vec![0_u8; size],
+            vec![0_u8; size],
+        )));
+    if warm {
+        writer.set_post_swap(|buffer: &mut Vec<u8>| dbuf::warm::touch_pages(buffer));
+    }
+
+    let stop = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|s| {
+        for _ in 0..readers {
+            let mut reader = writer.reader();
+            let stop = &stop;
+            s.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let guard = reader.get();
+                    std::hint::black_box(guard.iter().step_by(64).map(|&x| x as u64).sum::<u64>());
+                }
+            });
+        }
+
+        let mut swap = Duration::ZERO;
+        let mut write = Duration::ZERO;
+        for _ in 0..iterations {
+            let start = Instant::now();
+            let buffer = writer.swap_buffers().split_mut().writer;
+            swap += start.elapsed();
+
+            let start = Instant::now();
+            for x in buffer.iter_mut().step_by(64) {
+                *x = x.wrapping_add(1);
+            }
+            write += start.elapsed();
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        (swap, write)
+    })
+}
+
+/// time how long readers take to acquire a read guard while the writer swaps every `swap_every`,
+/// each reader acquires a guard every `read_every`
+fn read_latency_with_swapping_writer(
+    readers: u32,
+    swap_every: Duration,
+    read_every: Duration,
+    timeout: Duration,
+) -> Vec<Duration> {
+    let mut writer = dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::<
+        dbuf::strategy::HazardStrategy,
+        _,
+    >::from_buffers(0u64, 0));
+    let done = std::sync::atomic::AtomicBool::new(false);
+    let latencies = Mutex::new(Vec::new());
+
+    std::thread::scope(|s| {
+        for _ in 0..readers {
+            let mut reader = writer.reader();
+            let (done, latencies) = (&done, &latencies);
+            s.spawn(move || {
+                let mut samples = Vec::new();
+                let mut next = Instant::now();
+                while !done.load(Ordering::Relaxed) {
+                    while Instant::now() < next {
+                        std::hint::spin_loop();
+                    }
+                    next += read_every;
+
+                    let start = Instant::now();
+                    let guard = reader.get();
+                    samples.push(start.elapsed());
+                    std::hint::black_box(*guard);
+                }
+                latencies.lock().unwrap().extend(samples);
+            });
+        }
+
+        let start = Instant::now();
+        let mut next = start;
+        while start.elapsed() < timeout {
+            while Instant::now() < next {
+                std::hint::spin_loop();
+            }
+            next += swap_every;
+            writer.swap_buffers();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    latencies.into_inner().unwrap()
+}
+
+/// time the swaps of a writer which spins for `budget` waits before parking,
+/// while readers hold each read guard for `hold`
+///
+/// a larger budget reduces the latency of swaps, at the cost of burning cpu while spinning
+fn swap_latency_with_wait_budget(
+    budget: u32,
+    readers: u32,
+    iterations: u32,
+    hold: Duration,
+) -> (Vec<Duration>, u64) {
+    let strategy = dbuf::strategy::HazardStrategy::with_wait_strategy(
+        dbuf::wait::AdaptiveWait::with_spin_budget(budget),
+    );
+    let mut writer = dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+        dbuf::raw::Shared::from_raw_parts(strategy, dbuf::raw::RawDBuf::new(0u64, 0)),
+    ));
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        for _ in 0..readers {
+            let mut reader = writer.reader();
+            let done = &done;
+            s.spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let guard = reader.get();
+                    let start = Instant::now();
+                    while start.elapsed() < hold {
+                        std::hint::spin_loop();
+                    }
+                    std::hint::black_box(*guard);
+                }
+            });
+        }
+
+        let mut latencies = Vec::with_capacity(iterations as usize);
+        let mut pauses = 0;
+        for _ in 0..iterations {
+            let start = Instant::now();
+            pauses += u64::from(writer.swap_buffers_with_stats().pauses);
+            latencies.push(start.elapsed());
+        }
+        done.store(true, Ordering::Relaxed);
+        (latencies, pauses)
+    })
+}
+
+/// time the publishes of a `CMultiMap` using the strategy `Strat`, while readers look up a key
+/// and hold on to it for `hold`, returns the publish latencies and the number of reads
+///
+/// the readers share the cores with the writer, so a writer which burns cpu while waiting shows
+/// up as fewer reads. Run this with more cores than readers, otherwise the readers are rarely
+/// scheduled while the writer publishes, and every strategy looks the same
+fn publish_latency_with_strategy<Strat>(
+    readers: u32,
+    iterations: u32,
+    hold: Duration,
+) -> (Vec<Duration>, usize)
+where
+    Strat: dbuf::interface::Strategy<ValidationError = std::convert::Infallible> + Default,
+    cmap::CMultiMap<u32, u32, cmap::DefaultHasher, Strat>: Send,
+    cmap::CMultiMapReader<u32, u32, cmap::DefaultHasher, Strat>: Send,
+{
+    let mut map = cmap::CMultiMap::<u32, u32>::with_profile::<Strat>();
+    let done = std::sync::atomic::AtomicBool::new(false);
+    let reads = AtomicUsize::new(0);
+    let started = std::sync::Barrier::new(readers as usize + 1);
+
+    let latencies = std::thread::scope(|s| {
+        for _ in 0..readers {
+            let mut reader = map.reader();
+            let (done, reads, started) = (&done, &reads, &started);
+            s.spawn(move || {
+                started.wait();
+                let mut count = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let guard = reader.get(&0);
+                    if !hold.is_zero() {
+                        let start = Instant::now();
+                        while start.elapsed() < hold {
+                            std::hint::spin_loop();
+                        }
+                    }
+                    std::hint::black_box(guard.map(|values| values.len()));
+                    count += 1;
+                }
+                reads.fetch_add(count, Ordering::Relaxed);
+            });
+        }
+
+        started.wait();
+        let mut latencies = Vec::with_capacity(iterations as usize);
+        for i in 0..iterations {
+            map.clear(0);
+            map.insert(0, i);
+            let start = Instant::now();
+            map.publish();
+            latencies.push(start.elapsed());
+        }
+        done.store(true, Ordering::Relaxed);
+        latencies
+    });
+
+    (latencies, reads.into_inner())
+}
+
+/// print the percentiles of the publish latencies, and the reads per publish
+fn report_publish_latency(name: &str, (mut latencies, reads): (Vec<Duration>, usize)) {
+    latencies.sort_unstable();
+    let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+    println!(
+        "  {name}\tp50 {:?}\tp99 {:?}\treads/publish {}",
+        percentile(50),
+        percentile(99),
+        reads / latencies.len(),
+    );
+}
+
+/// count the reads of a small buffer while the writer keeps writing to the other buffer
+fn read_with_concurrent_writer<B>(readers: u32, timeout: Duration) -> usize
+where
+    B: dbuf::interface::FromBuffers<Buffer = u64> + Send + Sync,
+{
+    let shared = dbuf::ptrs::alloc::Owned::<dbuf::strategy::TrackingStrategy, _>::new(
+        dbuf::raw::Shared::from_raw_parts(Default::default(), B::from_buffers(0, 0)),
+    );
+    let mut writer = dbuf::raw::Writer::new(shared);
+    let reader = writer.reader();
+    let done = std::sync::atomic::AtomicBool::new(false);
+    let reads = AtomicUsize::new(0);
+
+    std::thread::scope(|s| {
+        for _ in 0..readers {
+            let mut reader = reader.clone();
+            let (done, reads) = (&done, &reads);
+            s.spawn(move || {
+                let mut count = 0;
+                while !done.load(Ordering::Relaxed) {
+                    std::hint::black_box(*reader.get());
+                    count += 1;
+                }
+                reads.fetch_add(count, Ordering::Relaxed);
+            });
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            for _ in 0..1000 {
+                *std::hint::black_box(&mut *writer.split_mut().writer) += 1;
+            }
+            writer.swap_buffers();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    reads.into_inner()
 }
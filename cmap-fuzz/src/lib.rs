@@ -0,0 +1,531 @@
+//! A model test of [`CMultiMap`]'s public API
+//!
+//! A sequence of [`Command`]s is run against a [`CMultiMap`] and against a [`Model`]
+//! built from a plain `HashMap<K, Vec<V>>`. After every command the writer's view of the
+//! published map must match the model as of the last publish, and every read from a
+//! reader must match it too.
+//!
+//! Commands are decoded from raw bytes, one byte for the command and one for each argument,
+//! so that removing bytes from a failing input removes whole commands, and lowering a byte
+//! picks a smaller key or value. The keys and values are picked from a few small numbers,
+//! so that sequences often hit the same bag, and move it between holding one value and many.
+//!
+//! This runs from `cargo test` with proptest, and from `cargo fuzz run cmap_ops` in `fuzz/`,
+//! which starts from the seeds in [`seeds`] (written to `fuzz/corpus/cmap_ops`).
+
+use std::collections::{BTreeMap, HashMap};
+
+use arbitrary::{Arbitrary, Unstructured};
+use cmap::{multimap::Bag, CMultiMap, CMultiMapReader};
+
+/// the number of distinct keys
+pub const KEYS: u8 = 4;
+/// the number of distinct values
+pub const VALUES: u8 = 4;
+/// one more than the largest count for `InsertN` and `SetCount`
+pub const COUNTS: u8 = 4;
+
+/// An operation on the map, or a check of what readers see
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// [`CMultiMap::insert`]
+    Insert(u8, u8),
+    /// [`CMultiMap::insert_n`]
+    InsertN(u8, u8, u8),
+    /// [`CMultiMap::set_count`]
+    SetCount(u8, u8, u8),
+    /// [`CMultiMap::remove`]
+    Remove(u8, u8),
+    /// [`CMultiMap::clear`]
+    Clear(u8),
+    /// [`CMultiMap::purge`]
+    Purge,
+    /// [`CMultiMap::retain`], removing each copy whose key and value are picked by [`drops`]
+    Retain(u8),
+    /// [`CMultiMap::retain_for`] the key, removing each copy picked by [`drops`]
+    RetainFor(u8, u8),
+    /// [`CMultiMap::publish`]
+    Publish,
+    /// [`CMultiMap::force_publish`]
+    ForcePublish,
+    /// [`CMultiMapReader::get`] the key
+    ReaderGet(u8),
+    /// [`CMultiMapReader::load`] the whole map
+    ReaderLoad,
+}
+
+/// the number of variants of [`Command`]
+const COMMANDS: u8 = 12;
+
+/// the predicate of [`Command::Retain`] and [`Command::RetainFor`], true if the copy should be removed
+///
+/// this only depends on its inputs, so that it removes the same copies from both maps
+pub fn drops(residue: u8, key: u8, value: u8) -> bool {
+    (key + value) % 3 == residue % 3
+}
+
+impl<'a> Arbitrary<'a> for Command {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let key = |u: &mut Unstructured<'a>| Ok(u8::arbitrary(u)? % KEYS);
+        let value = |u: &mut Unstructured<'a>| Ok(u8::arbitrary(u)? % VALUES);
+        let count = |u: &mut Unstructured<'a>| Ok(u8::arbitrary(u)? % COUNTS);
+
+        Ok(match u8::arbitrary(u)? % COMMANDS {
+            0 => Self::Insert(key(u)?, value(u)?),
+            1 => Self::InsertN(key(u)?, value(u)?, count(u)?),
+            2 => Self::SetCount(key(u)?, value(u)?, count(u)?),
+            3 => Self::Remove(key(u)?, value(u)?),
+            4 => Self::Clear(key(u)?),
+            5 => Self::Purge,
+            6 => Self::Retain(u8::arbitrary(u)? % 3),
+            7 => Self::RetainFor(key(u)?, u8::arbitrary(u)? % 3),
+            8 => Self::Publish,
+            9 => Self::ForcePublish,
+            10 => Self::ReaderGet(key(u)?),
+            _ => Self::ReaderLoad,
+        })
+    }
+}
+
+impl Command {
+    /// the bytes which decode to this command, the inverse of [`decode`]
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        match *self {
+            Self::Insert(k, v) => bytes.extend([0, k, v]),
+            Self::InsertN(k, v, n) => bytes.extend([1, k, v, n]),
+            Self::SetCount(k, v, n) => bytes.extend([2, k, v, n]),
+            Self::Remove(k, v) => bytes.extend([3, k, v]),
+            Self::Clear(k) => bytes.extend([4, k]),
+            Self::Purge => bytes.push(5),
+            Self::Retain(r) => bytes.extend([6, r]),
+            Self::RetainFor(k, r) => bytes.extend([7, k, r]),
+            Self::Publish => bytes.push(8),
+            Self::ForcePublish => bytes.push(9),
+            Self::ReaderGet(k) => bytes.extend([10, k]),
+            Self::ReaderLoad => bytes.push(11),
+        }
+    }
+}
+
+/// decode commands until the bytes run out
+///
+/// a trailing command with missing arguments gets zeros for them
+pub fn decode(bytes: &[u8]) -> Vec<Command> {
+    let mut u = Unstructured::new(bytes);
+    let mut commands = Vec::new();
+    while !u.is_empty() {
+        match Command::arbitrary(&mut u) {
+            Ok(command) => commands.push(command),
+            Err(_) => break,
+        }
+    }
+    commands
+}
+
+/// encode commands, the inverse of [`decode`]
+pub fn encode(commands: &[Command]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for command in commands {
+        command.encode(&mut bytes);
+    }
+    bytes
+}
+
+/// The expected contents of the map, as a key and all copies of its values
+///
+/// A key whose values were all removed one by one stays in the map with no values,
+/// like the empty bag [`CMultiMap::remove`] leaves behind
+pub type ModelMap = HashMap<u8, Vec<u8>>;
+
+/// The reference implementation of the map
+#[derive(Debug, Default, Clone)]
+pub struct Model {
+    /// the map with every operation applied
+    pub pending: ModelMap,
+    /// the map as of the last publish
+    pub published: ModelMap,
+}
+
+/// remove each copy picked by `drops`, and the key if it has no values left
+fn retain_key(map: &mut ModelMap, key: u8, residue: u8) {
+    if let Some(values) = map.get_mut(&key) {
+        values.retain(|&value| !drops(residue, key, value));
+        if values.is_empty() {
+            map.remove(&key);
+        }
+    }
+}
+
+impl Model {
+    /// apply an operation to the pending map, the checks don't change the model
+    pub fn apply(&mut self, command: Command) {
+        let map = &mut self.pending;
+        match command {
+            Command::Insert(k, v) => map.entry(k).or_default().push(v),
+            Command::InsertN(_, _, 0) => (),
+            Command::InsertN(k, v, n) => map
+                .entry(k)
+                .or_default()
+                .extend(std::iter::repeat_n(v, n.into())),
+            Command::SetCount(k, v, 0) => {
+                if let Some(values) = map.get_mut(&k) {
+                    values.retain(|&value| value != v);
+                    if values.is_empty() {
+                        map.remove(&k);
+                    }
+                }
+            }
+            Command::SetCount(k, v, n) => {
+                let values = map.entry(k).or_default();
+                values.retain(|&value| value != v);
+                values.extend(std::iter::repeat_n(v, n.into()));
+            }
+            Command::Remove(k, v) => {
+                if let Some(values) = map.get_mut(&k) {
+                    if let Some(i) = values.iter().position(|&value| value == v) {
+                        values.swap_remove(i);
+                    }
+                }
+            }
+            Command::Clear(k) => {
+                map.remove(&k);
+            }
+            Command::Purge => map.clear(),
+            Command::Retain(r) => {
+                for k in 0..KEYS {
+                    retain_key(map, k, r);
+                }
+                // `retain` also drops the keys which already had no values
+                map.retain(|_, values| !values.is_empty());
+            }
+            Command::RetainFor(k, r) => retain_key(map, k, r),
+            Command::Publish | Command::ForcePublish => self.published = self.pending.clone(),
+            Command::ReaderGet(_) | Command::ReaderLoad => (),
+        }
+    }
+}
+
+/// a map in a form which can be compared, with the values of each key sorted
+type Normalized = BTreeMap<u8, Vec<u8>>;
+
+/// sort the values of a bag
+fn normalize_bag(bag: &Bag<u8>) -> Vec<u8> {
+    let mut values = bag
+        .set_iter()
+        .flat_map(|(&value, count)| std::iter::repeat_n(value, count))
+        .collect::<Vec<_>>();
+    values.sort_unstable();
+    values
+}
+
+/// sort the values of each key of the map
+fn normalize_map(map: &HashMap<u8, Bag<u8>>) -> Normalized {
+    map.iter()
+        .map(|(&k, bag)| (k, normalize_bag(bag)))
+        .collect()
+}
+
+/// sort the values of each key of the model
+fn normalize_model(map: &ModelMap) -> Normalized {
+    map.iter()
+        .map(|(&k, values)| {
+            let mut values = values.clone();
+            values.sort_unstable();
+            (k, values)
+        })
+        .collect()
+}
+
+/// The map under test, next to the model
+pub struct Harness {
+    /// the map under test
+    map: CMultiMap<u8, u8>,
+    /// a reader of the map
+    reader: CMultiMapReader<u8, u8>,
+    /// the reference implementation
+    model: Model,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Harness {
+    /// an empty map and model
+    pub fn new() -> Self {
+        let map = CMultiMap::new();
+        Self {
+            reader: map.reader(),
+            map,
+            model: Model::default(),
+        }
+    }
+
+    /// the reference implementation
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// run one command on the map and the model, then check that they agree
+    ///
+    /// # Panics
+    ///
+    /// if the map doesn't match the model
+    pub fn step(&mut self, command: Command) {
+        let map = &mut self.map;
+        match command {
+            Command::Insert(k, v) => map.insert(k, v),
+            Command::InsertN(k, v, n) => map.insert_n(k, v, n.into()),
+            Command::SetCount(k, v, n) => map.set_count(k, v, n.into()),
+            Command::Remove(k, v) => map.remove(k, v),
+            Command::Clear(k) => map.clear(k),
+            Command::Purge => map.purge(),
+            Command::Retain(r) => map.retain(move |_, &k, &v| drops(r, k, v)),
+            Command::RetainFor(k, r) => map.retain_for(k, move |_, &v| drops(r, k, v)),
+            Command::Publish => map.publish(),
+            Command::ForcePublish => map.force_publish(),
+            Command::ReaderGet(k) => {
+                let bag = self.reader.get(&k).map(|bag| normalize_bag(&bag));
+                let expected = normalize_model(&self.model.published).remove(&k);
+                let one = self.reader.get_one(&k).map(|value| *value);
+                check_one(one, expected.as_deref(), k, command);
+                assert_eq!(bag, expected, "reader get({k}) after {command:?}");
+            }
+            Command::ReaderLoad => {
+                let published = normalize_map(&self.reader.load());
+                let expected = normalize_model(&self.model.published);
+                assert_eq!(published, expected, "reader load");
+            }
+        }
+        self.model.apply(command);
+        self.check(command);
+    }
+
+    /// check that the writer's view of the published map matches the model
+    fn check(&self, command: Command) {
+        let expected = normalize_model(&self.model.published);
+
+        assert_eq!(
+            normalize_map(self.map.load()),
+            expected,
+            "published map after {command:?}"
+        );
+        for k in 0..KEYS {
+            assert_eq!(
+                self.map.get(&k).map(normalize_bag),
+                expected.get(&k).cloned(),
+                "get({k}) after {command:?}"
+            );
+            check_one(
+                self.map.get_one(&k).copied(),
+                expected.get(&k).map(Vec::as_slice),
+                k,
+                command,
+            );
+        }
+        assert_eq!(self.map.published_len(), expected.len());
+        assert_eq!(
+            self.map.published_value_count(),
+            expected.values().map(Vec::len).sum::<usize>()
+        );
+    }
+}
+
+/// check that `get_one` found a value of the key if and only if the key has any,
+/// an empty bag has none
+fn check_one(one: Option<u8>, expected: Option<&[u8]>, k: u8, command: Command) {
+    match (one, expected.unwrap_or_default()) {
+        (Some(value), values) => assert!(
+            values.contains(&value),
+            "get_one({k}) = {value}, which isn't one of {values:?}, after {command:?}"
+        ),
+        (None, []) => (),
+        (None, values) => {
+            panic!("get_one({k}) = None, but the key has {values:?}, after {command:?}")
+        }
+    }
+}
+
+/// run the commands against a fresh map and model, publishing at the end
+///
+/// # Panics
+///
+/// if the map doesn't match the model
+pub fn run(commands: &[Command]) {
+    let mut harness = Harness::new();
+    for &command in commands {
+        harness.step(command);
+    }
+    harness.step(Command::Publish);
+    harness.step(Command::ReaderLoad);
+}
+
+/// decode and run the commands, see [`decode`] and [`run`]
+pub fn run_bytes(bytes: &[u8]) {
+    run(&decode(bytes))
+}
+
+/// Hand written command sequences which cover the interesting transitions of a bag,
+/// these are the seed corpus of the fuzz target
+pub fn seeds() -> Vec<(&'static str, Vec<Command>)> {
+    use Command::*;
+
+    vec![
+        (
+            "one_to_many_and_back",
+            vec![
+                Insert(0, 1),
+                Publish,
+                Insert(0, 2),
+                Publish,
+                ReaderGet(0),
+                SetCount(0, 2, 0),
+                Publish,
+                ReaderGet(0),
+                Publish,
+                ReaderGet(0),
+            ],
+        ),
+        (
+            "remove_leaves_empty_bag",
+            vec![
+                Insert(1, 1),
+                Publish,
+                Remove(1, 1),
+                Publish,
+                ReaderGet(1),
+                Insert(1, 3),
+                ForcePublish,
+                ReaderLoad,
+            ],
+        ),
+        (
+            "many_remove_to_empty",
+            vec![
+                InsertN(2, 0, 2),
+                Insert(2, 1),
+                Publish,
+                Remove(2, 0),
+                Remove(2, 0),
+                Remove(2, 1),
+                Publish,
+                ReaderLoad,
+                Publish,
+                ReaderLoad,
+            ],
+        ),
+        (
+            "retain_prunes_empty_bags",
+            vec![
+                Insert(0, 0),
+                Insert(1, 2),
+                Remove(1, 2),
+                InsertN(3, 3, 3),
+                Publish,
+                Retain(0),
+                Publish,
+                ReaderLoad,
+                RetainFor(3, 0),
+                Publish,
+                ReaderLoad,
+            ],
+        ),
+        (
+            "set_count_zero_prunes_key",
+            vec![
+                SetCount(0, 1, 3),
+                Publish,
+                SetCount(0, 1, 0),
+                Publish,
+                ReaderGet(0),
+                InsertN(0, 2, 0),
+                Publish,
+                ReaderGet(0),
+            ],
+        ),
+        (
+            "clear_and_purge",
+            vec![
+                Insert(0, 0),
+                Insert(1, 1),
+                Insert(1, 2),
+                Publish,
+                Clear(1),
+                Publish,
+                ReaderLoad,
+                Purge,
+                Insert(2, 2),
+                ForcePublish,
+                ReaderLoad,
+            ],
+        ),
+        (
+            "writer_reads_between_publishes",
+            vec![
+                Insert(3, 0),
+                ReaderGet(3),
+                Publish,
+                Insert(3, 0),
+                ReaderGet(3),
+                ForcePublish,
+                ForcePublish,
+                ReaderLoad,
+            ],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_seeds() {
+        for (name, commands) in seeds() {
+            println!("seed: {name}");
+            run(&commands);
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        for (name, commands) in seeds() {
+            assert_eq!(decode(&encode(&commands)), commands, "seed {name}");
+        }
+    }
+
+    /// the seed corpus is written from [`seeds`], set `CMAP_FUZZ_BLESS` to write it again
+    #[test]
+    fn test_seed_corpus() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/cmap_ops");
+        let bless = std::env::var_os("CMAP_FUZZ_BLESS").is_some();
+
+        for (name, commands) in seeds() {
+            let path = corpus.join(name);
+            if bless {
+                fs::write(&path, encode(&commands)).unwrap();
+            }
+            assert_eq!(
+                fs::read(&path).unwrap(),
+                encode(&commands),
+                "{} is out of date, run the tests with CMAP_FUZZ_BLESS=1",
+                path.display()
+            );
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(512))]
+
+        #[test]
+        fn test_matches_model(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+            run_bytes(&bytes);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Publish a [`CMap`] automatically, so that operations can't be forgotten
+//!
+//! Operations on a [`CMap`] aren't visible to readers until the map is published, so a code path
+//! which forgets to call [`publish`](CMap::publish) leaves readers with stale data. An
+//! [`AutoPublisher`] publishes the map once the oldest unpublished operation is older than a
+//! deadline. Either call [`check_deadline`](AutoPublisher::check_deadline) from an existing
+//! event loop, or [`spawn`](AutoPublisher::spawn) a thread which does it.
+
+use std::{
+    convert::Infallible,
+    hash::{BuildHasher, Hash},
+    ops::Deref,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use dbuf::interface::Strategy;
+
+use crate::{split::Split, CMap, DefaultHasher, DefaultStrat};
+
+/// A source of the current time, so that tests can control time
+pub trait Clock {
+    /// the current time
+    fn now(&self) -> Instant;
+}
+
+/// The system clock, see [`Instant::now`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A map which is published once its operations are older than a deadline, see module docs for details
+pub struct AutoPublisher<K, V, S = DefaultHasher, Strat = DefaultStrat, C = SystemClock>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// the map
+    map: CMap<K, V, S, Strat>,
+    /// how long an operation may stay unpublished
+    deadline: Duration,
+    /// when the oldest unpublished operation may have been applied
+    pending_since: Option<Instant>,
+    /// the source of time
+    clock: C,
+}
+
+/// An [`AutoPublisher`] on a background thread, see [`AutoPublisher::spawn`]
+///
+/// When this is dropped, the thread publishes any unpublished operations and exits
+pub struct AutoPublishHandle<K, V, S = DefaultHasher, Strat = DefaultStrat, C = SystemClock>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// the state shared with the background thread
+    #[allow(clippy::type_complexity)]
+    shared: Arc<Shared<AutoPublisher<K, V, S, Strat, C>>>,
+    /// the background thread
+    thread: Option<JoinHandle<()>>,
+}
+
+/// the state shared between an [`AutoPublishHandle`] and its thread
+struct Shared<P> {
+    /// the publisher, and if the handle was dropped
+    state: Mutex<State<P>>,
+    /// wakes the thread when there are new operations, or the handle was dropped
+    cv: Condvar,
+}
+
+/// the state of a [`Shared`]
+struct State<P> {
+    /// the publisher
+    publisher: P,
+    /// true once the handle was dropped
+    shutdown: bool,
+}
+
+impl<P> Shared<P> {
+    /// lock the state, ignoring poison since the map is checked for poison when it's published
+    fn lock(&self) -> MutexGuard<'_, State<P>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K, V, S, Strat> AutoPublisher<K, V, S, Strat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Publish `map` at most `deadline` after an operation is applied to it
+    pub fn new(map: CMap<K, V, S, Strat>, deadline: Duration) -> Self {
+        Self::with_clock(map, deadline, SystemClock)
+    }
+}
+
+impl<K, V, S, Strat, C> AutoPublisher<K, V, S, Strat, C>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+    C: Clock,
+{
+    /// Publish `map` at most `deadline` after an operation is applied to it, according to `clock`
+    pub fn with_clock(map: CMap<K, V, S, Strat>, deadline: Duration, clock: C) -> Self {
+        let pending_since = (!map.unapplied().is_empty()).then(|| clock.now());
+        Self {
+            map,
+            deadline,
+            pending_since,
+            clock,
+        }
+    }
+
+    /// Get the map to apply operations to it
+    ///
+    /// The deadline starts counting from the first call after a publish,
+    /// even if no operations were applied
+    pub fn map_mut(&mut self) -> &mut CMap<K, V, S, Strat> {
+        if self.pending_since.is_none() {
+            self.pending_since = Some(self.clock.now());
+        }
+        &mut self.map
+    }
+
+    /// When the map has to be published next, or `None` if nothing is waiting to be published
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending_since.map(|since| since + self.deadline)
+    }
+
+    /// The map, without publishing it
+    pub fn into_inner(self) -> CMap<K, V, S, Strat> {
+        self.map
+    }
+}
+
+impl<K, V, S, Strat, C> AutoPublisher<K, V, S, Strat, C>
+where
+    K: Hash + Eq + Split,
+    V: Split,
+    S: BuildHasher,
+    Strat: Strategy<ValidationError = Infallible>,
+    C: Clock,
+{
+    /// Publish the map if the oldest unpublished operation is at least as old as the deadline
+    ///
+    /// Returns true if the map was published. This is cheap if nothing is waiting
+    /// to be published, so it can be called on every iteration of an event loop
+    pub fn check_deadline(&mut self) -> bool {
+        let Some(since) = self.pending_since else {
+            return false;
+        };
+
+        if self.map.unapplied().is_empty() {
+            // the map was published through `map_mut`
+            self.pending_since = None;
+            return false;
+        }
+
+        if self.clock.now().saturating_duration_since(since) < self.deadline {
+            return false;
+        }
+
+        self.publish();
+        true
+    }
+
+    /// Publish the map now
+    pub fn publish(&mut self) {
+        self.map.publish();
+        self.pending_since = None;
+    }
+
+    /// Publish the map if there are any unpublished operations
+    pub fn flush(&mut self) {
+        if !self.map.unapplied().is_empty() {
+            self.map.publish();
+        }
+        self.pending_since = None;
+    }
+}
+
+impl<K, V, S, Strat, C> AutoPublisher<K, V, S, Strat, C>
+where
+    K: Hash + Eq + Split,
+    V: Split,
+    S: BuildHasher,
+    Strat: Strategy<ValidationError = Infallible>,
+    C: Clock,
+    Self: Send + 'static,
+{
+    /// Move the map to a background thread which publishes it when the deadline passes
+    ///
+    /// The thread waits with the system clock, so this should only be used with a real clock
+    pub fn spawn(self) -> AutoPublishHandle<K, V, S, Strat, C> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                publisher: self,
+                shutdown: false,
+            }),
+            cv: Condvar::new(),
+        });
+
+        let thread = std::thread::spawn({
+            let shared = shared.clone();
+            move || {
+                let mut state = shared.lock();
+                loop {
+                    if state.shutdown {
+                        state.publisher.flush();
+                        return;
+                    }
+
+                    let timeout = match state.publisher.next_deadline() {
+                        None => None,
+                        Some(deadline) => {
+                            let now = state.publisher.clock.now();
+                            if now >= deadline {
+                                state.publisher.check_deadline();
+                                continue;
+                            }
+                            Some(deadline - now)
+                        }
+                    };
+
+                    state = match timeout {
+                        None => shared
+                            .cv
+                            .wait(state)
+                            .unwrap_or_else(PoisonError::into_inner),
+                        Some(timeout) => {
+                            let (state, _) = shared
+                                .cv
+                                .wait_timeout(state, timeout)
+                                .unwrap_or_else(PoisonError::into_inner);
+                            state
+                        }
+                    };
+                }
+            }
+        });
+
+        AutoPublishHandle {
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<K, V, S, Strat, C> AutoPublishHandle<K, V, S, Strat, C>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+    C: Clock,
+{
+    /// Apply operations to the map, they are published within the deadline
+    pub fn with_map<R>(&self, f: impl FnOnce(&mut CMap<K, V, S, Strat>) -> R) -> R {
+        let result = f(self.shared.lock().publisher.map_mut());
+        self.shared.cv.notify_one();
+        result
+    }
+}
+
+impl<K, V, S, Strat, C> Drop for AutoPublishHandle<K, V, S, Strat, C>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.cv.notify_one();
+
+        if let Some(thread) = self.thread.take() {
+            // don't panic while panicking, the thread's panic is reported when it happens
+            if thread.join().is_err() && !std::thread::panicking() {
+                panic!("the auto publish thread panicked")
+            }
+        }
+    }
+}
+
+impl<K, V, S, Strat, C> Deref for AutoPublisher<K, V, S, Strat, C>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = CMap<K, V, S, Strat>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+/// a clock which only moves when the test advances it
+#[cfg(test)]
+#[derive(Clone)]
+struct PausedClock {
+    /// the time when the clock was created
+    start: Instant,
+    /// how far the clock was advanced
+    elapsed: std::rc::Rc<std::cell::Cell<Duration>>,
+}
+
+#[cfg(test)]
+impl PausedClock {
+    /// a clock which starts at the current time
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Default::default(),
+        }
+    }
+
+    /// move the clock forward
+    fn advance(&self, by: Duration) {
+        self.elapsed.set(self.elapsed.get() + by);
+    }
+}
+
+#[cfg(test)]
+impl Clock for PausedClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+}
+
+#[test]
+fn publishes_after_deadline() {
+    let ms = Duration::from_millis;
+    let clock = PausedClock::new();
+    let mut map = AutoPublisher::with_clock(CMap::<u32, u32>::new(), ms(100), clock.clone());
+    let mut reader = map.reader();
+
+    // nothing to publish
+    clock.advance(ms(1000));
+    assert!(!map.check_deadline());
+    assert_eq!(map.next_deadline(), None);
+
+    map.map_mut().insert(1, 10);
+    assert!(!map.check_deadline());
+    clock.advance(ms(99));
+    assert!(!map.check_deadline());
+    assert!(reader.get(&1).is_none());
+
+    clock.advance(ms(1));
+    assert!(map.check_deadline());
+    assert_eq!(reader.get(&1).as_deref(), Some(&10));
+    assert_eq!(map.next_deadline(), None);
+
+    // the deadline counts from the oldest unpublished operation
+    map.map_mut().insert(2, 20);
+    let deadline = map.next_deadline();
+    clock.advance(ms(60));
+    map.map_mut().insert(3, 30);
+    assert_eq!(map.next_deadline(), deadline);
+    clock.advance(ms(40));
+    assert!(map.check_deadline());
+    assert_eq!(reader.get(&2).as_deref(), Some(&20));
+    assert_eq!(reader.get(&3).as_deref(), Some(&30));
+
+    // an explicit publish resets the deadline
+    map.map_mut().insert(4, 40);
+    map.map_mut().publish();
+    clock.advance(ms(1000));
+    assert!(!map.check_deadline());
+    assert_eq!(map.next_deadline(), None);
+}
+
+#[test]
+fn background_publisher() {
+    let handle = AutoPublisher::new(CMap::<u32, u32>::new(), Duration::from_millis(10)).spawn();
+    let mut reader = handle.with_map(|map| map.reader());
+
+    handle.with_map(|map| map.insert(1, 10));
+    let start = Instant::now();
+    while reader.get(&1).is_none() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "the map was never published"
+        );
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    // the thread publishes before exiting
+    let handle = AutoPublisher::new(CMap::<u32, u32>::new(), Duration::from_secs(3600)).spawn();
+    let mut reader = handle.with_map(|map| map.reader());
+    handle.with_map(|map| map.insert(2, 20));
+    drop(handle);
+    assert_eq!(reader.get(&2).as_deref(), Some(&20));
+}
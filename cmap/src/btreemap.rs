@@ -1,46 +1,89 @@
 use super::DefaultStrat;
 use std::{borrow::Borrow, collections::BTreeMap, convert::Infallible, ops::Deref};
 
-use dbuf::interface::Strategy;
+use dbuf::interface::{FromBuffers, RawBuffers, Strategy, StrategyIntrospect};
 use sync_wrapper::SyncWrapper;
 
-use crate::split::Split;
+use crate::{split::Split, ActiveReaderInfo, BufferId, BuffersDiffer, Consistency, ReadError};
 
-pub struct CBTreeMap<K, V, Strat = DefaultStrat>
+pub struct CBTreeMap<K, V, Strat = DefaultStrat, B = dbuf::raw::RawDBuf<BTreeMap<K, V>>>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::op::OpWriter<
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<BTreeMap<K, V>>>,
-        MapOp<K, V>,
-    >,
+    inner: dbuf::op::OpWriter<dbuf::ptrs::alloc::OwnedPtr<Strat, B>, MapOp<K, V>>,
 }
 
-pub struct CBTreeMapReader<K, V, Strat>
+pub struct CBTreeMapReader<K, V, Strat, B = dbuf::raw::RawDBuf<BTreeMap<K, V>>>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner:
-        dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<BTreeMap<K, V>>>>,
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
 }
 
-pub struct CBTreeMapReadGuard<'a, K, V, Strat = DefaultStrat, T = BTreeMap<K, V>>
+pub struct CBTreeMapReadGuard<
+    'a,
+    K,
+    V,
+    Strat = DefaultStrat,
+    T = BTreeMap<K, V>,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, V>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    T: ?Sized,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T>,
+}
+
+pub struct CBTreeMapZoomGuard<
+    'a,
+    K,
+    V,
+    Strat,
+    T: ?Sized,
+    U: ?Sized,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, V>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ZoomGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T, U>,
+}
+
+pub struct CBTreeMapWeakReader<K, V, Strat = DefaultStrat, B = dbuf::raw::RawDBuf<BTreeMap<K, V>>>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedWeak<Strat, B>>,
+}
+
+pub struct CBTreeMapWeakReadGuard<
+    'a,
+    K,
+    V,
+    Strat = DefaultStrat,
+    T = BTreeMap<K, V>,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, V>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     T: ?Sized,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::raw::ReadGuard<
-        'a,
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<BTreeMap<K, V>>>,
-        T,
-    >,
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedStrong<Strat, B>, T>,
 }
 
 pub enum MapOp<K, V> {
     Insert(K, V),
+    Extend(Vec<(K, V)>),
     Remove(K),
     #[allow(clippy::type_complexity)]
     Arbitrary(SyncWrapper<Box<dyn FnMut(bool, &mut BTreeMap<K, V>) + Send>>),
@@ -57,6 +100,14 @@ where
             MapOp::Insert(key, value) => {
                 buffer.insert(key.split(), value.split());
             }
+            MapOp::Extend(items) => {
+                extend_sorted(
+                    buffer,
+                    items
+                        .iter_mut()
+                        .map(|(key, value)| (key.split(), value.split())),
+                );
+            }
             MapOp::Remove(key) => {
                 buffer.remove(key);
             }
@@ -70,6 +121,7 @@ where
             MapOp::Insert(key, value) => {
                 buffer.insert(key, value);
             }
+            MapOp::Extend(items) => extend_sorted(buffer, items),
             MapOp::Remove(ref key) => {
                 buffer.remove(key);
             }
@@ -79,14 +131,37 @@ where
     }
 }
 
+/// extend the map with sorted items, later duplicates overwrite earlier ones
+fn extend_sorted<K: Ord, V>(buffer: &mut BTreeMap<K, V>, items: impl IntoIterator<Item = (K, V)>) {
+    if buffer.is_empty() {
+        // collecting into a new map uses a bulk build which is linear for sorted input
+        *buffer = items.into_iter().collect();
+    } else {
+        buffer.extend(items);
+    }
+}
+
+fn is_sorted_by_key<K: Ord, V>(items: &[(K, V)]) -> bool {
+    items.windows(2).all(|pair| pair[0].0 <= pair[1].0)
+}
+
 impl<K, V> CBTreeMap<K, V> {
     pub fn new() -> Self {
         Self::from_maps(BTreeMap::new(), BTreeMap::new())
     }
+
+    /// Create a map which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> CBTreeMap<K, V, P>
+    where
+        P: Strategy<ValidationError = Infallible> + Default,
+    {
+        CBTreeMap::default()
+    }
 }
 
-impl<K, V, Strat> Default for CBTreeMap<K, V, Strat>
+impl<K, V, Strat, B> Default for CBTreeMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>> + FromBuffers,
     Strat: Strategy<ValidationError = Infallible> + Default,
 {
     fn default() -> Self {
@@ -94,40 +169,166 @@ where
     }
 }
 
-impl<K, V, Strat> CBTreeMap<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>> + FromBuffers,
     Strat: Strategy<ValidationError = Infallible> + Default,
 {
+    /// Create a map from the two buffers
+    ///
+    /// **The two maps must be equal.** Each operation is applied to both maps, so a difference
+    /// is never repaired, and readers will see one map or the other depending on which was
+    /// published last. Use [`from_maps_checked`](Self::from_maps_checked) if you aren't sure
     pub fn from_maps(front: BTreeMap<K, V>, back: BTreeMap<K, V>) -> Self {
         Self::from_raw_parts(front, back, Strat::default())
     }
+
+    /// Create a map from the two buffers, or return an error if they aren't equal
+    pub fn from_maps_checked(
+        front: BTreeMap<K, V>,
+        back: BTreeMap<K, V>,
+    ) -> Result<Self, BuffersDiffer>
+    where
+        K: PartialEq,
+        V: PartialEq,
+    {
+        crate::op_writer_checked(front, back).map(|inner| Self { inner })
+    }
+
+    /// Build both maps directly from an iterator sorted by key, without going through the op log
+    ///
+    /// If a key is duplicated, the last value wins
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self
+    where
+        K: Ord + Split,
+        V: Split,
+    {
+        let mut items = iter.into_iter().collect::<Vec<_>>();
+        debug_assert!(is_sorted_by_key(&items), "items must be sorted by key");
+        let front = items
+            .iter_mut()
+            .map(|(key, value)| (key.split(), value.split()))
+            .collect();
+        let back = items.into_iter().collect();
+        Self::from_maps(front, back)
+    }
 }
 
-impl<K, V, Strat> CBTreeMap<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
-    pub fn from_raw_parts(front: BTreeMap<K, V>, back: BTreeMap<K, V>, strategy: Strat) -> Self {
+    pub fn from_raw_parts(front: BTreeMap<K, V>, back: BTreeMap<K, V>, strategy: Strat) -> Self
+    where
+        B: FromBuffers,
+    {
         Self {
             inner: dbuf::op::OpWriter::from(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
-                dbuf::raw::Shared::from_raw_parts(strategy, dbuf::raw::RawDBuf::new(front, back)),
+                dbuf::raw::Shared::from_raw_parts(strategy, B::from_buffers(front, back)),
             ))),
         }
     }
 
-    pub fn reader(&self) -> CBTreeMapReader<K, V, Strat> {
+    pub fn reader(&self) -> CBTreeMapReader<K, V, Strat, B> {
         CBTreeMapReader {
             inner: self.inner.reader(),
         }
     }
 
+    /// Create a reader which doesn't keep the maps alive
+    ///
+    /// see [`CMap::weak_reader`](crate::CMap::weak_reader) for details
+    pub fn weak_reader(&self) -> CBTreeMapWeakReader<K, V, Strat, B> {
+        CBTreeMapWeakReader {
+            inner: self.inner.reader().into_weak(),
+        }
+    }
+
+    /// The readers which currently hold a read guard, useful for finding out who is blocking a publish
+    pub fn debug_blocking_readers(&self) -> Vec<ActiveReaderInfo>
+    where
+        Strat: StrategyIntrospect,
+    {
+        self.inner.debug_active_readers()
+    }
+
     pub fn load(&self) -> &BTreeMap<K, V> {
         self.inner.split().reader
     }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+    {
+        self.inner.split().reader.get(key)
+    }
+
+    pub fn unapplied(&self) -> &[MapOp<K, V>] {
+        self.inner.unapplied()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Wait until every read guard which was acquired before this call has been dropped
+    ///
+    /// After a publish, this means no reader can still see the previous version of the map,
+    /// so anything which was only referenced by that version can be safely retired
+    pub fn wait_readers_caught_up(&mut self) {
+        self.inner.wait_for_quiescence();
+    }
+
+    /// Move the maps to a different strategy without copying them
+    ///
+    /// This fails if there are any [`CBTreeMapReader`]s, since they use the old strategy.
+    /// [`CBTreeMapWeakReader`]s don't prevent the move, but will return [`ReadError::WriterGone`] afterwards.
+    /// The unpublished operations are carried over to the new map
+    pub fn try_map_strategy<Strat2>(
+        self,
+        f: impl FnOnce(Strat) -> Strat2,
+    ) -> Result<CBTreeMap<K, V, Strat2, B>, Self>
+    where
+        Strat2: Strategy<ValidationError = Infallible>,
+    {
+        self.inner
+            .try_map_writer(|writer| {
+                let shared = writer.try_into_shared()?;
+                Ok(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                    shared.map_strategy(f),
+                )))
+            })
+            .map(|inner| CBTreeMap { inner })
+            .map_err(|inner| Self { inner })
+    }
+
+    /// Move the maps to the default thread-safe strategy without copying them
+    ///
+    /// see [`try_map_strategy`](Self::try_map_strategy)
+    pub fn try_into_sync(self) -> Result<CBTreeMap<K, V, DefaultStrat, B>, Self> {
+        self.try_map_strategy(|_| DefaultStrat::default())
+    }
+}
+
+impl<K, V, Strat, B> CBTreeMap<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    K: Clone,
+    V: Clone,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Repair the maps after an operation panicked, see [`CMap::clear_poison`](crate::CMap::clear_poison)
+    pub fn clear_poison(&mut self) {
+        self.inner
+            .clear_poison_with(|writer, reader| writer.clone_from(reader))
+    }
 }
 
-impl<K, V, Strat> CBTreeMap<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     K: Ord + Split,
     V: Split,
     Strat: Strategy<ValidationError = Infallible>,
@@ -136,16 +337,16 @@ where
         self.inner.apply(MapOp::Insert(key, value));
     }
 
-    pub fn remove(&mut self, key: K) {
-        self.inner.apply(MapOp::Remove(key));
+    /// Insert all the items, which must be sorted by key, as a single operation
+    ///
+    /// If a key is duplicated, the last value wins
+    pub fn bulk_load_sorted(&mut self, items: Vec<(K, V)>) {
+        debug_assert!(is_sorted_by_key(&items), "items must be sorted by key");
+        self.inner.apply(MapOp::Extend(items));
     }
 
-    pub fn get<Q>(&self, key: &Q) -> Option<&V>
-    where
-        Q: ?Sized + Ord,
-        K: Borrow<Q>,
-    {
-        self.inner.split().reader.get(key)
+    pub fn remove(&mut self, key: K) {
+        self.inner.apply(MapOp::Remove(key));
     }
 
     pub fn clear(&mut self) {
@@ -158,10 +359,6 @@ where
         ))))
     }
 
-    pub fn unapplied(&self) -> &[MapOp<K, V>] {
-        self.inner.unapplied()
-    }
-
     pub fn force_publish(&mut self) {
         self.inner.swap_buffers();
     }
@@ -171,8 +368,22 @@ where
     }
 }
 
-impl<K, V, Strat> Clone for CBTreeMapReader<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    K: Ord + Split,
+    V: Split + PartialEq,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Check that the two maps are equal, see [`CMap::verify_consistent`](crate::CMap::verify_consistent)
+    pub fn verify_consistent(&mut self) -> Consistency {
+        self.inner.verify_buffers_eq()
+    }
+}
+
+impl<K, V, Strat, B> Clone for CBTreeMapReader<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     fn clone(&self) -> Self {
@@ -182,17 +393,23 @@ where
     }
 }
 
-impl<K, V, Strat> CBTreeMapReader<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMapReader<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
-    pub fn load(&mut self) -> CBTreeMapReadGuard<K, V, Strat> {
+    /// Check if both readers read from the same map
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    pub fn load(&mut self) -> CBTreeMapReadGuard<K, V, Strat, BTreeMap<K, V>, B> {
         CBTreeMapReadGuard {
             inner: self.inner.get(),
         }
     }
 
-    pub fn get<Q>(&mut self, key: &Q) -> Option<CBTreeMapReadGuard<K, V, Strat, V>>
+    pub fn get<Q>(&mut self, key: &Q) -> Option<CBTreeMapReadGuard<K, V, Strat, V, B>>
     where
         Q: ?Sized + Ord,
         K: Ord + Borrow<Q>,
@@ -201,8 +418,9 @@ where
     }
 }
 
-impl<K, V, Strat, T: ?Sized> Deref for CBTreeMapReadGuard<'_, K, V, Strat, T>
+impl<K, V, Strat, T: ?Sized, B> Deref for CBTreeMapReadGuard<'_, K, V, Strat, T, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     type Target = T;
@@ -212,36 +430,276 @@ where
     }
 }
 
-impl<'a, K, V, Strat, T: ?Sized> CBTreeMapReadGuard<'a, K, V, Strat, T>
+impl<'a, K, V, Strat, T: ?Sized, B> CBTreeMapReadGuard<'a, K, V, Strat, T, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
     pub fn map<U: ?Sized>(
         self,
         f: impl FnOnce(&T) -> &U,
-    ) -> CBTreeMapReadGuard<'a, K, V, Strat, U> {
+    ) -> CBTreeMapReadGuard<'a, K, V, Strat, U, B> {
         CBTreeMapReadGuard {
             inner: dbuf::raw::ReadGuard::map(self.inner, f),
         }
     }
 
+    pub fn map_with_parent<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CBTreeMapZoomGuard<'a, K, V, Strat, T, U, B> {
+        CBTreeMapZoomGuard {
+            inner: self.inner.map_with_parent(f),
+        }
+    }
+
     pub fn try_map<U: ?Sized>(
         self,
         f: impl FnOnce(&T) -> Option<&U>,
-    ) -> Result<CBTreeMapReadGuard<'a, K, V, Strat, U>, Self> {
+    ) -> Result<CBTreeMapReadGuard<'a, K, V, Strat, U, B>, Self> {
         match dbuf::raw::ReadGuard::try_map(self.inner, f) {
             Ok(inner) => Ok(CBTreeMapReadGuard { inner }),
             Err(inner) => Err(CBTreeMapReadGuard { inner }),
         }
     }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CBTreeMapReadGuard<'a, K, V, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CBTreeMapReadGuard { inner }),
+            Err((inner, err)) => Err((CBTreeMapReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CBTreeMapReadGuard { inner }),
+            Err(inner) => Err(CBTreeMapReadGuard { inner }),
+        }
+    }
 }
 
-impl<K, V, Strat, T: ?Sized + core::fmt::Debug> core::fmt::Debug
-    for CBTreeMapReadGuard<'_, K, V, Strat, T>
+impl<K, V, Strat, T: ?Sized, U: ?Sized, B> Deref for CBTreeMapZoomGuard<'_, K, V, Strat, T, U, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, Strat, T: ?Sized, U: ?Sized, B> CBTreeMapZoomGuard<'a, K, V, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn zoomed(&self) -> &U {
+        self.inner.zoomed()
+    }
+
+    pub fn parent(&self) -> &T {
+        self.inner.parent()
+    }
+
+    pub fn rezoom<W: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &W,
+    ) -> CBTreeMapZoomGuard<'a, K, V, Strat, T, W, B> {
+        CBTreeMapZoomGuard {
+            inner: self.inner.rezoom(f),
+        }
+    }
+
+    pub fn into_parent(self) -> CBTreeMapReadGuard<'a, K, V, Strat, T, B> {
+        CBTreeMapReadGuard {
+            inner: self.inner.into_parent(),
+        }
+    }
+}
+
+impl<K, V, Strat, T: ?Sized, U: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CBTreeMapZoomGuard<'_, K, V, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        U::fmt(self, f)
+    }
+}
+
+impl<K, V, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CBTreeMapReadGuard<'_, K, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         T::fmt(self, f)
     }
 }
+
+impl<K, V, Strat, B> Clone for CBTreeMapWeakReader<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V, Strat, B> CBTreeMapWeakReader<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Create another weak reader to the same map
+    ///
+    /// Unlike `clone`, this returns [`ReadError::WriterGone`] right away if the map was already dropped
+    pub fn try_fork(&self) -> Result<Self, ReadError> {
+        Ok(Self {
+            inner: self.inner.try_fork()?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn load(
+        &mut self,
+    ) -> Result<CBTreeMapWeakReadGuard<K, V, Strat, BTreeMap<K, V>, B>, ReadError> {
+        Ok(CBTreeMapWeakReadGuard {
+            inner: self.inner.checked_get()?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn get<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Result<Option<CBTreeMapWeakReadGuard<K, V, Strat, V, B>>, ReadError>
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+    {
+        Ok(self.load()?.try_map(|map| map.get(key)).ok())
+    }
+}
+
+impl<K, V, Strat, T: ?Sized, B> Deref for CBTreeMapWeakReadGuard<'_, K, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, Strat, T: ?Sized, B> CBTreeMapWeakReadGuard<'a, K, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CBTreeMapWeakReadGuard<'a, K, V, Strat, U, B> {
+        CBTreeMapWeakReadGuard {
+            inner: dbuf::raw::ReadGuard::map(self.inner, f),
+        }
+    }
+
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<CBTreeMapWeakReadGuard<'a, K, V, Strat, U, B>, Self> {
+        match dbuf::raw::ReadGuard::try_map(self.inner, f) {
+            Ok(inner) => Ok(CBTreeMapWeakReadGuard { inner }),
+            Err(inner) => Err(CBTreeMapWeakReadGuard { inner }),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CBTreeMapWeakReadGuard<'a, K, V, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CBTreeMapWeakReadGuard { inner }),
+            Err((inner, err)) => Err((CBTreeMapWeakReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CBTreeMapWeakReadGuard { inner }),
+            Err(inner) => Err(CBTreeMapWeakReadGuard { inner }),
+        }
+    }
+}
+
+impl<K, V, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CBTreeMapWeakReadGuard<'_, K, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, V>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+#[test]
+fn bulk_load_sorted() {
+    let mut map: CBTreeMap<_, _> = CBTreeMap::from_sorted_iter([(0, 'a'), (1, 'b'), (1, 'c')]);
+    let mut reader = map.reader();
+
+    assert_eq!(*reader.get(&1).unwrap(), 'c');
+
+    map.bulk_load_sorted(vec![(1, 'd'), (2, 'e'), (3, 'f')]);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.bulk_load_sorted(vec![(4, 'g')]);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    let expected = [(0, 'a'), (1, 'd'), (2, 'e'), (3, 'f'), (4, 'g')];
+    assert!(reader.load().iter().map(|(&k, &v)| (k, v)).eq(expected));
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert!(reader.load().iter().map(|(&k, &v)| (k, v)).eq(expected));
+}
@@ -6,13 +6,14 @@ use std::{
     collections::{btree_map::Entry, BTreeMap},
     convert::Infallible,
     fmt,
+    hash::{Hash, Hasher},
     ops::Deref,
 };
 
-use dbuf::interface::Strategy;
+use dbuf::interface::{FromBuffers, RawBuffers, Strategy, StrategyIntrospect};
 use sync_wrapper::SyncWrapper;
 
-use crate::split::Split;
+use crate::{split::Split, ActiveReaderInfo, BufferId, BuffersDiffer, Consistency, ReadError};
 
 pub mod ordbag;
 
@@ -20,6 +21,17 @@ pub struct Bag<T> {
     inner: BagInner<T>,
 }
 
+impl<T: Clone + Ord> Clone for Bag<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: match &self.inner {
+                BagInner::One(one) => BagInner::One(one.clone()),
+                BagInner::Many(many) => BagInner::Many(many.clone()),
+            },
+        }
+    }
+}
+
 impl<T> Default for Bag<T> {
     fn default() -> Self {
         Self {
@@ -31,7 +43,7 @@ impl<T> Default for Bag<T> {
 impl<T> Bag<T> {
     pub fn get_one(&self) -> Option<&T> {
         match &self.inner {
-            BagInner::One(None) => None,
+            BagInner::One(None) | BagInner::One(Some((_, 0))) => None,
             BagInner::One(Some((inner, _))) => Some(inner),
             BagInner::Many(many) => many.iter().next(),
         }
@@ -56,30 +68,87 @@ impl<T> Bag<T> {
             BagInner::Many(bag) => bag.len(),
         }
     }
+
+    pub fn set_iter(&self) -> impl Iterator<Item = (&T, usize)> + '_ {
+        let (one, many) = match &self.inner {
+            BagInner::One(Some((value, count))) if *count > 0 => (Some((value, *count)), None),
+            BagInner::One(_) => (None, None),
+            BagInner::Many(bag) => (None, Some(bag.set_iter())),
+        };
+
+        one.into_iter().chain(many.into_iter().flatten())
+    }
 }
 
 impl<T: Ord> Bag<T> {
     pub fn insert(&mut self, value: T) {
+        self.insert_many(value, 1)
+    }
+
+    /// insert `count` copies of `value`
+    ///
+    /// The total count of the bag saturates at `usize::MAX`, any copies past that are ignored
+    pub fn insert_many(&mut self, value: T, count: usize) {
+        let count = count.min(usize::MAX - self.len());
+        if count == 0 {
+            return;
+        }
+
         match self.inner {
-            BagInner::One(None) => self.inner = BagInner::One(Some((value, 1))),
-            BagInner::One(Some((ref inner, ref mut count))) if *inner == value => *count += 1,
+            BagInner::One(None) | BagInner::One(Some((_, 0))) => {
+                self.inner = BagInner::One(Some((value, count)))
+            }
+            BagInner::One(Some((ref inner, ref mut n))) if *inner == value => *n += count,
             BagInner::One(Some(_)) => {
-                let (inner, count) = match core::mem::take(self).inner {
-                    BagInner::One(Some((value, count))) => (value, count),
+                let (inner, n) = match core::mem::take(self).inner {
+                    BagInner::One(Some((value, n))) => (value, n),
                     _ => unreachable!(),
                 };
-                self.inner = BagInner::One(None);
                 let mut bag = OrdBag::new();
-                bag.insert_many(inner, count);
-                bag.insert(value);
+                bag.insert_many(inner, n);
+                bag.insert_many(value, count);
                 self.inner = BagInner::Many(bag);
             }
             BagInner::Many(ref mut bag) => {
-                bag.insert(value);
+                bag.insert_many(value, count);
+            }
+        }
+    }
+
+    /// set the number of copies of `value` to exactly `count`, removing it if `count` is zero
+    ///
+    /// Like [`insert_many`](Self::insert_many), the total count of the bag saturates at `usize::MAX`
+    pub fn set_count(&mut self, value: T, count: usize) {
+        self.remove_all(&value);
+        self.insert_many(value, count);
+    }
+
+    /// remove every copy of `value`
+    pub fn remove_all(&mut self, value: &T) {
+        match self.inner {
+            BagInner::One(Some((ref inner, _))) if inner == value => {
+                self.inner = BagInner::One(None)
+            }
+            BagInner::One(_) => (),
+            BagInner::Many(ref mut bag) => {
+                bag.take_all(value);
+                // go back to the compact representation once there's at most one value left
+                if bag.set_len() <= 1 {
+                    let one = core::mem::take(bag).into_iter().next();
+                    self.inner = BagInner::One(one);
+                }
             }
         }
     }
 
+    pub fn contains(&self, value: &T) -> usize {
+        match self.inner {
+            BagInner::One(Some((ref inner, count))) if inner == value => count,
+            BagInner::One(_) => 0,
+            BagInner::Many(ref bag) => bag.contains(value),
+        }
+    }
+
     pub fn remove(&mut self, value: &T) {
         match self.inner {
             BagInner::One(Some((ref inner, ref mut count))) if inner == value && *count > 0 => {
@@ -103,46 +172,156 @@ impl<T: Ord> Bag<T> {
     }
 }
 
+impl<T: Ord> PartialEq for Bag<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.set_iter().eq(other.set_iter())
+    }
+}
+
+impl<T: Ord> Eq for Bag<T> {}
+
+impl<T: Hash> Hash for Bag<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for (value, count) in self.set_iter() {
+            value.hash(state);
+            count.hash(state);
+        }
+    }
+}
+
+impl<T: Ord> Extend<T> for Bag<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value)
+        }
+    }
+}
+
+impl<T: Ord> FromIterator<T> for Bag<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut bag = Self::default();
+        bag.extend(iter);
+        bag
+    }
+}
+
+/// serialized as a sequence of `(value, count)` pairs, like `OrdBag`
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Bag<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.set_iter())
+    }
+}
+
+/// deserialized from a sequence of `(value, count)` pairs, the counts of duplicate values are merged
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de> + Ord> serde::Deserialize<'de> for Bag<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut bag = Self::default();
+        for (value, count) in <Vec<(T, usize)> as serde::Deserialize>::deserialize(deserializer)? {
+            bag.insert_many(value, count);
+        }
+        Ok(bag)
+    }
+}
+
 enum BagInner<T> {
     One(Option<(T, usize)>),
     Many(OrdBag<T>),
 }
 
-pub struct CBTreeMultiMap<K, V = DefaultHasher, Strat = DefaultStrat>
-where
+pub struct CBTreeMultiMap<
+    K,
+    V = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::op::OpWriter<
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>>,
-        MapOp<K, V>,
-    >,
+    inner: dbuf::op::OpWriter<dbuf::ptrs::alloc::OwnedPtr<Strat, B>, MapOp<K, V>>,
 }
 
-pub struct CBTreeMultiMapReader<K, V = DefaultHasher, Strat = DefaultStrat>
-where
+pub struct CBTreeMultiMapReader<
+    K,
+    V = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::raw::Reader<
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>>,
-    >,
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
 }
 
-pub struct CBTreeMapReadGuard<'a, K, V, Strat = DefaultStrat, T: ?Sized = BTreeMap<K, Bag<V>>>
-where
+pub struct CBTreeMapReadGuard<
+    'a,
+    K,
+    V,
+    Strat = DefaultStrat,
+    T: ?Sized = BTreeMap<K, Bag<V>>,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T>,
+}
+
+pub struct CBTreeMapZoomGuard<
+    'a,
+    K,
+    V,
+    Strat,
+    T: ?Sized,
+    U: ?Sized,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ZoomGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T, U>,
+}
+
+pub struct CBTreeMultiMapWeakReader<
+    K,
+    V,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedWeak<Strat, B>>,
+}
+
+pub struct CBTreeMultiMapWeakReadGuard<
+    'a,
+    K,
+    V,
+    Strat = DefaultStrat,
+    T = BTreeMap<K, Bag<V>>,
+    B = dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>,
+> where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    T: ?Sized,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::raw::ReadGuard<
-        'a,
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<BTreeMap<K, Bag<V>>>>,
-        T,
-    >,
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedStrong<Strat, B>, T>,
 }
 
 pub enum MapOp<K, V> {
     Insert(K, V),
+    /// insert the value this many times, see [`Bag::insert_many`]
+    InsertN(K, V, usize),
+    /// set how many times the value is in the key's bag, see [`Bag::set_count`]
+    SetCount(K, V, usize),
     Clear(K),
     Remove(K, V),
     #[allow(clippy::type_complexity)]
@@ -155,6 +334,16 @@ pub enum MapOp<K, V> {
     Purge,
 }
 
+/// remove every copy of the value from the key's bag, and the key if its bag is empty afterwards
+fn remove_all<K: Ord, V: Ord>(buffer: &mut BTreeMap<K, Bag<V>>, key: &K, value: &V) {
+    if let Some(bag) = buffer.get_mut(key) {
+        bag.remove_all(value);
+        if bag.is_empty() {
+            buffer.remove(key);
+        }
+    }
+}
+
 impl<K, V> dbuf::op_log::Operation<BTreeMap<K, Bag<V>>> for MapOp<K, V>
 where
     K: Ord + Split,
@@ -168,6 +357,21 @@ where
                     .or_insert_with(Bag::default)
                     .insert(value.split());
             }
+            // inserting a value zero times would leave an empty bag
+            MapOp::InsertN(_, _, 0) => (),
+            MapOp::InsertN(key, value, count) => {
+                buffer
+                    .entry(key.split())
+                    .or_insert_with(Bag::default)
+                    .insert_many(value.split(), *count);
+            }
+            MapOp::SetCount(key, value, 0) => remove_all(buffer, key, value),
+            MapOp::SetCount(key, value, count) => {
+                buffer
+                    .entry(key.split())
+                    .or_insert_with(Bag::default)
+                    .set_count(value.split(), *count);
+            }
             MapOp::Clear(key) => {
                 buffer.remove(key);
             }
@@ -188,6 +392,20 @@ where
             MapOp::Insert(key, value) => {
                 buffer.entry(key).or_insert_with(Bag::default).insert(value);
             }
+            MapOp::InsertN(_, _, 0) => (),
+            MapOp::InsertN(key, value, count) => {
+                buffer
+                    .entry(key)
+                    .or_insert_with(Bag::default)
+                    .insert_many(value, count);
+            }
+            MapOp::SetCount(key, value, 0) => remove_all(buffer, &key, &value),
+            MapOp::SetCount(key, value, count) => {
+                buffer
+                    .entry(key)
+                    .or_insert_with(Bag::default)
+                    .set_count(value, count);
+            }
             MapOp::Clear(key) => {
                 buffer.remove(&key);
             }
@@ -208,10 +426,19 @@ impl<K, V> CBTreeMultiMap<K, V> {
     pub fn new() -> Self {
         Self::from_maps(BTreeMap::new(), BTreeMap::new())
     }
+
+    /// Create a map which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> CBTreeMultiMap<K, V, P>
+    where
+        P: Strategy<ValidationError = Infallible> + Default,
+    {
+        CBTreeMultiMap::default()
+    }
 }
 
-impl<K, V, Strat> Default for CBTreeMultiMap<K, V, Strat>
+impl<K, V, Strat, B> Default for CBTreeMultiMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>> + FromBuffers,
     Strat: Strategy<ValidationError = Infallible> + Default,
 {
     fn default() -> Self {
@@ -219,44 +446,170 @@ where
     }
 }
 
-impl<K, V, Strat> CBTreeMultiMap<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMultiMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>> + FromBuffers,
     Strat: Strategy<ValidationError = Infallible> + Default,
 {
+    /// Create a map from the two buffers
+    ///
+    /// **The two maps must be equal.** Each operation is applied to both maps, so a difference
+    /// is never repaired, and readers will see one map or the other depending on which was
+    /// published last. Use [`from_maps_checked`](Self::from_maps_checked) if you aren't sure
     pub fn from_maps(front: BTreeMap<K, Bag<V>>, back: BTreeMap<K, Bag<V>>) -> Self {
         Self::from_raw_parts(front, back, Strat::default())
     }
+
+    /// Create a map from the two buffers, or return an error if they aren't equal
+    pub fn from_maps_checked(
+        front: BTreeMap<K, Bag<V>>,
+        back: BTreeMap<K, Bag<V>>,
+    ) -> Result<Self, BuffersDiffer>
+    where
+        K: PartialEq,
+        V: Ord,
+    {
+        crate::op_writer_checked(front, back).map(|inner| Self { inner })
+    }
 }
 
-impl<K, V, Strat> CBTreeMultiMap<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMultiMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     pub fn from_raw_parts(
         front: BTreeMap<K, Bag<V>>,
         back: BTreeMap<K, Bag<V>>,
         strategy: Strat,
-    ) -> Self {
+    ) -> Self
+    where
+        B: FromBuffers,
+    {
         Self {
             inner: dbuf::op::OpWriter::from(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
-                dbuf::raw::Shared::from_raw_parts(strategy, dbuf::raw::RawDBuf::new(front, back)),
+                dbuf::raw::Shared::from_raw_parts(strategy, B::from_buffers(front, back)),
             ))),
         }
     }
 
-    pub fn reader(&self) -> CBTreeMultiMapReader<K, V, Strat> {
+    pub fn reader(&self) -> CBTreeMultiMapReader<K, V, Strat, B> {
         CBTreeMultiMapReader {
             inner: self.inner.reader(),
         }
     }
 
+    /// Create a reader which doesn't keep the maps alive
+    ///
+    /// see [`CMap::weak_reader`](crate::CMap::weak_reader) for details
+    pub fn weak_reader(&self) -> CBTreeMultiMapWeakReader<K, V, Strat, B> {
+        CBTreeMultiMapWeakReader {
+            inner: self.inner.reader().into_weak(),
+        }
+    }
+
+    /// The readers which currently hold a read guard, useful for finding out who is blocking a publish
+    pub fn debug_blocking_readers(&self) -> Vec<ActiveReaderInfo>
+    where
+        Strat: StrategyIntrospect,
+    {
+        self.inner.debug_active_readers()
+    }
+
     pub fn load(&self) -> &BTreeMap<K, Bag<V>> {
         self.inner.split().reader
     }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&Bag<V>>
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+    {
+        self.inner.split().reader.get(key)
+    }
+
+    pub fn get_one<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+    {
+        self.inner.split().reader.get(key)?.get_one()
+    }
+
+    /// how many times the value is in the key's bag, in the published map
+    pub fn count<Q>(&self, key: &Q, value: &V) -> usize
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+        V: Ord,
+    {
+        self.get(key).map_or(0, |bag| bag.contains(value))
+    }
+
+    pub fn unapplied(&self) -> &[MapOp<K, V>] {
+        self.inner.unapplied()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Wait until every read guard which was acquired before this call has been dropped
+    ///
+    /// After a publish, this means no reader can still see the previous version of the map,
+    /// so anything which was only referenced by that version can be safely retired
+    pub fn wait_readers_caught_up(&mut self) {
+        self.inner.wait_for_quiescence();
+    }
+
+    /// Move the maps to a different strategy without copying them
+    ///
+    /// This fails if there are any [`CBTreeMultiMapReader`]s, since they use the old strategy.
+    /// [`CBTreeMultiMapWeakReader`]s don't prevent the move, but will return [`ReadError::WriterGone`] afterwards.
+    /// The unpublished operations are carried over to the new map
+    pub fn try_map_strategy<Strat2>(
+        self,
+        f: impl FnOnce(Strat) -> Strat2,
+    ) -> Result<CBTreeMultiMap<K, V, Strat2, B>, Self>
+    where
+        Strat2: Strategy<ValidationError = Infallible>,
+    {
+        self.inner
+            .try_map_writer(|writer| {
+                let shared = writer.try_into_shared()?;
+                Ok(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                    shared.map_strategy(f),
+                )))
+            })
+            .map(|inner| CBTreeMultiMap { inner })
+            .map_err(|inner| Self { inner })
+    }
+
+    /// Move the maps to the default thread-safe strategy without copying them
+    ///
+    /// see [`try_map_strategy`](Self::try_map_strategy)
+    pub fn try_into_sync(self) -> Result<CBTreeMultiMap<K, V, DefaultStrat, B>, Self> {
+        self.try_map_strategy(|_| DefaultStrat::default())
+    }
 }
 
-impl<K, V, Strat> CBTreeMultiMap<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMultiMap<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    K: Clone,
+    V: Clone + Ord,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Repair the maps after an operation panicked, see [`CMap::clear_poison`](crate::CMap::clear_poison)
+    pub fn clear_poison(&mut self) {
+        self.inner
+            .clear_poison_with(|writer, reader| writer.clone_from(reader))
+    }
+}
+
+impl<K, V, Strat, B> CBTreeMultiMap<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
     K: Ord + Split,
     V: Split + Ord,
@@ -269,20 +622,16 @@ where
         self.inner.apply(MapOp::Remove(key, value));
     }
 
-    pub fn get<Q>(&self, key: &Q) -> Option<&Bag<V>>
-    where
-        Q: ?Sized + Ord,
-        K: Borrow<Q>,
-    {
-        self.inner.split().reader.get(key)
+    /// insert the value `count` times, see [`Bag::insert_many`]
+    pub fn insert_n(&mut self, key: K, value: V, count: usize) {
+        self.inner.apply(MapOp::InsertN(key, value, count));
     }
 
-    pub fn get_one<Q>(&self, key: &Q) -> Option<&V>
-    where
-        Q: ?Sized + Ord,
-        K: Borrow<Q>,
-    {
-        self.inner.split().reader.get(key)?.get_one()
+    /// set how many times the value is in the key's bag, in a single operation
+    ///
+    /// Setting the count to zero removes the value, and the key if its bag is empty afterwards
+    pub fn set_count(&mut self, key: K, value: V, count: usize) {
+        self.inner.apply(MapOp::SetCount(key, value, count));
     }
 
     pub fn purge(&mut self) {
@@ -332,10 +681,6 @@ where
         ))
     }
 
-    pub fn unapplied(&self) -> &[MapOp<K, V>] {
-        self.inner.unapplied()
-    }
-
     pub fn force_publish(&mut self) {
         self.inner.swap_buffers();
     }
@@ -345,8 +690,22 @@ where
     }
 }
 
-impl<K, V, Strat> Clone for CBTreeMultiMapReader<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMultiMap<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    K: Ord + Split,
+    V: Split + Ord,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Check that the two maps are equal, see [`CMap::verify_consistent`](crate::CMap::verify_consistent)
+    pub fn verify_consistent(&mut self) -> Consistency {
+        self.inner.verify_buffers_eq()
+    }
+}
+
+impl<K, V, Strat, B> Clone for CBTreeMultiMapReader<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     fn clone(&self) -> Self {
@@ -356,17 +715,23 @@ where
     }
 }
 
-impl<K, V, Strat> CBTreeMultiMapReader<K, V, Strat>
+impl<K, V, Strat, B> CBTreeMultiMapReader<K, V, Strat, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
-    pub fn load(&mut self) -> CBTreeMapReadGuard<K, V, Strat> {
+    /// Check if both readers read from the same map
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    pub fn load(&mut self) -> CBTreeMapReadGuard<K, V, Strat, BTreeMap<K, Bag<V>>, B> {
         CBTreeMapReadGuard {
             inner: self.inner.get(),
         }
     }
 
-    pub fn get<Q>(&mut self, key: &Q) -> Option<CBTreeMapReadGuard<K, V, Strat, Bag<V>>>
+    pub fn get<Q>(&mut self, key: &Q) -> Option<CBTreeMapReadGuard<K, V, Strat, Bag<V>, B>>
     where
         Q: ?Sized + Ord,
         K: Ord + Borrow<Q>,
@@ -374,7 +739,7 @@ where
         self.load().try_map(|map| map.get(key)).ok()
     }
 
-    pub fn get_one<Q>(&mut self, key: &Q) -> Option<CBTreeMapReadGuard<K, V, Strat, V>>
+    pub fn get_one<Q>(&mut self, key: &Q) -> Option<CBTreeMapReadGuard<K, V, Strat, V, B>>
     where
         Q: ?Sized + Ord,
         K: Ord + Borrow<Q>,
@@ -383,10 +748,21 @@ where
 
         CBTreeMapReadGuard::try_map(guard, Bag::get_one).ok()
     }
+
+    /// how many times the value is in the key's bag
+    pub fn count<Q>(&mut self, key: &Q, value: &V) -> usize
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+        V: Ord,
+    {
+        self.get(key).map_or(0, |bag| bag.contains(value))
+    }
 }
 
-impl<K, V, Strat, T: ?Sized> Deref for CBTreeMapReadGuard<'_, K, V, Strat, T>
+impl<K, V, Strat, T: ?Sized, B> Deref for CBTreeMapReadGuard<'_, K, V, Strat, T, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     type Target = T;
@@ -396,33 +772,276 @@ where
     }
 }
 
-impl<'a, K, V, Strat, T: ?Sized> CBTreeMapReadGuard<'a, K, V, Strat, T>
+impl<'a, K, V, Strat, T: ?Sized, B> CBTreeMapReadGuard<'a, K, V, Strat, T, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
     pub fn map<U: ?Sized>(
         self,
         f: impl FnOnce(&T) -> &U,
-    ) -> CBTreeMapReadGuard<'a, K, V, Strat, U> {
+    ) -> CBTreeMapReadGuard<'a, K, V, Strat, U, B> {
         CBTreeMapReadGuard {
             inner: dbuf::raw::ReadGuard::map(self.inner, f),
         }
     }
 
+    pub fn map_with_parent<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CBTreeMapZoomGuard<'a, K, V, Strat, T, U, B> {
+        CBTreeMapZoomGuard {
+            inner: self.inner.map_with_parent(f),
+        }
+    }
+
     pub fn try_map<U: ?Sized>(
         self,
         f: impl FnOnce(&T) -> Option<&U>,
-    ) -> Result<CBTreeMapReadGuard<'a, K, V, Strat, U>, Self> {
+    ) -> Result<CBTreeMapReadGuard<'a, K, V, Strat, U, B>, Self> {
         match dbuf::raw::ReadGuard::try_map(self.inner, f) {
             Ok(inner) => Ok(CBTreeMapReadGuard { inner }),
             Err(inner) => Err(CBTreeMapReadGuard { inner }),
         }
     }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CBTreeMapReadGuard<'a, K, V, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CBTreeMapReadGuard { inner }),
+            Err((inner, err)) => Err((CBTreeMapReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CBTreeMapReadGuard { inner }),
+            Err(inner) => Err(CBTreeMapReadGuard { inner }),
+        }
+    }
+}
+
+impl<K, V, Strat, T: ?Sized, U: ?Sized, B> Deref for CBTreeMapZoomGuard<'_, K, V, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, Strat, T: ?Sized, U: ?Sized, B> CBTreeMapZoomGuard<'a, K, V, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn zoomed(&self) -> &U {
+        self.inner.zoomed()
+    }
+
+    pub fn parent(&self) -> &T {
+        self.inner.parent()
+    }
+
+    pub fn rezoom<W: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &W,
+    ) -> CBTreeMapZoomGuard<'a, K, V, Strat, T, W, B> {
+        CBTreeMapZoomGuard {
+            inner: self.inner.rezoom(f),
+        }
+    }
+
+    pub fn into_parent(self) -> CBTreeMapReadGuard<'a, K, V, Strat, T, B> {
+        CBTreeMapReadGuard {
+            inner: self.inner.into_parent(),
+        }
+    }
+}
+
+impl<K, V, Strat, T: ?Sized, U: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CBTreeMapZoomGuard<'_, K, V, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        U::fmt(self, f)
+    }
 }
 
-impl<K, V, Strat, T: ?Sized + core::fmt::Debug> core::fmt::Debug
-    for CBTreeMapReadGuard<'_, K, V, Strat, T>
+impl<K, V, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CBTreeMapReadGuard<'_, K, V, Strat, T, B>
 where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<K, V, Strat, B> Clone for CBTreeMultiMapWeakReader<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V, Strat, B> CBTreeMultiMapWeakReader<K, V, Strat, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Create another weak reader to the same map
+    ///
+    /// Unlike `clone`, this returns [`ReadError::WriterGone`] right away if the map was already dropped
+    pub fn try_fork(&self) -> Result<Self, ReadError> {
+        Ok(Self {
+            inner: self.inner.try_fork()?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn load(
+        &mut self,
+    ) -> Result<CBTreeMultiMapWeakReadGuard<K, V, Strat, BTreeMap<K, Bag<V>>, B>, ReadError> {
+        Ok(CBTreeMultiMapWeakReadGuard {
+            inner: self.inner.checked_get()?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn get<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Result<Option<CBTreeMultiMapWeakReadGuard<K, V, Strat, Bag<V>, B>>, ReadError>
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+    {
+        Ok(self.load()?.try_map(|map| map.get(key)).ok())
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn get_one<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Result<Option<CBTreeMultiMapWeakReadGuard<K, V, Strat, V, B>>, ReadError>
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+    {
+        Ok(self
+            .get(key)?
+            .and_then(|guard| CBTreeMultiMapWeakReadGuard::try_map(guard, Bag::get_one).ok()))
+    }
+
+    /// how many times the value is in the key's bag
+    pub fn count<Q>(&mut self, key: &Q, value: &V) -> Result<usize, ReadError>
+    where
+        Q: ?Sized + Ord,
+        K: Ord + Borrow<Q>,
+        V: Ord,
+    {
+        Ok(self.get(key)?.map_or(0, |bag| bag.contains(value)))
+    }
+}
+
+impl<K, V, Strat, T: ?Sized, B> Deref for CBTreeMultiMapWeakReadGuard<'_, K, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, Strat, T: ?Sized, B> CBTreeMultiMapWeakReadGuard<'a, K, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CBTreeMultiMapWeakReadGuard<'a, K, V, Strat, U, B> {
+        CBTreeMultiMapWeakReadGuard {
+            inner: dbuf::raw::ReadGuard::map(self.inner, f),
+        }
+    }
+
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<CBTreeMultiMapWeakReadGuard<'a, K, V, Strat, U, B>, Self> {
+        match dbuf::raw::ReadGuard::try_map(self.inner, f) {
+            Ok(inner) => Ok(CBTreeMultiMapWeakReadGuard { inner }),
+            Err(inner) => Err(CBTreeMultiMapWeakReadGuard { inner }),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CBTreeMultiMapWeakReadGuard<'a, K, V, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CBTreeMultiMapWeakReadGuard { inner }),
+            Err((inner, err)) => Err((CBTreeMultiMapWeakReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CBTreeMultiMapWeakReadGuard { inner }),
+            Err(inner) => Err(CBTreeMultiMapWeakReadGuard { inner }),
+        }
+    }
+}
+
+impl<K, V, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CBTreeMultiMapWeakReadGuard<'_, K, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = BTreeMap<K, Bag<V>>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -461,6 +1080,26 @@ impl<'a, T> Iterator for BagIter<'a, T> {
             BagIter::Many(many) => many.next(),
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            BagIter::One(None) => (0, Some(0)),
+            BagIter::One(Some((_, count))) => (*count, Some(*count)),
+            BagIter::Many(many) => many.size_hint(),
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for BagIter<'_, T> {}
+impl<T> core::iter::FusedIterator for BagIter<'_, T> {}
+
+impl<T> Clone for BagIter<'_, T> {
+    fn clone(&self) -> Self {
+        match self {
+            BagIter::One(one) => BagIter::One(*one),
+            BagIter::Many(many) => BagIter::Many(many.clone()),
+        }
+    }
 }
 
 impl<T: fmt::Debug> fmt::Debug for Bag<T> {
@@ -468,3 +1107,114 @@ impl<T: fmt::Debug> fmt::Debug for Bag<T> {
         f.debug_list().entries(self).finish()
     }
 }
+
+#[test]
+fn bag_iter_is_exact_size() {
+    let mut one = Bag::default();
+    one.insert_many(1, 3);
+    let mut iter = one.iter();
+    assert_eq!(iter.len(), 3);
+    iter.next();
+    assert_eq!(iter.clone().count(), 2);
+    assert_eq!(iter.len(), 2);
+
+    // tombstones are empty
+    one.retain(|_, _| 0);
+    assert_eq!(one.iter().len(), 0);
+
+    let many = [1, 2, 2].into_iter().collect::<Bag<_>>();
+    assert_eq!(many.iter().len(), 3);
+    assert_eq!(many.iter().copied().collect::<Vec<_>>(), [1, 2, 2]);
+}
+
+#[test]
+fn set_count_and_insert_n() {
+    let mut map = CBTreeMultiMap::<i32, i32>::new();
+    let mut reader = map.reader();
+
+    map.insert_n(1, 10, 3);
+    map.insert_n(1, 11, 0);
+    map.set_count(2, 20, 2);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.count(&1, &10), 3);
+    assert_eq!(reader.count(&1, &11), 0);
+    assert_eq!(map.count(&2, &20), 2);
+
+    // one value to many, and back to one
+    map.set_count(1, 11, 2);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.count(&1, &10), 3);
+    assert_eq!(reader.count(&1, &11), 2);
+    assert!(matches!(map.get(&1).unwrap().inner, BagInner::Many(_)));
+
+    map.set_count(1, 10, 0);
+    map.set_count(2, 20, 0);
+    map.set_count(3, 30, 0);
+    // publish twice so that both buffers replayed the ops
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert!(matches!(
+        map.get(&1).unwrap().inner,
+        BagInner::One(Some((11, 2)))
+    ));
+    assert_eq!(reader.count(&1, &10), 0);
+    // setting the count to zero prunes empty bags
+    assert!(reader.get(&2).is_none());
+    assert!(reader.get(&3).is_none());
+    assert_eq!(map.load().len(), 1);
+}
+
+#[test]
+fn insert_n_zero_is_a_noop() {
+    let mut map = CBTreeMultiMap::<i32, i32>::new();
+    let mut reader = map.reader();
+
+    map.insert_n(1, 10, 0);
+    map.insert(2, 20);
+    map.insert_n(2, 21, 0);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    // no empty bag for a missing key, in either buffer
+    assert!(reader.get(&1).is_none());
+    assert!(map.get(&1).is_none());
+    assert_eq!(map.load().len(), 1);
+    assert_eq!(reader.count(&2, &20), 1);
+    assert_eq!(reader.count(&2, &21), 0);
+}
+
+#[test]
+fn insert_n_saturates() {
+    let mut bag = Bag::default();
+    bag.insert_many(1, usize::MAX - 1);
+    bag.insert_many(1, 5);
+    assert_eq!(bag.contains(&1), usize::MAX);
+    // the bag is full, so this is ignored
+    bag.insert_many(2, 1);
+    assert_eq!(bag.contains(&2), 0);
+
+    bag.set_count(1, 3);
+    bag.insert_many(2, usize::MAX);
+    assert_eq!(bag.contains(&1), 3);
+    assert_eq!(bag.contains(&2), usize::MAX - 3);
+    assert_eq!(bag.len(), usize::MAX);
+    bag.insert_many(1, 1);
+    assert_eq!(bag.contains(&1), 3);
+
+    let mut map = CBTreeMultiMap::<i32, i32>::new();
+    map.insert_n(1, 10, usize::MAX);
+    map.insert_n(1, 10, usize::MAX);
+    map.insert(1, 11);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(map.count(&1, &10), usize::MAX);
+    assert_eq!(map.count(&1, &11), 0);
+}
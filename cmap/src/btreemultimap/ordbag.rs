@@ -444,6 +444,10 @@ where
     /// ```
     #[inline]
     pub fn insert_many(&mut self, value: T, count: usize) -> usize {
+        if count == 0 {
+            return self.contains(&value);
+        }
+
         self.count += count;
         let n = self.items.entry(value).or_insert(0);
         let was_there = *n;
@@ -784,6 +788,15 @@ where
 
 impl<T> Eq for OrdBag<T> where T: Ord {}
 
+impl<T> std::hash::Hash for OrdBag<T>
+where
+    T: std::hash::Hash,
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.items.hash(state)
+    }
+}
+
 impl<'a, T> Extend<&'a T> for OrdBag<T>
 where
     T: 'a + Ord + Clone,
@@ -801,8 +814,7 @@ where
 {
     fn extend<I: IntoIterator<Item = (&'a T, usize)>>(&mut self, iter: I) {
         for (e, n) in iter {
-            self.count += n;
-            *self.items.entry(e.clone()).or_insert(0) += n;
+            self.insert_many(e.clone(), n);
         }
     }
 }
@@ -824,8 +836,7 @@ where
 {
     fn extend<I: IntoIterator<Item = (T, usize)>>(&mut self, iter: I) {
         for (e, n) in iter {
-            self.count += n;
-            *self.items.entry(e).or_insert(0) += n;
+            self.insert_many(e, n);
         }
     }
 }
@@ -841,6 +852,17 @@ where
     }
 }
 
+impl<T> std::iter::FromIterator<(T, usize)> for OrdBag<T>
+where
+    T: Ord,
+{
+    fn from_iter<I: IntoIterator<Item = (T, usize)>>(iter: I) -> Self {
+        let mut bag = Self::default();
+        bag.extend(iter);
+        bag
+    }
+}
+
 impl<'a, T> IntoIterator for &'a OrdBag<T> {
     type Item = &'a T;
     type IntoIter = Iter<'a, T>;
@@ -1010,6 +1032,16 @@ mod tests {
         println!("{:?}", vikings.clone().into_iter());
     }
 
+    #[test]
+    fn zero_counts_are_not_stored() {
+        let mut bag: OrdBag<_> = [(1, 0), (2, 2)].iter().cloned().collect();
+        assert_eq!(bag.set_len(), 1);
+        assert_eq!(bag.insert_many(3, 0), 0);
+        assert_eq!(bag.set_len(), 1);
+        assert_eq!(bag.remove(&2), 2);
+        assert_eq!(bag, [2].iter().cloned().collect());
+    }
+
     #[test]
     fn sane_iterators() {
         let vikings: OrdBag<&'static str> = ["Einar", "Einar", "Harald"].iter().cloned().collect();
@@ -0,0 +1,140 @@
+use super::OrdBag;
+use core::fmt;
+use core::marker::PhantomData;
+use serde::de::{SeqAccess, Visitor};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::Deserializer;
+use serde::{Deserialize, Serialize};
+
+pub(crate) struct OrdBagVisitor<T> {
+    marker: PhantomData<fn() -> OrdBag<T>>,
+}
+
+impl<T> OrdBagVisitor<T>
+where
+    T: Ord,
+{
+    fn new() -> Self {
+        OrdBagVisitor {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T> Visitor<'de> for OrdBagVisitor<T>
+where
+    T: Deserialize<'de> + Ord,
+{
+    type Value = OrdBag<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("an OrdBag")
+    }
+
+    fn visit_seq<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+    where
+        M: SeqAccess<'de>,
+    {
+        let mut bag: OrdBag<T> = OrdBag::new();
+
+        while let Some(entry) = access.next_element::<(T, usize)>()? {
+            bag.insert_many(entry.0, entry.1);
+        }
+
+        Ok(bag)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for OrdBag<T>
+where
+    T: Deserialize<'de> + Ord,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(OrdBagVisitor::<T>::new())
+    }
+}
+
+impl<T> Serialize for OrdBag<T>
+where
+    T: Serialize + Ord,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut bag = serializer.serialize_seq(Some(self.set_len()))?;
+
+        for (entry, count) in self.set_iter() {
+            bag.serialize_element(&(entry, count))?;
+        }
+
+        bag.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    struct VeryHelpfulStruct {
+        pub(crate) name: String,
+    }
+
+    #[test]
+    fn format_simple_data() {
+        let vikings: OrdBag<String> = ["Einar", "Olaf", "Olaf", "Harald", "Harald", "Harald"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let einar = "Einar".to_string();
+        let olaf = "Olaf".to_string();
+        let harald = "Harald".to_string();
+        assert_eq!(vikings.get(&einar), Some((&einar, 1)));
+        assert_eq!(vikings.get(&olaf), Some((&olaf, 2)));
+        assert_eq!(vikings.get(&harald), Some((&harald, 3)));
+        let jsonified_vikings: String =
+            serde_json::to_string(&vikings).expect("Unable to convert data to json!");
+        assert_eq!(
+            jsonified_vikings,
+            "[[\"Einar\",1],[\"Harald\",3],[\"Olaf\",2]]"
+        );
+        let reconstituted_vikings: OrdBag<String> =
+            serde_json::from_str(&jsonified_vikings).expect("Unable to convert json to ordbag!");
+        assert_eq!(vikings, reconstituted_vikings);
+    }
+
+    #[test]
+    fn format_struct_data() {
+        let vikings: OrdBag<VeryHelpfulStruct> =
+            ["Einar", "Olaf", "Olaf", "Harald", "Harald", "Harald"]
+                .iter()
+                .map(|n| VeryHelpfulStruct {
+                    name: n.to_string(),
+                })
+                .collect();
+        let jsonified_vikings: String =
+            serde_json::to_string(&vikings).expect("Unable to convert data to json!");
+        let reconstituted_vikings: OrdBag<VeryHelpfulStruct> =
+            serde_json::from_str(&jsonified_vikings).expect("Unable to convert json to ordbag!");
+        assert_eq!(vikings, reconstituted_vikings);
+    }
+
+    #[test]
+    fn repeat_simple_entries() {
+        let jsonified_vikings: String =
+            "[[\"Einar\",1],[\"Olaf\",1],[\"Olaf\",1],[\"Harald\",2],[\"Harald\",1]]".to_string();
+        let reconstituted_vikings: OrdBag<String> =
+            serde_json::from_str(&jsonified_vikings).expect("Unable to convert json to ordbag!");
+        let einar = "Einar".to_string();
+        let olaf = "Olaf".to_string();
+        let harald = "Harald".to_string();
+        assert_eq!(reconstituted_vikings.get(&einar), Some((&einar, 1)));
+        assert_eq!(reconstituted_vikings.get(&olaf), Some((&olaf, 2)));
+        assert_eq!(reconstituted_vikings.get(&harald), Some((&harald, 3)));
+        assert_eq!(reconstituted_vikings.len(), 6);
+    }
+}
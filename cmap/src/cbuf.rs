@@ -0,0 +1,320 @@
+//! A double buffered byte log, for aggregating output from one writer
+//!
+//! [`CBuf`] appends to the writer buffer, and [`publish_and_take_previous`](CBuf::publish_and_take_previous)
+//! publishes it and hands back the batch which was published before it. Each appended byte is
+//! published once, and returned once, on the publish after the one which published it.
+//!
+//! ```
+//! let mut log = cmap::CBuf::new();
+//! let mut reader = log.reader();
+//!
+//! log.append_str("first ");
+//! log.append_str("batch");
+//! assert!(log.publish_and_take_previous().is_empty());
+//! assert_eq!(&*reader.load(), b"first batch");
+//!
+//! log.append_str("second batch");
+//! assert_eq!(log.publish_and_take_previous(), b"first batch");
+//! assert_eq!(&*reader.load(), b"second batch");
+//! ```
+
+use super::DefaultStrat;
+use std::{convert::Infallible, ops::Deref};
+
+use dbuf::interface::Strategy;
+
+use crate::{BufferId, RawDBuf};
+
+pub struct CBuf<Strat = DefaultStrat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    inner: dbuf::raw::Writer<dbuf::ptrs::alloc::OwnedPtr<Strat, RawDBuf<Vec<u8>>>>,
+}
+
+pub struct CBufReader<Strat = DefaultStrat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, RawDBuf<Vec<u8>>>>,
+}
+
+/// The published batch, see [`CBufReader::load`]
+///
+/// The buffer can't be published again until this is dropped
+pub struct CBufReadGuard<'a, Strat = DefaultStrat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, RawDBuf<Vec<u8>>>, Vec<u8>>,
+}
+
+impl CBuf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a buffer which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> CBuf<P>
+    where
+        P: Strategy<ValidationError = Infallible> + Default,
+    {
+        CBuf::default()
+    }
+}
+
+impl<Strat> Default for CBuf<Strat>
+where
+    Strat: Strategy<ValidationError = Infallible> + Default,
+{
+    fn default() -> Self {
+        Self::from_strategy(Strat::default())
+    }
+}
+
+impl<Strat> CBuf<Strat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn from_strategy(strategy: Strat) -> Self {
+        Self {
+            inner: dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                dbuf::raw::Shared::from_raw_parts(strategy, RawDBuf::new(Vec::new(), Vec::new())),
+            )),
+        }
+    }
+
+    pub fn reader(&self) -> CBufReader<Strat> {
+        CBufReader {
+            inner: self.inner.reader(),
+        }
+    }
+
+    pub fn append(&mut self, bytes: &[u8]) {
+        self.inner.split_mut().writer.extend_from_slice(bytes)
+    }
+
+    pub fn append_str(&mut self, s: &str) {
+        self.append(s.as_bytes())
+    }
+
+    /// The bytes appended since the last publish
+    pub fn pending(&self) -> &[u8] {
+        self.inner.split().writer
+    }
+
+    /// The batch readers currently see
+    pub fn published(&self) -> &[u8] {
+        self.inner.split().reader
+    }
+
+    /// Publish the pending bytes, and take the batch which was published before them
+    ///
+    /// This waits for the readers to leave the previous batch, so a reader which holds on to a
+    /// [`CBufReadGuard`] blocks this. The writer buffer is empty afterwards, and its allocation
+    /// goes with the returned batch, see [`publish_and_replace_previous`](Self::publish_and_replace_previous)
+    /// to reuse allocations.
+    pub fn publish_and_take_previous(&mut self) -> Vec<u8> {
+        self.publish_and_replace_previous(Vec::new())
+    }
+
+    /// Publish the pending bytes, and swap the batch which was published before them with `spare`
+    ///
+    /// `spare` is cleared, and becomes the new writer buffer. Passing the batches back in once
+    /// they are processed avoids allocating a new buffer on each publish.
+    pub fn publish_and_replace_previous(&mut self, mut spare: Vec<u8>) -> Vec<u8> {
+        spare.clear();
+        self.inner.swap_buffers();
+        std::mem::replace(self.inner.split_mut().writer, spare)
+    }
+
+    /// Take the pending and published bytes, in the order they were appended
+    ///
+    /// This leaves both buffers empty, so readers see an empty batch afterwards. Use it to drain
+    /// the log before dropping it, so no appended bytes are lost.
+    pub fn take_all(&mut self) -> Vec<u8> {
+        let mut previous = self.publish_and_take_previous();
+        previous.append(&mut self.publish_and_take_previous());
+        previous
+    }
+}
+
+impl<Strat> Clone for CBufReader<Strat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<Strat> CBufReader<Strat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Check if both readers read from the same buffer
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    /// The published batch, this doesn't include any earlier batches
+    pub fn load(&mut self) -> CBufReadGuard<'_, Strat> {
+        CBufReadGuard {
+            inner: self.inner.get(),
+        }
+    }
+}
+
+impl<Strat> Deref for CBufReadGuard<'_, Strat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<Strat> CBufReadGuard<'_, Strat>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// The identity of the batch this guard reads, see [`BufferId`]
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+}
+
+#[cfg(test)]
+struct XorShift(u64);
+
+#[cfg(test)]
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[test]
+fn publish_and_take_previous() {
+    let mut log = CBuf::new();
+    let mut reader = log.reader();
+
+    log.append(b"a");
+    log.append_str("b");
+    assert_eq!(log.pending(), b"ab");
+    assert!(reader.load().is_empty());
+
+    assert!(log.publish_and_take_previous().is_empty());
+    assert!(log.pending().is_empty());
+    assert_eq!(log.published(), b"ab");
+    assert_eq!(&*reader.load(), b"ab");
+
+    log.append(b"c");
+    assert_eq!(log.publish_and_take_previous(), b"ab");
+    assert_eq!(&*reader.load(), b"c");
+
+    log.append(b"d");
+    assert_eq!(log.take_all(), b"cd");
+    assert!(reader.load().is_empty());
+    assert!(log.take_all().is_empty());
+}
+
+#[test]
+fn replace_previous_reuses_spare() {
+    let mut log = CBuf::new();
+    let spare = Vec::with_capacity(64);
+    let ptr = spare.as_ptr();
+
+    log.append(b"a");
+    log.publish_and_replace_previous(b"junk".to_vec());
+    log.append(b"b");
+    let mut batch = log.publish_and_replace_previous(spare);
+    assert_eq!(batch, b"a");
+    assert_eq!(log.pending().as_ptr(), ptr);
+
+    log.append(b"c");
+    batch = log.publish_and_replace_previous(batch);
+    assert_eq!(batch, b"b");
+    assert_eq!(log.take_all(), b"c");
+}
+
+#[test]
+fn every_append_is_taken_once() {
+    for seed in 1..=8u64 {
+        let mut rng = XorShift(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let mut log = CBuf::new();
+        let mut appended = Vec::new();
+        let mut taken = Vec::new();
+        let mut spare = Vec::new();
+
+        for _ in 0..2000 {
+            for _ in 0..rng.below(4) {
+                let chunk = (0..rng.below(16))
+                    .map(|_| rng.next() as u8)
+                    .collect::<Vec<_>>();
+                appended.extend_from_slice(&chunk);
+                log.append(&chunk);
+            }
+
+            if rng.below(2) == 0 {
+                let batch = log.publish_and_replace_previous(std::mem::take(&mut spare));
+                taken.extend_from_slice(&batch);
+                spare = batch;
+            } else {
+                taken.extend(log.publish_and_take_previous());
+            }
+        }
+
+        taken.extend(log.take_all());
+        assert_eq!(taken, appended, "seed {seed}");
+    }
+}
+
+#[test]
+fn readers_see_whole_batches() {
+    let mut log = CBuf::new();
+    let mut reader = log.reader();
+    let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    let reader = std::thread::spawn({
+        let done = done.clone();
+        move || {
+            while !done.load(std::sync::atomic::Ordering::Acquire) {
+                let batch = reader.load();
+                // each batch is a run of consecutive bytes, so a reader never sees a torn batch
+                assert!(batch.windows(2).all(|w| w[1] == w[0].wrapping_add(1)));
+            }
+        }
+    });
+
+    let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+    let mut next = 0u8;
+    let mut appended = 0;
+    let mut taken = 0;
+    for _ in 0..5000 {
+        for _ in 0..rng.below(32) {
+            log.append(&[next]);
+            next = next.wrapping_add(1);
+            appended += 1;
+        }
+        let batch = log.publish_and_take_previous();
+        assert!(batch.windows(2).all(|w| w[1] == w[0].wrapping_add(1)));
+        taken += batch.len();
+    }
+    taken += log.take_all().len();
+
+    done.store(true, std::sync::atomic::Ordering::Release);
+    reader.join().unwrap();
+    assert_eq!(taken, appended);
+}
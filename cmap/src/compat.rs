@@ -0,0 +1,8 @@
+//! Shims which mimic the API of other concurrent maps, to ease migrating to this crate
+//!
+//! Each shim is behind a feature of the same name as the module, with a `-compat` suffix.
+//! They only cover the commonly used parts of the original API, and document where the
+//! semantics differ. Once a call site is migrated, it can move on to the native maps.
+
+#[cfg(feature = "evmap-compat")]
+pub mod evmap;
@@ -0,0 +1,452 @@
+//! An evmap-like API over [`CMultiMap`]
+//!
+//! The subset of [evmap](https://docs.rs/evmap) which most services use compiles against this
+//! module with only the imports changed, so a service can switch over first, and then move to
+//! [`CMultiMap`] one call site at a time, see [`WriteHandle::into_inner`].
+//!
+//! ```
+//! use cmap::compat::evmap;
+//!
+//! let (mut w, r) = evmap::new();
+//! w.insert("fruit", "apple").insert("fruit", "pear");
+//! assert!(r.get("fruit").is_none());
+//!
+//! w.publish();
+//! assert_eq!(r.get("fruit").map(|values| values.len()), Some(2));
+//! ```
+//!
+//! # Differences from evmap
+//!
+//! * Values are stored in a [`Bag`], which counts copies of equal values, so inserting the same
+//!   value twice keeps both copies, and [`remove_value`](WriteHandle::remove_value) only removes
+//!   one of them. Values are compared with `Hash` and `Eq`, never by address, and
+//!   [`Bag::contains`] returns the number of copies instead of a `bool`.
+//! * Removing the last value from a bag doesn't remove its key, readers see an empty bag until
+//!   [`remove_entry`](WriteHandle::remove_entry) or [`purge`](WriteHandle::purge) evicts it.
+//!   [`get_one`](ReadHandle::get_one) skips empty bags, [`MapReadRef::iter`] doesn't.
+//! * Both maps own a copy of each key and value, so they must be `Clone`. There's no
+//!   `ShallowCopy` and no meta value.
+//! * [`WriteHandle`] doesn't deref to a [`ReadHandle`], create one with
+//!   [`WriteHandle::reader`] instead.
+//! * [`enter`](ReadHandle::enter) only returns `None` once the [`WriteHandle`] is dropped,
+//!   and the guards it returns don't borrow the [`ReadHandle`].
+
+use std::{
+    borrow::Borrow,
+    collections::{hash_map, HashMap},
+    fmt,
+    hash::Hash,
+    ops::Deref,
+};
+
+use dbuf::{
+    ptrs::alloc::OwnedWeak,
+    raw::{OwnedReadGuard, SharedReader},
+};
+
+use crate::{multimap::Bag, CMultiMap, DefaultHasher, DefaultStrat, RawDBuf};
+
+/// The values of a key, called `Values` in evmap
+pub type Values<V> = Bag<V>;
+
+/// the pointer which the read handles read through
+type Ptr<K, V> = OwnedWeak<DefaultStrat, RawDBuf<HashMap<K, Bag<V>, DefaultHasher>>>;
+
+/// any value in the bag, [`Bag::get_one`] may return a removed value if the bag is empty
+fn get_one<V>(values: &Values<V>) -> Option<&V> {
+    if values.is_empty() {
+        None
+    } else {
+        values.get_one()
+    }
+}
+
+/// Create an empty map, and a handle to write to it and one to read from it
+pub fn new<K, V>() -> (WriteHandle<K, V>, ReadHandle<K, V>)
+where
+    K: Hash + Eq + Clone,
+    V: Hash + Eq + Clone,
+{
+    let write = WriteHandle {
+        map: CMultiMap::new(),
+    };
+    let read = write.reader();
+    (write, read)
+}
+
+/// The handle which writes to the map, see [`new`]
+pub struct WriteHandle<K, V> {
+    map: CMultiMap<K, V>,
+}
+
+/// A handle which reads from the map, see [`new`]
+pub struct ReadHandle<K, V> {
+    inner: SharedReader<Ptr<K, V>>,
+}
+
+/// A `Sync` source of [`ReadHandle`]s, see [`ReadHandle::factory`]
+pub struct ReadHandleFactory<K, V> {
+    inner: SharedReader<Ptr<K, V>>,
+}
+
+/// A snapshot of the published map, see [`ReadHandle::enter`]
+///
+/// The map can't be published again until this is dropped
+pub struct MapReadRef<K, V> {
+    #[allow(clippy::type_complexity)]
+    guard: OwnedReadGuard<Ptr<K, V>>,
+}
+
+/// A part of the published map, see [`ReadHandle::get`]
+///
+/// The map can't be published again until this is dropped
+pub struct ReadGuard<K, V, T: ?Sized> {
+    inner: OwnedReadGuard<Ptr<K, V>, T>,
+}
+
+impl<K, V> WriteHandle<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Hash + Eq + Clone,
+{
+    /// Create another handle which reads from this map
+    pub fn reader(&self) -> ReadHandle<K, V> {
+        ReadHandle {
+            inner: self.map.weak_reader().into_raw().into_shared(),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> &mut Self {
+        self.map.insert(key, value);
+        self
+    }
+
+    /// remove one copy of the value from the key's bag, the key stays even if its bag is empty
+    pub fn remove_value(&mut self, key: K, value: V) -> &mut Self {
+        self.map.remove(key, value);
+        self
+    }
+
+    /// remove the key and all of its values
+    pub fn remove_entry(&mut self, key: K) -> &mut Self {
+        self.map.clear(key);
+        self
+    }
+
+    /// remove every key
+    pub fn purge(&mut self) -> &mut Self {
+        self.map.purge();
+        self
+    }
+
+    /// Publish all changes, this always swaps the maps even if there are no changes
+    pub fn publish(&mut self) -> &mut Self {
+        self.map.force_publish();
+        self
+    }
+
+    /// Publish all changes, but only if there are any
+    pub fn flush(&mut self) {
+        self.map.publish();
+    }
+
+    /// The map this handle writes to, for call sites which were migrated to [`CMultiMap`]
+    pub fn as_inner(&mut self) -> &mut CMultiMap<K, V> {
+        &mut self.map
+    }
+
+    /// Convert into the map this handle writes to
+    ///
+    /// Existing [`ReadHandle`]s keep reading from it
+    pub fn into_inner(self) -> CMultiMap<K, V> {
+        self.map
+    }
+}
+
+impl<K, V> Clone for ReadHandle<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> ReadHandle<K, V> {
+    /// Take a snapshot of the published map, or `None` if the [`WriteHandle`] was dropped
+    pub fn enter(&self) -> Option<MapReadRef<K, V>> {
+        let guard = self.inner.try_get().ok()?;
+        Some(MapReadRef { guard })
+    }
+
+    /// Check if the [`WriteHandle`] was dropped
+    pub fn was_dropped(&self) -> bool {
+        self.enter().is_none()
+    }
+
+    /// The number of keys in the published map, including keys with empty bags
+    pub fn len(&self) -> usize {
+        self.enter().map_or(0, |map| map.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<ReadGuard<K, V, Values<V>>>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+    {
+        let inner = self.enter()?.guard.try_map(|map| map.get(key)).ok()?;
+        Some(ReadGuard { inner })
+    }
+
+    /// Get any of the key's values, or `None` if the key is missing or its bag is empty
+    pub fn get_one<Q>(&self, key: &Q) -> Option<ReadGuard<K, V, V>>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+    {
+        let inner = self.get(key)?.inner.try_map(get_one).ok()?;
+        Some(ReadGuard { inner })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+    {
+        self.enter().is_some_and(|map| map.contains_key(key))
+    }
+
+    /// Collect `f` of each key and its values, this is empty if the [`WriteHandle`] was dropped
+    pub fn map_into<Map, Collector, Target>(&self, mut f: Map) -> Collector
+    where
+        Map: FnMut(&K, &Values<V>) -> Target,
+        Collector: FromIterator<Target>,
+    {
+        match self.enter() {
+            Some(map) => map.iter().map(|(key, values)| f(key, values)).collect(),
+            None => Collector::from_iter(None),
+        }
+    }
+
+    /// Create a factory which can be shared between threads to create more handles
+    pub fn factory(&self) -> ReadHandleFactory<K, V> {
+        ReadHandleFactory {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> Clone for ReadHandleFactory<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> ReadHandleFactory<K, V> {
+    /// Create a handle which reads from the map
+    pub fn handle(&self) -> ReadHandle<K, V> {
+        ReadHandle {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> MapReadRef<K, V> {
+    /// Iterate over each key and its values, including keys with empty bags
+    pub fn iter(&self) -> hash_map::Iter<'_, K, Values<V>> {
+        self.guard.iter()
+    }
+
+    pub fn keys(&self) -> hash_map::Keys<'_, K, Values<V>> {
+        self.guard.keys()
+    }
+
+    pub fn values(&self) -> hash_map::Values<'_, K, Values<V>> {
+        self.guard.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.guard.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.guard.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&Values<V>>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+    {
+        self.guard.get(key)
+    }
+
+    pub fn get_one<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+    {
+        get_one(self.get(key)?)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+    {
+        self.guard.contains_key(key)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a MapReadRef<K, V> {
+    type Item = (&'a K, &'a Values<V>);
+    type IntoIter = hash_map::Iter<'a, K, Values<V>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for MapReadRef<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, T: ?Sized> Deref for ReadGuard<K, V, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<K, V, T: ?Sized + fmt::Debug> fmt::Debug for ReadGuard<K, V, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+fn snapshot(map: &HashMap<u32, Bag<u32>, DefaultHasher>) -> Vec<(u32, Vec<u32>)> {
+    let mut entries = map
+        .iter()
+        .map(|(&key, values)| {
+            let mut values = values.iter().copied().collect::<Vec<_>>();
+            values.sort();
+            (key, values)
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn matches_multimap() {
+    let (mut w, r) = new::<u32, u32>();
+    let mut native = CMultiMap::<u32, u32>::new();
+    let mut native_reader = native.reader();
+
+    let mut check = |w: &mut WriteHandle<u32, u32>, native: &mut CMultiMap<u32, u32>| {
+        w.publish();
+        native.force_publish();
+        let shim = snapshot(&r.enter().unwrap().guard);
+        assert_eq!(shim, snapshot(&native_reader.load()));
+        shim
+    };
+
+    for i in 0..10 {
+        w.insert(i % 4, i).insert(i % 4, i);
+        native.insert(i % 4, i);
+        native.insert(i % 4, i);
+    }
+    check(&mut w, &mut native);
+
+    w.remove_value(1, 1).remove_value(1, 5).remove_value(1, 9);
+    native.remove(1, 1);
+    native.remove(1, 5);
+    native.remove(1, 9);
+    w.remove_entry(2);
+    native.clear(2);
+    assert_eq!(
+        check(&mut w, &mut native),
+        [
+            (0, vec![0, 0, 4, 4, 8, 8]),
+            (1, vec![1, 5, 9]),
+            (3, vec![3, 3, 7, 7]),
+        ]
+    );
+
+    w.purge().insert(7, 7);
+    native.purge();
+    native.insert(7, 7);
+    assert_eq!(check(&mut w, &mut native), [(7, vec![7])]);
+}
+
+#[test]
+fn empty_bags() {
+    let (mut w, r) = new::<&str, u32>();
+    w.insert("a", 1).insert("b", 2).publish();
+    w.remove_value("a", 1).publish();
+
+    assert!(r.contains_key("a"));
+    assert_eq!(r.get("a").map(|values| values.len()), Some(0));
+    assert!(r.get_one("a").is_none());
+    assert!(r.enter().unwrap().get_one("a").is_none());
+    assert_eq!(r.get_one("b").map(|value| *value), Some(2));
+    assert_eq!(r.len(), 2);
+
+    w.remove_entry("a").publish();
+    assert!(!r.contains_key("a"));
+    assert_eq!(r.len(), 1);
+}
+
+#[test]
+fn flush_only_publishes_changes() {
+    let (mut w, r) = new::<u32, u32>();
+    let id = r.enter().unwrap().guard.buffer_id();
+    w.flush();
+    assert!(r.enter().unwrap().guard.same_buffer(id));
+
+    w.insert(1, 1).flush();
+    assert!(!r.enter().unwrap().guard.same_buffer(id));
+    assert_eq!(r.get_one(&1).map(|value| *value), Some(1));
+}
+
+#[test]
+fn readers() {
+    let (mut w, r) = new::<u32, u32>();
+    w.insert(1, 10).insert(2, 20).publish();
+
+    fn assert_sync<T: Sync>(_: &T) {}
+
+    let factory = r.factory();
+    assert_sync(&factory);
+    let values = std::thread::spawn(move || {
+        let r = factory.handle();
+        let mut values: Vec<u32> = r.map_into(|_, values| values.iter().sum());
+        values.sort();
+        values
+    })
+    .join()
+    .unwrap();
+    assert_eq!(values, [10, 20]);
+
+    let map = r.enter().unwrap();
+    let mut keys = (&map).into_iter().map(|(&key, _)| key).collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, [1, 2]);
+    drop(map);
+
+    assert!(!r.was_dropped());
+    drop(w);
+    assert!(r.was_dropped());
+    assert!(r.enter().is_none());
+    assert!(r.get(&1).is_none());
+    assert!(r.map_into::<_, Vec<_>, _>(|&key, _| key).is_empty());
+}
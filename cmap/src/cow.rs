@@ -0,0 +1,263 @@
+//! Values which are shared between both maps, and copied on write
+//!
+//! Each operation on a [`CMap`](crate::CMap) is applied to both maps, so
+//! [`insert`](crate::CMap::insert) splits the value into two, one for each map. For
+//! most values [`Split`](crate::split::Split) is [`Clone`], so that's a full clone of each
+//! value, which is expensive for large values like big configs.
+//!
+//! [`SharedValue`] is an [`Arc`], so splitting it is only a reference count increment, and both
+//! maps share one allocation for each value. Readers can't change the value, and the writer can
+//! only change it through [`SharedValue::make_mut`], which clones the value if the other map
+//! still has it. So changing the writer's map never changes a value which readers can see.
+//!
+//! [`CMap::update`](crate::CMap::update) applies the change once, and gives the other map the
+//! changed value, so both maps share the changed value afterwards.
+//! [`CMap::retain`](crate::CMap::retain) runs on each map, so the maps end up with one copy each.
+//!
+//! ```
+//! use cmap::cow::SharedValue;
+//!
+//! let mut map = cmap::CMap::<u32, SharedValue<Vec<u8>>>::new();
+//! let mut reader = map.reader();
+//!
+//! map.insert(1, SharedValue::new(vec![0; 4096]));
+//! map.publish();
+//!
+//! map.update(1, |value| value.make_mut()[0] = 1);
+//! // readers only see the change once it's published
+//! assert_eq!(reader.get_deref(&1).unwrap()[0], 0);
+//! map.publish();
+//! assert_eq!(reader.get_deref(&1).unwrap()[0], 1);
+//! ```
+
+use std::{borrow::Borrow, fmt, hash::Hash, ops::Deref, sync::Arc};
+
+/// A value which is shared between both maps, see the [module docs](self)
+///
+/// Comparisons, hashing and formatting all use the value, not the pointer.
+/// [`Split`](crate::split::Split) comes from the [`Clone`] impl, which only clones the [`Arc`]
+pub struct SharedValue<T: ?Sized>(Arc<T>);
+
+impl<T> SharedValue<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+
+    /// Get the value back, cloning it if it's still shared
+    pub fn into_inner(this: Self) -> T
+    where
+        T: Clone,
+    {
+        Arc::try_unwrap(this.0).unwrap_or_else(|arc| T::clone(&arc))
+    }
+}
+
+impl<T: ?Sized> SharedValue<T> {
+    pub fn from_arc(arc: Arc<T>) -> Self {
+        Self(arc)
+    }
+
+    pub fn into_arc(this: Self) -> Arc<T> {
+        this.0
+    }
+
+    /// Get a mutable reference to the value, cloning it first if anything else shares it,
+    /// see [`Arc::make_mut`]
+    ///
+    /// So if the other map still has this value, this map gets a new copy, and the other map keeps the old one
+    pub fn make_mut(&mut self) -> &mut T
+    where
+        T: Clone,
+    {
+        Arc::make_mut(&mut self.0)
+    }
+
+    /// Get a mutable reference to the value if nothing else shares it, see [`Arc::get_mut`]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        Arc::get_mut(&mut self.0)
+    }
+
+    /// Check if both values are the same allocation
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.0, &other.0)
+    }
+
+    /// Check if anything else shares this value, like the other map
+    pub fn is_shared(this: &Self) -> bool {
+        Arc::strong_count(&this.0) != 1 || Arc::weak_count(&this.0) != 0
+    }
+}
+
+impl<T: ?Sized> Clone for SharedValue<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Deref for SharedValue<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> From<T> for SharedValue<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized> From<Arc<T>> for SharedValue<T> {
+    fn from(arc: Arc<T>) -> Self {
+        Self(arc)
+    }
+}
+
+impl<T: Default> Default for SharedValue<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for SharedValue<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for SharedValue<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Borrow<str>> Borrow<str> for SharedValue<T> {
+    fn borrow(&self) -> &str {
+        T::borrow(self)
+    }
+}
+
+impl<T: Borrow<[U]>, U> Borrow<[U]> for SharedValue<T> {
+    fn borrow(&self) -> &[U] {
+        T::borrow(self)
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for SharedValue<T> {}
+impl<T: ?Sized + PartialEq> PartialEq<T> for SharedValue<T> {
+    #[inline]
+    fn eq(&self, other: &T) -> bool {
+        T::eq(self, other)
+    }
+}
+impl<T: ?Sized + PartialEq> PartialEq<SharedValue<T>> for SharedValue<T> {
+    #[inline]
+    fn eq(&self, other: &SharedValue<T>) -> bool {
+        T::eq(self, other)
+    }
+}
+
+impl<T: ?Sized + PartialOrd> PartialOrd<T> for SharedValue<T> {
+    #[inline]
+    fn partial_cmp(&self, other: &T) -> Option<std::cmp::Ordering> {
+        T::partial_cmp(self, other)
+    }
+}
+impl<T: ?Sized + PartialOrd> PartialOrd<SharedValue<T>> for SharedValue<T> {
+    #[inline]
+    fn partial_cmp(&self, other: &SharedValue<T>) -> Option<std::cmp::Ordering> {
+        T::partial_cmp(self, other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for SharedValue<T> {
+    #[inline]
+    fn cmp(&self, other: &SharedValue<T>) -> std::cmp::Ordering {
+        T::cmp(self, other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for SharedValue<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        T::hash(self, state)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SharedValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for SharedValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ?Sized + serde::Serialize> serde::Serialize for SharedValue<T> {
+    fn serialize<Se: serde::Serializer>(&self, serializer: Se) -> Result<Se::Ok, Se::Error> {
+        T::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for SharedValue<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[test]
+fn split_shares_the_value() {
+    use crate::split::Split;
+
+    let mut a = SharedValue::new(vec![1, 2, 3]);
+    let mut b = a.split();
+    assert!(SharedValue::ptr_eq(&a, &b));
+    assert!(SharedValue::is_shared(&a));
+
+    // changing one copies it, and leaves the other alone
+    b.make_mut().push(4);
+    assert!(!SharedValue::ptr_eq(&a, &b));
+    assert_eq!(*a, [1, 2, 3]);
+    assert_eq!(*b, [1, 2, 3, 4]);
+
+    // once it's not shared, it's changed in place
+    assert!(!SharedValue::is_shared(&a));
+    let ptr: *const Vec<i32> = &*a;
+    a.make_mut().push(5);
+    assert!(std::ptr::eq(&*a, ptr));
+    assert_eq!(SharedValue::into_inner(a), [1, 2, 3, 5]);
+}
+
+#[test]
+fn forwards_to_the_value() {
+    use std::collections::HashSet;
+
+    let a = SharedValue::new("a".to_string());
+    assert_eq!(a, "a".to_string());
+    assert_eq!(a, SharedValue::new("a".to_string()));
+    assert!(a < SharedValue::new("b".to_string()));
+    assert_eq!(format!("{a} {a:?}"), "a \"a\"");
+
+    let set = HashSet::from([a]);
+    assert!(set.contains("a"));
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde_round_trip() {
+    let a = SharedValue::new(vec!["a".to_string(), "b".to_string()]);
+    let json = serde_json::to_string(&a).unwrap();
+    // serialized like the value it wraps
+    assert_eq!(json, r#"["a","b"]"#);
+
+    let b: SharedValue<Vec<String>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(a, b);
+    assert!(!SharedValue::ptr_eq(&a, &b));
+}
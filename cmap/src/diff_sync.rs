@@ -0,0 +1,464 @@
+//! A map which keeps its two maps in sync by copying changed entries, instead of replaying operations
+//!
+//! [`CMap`](crate::CMap) logs each operation, and applies it to both maps, once before it's
+//! published and once after. [`CMapDiffSync`] writes straight into the writer map instead, and
+//! records which keys changed. After a publish, once the readers have left the old published
+//! map, it copies the current values of those keys from the new published map into it.
+//!
+//! This is better when few keys change per publish, or the changes are expensive to apply or can't
+//! be applied twice, at the cost of cloning each changed value, and the memory for the changed keys.
+//! The writer map is always up to date, so the writer can read its own writes, and
+//! [`insert`](CMapDiffSync::insert) and [`remove`](CMapDiffSync::remove) return the old value.
+//!
+//! The copy happens on the first write after a publish, or in [`sync`](CMapDiffSync::sync), and
+//! it waits for the readers to leave the old published map first, like any other write.
+//!
+//! Readers are the same as for [`CMap`](crate::CMap)
+//!
+//! ```
+//! let mut map = cmap::CMapDiffSync::<u32, String>::new();
+//! let mut reader = map.reader();
+//!
+//! map.insert(1, "one".to_string());
+//! assert_eq!(map.get(&1).map(String::as_str), Some("one"));
+//! assert!(reader.get(&1).is_none());
+//!
+//! map.publish();
+//! assert_eq!(reader.get(&1).as_deref().map(String::as_str), Some("one"));
+//!
+//! assert_eq!(map.insert(1, "uno".to_string()).as_deref(), Some("one"));
+//! map.publish();
+//! assert_eq!(reader.get(&1).as_deref().map(String::as_str), Some("uno"));
+//! ```
+
+use super::{DefaultHasher, DefaultStrat};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    hash::{BuildHasher, Hash},
+};
+
+use dbuf::{
+    delayed::DelayedWriter,
+    interface::{FromBuffers, RawBuffers, Strategy},
+};
+
+use crate::{CMapReader, CMapWeakReader, PublishedCount};
+
+pub struct CMapDiffSync<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    inner: DelayedWriter<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
+    /// the keys which were changed in the writer map since the last publish
+    changes: ChangeSet<K>,
+    /// the changes of the last publish, which still have to be copied into the writer map,
+    /// see [`CMapDiffSync::sync`]
+    unsynced: ChangeSet<K>,
+    /// the length of the published map, see [`CMapDiffSync::published_len`]
+    published_len: PublishedCount,
+}
+
+/// the changes made to a map, see [`CMapDiffSync`]
+struct ChangeSet<K> {
+    /// the keys which were inserted, removed or changed
+    keys: HashSet<K>,
+    /// true if the map was cleared before the keys were changed
+    cleared: bool,
+}
+
+impl<K> ChangeSet<K> {
+    fn new() -> Self {
+        Self {
+            keys: HashSet::new(),
+            cleared: false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.cleared && self.keys.is_empty()
+    }
+}
+
+impl<K, V> CMapDiffSync<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a map which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> CMapDiffSync<K, V, DefaultHasher, P>
+    where
+        P: Strategy<ValidationError = Infallible> + Default,
+    {
+        CMapDiffSync::default()
+    }
+}
+
+impl<K, V, S, Strat, B> Default for CMapDiffSync<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>> + FromBuffers,
+    S: Default,
+    Strat: Strategy<ValidationError = Infallible> + Default,
+{
+    fn default() -> Self {
+        Self::from_raw_parts(Default::default(), Default::default(), Strat::default())
+    }
+}
+
+impl<K, V, S, Strat, B> CMapDiffSync<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Create a map from the two buffers
+    ///
+    /// **The two maps must be equal.** Only the changed keys are copied between the maps,
+    /// so a difference is never repaired, like in [`CMap::from_maps`](crate::CMap::from_maps)
+    pub fn from_raw_parts(front: HashMap<K, V, S>, back: HashMap<K, V, S>, strategy: Strat) -> Self
+    where
+        B: FromBuffers,
+    {
+        let inner = DelayedWriter::new(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+            dbuf::raw::Shared::from_raw_parts(strategy, B::from_buffers(front, back)),
+        )));
+        Self {
+            published_len: PublishedCount::new(inner.split().reader.len()),
+            inner,
+            changes: ChangeSet::new(),
+            unsynced: ChangeSet::new(),
+        }
+    }
+
+    pub fn reader(&self) -> CMapReader<K, V, S, Strat, B> {
+        CMapReader::from_parts(self.inner.reader(), self.published_len.clone())
+    }
+
+    /// Create a reader which doesn't keep the maps alive, see [`CMap::weak_reader`](crate::CMap::weak_reader)
+    pub fn weak_reader(&self) -> CMapWeakReader<K, V, S, Strat, B> {
+        CMapWeakReader::from_parts(self.inner.reader().into_weak())
+    }
+
+    /// The map with every change made so far, including the ones which aren't published yet
+    ///
+    /// This doesn't wait for readers
+    pub fn load(&self) -> &HashMap<K, V, S> {
+        let split = self.inner.split();
+        // until the last publish is copied into the writer map, the published map is the newest
+        if self.unsynced.is_empty() {
+            split.writer
+        } else {
+            split.reader
+        }
+    }
+
+    /// The map readers see after the last publish
+    pub fn load_published(&self) -> &HashMap<K, V, S> {
+        self.inner.split().reader
+    }
+
+    /// The number of entries in the published map, without taking a read guard
+    ///
+    /// This is exact as of the last publish, and doesn't count the unpublished changes
+    pub fn published_len(&self) -> usize {
+        self.published_len.get()
+    }
+
+    /// The number of keys which were changed since the last publish
+    pub fn changed_len(&self) -> usize {
+        self.changes.keys.len()
+    }
+
+    /// true if the map was changed since the last publish
+    pub fn has_changes(&self) -> bool {
+        !self.changes.is_empty()
+    }
+
+    /// check if the readers have left the old published map, so the next write doesn't wait for them
+    pub fn is_swap_finished(&mut self) -> bool {
+        self.inner.is_swap_finished()
+    }
+}
+
+impl<K, V, S, Strat, B> CMapDiffSync<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.load().get(key)
+    }
+
+    /// Insert the value, and return the old value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.changes.keys.insert(key.clone());
+        self.writer_map().insert(key, value)
+    }
+
+    /// Remove the key, and return its value
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, value) = self.writer_map().remove_entry(key)?;
+        self.changes.keys.insert(key);
+        Some(value)
+    }
+
+    /// Change a value in place
+    ///
+    /// The key is recorded as changed, even if the value isn't changed
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.sync();
+        let map = self.inner.finish_swap().split_mut().writer;
+        let key = map.get_key_value(key)?.0.clone();
+        let value = map.get_mut::<K>(&key);
+        self.changes.keys.insert(key);
+        value
+    }
+
+    pub fn clear(&mut self) {
+        self.writer_map().clear();
+        self.changes.keys.clear();
+        self.changes.cleared = true;
+    }
+
+    /// Remove the entries which `f` returns false for
+    ///
+    /// Only the removed keys are recorded as changed, so `f` can't change the values,
+    /// see [`get_mut`](Self::get_mut)
+    pub fn retain(&mut self, mut f: impl FnMut(&K, &V) -> bool) {
+        self.sync();
+        let changes = &mut self.changes.keys;
+        self.inner
+            .finish_swap()
+            .split_mut()
+            .writer
+            .retain(|key, value| {
+                let keep = f(key, value);
+                if !keep {
+                    changes.insert(key.clone());
+                }
+                keep
+            });
+    }
+
+    /// Publish the changes if there are any
+    pub fn publish(&mut self) {
+        if self.has_changes() {
+            self.force_publish();
+        }
+    }
+
+    /// Publish the changes, even if there aren't any
+    ///
+    /// This doesn't wait for the readers to leave the old published map, that's left to the next write
+    pub fn force_publish(&mut self) {
+        self.sync();
+        self.inner.start_buffer_swap();
+        // `unsynced` was emptied by `sync`, so this reuses its allocation for the next changes
+        std::mem::swap(&mut self.changes, &mut self.unsynced);
+        self.published_len.set(self.inner.split().reader.len());
+    }
+
+    /// Copy the changes of the last publish into the writer map, if they weren't copied yet
+    ///
+    /// This waits for the readers to leave the old published map. The next write does this anyway,
+    /// so this only moves the cost of the copy
+    pub fn sync(&mut self) {
+        let split = self.inner.finish_swap().split_mut();
+        let unsynced = &mut self.unsynced;
+
+        if unsynced.cleared {
+            split.writer.clear();
+            unsynced.cleared = false;
+        }
+
+        for key in unsynced.keys.drain() {
+            match split.reader.get(&key) {
+                Some(value) => match split.writer.get_mut(&key) {
+                    Some(old) => old.clone_from(value),
+                    None => {
+                        split.writer.insert(key, value.clone());
+                    }
+                },
+                None => {
+                    split.writer.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// the writer map, with the last publish copied into it
+    fn writer_map(&mut self) -> &mut HashMap<K, V, S> {
+        self.sync();
+        self.inner.finish_swap().split_mut().writer
+    }
+}
+
+#[cfg(test)]
+fn assert_synced<K, V, S, Strat, B>(map: &mut CMapDiffSync<K, V, S, Strat, B>)
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    K: Hash + Eq + Clone + std::fmt::Debug,
+    V: Clone + PartialEq + std::fmt::Debug,
+    S: BuildHasher,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    map.sync();
+    let split = map.inner.split();
+    assert_eq!(split.writer, split.reader);
+}
+
+#[test]
+fn read_your_writes() {
+    let mut map = CMapDiffSync::<u32, u32>::new();
+    let mut reader = map.reader();
+
+    assert_eq!(map.insert(1, 10), None);
+    assert_eq!(map.insert(2, 20), None);
+    assert_eq!(map.insert(1, 11), Some(10));
+    assert_eq!(map.get(&1), Some(&11));
+    assert!(reader.get(&1).is_none());
+    assert_eq!(map.changed_len(), 2);
+
+    map.publish();
+    assert_eq!(*reader.get(&1).unwrap(), 11);
+    assert_eq!(reader.published_len(), 2);
+    assert!(!map.has_changes());
+
+    // the writer still sees the published values before the copy
+    assert_eq!(map.get(&2), Some(&20));
+    assert_eq!(map.remove(&2), Some(20));
+    assert_eq!(map.remove(&2), None);
+    assert_eq!(map.get(&2), None);
+    assert_eq!(*reader.get(&2).unwrap(), 20);
+
+    map.publish();
+    assert!(reader.get(&2).is_none());
+    assert_synced(&mut map);
+}
+
+#[test]
+fn publish_without_changes_does_nothing() {
+    let mut map = CMapDiffSync::<u32, u32>::new();
+    let mut reader = map.reader();
+    map.insert(1, 1);
+    map.publish();
+    map.sync();
+
+    // the guard is in the old published map
+    let guard = reader.load();
+    map.insert(2, 2);
+    map.publish();
+    assert!(!map.is_swap_finished());
+    // reading doesn't wait for the old published map
+    assert_eq!(map.get(&2), Some(&2));
+    drop(guard);
+    assert!(map.is_swap_finished());
+
+    let id = reader.load().buffer_id();
+    map.publish();
+    assert!(reader.load().same_buffer(id));
+    map.force_publish();
+    assert!(!reader.load().same_buffer(id));
+}
+
+#[test]
+fn clear_retain_and_get_mut() {
+    let mut map = CMapDiffSync::<u32, String>::new();
+    let mut reader = map.reader();
+    for i in 0..10 {
+        map.insert(i, i.to_string());
+    }
+    map.publish();
+
+    map.retain(|&key, _| key % 2 == 0);
+    map.get_mut(&4).unwrap().push('!');
+    assert!(map.get_mut(&5).is_none());
+    map.publish();
+    assert_eq!(reader.load().len(), 5);
+    assert_eq!(*reader.get(&4).unwrap(), "4!");
+    assert_synced(&mut map);
+
+    map.insert(100, "a".into());
+    map.clear();
+    map.insert(200, "b".into());
+    map.publish();
+    assert_eq!(*reader.load(), HashMap::from([(200, "b".to_string())]));
+    assert_synced(&mut map);
+}
+
+#[test]
+fn matches_a_hashmap() {
+    let mut rng = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move |n: u64| {
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng % n
+    };
+
+    let mut map = CMapDiffSync::<u64, u64>::new();
+    let mut reader = map.reader();
+    let mut model = HashMap::new();
+    let mut published = HashMap::new();
+
+    for _ in 0..2000 {
+        for _ in 0..next(8) {
+            let key = next(32);
+            match next(8) {
+                0..=3 => {
+                    let value = next(1000);
+                    assert_eq!(map.insert(key, value), model.insert(key, value));
+                }
+                4 | 5 => assert_eq!(map.remove(&key), model.remove(&key)),
+                6 => match map.get_mut(&key) {
+                    Some(value) => {
+                        *value += 1;
+                        *model.get_mut(&key).unwrap() += 1;
+                    }
+                    None => assert!(!model.contains_key(&key)),
+                },
+                _ if next(8) == 0 => {
+                    map.clear();
+                    model.clear();
+                }
+                _ => {
+                    let residue = next(4);
+                    map.retain(|key, _| key % 4 != residue);
+                    model.retain(|key, _| key % 4 != residue);
+                }
+            }
+            assert_eq!(*map.load(), model);
+        }
+
+        if next(2) == 0 {
+            map.publish();
+            published.clone_from(&model);
+        }
+        assert_eq!(*reader.load(), published);
+        assert_eq!(reader.published_len(), published.len());
+    }
+
+    map.force_publish();
+    assert_synced(&mut map);
+}
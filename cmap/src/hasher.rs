@@ -0,0 +1,90 @@
+//! A hasher which is chosen at runtime
+//!
+//! The hasher of a [`CMap`](crate::CMap) is part of its type, so [`CMap::rehash_with`](crate::CMap::rehash_with)
+//! usually changes the type of the map. A [`DynCMap`](crate::DynCMap) uses a [`DynHasher`], so the
+//! hasher can be rotated (for example to get new random keys) without changing the type.
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+};
+
+/// An object safe version of [`BuildHasher`]
+///
+/// This is implemented for every hasher builder which can be cloned
+pub trait DynBuildHasher: Send + Sync {
+    /// build a new hasher
+    fn build_dyn_hasher(&self) -> Box<dyn Hasher>;
+
+    /// clone the hasher builder
+    fn clone_dyn(&self) -> Box<dyn DynBuildHasher>;
+}
+
+impl<S> DynBuildHasher for S
+where
+    S: BuildHasher + Clone + Send + Sync + 'static,
+    S::Hasher: 'static,
+{
+    fn build_dyn_hasher(&self) -> Box<dyn Hasher> {
+        Box::new(self.build_hasher())
+    }
+
+    fn clone_dyn(&self) -> Box<dyn DynBuildHasher> {
+        Box::new(self.clone())
+    }
+}
+
+/// A hasher builder which is chosen at runtime
+pub struct DynHasher {
+    inner: Box<dyn DynBuildHasher>,
+}
+
+impl DynHasher {
+    /// Erase the type of `hasher`
+    pub fn new<S: DynBuildHasher + 'static>(hasher: S) -> Self {
+        Self {
+            inner: Box::new(hasher),
+        }
+    }
+}
+
+impl Default for DynHasher {
+    /// a [`RandomState`], like the default hasher of a `HashMap`
+    fn default() -> Self {
+        Self::new(RandomState::new())
+    }
+}
+
+impl Clone for DynHasher {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone_dyn(),
+        }
+    }
+}
+
+impl BuildHasher for DynHasher {
+    type Hasher = Box<dyn Hasher>;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        self.inner.build_dyn_hasher()
+    }
+}
+
+impl fmt::Debug for DynHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynHasher").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn dyn_hasher_matches_inner() {
+    use std::hash::BuildHasherDefault;
+
+    type Inner = BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+    let hasher = DynHasher::new(Inner::default());
+    assert_eq!(hasher.hash_one(10), Inner::default().hash_one(10));
+    assert_eq!(hasher.clone().hash_one("a"), Inner::default().hash_one("a"));
+}
@@ -0,0 +1,481 @@
+//! A concurrent map for small, dense integer keys
+//!
+//! [`CIntMap`] stores its values in a `Vec<Option<V>>` indexed by the key, so operations don't
+//! need to hash or compare keys. This is a good fit for ids which are handed out sequentially,
+//! since the map is as long as the largest key ever inserted (until it's [compacted](CIntMap::compact)).
+
+use super::DefaultStrat;
+use std::{convert::Infallible, ops::Deref};
+
+use dbuf::interface::{FromBuffers, RawBuffers, Strategy, StrategyIntrospect};
+use sync_wrapper::SyncWrapper;
+
+use crate::{split::Split, ActiveReaderInfo, BufferId, Consistency};
+
+pub struct CIntMap<V, Strat = DefaultStrat, B = dbuf::raw::RawDBuf<Vec<Option<V>>>>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::op::OpWriter<dbuf::ptrs::alloc::OwnedPtr<Strat, B>, IntMapOp<V>>,
+}
+
+pub struct CIntMapReader<V, Strat = DefaultStrat, B = dbuf::raw::RawDBuf<Vec<Option<V>>>>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
+}
+
+pub struct CIntMapReadGuard<
+    'a,
+    V,
+    Strat = DefaultStrat,
+    T = Vec<Option<V>>,
+    B = dbuf::raw::RawDBuf<Vec<Option<V>>>,
+> where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    T: ?Sized,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T>,
+}
+
+pub enum IntMapOp<V> {
+    Insert(u32, V),
+    Remove(u32),
+    #[allow(clippy::type_complexity)]
+    Arbitrary(SyncWrapper<Box<dyn FnMut(bool, &mut Vec<Option<V>>) + Send>>),
+    Clear,
+    Compact,
+}
+
+impl<V> dbuf::op_log::Operation<Vec<Option<V>>> for IntMapOp<V>
+where
+    V: Split,
+{
+    fn apply(&mut self, buffer: &mut Vec<Option<V>>) {
+        match self {
+            IntMapOp::Insert(key, value) => *slot_mut(buffer, *key) = Some(value.split()),
+            IntMapOp::Remove(key) => remove(buffer, *key),
+            IntMapOp::Arbitrary(f) => f.get_mut()(false, buffer),
+            IntMapOp::Clear => buffer.clear(),
+            IntMapOp::Compact => compact(buffer),
+        }
+    }
+
+    fn apply_last(self, buffer: &mut Vec<Option<V>>) {
+        match self {
+            IntMapOp::Insert(key, value) => *slot_mut(buffer, key) = Some(value),
+            IntMapOp::Remove(key) => remove(buffer, key),
+            IntMapOp::Arbitrary(f) => f.into_inner()(true, buffer),
+            IntMapOp::Clear => buffer.clear(),
+            IntMapOp::Compact => compact(buffer),
+        }
+    }
+}
+
+/// the slot for `key`, growing the buffer if it's past the end
+fn slot_mut<V>(buffer: &mut Vec<Option<V>>, key: u32) -> &mut Option<V> {
+    let index = key as usize;
+    if index >= buffer.len() {
+        buffer.resize_with(index + 1, || None);
+    }
+    &mut buffer[index]
+}
+
+fn remove<V>(buffer: &mut [Option<V>], key: u32) {
+    if let Some(slot) = buffer.get_mut(key as usize) {
+        *slot = None;
+    }
+}
+
+/// remove the empty slots at the end of the buffer
+fn compact<V>(buffer: &mut Vec<Option<V>>) {
+    let len = buffer
+        .iter()
+        .rposition(Option::is_some)
+        .map_or(0, |last| last + 1);
+    buffer.truncate(len);
+}
+
+fn iter<V>(buffer: &[Option<V>]) -> impl Iterator<Item = (u32, &V)> {
+    buffer
+        .iter()
+        .enumerate()
+        .filter_map(|(key, value)| Some((key as u32, value.as_ref()?)))
+}
+
+impl<V> CIntMap<V> {
+    pub fn new() -> Self {
+        Self::from_maps(Vec::new(), Vec::new())
+    }
+
+    /// Create a map which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> CIntMap<V, P>
+    where
+        P: Strategy<ValidationError = Infallible> + Default,
+    {
+        CIntMap::default()
+    }
+}
+
+impl<V, Strat, B> Default for CIntMap<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>> + FromBuffers,
+    Strat: Strategy<ValidationError = Infallible> + Default,
+{
+    fn default() -> Self {
+        Self::from_maps(Default::default(), Default::default())
+    }
+}
+
+impl<V, Strat, B> CIntMap<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>> + FromBuffers,
+    Strat: Strategy<ValidationError = Infallible> + Default,
+{
+    /// Create a map from the two buffers
+    ///
+    /// **The two buffers must be equal**, see [`CBTreeMap::from_maps`](crate::CBTreeMap::from_maps)
+    pub fn from_maps(front: Vec<Option<V>>, back: Vec<Option<V>>) -> Self {
+        Self::from_raw_parts(front, back, Strat::default())
+    }
+}
+
+impl<V, Strat, B> CIntMap<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn from_raw_parts(front: Vec<Option<V>>, back: Vec<Option<V>>, strategy: Strat) -> Self
+    where
+        B: FromBuffers,
+    {
+        Self {
+            inner: dbuf::op::OpWriter::from(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                dbuf::raw::Shared::from_raw_parts(strategy, B::from_buffers(front, back)),
+            ))),
+        }
+    }
+
+    pub fn reader(&self) -> CIntMapReader<V, Strat, B> {
+        CIntMapReader {
+            inner: self.inner.reader(),
+        }
+    }
+
+    /// The readers which currently hold a read guard, useful for finding out who is blocking a publish
+    pub fn debug_blocking_readers(&self) -> Vec<ActiveReaderInfo>
+    where
+        Strat: StrategyIntrospect,
+    {
+        self.inner.debug_active_readers()
+    }
+
+    pub fn load(&self) -> &[Option<V>] {
+        self.inner.split().reader
+    }
+
+    pub fn get(&self, key: u32) -> Option<&V> {
+        self.inner.split().reader.get(key as usize)?.as_ref()
+    }
+
+    /// Iterate over the published keys and values in key order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &V)> {
+        iter(self.inner.split().reader)
+    }
+
+    pub fn unapplied(&self) -> &[IntMapOp<V>] {
+        self.inner.unapplied()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+}
+
+impl<V, Strat, B> CIntMap<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    V: Clone,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Repair the maps after an operation panicked, see [`CMap::clear_poison`](crate::CMap::clear_poison)
+    pub fn clear_poison(&mut self) {
+        self.inner
+            .clear_poison_with(|writer, reader| writer.clone_from(reader))
+    }
+}
+
+impl<V, Strat, B> CIntMap<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    V: Split,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Insert a value, growing the map if `key` is past the end
+    ///
+    /// The map is at least `key + 1` slots long afterwards, so keys should be small
+    pub fn insert(&mut self, key: u32, value: V) {
+        self.inner.apply(IntMapOp::Insert(key, value));
+    }
+
+    /// Remove a value, this doesn't shrink the map, see [`compact`](Self::compact)
+    pub fn remove(&mut self, key: u32) {
+        self.inner.apply(IntMapOp::Remove(key));
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.apply(IntMapOp::Clear)
+    }
+
+    /// Remove the empty slots after the largest key from both maps
+    pub fn compact(&mut self) {
+        self.inner.apply(IntMapOp::Compact)
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(bool, u32, &mut V) -> bool + Send + 'static) {
+        self.inner
+            .apply(IntMapOp::Arbitrary(SyncWrapper::new(Box::new(
+                move |is_first, map: &mut Vec<Option<V>>| {
+                    for (key, slot) in map.iter_mut().enumerate() {
+                        if let Some(value) = slot {
+                            if !f(is_first, key as u32, value) {
+                                *slot = None;
+                            }
+                        }
+                    }
+                },
+            ))))
+    }
+
+    pub fn force_publish(&mut self) {
+        self.inner.swap_buffers();
+    }
+
+    pub fn publish(&mut self) {
+        self.inner.publish()
+    }
+}
+
+impl<V, Strat, B> CIntMap<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    V: Split + PartialEq,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Check that the two maps are equal, see [`CMap::verify_consistent`](crate::CMap::verify_consistent)
+    pub fn verify_consistent(&mut self) -> Consistency {
+        self.inner.verify_buffers_eq()
+    }
+}
+
+impl<V, Strat, B> Clone for CIntMapReader<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<V, Strat, B> CIntMapReader<V, Strat, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Check if both readers read from the same map
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    pub fn load(&mut self) -> CIntMapReadGuard<'_, V, Strat, Vec<Option<V>>, B> {
+        CIntMapReadGuard {
+            inner: self.inner.get(),
+        }
+    }
+
+    pub fn get(&mut self, key: u32) -> Option<CIntMapReadGuard<'_, V, Strat, V, B>> {
+        self.load()
+            .try_map(|map| map.get(key as usize)?.as_ref())
+            .ok()
+    }
+}
+
+impl<V, Strat, T: ?Sized, B> Deref for CIntMapReadGuard<'_, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<V, Strat, B> CIntMapReadGuard<'_, V, Strat, Vec<Option<V>>, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Iterate over the keys and values in key order, skipping the empty slots
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &V)> {
+        iter(self)
+    }
+}
+
+impl<'a, V, Strat, T: ?Sized, B> CIntMapReadGuard<'a, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(self, f: impl FnOnce(&T) -> &U) -> CIntMapReadGuard<'a, V, Strat, U, B> {
+        CIntMapReadGuard {
+            inner: dbuf::raw::ReadGuard::map(self.inner, f),
+        }
+    }
+
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<CIntMapReadGuard<'a, V, Strat, U, B>, Self> {
+        match dbuf::raw::ReadGuard::try_map(self.inner, f) {
+            Ok(inner) => Ok(CIntMapReadGuard { inner }),
+            Err(inner) => Err(CIntMapReadGuard { inner }),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CIntMapReadGuard<'a, V, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CIntMapReadGuard { inner }),
+            Err((inner, err)) => Err((CIntMapReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CIntMapReadGuard { inner }),
+            Err(inner) => Err(CIntMapReadGuard { inner }),
+        }
+    }
+}
+
+impl<V, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CIntMapReadGuard<'_, V, Strat, T, B>
+where
+    B: RawBuffers<Buffer = Vec<Option<V>>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+#[test]
+fn insert_get_remove() {
+    let mut map = CIntMap::new();
+    let mut reader = map.reader();
+
+    map.insert(3, 'a');
+    map.insert(0, 'b');
+    assert!(reader.get(3).is_none());
+    map.publish();
+    assert_eq!(*reader.get(3).unwrap(), 'a');
+    assert_eq!(*reader.get(0).unwrap(), 'b');
+    assert!(reader.get(1).is_none());
+    assert!(reader.get(100).is_none());
+    assert_eq!(map.get(3), Some(&'a'));
+
+    map.remove(3);
+    map.remove(100);
+    map.publish();
+    assert!(reader.get(3).is_none());
+    assert_eq!(reader.load().len(), 4);
+
+    // the writer's buffer got the same ops
+    map.publish();
+    assert_eq!(map.load(), [Some('b'), None, None, None]);
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn guard_iter_skips_empty_slots() {
+    let mut map = CIntMap::new();
+    let mut reader = map.reader();
+
+    for key in [5, 1, 8] {
+        map.insert(key, key * 10);
+    }
+    map.remove(8);
+    map.publish();
+
+    let guard = reader.load();
+    assert!(guard.iter().map(|(k, &v)| (k, v)).eq([(1, 10), (5, 50)]));
+    assert_eq!(guard.len(), 9);
+    drop(guard);
+    assert!(map.iter().map(|(k, &v)| (k, v)).eq([(1, 10), (5, 50)]));
+}
+
+#[test]
+fn compact_truncates_empty_slots() {
+    let mut map = CIntMap::new();
+    let mut reader = map.reader();
+
+    map.insert(1, 1);
+    map.insert(10, 10);
+    map.remove(10);
+    map.compact();
+    map.publish();
+    assert_eq!(reader.load().len(), 2);
+
+    map.clear();
+    map.compact();
+    map.publish();
+    map.publish();
+    assert!(reader.load().is_empty());
+    assert!(map.load().is_empty());
+}
+
+#[test]
+fn retain() {
+    let mut map = CIntMap::new();
+    let mut reader = map.reader();
+
+    for key in 0..10 {
+        map.insert(key, key);
+    }
+    map.retain(|_, key, value| {
+        *value += 1;
+        key % 3 == 0
+    });
+    map.publish();
+    map.publish();
+
+    assert!(reader
+        .load()
+        .iter()
+        .map(|(k, &v)| (k, v))
+        .eq([(0, 1), (3, 4), (6, 7), (9, 10)]));
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
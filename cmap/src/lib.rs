@@ -1,17 +1,226 @@
 #[forbid(unsafe_code)]
+pub mod auto;
+#[forbid(unsafe_code)]
 pub mod btreemap;
 #[forbid(unsafe_code)]
 pub mod btreemultimap;
 #[forbid(unsafe_code)]
+pub mod cbuf;
+#[forbid(unsafe_code)]
+pub mod compat;
+#[forbid(unsafe_code)]
+pub mod cow;
+#[forbid(unsafe_code)]
+pub mod diff_sync;
+#[forbid(unsafe_code)]
+pub mod hasher;
+#[forbid(unsafe_code)]
+pub mod intmap;
+#[forbid(unsafe_code)]
 pub mod map;
 #[forbid(unsafe_code)]
 pub mod multimap;
+#[forbid(unsafe_code)]
+pub mod sharded;
 pub mod split;
+#[forbid(unsafe_code)]
+pub mod watch;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, PoisonError,
+    },
+    time::Duration,
+};
 
 pub type DefaultHasher = std::collections::hash_map::RandomState;
-pub type DefaultStrat = dbuf::strategy::HazardStrategy<dbuf::wait::DefaultWait>;
+/// The strategy of a map if none is given, see [`profiles`](dbuf::profiles) for others
+pub type DefaultStrat = dbuf::profiles::Balanced;
+/// A [`CMap`] whose hasher can be replaced without changing its type, see [`hasher`]
+pub type DynCMap<K, V, Strat = DefaultStrat> = CMap<K, V, DynHasher, Strat>;
+
+pub use auto::{AutoPublishHandle, AutoPublisher, Clock, SystemClock};
+pub use btreemap::{CBTreeMap, CBTreeMapReader, CBTreeMapWeakReader};
+pub use btreemultimap::{CBTreeMultiMap, CBTreeMultiMapReader, CBTreeMultiMapWeakReader};
+pub use cbuf::{CBuf, CBufReader};
+pub use cow::SharedValue;
+pub use diff_sync::CMapDiffSync;
+pub use hasher::{DynBuildHasher, DynHasher};
+pub use intmap::{CIntMap, CIntMapReader};
+pub use map::{
+    CMap, CMapReader, CMapResilientReader, CMapSharedReader, CMapWeakReader, MigrateError,
+    ReplayError, ReplicatedOp,
+};
+pub use multimap::{CMultiMap, CMultiMapReader, CMultiMapWeakReader};
+pub use sharded::{ShardedCMap, ShardedCMapReader};
+pub use watch::{KeyChange, KeyWatcher, WriterGone};
+
+pub use dbuf::cached::CachedProjection;
+pub use dbuf::interface::ActiveReaderInfo;
+pub use dbuf::op::{BuffersDiffer, Consistency, OpDiff, PublishRecord};
+pub use dbuf::raw::{BufferId, Busy, PaddedRawDBuf, RawDBuf};
+pub use dbuf::ReadError;
+
+/// The map was dropped, which readers report as [`ReadError::WriterGone`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapDropped;
+
+impl std::fmt::Display for MapDropped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the map was dropped")
+    }
+}
+
+impl std::error::Error for MapDropped {}
+
+impl From<MapDropped> for ReadError {
+    fn from(MapDropped: MapDropped) -> Self {
+        Self::WriterGone
+    }
+}
+
+/// The error returned from [`CMapReader::wait_first_publish`] if the map wasn't published in time
+///
+/// Loading a gated map before its first publish fails with [`ReadError::NotYetPublished`] instead,
+/// see [`CMap::with_initial_gate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotYetPublished;
+
+impl std::fmt::Display for NotYetPublished {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the map wasn't published yet")
+    }
+}
+
+impl std::error::Error for NotYetPublished {}
+
+impl From<NotYetPublished> for ReadError {
+    fn from(NotYetPublished: NotYetPublished) -> Self {
+        Self::NotYetPublished
+    }
+}
+
+/// Why a lookup didn't find a value, see [`CMapReader::get_or_reason`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupError {
+    /// the key isn't in the map
+    MissingKey,
+    /// the key is in the multimap, but its bag is empty
+    EmptyBag,
+    /// the value was found, but the filter rejected it
+    Filtered,
+}
+
+impl std::fmt::Display for LookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::MissingKey => "the key isn't in the map",
+            Self::EmptyBag => "the key has no values",
+            Self::Filtered => "the value was rejected by the filter",
+        })
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+/// Build the op writer of a map from its two buffers, or return an error if they aren't equal
+///
+/// This is shared by the `from_maps_checked` constructors, see [`CMap::from_maps_checked`]
+#[allow(clippy::type_complexity)]
+pub(crate) fn op_writer_checked<T, Strat, B, O>(
+    front: T,
+    back: T,
+) -> Result<dbuf::op::OpWriter<dbuf::ptrs::alloc::OwnedPtr<Strat, B>, O>, BuffersDiffer>
+where
+    T: PartialEq,
+    Strat: dbuf::interface::Strategy + Default,
+    B: dbuf::interface::RawBuffers<Buffer = T> + dbuf::interface::FromBuffers,
+{
+    let writer = dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+        dbuf::raw::Shared::from_raw_parts(Strat::default(), B::from_buffers(front, back)),
+    ));
+
+    dbuf::op::OpWriter::from_writer_checked(writer)
+}
+
+/// A count of the published map, shared by the writer and its readers
+///
+/// The writer updates it after each publish, so readers can load it without a read guard.
+/// It also tracks if the map was published at all, see [`CMap::with_initial_gate`]
+#[derive(Clone)]
+pub(crate) struct PublishedCount(Arc<PublishedState>);
+
+/// the state shared by each clone of a [`PublishedCount`]
+struct PublishedState {
+    count: AtomicUsize,
+    /// true once the map was published, or from the start if the map isn't gated
+    initialized: AtomicBool,
+    /// the lock for `first_publish`
+    lock: Mutex<()>,
+    /// wakes the readers which wait for the first publish
+    first_publish: Condvar,
+}
+
+impl PublishedCount {
+    pub(crate) fn new(count: usize) -> Self {
+        Self::with_initialized(count, true)
+    }
+
+    /// a count which isn't initialized until the first publish
+    pub(crate) fn new_gated(count: usize) -> Self {
+        Self::with_initialized(count, false)
+    }
+
+    fn with_initialized(count: usize, initialized: bool) -> Self {
+        Self(Arc::new(PublishedState {
+            count: count.into(),
+            initialized: initialized.into(),
+            lock: Mutex::new(()),
+            first_publish: Condvar::new(),
+        }))
+    }
+
+    pub(crate) fn get(&self) -> usize {
+        // pairs with the store in `set`, so a read guard taken afterwards sees at least that publish
+        self.0.count.load(Ordering::Acquire)
+    }
+
+    /// update the count after a publish, this also marks the map as initialized
+    pub(crate) fn set(&self, count: usize) {
+        self.0.count.store(count, Ordering::Release);
+
+        if !self.0.initialized.load(Ordering::Relaxed) {
+            // hold the lock so that a reader can't miss the notification between
+            // checking the flag and starting to wait
+            let _lock = self.0.lock.lock().unwrap_or_else(PoisonError::into_inner);
+            self.0.initialized.store(true, Ordering::Release);
+            self.0.first_publish.notify_all();
+        }
+    }
+
+    pub(crate) fn is_initialized(&self) -> bool {
+        // pairs with the store in `set`, like in `get`
+        self.0.initialized.load(Ordering::Acquire)
+    }
+
+    /// wait until the map is initialized, or `timeout` passes
+    pub(crate) fn wait_initialized(&self, timeout: Duration) -> Result<(), NotYetPublished> {
+        if self.is_initialized() {
+            return Ok(());
+        }
+
+        let lock = self.0.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let _lock = self
+            .0
+            .first_publish
+            .wait_timeout_while(lock, timeout, |()| !self.is_initialized())
+            .unwrap_or_else(PoisonError::into_inner);
 
-pub use btreemap::{CBTreeMap, CBTreeMapReader};
-pub use btreemultimap::{CBTreeMultiMap, CBTreeMultiMapReader};
-pub use map::{CMap, CMapReader};
-pub use multimap::{CMultiMap, CMultiMapReader};
+        if self.is_initialized() {
+            Ok(())
+        } else {
+            Err(NotYetPublished)
+        }
+    }
+}
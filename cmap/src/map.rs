@@ -5,52 +5,312 @@ use std::{
     convert::Infallible,
     hash::{BuildHasher, Hash},
     ops::Deref,
+    time::Duration,
 };
 
-use dbuf::interface::Strategy;
+use dbuf::cached::CachedProjection;
+use dbuf::footprint::{Footprint, MemoryFootprint, MemoryUsage, CLOSURE_BYTES};
+use dbuf::interface::{
+    FreshCopy, FromBuffers, RawBuffers, Strategy, StrategyIntrospect, ValidationErrorOf,
+};
+use dbuf::op_log::{ApplyToBoth, Boxed, Inline, OpIter, OpLog, OpStorage};
 use sync_wrapper::SyncWrapper;
 
-use crate::split::Split;
+use crate::{
+    split::Split,
+    watch::{KeyWatcher, Watchers},
+    ActiveReaderInfo, BufferId, BuffersDiffer, Consistency, LookupError, NotYetPublished, OpDiff,
+    PublishRecord, PublishedCount, ReadError,
+};
 
-pub struct CMap<K, V, S = DefaultHasher, Strat = DefaultStrat>
-where
-    Strat: Strategy<ValidationError = Infallible>,
+pub struct CMap<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+    Ops = Inline,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+    Ops: OpStorage,
 {
+    /// the maps and the unpublished operations, which are stored as `Ops` says, see [`CMap::with_boxed_ops`]
     #[allow(clippy::type_complexity)]
     inner: dbuf::op::OpWriter<
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<HashMap<K, V, S>>>,
+        dbuf::ptrs::alloc::OwnedPtr<Strat, B>,
         MapOp<K, V, S>,
+        OpLog<MapOp<K, V, S>, Ops>,
     >,
+    watchers: Watchers<K, V>,
+    /// copies the published map into the writer map, while the writer map is out of date,
+    /// see [`CMap::new_lazy`]
+    #[allow(clippy::type_complexity)]
+    materialize: Option<fn(&mut HashMap<K, V, S>, &HashMap<K, V, S>)>,
+    /// the length of the published map, see [`CMap::published_len`]
+    published_len: PublishedCount,
 }
 
-pub struct CMapReader<K, V, S, Strat>
+pub struct CMapReader<K, V, S, Strat, B = dbuf::raw::RawDBuf<HashMap<K, V, S>>>
 where
-    Strat: Strategy<ValidationError = Infallible>,
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
+    published_len: PublishedCount,
+}
+
+pub struct CMapReadGuard<
+    'a,
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    T = HashMap<K, V, S>,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    T: ?Sized,
+    Strat: Strategy,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T>,
+}
+
+pub struct CMapZoomGuard<
+    'a,
+    K,
+    V,
+    S,
+    Strat,
+    T: ?Sized,
+    U: ?Sized,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ZoomGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T, U>,
+}
+
+pub struct CMapWeakReader<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedWeak<Strat, B>>,
+}
+
+pub struct CMapWeakReadGuard<
+    'a,
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    T = HashMap<K, V, S>,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    T: ?Sized,
+    Strat: Strategy,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedStrong<Strat, B>, T>,
+}
+
+/// A weak reader which keeps serving the last map it read after the map is dropped,
+/// see [`CMapWeakReader::into_resilient`]
+pub struct CMapResilientReader<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
 {
     #[allow(clippy::type_complexity)]
     inner:
-        dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<HashMap<K, V, S>>>>,
+        dbuf::resilient::ResilientReader<dbuf::ptrs::alloc::OwnedWeak<Strat, B>, HashMap<K, V, S>>,
+    /// whether the last read fell back to the last map
+    is_stale: bool,
 }
 
-pub struct CMapReadGuard<'a, K, V, S = DefaultHasher, Strat = DefaultStrat, T = HashMap<K, V, S>>
-where
+/// A reader which reads through `&self`, see [`CMap::shared_reader`]
+pub struct CMapSharedReader<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::SharedReader<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
+    published_len: PublishedCount,
+}
+
+/// A read guard which doesn't borrow its reader, see [`CMapSharedReader`]
+pub struct CMapOwnedReadGuard<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    T = HashMap<K, V, S>,
+    B = dbuf::raw::RawDBuf<HashMap<K, V, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
     T: ?Sized,
-    Strat: Strategy<ValidationError = Infallible>,
+    Strat: Strategy,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::raw::ReadGuard<
-        'a,
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<HashMap<K, V, S>>>,
-        T,
-    >,
+    inner: dbuf::raw::OwnedReadGuard<dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T>,
 }
 
 pub enum MapOp<K, V, S> {
     Insert(K, V),
+    Extend(Vec<(K, V)>),
     Remove(K),
+    /// change the value of a key, see [`CMap::update`]
+    #[allow(clippy::type_complexity)]
+    Update(K, SyncWrapper<Box<dyn FnMut(&mut V) + Send>>, Option<V>),
     #[allow(clippy::type_complexity)]
     Arbitrary(SyncWrapper<Box<dyn FnMut(bool, &mut HashMap<K, V, S>) + Send>>),
+    /// a closure which runs on each map, see [`OpWriter::apply_to_both`](dbuf::op::OpWriter::apply_to_both)
+    ApplyToBoth(ApplyToBoth<HashMap<K, V, S>>),
+    Clear,
+}
+
+/// A [`MapOp`] as plain data, so that it can be sent to another map
+///
+/// see [`CMap::replication_batch`] and [`CMap::apply_replicated`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReplicatedOp<K, V> {
+    Insert(K, V),
+    Extend(Vec<(K, V)>),
+    Remove(K),
     Clear,
+    /// an operation which runs a closure, like [`CMap::retain`], so it can't be replicated.
+    /// Replicated maps should only use operations which are plain data
+    Opaque,
+}
+
+/// The error returned from [`CMap::apply_replicated`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError {
+    /// the batch had a [`ReplicatedOp::Opaque`], so none of it was applied
+    OpaqueOp,
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::OpaqueOp => f.write_str("an opaque operation can't be replayed"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// The error returned from [`CMap::migrate_values`]
+pub enum MigrateError<M, M2> {
+    /// there are [`CMapReader`]s, which can only read the old values, so the map is given back unchanged
+    HasReaders(M),
+    /// the values were migrated, but some unpublished operations run closures on the old values,
+    /// like [`CMap::retain`], so they were dropped
+    DroppedOps {
+        /// the migrated map, with the rest of the unpublished operations
+        map: M2,
+        /// the number of dropped operations
+        dropped: usize,
+    },
+}
+
+impl<M, M2> std::fmt::Debug for MigrateError<M, M2> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrateError::HasReaders(_) => f.write_str("HasReaders(..)"),
+            MigrateError::DroppedOps { dropped, .. } => f
+                .debug_struct("DroppedOps")
+                .field("dropped", dropped)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<M, M2> std::fmt::Display for MigrateError<M, M2> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrateError::HasReaders(_) => {
+                f.write_str("the map still has readers, so its values can't be migrated")
+            }
+            MigrateError::DroppedOps { dropped, .. } => {
+                write!(f, "{dropped} unpublished operations couldn't be migrated")
+            }
+        }
+    }
+}
+
+impl<M, M2> std::error::Error for MigrateError<M, M2> {}
+
+impl<K: Clone, V: Clone, S> MapOp<K, V, S> {
+    /// copy this operation as plain data
+    pub fn to_replicated(&self) -> ReplicatedOp<K, V> {
+        match self {
+            MapOp::Insert(key, value) => ReplicatedOp::Insert(key.clone(), value.clone()),
+            MapOp::Extend(items) => ReplicatedOp::Extend(items.clone()),
+            MapOp::Remove(key) => ReplicatedOp::Remove(key.clone()),
+            MapOp::Update(..) | MapOp::Arbitrary(_) | MapOp::ApplyToBoth(_) => ReplicatedOp::Opaque,
+            MapOp::Clear => ReplicatedOp::Clear,
+        }
+    }
+}
+
+impl<K, V, S> MapOp<K, V, S> {
+    /// call `touch` with each key which this operation may change
+    ///
+    /// returns false if this operation may change any key
+    fn touched_keys(&self, mut touch: impl FnMut(&K)) -> bool {
+        match self {
+            MapOp::Insert(key, _) | MapOp::Remove(key) | MapOp::Update(key, ..) => touch(key),
+            MapOp::Extend(items) => items.iter().for_each(|(key, _)| touch(key)),
+            MapOp::Arbitrary(_) | MapOp::ApplyToBoth(_) | MapOp::Clear => return false,
+        }
+        true
+    }
+}
+
+// closures are counted as `CLOSURE_BYTES`, see `dbuf::footprint`
+impl<K: MemoryUsage, V: MemoryUsage, S> MemoryUsage for MapOp<K, V, S> {
+    fn heap_bytes(&self) -> usize {
+        match self {
+            MapOp::Insert(key, value) => key.heap_bytes() + value.heap_bytes(),
+            MapOp::Extend(items) => items.heap_bytes(),
+            MapOp::Remove(key) => key.heap_bytes(),
+            MapOp::Update(key, _, value) => key.heap_bytes() + CLOSURE_BYTES + value.heap_bytes(),
+            MapOp::Arbitrary(_) => CLOSURE_BYTES,
+            MapOp::ApplyToBoth(op) => op.heap_bytes(),
+            MapOp::Clear => 0,
+        }
+    }
+}
+
+impl<K, V, S> From<ApplyToBoth<HashMap<K, V, S>>> for MapOp<K, V, S> {
+    fn from(op: ApplyToBoth<HashMap<K, V, S>>) -> Self {
+        MapOp::ApplyToBoth(op)
+    }
 }
 
 impl<K, V, S> dbuf::op_log::Operation<HashMap<K, V, S>> for MapOp<K, V, S>
@@ -64,10 +324,26 @@ where
             MapOp::Insert(key, value) => {
                 buffer.insert(key.split(), value.split());
             }
+            MapOp::Extend(items) => {
+                buffer.reserve(items.len());
+                buffer.extend(
+                    items
+                        .iter_mut()
+                        .map(|(key, value)| (key.split(), value.split())),
+                );
+            }
             MapOp::Remove(key) => {
                 buffer.remove(key);
             }
+            MapOp::Update(key, f, updated) => {
+                // keep a split of the changed value for the other map
+                *updated = buffer.get_mut(key).map(|value| {
+                    f.get_mut()(value);
+                    value.split()
+                });
+            }
             MapOp::Arbitrary(f) => f.get_mut()(false, buffer),
+            MapOp::ApplyToBoth(op) => op.apply(buffer),
             MapOp::Clear => buffer.clear(),
         }
     }
@@ -77,10 +353,23 @@ where
             MapOp::Insert(key, value) => {
                 buffer.insert(key, value);
             }
+            MapOp::Extend(items) => {
+                buffer.reserve(items.len());
+                buffer.extend(items);
+            }
             MapOp::Remove(ref key) => {
                 buffer.remove(key);
             }
+            MapOp::Update(key, mut f, updated) => {
+                if let Some(value) = buffer.get_mut(&key) {
+                    match updated {
+                        Some(updated) => *value = updated,
+                        None => f.get_mut()(value),
+                    }
+                }
+            }
             MapOp::Arbitrary(f) => f.into_inner()(true, buffer),
+            MapOp::ApplyToBoth(op) => op.apply_last(buffer),
             MapOp::Clear => buffer.clear(),
         }
     }
@@ -90,12 +379,34 @@ impl<K, V> CMap<K, V> {
     pub fn new() -> Self {
         Self::from_maps(HashMap::new(), HashMap::new())
     }
+
+    /// Create a map whose readers can tell if it was published yet
+    ///
+    /// Readers usually see the empty map until the first publish, so a reader which starts
+    /// before the initial load may see a map which is missing everything. With the gate,
+    /// [`CMapReader::try_load_initialized`] returns [`ReadError::NotYetPublished`] until the first publish,
+    /// and [`CMapReader::wait_first_publish`] waits for it. The plain [`CMapReader::load`]
+    /// isn't affected
+    pub fn with_initial_gate() -> Self {
+        let mut map = Self::new();
+        map.published_len = PublishedCount::new_gated(0);
+        map
+    }
+
+    /// Create a map which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> CMap<K, V, DefaultHasher, P>
+    where
+        P: Strategy + Default,
+    {
+        CMap::default()
+    }
 }
 
-impl<K, V, S, Strat> Default for CMap<K, V, S, Strat>
+impl<K, V, S, Strat, B> Default for CMap<K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, V, S>> + FromBuffers,
     S: Default,
-    Strat: Strategy<ValidationError = Infallible> + Default,
+    Strat: Strategy + Default,
 {
     fn default() -> Self {
         Self::from_maps(Default::default(), Default::default())
@@ -111,157 +422,2898 @@ impl<K, V, S: Split> CMap<K, V, S> {
     }
 }
 
-impl<K, V, S, Strat> CMap<K, V, S, Strat>
+impl<K, V, S, Strat, B> CMap<K, V, S, Strat, B>
 where
-    Strat: Strategy<ValidationError = Infallible> + Default,
+    B: RawBuffers<Buffer = HashMap<K, V, S>> + FromBuffers,
+    Strat: Strategy + Default,
 {
+    /// Create a map from the two buffers
+    ///
+    /// **The two maps must be equal.** Each operation is applied to both maps, so a difference
+    /// is never repaired, and readers will see one map or the other depending on which was
+    /// published last. Use [`from_maps_checked`](Self::from_maps_checked) if you aren't sure
     pub fn from_maps(front: HashMap<K, V, S>, back: HashMap<K, V, S>) -> Self {
         Self::from_raw_parts(front, back, Strat::default())
     }
+
+    /// Create a map from the two buffers, or return an error if they aren't equal
+    pub fn from_maps_checked(
+        front: HashMap<K, V, S>,
+        back: HashMap<K, V, S>,
+    ) -> Result<Self, BuffersDiffer>
+    where
+        K: Hash + Eq,
+        V: PartialEq,
+        S: BuildHasher,
+    {
+        let inner = crate::op_writer_checked::<_, Strat, B, _>(front, back)?;
+
+        Ok(Self {
+            published_len: PublishedCount::new(inner.split().reader.len()),
+            inner,
+            watchers: Watchers::new(),
+            materialize: None,
+        })
+    }
+
+    /// Create a map which publishes `initial`, without copying it up front
+    ///
+    /// Both maps are usually built when the map is created. Here the writer map starts out
+    /// empty, and `initial` is copied into it by the first publish (or [`materialize`](Self::materialize)),
+    /// right before the operations are applied. So a large map which is loaded at startup
+    /// and rarely written only pays for the copy once it's written to.
+    pub fn new_lazy(initial: HashMap<K, V, S>) -> Self
+    where
+        K: Clone,
+        V: Clone,
+        S: Clone,
+    {
+        let writer = HashMap::with_hasher(initial.hasher().clone());
+        // the first buffer starts out as the writer buffer
+        let mut map = Self::from_maps(writer, initial);
+        map.materialize = Some(|writer, published| writer.clone_from(published));
+        map
+    }
 }
 
-impl<K, V, S, Strat> CMap<K, V, S, Strat>
+impl<K, V, S, Strat, B> CMap<K, V, S, Strat, B>
 where
-    Strat: Strategy<ValidationError = Infallible>,
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
 {
-    pub fn from_raw_parts(
-        front: HashMap<K, V, S>,
-        back: HashMap<K, V, S>,
-        strategy: Strat,
-    ) -> Self {
+    pub fn from_raw_parts(front: HashMap<K, V, S>, back: HashMap<K, V, S>, strategy: Strat) -> Self
+    where
+        B: FromBuffers,
+    {
+        let inner =
+            dbuf::op::OpWriter::from(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                dbuf::raw::Shared::from_raw_parts(strategy, B::from_buffers(front, back)),
+            )));
         Self {
-            inner: dbuf::op::OpWriter::from(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
-                dbuf::raw::Shared::from_raw_parts(strategy, dbuf::raw::RawDBuf::new(front, back)),
-            ))),
+            published_len: PublishedCount::new(inner.split().reader.len()),
+            inner,
+            watchers: Watchers::new(),
+            materialize: None,
         }
     }
 
-    pub fn reader(&self) -> CMapReader<K, V, S, Strat> {
+    pub fn unapplied(&self) -> &[MapOp<K, V, S>] {
+        self.inner.unapplied()
+    }
+
+    /// Store each unpublished operation in its own allocation
+    ///
+    /// A [`MapOp`] is as large as its largest variant, so with large keys or values pushing
+    /// operations and publishing them moves a lot of bytes. With boxed operations only a
+    /// pointer is moved, at the cost of an allocation for each operation. The operations are
+    /// still applied the same way, see [`Boxed`]. The unpublished operations can't be borrowed
+    /// as a slice anymore, see [`unapplied_iter`](Self::unapplied_iter)
+    pub fn with_boxed_ops(self) -> CMap<K, V, S, Strat, B, Boxed> {
+        CMap {
+            inner: self.inner.with_boxed_ops(),
+            watchers: self.watchers,
+            materialize: self.materialize,
+            published_len: self.published_len,
+        }
+    }
+}
+
+impl<K, V, S, Strat, B, Ops> CMap<K, V, S, Strat, B, Ops>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+    Ops: OpStorage,
+{
+    pub fn reader(&self) -> CMapReader<K, V, S, Strat, B> {
         CMapReader {
             inner: self.inner.reader(),
+            published_len: self.published_len.clone(),
+        }
+    }
+
+    /// Create a reader which doesn't keep the maps alive
+    ///
+    /// A [`CMapReader`] holds a strong reference to the maps, so a forgotten reader
+    /// keeps both maps alive after the `CMap` is dropped. A weak reader only keeps
+    /// the small shared allocation alive: once the `CMap` and all strong readers are
+    /// dropped, the maps are dropped and the weak reader returns [`ReadError::WriterGone`].
+    ///
+    /// The trade-off is that every read needs to upgrade the weak reference
+    pub fn weak_reader(&self) -> CMapWeakReader<K, V, S, Strat, B> {
+        CMapWeakReader {
+            inner: self.inner.reader().into_weak(),
         }
     }
 
+    /// Create a reader which reads through `&self`
+    ///
+    /// A [`CMapReader`] needs `&mut self` to read, so it can't be shared between futures
+    /// which are polled at the same time, like the futures in a `join!`. A shared reader can,
+    /// because every read creates a fresh reader tag, and returns a guard which owns it.
+    /// The guards are `Send`, so they can be held across an `.await`, but the map can't be
+    /// published while one is held.
+    ///
+    /// The trade-off is that a fresh reader tag can't use the per-reader cache of a [`CMapReader`],
+    /// so every read does a little more work. This is only available for strategies where creating
+    /// reader tags is cheap, see [`CheapReaderTag`](dbuf::interface::CheapReaderTag)
+    pub fn shared_reader(&self) -> CMapSharedReader<K, V, S, Strat, B>
+    where
+        Strat: dbuf::interface::CheapReaderTag,
+    {
+        self.reader().into_shared()
+    }
+
+    /// The readers which currently hold a read guard, useful for finding out who is blocking a publish
+    pub fn debug_blocking_readers(&self) -> Vec<ActiveReaderInfo>
+    where
+        Strat: StrategyIntrospect,
+    {
+        self.inner.debug_active_readers()
+    }
+
+    /// How many bytes both maps, the unpublished operations and the strategy hold
+    ///
+    /// Each map is counted as its hash table, which is `buckets * size_of::<(K, V)>() + buckets + 16`
+    /// bytes (see [`hash_table_bytes`](dbuf::footprint::hash_table_bytes) for how many buckets there are),
+    /// plus what the keys and values own on the heap. Operations which run a closure, like
+    /// [`CMap::update`], are counted as [`CLOSURE_BYTES`]
+    pub fn memory_footprint(&self) -> Footprint
+    where
+        K: MemoryUsage,
+        V: MemoryUsage,
+        Strat: StrategyIntrospect,
+        OpLog<MapOp<K, V, S>, Ops>: MemoryUsage,
+    {
+        self.inner.footprint()
+    }
+
+    /// Returns true if a publish now may have to wait for readers to leave the published map
+    ///
+    /// This doesn't change anything, so it can be used to put off a publish while the readers are busy.
+    /// It's advisory, since a reader may start reading right after this returns false,
+    /// see [`Writer::would_swap_block`](dbuf::raw::Writer::would_swap_block)
+    pub fn would_publish_block(&self) -> bool {
+        self.inner.would_swap_block()
+    }
+
     pub fn load(&self) -> &HashMap<K, V, S> {
         self.inner.split().reader
     }
-}
 
-impl<K, V, S, Strat> CMap<K, V, S, Strat>
-where
-    K: Hash + Eq + Split,
-    V: Split,
-    S: BuildHasher,
-    Strat: Strategy<ValidationError = Infallible>,
-{
-    pub fn insert(&mut self, key: K, value: V) {
-        self.inner.apply(MapOp::Insert(key, value));
+    /// The number of entries in the published map, without taking a read guard
+    ///
+    /// This is exact as of the last publish, and doesn't count the unpublished operations
+    pub fn published_len(&self) -> usize {
+        self.published_len.get()
     }
 
-    pub fn remove(&mut self, key: K) {
-        self.inner.apply(MapOp::Remove(key));
+    /// The hasher of the published map
+    pub fn hasher(&self) -> &S {
+        self.inner.split().reader.hasher()
     }
 
     pub fn get<Q>(&self, key: &Q) -> Option<&V>
     where
         Q: ?Sized + Hash + Eq,
-        K: Borrow<Q>,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
     {
         self.inner.split().reader.get(key)
     }
 
-    pub fn clear(&mut self) {
-        self.inner.apply(MapOp::Clear)
+    /// The unpublished operations
+    pub fn unapplied_iter(&self) -> OpIter<'_, MapOp<K, V, S>> {
+        self.inner.unapplied_iter()
     }
 
-    pub fn retain(&mut self, mut f: impl FnMut(bool, &K, &mut V) -> bool + Send + 'static) {
-        self.inner.apply(MapOp::Arbitrary(SyncWrapper::new(Box::new(
-            move |is_first, map| map.retain(|k, v| f(is_first, k, v)),
-        ))))
+    /// Copy the unpublished operations as plain data
+    ///
+    /// Call this right before each [`publish`](Self::publish), and replay the batch
+    /// into a mirror map with [`apply_replicated`](Self::apply_replicated)
+    pub fn replication_batch(&self) -> Vec<ReplicatedOp<K, V>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.unapplied_iter().map(MapOp::to_replicated).collect()
     }
 
-    pub fn unapplied(&self) -> &[MapOp<K, V, S>] {
-        self.inner.unapplied()
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
     }
 
-    pub fn force_publish(&mut self) {
-        self.inner.swap_buffers();
+    /// Keep a record of the last `capacity` publishes, 0 disables the history (the default)
+    ///
+    /// see [`OpWriter::set_history_capacity`](dbuf::op::OpWriter::set_history_capacity)
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.inner.set_history_capacity(capacity)
     }
 
-    pub fn publish(&mut self) {
-        self.inner.publish()
+    /// Record each publish into `recorder`, or stop recording them with `None`
+    ///
+    /// To record the read guards too, create the map with a [`RecordedStrategy`](dbuf::flight_recorder::RecordedStrategy),
+    /// see [`OpWriter::set_flight_recorder`](dbuf::op::OpWriter::set_flight_recorder)
+    #[cfg(feature = "flight-recorder")]
+    pub fn set_flight_recorder(
+        &mut self,
+        recorder: Option<std::sync::Arc<dbuf::flight_recorder::FlightRecorder>>,
+    ) {
+        self.inner.set_flight_recorder(recorder)
     }
-}
 
-impl<K, V, S, Strat> Clone for CMapReader<K, V, S, Strat>
-where
-    Strat: Strategy<ValidationError = Infallible>,
-{
-    fn clone(&self) -> Self {
-        Self {
-            inner: self.inner.clone(),
+    /// The most recent publishes, oldest first
+    ///
+    /// This is empty unless the history was enabled with [`set_history_capacity`](Self::set_history_capacity)
+    pub fn recent_publishes(&self) -> impl Iterator<Item = PublishRecord> + '_ {
+        self.inner.recent_publishes()
+    }
+
+    /// Make every publish block until readers have left the old map, to rule out the delayed
+    /// swap when chasing a bug
+    ///
+    /// see [`OpWriter::set_strict_mode`](dbuf::op::OpWriter::set_strict_mode), and
+    /// [`set_strict_mode_checked`](Self::set_strict_mode_checked) to also compare the maps
+    pub fn set_strict_mode(&mut self, on: bool) {
+        self.inner.set_strict_mode(on)
+    }
+
+    /// true if strict mode is on, see [`set_strict_mode`](Self::set_strict_mode)
+    pub fn is_strict_mode(&self) -> bool {
+        self.inner.is_strict_mode()
+    }
+
+    /// Wait until every read guard which was acquired before this call has been dropped
+    ///
+    /// After a publish, this means no reader can still see the previous version of the map,
+    /// so anything which was only referenced by that version can be safely retired
+    pub fn wait_readers_caught_up(&mut self)
+    where
+        Strat: Strategy<ValidationError = Infallible>,
+    {
+        self.inner.wait_for_quiescence();
+    }
+
+    /// Move the maps to a different strategy without copying them
+    ///
+    /// This fails if there are any [`CMapReader`]s, since they use the old strategy.
+    /// [`CMapWeakReader`]s don't prevent the move, but will return [`ReadError::WriterGone`] afterwards.
+    /// The unpublished operations are carried over to the new map
+    #[allow(clippy::result_large_err)]
+    pub fn try_map_strategy<Strat2>(
+        self,
+        f: impl FnOnce(Strat) -> Strat2,
+    ) -> Result<CMap<K, V, S, Strat2, B, Ops>, Self>
+    where
+        Strat2: Strategy,
+    {
+        let watchers = self.watchers;
+        let materialize = self.materialize;
+        let published_len = self.published_len;
+        match self.inner.try_map_writer(|writer| {
+            let shared = writer.try_into_shared()?;
+            Ok(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                shared.map_strategy(f),
+            )))
+        }) {
+            Ok(inner) => Ok(CMap {
+                inner,
+                watchers,
+                materialize,
+                published_len,
+            }),
+            Err(inner) => Err(Self {
+                inner,
+                watchers,
+                materialize,
+                published_len,
+            }),
         }
     }
+
+    /// Move the maps to the default thread-safe strategy without copying them
+    ///
+    /// see [`try_map_strategy`](Self::try_map_strategy)
+    #[allow(clippy::result_large_err)]
+    pub fn try_into_sync(self) -> Result<CMap<K, V, S, DefaultStrat, B, Ops>, Self> {
+        self.try_map_strategy(|_| DefaultStrat::default())
+    }
 }
 
-impl<K, V, S, Strat> CMapReader<K, V, S, Strat>
+impl<K, V, S, Strat> CMap<K, V, S, Strat>
 where
-    Strat: Strategy<ValidationError = Infallible>,
+    K: Hash + Eq + Clone,
+    V: Clone,
+    S: BuildHasher + Clone,
+    Strat: Strategy + FreshCopy,
 {
-    pub fn load(&mut self) -> CMapReadGuard<K, V, S, Strat> {
-        CMapReadGuard {
-            inner: self.inner.get(),
+    /// Rebuild the map with a new hasher, for example to rotate the keys of a [`RandomState`](std::collections::hash_map::RandomState)
+    ///
+    /// The new map is built from a copy of the published map with the unpublished operations
+    /// applied, so all operations are published in the new map. Watchers are moved to the new map.
+    ///
+    /// **Existing readers are not moved.** They keep reading the last map that was published
+    /// before the rehash, which is never updated again, and it stays alive until all of them are
+    /// dropped. Create new readers from the returned map. To rotate the hasher without changing
+    /// the type of the map, use a [`DynCMap`](crate::DynCMap)
+    ///
+    /// The old readers still use the old strategy, so the new map gets a fresh strategy
+    /// with the same configuration, see [`FreshCopy`]
+    pub fn rehash_with<S2>(mut self, mut hasher: S2) -> CMap<K, V, S2, Strat>
+    where
+        S2: BuildHasher + Split,
+    {
+        self.touch_watched_keys();
+
+        // the published map is copied anyway, so the writer map doesn't need to be materialized
+        let Self {
+            inner, watchers, ..
+        } = self;
+        let (writer, mut op_log) = inner.into_raw_parts();
+
+        // the readers may still be reading the published map, so it must be copied
+        let mut current = writer.split().reader.clone();
+        op_log.apply_unapplied(&mut current);
+        let strategy = writer.fresh_strategy();
+        drop(writer);
+
+        let mut front = HashMap::with_capacity_and_hasher(current.len(), hasher.split());
+        let mut back = HashMap::with_capacity_and_hasher(current.len(), hasher);
+        for (mut key, mut value) in current {
+            front.insert(key.split(), value.split());
+            back.insert(key, value);
         }
+
+        let mut map = CMap::from_raw_parts(front, back, strategy);
+        map.watchers = watchers;
+        map.notify_watchers();
+        map
     }
+}
 
-    pub fn get<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<K, V, S, Strat, V>>
+impl<K, V, S, Strat> CMap<K, V, S, Strat>
+where
+    K: Hash + Eq + Split,
+    V: Split,
+    S: BuildHasher + Clone,
+    Strat: Strategy,
+{
+    /// Change the type of the values, by calling `f` on each value in both maps
+    ///
+    /// This fails if there are any [`CMapReader`]s, since they can only read the old values.
+    /// [`CMapWeakReader`]s don't prevent the migration, but will return [`ReadError::WriterGone`] afterwards.
+    /// Create new readers from the returned map. The strategy and the publish count are kept.
+    ///
+    /// The unpublished inserts are migrated with `f` too. Operations which run closures on the
+    /// old values, like [`update`](Self::update) and [`retain`](Self::retain), can't be migrated,
+    /// so they are dropped, and the migrated map is returned in [`MigrateError::DroppedOps`].
+    /// Watchers of the old map see it as dropped.
+    ///
+    /// # Panics
+    ///
+    /// if the map is poisoned, see [`clear_poison`](Self::clear_poison)
+    ///
+    /// ```
+    /// let mut map = cmap::CMap::<&str, u32>::new();
+    /// map.insert("a", 1);
+    /// map.publish();
+    /// map.insert("b", 2);
+    ///
+    /// let Ok(mut map) = map.migrate_values(|value| value.to_string()) else {
+    ///     unreachable!()
+    /// };
+    /// assert_eq!(map.get("a"), Some(&"1".to_string()));
+    /// assert_eq!(map.get("b"), None);
+    /// map.publish();
+    /// assert_eq!(map.get("b"), Some(&"2".to_string()));
+    /// ```
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn migrate_values<V2>(
+        mut self,
+        mut f: impl FnMut(V) -> V2,
+    ) -> Result<CMap<K, V2, S, Strat>, MigrateError<Self, CMap<K, V2, S, Strat>>>
     where
-        Q: ?Sized + Hash + Eq,
-        K: Hash + Eq + Borrow<Q>,
-        S: BuildHasher,
+        V2: Split,
     {
-        self.load().try_map(|map| map.get(key)).ok()
+        // the migration catches up the writer map, which must be a copy of the published map for that
+        self.materialize();
+
+        let watchers = self.watchers;
+        let published_len = self.published_len;
+        let mut dropped = 0;
+        let migrated = self.inner.try_migrate(|writer, ops| {
+            let writer = writer.map_buffers(|map| {
+                let mut migrated =
+                    HashMap::with_capacity_and_hasher(map.len(), map.hasher().clone());
+                migrated.extend(map.into_iter().map(|(key, value)| (key, f(value))));
+                migrated
+            });
+            let writer = match writer {
+                Ok(writer) => writer,
+                Err(writer) => return Err((writer, ops)),
+            };
+
+            let ops = ops
+                .into_iter()
+                .filter_map(|op| match op {
+                    MapOp::Insert(key, value) => Some(MapOp::Insert(key, f(value))),
+                    MapOp::Extend(items) => Some(MapOp::Extend(
+                        items
+                            .into_iter()
+                            .map(|(key, value)| (key, f(value)))
+                            .collect(),
+                    )),
+                    MapOp::Remove(key) => Some(MapOp::Remove(key)),
+                    MapOp::Clear => Some(MapOp::Clear),
+                    MapOp::Update(..) | MapOp::Arbitrary(_) | MapOp::ApplyToBoth(_) => {
+                        dropped += 1;
+                        None
+                    }
+                })
+                .collect();
+            Ok((writer, ops))
+        });
+
+        match migrated {
+            Ok(inner) => {
+                let map = CMap {
+                    inner,
+                    watchers: Watchers::new(),
+                    materialize: None,
+                    published_len,
+                };
+                if dropped == 0 {
+                    Ok(map)
+                } else {
+                    Err(MigrateError::DroppedOps { map, dropped })
+                }
+            }
+            Err(inner) => Err(MigrateError::HasReaders(Self {
+                inner,
+                watchers,
+                materialize: None,
+                published_len,
+            })),
+        }
     }
 }
 
-impl<K, V, S, Strat, T: ?Sized> Deref for CMapReadGuard<'_, K, V, S, Strat, T>
+impl<K, V, S, Strat, B, Ops> CMap<K, V, S, Strat, B, Ops>
 where
-    Strat: Strategy<ValidationError = Infallible>,
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    K: Clone,
+    V: Clone,
+    S: Clone,
+    Strat: Strategy,
+    Ops: OpStorage,
 {
-    type Target = T;
+    /// Repair the maps after an operation panicked while being published
+    ///
+    /// The published map is cloned into the other map, and all unpublished operations are
+    /// discarded. See [`OpWriter::clear_poison_with`](dbuf::op::OpWriter::clear_poison_with)
+    pub fn clear_poison(&mut self) {
+        if !self.inner.is_poisoned() {
+            return;
+        }
 
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+        self.inner
+            .clear_poison_with(|writer, reader| writer.clone_from(reader));
+        // the writer map is a copy of the published map now
+        self.materialize = None;
     }
 }
 
-impl<'a, K, V, S, Strat, T: ?Sized> CMapReadGuard<'a, K, V, S, Strat, T>
+impl<K, V, S, Strat, B, Ops> CMap<K, V, S, Strat, B, Ops>
 where
-    Strat: Strategy<ValidationError = Infallible>,
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    K: Hash + Eq + Split,
+    V: Split,
+    S: BuildHasher,
+    Strat: Strategy,
+    Ops: OpStorage,
 {
-    pub fn map<U: ?Sized>(self, f: impl FnOnce(&T) -> &U) -> CMapReadGuard<'a, K, V, S, Strat, U> {
-        CMapReadGuard {
-            inner: dbuf::raw::ReadGuard::map(self.inner, f),
-        }
+    pub fn insert(&mut self, key: K, value: V) {
+        self.inner.apply(MapOp::Insert(key, value));
     }
 
-    pub fn try_map<U: ?Sized>(
-        self,
-        f: impl FnOnce(&T) -> Option<&U>,
-    ) -> Result<CMapReadGuard<'a, K, V, S, Strat, U>, Self> {
-        match dbuf::raw::ReadGuard::try_map(self.inner, f) {
-            Ok(inner) => Ok(CMapReadGuard { inner }),
-            Err(inner) => Err(CMapReadGuard { inner }),
+    /// Insert all the items as a single operation
+    ///
+    /// If a key is duplicated, the last value wins
+    pub fn bulk_insert(&mut self, items: Vec<(K, V)>) {
+        self.inner.apply(MapOp::Extend(items));
+    }
+
+    pub fn remove(&mut self, key: K) {
+        self.inner.apply(MapOp::Remove(key));
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.apply(MapOp::Clear)
+    }
+
+    /// Change the value of `key` with `f`, if the key is in the map
+    ///
+    /// `f` only runs on the writer's map, and the other map gets a split of the changed value
+    /// once the changes are published. So with a [`SharedValue`](crate::cow::SharedValue),
+    /// both maps share the changed value, see [`cow`](crate::cow)
+    pub fn update(&mut self, key: K, f: impl FnMut(&mut V) + Send + 'static) {
+        self.inner
+            .apply(MapOp::Update(key, SyncWrapper::new(Box::new(f)), None))
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(bool, &K, &mut V) -> bool + Send + 'static) {
+        self.inner.apply(MapOp::Arbitrary(SyncWrapper::new(Box::new(
+            move |is_first, map| map.retain(|k, v| f(is_first, k, v)),
+        ))))
+    }
+
+    /// Shrink the capacity of both maps as much as possible, see [`HashMap::shrink_to_fit`]
+    ///
+    /// This is an operation, so the published map shrinks on the next publish,
+    /// and the other map on the publish after that. It's replicated as [`ReplicatedOp::Opaque`]
+    pub fn shrink_buffers_to_fit(&mut self) {
+        self.inner
+            .apply_to_both(|map: &mut HashMap<K, V, S>| map.shrink_to_fit())
+    }
+
+    /// Shrink the capacity of both maps down to `capacity`, see [`HashMap::shrink_to`]
+    ///
+    /// see [`shrink_buffers_to_fit`](Self::shrink_buffers_to_fit) for when each map shrinks
+    pub fn shrink_buffers_to(&mut self, capacity: usize) {
+        self.inner
+            .apply_to_both(move |map: &mut HashMap<K, V, S>| map.shrink_to(capacity))
+    }
+
+    /// Apply a batch of operations from [`replication_batch`](Self::replication_batch),
+    /// just like the same operations applied to this map
+    ///
+    /// If any operation is [`ReplicatedOp::Opaque`], then none of the batch is applied
+    pub fn apply_replicated(&mut self, ops: Vec<ReplicatedOp<K, V>>) -> Result<(), ReplayError> {
+        if ops.iter().any(|op| matches!(op, ReplicatedOp::Opaque)) {
+            return Err(ReplayError::OpaqueOp);
+        }
+
+        for op in ops {
+            match op {
+                ReplicatedOp::Insert(key, value) => self.insert(key, value),
+                ReplicatedOp::Extend(items) => self.bulk_insert(items),
+                ReplicatedOp::Remove(key) => self.remove(key),
+                ReplicatedOp::Clear => self.clear(),
+                ReplicatedOp::Opaque => unreachable!(),
+            }
         }
+
+        Ok(())
     }
-}
 
-impl<K, V, S, Strat, T: ?Sized + core::fmt::Debug> core::fmt::Debug
-    for CMapReadGuard<'_, K, V, S, Strat, T>
-where
-    Strat: Strategy<ValidationError = Infallible>,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        T::fmt(self, f)
+    /// The published map together with the operations which haven't been published yet
+    ///
+    /// This waits for readers to exit the writer map, see [`OpWriter::diff`](dbuf::op::OpWriter::diff)
+    pub fn pending_diff(&mut self) -> OpDiff<'_, HashMap<K, V, S>, MapOp<K, V, S>> {
+        self.materialize();
+        self.inner.diff()
     }
+
+    /// Rewrite the operations which haven't been published yet, for example to drop inserts
+    /// which are overwritten later, see [`OpWriter::rewrite_unapplied`](dbuf::op::OpWriter::rewrite_unapplied)
+    ///
+    /// The operations which were already published can't be changed. The rewritten operations
+    /// are applied to both maps like any others, so `f` only has to keep the meaning of the batch.
+    /// With [boxed operations](CMap::with_boxed_ops), `f` gets the boxes
+    ///
+    /// ```
+    /// use cmap::map::MapOp;
+    /// use std::collections::HashSet;
+    ///
+    /// let mut map = cmap::CMap::<u32, u32>::new();
+    /// for i in 0..10 {
+    ///     map.insert(i % 2, i);
+    /// }
+    ///
+    /// // only the last insert of each key matters
+    /// map.rewrite_pending(|ops| {
+    ///     let mut seen = HashSet::new();
+    ///     ops.reverse();
+    ///     ops.retain(|op| match op {
+    ///         MapOp::Insert(key, _) => seen.insert(*key),
+    ///         _ => true,
+    ///     });
+    ///     ops.reverse();
+    /// });
+    /// assert_eq!(map.unapplied().len(), 2);
+    ///
+    /// map.publish();
+    /// assert_eq!(map.get(&0), Some(&8));
+    /// assert_eq!(map.get(&1), Some(&9));
+    /// ```
+    pub fn rewrite_pending(&mut self, f: impl FnOnce(&mut Vec<Ops::Slot<MapOp<K, V, S>>>)) {
+        self.inner.rewrite_unapplied(f)
+    }
+
+    pub fn force_publish(&mut self)
+    where
+        Strat: Strategy<ValidationError = Infallible>,
+    {
+        match self.force_publish_checked() {
+            Ok(()) => (),
+            Err(inf) => match inf {},
+        }
+    }
+
+    pub fn publish(&mut self)
+    where
+        Strat: Strategy<ValidationError = Infallible>,
+    {
+        match self.publish_checked() {
+            Ok(_) => (),
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// Swap the maps, or return the strategy's error if it couldn't start the swap
+    ///
+    /// see [`publish_checked`](Self::publish_checked)
+    pub fn force_publish_checked(&mut self) -> Result<(), ValidationErrorOf<Strat>> {
+        self.materialize();
+        self.touch_watched_keys();
+        self.inner.swap_buffers_checked()?;
+        self.after_publish();
+        Ok(())
+    }
+
+    /// Publish the unpublished operations, or return the strategy's error if it couldn't start the swap
+    ///
+    /// Returns true if the maps were swapped. This is for strategies which can fail to swap, like
+    /// [`LocalStrategy`](dbuf::strategy::LocalStrategy) while a read guard is held, then the
+    /// operations are published by the next publish which succeeds, see
+    /// [`OpWriter::publish_checked`](dbuf::op::OpWriter::publish_checked)
+    pub fn publish_checked(&mut self) -> Result<bool, ValidationErrorOf<Strat>> {
+        self.materialize();
+        self.touch_watched_keys();
+        let published = self.inner.publish_checked()?;
+        self.after_publish();
+        Ok(published)
+    }
+
+    /// Copy the published map into the writer map, if it was left empty by [`new_lazy`](Self::new_lazy)
+    ///
+    /// This happens on the first publish anyway, so this only moves the cost of the copy.
+    /// The copy isn't an operation, so it isn't in [`unapplied`](Self::unapplied)
+    pub fn materialize(&mut self) {
+        if let Some(copy) = self.materialize.take() {
+            self.inner.resync_writer_buffer(copy);
+        }
+    }
+
+    /// true unless the writer map is still waiting to be copied from the published map,
+    /// see [`new_lazy`](Self::new_lazy)
+    pub fn is_materialized(&self) -> bool {
+        self.materialize.is_none()
+    }
+
+    /// Watch the key for changes
+    ///
+    /// Each publish which may change the key sends the key's new value to the watcher.
+    /// Operations which may change any key, like [`retain`](Self::retain) and [`clear`](Self::clear),
+    /// count as changing every watched key. Once the map is dropped, the watcher returns
+    /// [`WriterGone`](crate::watch::WriterGone)
+    pub fn watch_key(&mut self, key: K) -> KeyWatcher<V>
+    where
+        V: Clone,
+    {
+        self.watchers.watch(key)
+    }
+
+    /// mark the watched keys which the unpublished operations may change
+    fn touch_watched_keys(&mut self) {
+        if self.watchers.is_empty() {
+            return;
+        }
+
+        for op in self.inner.unapplied_iter() {
+            if !op.touched_keys(|key| self.watchers.touch(key)) {
+                self.watchers.touch_all();
+                break;
+            }
+        }
+    }
+
+    /// update everything which depends on the published map, this must run after every publish
+    fn after_publish(&mut self) {
+        self.published_len.set(self.inner.split().reader.len());
+        self.notify_watchers();
+    }
+
+    /// send the published values of the touched keys to their watchers
+    fn notify_watchers(&mut self) {
+        let published = self.inner.split().reader;
+        self.watchers.notify(|key| published.get(key));
+    }
+}
+
+impl<K, V, S, Strat, B, Ops> CMap<K, V, S, Strat, B, Ops>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    K: Hash + Eq + Split,
+    V: Split + PartialEq,
+    S: BuildHasher,
+    Strat: Strategy,
+    Ops: OpStorage,
+{
+    /// Check that the two maps are equal, to catch operations that aren't deterministic
+    ///
+    /// see [`OpWriter::verify_buffers_eq`](dbuf::op::OpWriter::verify_buffers_eq)
+    pub fn verify_consistent(&mut self) -> Consistency {
+        self.materialize();
+        self.inner.verify_buffers_eq()
+    }
+
+    /// Turn strict mode on or off, and check that the two maps are equal after each publish
+    ///
+    /// see [`OpWriter::set_strict_mode_checked`](dbuf::op::OpWriter::set_strict_mode_checked)
+    pub fn set_strict_mode_checked(&mut self, on: bool) {
+        self.inner.set_strict_mode_checked(on)
+    }
+}
+
+impl<K, V, S, Strat, B> Clone for CMapReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            published_len: self.published_len.clone(),
+        }
+    }
+}
+
+impl<K, V, S, Strat, B> CMapReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    /// a reader for another writer of the same maps, like [`CMapDiffSync`](crate::CMapDiffSync)
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn from_parts(
+        inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
+        published_len: PublishedCount,
+    ) -> Self {
+        Self {
+            inner,
+            published_len,
+        }
+    }
+
+    /// Check if both readers read from the same map
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    /// The number of entries in the published map, without taking a read guard
+    ///
+    /// This is exact as of the last publish, see [`CMap::published_len`]. So a guard taken
+    /// afterwards may see a later publish, but never an earlier one
+    pub fn published_len(&self) -> usize {
+        self.published_len.get()
+    }
+
+    /// Cache a value derived from the published map, which is only recomputed after a publish
+    ///
+    /// The cache reads through a clone of this reader. see [`CachedProjection`](dbuf::cached::CachedProjection)
+    #[allow(clippy::type_complexity)]
+    pub fn cached<T, F: FnMut(&HashMap<K, V, S>) -> T>(
+        &self,
+        project: F,
+    ) -> CachedProjection<dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T, F> {
+        CachedProjection::new(self.inner.clone(), project)
+    }
+
+    /// Convert into a reader which reads through `&self`, see [`CMap::shared_reader`]
+    pub fn into_shared(self) -> CMapSharedReader<K, V, S, Strat, B>
+    where
+        Strat: dbuf::interface::CheapReaderTag,
+    {
+        CMapSharedReader {
+            inner: self.inner.into_shared(),
+            published_len: self.published_len,
+        }
+    }
+
+    pub fn load(&mut self) -> CMapReadGuard<K, V, S, Strat, HashMap<K, V, S>, B> {
+        CMapReadGuard {
+            inner: self.inner.get(),
+        }
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<K, V, S, Strat, V, B>>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        self.load().try_map(|map| map.get(key)).ok()
+    }
+
+    /// Get the value of `key`, dereferenced, so a `CMap<K, SharedValue<V>>` gives a guard to `V`
+    ///
+    /// see [`cow`](crate::cow)
+    pub fn get_deref<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<K, V, S, Strat, V::Target, B>>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+        V: Deref,
+    {
+        self.get(key).map(|value| value.map(|value| &**value))
+    }
+
+    /// Check if the map was published yet
+    ///
+    /// This is always true, unless the map was created with [`CMap::with_initial_gate`]
+    pub fn is_initialized(&self) -> bool {
+        self.published_len.is_initialized()
+    }
+
+    /// Load the map, or return [`ReadError::NotYetPublished`] if it wasn't published yet
+    ///
+    /// see [`CMap::with_initial_gate`]
+    #[allow(clippy::type_complexity)]
+    pub fn try_load_initialized(
+        &mut self,
+    ) -> Result<CMapReadGuard<'_, K, V, S, Strat, HashMap<K, V, S>, B>, ReadError> {
+        if self.is_initialized() {
+            Ok(self.load())
+        } else {
+            Err(ReadError::NotYetPublished)
+        }
+    }
+
+    /// Get the value of `key`, or return [`ReadError::NotYetPublished`] if the map wasn't published yet
+    ///
+    /// see [`CMap::with_initial_gate`]
+    #[allow(clippy::type_complexity)]
+    pub fn try_get_initialized<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Result<Option<CMapReadGuard<'_, K, V, S, Strat, V, B>>, ReadError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        Ok(self
+            .try_load_initialized()?
+            .try_map(|map| map.get(key))
+            .ok())
+    }
+
+    /// Block until the map is published for the first time, or until `timeout` passes
+    ///
+    /// This returns right away if the map was already published, or wasn't created with
+    /// [`CMap::with_initial_gate`]. If the map is dropped before it's published, this waits
+    /// for the whole `timeout`
+    pub fn wait_first_publish(&self, timeout: Duration) -> Result<(), NotYetPublished> {
+        self.published_len.wait_initialized(timeout)
+    }
+
+    /// Get the value of `key` if `filter` accepts it, or the reason there isn't one
+    pub fn get_or_reason<Q>(
+        &mut self,
+        key: &Q,
+        filter: impl FnOnce(&V) -> bool,
+    ) -> Result<CMapReadGuard<K, V, S, Strat, V, B>, LookupError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        self.load()
+            .try_map_or_else(|map| map.get(key).ok_or(LookupError::MissingKey))
+            .map_err(|(_, err)| err)?
+            .filter(filter)
+            .map_err(|_| LookupError::Filtered)
+    }
+
+    /// Load the map, or give up if acquiring the read guard would need to pause more than
+    /// `max_pauses` times
+    ///
+    /// None of the strategies in `dbuf` block readers, so this only fails with [`ReadError::Busy`] for custom strategies.
+    /// see [`Reader::try_get_bounded`](dbuf::raw::Reader::try_get_bounded)
+    #[allow(clippy::type_complexity)]
+    pub fn load_bounded(
+        &mut self,
+        max_pauses: usize,
+    ) -> Result<CMapReadGuard<K, V, S, Strat, HashMap<K, V, S>, B>, ReadError> {
+        Ok(CMapReadGuard {
+            inner: self.inner.try_get_bounded(max_pauses)?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn get_bounded<Q>(
+        &mut self,
+        key: &Q,
+        max_pauses: usize,
+    ) -> Result<Option<CMapReadGuard<K, V, S, Strat, V, B>>, ReadError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        Ok(self
+            .load_bounded(max_pauses)?
+            .try_map(|map| map.get(key))
+            .ok())
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, B> Deref for CMapReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, S, Strat, T: ?Sized, B> CMapReadGuard<'a, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CMapReadGuard<'a, K, V, S, Strat, U, B> {
+        CMapReadGuard {
+            inner: dbuf::raw::ReadGuard::map(self.inner, f),
+        }
+    }
+
+    pub fn map_with_parent<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CMapZoomGuard<'a, K, V, S, Strat, T, U, B> {
+        CMapZoomGuard {
+            inner: self.inner.map_with_parent(f),
+        }
+    }
+
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<CMapReadGuard<'a, K, V, S, Strat, U, B>, Self> {
+        match dbuf::raw::ReadGuard::try_map(self.inner, f) {
+            Ok(inner) => Ok(CMapReadGuard { inner }),
+            Err(inner) => Err(CMapReadGuard { inner }),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CMapReadGuard<'a, K, V, S, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CMapReadGuard { inner }),
+            Err((inner, err)) => Err((CMapReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CMapReadGuard { inner }),
+            Err(inner) => Err(CMapReadGuard { inner }),
+        }
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, U: ?Sized, B> Deref for CMapZoomGuard<'_, K, V, S, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, S, Strat, T: ?Sized, U: ?Sized, B> CMapZoomGuard<'a, K, V, S, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn zoomed(&self) -> &U {
+        self.inner.zoomed()
+    }
+
+    pub fn parent(&self) -> &T {
+        self.inner.parent()
+    }
+
+    pub fn rezoom<W: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &W,
+    ) -> CMapZoomGuard<'a, K, V, S, Strat, T, W, B> {
+        CMapZoomGuard {
+            inner: self.inner.rezoom(f),
+        }
+    }
+
+    pub fn into_parent(self) -> CMapReadGuard<'a, K, V, S, Strat, T, B> {
+        CMapReadGuard {
+            inner: self.inner.into_parent(),
+        }
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, U: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CMapZoomGuard<'_, K, V, S, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        U::fmt(self, f)
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CMapReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<K, V, S, Strat, B> Clone for CMapWeakReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V, S, Strat, B> CMapWeakReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    /// a weak reader for another writer of the same maps, like [`CMapDiffSync`](crate::CMapDiffSync)
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn from_parts(
+        inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedWeak<Strat, B>>,
+    ) -> Self {
+        Self { inner }
+    }
+
+    /// Create another weak reader to the same map
+    ///
+    /// Unlike `clone`, this returns [`ReadError::WriterGone`] right away if the map was already dropped
+    pub fn try_fork(&self) -> Result<Self, ReadError> {
+        Ok(Self {
+            inner: self.inner.try_fork()?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn load(
+        &mut self,
+    ) -> Result<CMapWeakReadGuard<K, V, S, Strat, HashMap<K, V, S>, B>, ReadError> {
+        Ok(CMapWeakReadGuard {
+            inner: self.inner.checked_get()?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn get<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Result<Option<CMapWeakReadGuard<K, V, S, Strat, V, B>>, ReadError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        Ok(self.load()?.try_map(|map| map.get(key)).ok())
+    }
+
+    /// Create a reader which keeps a clone of the last map it read, and serves it after the
+    /// map is dropped, see [`dbuf::resilient`]
+    ///
+    /// The map is only cloned after a publish, see [`CMapResilientReader::set_refresh_if_epoch_changed`].
+    /// This fails if the map was already dropped
+    pub fn into_resilient(self) -> Result<CMapResilientReader<K, V, S, Strat, B>, ReadError>
+    where
+        K: Clone,
+        V: Clone,
+        S: Clone,
+    {
+        let mut inner = dbuf::resilient::ResilientReader::new(self.inner)?;
+        inner.set_refresh_if_epoch_changed(true);
+        Ok(CMapResilientReader {
+            inner,
+            is_stale: false,
+        })
+    }
+}
+
+impl<K, V, S, Strat, B> CMapResilientReader<K, V, S, Strat, B>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone,
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    /// clone the map on every read, instead of only after a publish
+    ///
+    /// see [`ResilientReader::set_refresh_if_epoch_changed`](dbuf::resilient::ResilientReader::set_refresh_if_epoch_changed)
+    pub fn set_refresh_if_epoch_changed(&mut self, on: bool) {
+        self.inner.set_refresh_if_epoch_changed(on);
+    }
+
+    /// Load the map, or the last map which was read if it was dropped
+    pub fn load(&mut self) -> dbuf::resilient::Snapshot<'_, HashMap<K, V, S>> {
+        let snapshot = self.inner.get_or_last();
+        self.is_stale = snapshot.is_stale();
+        snapshot
+    }
+
+    /// Get the value of `key`, from the last map which was read if it was dropped
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        self.is_stale = self.inner.get_or_last().is_stale();
+        self.inner.last().get(key)
+    }
+
+    /// whether the last [`load`](Self::load) or [`get`](Self::get) fell back to the last map,
+    /// because the map was dropped
+    pub fn is_stale(&self) -> bool {
+        self.is_stale
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, B> Deref for CMapWeakReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, S, Strat, T: ?Sized, B> CMapWeakReadGuard<'a, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CMapWeakReadGuard<'a, K, V, S, Strat, U, B> {
+        CMapWeakReadGuard {
+            inner: dbuf::raw::ReadGuard::map(self.inner, f),
+        }
+    }
+
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<CMapWeakReadGuard<'a, K, V, S, Strat, U, B>, Self> {
+        match dbuf::raw::ReadGuard::try_map(self.inner, f) {
+            Ok(inner) => Ok(CMapWeakReadGuard { inner }),
+            Err(inner) => Err(CMapWeakReadGuard { inner }),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CMapWeakReadGuard<'a, K, V, S, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CMapWeakReadGuard { inner }),
+            Err((inner, err)) => Err((CMapWeakReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CMapWeakReadGuard { inner }),
+            Err(inner) => Err(CMapWeakReadGuard { inner }),
+        }
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CMapWeakReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<K, V, S, Strat, B> Clone for CMapSharedReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            published_len: self.published_len.clone(),
+        }
+    }
+}
+
+impl<K, V, S, Strat, B> CMapSharedReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy + dbuf::interface::CheapReaderTag,
+{
+    /// Check if both readers read from the same map
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    /// The number of entries in the published map, see [`CMapReader::published_len`]
+    pub fn published_len(&self) -> usize {
+        self.published_len.get()
+    }
+
+    /// Convert back into a reader which needs `&mut self` to read
+    pub fn into_reader(self) -> CMapReader<K, V, S, Strat, B> {
+        CMapReader {
+            inner: self.inner.into_reader(),
+            published_len: self.published_len,
+        }
+    }
+
+    pub fn load(&self) -> CMapOwnedReadGuard<K, V, S, Strat, HashMap<K, V, S>, B> {
+        CMapOwnedReadGuard {
+            inner: self.inner.get(),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<CMapOwnedReadGuard<K, V, S, Strat, V, B>>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        self.load().try_map(|map| map.get(key)).ok()
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, B> Deref for CMapOwnedReadGuard<K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, B> CMapOwnedReadGuard<K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CMapOwnedReadGuard<K, V, S, Strat, U, B> {
+        CMapOwnedReadGuard {
+            inner: self.inner.map(f),
+        }
+    }
+
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<CMapOwnedReadGuard<K, V, S, Strat, U, B>, Self> {
+        match self.inner.try_map(f) {
+            Ok(inner) => Ok(CMapOwnedReadGuard { inner }),
+            Err(inner) => Err(CMapOwnedReadGuard { inner }),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CMapOwnedReadGuard<K, V, S, Strat, U, B>, (Self, E)> {
+        match self.inner.try_map_or_else(f) {
+            Ok(inner) => Ok(CMapOwnedReadGuard { inner }),
+            Err((inner, err)) => Err((CMapOwnedReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match self.inner.filter(pred) {
+            Ok(inner) => Ok(CMapOwnedReadGuard { inner }),
+            Err(inner) => Err(CMapOwnedReadGuard { inner }),
+        }
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CMapOwnedReadGuard<K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, V, S>>,
+    Strat: Strategy,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+#[test]
+fn weak_reader_doesnt_keep_map_alive() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Counted {
+        fn new(live: &Arc<AtomicUsize>) -> Self {
+            live.fetch_add(1, Ordering::Relaxed);
+            Self(live.clone())
+        }
+    }
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            Self::new(&self.0)
+        }
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    let live = Arc::new(AtomicUsize::new(0));
+    let mut map = CMap::new();
+    let mut reader = map.weak_reader();
+
+    for i in 0..3 {
+        map.insert(i, Counted::new(&live));
+    }
+    map.publish();
+    map.insert(3, Counted::new(&live));
+    map.publish();
+
+    assert!(reader.get(&3).unwrap().is_some());
+    assert_eq!(reader.load().unwrap().len(), 4);
+
+    let mut cloned = reader.clone();
+    drop(map);
+
+    assert_eq!(live.load(Ordering::Relaxed), 0);
+    assert!(matches!(reader.load(), Err(ReadError::WriterGone)));
+    assert!(matches!(cloned.get(&0), Err(ReadError::WriterGone)));
+    assert!(matches!(cloned.clone().get(&0), Err(ReadError::WriterGone)));
+}
+
+#[test]
+fn cached_projection_recomputes_after_publish() {
+    let mut map = CMap::new();
+    map.insert("a", 1);
+    map.insert("b", 2);
+    map.publish();
+
+    let reader = map.reader();
+    let mut total = reader.cached(|map| map.values().sum::<i32>());
+    assert_eq!(*total.get(), 3);
+
+    map.insert("c", 3);
+    assert_eq!(*total.get(), 3);
+    map.publish();
+    assert_eq!(*total.get(), 6);
+}
+
+#[test]
+fn zoom_guard_keeps_parent() {
+    let mut map = CMap::new();
+    let mut reader = map.reader();
+
+    map.insert(0, "zero");
+    map.insert(1, "one");
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    let zoom = reader.load().map_with_parent(|map| &map[&0]);
+    let parent: *const HashMap<_, _, _> = zoom.parent();
+    assert_eq!(*zoom, "zero");
+
+    let zoom = zoom.rezoom(|map| &map[&1]);
+    assert!(std::ptr::eq(parent, zoom.parent()));
+    assert_eq!(*zoom.zoomed(), "one");
+
+    let guard = zoom.into_parent();
+    assert!(std::ptr::eq(parent, &*guard));
+    assert_eq!(guard.len(), 2);
+}
+
+#[test]
+fn clear_poison_after_panicking_op() {
+    let mut map = CMap::new();
+    let mut reader = map.reader();
+
+    map.insert(0, 0);
+    map.insert(1, 1);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    map.insert(2, 2);
+    map.retain(|_, _, _| panic!("panicking op"));
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| map.publish()));
+    assert!(result.is_err());
+    assert!(map.is_poisoned());
+    assert_eq!(reader.load().len(), 2);
+
+    map.clear_poison();
+    assert!(!map.is_poisoned());
+
+    map.insert(3, 3);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.get(&3).as_deref(), Some(&3));
+    assert_eq!(reader.get(&2).as_deref(), None);
+
+    for _ in 0..2 {
+        map.force_publish();
+        assert_eq!(map.verify_consistent(), Consistency::Consistent);
+        assert_eq!(reader.load().len(), 3);
+    }
+}
+
+#[test]
+fn verify_consistent_catches_divergence() {
+    let mut map = CMap::new();
+
+    map.insert(0, 0);
+    map.insert(1, 1);
+    assert_eq!(map.verify_consistent(), Consistency::PendingOps);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    map.retain(|is_first, _, _| is_first);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Diverged);
+}
+
+#[test]
+fn pending_diff_shows_unpublished_ops() {
+    let mut map = CMap::new();
+
+    map.insert(0, 0);
+    map.publish();
+    map.insert(1, 1);
+    map.remove(0);
+
+    let diff = map.pending_diff();
+    assert_eq!(diff.published().len(), 1);
+    assert_eq!(diff.write_buffer(), diff.published());
+    assert!(matches!(
+        diff.unapplied_ops(),
+        [MapOp::Insert(1, 1), MapOp::Remove(0)]
+    ));
+
+    map.publish();
+    assert!(map.pending_diff().unapplied_ops().is_empty());
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn into_sync_keeps_maps_and_ops() {
+    use dbuf::strategy::LocalTrackingStrategy;
+
+    let mut map = CMap::<_, _, DefaultHasher, LocalTrackingStrategy>::default();
+    let reader = map.reader();
+    let mut weak_reader = map.weak_reader();
+
+    map.insert(0, "zero");
+    map.publish();
+    map.insert(1, "one");
+
+    // readers use the old strategy, so they block the move
+    let map = map.try_into_sync().err().unwrap();
+    drop(reader);
+
+    let mut map = map.try_into_sync().ok().unwrap();
+    assert!(weak_reader.load().is_err());
+
+    let mut reader = std::thread::spawn({
+        let reader = map.reader();
+        move || {
+            let mut reader = reader;
+            assert_eq!(reader.get(&0).as_deref(), Some(&"zero"));
+            assert_eq!(reader.get(&1).as_deref(), None);
+            reader
+        }
+    })
+    .join()
+    .unwrap();
+
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.get(&1).as_deref(), Some(&"one"));
+}
+
+#[test]
+fn buffer_id_alternates_across_publishes() {
+    let mut map = CMap::new();
+    let mut reader = map.reader();
+
+    map.insert(0, 0);
+    map.publish();
+    let first = reader.load().buffer_id();
+
+    map.insert(1, 1);
+    map.publish();
+    let guard = reader.get(&1).unwrap();
+    assert!(!guard.same_buffer(first));
+    let second = guard.buffer_id();
+    drop(guard);
+
+    map.insert(2, 2);
+    map.publish();
+    assert_eq!(reader.load().buffer_id(), first);
+    map.insert(3, 3);
+    map.publish();
+    assert_eq!(reader.load().buffer_id(), second);
+}
+
+#[test]
+fn debug_blocking_readers_reports_held_guards() {
+    let map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    assert!(map.debug_blocking_readers().is_empty());
+
+    let guard = reader.load();
+    assert_eq!(map.debug_blocking_readers().len(), 1);
+    drop(guard);
+    assert!(map.debug_blocking_readers().is_empty());
+}
+
+#[test]
+fn reads_dont_need_split() {
+    struct NotClone(i32);
+
+    let map = CMap::<i32, NotClone>::from_maps(
+        [(1, NotClone(10))].into_iter().collect(),
+        [(1, NotClone(10))].into_iter().collect(),
+    );
+    let mut reader = map.reader();
+
+    assert_eq!(map.get(&1).map(|v| v.0), Some(10));
+    assert!(map.get(&2).is_none());
+    assert_eq!(map.load().len(), 1);
+    assert!(map.unapplied().is_empty());
+    assert_eq!(reader.get(&1).map(|v| v.0), Some(10));
+}
+
+#[test]
+fn wait_readers_caught_up_retires_old_values() {
+    use dbuf::strategy::mock::{MockEvent, MockStrategy};
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    struct Resource(Arc<AtomicBool>);
+
+    impl Drop for Resource {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed)
+        }
+    }
+
+    let retired = Arc::new(AtomicBool::new(false));
+    let mock = MockStrategy::new();
+    let mut map: CMap<i32, Arc<Resource>, DefaultHasher, MockStrategy> =
+        CMap::from_raw_parts(HashMap::default(), HashMap::default(), mock.clone());
+    map.insert(0, Arc::new(Resource(retired.clone())));
+    map.publish();
+
+    let mut reader = map.reader();
+    let guard = reader.get(&0).unwrap();
+    map.remove(0);
+    map.publish();
+    assert!(!guard.0.load(Ordering::Relaxed));
+    drop(guard);
+
+    // a reader which is slow to leave holds up the wait for a couple of pauses
+    mock.hold_next_capture_for(2);
+    mock.take_events();
+    map.wait_readers_caught_up();
+    let pauses = mock
+        .take_events()
+        .into_iter()
+        .filter(|e| *e == MockEvent::Pause);
+    assert_eq!(pauses.count(), 2);
+
+    // no reader can reference the old value anymore, so it can be dropped from both maps
+    map.force_publish();
+    map.force_publish();
+    assert!(retired.load(Ordering::Relaxed));
+}
+
+#[test]
+fn from_maps_checked_rejects_different_maps() {
+    let front = HashMap::from_iter([(1, 10)]);
+    let back = HashMap::from_iter([(1, 20)]);
+
+    // without the check, the maps are never reconciled,
+    // so readers flip between the two maps on each publish
+    let mut map = CMap::<i32, i32>::from_maps(front.clone(), back.clone());
+    let mut reader = map.reader();
+    map.insert(2, 2);
+    map.publish();
+    let first = *reader.get(&1).unwrap();
+    map.insert(3, 3);
+    map.publish();
+    let second = *reader.get(&1).unwrap();
+    assert_ne!(first, second);
+    assert_eq!(reader.load().len(), 3);
+
+    assert!(CMap::<i32, i32>::from_maps_checked(front.clone(), back).is_err());
+
+    let map = CMap::<i32, i32>::from_maps_checked(front.clone(), front).unwrap();
+    assert_eq!(map.reader().get(&1).as_deref(), Some(&10));
+}
+
+#[test]
+fn get_bounded_never_fails_with_non_blocking_strategies() {
+    let mut map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    map.insert(1, 10);
+    map.publish();
+
+    assert_eq!(reader.get_bounded(&1, 0).unwrap().as_deref(), Some(&10));
+    assert!(reader.get_bounded(&2, 0).unwrap().is_none());
+    assert_eq!(reader.load_bounded(0).unwrap().len(), 1);
+}
+
+#[test]
+fn padded_layout() {
+    let mut map = CMap::<
+        i32,
+        i32,
+        DefaultHasher,
+        DefaultStrat,
+        dbuf::raw::PaddedRawDBuf<HashMap<i32, i32>>,
+    >::default();
+    let mut reader = map.reader();
+    map.insert(1, 10);
+    map.publish();
+
+    // the two maps are on different cache lines
+    let split = map.inner.split();
+    let distance = (split.reader as *const HashMap<_, _> as usize)
+        .abs_diff(split.writer as *const HashMap<_, _> as usize);
+    assert!(distance >= 128);
+
+    assert_eq!(reader.get(&1).as_deref(), Some(&10));
+    map.insert(2, 20);
+    map.publish();
+    assert_eq!(reader.load().len(), 2);
+}
+
+#[test]
+fn readers_ptr_eq() {
+    let map = CMap::<i32, i32>::default();
+    let other = CMap::<i32, i32>::default();
+
+    let reader = map.reader();
+    assert!(reader.ptr_eq(&reader.clone()));
+    assert!(reader.ptr_eq(&map.reader()));
+    assert!(!reader.ptr_eq(&other.reader()));
+
+    // the readers keep the maps alive, so they stay equal after the map is dropped
+    let clone = reader.clone();
+    drop(map);
+    assert!(reader.ptr_eq(&clone));
+}
+
+#[test]
+fn weak_reader_try_fork() {
+    let mut map = CMap::new();
+    let reader = map.reader();
+    let weak = map.weak_reader();
+
+    map.insert(1, 10);
+    map.publish();
+    let mut fork = weak.try_fork().unwrap();
+    assert_eq!(fork.get(&1).unwrap().as_deref(), Some(&10));
+
+    // the strong reader keeps the map alive
+    drop(map);
+    let mut fork = weak.try_fork().unwrap();
+    assert_eq!(fork.load().unwrap().len(), 1);
+    drop(fork);
+
+    drop(reader);
+    assert!(matches!(weak.try_fork(), Err(ReadError::WriterGone)));
+}
+
+#[test]
+fn watch_key() {
+    use crate::{KeyChange, WriterGone};
+
+    let mut map = CMap::new();
+    let mut watcher = map.watch_key(1);
+    let mut other = map.watch_key(2);
+
+    map.insert(1, 10);
+    map.insert(3, 30);
+    assert_eq!(watcher.poll(), Ok(None));
+    map.publish();
+    assert_eq!(watcher.poll(), Ok(Some(KeyChange::Changed(10))));
+    assert_eq!(watcher.poll(), Ok(None));
+    assert_eq!(other.poll(), Ok(None));
+
+    // only the latest change is kept
+    map.insert(1, 11);
+    map.publish();
+    map.insert(1, 12);
+    map.publish();
+    assert_eq!(watcher.wait(), Ok(KeyChange::Changed(12)));
+
+    map.remove(1);
+    map.publish();
+    assert_eq!(watcher.poll(), Ok(Some(KeyChange::Removed)));
+
+    // clear may change any key
+    map.clear();
+    map.publish();
+    assert_eq!(other.poll(), Ok(Some(KeyChange::Removed)));
+
+    let thread = std::thread::spawn(move || watcher.wait());
+    map.insert(1, 13);
+    map.publish();
+    assert_eq!(thread.join().unwrap(), Ok(KeyChange::Changed(13)));
+
+    drop(map);
+    assert_eq!(other.poll(), Err(WriterGone));
+    assert_eq!(other.wait(), Err(WriterGone));
+}
+
+#[test]
+fn replicate_into_mirror() {
+    let mut source = CMap::new();
+    let mut mirror = CMap::new();
+
+    fn publish(source: &mut CMap<u32, String>, mirror: &mut CMap<u32, String>) {
+        let batch = source.replication_batch();
+        source.publish();
+        mirror.apply_replicated(batch).unwrap();
+        mirror.publish();
+        assert_eq!(source.load(), mirror.load());
+    }
+
+    source.insert(1, "one".to_string());
+    source.insert(2, "two".to_string());
+    publish(&mut source, &mut mirror);
+
+    source.bulk_insert(vec![(3, "three".to_string()), (1, "uno".to_string())]);
+    source.remove(2);
+    publish(&mut source, &mut mirror);
+
+    source.clear();
+    source.insert(4, "four".to_string());
+    publish(&mut source, &mut mirror);
+
+    // retain can't be replicated, so the mirror rejects the whole batch
+    source.insert(5, "five".to_string());
+    source.retain(|_, key, _| *key != 4);
+    let batch = source.replication_batch();
+    assert_eq!(batch[0], ReplicatedOp::Insert(5, "five".to_string()));
+    assert_eq!(batch[1], ReplicatedOp::Opaque);
+    assert_eq!(mirror.apply_replicated(batch), Err(ReplayError::OpaqueOp));
+    assert!(mirror.unapplied().is_empty());
+
+    // so replicated maps remove keys explicitly instead
+    source.publish();
+    mirror.insert(5, "five".to_string());
+    mirror.remove(4);
+    mirror.publish();
+    assert_eq!(source.load(), mirror.load());
+
+    source.remove(5);
+    publish(&mut source, &mut mirror);
+    assert!(mirror.load().is_empty());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn replicated_ops_serde_round_trip() {
+    let mut source = CMap::new();
+    let mut mirror = CMap::new();
+
+    source.insert(1, "one".to_string());
+    source.bulk_insert(vec![(2, "two".to_string()), (3, "three".to_string())]);
+    source.remove(1);
+    let batch = source.replication_batch();
+    source.publish();
+
+    // send the batch over the wire
+    let json = serde_json::to_string(&batch).unwrap();
+    let received: Vec<ReplicatedOp<u32, String>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(received, batch);
+
+    mirror.apply_replicated(received).unwrap();
+    mirror.publish();
+    assert_eq!(source.load(), mirror.load());
+
+    source.clear();
+    let batch = source.replication_batch();
+    let json = serde_json::to_string(&batch).unwrap();
+    assert_eq!(json, r#"["Clear"]"#);
+    assert_eq!(
+        serde_json::from_str::<Vec<ReplicatedOp<u32, String>>>(&json).unwrap(),
+        batch
+    );
+}
+
+#[test]
+fn shared_reader_join() {
+    use std::{
+        future::Future,
+        pin::{pin, Pin},
+        task::{Context, Poll, Waker},
+    };
+
+    /// a future which is pending once, so that both futures below hold their guards at the same time
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<()> {
+            if std::mem::replace(&mut self.0, true) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    fn assert_send<T: Send>(value: T) -> T {
+        value
+    }
+
+    let mut map = CMap::<u32, u32, DefaultHasher, dbuf::strategy::HazardStrategy>::from_raw_parts(
+        HashMap::default(),
+        HashMap::default(),
+        dbuf::strategy::HazardStrategy::new(),
+    );
+    map.insert(1, 10);
+    map.insert(2, 20);
+    map.publish();
+
+    let reader = map.shared_reader();
+    let get = |key| {
+        let reader = &reader;
+        async move {
+            let value = reader.get(&key).unwrap();
+            YieldOnce(false).await;
+            *value
+        }
+    };
+
+    // poll both futures in turns, like `join!` does
+    let mut a = pin!(assert_send(get(1)));
+    let mut b = pin!(assert_send(get(2)));
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(a.as_mut().poll(&mut cx).is_pending());
+    assert!(b.as_mut().poll(&mut cx).is_pending());
+
+    // both guards are held across the `.await`, so the map can't be published
+    map.insert(3, 30);
+    assert!(!map.debug_blocking_readers().is_empty());
+
+    assert_eq!(a.poll(&mut cx), Poll::Ready(10));
+    assert_eq!(b.poll(&mut cx), Poll::Ready(20));
+    assert!(map.debug_blocking_readers().is_empty());
+
+    map.publish();
+    assert_eq!(reader.get(&3).as_deref(), Some(&30));
+}
+
+#[test]
+fn rehash_with_keeps_contents() {
+    use std::hash::BuildHasherDefault;
+
+    type Fixed = BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+    let mut map = CMap::<u32, String>::new();
+    for i in 0..100 {
+        map.insert(i, i.to_string());
+    }
+    map.publish();
+    map.remove(0);
+    map.retain(|_, &k, _| k != 1);
+    map.insert(100, "100".to_string());
+
+    let mut old_reader = map.reader();
+    let mut watcher = map.watch_key(100);
+    let mut map = map.rehash_with(Fixed::default());
+    assert_eq!(map.hasher().hash_one(7), Fixed::default().hash_one(7));
+
+    // the unpublished operations were published in the new map
+    let mut reader = map.reader();
+    assert_eq!(reader.load().len(), 99);
+    assert!(map.unapplied().is_empty());
+    assert!(reader.get(&0).is_none() && reader.get(&1).is_none());
+    assert_eq!(reader.get(&100).as_deref().map(String::as_str), Some("100"));
+    assert_eq!(
+        watcher.poll(),
+        Ok(Some(crate::KeyChange::Changed("100".to_string())))
+    );
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    // the old reader still sees the map from before the rehash
+    assert_eq!(old_reader.load().len(), 100);
+    assert_eq!(old_reader.get(&0).as_deref().map(String::as_str), Some("0"));
+
+    // later publishes behave just like before
+    map.insert(5, "five".to_string());
+    map.remove(6);
+    map.publish();
+    assert_eq!(reader.get(&5).as_deref().map(String::as_str), Some("five"));
+    assert!(reader.get(&6).is_none());
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(old_reader.load().len(), 100);
+}
+
+#[test]
+#[cfg(feature = "flight-recorder")]
+fn rehash_with_keeps_the_strategy() {
+    use dbuf::{
+        flight_recorder::{FlightRecorder, RecordedStrategy},
+        strategy::HazardStrategy,
+    };
+    use std::{hash::BuildHasherDefault, sync::Arc};
+
+    type Fixed = BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+    let recorder = Arc::new(FlightRecorder::new(64));
+    let mut map = CMap::<u32, u32, DefaultHasher, RecordedStrategy<HazardStrategy>>::from_raw_parts(
+        HashMap::default(),
+        HashMap::default(),
+        RecordedStrategy::new(HazardStrategy::new(), recorder.clone()),
+    );
+    map.insert(1, 1);
+    map.publish();
+
+    // the new map records into the same recorder
+    let mut map = map.rehash_with(Fixed::default());
+    let recorded = recorder.recorded();
+    map.insert(2, 2);
+    map.publish();
+    assert!(recorder.recorded() > recorded);
+    assert_eq!(map.reader().load().len(), 2);
+}
+
+#[test]
+fn rotate_dyn_hasher() {
+    use crate::{DynCMap, DynHasher};
+    use std::collections::hash_map::RandomState;
+
+    let mut map = DynCMap::<u32, u32>::default();
+    map.bulk_insert((0..50).map(|i| (i, i * 2)).collect());
+    map.publish();
+
+    for _ in 0..3 {
+        // the same type, so a long-lived map can keep rotating its keys
+        let keys = RandomState::new();
+        let expected = keys.hash_one(3_u32);
+        map = map.rehash_with(DynHasher::new(keys));
+        assert_eq!(map.hasher().hash_one(3_u32), expected);
+
+        let mut reader = map.reader();
+        assert_eq!(reader.load().len(), 50);
+        assert!((0..50).all(|i| reader.get(&i).as_deref() == Some(&(i * 2))));
+
+        map.insert(50, 100);
+        map.publish();
+        assert_eq!(reader.get(&50).as_deref(), Some(&100));
+        map.remove(50);
+        map.publish();
+    }
+}
+
+#[test]
+fn recent_publishes() {
+    let mut map = CMap::<u32, u32>::new();
+    map.insert(0, 0);
+    map.publish();
+    assert_eq!(map.recent_publishes().count(), 0);
+
+    map.set_history_capacity(2);
+    for i in 1..4 {
+        map.bulk_insert((0..i).map(|j| (j, i)).collect());
+        map.publish();
+    }
+
+    let records = map.recent_publishes().collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+    assert_eq!((records[0].epoch, records[0].ops_applied), (3, 1));
+    assert_eq!((records[1].epoch, records[1].ops_applied), (4, 1));
+}
+
+#[test]
+fn with_profile() {
+    use crate::{CBTreeMap, CBTreeMultiMap, CMultiMap, ShardedCMap};
+    use dbuf::profiles;
+
+    fn check<P: Strategy<ValidationError = Infallible> + Default>() {
+        let mut map = CMap::<u32, u32>::with_profile::<P>();
+        map.insert(1, 10);
+        map.publish();
+        assert_eq!(map.reader().get(&1).as_deref(), Some(&10));
+
+        let mut map = CBTreeMap::<u32, u32>::with_profile::<P>();
+        map.insert(1, 10);
+        map.publish();
+
+        let mut map = CMultiMap::<u32, u32>::with_profile::<P>();
+        map.insert(1, 10);
+        map.publish();
+
+        let mut map = CBTreeMultiMap::<u32, u32>::with_profile::<P>();
+        map.insert(1, 10);
+        map.publish();
+
+        let map = ShardedCMap::<u32, u32>::with_profile::<P>();
+        map.insert(1, 10);
+        map.publish();
+    }
+
+    check::<profiles::ReadMostly>();
+    check::<profiles::LowLatencyWriter>();
+    check::<profiles::SingleThread>();
+    check::<profiles::Precise>();
+    check::<profiles::Balanced>();
+}
+
+#[test]
+fn get_or_reason() {
+    let mut map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    map.insert(1, 10);
+    map.insert(2, 21);
+    map.publish();
+
+    assert_eq!(reader.get_or_reason(&1, |_| true).as_deref(), Ok(&10));
+    assert_eq!(
+        reader.get_or_reason(&3, |_| true).err(),
+        Some(LookupError::MissingKey)
+    );
+    assert_eq!(
+        reader.get_or_reason(&2, |v| v % 2 == 0).err(),
+        Some(LookupError::Filtered)
+    );
+}
+
+#[test]
+fn guard_try_map_or_else() {
+    let mut map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    map.insert(1, 10);
+    map.publish();
+
+    // the guard is kept on failure, so it can be mapped again
+    let (guard, err) = reader
+        .load()
+        .try_map_or_else(|map| map.get(&2).ok_or("missing"))
+        .err()
+        .unwrap();
+    assert_eq!(err, "missing");
+    let guard = guard
+        .try_map_or_else(|map| map.get(&1).ok_or("missing"))
+        .ok()
+        .unwrap();
+    assert_eq!(*guard, 10);
+
+    let guard = guard.filter(|&v| v == 11).err().unwrap();
+    assert_eq!(*guard.filter(|&v| v == 10).ok().unwrap(), 10);
+}
+
+#[test]
+fn strict_mode_matches_normal() {
+    let mut normal = CMap::<i32, i32>::new();
+    let mut strict = CMap::<i32, i32>::new();
+    strict.set_strict_mode_checked(true);
+    assert!(strict.is_strict_mode());
+    let mut normal_reader = normal.reader();
+    let mut strict_reader = strict.reader();
+
+    for i in 0..20 {
+        for map in [&mut normal, &mut strict] {
+            map.insert(i, i * 10);
+            map.remove(i - 3);
+            if i % 4 == 0 {
+                map.retain(|_, _, v| *v % 20 == 0);
+            }
+            map.publish();
+        }
+
+        assert_eq!(*normal_reader.load(), *strict_reader.load());
+    }
+
+    assert_eq!(strict.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+#[should_panic = "strict mode: the buffers diverged"]
+fn strict_mode_catches_divergence() {
+    let mut map = CMap::new();
+    map.set_strict_mode_checked(true);
+    map.insert(0, 0);
+    map.insert(1, 1);
+    map.publish();
+
+    map.retain(|is_first, _, _| is_first);
+    map.publish();
+}
+
+#[test]
+fn new_lazy() {
+    let initial = (0..100).map(|i| (i, i * 10)).collect::<HashMap<i32, i32>>();
+    let mut map = CMap::<i32, i32>::new_lazy(initial.clone());
+    let mut reader = map.reader();
+    assert_eq!(*reader.load(), initial);
+    assert!(!map.is_materialized());
+
+    // the copy happens on the first publish, and isn't an operation
+    map.insert(100, 1000);
+    map.remove(0);
+    assert_eq!(map.unapplied().len(), 2);
+    map.publish();
+    assert!(map.is_materialized());
+    assert_eq!(reader.get(&100).as_deref(), Some(&1000));
+    assert!(reader.get(&0).is_none());
+    assert_eq!(reader.load().len(), 100);
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    map.insert(0, 0);
+    map.publish();
+    map.publish();
+    assert_eq!(reader.load().len(), 101);
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn new_lazy_materialize() {
+    let initial = HashMap::from([(1, 10), (2, 20)]);
+    let mut map = CMap::<i32, i32>::new_lazy(initial);
+    map.insert(3, 30);
+    map.materialize();
+    assert!(map.is_materialized());
+    assert_eq!(map.unapplied().len(), 1);
+
+    let diff = map.pending_diff();
+    assert_eq!(diff.write_buffer(), diff.published());
+
+    // the comparison materializes the writer map first, so it doesn't see an empty map
+    let mut map = CMap::<i32, i32>::new_lazy(HashMap::from([(1, 10)]));
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn published_len() {
+    let mut map = CMap::new();
+    let reader = map.reader();
+    assert_eq!(reader.published_len(), 0);
+
+    map.insert(0, 0);
+    map.insert(1, 1);
+    // unpublished operations aren't counted
+    assert_eq!(reader.published_len(), 0);
+    map.publish();
+    assert_eq!(reader.published_len(), 2);
+    assert_eq!(map.published_len(), 2);
+
+    map.remove(0);
+    map.force_publish();
+    assert_eq!(reader.published_len(), 1);
+    // a publish without operations doesn't change anything
+    map.publish();
+    map.force_publish();
+    assert_eq!(reader.clone().published_len(), 1);
+
+    // readers created later share the count
+    let shared = map.shared_reader();
+    map.insert(2, 2);
+    map.publish();
+    assert_eq!(shared.published_len(), 2);
+    assert_eq!(shared.into_reader().published_len(), 2);
+}
+
+#[test]
+fn published_len_lazy() {
+    let initial = (0..10).map(|i| (i, i)).collect::<HashMap<i32, i32>>();
+    let mut map = CMap::<i32, i32>::new_lazy(initial);
+    let reader = map.reader();
+    assert_eq!(reader.published_len(), 10);
+
+    map.remove(0);
+    map.publish();
+    assert_eq!(reader.published_len(), 9);
+}
+
+#[test]
+fn published_len_after_poison() {
+    let mut map = CMap::new();
+    let mut reader = map.reader();
+
+    map.insert(0, 0);
+    map.publish();
+    map.insert(1, 1);
+    map.retain(|_, _, _| panic!("panicking op"));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| map.publish()));
+    assert!(result.is_err());
+    assert_eq!(reader.published_len(), reader.load().len());
+
+    map.clear_poison();
+    map.insert(2, 2);
+    map.publish();
+    assert_eq!(reader.published_len(), 2);
+    assert_eq!(reader.published_len(), reader.load().len());
+}
+
+#[test]
+fn published_len_map_strategy() {
+    let mut map = CMap::<i32, i32>::new();
+    map.insert(0, 0);
+    map.publish();
+
+    let mut map = map
+        .try_map_strategy(|_| dbuf::strategy::TrackingStrategy::default())
+        .ok()
+        .unwrap();
+    let reader = map.reader();
+    assert_eq!(reader.published_len(), 1);
+    map.insert(1, 1);
+    map.publish();
+    assert_eq!(reader.published_len(), 2);
+}
+
+#[test]
+fn shrink_buffers() {
+    let mut map = CMap::new();
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+    map.publish();
+    map.clear();
+    map.publish();
+    map.force_publish();
+
+    let capacity = |map: &CMap<i32, i32>| {
+        let split = map.inner.split();
+        (split.reader.capacity(), split.writer.capacity())
+    };
+    let (reader, writer) = capacity(&map);
+    assert!(reader >= 1000 && writer >= 1000);
+
+    map.shrink_buffers_to_fit();
+    map.publish();
+    assert_eq!(capacity(&map).0, 0);
+    map.force_publish();
+    assert_eq!(capacity(&map), (0, 0));
+
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+    map.publish();
+    map.retain(|_, &k, _| k < 10);
+    map.shrink_buffers_to(100);
+    map.publish();
+    map.force_publish();
+    let (reader, writer) = capacity(&map);
+    assert!((100..1000).contains(&reader));
+    assert!((100..1000).contains(&writer));
+    assert_eq!(map.reader().load().len(), 10);
+}
+
+#[test]
+fn initial_gate() {
+    const COUNT: usize = 1000;
+    let mut map = CMap::with_initial_gate();
+
+    let waiting = (0..4)
+        .map(|_| {
+            let mut reader = map.reader();
+            std::thread::spawn(move || {
+                reader.wait_first_publish(Duration::from_secs(60)).unwrap();
+                reader.load().len()
+            })
+        })
+        .collect::<Vec<_>>();
+    let polling = (0..4)
+        .map(|_| {
+            let mut reader = map.reader();
+            std::thread::spawn(move || loop {
+                match reader.try_load_initialized() {
+                    Ok(map) => return (map.get(&0).copied(), map.len()),
+                    Err(ReadError::NotYetPublished) => std::thread::yield_now(),
+                    Err(err) => panic!("{err}"),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut reader = map.reader();
+    assert_eq!(
+        reader.wait_first_publish(Duration::from_millis(10)),
+        Err(NotYetPublished)
+    );
+    assert!(matches!(
+        reader.try_get_initialized(&0),
+        Err(ReadError::NotYetPublished)
+    ));
+    // the plain API still sees the empty map
+    assert_eq!(reader.load().len(), 0);
+
+    std::thread::sleep(Duration::from_millis(20));
+    map.bulk_insert((0..COUNT).map(|i| (i, i)).collect());
+    map.publish();
+
+    for reader in waiting {
+        assert_eq!(reader.join().unwrap(), COUNT);
+    }
+    for reader in polling {
+        assert_eq!(reader.join().unwrap(), (Some(0), COUNT));
+    }
+    assert_eq!(reader.try_get_initialized(&7).unwrap().as_deref(), Some(&7));
+    assert!(map.reader().is_initialized());
+}
+
+#[test]
+fn initial_gate_is_opt_in() {
+    let map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    assert!(reader.is_initialized());
+    assert_eq!(reader.wait_first_publish(Duration::ZERO), Ok(()));
+    assert!(reader.try_load_initialized().unwrap().is_empty());
+}
+
+#[test]
+fn would_publish_block() {
+    let mut map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    assert!(!map.would_publish_block());
+
+    map.insert(1, 1);
+    let guard = reader.load();
+    assert!(map.would_publish_block());
+    drop(guard);
+    assert!(!map.would_publish_block());
+    map.publish();
+
+    // a writer which only publishes when it wouldn't block keeps up with a busy reader
+    let done = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(std::sync::atomic::Ordering::Acquire) {
+                let map = reader.load();
+                assert!(map.len() <= 1);
+            }
+        });
+
+        for i in 0..1000 {
+            map.insert(1, i);
+            if !map.would_publish_block() {
+                map.publish();
+            }
+        }
+
+        done.store(true, std::sync::atomic::Ordering::Release);
+    });
+
+    map.publish();
+    assert_eq!(map.load().get(&1), Some(&999));
+}
+
+#[test]
+fn update() {
+    let mut map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    map.insert(1, 1);
+    map.update(1, |value| *value += 10);
+    // missing keys are left alone
+    map.update(2, |value| *value += 10);
+    assert!(reader.get(&1).is_none());
+
+    map.publish();
+    assert_eq!(reader.get(&1).as_deref(), Some(&11));
+    map.update(1, |value| *value *= 2);
+    map.publish();
+    assert_eq!(reader.get(&1).as_deref(), Some(&22));
+    assert!(reader.get(&2).is_none());
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn shared_values_are_shared_by_both_maps() {
+    use crate::cow::SharedValue;
+
+    let mut map = CMap::<i32, SharedValue<Vec<u8>>>::new();
+    let shared = |map: &CMap<i32, SharedValue<Vec<u8>>>| {
+        let split = map.inner.split();
+        SharedValue::ptr_eq(&split.writer[&1], &split.reader[&1])
+    };
+
+    map.insert(1, SharedValue::new(vec![0; 4096]));
+    map.publish();
+    map.force_publish();
+    assert!(shared(&map));
+    assert_eq!(map.reader().get_deref(&1).unwrap().len(), 4096);
+
+    // updating copies the value once, and both maps share the copy once it's in both
+    map.update(1, |value| value.make_mut()[0] = 1);
+    map.publish();
+    assert!(!shared(&map));
+    map.force_publish();
+    assert!(shared(&map));
+    assert_eq!(map.reader().get_deref(&1).unwrap()[..2], [1, 0]);
+}
+
+#[test]
+fn make_mut_never_changes_a_held_guard() {
+    use crate::cow::SharedValue;
+    use std::sync::Barrier;
+
+    let mut map = CMap::<i32, SharedValue<Vec<u8>>>::new();
+    map.insert(1, SharedValue::new(vec![0; 4096]));
+    map.publish();
+    map.force_publish();
+    // so that the publish below doesn't wait for the guard
+    map.wait_readers_caught_up();
+
+    let mut reader = map.reader();
+    let barrier = Barrier::new(2);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let guard = reader.get_deref(&1).unwrap();
+            barrier.wait();
+            // the writer changes its map and publishes while the guard is held
+            barrier.wait();
+            assert!(guard.iter().all(|&byte| byte == 0));
+        });
+
+        barrier.wait();
+        map.update(1, |value| value.make_mut().fill(1));
+        map.retain(|_, _, value| {
+            value.make_mut()[0] = 2;
+            true
+        });
+        map.publish();
+        assert_eq!(map.reader().get_deref(&1).unwrap()[..2], [2, 1]);
+        barrier.wait();
+    });
+
+    // this waits for the guard to be dropped before it changes the old map
+    map.force_publish();
+    assert_eq!(reader.get_deref(&1).unwrap()[..2], [2, 1]);
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn rewrite_pending() {
+    let mut map = CMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    map.insert(0, 0);
+    map.publish();
+
+    // drop the inserts which are overwritten by later inserts of the same key
+    let dedup = |ops: &mut Vec<MapOp<i32, i32, DefaultHasher>>| {
+        let mut seen = std::collections::HashSet::new();
+        ops.reverse();
+        ops.retain(|op| match op {
+            MapOp::Insert(key, _) => seen.insert(*key),
+            MapOp::Remove(_) => true,
+            _ => {
+                // any other operation may read the earlier values
+                seen.clear();
+                true
+            }
+        });
+        ops.reverse();
+    };
+
+    for i in 0..100 {
+        map.insert(i % 10, i);
+        if i % 7 == 0 {
+            map.remove(i % 10);
+        }
+    }
+    map.retain(|_, _, value| *value != 93);
+    map.insert(3, 3);
+    map.rewrite_pending(dedup);
+    assert!(map.unapplied().len() < 50);
+    map.publish();
+
+    let mut expected = (0..10).map(|i| (i, 90 + i)).collect::<HashMap<_, _>>();
+    expected.insert(3, 3);
+    // 91 and 98 were removed after they were inserted
+    expected.remove(&1);
+    expected.remove(&8);
+    assert_eq!(*reader.load(), expected);
+
+    // inserts of different keys can be reordered
+    for i in (0..10).rev() {
+        map.insert(i, -i);
+    }
+    map.rewrite_pending(|ops| {
+        ops.sort_by_key(|op| match op {
+            MapOp::Insert(key, _) => *key,
+            _ => unreachable!(),
+        })
+    });
+    assert!(matches!(map.unapplied()[0], MapOp::Insert(0, 0)));
+    map.publish();
+    map.force_publish();
+    assert_eq!(
+        *reader.load(),
+        (0..10).map(|i| (i, -i)).collect::<HashMap<_, _>>()
+    );
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn migrate_values_fails_with_readers() {
+    let mut map = CMap::<i32, i32>::new();
+    let reader = map.reader();
+    map.insert(0, 0);
+    map.publish();
+    map.insert(1, 1);
+
+    // readers can only read the old values
+    let Err(MigrateError::HasReaders(mut map)) = map.migrate_values(|value| value as i64) else {
+        panic!("migrated while a reader was alive")
+    };
+    assert_eq!(map.unapplied().len(), 1);
+    map.publish();
+    assert_eq!(map.get(&1), Some(&1));
+
+    drop(reader);
+    let mut map = map.migrate_values(|value| value as i64).ok().unwrap();
+    assert_eq!(map.get(&0), Some(&0i64));
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn migrate_values_with_pending_ops() {
+    use crate::WriterGone;
+
+    let mut map = CMap::<i32, i32>::new();
+    let mut weak_reader = map.weak_reader();
+    let mut watcher = map.watch_key(1);
+    map.bulk_insert((0..10).map(|i| (i, i)).collect());
+    map.publish();
+    let published = map.load().len();
+    assert!(watcher.wait().is_ok());
+
+    map.insert(10, 10);
+    map.bulk_insert(vec![(11, 11), (12, 12)]);
+    map.remove(0);
+    map.retain(|_, _, value| *value != 5);
+    map.update(1, |value| *value += 1);
+    map.insert(13, 13);
+
+    let Err(MigrateError::DroppedOps { mut map, dropped }) =
+        map.migrate_values(|value| value.to_string())
+    else {
+        panic!("the retain and update weren't dropped")
+    };
+    assert_eq!(dropped, 2);
+    assert_eq!(map.unapplied().len(), 4);
+    assert!(weak_reader.load().is_err());
+    assert_eq!(watcher.wait(), Err(WriterGone));
+
+    // the published values were migrated, but not published again
+    let mut reader = map.reader();
+    assert_eq!(reader.load().len(), published);
+    assert_eq!(reader.get(&1).as_deref(), Some(&"1".to_string()));
+
+    map.publish();
+    let expected = (1..14)
+        .map(|i| (i, i.to_string()))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(*reader.load(), expected);
+    map.force_publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn read_errors() {
+    let mut map = CMap::<i32, i32>::with_initial_gate();
+    let mut reader = map.reader();
+    let mut weak = map.weak_reader();
+
+    assert!(matches!(
+        reader.try_load_initialized(),
+        Err(ReadError::NotYetPublished)
+    ));
+    // waiting has its own error, which converts into the same variant
+    let waited = reader
+        .wait_first_publish(Duration::ZERO)
+        .map_err(ReadError::from);
+    assert_eq!(waited, Err(ReadError::NotYetPublished));
+
+    map.insert(1, 10);
+    map.publish();
+    assert_eq!(
+        reader.try_get_initialized(&1).unwrap().as_deref(),
+        Some(&10)
+    );
+    assert_eq!(reader.get_bounded(&1, 0).unwrap().as_deref(), Some(&10));
+
+    drop((map, reader));
+    assert_eq!(weak.try_fork().err(), Some(ReadError::WriterGone));
+    assert!(matches!(weak.load(), Err(ReadError::WriterGone)));
+    assert!(matches!(weak.get(&1), Err(ReadError::WriterGone)));
+    assert_eq!(ReadError::from(crate::MapDropped), ReadError::WriterGone);
+}
+
+#[test]
+fn resilient_reader_serves_the_last_map() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Counted {
+        fn new(live: &Arc<AtomicUsize>) -> Self {
+            live.fetch_add(1, Ordering::Relaxed);
+            Self(live.clone())
+        }
+    }
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            Self::new(&self.0)
+        }
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    let live = Arc::new(AtomicUsize::new(0));
+    let mut map = CMap::new();
+    let mut resilient = map.weak_reader().into_resilient().ok().unwrap();
+    let mut other = map.weak_reader().into_resilient().ok().unwrap();
+    for i in 0..4 {
+        map.insert(i, Counted::new(&live));
+    }
+    map.publish();
+
+    assert!(resilient.get(&3).is_some());
+    assert!(!resilient.is_stale());
+    assert_eq!(other.load().len(), 4);
+    map.insert(4, Counted::new(&live));
+
+    drop(map);
+    // each resilient reader keeps its own clone of the map, the map's buffers were freed
+    assert_eq!(live.load(Ordering::Relaxed), 8);
+
+    assert!(resilient.get(&3).is_some());
+    assert!(resilient.get(&4).is_none());
+    assert!(resilient.is_stale());
+    let snapshot = other.load();
+    assert_eq!(snapshot.len(), 4);
+    assert!(snapshot.is_stale());
+
+    drop(resilient);
+    assert_eq!(live.load(Ordering::Relaxed), 4);
+    drop(other);
+    assert_eq!(live.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn local_strategy_publish_checked() {
+    let mut map = CMap::<i32, i32, DefaultHasher, dbuf::strategy::LocalStrategy>::default();
+    let mut reader = map.reader();
+
+    map.insert(1, 10);
+    assert_eq!(map.publish_checked().ok(), Some(true));
+
+    // the local strategy can't swap while a read guard is held
+    let guard = reader.load();
+    map.insert(2, 20);
+    assert!(map.publish_checked().is_err());
+    assert!(map.force_publish_checked().is_err());
+    assert_eq!(guard.len(), 1);
+    assert_eq!(map.published_len(), 1);
+    drop(guard);
+
+    map.insert(3, 30);
+    assert_eq!(map.publish_checked().ok(), Some(true));
+    assert_eq!(reader.load().len(), 3);
+    assert_eq!(map.published_len(), 3);
+
+    for _ in 0..3 {
+        assert!(map.force_publish_checked().is_ok());
+    }
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn boxed_ops_match_inline() {
+    let mut inline = CMap::<u32, [u8; 256]>::new();
+    let mut boxed = CMap::<u32, [u8; 256]>::new().with_boxed_ops();
+    let mut inline_reader = inline.reader();
+    let mut boxed_reader = boxed.reader();
+
+    // the same writes, to maps with different types
+    macro_rules! write {
+        ($map:ident, $round:expr) => {
+            for i in 0..100 {
+                $map.insert(i, [$round; 256]);
+            }
+            $map.remove(u32::from($round));
+            $map.update(50, |value| value[0] = 0);
+            $map.retain(|_, key, _| *key != 99);
+        };
+    }
+
+    for round in 0..4u8 {
+        write!(inline, round);
+        write!(boxed, round);
+        assert_eq!(boxed.unapplied_iter().len(), inline.unapplied().len());
+        assert_eq!(
+            boxed.pending_diff().unapplied_iter().len(),
+            inline.pending_diff().unapplied_ops().len()
+        );
+        assert!(boxed
+            .unapplied_iter()
+            .zip(inline.unapplied())
+            .all(|(boxed, inline)| boxed.to_replicated() == inline.to_replicated()));
+
+        inline.publish();
+        boxed.publish();
+        assert_eq!(*boxed_reader.load(), *inline_reader.load());
+        assert_eq!(boxed_reader.get(&50).unwrap()[..2], [0, round]);
+    }
+
+    // the rewrite gets the boxed operations
+    boxed.insert(1000, [0; 256]);
+    boxed.rewrite_pending(|ops| ops.retain(|op| !matches!(**op, MapOp::Insert(1000, _))));
+    boxed.publish();
+    assert!(boxed.get(&1000).is_none());
+    assert_eq!(boxed.verify_consistent(), Consistency::Consistent);
+}
+
+#[test]
+fn apply_last_writes_dont_need_split() {
+    use dbuf::op_log::Operation;
+
+    #[derive(Debug, PartialEq)]
+    struct NotClone(i32);
+
+    /// builds a new value for the first map, and moves its value into the second map
+    struct Insert(i32, NotClone);
+
+    impl Operation<HashMap<i32, NotClone>> for Insert {
+        fn apply(&mut self, map: &mut HashMap<i32, NotClone>) {
+            map.insert(self.0, NotClone(self.1 .0));
+        }
+
+        fn apply_last(self, map: &mut HashMap<i32, NotClone>) {
+            map.insert(self.0, self.1);
+        }
+    }
+
+    let mut writer = dbuf::op::OpWriter::<_, Insert>::from(dbuf::raw::Writer::new(
+        dbuf::ptrs::alloc::Owned::new(dbuf::raw::Shared::from_raw_parts(
+            DefaultStrat::default(),
+            dbuf::raw::RawDBuf::new(HashMap::new(), HashMap::new()),
+        )),
+    ));
+
+    writer.apply(Insert(1, NotClone(10)));
+    writer.publish();
+    assert_eq!(writer.split().reader.get(&1), Some(&NotClone(10)));
+    assert!(writer.split().writer.is_empty());
+
+    // the next swap moves the value into the other map
+    writer.apply(Insert(2, NotClone(20)));
+    writer.publish();
+    assert_eq!(writer.split().reader.len(), 2);
+    assert_eq!(writer.split().writer.get(&1), Some(&NotClone(10)));
 }
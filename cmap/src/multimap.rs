@@ -1,23 +1,59 @@
 use super::{DefaultHasher, DefaultStrat};
 use std::{
     borrow::Borrow,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
     convert::Infallible,
     fmt,
-    hash::{BuildHasher, Hash},
+    hash::{BuildHasher, Hash, Hasher},
     ops::Deref,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
-use dbuf::interface::Strategy;
+use dbuf::footprint::{hash_table_bytes, Footprint, MemoryFootprint, MemoryUsage, CLOSURE_BYTES};
+use dbuf::interface::{FromBuffers, RawBuffers, Strategy, StrategyIntrospect};
+use dbuf::op_log::ApplyToBoth;
 use hashbag::HashBag;
 use sync_wrapper::SyncWrapper;
 
-use crate::split::Split;
+use crate::{
+    split::Split, ActiveReaderInfo, BufferId, BuffersDiffer, Consistency, LookupError, ReadError,
+};
 
 pub struct Bag<T> {
     inner: BagInner<T>,
 }
 
+impl<T: Clone + Hash> Clone for Bag<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: match &self.inner {
+                BagInner::One(one) => BagInner::One(one.clone()),
+                BagInner::Many(many) => BagInner::Many(many.clone()),
+            },
+        }
+    }
+}
+
+impl<T: MemoryUsage> MemoryUsage for Bag<T> {
+    fn heap_bytes(&self) -> usize {
+        match &self.inner {
+            BagInner::One(None) => 0,
+            BagInner::One(Some((value, _))) => value.heap_bytes(),
+            // a hash bag is a map from each value to its count
+            BagInner::Many(bag) => {
+                hash_table_bytes::<(T, usize)>(bag.capacity())
+                    + bag
+                        .set_iter()
+                        .map(|(value, _)| value.heap_bytes())
+                        .sum::<usize>()
+            }
+        }
+    }
+}
+
 impl<T> Default for Bag<T> {
     fn default() -> Self {
         Self {
@@ -29,7 +65,7 @@ impl<T> Default for Bag<T> {
 impl<T> Bag<T> {
     pub fn get_one(&self) -> Option<&T> {
         match &self.inner {
-            BagInner::One(None) => None,
+            BagInner::One(None) | BagInner::One(Some((_, 0))) => None,
             BagInner::One(Some((inner, _))) => Some(inner),
             BagInner::Many(many) => many.iter().next(),
         }
@@ -54,30 +90,87 @@ impl<T> Bag<T> {
             BagInner::Many(bag) => bag.len(),
         }
     }
+
+    pub fn set_iter(&self) -> impl Iterator<Item = (&T, usize)> + '_ {
+        let (one, many) = match &self.inner {
+            BagInner::One(Some((value, count))) if *count > 0 => (Some((value, *count)), None),
+            BagInner::One(_) => (None, None),
+            BagInner::Many(bag) => (None, Some(bag.set_iter())),
+        };
+
+        one.into_iter().chain(many.into_iter().flatten())
+    }
 }
 
 impl<T: Hash + Eq> Bag<T> {
     pub fn insert(&mut self, value: T) {
+        self.insert_many(value, 1)
+    }
+
+    /// insert `count` copies of `value`
+    ///
+    /// The total count of the bag saturates at `usize::MAX`, any copies past that are ignored
+    pub fn insert_many(&mut self, value: T, count: usize) {
+        let count = count.min(usize::MAX - self.len());
+        if count == 0 {
+            return;
+        }
+
         match self.inner {
-            BagInner::One(None) => self.inner = BagInner::One(Some((value, 1))),
-            BagInner::One(Some((ref inner, ref mut count))) if *inner == value => *count += 1,
+            BagInner::One(None) | BagInner::One(Some((_, 0))) => {
+                self.inner = BagInner::One(Some((value, count)))
+            }
+            BagInner::One(Some((ref inner, ref mut n))) if *inner == value => *n += count,
             BagInner::One(Some(_)) => {
-                let (inner, count) = match core::mem::take(self).inner {
-                    BagInner::One(Some((value, count))) => (value, count),
+                let (inner, n) = match core::mem::take(self).inner {
+                    BagInner::One(Some((value, n))) => (value, n),
                     _ => unreachable!(),
                 };
-                self.inner = BagInner::One(None);
                 let mut bag = HashBag::new();
-                bag.insert_many(inner, count);
-                bag.insert(value);
+                bag.insert_many(inner, n);
+                bag.insert_many(value, count);
                 self.inner = BagInner::Many(bag);
             }
             BagInner::Many(ref mut bag) => {
-                bag.insert(value);
+                bag.insert_many(value, count);
+            }
+        }
+    }
+
+    /// set the number of copies of `value` to exactly `count`, removing it if `count` is zero
+    ///
+    /// Like [`insert_many`](Self::insert_many), the total count of the bag saturates at `usize::MAX`
+    pub fn set_count(&mut self, value: T, count: usize) {
+        self.remove_all(&value);
+        self.insert_many(value, count);
+    }
+
+    /// remove every copy of `value`
+    pub fn remove_all(&mut self, value: &T) {
+        match self.inner {
+            BagInner::One(Some((ref inner, _))) if inner == value => {
+                self.inner = BagInner::One(None)
+            }
+            BagInner::One(_) => (),
+            BagInner::Many(ref mut bag) => {
+                bag.take_all(value);
+                // go back to the compact representation once there's at most one value left
+                if bag.set_len() <= 1 {
+                    let one = core::mem::take(bag).into_iter().next();
+                    self.inner = BagInner::One(one);
+                }
             }
         }
     }
 
+    pub fn contains(&self, value: &T) -> usize {
+        match self.inner {
+            BagInner::One(Some((ref inner, count))) if inner == value => count,
+            BagInner::One(_) => 0,
+            BagInner::Many(ref bag) => bag.contains(value),
+        }
+    }
+
     pub fn remove(&mut self, value: &T) {
         match self.inner {
             BagInner::One(Some((ref inner, ref mut count))) if inner == value && *count > 0 => {
@@ -101,50 +194,255 @@ impl<T: Hash + Eq> Bag<T> {
     }
 }
 
+impl<T: Hash + Eq> PartialEq for Bag<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .set_iter()
+                .all(|(value, count)| other.contains(value) == count)
+    }
+}
+
+impl<T: Hash + Eq> Eq for Bag<T> {}
+
+/// the values of a hash bag don't have an order, so each value is hashed on its own,
+/// and the hashes are combined in a way which doesn't depend on the order
+impl<T: Hash> Hash for Bag<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut combined = 0u64;
+        for (value, count) in self.set_iter() {
+            // not `RandomState`, so equal bags have the same hash
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            value.hash(&mut hasher);
+            count.hash(&mut hasher);
+            combined = combined.wrapping_add(hasher.finish());
+        }
+        state.write_usize(self.len());
+        state.write_u64(combined);
+    }
+}
+
+impl<T: Hash + Eq> Extend<T> for Bag<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value)
+        }
+    }
+}
+
+impl<T: Hash + Eq> FromIterator<T> for Bag<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut bag = Self::default();
+        bag.extend(iter);
+        bag
+    }
+}
+
+/// serialized as a sequence of `(value, count)` pairs, like `HashBag`
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Bag<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.set_iter())
+    }
+}
+
+/// deserialized from a sequence of `(value, count)` pairs, the counts of duplicate values are merged
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de> + Hash + Eq> serde::Deserialize<'de> for Bag<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut bag = Self::default();
+        for (value, count) in <Vec<(T, usize)> as serde::Deserialize>::deserialize(deserializer)? {
+            bag.insert_many(value, count);
+        }
+        Ok(bag)
+    }
+}
+
 enum BagInner<T> {
     One(Option<(T, usize)>),
     Many(HashBag<T>),
 }
 
-pub struct CMultiMap<K, V, S = DefaultHasher, Strat = DefaultStrat>
-where
+pub struct CMultiMap<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::op::OpWriter<
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>>,
-        MapOp<K, V, S>,
-    >,
+    inner: dbuf::op::OpWriter<dbuf::ptrs::alloc::OwnedPtr<Strat, B>, MapOp<K, V, S>>,
+    /// the number of keys and values in the published map, see [`CMultiMap::published_len`]
+    published: PublishedLen,
 }
 
-pub struct CMultiMapReader<K, V, S = DefaultHasher, Strat = DefaultStrat>
-where
+pub struct CMultiMapReader<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::raw::Reader<
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>>,
-    >,
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedPtr<Strat, B>>,
+    published: PublishedLen,
 }
 
-pub struct CMapReadGuard<'a, K, V, S, Strat = DefaultStrat, T: ?Sized = HashMap<K, Bag<V>, S>>
-where
+/// the number of keys and values in the published map, shared by the writer and its readers
+///
+/// Both counts share one allocation, so that the multimap stays small
+#[derive(Clone)]
+struct PublishedLen(Arc<PublishedLenState>);
+
+/// the counts shared by each clone of a [`PublishedLen`]
+struct PublishedLenState {
+    keys: AtomicUsize,
+    values: AtomicUsize,
+}
+
+impl PublishedLen {
+    fn new<K, V, S>(map: &HashMap<K, Bag<V>, S>) -> Self {
+        Self(Arc::new(PublishedLenState {
+            keys: map.len().into(),
+            values: count_values(map).into(),
+        }))
+    }
+
+    fn set<K, V, S>(&self, map: &HashMap<K, Bag<V>, S>) {
+        // pairs with the loads below, like in `PublishedCount`
+        self.0.keys.store(map.len(), Ordering::Release);
+        self.0.values.store(count_values(map), Ordering::Release);
+    }
+
+    fn keys(&self) -> usize {
+        self.0.keys.load(Ordering::Acquire)
+    }
+
+    fn values(&self) -> usize {
+        self.0.values.load(Ordering::Acquire)
+    }
+}
+
+fn count_values<K, V, S>(map: &HashMap<K, Bag<V>, S>) -> usize {
+    map.values().map(Bag::len).sum()
+}
+
+pub struct CMapReadGuard<
+    'a,
+    K,
+    V,
+    S,
+    Strat = DefaultStrat,
+    T: ?Sized = HashMap<K, Bag<V>, S>,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T>,
+}
+
+pub struct CMapZoomGuard<
+    'a,
+    K,
+    V,
+    S,
+    Strat,
+    T: ?Sized,
+    U: ?Sized,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::ZoomGuard<'a, dbuf::ptrs::alloc::OwnedPtr<Strat, B>, T, U>,
+}
+
+/// A guard over the whole published map, which iterates over each key and its bag
+///
+/// Keys whose bag is empty are skipped
+pub struct MultiMapIterGuard<
+    'a,
+    K,
+    V,
+    S,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    guard: CMapReadGuard<'a, K, V, S, Strat, HashMap<K, Bag<V>, S>, B>,
+}
+
+/// A guard over the whole published map, which iterates over each value along with its key
+pub struct MultiMapFlatIterGuard<
+    'a,
+    K,
+    V,
+    S,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    guard: CMapReadGuard<'a, K, V, S, Strat, HashMap<K, Bag<V>, S>, B>,
+}
+
+pub struct CMultiMapWeakReader<
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    #[allow(clippy::type_complexity)]
+    inner: dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedWeak<Strat, B>>,
+}
+
+pub struct CMultiMapWeakReadGuard<
+    'a,
+    K,
+    V,
+    S = DefaultHasher,
+    Strat = DefaultStrat,
+    T = HashMap<K, Bag<V>, S>,
+    B = dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>,
+> where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    T: ?Sized,
     Strat: Strategy<ValidationError = Infallible>,
 {
     #[allow(clippy::type_complexity)]
-    inner: dbuf::raw::ReadGuard<
-        'a,
-        dbuf::ptrs::alloc::OwnedPtr<Strat, dbuf::raw::RawDBuf<HashMap<K, Bag<V>, S>>>,
-        T,
-    >,
+    inner: dbuf::raw::ReadGuard<'a, dbuf::ptrs::alloc::OwnedStrong<Strat, B>, T>,
 }
 
 pub enum MapOp<K, V, S> {
     Insert(K, V),
+    /// insert the value this many times, see [`Bag::insert_many`]
+    InsertN(K, V, usize),
+    /// set how many times the value is in the key's bag, see [`Bag::set_count`]
+    SetCount(K, V, usize),
     Clear(K),
     Remove(K, V),
     #[allow(clippy::type_complexity)]
     Arbitrary(SyncWrapper<Box<dyn FnMut(bool, &mut HashMap<K, Bag<V>, S>) + Send>>),
+    /// a closure which runs on each map, see [`OpWriter::apply_to_both`](dbuf::op::OpWriter::apply_to_both)
+    ApplyToBoth(ApplyToBoth<HashMap<K, Bag<V>, S>>),
     #[allow(clippy::type_complexity)]
     ArbitraryFor(
         K,
@@ -153,6 +451,58 @@ pub enum MapOp<K, V, S> {
     Purge,
 }
 
+// closures are counted as `CLOSURE_BYTES`, see `dbuf::footprint`
+impl<K: MemoryUsage, V: MemoryUsage, S> MemoryUsage for MapOp<K, V, S> {
+    fn heap_bytes(&self) -> usize {
+        match self {
+            MapOp::Insert(key, value)
+            | MapOp::InsertN(key, value, _)
+            | MapOp::SetCount(key, value, _)
+            | MapOp::Remove(key, value) => key.heap_bytes() + value.heap_bytes(),
+            MapOp::Clear(key) => key.heap_bytes(),
+            MapOp::Arbitrary(_) => CLOSURE_BYTES,
+            MapOp::ApplyToBoth(op) => op.heap_bytes(),
+            MapOp::ArbitraryFor(key, _) => key.heap_bytes() + CLOSURE_BYTES,
+            MapOp::Purge => 0,
+        }
+    }
+}
+
+/// remove every copy of the value from the key's bag, and the key if its bag is empty afterwards
+fn remove_all<K: Hash + Eq, V: Hash + Eq, S: BuildHasher>(
+    buffer: &mut HashMap<K, Bag<V>, S>,
+    key: &K,
+    value: &V,
+) {
+    if let Some(bag) = buffer.get_mut(key) {
+        bag.remove_all(value);
+        if bag.is_empty() {
+            buffer.remove(key);
+        }
+    }
+}
+
+impl<K, V, S> MapOp<K, V, S> {
+    /// the only key this operation changes, or `None` if it may change any key
+    fn key(&self) -> Option<&K> {
+        match self {
+            MapOp::Insert(key, _)
+            | MapOp::InsertN(key, _, _)
+            | MapOp::SetCount(key, _, _)
+            | MapOp::Clear(key)
+            | MapOp::Remove(key, _)
+            | MapOp::ArbitraryFor(key, _) => Some(key),
+            MapOp::Arbitrary(_) | MapOp::ApplyToBoth(_) | MapOp::Purge => None,
+        }
+    }
+}
+
+impl<K, V, S> From<ApplyToBoth<HashMap<K, Bag<V>, S>>> for MapOp<K, V, S> {
+    fn from(op: ApplyToBoth<HashMap<K, Bag<V>, S>>) -> Self {
+        MapOp::ApplyToBoth(op)
+    }
+}
+
 impl<K: Hash + Eq + Split, V: Split + Hash + Eq, S: BuildHasher>
     dbuf::op_log::Operation<HashMap<K, Bag<V>, S>> for MapOp<K, V, S>
 {
@@ -164,6 +514,21 @@ impl<K: Hash + Eq + Split, V: Split + Hash + Eq, S: BuildHasher>
                     .or_insert_with(Bag::default)
                     .insert(value.split());
             }
+            // inserting a value zero times would leave an empty bag
+            MapOp::InsertN(_, _, 0) => (),
+            MapOp::InsertN(key, value, count) => {
+                buffer
+                    .entry(key.split())
+                    .or_insert_with(Bag::default)
+                    .insert_many(value.split(), *count);
+            }
+            MapOp::SetCount(key, value, 0) => remove_all(buffer, key, value),
+            MapOp::SetCount(key, value, count) => {
+                buffer
+                    .entry(key.split())
+                    .or_insert_with(Bag::default)
+                    .set_count(value.split(), *count);
+            }
             MapOp::Clear(key) => {
                 buffer.remove(key);
             }
@@ -174,6 +539,7 @@ impl<K: Hash + Eq + Split, V: Split + Hash + Eq, S: BuildHasher>
                 None => (),
             },
             MapOp::Arbitrary(f) => f.get_mut()(false, buffer),
+            MapOp::ApplyToBoth(op) => op.apply(buffer),
             MapOp::ArbitraryFor(ref mut key, f) => f.get_mut()(false, key.split(), buffer),
             MapOp::Purge => buffer.clear(),
         }
@@ -184,6 +550,20 @@ impl<K: Hash + Eq + Split, V: Split + Hash + Eq, S: BuildHasher>
             MapOp::Insert(key, value) => {
                 buffer.entry(key).or_insert_with(Bag::default).insert(value);
             }
+            MapOp::InsertN(_, _, 0) => (),
+            MapOp::InsertN(key, value, count) => {
+                buffer
+                    .entry(key)
+                    .or_insert_with(Bag::default)
+                    .insert_many(value, count);
+            }
+            MapOp::SetCount(key, value, 0) => remove_all(buffer, &key, &value),
+            MapOp::SetCount(key, value, count) => {
+                buffer
+                    .entry(key)
+                    .or_insert_with(Bag::default)
+                    .set_count(value, count);
+            }
             MapOp::Clear(key) => {
                 buffer.remove(&key);
             }
@@ -194,6 +574,7 @@ impl<K: Hash + Eq + Split, V: Split + Hash + Eq, S: BuildHasher>
                 None => (),
             },
             MapOp::Arbitrary(mut f) => f.get_mut()(false, buffer),
+            MapOp::ApplyToBoth(op) => op.apply_last(buffer),
             MapOp::ArbitraryFor(key, mut f) => f.get_mut()(false, key, buffer),
             MapOp::Purge => buffer.clear(),
         }
@@ -204,10 +585,19 @@ impl<K, V> CMultiMap<K, V> {
     pub fn new() -> Self {
         Self::from_maps(HashMap::new(), HashMap::new())
     }
+
+    /// Create a map which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> CMultiMap<K, V, DefaultHasher, P>
+    where
+        P: Strategy<ValidationError = Infallible> + Default,
+    {
+        CMultiMap::default()
+    }
 }
 
-impl<K, V, S: Default, Strat: Default> Default for CMultiMap<K, V, S, Strat>
+impl<K, V, S: Default, Strat: Default, B> Default for CMultiMap<K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>> + FromBuffers,
     Strat: Strategy<ValidationError = Infallible>,
 {
     fn default() -> Self {
@@ -215,8 +605,9 @@ where
     }
 }
 
-impl<K, V, S: Split, Strat> CMultiMap<K, V, S, Strat>
+impl<K, V, S: Split, Strat, B> CMultiMap<K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>> + FromBuffers,
     Strat: Strategy<ValidationError = Infallible> + Default,
 {
     pub fn with_hasher(mut hasher: S) -> Self {
@@ -227,58 +618,123 @@ where
     }
 }
 
-impl<K, V, S, Strat> CMultiMap<K, V, S, Strat>
+impl<K, V, S, Strat, B> CMultiMap<K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>> + FromBuffers,
     Strat: Strategy<ValidationError = Infallible> + Default,
 {
+    /// Create a map from the two buffers
+    ///
+    /// **The two maps must be equal.** Each operation is applied to both maps, so a difference
+    /// is never repaired, and readers will see one map or the other depending on which was
+    /// published last. Use [`from_maps_checked`](Self::from_maps_checked) if you aren't sure
     pub fn from_maps(front: HashMap<K, Bag<V>, S>, back: HashMap<K, Bag<V>, S>) -> Self {
         Self::from_raw_parts(front, back, Strat::default())
     }
+
+    /// Create a map from the two buffers, or return an error if they aren't equal
+    pub fn from_maps_checked(
+        front: HashMap<K, Bag<V>, S>,
+        back: HashMap<K, Bag<V>, S>,
+    ) -> Result<Self, BuffersDiffer>
+    where
+        K: Hash + Eq,
+        V: Hash + Eq,
+        S: BuildHasher,
+    {
+        crate::op_writer_checked(front, back).map(|inner| Self {
+            published: PublishedLen::new(inner.split().reader),
+            inner,
+        })
+    }
 }
 
-impl<K, V, S, Strat> CMultiMap<K, V, S, Strat>
+impl<K, V, S, Strat, B> CMultiMap<K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     pub fn from_raw_parts(
         front: HashMap<K, Bag<V>, S>,
         back: HashMap<K, Bag<V>, S>,
         strategy: Strat,
-    ) -> Self {
+    ) -> Self
+    where
+        B: FromBuffers,
+    {
+        let inner =
+            dbuf::op::OpWriter::from(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                dbuf::raw::Shared::from_raw_parts(strategy, B::from_buffers(front, back)),
+            )));
         Self {
-            inner: dbuf::op::OpWriter::from(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
-                dbuf::raw::Shared::from_raw_parts(strategy, dbuf::raw::RawDBuf::new(front, back)),
-            ))),
+            published: PublishedLen::new(inner.split().reader),
+            inner,
         }
     }
 
-    pub fn reader(&self) -> CMultiMapReader<K, V, S, Strat> {
+    pub fn reader(&self) -> CMultiMapReader<K, V, S, Strat, B> {
         CMultiMapReader {
             inner: self.inner.reader(),
+            published: self.published.clone(),
+        }
+    }
+
+    /// Create a reader which doesn't keep the maps alive
+    ///
+    /// see [`CMap::weak_reader`](crate::CMap::weak_reader) for details
+    pub fn weak_reader(&self) -> CMultiMapWeakReader<K, V, S, Strat, B> {
+        CMultiMapWeakReader {
+            inner: self.inner.reader().into_weak(),
         }
     }
 
+    /// The readers which currently hold a read guard, useful for finding out who is blocking a publish
+    pub fn debug_blocking_readers(&self) -> Vec<ActiveReaderInfo>
+    where
+        Strat: StrategyIntrospect,
+    {
+        self.inner.debug_active_readers()
+    }
+
+    /// How many bytes both maps, the unpublished operations and the strategy hold
+    ///
+    /// see [`CMap::memory_footprint`](crate::CMap::memory_footprint), each bag with more than
+    /// one distinct value is counted as a hash table too
+    pub fn memory_footprint(&self) -> Footprint
+    where
+        K: MemoryUsage,
+        V: MemoryUsage,
+        Strat: StrategyIntrospect,
+    {
+        self.inner.footprint()
+    }
+
     pub fn load(&self) -> &HashMap<K, Bag<V>, S> {
         self.inner.split().reader
     }
-}
 
-impl<K: Hash + Eq + Split, V: Split + Hash + Eq, S: BuildHasher, Strat> CMultiMap<K, V, S, Strat>
-where
-    Strat: Strategy<ValidationError = Infallible>,
-{
-    pub fn insert(&mut self, key: K, value: V) {
-        self.inner.apply(MapOp::Insert(key, value));
+    /// The number of keys in the published map, without taking a read guard
+    ///
+    /// This is exact as of the last publish, like [`CMap::published_len`](crate::CMap::published_len).
+    /// Keys whose bag is empty are counted too
+    pub fn published_len(&self) -> usize {
+        self.published.keys()
     }
 
-    pub fn remove(&mut self, key: K, value: V) {
-        self.inner.apply(MapOp::Remove(key, value));
+    /// The number of values in the published map, counting each copy of a value,
+    /// without taking a read guard
+    ///
+    /// This is exact as of the last publish. It's counted by walking the keys of the published map
+    /// after each publish
+    pub fn published_value_count(&self) -> usize {
+        self.published.values()
     }
 
     pub fn get<Q>(&self, key: &Q) -> Option<&Bag<V>>
     where
         Q: ?Sized + Hash + Eq,
-        K: Borrow<Q>,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
     {
         self.inner.split().reader.get(key)
     }
@@ -286,11 +742,141 @@ where
     pub fn get_one<Q>(&self, key: &Q) -> Option<&V>
     where
         Q: ?Sized + Hash + Eq,
-        K: Borrow<Q>,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
     {
         self.get(key)?.get_one()
     }
 
+    /// how many times the value is in the key's bag, in the published map
+    pub fn count<Q>(&self, key: &Q, value: &V) -> usize
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        V: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.get(key).map_or(0, |bag| bag.contains(value))
+    }
+
+    pub fn unapplied(&self) -> &[MapOp<K, V, S>] {
+        self.inner.unapplied()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Make every publish block until readers have left the old map, to rule out the delayed
+    /// swap when chasing a bug
+    ///
+    /// This also checks that [`publish_matching`](Self::publish_matching) is only used to
+    /// publish keys which no kept operation changes.
+    /// see [`OpWriter::set_strict_mode`](dbuf::op::OpWriter::set_strict_mode)
+    pub fn set_strict_mode(&mut self, on: bool) {
+        self.inner.set_strict_mode(on)
+    }
+
+    /// true if strict mode is on, see [`set_strict_mode`](Self::set_strict_mode)
+    pub fn is_strict_mode(&self) -> bool {
+        self.inner.is_strict_mode()
+    }
+
+    /// Wait until every read guard which was acquired before this call has been dropped
+    ///
+    /// After a publish, this means no reader can still see the previous version of the map,
+    /// so anything which was only referenced by that version can be safely retired
+    pub fn wait_readers_caught_up(&mut self) {
+        self.inner.wait_for_quiescence();
+    }
+
+    /// Move the maps to a different strategy without copying them
+    ///
+    /// This fails if there are any [`CMultiMapReader`]s, since they use the old strategy.
+    /// [`CMultiMapWeakReader`]s don't prevent the move, but will return [`ReadError::WriterGone`] afterwards.
+    /// The unpublished operations are carried over to the new map
+    pub fn try_map_strategy<Strat2>(
+        self,
+        f: impl FnOnce(Strat) -> Strat2,
+    ) -> Result<CMultiMap<K, V, S, Strat2, B>, Self>
+    where
+        Strat2: Strategy<ValidationError = Infallible>,
+    {
+        let published = self.published;
+        match self.inner.try_map_writer(|writer| {
+            let shared = writer.try_into_shared()?;
+            Ok(dbuf::raw::Writer::new(dbuf::ptrs::alloc::Owned::new(
+                shared.map_strategy(f),
+            )))
+        }) {
+            Ok(inner) => Ok(CMultiMap { inner, published }),
+            Err(inner) => Err(Self { inner, published }),
+        }
+    }
+
+    /// Move the maps to the default thread-safe strategy without copying them
+    ///
+    /// see [`try_map_strategy`](Self::try_map_strategy)
+    pub fn try_into_sync(self) -> Result<CMultiMap<K, V, S, DefaultStrat, B>, Self> {
+        self.try_map_strategy(|_| DefaultStrat::default())
+    }
+}
+
+impl<K, V, S, Strat, B> CMultiMap<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    K: Clone,
+    V: Clone + Hash,
+    S: Clone,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Repair the maps after an operation panicked, see [`CMap::clear_poison`](crate::CMap::clear_poison)
+    pub fn clear_poison(&mut self) {
+        self.inner
+            .clear_poison_with(|writer, reader| writer.clone_from(reader))
+    }
+}
+
+impl<K, V, S, Strat, B> CMultiMap<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    K: Hash + Eq + Split,
+    V: Split + Hash + Eq,
+    S: BuildHasher,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Check that the two maps are equal, see [`CMap::verify_consistent`](crate::CMap::verify_consistent)
+    pub fn verify_consistent(&mut self) -> Consistency {
+        self.inner.verify_buffers_eq()
+    }
+}
+
+impl<K: Hash + Eq + Split, V: Split + Hash + Eq, S: BuildHasher, Strat, B>
+    CMultiMap<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn insert(&mut self, key: K, value: V) {
+        self.inner.apply(MapOp::Insert(key, value));
+    }
+
+    pub fn remove(&mut self, key: K, value: V) {
+        self.inner.apply(MapOp::Remove(key, value));
+    }
+
+    /// insert the value `count` times, see [`Bag::insert_many`]
+    pub fn insert_n(&mut self, key: K, value: V, count: usize) {
+        self.inner.apply(MapOp::InsertN(key, value, count));
+    }
+
+    /// set how many times the value is in the key's bag, in a single operation
+    ///
+    /// Setting the count to zero removes the value, and the key if its bag is empty afterwards
+    pub fn set_count(&mut self, key: K, value: V, count: usize) {
+        self.inner.apply(MapOp::SetCount(key, value, count));
+    }
+
     pub fn purge(&mut self) {
         self.inner.apply(MapOp::Purge)
     }
@@ -338,41 +924,130 @@ where
         ))
     }
 
-    pub fn unapplied(&self) -> &[MapOp<K, V, S>] {
-        self.inner.unapplied()
+    /// Shrink the capacity of both maps as much as possible, see [`HashMap::shrink_to_fit`]
+    ///
+    /// This is an operation, so the published map shrinks on the next publish,
+    /// and the other map on the publish after that
+    pub fn shrink_buffers_to_fit(&mut self) {
+        self.inner
+            .apply_to_both(|map: &mut HashMap<K, Bag<V>, S>| map.shrink_to_fit())
     }
 
+    /// Shrink the capacity of both maps down to `capacity`, see [`HashMap::shrink_to`]
+    ///
+    /// see [`shrink_buffers_to_fit`](Self::shrink_buffers_to_fit) for when each map shrinks
+    pub fn shrink_buffers_to(&mut self, capacity: usize) {
+        self.inner
+            .apply_to_both(move |map: &mut HashMap<K, Bag<V>, S>| map.shrink_to(capacity))
+    }
     pub fn force_publish(&mut self) {
         self.inner.swap_buffers();
+        self.after_publish();
     }
 
     pub fn publish(&mut self) {
-        self.inner.publish()
+        self.inner.publish();
+        self.after_publish();
+    }
+
+    /// Publish the unapplied operations which match `pred`, and keep the others for a later publish
+    ///
+    /// This lets one group of keys, e.g. one tenant's, become visible without waiting for another
+    /// group's batch to be finished. The kept operations stay in the same order, and are published
+    /// by the next [`publish`](Self::publish). Returns how many operations were published.
+    ///
+    /// The two groups must not share any keys, otherwise the maps end up different than if the
+    /// operations were published in order. In [strict mode](Self::set_strict_mode) this panics if
+    /// they do. If any unapplied operation may change every key, like [`retain`](Self::retain)
+    /// or [`purge`](Self::purge), then all of them are published.
+    ///
+    /// see [`OpWriter::publish_matching`](dbuf::op::OpWriter::publish_matching)
+    pub fn publish_matching(&mut self, mut pred: impl FnMut(&MapOp<K, V, S>) -> bool) -> usize {
+        let unapplied = self.inner.unapplied();
+        if unapplied.iter().any(|op| op.key().is_none()) {
+            let published = unapplied.len();
+            self.publish();
+            return published;
+        }
+
+        let matches = unapplied.iter().map(&mut pred).collect::<Vec<_>>();
+
+        if self.inner.is_strict_mode() {
+            let published = unapplied
+                .iter()
+                .zip(&matches)
+                .filter(|(_, &matches)| matches)
+                .filter_map(|(op, _)| op.key())
+                .collect::<HashSet<_>>();
+            let shared = unapplied
+                .iter()
+                .zip(&matches)
+                .filter(|(_, &matches)| !matches)
+                .filter_map(|(op, _)| op.key())
+                .any(|key| published.contains(key));
+            assert!(
+                !shared,
+                "publish_matching: a published operation and a kept operation change the same key"
+            );
+        }
+
+        let mut matches = matches.into_iter();
+        let published = self
+            .inner
+            .publish_matching(|_| matches.next().unwrap_or(false));
+        self.after_publish();
+        published
+    }
+
+    /// update everything which depends on the published map, this must run after every publish
+    ///
+    /// counting the values walks the keys of the published map
+    fn after_publish(&mut self) {
+        self.published.set(self.inner.split().reader);
     }
 }
 
-impl<K, V, S, Strat> Clone for CMultiMapReader<K, V, S, Strat>
+impl<K, V, S, Strat, B> Clone for CMultiMapReader<K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            published: self.published.clone(),
         }
     }
 }
 
-impl<K, V, S, Strat> CMultiMapReader<K, V, S, Strat>
+impl<K, V, S, Strat, B> CMultiMapReader<K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
-    pub fn load(&mut self) -> CMapReadGuard<K, V, S, Strat> {
+    /// Check if both readers read from the same map
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.inner.ptr_eq(&other.inner)
+    }
+
+    /// The number of keys in the published map, see [`CMultiMap::published_len`]
+    pub fn published_len(&self) -> usize {
+        self.published.keys()
+    }
+
+    /// The number of values in the published map, see [`CMultiMap::published_value_count`]
+    pub fn published_value_count(&self) -> usize {
+        self.published.values()
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn load(&mut self) -> CMapReadGuard<K, V, S, Strat, HashMap<K, Bag<V>, S>, B> {
         CMapReadGuard {
             inner: self.inner.get(),
         }
     }
 
-    pub fn get<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<K, V, S, Strat, Bag<V>>>
+    pub fn get<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<K, V, S, Strat, Bag<V>, B>>
     where
         Q: ?Sized + Hash + Eq,
         K: Hash + Eq + Borrow<Q>,
@@ -381,7 +1056,7 @@ where
         self.load().try_map(|map| map.get(key)).ok()
     }
 
-    pub fn get_one<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<K, V, S, Strat, V>>
+    pub fn get_one<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<K, V, S, Strat, V, B>>
     where
         Q: ?Sized + Hash + Eq,
         K: Hash + Eq + Borrow<Q>,
@@ -391,43 +1066,403 @@ where
 
         CMapReadGuard::try_map(guard, Bag::get_one).ok()
     }
-}
-
-impl<K, V, S, Strat, T: ?Sized> Deref for CMapReadGuard<'_, K, V, S, Strat, T>
-where
-    Strat: Strategy<ValidationError = Infallible>,
-{
-    type Target = T;
 
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+    /// Get a value of `key` if `filter` accepts it, or the reason there isn't one
+    pub fn get_one_or_reason<Q>(
+        &mut self,
+        key: &Q,
+        filter: impl FnOnce(&V) -> bool,
+    ) -> Result<CMapReadGuard<K, V, S, Strat, V, B>, LookupError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        self.load()
+            .try_map_or_else(|map| map.get(key).ok_or(LookupError::MissingKey))
+            .map_err(|(_, err)| err)?
+            .try_map_or_else(|bag| bag.get_one().ok_or(LookupError::EmptyBag))
+            .map_err(|(_, err)| err)?
+            .filter(filter)
+            .map_err(|_| LookupError::Filtered)
+    }
+
+    /// how many times the value is in the key's bag
+    pub fn count<Q>(&mut self, key: &Q, value: &V) -> usize
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        V: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.get(key).map_or(0, |bag| bag.contains(value))
+    }
+
+    /// Iterate over every key and its bag of values in the published map
+    pub fn iter(&mut self) -> MultiMapIterGuard<'_, K, V, S, Strat, B> {
+        MultiMapIterGuard { guard: self.load() }
+    }
+
+    /// Iterate over every value in the published map, the key is repeated for each value
+    pub fn flat_iter(&mut self) -> MultiMapFlatIterGuard<'_, K, V, S, Strat, B> {
+        MultiMapFlatIterGuard { guard: self.load() }
+    }
+}
+
+impl<'a, K, V, S, Strat, B> MultiMapIterGuard<'a, K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn iter(&self) -> MultiMapIter<'_, K, V> {
+        MultiMapIter {
+            iter: self.guard.iter(),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn into_guard(self) -> CMapReadGuard<'a, K, V, S, Strat, HashMap<K, Bag<V>, S>, B> {
+        self.guard
+    }
+}
+
+impl<'a, K, V, S, Strat, B> IntoIterator for &'a MultiMapIterGuard<'_, K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Item = (&'a K, BagIter<'a, V>);
+    type IntoIter = MultiMapIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S, Strat, B> MultiMapFlatIterGuard<'a, K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn iter(&self) -> MultiMapFlatIter<'_, K, V> {
+        MultiMapFlatIter {
+            iter: MultiMapIter {
+                iter: self.guard.iter(),
+            },
+            current: None,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn into_guard(self) -> CMapReadGuard<'a, K, V, S, Strat, HashMap<K, Bag<V>, S>, B> {
+        self.guard
     }
 }
 
-impl<'a, K, V, S, Strat, T: ?Sized> CMapReadGuard<'a, K, V, S, Strat, T>
+impl<'a, K, V, S, Strat, B> IntoIterator for &'a MultiMapFlatIterGuard<'_, K, V, S, Strat, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
-    pub fn map<U: ?Sized>(self, f: impl FnOnce(&T) -> &U) -> CMapReadGuard<'a, K, V, S, Strat, U> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = MultiMapFlatIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, B> Deref for CMapReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, S, Strat, T: ?Sized, B> CMapReadGuard<'a, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CMapReadGuard<'a, K, V, S, Strat, U, B> {
         CMapReadGuard {
             inner: dbuf::raw::ReadGuard::map(self.inner, f),
         }
     }
 
+    pub fn map_with_parent<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CMapZoomGuard<'a, K, V, S, Strat, T, U, B> {
+        CMapZoomGuard {
+            inner: self.inner.map_with_parent(f),
+        }
+    }
+
     pub fn try_map<U: ?Sized>(
         self,
         f: impl FnOnce(&T) -> Option<&U>,
-    ) -> Result<CMapReadGuard<'a, K, V, S, Strat, U>, Self> {
+    ) -> Result<CMapReadGuard<'a, K, V, S, Strat, U, B>, Self> {
         match dbuf::raw::ReadGuard::try_map(self.inner, f) {
             Ok(inner) => Ok(CMapReadGuard { inner }),
             Err(inner) => Err(CMapReadGuard { inner }),
         }
     }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CMapReadGuard<'a, K, V, S, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CMapReadGuard { inner }),
+            Err((inner, err)) => Err((CMapReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CMapReadGuard { inner }),
+            Err(inner) => Err(CMapReadGuard { inner }),
+        }
+    }
 }
 
-impl<K, V, S, Strat, T: ?Sized + core::fmt::Debug> core::fmt::Debug
-    for CMapReadGuard<'_, K, V, S, Strat, T>
+impl<K, V, S, Strat, T: ?Sized, U: ?Sized, B> Deref for CMapZoomGuard<'_, K, V, S, Strat, T, U, B>
 where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, S, Strat, T: ?Sized, U: ?Sized, B> CMapZoomGuard<'a, K, V, S, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn zoomed(&self) -> &U {
+        self.inner.zoomed()
+    }
+
+    pub fn parent(&self) -> &T {
+        self.inner.parent()
+    }
+
+    pub fn rezoom<W: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &W,
+    ) -> CMapZoomGuard<'a, K, V, S, Strat, T, W, B> {
+        CMapZoomGuard {
+            inner: self.inner.rezoom(f),
+        }
+    }
+
+    pub fn into_parent(self) -> CMapReadGuard<'a, K, V, S, Strat, T, B> {
+        CMapReadGuard {
+            inner: self.inner.into_parent(),
+        }
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, U: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CMapZoomGuard<'_, K, V, S, Strat, T, U, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        U::fmt(self, f)
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CMapReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<K, V, S, Strat, B> Clone for CMultiMapWeakReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V, S, Strat, B> CMultiMapWeakReader<K, V, S, Strat, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Create another weak reader to the same map
+    ///
+    /// Unlike `clone`, this returns [`ReadError::WriterGone`] right away if the map was already dropped
+    pub fn try_fork(&self) -> Result<Self, ReadError> {
+        Ok(Self {
+            inner: self.inner.try_fork()?,
+        })
+    }
+
+    /// the underlying reader, used by the [`evmap`](crate::compat::evmap) shim
+    #[cfg(feature = "evmap-compat")]
+    pub(crate) fn into_raw(self) -> dbuf::raw::Reader<dbuf::ptrs::alloc::OwnedWeak<Strat, B>> {
+        self.inner
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn load(
+        &mut self,
+    ) -> Result<CMultiMapWeakReadGuard<K, V, S, Strat, HashMap<K, Bag<V>, S>, B>, ReadError> {
+        Ok(CMultiMapWeakReadGuard {
+            inner: self.inner.checked_get()?,
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn get<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Result<Option<CMultiMapWeakReadGuard<K, V, S, Strat, Bag<V>, B>>, ReadError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        Ok(self.load()?.try_map(|map| map.get(key)).ok())
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn get_one<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Result<Option<CMultiMapWeakReadGuard<K, V, S, Strat, V, B>>, ReadError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        Ok(self
+            .get(key)?
+            .and_then(|guard| CMultiMapWeakReadGuard::try_map(guard, Bag::get_one).ok()))
+    }
+
+    /// how many times the value is in the key's bag
+    pub fn count<Q>(&mut self, key: &Q, value: &V) -> Result<usize, ReadError>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        V: Hash + Eq,
+        S: BuildHasher,
+    {
+        Ok(self.get(key)?.map_or(0, |bag| bag.contains(value)))
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized, B> Deref for CMultiMapWeakReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, K, V, S, Strat, T: ?Sized, B> CMultiMapWeakReadGuard<'a, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn buffer_id(&self) -> BufferId {
+        self.inner.buffer_id()
+    }
+
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.inner.same_buffer(id)
+    }
+
+    pub fn map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> &U,
+    ) -> CMultiMapWeakReadGuard<'a, K, V, S, Strat, U, B> {
+        CMultiMapWeakReadGuard {
+            inner: dbuf::raw::ReadGuard::map(self.inner, f),
+        }
+    }
+
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<CMultiMapWeakReadGuard<'a, K, V, S, Strat, U, B>, Self> {
+        match dbuf::raw::ReadGuard::try_map(self.inner, f) {
+            Ok(inner) => Ok(CMultiMapWeakReadGuard { inner }),
+            Err(inner) => Err(CMultiMapWeakReadGuard { inner }),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn try_map_or_else<U: ?Sized, E>(
+        self,
+        f: impl FnOnce(&T) -> Result<&U, E>,
+    ) -> Result<CMultiMapWeakReadGuard<'a, K, V, S, Strat, U, B>, (Self, E)> {
+        match dbuf::raw::ReadGuard::try_map_or_else(self.inner, f) {
+            Ok(inner) => Ok(CMultiMapWeakReadGuard { inner }),
+            Err((inner, err)) => Err((CMultiMapWeakReadGuard { inner }, err)),
+        }
+    }
+
+    pub fn filter(self, pred: impl FnOnce(&T) -> bool) -> Result<Self, Self> {
+        match dbuf::raw::ReadGuard::filter(self.inner, pred) {
+            Ok(inner) => Ok(CMultiMapWeakReadGuard { inner }),
+            Err(inner) => Err(CMultiMapWeakReadGuard { inner }),
+        }
+    }
+}
+
+impl<K, V, S, Strat, T: ?Sized + core::fmt::Debug, B> core::fmt::Debug
+    for CMultiMapWeakReadGuard<'_, K, V, S, Strat, T, B>
+where
+    B: RawBuffers<Buffer = HashMap<K, Bag<V>, S>>,
     Strat: Strategy<ValidationError = Infallible>,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -466,10 +1501,488 @@ impl<'a, T> Iterator for BagIter<'a, T> {
             BagIter::Many(many) => many.next(),
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            BagIter::One(None) => (0, Some(0)),
+            BagIter::One(Some((_, count))) => (*count, Some(*count)),
+            BagIter::Many(many) => many.size_hint(),
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for BagIter<'_, T> {}
+impl<T> core::iter::FusedIterator for BagIter<'_, T> {}
+
+impl<T> Clone for BagIter<'_, T> {
+    fn clone(&self) -> Self {
+        match self {
+            BagIter::One(one) => BagIter::One(*one),
+            BagIter::Many(many) => BagIter::Many(many.clone()),
+        }
+    }
+}
+
+/// An iterator over each key and its non-empty bag
+pub struct MultiMapIter<'a, K, V> {
+    iter: std::collections::hash_map::Iter<'a, K, Bag<V>>,
+}
+
+impl<K, V> Clone for MultiMapIter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for MultiMapIter<'a, K, V> {
+    type Item = (&'a K, BagIter<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .by_ref()
+            .find(|(_, bag)| !bag.is_empty())
+            .map(|(key, bag)| (key, bag.iter()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // empty bags are skipped, so only the upper bound is known
+        (0, self.iter.size_hint().1)
+    }
+}
+
+impl<K, V> core::iter::FusedIterator for MultiMapIter<'_, K, V> {}
+
+/// An iterator over each value in the map along with its key
+pub struct MultiMapFlatIter<'a, K, V> {
+    iter: MultiMapIter<'a, K, V>,
+    current: Option<(&'a K, BagIter<'a, V>)>,
+}
+
+impl<K, V> Clone for MultiMapFlatIter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for MultiMapFlatIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, values)) = &mut self.current {
+                if let Some(value) = values.next() {
+                    return Some((*key, value));
+                }
+            }
+
+            self.current = Some(self.iter.next()?);
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let current = self.current.as_ref().map_or(0, |(_, values)| values.len());
+        match self.iter.size_hint() {
+            (_, Some(0)) => (current, Some(current)),
+            _ => (current, None),
+        }
+    }
 }
 
+impl<K, V> core::iter::FusedIterator for MultiMapFlatIter<'_, K, V> {}
+
 impl<T: fmt::Debug> fmt::Debug for Bag<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_list().entries(self).finish()
     }
 }
+
+#[test]
+fn bag_eq_ignores_representation() {
+    let mut one = Bag::default();
+    one.insert(1);
+    one.insert(1);
+    assert!(matches!(one.inner, BagInner::One(Some((1, 2)))));
+
+    let mut many = [1, 2, 1].into_iter().collect::<Bag<_>>();
+    assert!(matches!(many.inner, BagInner::Many(_)));
+    assert_ne!(one, many);
+    many.remove(&2);
+    assert!(matches!(many.inner, BagInner::Many(_)));
+    assert_eq!(one, many);
+
+    // a zero-count tombstone is the same as an empty bag
+    one.remove(&1);
+    one.remove(&1);
+    assert!(matches!(one.inner, BagInner::One(Some((1, 0)))));
+    assert_eq!(one, Bag::default());
+    assert!(one.set_iter().next().is_none());
+
+    // and is replaced on the next insert, instead of moving to the many representation
+    one.extend([3, 3]);
+    assert!(matches!(one.inner, BagInner::One(Some((3, 2)))));
+    many.retain(|_, _| 0);
+    many.insert_many(3, 2);
+    assert_eq!(one, many);
+    assert_eq!(many.contains(&3), 2);
+    assert_eq!(many.contains(&1), 0);
+}
+
+#[test]
+fn bag_hash_ignores_order_and_representation() {
+    let state = DefaultHasher::new();
+
+    let mut one = Bag::default();
+    one.insert_many(1, 2);
+    let many = [2, 1, 3, 1].into_iter().collect::<Bag<_>>();
+    let reversed = [3, 1, 1, 2].into_iter().collect::<Bag<_>>();
+    assert_eq!(state.hash_one(&many), state.hash_one(&reversed));
+
+    let mut many = many;
+    many.remove(&2);
+    many.remove(&3);
+    assert_eq!(one, many);
+    assert_eq!(state.hash_one(&one), state.hash_one(&many));
+    assert_ne!(state.hash_one(&one), state.hash_one(Bag::<i32>::default()));
+}
+
+#[test]
+fn reads_dont_need_split() {
+    #[derive(PartialEq, Eq, Hash)]
+    struct NotClone(i32);
+
+    let map = CMultiMap::<i32, NotClone>::from_maps(
+        [(1, Bag::from_iter([NotClone(10)]))].into_iter().collect(),
+        [(1, Bag::from_iter([NotClone(10)]))].into_iter().collect(),
+    );
+    let mut reader = map.reader();
+
+    assert_eq!(map.get_one(&1).map(|v| v.0), Some(10));
+    assert_eq!(map.get(&1).map(Bag::len), Some(1));
+    assert!(map.unapplied().is_empty());
+    assert_eq!(reader.get_one(&1).map(|v| v.0), Some(10));
+}
+
+#[test]
+fn reader_iter_skips_empty_bags() {
+    let mut map = CMultiMap::<i32, i32>::new();
+    let mut reader = map.reader();
+
+    map.insert(1, 10);
+    map.insert(2, 20);
+    map.insert(2, 21);
+    map.insert(2, 21);
+    map.insert(3, 30);
+    map.insert(4, 40);
+    map.insert(4, 41);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    // a zero-count tombstone for 3, and an empty many-bag for 4
+    map.remove(3, 30);
+    map.remove(4, 40);
+    map.remove(4, 41);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    let guard = reader.iter();
+    assert_eq!(guard.into_guard().len(), 4);
+
+    let guard = reader.iter();
+    let mut groups = guard
+        .iter()
+        .map(|(&key, values)| {
+            assert_eq!(values.len(), values.clone().count());
+            let mut values = values.copied().collect::<Vec<_>>();
+            values.sort_unstable();
+            (key, values)
+        })
+        .collect::<Vec<_>>();
+    groups.sort_unstable();
+    assert_eq!(groups, [(1, vec![10]), (2, vec![20, 21, 21])]);
+    drop(guard);
+
+    let guard = reader.flat_iter();
+    let mut values = guard.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>();
+    values.sort_unstable();
+    assert_eq!(values, [(1, 10), (2, 20), (2, 21), (2, 21)]);
+}
+
+#[test]
+fn set_count_and_insert_n() {
+    let mut map = CMultiMap::<i32, i32>::new();
+    let mut reader = map.reader();
+
+    map.insert_n(1, 10, 3);
+    map.insert_n(1, 11, 0);
+    map.set_count(2, 20, 2);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.count(&1, &10), 3);
+    assert_eq!(reader.count(&1, &11), 0);
+    assert_eq!(map.count(&2, &20), 2);
+
+    // one value to many, and back to one
+    map.set_count(1, 11, 2);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.count(&1, &10), 3);
+    assert_eq!(reader.count(&1, &11), 2);
+    assert!(matches!(map.get(&1).unwrap().inner, BagInner::Many(_)));
+
+    map.set_count(1, 10, 0);
+    map.set_count(2, 20, 0);
+    map.set_count(3, 30, 0);
+    // publish twice so that both buffers replayed the ops
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert!(matches!(
+        map.get(&1).unwrap().inner,
+        BagInner::One(Some((11, 2)))
+    ));
+    assert_eq!(reader.count(&1, &10), 0);
+    // setting the count to zero prunes empty bags
+    assert!(reader.get(&2).is_none());
+    assert!(reader.get(&3).is_none());
+    assert_eq!(map.load().len(), 1);
+}
+
+#[test]
+fn insert_n_zero_is_a_noop() {
+    let mut map = CMultiMap::<i32, i32>::new();
+    let mut reader = map.reader();
+
+    map.insert_n(1, 10, 0);
+    map.insert(2, 20);
+    map.insert_n(2, 21, 0);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    // no empty bag for a missing key, in either buffer
+    assert!(reader.get(&1).is_none());
+    assert!(map.get(&1).is_none());
+    assert_eq!(map.load().len(), 1);
+    assert_eq!(reader.count(&2, &20), 1);
+    assert_eq!(reader.count(&2, &21), 0);
+}
+
+#[test]
+fn insert_n_saturates() {
+    let mut bag = Bag::default();
+    bag.insert_many(1, usize::MAX - 1);
+    bag.insert_many(1, 5);
+    assert_eq!(bag.contains(&1), usize::MAX);
+    // the bag is full, so this is ignored
+    bag.insert_many(2, 1);
+    assert_eq!(bag.contains(&2), 0);
+
+    bag.set_count(1, 3);
+    bag.insert_many(2, usize::MAX);
+    assert_eq!(bag.contains(&1), 3);
+    assert_eq!(bag.contains(&2), usize::MAX - 3);
+    assert_eq!(bag.len(), usize::MAX);
+    bag.insert_many(1, 1);
+    assert_eq!(bag.contains(&1), 3);
+
+    let mut map = CMultiMap::<i32, i32>::new();
+    map.insert_n(1, 10, usize::MAX);
+    map.insert_n(1, 10, usize::MAX);
+    map.insert(1, 11);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(map.count(&1, &10), usize::MAX);
+    assert_eq!(map.count(&1, &11), 0);
+}
+
+#[test]
+fn get_one_or_reason() {
+    let mut map = CMultiMap::<i32, i32>::new();
+    let mut reader = map.reader();
+    map.insert(1, 10);
+    // removing the only value leaves an empty bag
+    map.insert(2, 20);
+    map.remove(2, 20);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    assert_eq!(reader.get_one_or_reason(&1, |_| true).as_deref(), Ok(&10));
+    assert_eq!(
+        reader.get_one_or_reason(&3, |_| true).err(),
+        Some(LookupError::MissingKey)
+    );
+    assert_eq!(
+        reader.get_one_or_reason(&2, |_| true).err(),
+        Some(LookupError::EmptyBag)
+    );
+    assert_eq!(
+        reader.get_one_or_reason(&1, |&v| v > 10).err(),
+        Some(LookupError::Filtered)
+    );
+}
+
+#[test]
+fn published_len() {
+    let mut map = CMultiMap::new();
+    let reader = map.reader();
+
+    map.insert("a", 1);
+    map.insert("a", 2);
+    map.insert_n("b", 3, 4);
+    assert_eq!(reader.published_len(), 0);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.published_len(), 2);
+    assert_eq!(reader.published_value_count(), 6);
+    assert_eq!(map.published_value_count(), 6);
+
+    map.remove("b", 3);
+    map.force_publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.published_value_count(), 5);
+
+    map.clear("b");
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(reader.clone().published_len(), 1);
+    assert_eq!(reader.published_value_count(), 2);
+}
+
+#[test]
+fn shrink_buffers() {
+    let mut map = CMultiMap::new();
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.purge();
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.force_publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+
+    let capacity = |map: &CMultiMap<i32, i32>| {
+        let split = map.inner.split();
+        (split.reader.capacity(), split.writer.capacity())
+    };
+    let (reader, writer) = capacity(&map);
+    assert!(reader >= 1000 && writer >= 1000);
+
+    map.shrink_buffers_to(100);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.force_publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    let (reader, writer) = capacity(&map);
+    assert!((100..1000).contains(&reader));
+    assert!((100..1000).contains(&writer));
+
+    map.shrink_buffers_to_fit();
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    map.force_publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    assert_eq!(capacity(&map), (0, 0));
+}
+
+#[test]
+fn publish_matching_tenants() {
+    /// how many times each value is in each key's bag
+    type Model = HashMap<(char, i32), HashMap<i32, usize>>;
+
+    /// check the published map against the model
+    fn assert_matches(reader: &mut CMultiMapReader<(char, i32), i32>, model: &Model) {
+        let map = reader.load();
+        let bags = model.values().filter(|bag| bag.values().any(|&n| n > 0));
+        assert_eq!(map.len(), bags.count());
+        for (key, bag) in model {
+            for (value, &count) in bag {
+                assert_eq!(map.get(key).map_or(0, |bag| bag.contains(value)), count);
+            }
+        }
+    }
+
+    let mut map = CMultiMap::<(char, i32), i32>::new();
+    map.set_strict_mode(true);
+    let mut reader = map.reader();
+    let mut published = Model::new();
+    let mut pending = Model::new();
+
+    for i in 0..10 {
+        map.insert(('a', i % 3), i);
+        *published
+            .entry(('a', i % 3))
+            .or_default()
+            .entry(i)
+            .or_default() += 1;
+        map.insert(('b', i % 4), i);
+        *pending
+            .entry(('b', i % 4))
+            .or_default()
+            .entry(i)
+            .or_default() += 1;
+    }
+    map.set_count(('b', 0), 100, 2);
+    pending.entry(('b', 0)).or_default().insert(100, 2);
+    map.remove(('a', 0), 3);
+    published.entry(('a', 0)).or_default().insert(3, 0);
+
+    // tenant a is published, while tenant b's batch is still being written
+    assert_eq!(map.publish_matching(|op| op.key().unwrap().0 == 'a'), 11);
+    assert_matches(&mut reader, &published);
+    assert_eq!(map.unapplied().len(), 11);
+    assert!(map.unapplied().iter().all(|op| op.key().unwrap().0 == 'b'));
+
+    map.clear(('a', 1));
+    published.remove(&('a', 1));
+    assert_eq!(map.publish_matching(|op| op.key().unwrap().0 == 'a'), 1);
+    assert_matches(&mut reader, &published);
+
+    // the rest of tenant b's batch
+    map.remove(('b', 1), 1);
+    pending.entry(('b', 1)).or_default().insert(1, 0);
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    published.extend(pending);
+    assert_matches(&mut reader, &published);
+
+    // both maps end up the same
+    map.publish();
+    assert_eq!(map.verify_consistent(), Consistency::Consistent);
+    let split = map.inner.split();
+    assert_eq!(split.writer, split.reader);
+}
+
+#[test]
+fn publish_matching_whole_map_ops() {
+    let mut map = CMultiMap::<i32, i32>::new();
+    map.insert(1, 10);
+    map.purge();
+    map.insert(2, 20);
+
+    // purge changes every key, so everything is published
+    assert_eq!(map.publish_matching(|op| op.key() == Some(&1)), 3);
+    assert!(map.unapplied().is_empty());
+    assert!(map.get(&1).is_none());
+    assert_eq!(map.count(&2, &20), 1);
+}
+
+#[test]
+#[should_panic = "change the same key"]
+fn publish_matching_shared_keys_in_strict_mode() {
+    let mut map = CMultiMap::<i32, i32>::new();
+    map.set_strict_mode(true);
+    map.insert(1, 10);
+    map.remove(1, 10);
+    map.publish_matching(|op| matches!(op, MapOp::Insert(..)));
+}
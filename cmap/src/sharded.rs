@@ -0,0 +1,387 @@
+//! A [`CMap`] split into independent shards
+//!
+//! Every write to a [`CMap`] goes through a single op log, and publishing applies every
+//! operation to both maps. [`ShardedCMap`] routes each key to one of `SHARDS` independent
+//! [`CMap`]s by its hash, so each shard has its own op log and a publish only has to touch
+//! the shards which were written to since the last publish.
+//!
+//! Each shard has its own lock, so the writer methods take `&self`, and threads which write
+//! to keys in different shards don't wait on each other.
+//!
+//! The trade-off is on the read side: a [`ShardedCMapReadGuard`] holds a guard for every shard,
+//! and each shard may be at a different publish. So the snapshot is consistent per shard,
+//! but it is *not* an atomic snapshot of the whole map. Writes to keys in different shards
+//! may become visible in any order, even if they were published together.
+
+use super::{DefaultHasher, DefaultStrat};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    convert::Infallible,
+    hash::{BuildHasher, Hash},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use dbuf::interface::Strategy;
+
+use crate::{
+    map::{CMap, CMapReadGuard, CMapReader},
+    split::Split,
+};
+
+pub struct ShardedCMap<K, V, S = DefaultHasher, Strat = DefaultStrat, const SHARDS: usize = 8>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    hasher: S,
+    /// the shards, each behind its own lock so that they can be written to concurrently
+    shards: [Mutex<CMap<K, V, S, Strat>>; SHARDS],
+    /// which shards have unpublished operations, each flag is only changed while holding its shard's lock
+    dirty: [AtomicBool; SHARDS],
+}
+
+pub struct ShardedCMapReader<K, V, S = DefaultHasher, Strat = DefaultStrat, const SHARDS: usize = 8>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    hasher: S,
+    readers: [CMapReader<K, V, S, Strat>; SHARDS],
+}
+
+pub struct ShardedCMapReadGuard<'a, K, V, S, Strat, const SHARDS: usize>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    hasher: &'a S,
+    guards: [CMapReadGuard<'a, K, V, S, Strat>; SHARDS],
+}
+
+/// pick the shard for a key
+///
+/// The shards use the same hasher for their maps, so this uses the middle bits of the hash.
+/// The low bits pick the bucket and the high bits are the tag inside each shard, so if
+/// those were used here, every key in a shard would collide on them.
+fn shard_index<Q: ?Sized + Hash, S: BuildHasher>(hasher: &S, key: &Q, shards: usize) -> usize {
+    ((hasher.hash_one(key) >> 32) as usize) % shards
+}
+
+impl<K, V> ShardedCMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHasher::default())
+    }
+
+    /// Create a map which uses the strategy `P`, usually one of the [`profiles`](dbuf::profiles)
+    pub fn with_profile<P>() -> ShardedCMap<K, V, DefaultHasher, P>
+    where
+        P: Strategy<ValidationError = Infallible> + Default,
+    {
+        ShardedCMap::default()
+    }
+}
+
+impl<K, V, S, Strat, const SHARDS: usize> Default for ShardedCMap<K, V, S, Strat, SHARDS>
+where
+    S: Default + Split,
+    Strat: Strategy<ValidationError = Infallible> + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S: Split, Strat, const SHARDS: usize> ShardedCMap<K, V, S, Strat, SHARDS>
+where
+    Strat: Strategy<ValidationError = Infallible> + Default,
+{
+    pub fn with_hasher(mut hasher: S) -> Self {
+        assert!(SHARDS != 0, "a sharded map needs at least one shard");
+
+        Self {
+            shards: std::array::from_fn(|_| {
+                Mutex::new(CMap::from_maps(
+                    HashMap::with_hasher(hasher.split()),
+                    HashMap::with_hasher(hasher.split()),
+                ))
+            }),
+            hasher,
+            dirty: std::array::from_fn(|_| AtomicBool::new(false)),
+        }
+    }
+}
+
+impl<K, V, S, Strat, const SHARDS: usize> ShardedCMap<K, V, S, Strat, SHARDS>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn reader(&self) -> ShardedCMapReader<K, V, S, Strat, SHARDS>
+    where
+        S: Clone,
+    {
+        ShardedCMapReader {
+            hasher: self.hasher.clone(),
+            readers: std::array::from_fn(|shard| self.lock_shard(shard).reader()),
+        }
+    }
+
+    /// Lock a shard, this blocks writes to the keys in that shard until the guard is dropped
+    ///
+    /// Operations applied through the guard aren't tracked as dirty, so publish them with
+    /// [`force_publish`](Self::force_publish), or on the shard itself
+    pub fn lock_shard(&self, shard: usize) -> MutexGuard<'_, CMap<K, V, S, Strat>> {
+        // ignore poison, since the shard is checked for poison when it's published
+        self.shards[shard]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn shards_mut(&mut self) -> [&mut CMap<K, V, S, Strat>; SHARDS] {
+        self.shards
+            .each_mut()
+            .map(|shard| shard.get_mut().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Returns true if the shard has operations which haven't been published yet
+    pub fn is_dirty(&self, shard: usize) -> bool {
+        self.dirty[shard].load(Ordering::Relaxed)
+    }
+
+    pub fn shard_for<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + Hash,
+        K: Borrow<Q>,
+        S: BuildHasher,
+    {
+        shard_index(&self.hasher, key, SHARDS)
+    }
+
+    /// Get the value of `key` in the published map of its shard
+    ///
+    /// This needs exclusive access, so that it doesn't need to lock the shard. Use a
+    /// [`ShardedCMapReader`] to read while other threads are writing
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        let shard = self.shard_for(key);
+        self.shards_mut()[shard].get(key)
+    }
+}
+
+impl<K, V, S, Strat, const SHARDS: usize> ShardedCMap<K, V, S, Strat, SHARDS>
+where
+    K: Hash + Eq + Split,
+    V: Split,
+    S: BuildHasher,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// lock the shard which `key` belongs to, and mark it as dirty
+    fn dirty_shard(&self, key: &K) -> MutexGuard<'_, CMap<K, V, S, Strat>> {
+        let index = self.shard_for(key);
+        let shard = self.lock_shard(index);
+        self.dirty[index].store(true, Ordering::Relaxed);
+        shard
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.dirty_shard(&key).insert(key, value)
+    }
+
+    pub fn remove(&self, key: K) {
+        self.dirty_shard(&key).remove(key)
+    }
+
+    /// Clear every shard, one after another
+    pub fn clear(&self) {
+        for (index, dirty) in self.dirty.iter().enumerate() {
+            let mut shard = self.lock_shard(index);
+            dirty.store(true, Ordering::Relaxed);
+            shard.clear()
+        }
+    }
+
+    /// Retain the entries for which `f` returns true
+    ///
+    /// `f` is cloned for each shard
+    pub fn retain(&self, f: impl FnMut(bool, &K, &mut V) -> bool + Clone + Send + 'static) {
+        for (index, dirty) in self.dirty.iter().enumerate() {
+            let mut shard = self.lock_shard(index);
+            dirty.store(true, Ordering::Relaxed);
+            shard.retain(f.clone())
+        }
+    }
+
+    /// Swap the buffers of every shard, even the ones without any new operations
+    pub fn force_publish(&self) {
+        for (index, dirty) in self.dirty.iter().enumerate() {
+            let mut shard = self.lock_shard(index);
+            dirty.store(false, Ordering::Relaxed);
+            shard.force_publish()
+        }
+    }
+
+    /// Publish the shards which were written to since the last publish
+    ///
+    /// Each shard is locked while it's published, so writes to the other shards can continue
+    pub fn publish(&self) {
+        for (index, dirty) in self.dirty.iter().enumerate() {
+            // check the flag while holding the lock, so that a concurrent write can't be missed
+            let mut shard = self.lock_shard(index);
+            if dirty.swap(false, Ordering::Relaxed) {
+                shard.publish()
+            }
+        }
+    }
+}
+
+impl<K, V, S, Strat, const SHARDS: usize> Clone for ShardedCMapReader<K, V, S, Strat, SHARDS>
+where
+    S: Clone,
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            hasher: self.hasher.clone(),
+            readers: self.readers.clone(),
+        }
+    }
+}
+
+impl<K, V, S, Strat, const SHARDS: usize> ShardedCMapReader<K, V, S, Strat, SHARDS>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    /// Load every shard
+    ///
+    /// Each shard is a consistent snapshot, but the shards may be from different publishes.
+    /// This blocks publishing *every* shard until the guard is dropped, so prefer
+    /// [`get`](Self::get) for point reads
+    pub fn load(&mut self) -> ShardedCMapReadGuard<'_, K, V, S, Strat, SHARDS> {
+        ShardedCMapReadGuard {
+            hasher: &self.hasher,
+            guards: self.readers.each_mut().map(CMapReader::load),
+        }
+    }
+
+    pub fn get<Q>(&mut self, key: &Q) -> Option<CMapReadGuard<'_, K, V, S, Strat, V>>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        self.readers[shard_index(&self.hasher, key, SHARDS)].get(key)
+    }
+}
+
+impl<'a, K, V, S, Strat, const SHARDS: usize> ShardedCMapReadGuard<'a, K, V, S, Strat, SHARDS>
+where
+    Strat: Strategy<ValidationError = Infallible>,
+{
+    pub fn shards(&self) -> &[CMapReadGuard<'a, K, V, S, Strat>; SHARDS] {
+        &self.guards
+    }
+
+    pub fn len(&self) -> usize {
+        self.guards.iter().map(|guard| guard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.guards.iter().all(|guard| guard.is_empty())
+    }
+
+    /// Iterate over all the entries, one shard after another
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.guards.iter().flat_map(|guard| guard.iter())
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Hash + Eq + Borrow<Q>,
+        S: BuildHasher,
+    {
+        self.guards[shard_index(self.hasher, key, SHARDS)].get(key)
+    }
+}
+
+#[test]
+fn sharded_publishes_dirty_shards() {
+    let mut map = ShardedCMap::<i32, i32>::new();
+    let mut reader = map.reader();
+
+    map.insert(1, 10);
+    let shard = map.shard_for(&1);
+    assert!(map.is_dirty(shard));
+    assert_eq!(
+        map.shards_mut()
+            .iter()
+            .filter(|s| !s.unapplied().is_empty())
+            .count(),
+        1
+    );
+    assert!(reader.get(&1).is_none());
+
+    map.publish();
+    assert!(!map.is_dirty(shard));
+    assert_eq!(reader.get(&1).as_deref(), Some(&10));
+
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    map.remove(1);
+    map.publish();
+
+    let guard = reader.load();
+    assert_eq!(guard.len(), 99);
+    assert_eq!(guard.get(&2), Some(&2));
+    assert!(guard.get(&1).is_none());
+
+    let mut keys = guard.iter().map(|(&k, _)| k).collect::<Vec<_>>();
+    keys.sort_unstable();
+    assert_eq!(keys, (0..100).filter(|&i| i != 1).collect::<Vec<_>>());
+    // the keys are spread over all the shards
+    assert!(guard.shards().iter().all(|shard| !shard.is_empty()));
+    drop(guard);
+
+    map.retain(|_, &k, _| k % 2 == 0);
+    map.publish();
+    assert_eq!(reader.load().len(), 50);
+    assert_eq!(map.get(&4), Some(&4));
+    assert!(map.get(&3).is_none());
+}
+
+#[test]
+fn sharded_concurrent_writes() {
+    let map = ShardedCMap::<u32, u32>::new();
+    let mut reader = map.reader();
+
+    // one shard is locked, but writes to the other shards still go through
+    let locked = map.lock_shard(0);
+    std::thread::scope(|s| {
+        for thread in 0..4 {
+            let map = &map;
+            s.spawn(move || {
+                for key in
+                    (thread * 1000..(thread + 1) * 1000).filter(|key| map.shard_for(key) != 0)
+                {
+                    map.insert(key, key);
+                }
+            });
+        }
+    });
+    drop(locked);
+    map.publish();
+
+    let guard = reader.load();
+    assert!(guard.shards()[0].is_empty());
+    assert!(guard.shards()[1..].iter().all(|shard| !shard.is_empty()));
+    assert_eq!(guard.get(&1), (map.shard_for(&1) != 0).then_some(&1));
+    assert_eq!(
+        guard.len(),
+        (0..4000).filter(|key| map.shard_for(key) != 0).count()
+    );
+}
@@ -0,0 +1,222 @@
+//! Watch a single key of a [`CMap`](crate::CMap) for changes
+//!
+//! see [`CMap::watch_key`](crate::CMap::watch_key)
+
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+/// A change to a watched key, see [`KeyWatcher`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyChange<V> {
+    /// the key may have changed, and this is its value in the published map
+    Changed(V),
+    /// the key isn't in the published map anymore
+    Removed,
+}
+
+/// The error returned from a [`KeyWatcher`] once the map was dropped and all changes were seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterGone;
+
+impl std::fmt::Display for WriterGone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the map was dropped, so the key won't change anymore")
+    }
+}
+
+impl std::error::Error for WriterGone {}
+
+/// Waits for publishes which change one key of a map
+///
+/// Changes are coalesced: if the key changed in several publishes since the
+/// last [`poll`](Self::poll) or [`wait`](Self::wait), only the latest change is returned
+pub struct KeyWatcher<V> {
+    /// shared with the map's writer
+    slot: Arc<Slot<V>>,
+}
+
+/// the state shared between a [`KeyWatcher`] and the map's writer
+struct Slot<V> {
+    /// the latest change, and if the writer is gone
+    state: Mutex<State<V>>,
+    /// wakes the watcher when the state changes
+    cv: Condvar,
+}
+
+/// the state of a [`Slot`]
+struct State<V> {
+    /// the latest change which the watcher hasn't seen yet
+    change: Option<KeyChange<V>>,
+    /// true once the map was dropped
+    closed: bool,
+}
+
+impl<V> Slot<V> {
+    /// lock the state, ignoring poison since the state is always consistent
+    fn lock(&self) -> MutexGuard<'_, State<V>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// update the state and wake the watcher
+    fn update(&self, f: impl FnOnce(&mut State<V>)) {
+        f(&mut self.lock());
+        self.cv.notify_all();
+    }
+}
+
+impl<V> KeyWatcher<V> {
+    /// get the latest change, without blocking
+    ///
+    /// Returns `Ok(None)` if the key didn't change since the last call
+    pub fn poll(&mut self) -> Result<Option<KeyChange<V>>, WriterGone> {
+        let mut state = self.slot.lock();
+        match state.change.take() {
+            Some(change) => Ok(Some(change)),
+            None if state.closed => Err(WriterGone),
+            None => Ok(None),
+        }
+    }
+
+    /// block until the key changes, and get the latest change
+    pub fn wait(&mut self) -> Result<KeyChange<V>, WriterGone> {
+        let mut state = self.slot.lock();
+        loop {
+            if let Some(change) = state.change.take() {
+                return Ok(change);
+            }
+
+            if state.closed {
+                return Err(WriterGone);
+            }
+
+            state = self
+                .slot
+                .cv
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// the watchers of a map, grouped by key
+pub(crate) struct Watchers<K, V> {
+    /// the watchers of each watched key
+    keys: HashMap<K, Watched<V>>,
+    /// clones values out of the published map, this is set by the first watcher
+    /// so that the map doesn't need `V: Clone` to publish
+    clone_value: Option<fn(&V) -> V>,
+    /// true if some watched key may be changed by the next publish
+    any_touched: bool,
+}
+
+/// the watchers of a single key
+struct Watched<V> {
+    /// the watchers, shared with each [`KeyWatcher`]
+    slots: Vec<Arc<Slot<V>>>,
+    /// true if the key may be changed by the next publish
+    touched: bool,
+}
+
+impl<K, V> Watchers<K, V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            keys: HashMap::new(),
+            clone_value: None,
+            any_touched: false,
+        }
+    }
+
+    /// true if no key is watched
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// mark every watched key as maybe changed by the next publish
+    pub(crate) fn touch_all(&mut self) {
+        for watched in self.keys.values_mut() {
+            watched.touched = true;
+        }
+        self.any_touched = !self.keys.is_empty();
+    }
+}
+
+impl<K: Hash + Eq, V> Watchers<K, V> {
+    pub(crate) fn watch(&mut self, key: K) -> KeyWatcher<V>
+    where
+        V: Clone,
+    {
+        self.clone_value = Some(V::clone);
+
+        let slot = Arc::new(Slot {
+            state: Mutex::new(State {
+                change: None,
+                closed: false,
+            }),
+            cv: Condvar::new(),
+        });
+
+        let watched = self.keys.entry(key).or_insert_with(|| Watched {
+            slots: Vec::new(),
+            touched: false,
+        });
+        watched.slots.retain(|slot| Arc::strong_count(slot) > 1);
+        watched.slots.push(slot.clone());
+
+        KeyWatcher { slot }
+    }
+
+    /// mark the key as maybe changed by the next publish
+    pub(crate) fn touch<Q>(&mut self, key: &Q)
+    where
+        Q: ?Sized + Hash + Eq,
+        K: Borrow<Q>,
+    {
+        if let Some(watched) = self.keys.get_mut(key) {
+            watched.touched = true;
+            self.any_touched = true;
+        }
+    }
+
+    /// tell the watchers of the touched keys about their values in the published map
+    pub(crate) fn notify<'a>(&mut self, published: impl Fn(&K) -> Option<&'a V>)
+    where
+        V: 'a,
+    {
+        if !core::mem::take(&mut self.any_touched) {
+            return;
+        }
+
+        let Some(clone_value) = self.clone_value else {
+            return;
+        };
+
+        self.keys.retain(|key, watched| {
+            watched.slots.retain(|slot| Arc::strong_count(slot) > 1);
+
+            if core::mem::take(&mut watched.touched) {
+                let value = published(key);
+                for slot in &watched.slots {
+                    let change = match value {
+                        Some(value) => KeyChange::Changed(clone_value(value)),
+                        None => KeyChange::Removed,
+                    };
+                    slot.update(|state| state.change = Some(change));
+                }
+            }
+
+            !watched.slots.is_empty()
+        });
+    }
+}
+
+impl<K, V> Drop for Watchers<K, V> {
+    fn drop(&mut self) {
+        for slot in self.keys.values().flat_map(|watched| &watched.slots) {
+            slot.update(|state| state.closed = true);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! compare the estimated footprint of maps with what they actually allocated
+//!
+//! A counting global allocator keeps track of the bytes allocated by each thread,
+//! so the tests can run in parallel. The estimates should be within 20%
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use cmap::{CMap, CMultiMap};
+
+/// forwards to the system allocator, and counts the bytes which are allocated by each thread
+struct CountingAlloc;
+
+thread_local! {
+    /// the bytes allocated by this thread which weren't freed yet
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+/// add `bytes` to this thread's count, unless the thread is being torn down
+fn count(bytes: isize) {
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + bytes));
+}
+
+// SAFETY: forwards to the system allocator
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        // SAFETY: forwarded from the caller
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        // SAFETY: forwarded from the caller
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// the bytes allocated by this thread while running `f`, which weren't freed yet
+fn allocated_by<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.with(Cell::get);
+    let value = f();
+    let after = ALLOCATED.with(Cell::get);
+    (value, (after - before) as usize)
+}
+
+/// check that the footprint is within 20% of what was allocated
+#[track_caller]
+fn assert_close(estimate: usize, allocated: usize) {
+    let error = estimate.abs_diff(allocated) as f64 / allocated as f64;
+    assert!(
+        error <= 0.2,
+        "estimated {estimate} bytes, but {allocated} bytes were allocated ({:.0}% off)",
+        error * 100.0
+    );
+}
+
+#[test]
+fn map_footprint() {
+    let (map, allocated) = allocated_by(|| {
+        let mut map = CMap::new();
+        for i in 0..10_000u64 {
+            map.insert(i, format!("value {i}"));
+        }
+        // the second swap applies the operations to the other map too
+        map.publish();
+        map.force_publish();
+        map
+    });
+    assert_close(map.memory_footprint().total(), allocated);
+
+    // both maps are counted
+    let footprint = map.memory_footprint();
+    assert!(footprint.buffers > 2 * 10_000 * (8 + 24 + "value 0000".len()));
+}
+
+#[test]
+fn map_with_pending_ops() {
+    let (map, allocated) = allocated_by(|| {
+        let mut map = CMap::new();
+        for i in 0..1000u64 {
+            map.insert(i, i.to_string());
+        }
+        map.publish();
+        for i in 0..1000u64 {
+            map.insert(i, (i + 1).to_string());
+        }
+        map
+    });
+    let footprint = map.memory_footprint();
+    assert!(
+        footprint.op_log > 1000 * size_of::<cmap::map::MapOp<u64, String, cmap::DefaultHasher>>()
+    );
+    assert_close(footprint.total(), allocated);
+}
+
+#[test]
+fn multimap_footprint() {
+    let (map, allocated) = allocated_by(|| {
+        let mut map = CMultiMap::new();
+        for i in 0..10_000u64 {
+            // most keys have a single value, some have many
+            let values = if i % 10 == 0 { 8 } else { 1 };
+            for value in 0..values {
+                map.insert(i, value);
+            }
+        }
+        map.publish();
+        map
+    });
+    assert_close(map.memory_footprint().total(), allocated);
+}
@@ -0,0 +1,128 @@
+#ifndef DBUF_H
+#define DBUF_H
+
+/* This file is generated by cbindgen from dbuf-capi/src/lib.rs, don't edit it by hand */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// The result of the functions which don't return an object
+typedef enum dbuf_status_t {
+  // the call succeeded
+  DBUF_OK = 0,
+  // the last publish is still waiting for readers to release the old half, try again later
+  DBUF_BUSY = 1,
+  // a pointer was null, or the token doesn't belong to the reader
+  DBUF_INVALID_ARGUMENT = 2,
+  // a panic was caught, the object should be destroyed
+  DBUF_PANICKED = 3,
+} dbuf_status_t;
+
+// The writer of a double buffer, see [`dbuf_create`]
+typedef struct dbuf_handle_t dbuf_handle_t;
+
+// A reader of a double buffer, see [`dbuf_reader_create`]
+typedef struct dbuf_reader_t dbuf_reader_t;
+
+// A token for a locked half, from [`dbuf_reader_acquire`]
+//
+// Zero is never a valid token, it's returned when acquiring failed
+typedef uint64_t dbuf_guard_t;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create a double buffer where each half is `half_len` bytes, all zero
+//
+// Returns null if `2 * half_len` overflows or a panic was caught. Destroy it with [`dbuf_destroy`]
+dbuf_handle_t *dbuf_create(size_t half_len);
+
+// Destroy the writer
+//
+// A publish which is still waiting for readers is abandoned. The readers stay valid, and keep
+// reading the last published half, until they are destroyed. Does nothing if `handle` is null.
+//
+// # Safety
+//
+// `handle` must be null or from [`dbuf_create`], and not destroyed already.
+// No pointer from [`dbuf_writer_buf`] may be used afterwards
+void dbuf_destroy(dbuf_handle_t *handle);
+
+// Get the half which the writer may write to
+//
+// On success `*ptr` and `*len` are the writer's half, which stays valid until the next call to
+// [`dbuf_publish`] or [`dbuf_destroy`] with this handle. The half isn't cleared after a publish,
+// it holds what was written to it two publishes ago.
+//
+// Returns [`DBUF_BUSY`] if the last publish is still waiting for readers, then `*ptr` is null and `*len` is 0.
+//
+// # Safety
+//
+// `handle` must be from [`dbuf_create`] and not destroyed, and not be used by another thread during this call.
+// `ptr` and `len` must be null or valid for writes
+dbuf_status_t dbuf_writer_buf(dbuf_handle_t *handle, uint8_t **ptr, size_t *len);
+
+// Publish the writer's half, so that readers see it from their next [`dbuf_reader_acquire`]
+//
+// This never waits for readers. Readers which locked the old half before this call keep reading it,
+// and until they release it, [`dbuf_writer_buf`] and [`dbuf_publish`] return [`DBUF_BUSY`].
+// When this returns [`DBUF_BUSY`] nothing was published.
+//
+// # Safety
+//
+// `handle` must be from [`dbuf_create`] and not destroyed, and not be used by another thread during this call.
+// Pointers from [`dbuf_writer_buf`] may not be used afterwards
+dbuf_status_t dbuf_publish(dbuf_handle_t *handle);
+
+// Create a reader of the double buffer
+//
+// Returns null if `handle` is null or a panic was caught. The reader can outlive the handle.
+// Destroy it with [`dbuf_reader_destroy`]
+//
+// # Safety
+//
+// `handle` must be null or from [`dbuf_create`] and not destroyed, and not be used with a function
+// which takes a mutable pointer to it during this call
+dbuf_reader_t *dbuf_reader_create(const dbuf_handle_t *handle);
+
+// Destroy a reader, releasing its half if it's locked. Does nothing if `reader` is null.
+//
+// # Safety
+//
+// `reader` must be null or from [`dbuf_reader_create`], and not destroyed already.
+// No pointer from [`dbuf_reader_acquire`] may be used afterwards
+void dbuf_reader_destroy(dbuf_reader_t *reader);
+
+// Lock the last published half, and get a token to release it with
+//
+// This never waits for the writer. On success `*ptr` and `*len` are the published half, which stays
+// valid and unchanged until [`dbuf_reader_release`] with the returned token, or [`dbuf_reader_destroy`].
+// While it's locked the writer can't finish its next publish, so release it soon.
+//
+// Each reader can only lock one half at a time. Returns 0 if the reader already locked a half,
+// a pointer is null, or a panic was caught. Then `*ptr` is null and `*len` is 0, if they aren't null
+//
+// # Safety
+//
+// `reader` must be from [`dbuf_reader_create`] and not destroyed, and not be used by another thread
+// during this call. `ptr` and `len` must be null or valid for writes
+dbuf_guard_t dbuf_reader_acquire(dbuf_reader_t *reader, const uint8_t **ptr, size_t *len);
+
+// Release the half which was locked by [`dbuf_reader_acquire`]
+//
+// Returns [`DBUF_INVALID_ARGUMENT`] if `token` isn't the token of the currently locked half,
+// then nothing is released
+//
+// # Safety
+//
+// `reader` must be from [`dbuf_reader_create`] and not destroyed, and not be used by another thread
+// during this call. The pointer from [`dbuf_reader_acquire`] may not be used afterwards
+dbuf_status_t dbuf_reader_release(dbuf_reader_t *reader, dbuf_guard_t token);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DBUF_H */
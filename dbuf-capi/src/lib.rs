@@ -0,0 +1,467 @@
+//! A C API for a double buffer of bytes, for hosts which aren't written in rust
+//!
+//! This only exposes one shape of double buffer: two halves of `half_len` bytes each, with the
+//! [`HazardStrategy`]. The header is `include/dbuf.h`, which is generated by cbindgen (see `cbindgen.toml`).
+//!
+//! There is one writer, the [`dbuf_handle_t`], and any number of readers, each a [`dbuf_reader_t`].
+//!
+//! * the writer fills its half with [`dbuf_writer_buf`], then publishes it with [`dbuf_publish`]
+//! * [`dbuf_publish`] only starts the swap, it never waits for readers. Until every reader which was
+//!   reading the old half released it, [`dbuf_writer_buf`] and [`dbuf_publish`] return [`DBUF_BUSY`]
+//! * a reader locks the published half with [`dbuf_reader_acquire`], which never waits,
+//!   and unlocks it with [`dbuf_reader_release`]
+//!
+//! # Threads
+//!
+//! The handle and each reader may be moved to another thread, but each of them may only be used by one
+//! thread at a time. The functions which take a `const` pointer to a handle may be called at the same
+//! time as each other, but not at the same time as the functions which take a mutable pointer to it.
+//! So create the readers before handing them, or the handle, to other threads.
+//!
+//! # Panics
+//!
+//! No panic unwinds into the host. Every function catches panics, and reports them with [`DBUF_PANICKED`]
+//! (or a null pointer or a zero token where there is no status). After that the object may be unusable,
+//! and it should be destroyed. Running out of memory aborts the process, like it does in rust.
+
+#![forbid(
+    clippy::undocumented_unsafe_blocks,
+    unsafe_op_in_unsafe_fn,
+    clippy::missing_safety_doc
+)]
+#![deny(clippy::missing_docs_in_private_items)]
+#![allow(non_camel_case_types)]
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use dbuf::{
+    delayed::DelayedWriter,
+    ptrs::alloc::{Owned, OwnedPtr},
+    raw::{OwnedReadGuard, Reader, Shared, SliceRawDbuf, Writer},
+    strategy::HazardStrategy,
+};
+
+/// both halves of the double buffer, in one allocation
+type Buffers = Box<SliceRawDbuf<[u8]>>;
+/// the pointer which the writer and all readers share
+type Ptr = OwnedPtr<HazardStrategy, Buffers>;
+
+/// The result of the functions which don't return an object
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum dbuf_status_t {
+    /// the call succeeded
+    DBUF_OK = 0,
+    /// the last publish is still waiting for readers to release the old half, try again later
+    DBUF_BUSY = 1,
+    /// a pointer was null, or the token doesn't belong to the reader
+    DBUF_INVALID_ARGUMENT = 2,
+    /// a panic was caught, the object should be destroyed
+    DBUF_PANICKED = 3,
+}
+
+pub use dbuf_status_t::*;
+
+/// A token for a locked half, from [`dbuf_reader_acquire`]
+///
+/// Zero is never a valid token, it's returned when acquiring failed
+pub type dbuf_guard_t = u64;
+
+/// The writer of a double buffer, see [`dbuf_create`]
+pub struct dbuf_handle_t {
+    /// the writer, and the swap it may have started
+    writer: DelayedWriter<Ptr>,
+}
+
+/// A reader of a double buffer, see [`dbuf_reader_create`]
+pub struct dbuf_reader_t {
+    /// the reader, or the guard which owns it while a half is locked
+    state: ReaderState,
+    /// the token of the current guard, or the last one if no half is locked
+    token: dbuf_guard_t,
+}
+
+/// what a reader is doing
+enum ReaderState {
+    /// no half is locked
+    Idle(Reader<Ptr>),
+    /// a half is locked by this guard
+    Reading(OwnedReadGuard<Ptr, [u8]>),
+    /// a panic was caught while the state was taken out
+    Poisoned,
+}
+
+/// run `f`, and return `on_panic` instead of unwinding into the host if it panics
+fn catch<R>(on_panic: R, f: impl FnOnce() -> R) -> R {
+    // none of the state can be observed in a broken state after a panic: it's either
+    // dropped by the closure, or a reader is left poisoned
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// Create a double buffer where each half is `half_len` bytes, all zero
+///
+/// Returns null if `2 * half_len` overflows or a panic was caught. Destroy it with [`dbuf_destroy`]
+#[no_mangle]
+pub extern "C" fn dbuf_create(half_len: usize) -> *mut dbuf_handle_t {
+    catch(core::ptr::null_mut(), || {
+        let Some(len) = half_len.checked_mul(2) else {
+            return core::ptr::null_mut();
+        };
+        let buffers = SliceRawDbuf::from_box(vec![0; len].into_boxed_slice());
+        let shared = Shared::from_raw_parts(HazardStrategy::new(), buffers);
+        let writer = DelayedWriter::new(Writer::new(Owned::new(shared)));
+        Box::into_raw(Box::new(dbuf_handle_t { writer }))
+    })
+}
+
+/// Destroy the writer
+///
+/// A publish which is still waiting for readers is abandoned. The readers stay valid, and keep
+/// reading the last published half, until they are destroyed. Does nothing if `handle` is null.
+///
+/// # Safety
+///
+/// `handle` must be null or from [`dbuf_create`], and not destroyed already.
+/// No pointer from [`dbuf_writer_buf`] may be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn dbuf_destroy(handle: *mut dbuf_handle_t) {
+    if handle.is_null() {
+        return;
+    }
+
+    // SAFETY: the caller ensures that the handle came from `Box::into_raw` in `dbuf_create`, and is only dropped once
+    let handle = unsafe { Box::from_raw(handle) };
+    catch((), || drop(handle))
+}
+
+/// Get the half which the writer may write to
+///
+/// On success `*ptr` and `*len` are the writer's half, which stays valid until the next call to
+/// [`dbuf_publish`] or [`dbuf_destroy`] with this handle. The half isn't cleared after a publish,
+/// it holds what was written to it two publishes ago.
+///
+/// Returns [`DBUF_BUSY`] if the last publish is still waiting for readers, then `*ptr` is null and `*len` is 0.
+///
+/// # Safety
+///
+/// `handle` must be from [`dbuf_create`] and not destroyed, and not be used by another thread during this call.
+/// `ptr` and `len` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn dbuf_writer_buf(
+    handle: *mut dbuf_handle_t,
+    ptr: *mut *mut u8,
+    len: *mut usize,
+) -> dbuf_status_t {
+    if handle.is_null() || ptr.is_null() || len.is_null() {
+        return DBUF_INVALID_ARGUMENT;
+    }
+
+    // SAFETY: the caller ensures that the handle is valid, and not used by anything else during this call
+    let handle = unsafe { &mut *handle };
+    let empty = core::ptr::slice_from_raw_parts_mut(core::ptr::null_mut(), 0);
+    let (buffer, status) = catch((empty, DBUF_PANICKED), || {
+        match handle.writer.try_writer_mut() {
+            Some(writer) => (writer.split_mut().writer as *mut [u8], DBUF_OK),
+            None => (empty, DBUF_BUSY),
+        }
+    });
+
+    // SAFETY: the caller ensures that `ptr` and `len` are valid for writes
+    unsafe {
+        ptr.write(buffer.cast());
+        len.write(buffer.len());
+    }
+
+    status
+}
+
+/// Publish the writer's half, so that readers see it from their next [`dbuf_reader_acquire`]
+///
+/// This never waits for readers. Readers which locked the old half before this call keep reading it,
+/// and until they release it, [`dbuf_writer_buf`] and [`dbuf_publish`] return [`DBUF_BUSY`].
+/// When this returns [`DBUF_BUSY`] nothing was published.
+///
+/// # Safety
+///
+/// `handle` must be from [`dbuf_create`] and not destroyed, and not be used by another thread during this call.
+/// Pointers from [`dbuf_writer_buf`] may not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn dbuf_publish(handle: *mut dbuf_handle_t) -> dbuf_status_t {
+    if handle.is_null() {
+        return DBUF_INVALID_ARGUMENT;
+    }
+
+    // SAFETY: the caller ensures that the handle is valid, and not used by anything else during this call
+    let handle = unsafe { &mut *handle };
+    catch(DBUF_PANICKED, || {
+        if !handle.writer.is_swap_finished() {
+            return DBUF_BUSY;
+        }
+
+        handle.writer.start_buffer_swap();
+        DBUF_OK
+    })
+}
+
+/// Create a reader of the double buffer
+///
+/// Returns null if `handle` is null or a panic was caught. The reader can outlive the handle.
+/// Destroy it with [`dbuf_reader_destroy`]
+///
+/// # Safety
+///
+/// `handle` must be null or from [`dbuf_create`] and not destroyed, and not be used with a function
+/// which takes a mutable pointer to it during this call
+#[no_mangle]
+pub unsafe extern "C" fn dbuf_reader_create(handle: *const dbuf_handle_t) -> *mut dbuf_reader_t {
+    if handle.is_null() {
+        return core::ptr::null_mut();
+    }
+
+    // SAFETY: the caller ensures that the handle is valid, and only shared during this call
+    let handle = unsafe { &*handle };
+    catch(core::ptr::null_mut(), || {
+        let reader = dbuf_reader_t {
+            state: ReaderState::Idle(handle.writer.reader()),
+            token: 0,
+        };
+        Box::into_raw(Box::new(reader))
+    })
+}
+
+/// Destroy a reader, releasing its half if it's locked. Does nothing if `reader` is null.
+///
+/// # Safety
+///
+/// `reader` must be null or from [`dbuf_reader_create`], and not destroyed already.
+/// No pointer from [`dbuf_reader_acquire`] may be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn dbuf_reader_destroy(reader: *mut dbuf_reader_t) {
+    if reader.is_null() {
+        return;
+    }
+
+    // SAFETY: the caller ensures that the reader came from `Box::into_raw` in `dbuf_reader_create`, and is only dropped once
+    let reader = unsafe { Box::from_raw(reader) };
+    catch((), || drop(reader))
+}
+
+/// Lock the last published half, and get a token to release it with
+///
+/// This never waits for the writer. On success `*ptr` and `*len` are the published half, which stays
+/// valid and unchanged until [`dbuf_reader_release`] with the returned token, or [`dbuf_reader_destroy`].
+/// While it's locked the writer can't finish its next publish, so release it soon.
+///
+/// Each reader can only lock one half at a time. Returns 0 if the reader already locked a half,
+/// a pointer is null, or a panic was caught. Then `*ptr` is null and `*len` is 0, if they aren't null
+///
+/// # Safety
+///
+/// `reader` must be from [`dbuf_reader_create`] and not destroyed, and not be used by another thread
+/// during this call. `ptr` and `len` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn dbuf_reader_acquire(
+    reader: *mut dbuf_reader_t,
+    ptr: *mut *const u8,
+    len: *mut usize,
+) -> dbuf_guard_t {
+    if ptr.is_null() || len.is_null() {
+        return 0;
+    }
+
+    // SAFETY: the caller ensures that `ptr` and `len` are valid for writes
+    unsafe {
+        ptr.write(core::ptr::null());
+        len.write(0);
+    }
+
+    if reader.is_null() {
+        return 0;
+    }
+
+    // SAFETY: the caller ensures that the reader is valid, and not used by anything else during this call
+    let reader = unsafe { &mut *reader };
+    catch(0, || {
+        if !matches!(reader.state, ReaderState::Idle(_)) {
+            return 0;
+        }
+        let ReaderState::Idle(idle) = core::mem::replace(&mut reader.state, ReaderState::Poisoned)
+        else {
+            unreachable!()
+        };
+
+        let guard = idle.into_guard();
+        // SAFETY: the caller ensures that `ptr` and `len` are valid for writes
+        unsafe {
+            ptr.write(guard.as_ptr());
+            len.write(guard.len());
+        }
+
+        reader.state = ReaderState::Reading(guard);
+        reader.token = reader.token.wrapping_add(1).max(1);
+        reader.token
+    })
+}
+
+/// Release the half which was locked by [`dbuf_reader_acquire`]
+///
+/// Returns [`DBUF_INVALID_ARGUMENT`] if `token` isn't the token of the currently locked half,
+/// then nothing is released
+///
+/// # Safety
+///
+/// `reader` must be from [`dbuf_reader_create`] and not destroyed, and not be used by another thread
+/// during this call. The pointer from [`dbuf_reader_acquire`] may not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn dbuf_reader_release(
+    reader: *mut dbuf_reader_t,
+    token: dbuf_guard_t,
+) -> dbuf_status_t {
+    if reader.is_null() {
+        return DBUF_INVALID_ARGUMENT;
+    }
+
+    // SAFETY: the caller ensures that the reader is valid, and not used by anything else during this call
+    let reader = unsafe { &mut *reader };
+    catch(DBUF_PANICKED, || match reader.state {
+        ReaderState::Reading(_) if token == reader.token => {
+            let ReaderState::Reading(guard) =
+                core::mem::replace(&mut reader.state, ReaderState::Poisoned)
+            else {
+                unreachable!()
+            };
+            reader.state = ReaderState::Idle(guard.into_reader());
+            DBUF_OK
+        }
+        ReaderState::Poisoned => DBUF_PANICKED,
+        _ => DBUF_INVALID_ARGUMENT,
+    })
+}
+
+#[test]
+fn test_publish_and_read() {
+    use core::ptr::null_mut;
+
+    // SAFETY: the handle and reader are only used from this thread, and the pointers are
+    // only used while they are valid
+    unsafe {
+        let handle = dbuf_create(4);
+        let reader = dbuf_reader_create(handle);
+
+        let (mut ptr, mut len) = (null_mut(), 0);
+        assert_eq!(dbuf_writer_buf(handle, &mut ptr, &mut len), DBUF_OK);
+        assert_eq!(len, 4);
+        core::slice::from_raw_parts_mut(ptr, len).copy_from_slice(&[1, 2, 3, 4]);
+
+        let (mut read, mut read_len) = (core::ptr::null(), 0);
+        let token = dbuf_reader_acquire(reader, &mut read, &mut read_len);
+        assert_ne!(token, 0);
+        assert_eq!(core::slice::from_raw_parts(read, read_len), [0; 4]);
+        // only one half can be locked at a time
+        assert_eq!(dbuf_reader_acquire(reader, &mut read, &mut read_len), 0);
+        assert!(read.is_null());
+
+        // the reader still holds the old half after publishing, so the writer is busy
+        assert_eq!(dbuf_publish(handle), DBUF_OK);
+        assert_eq!(dbuf_writer_buf(handle, &mut ptr, &mut len), DBUF_BUSY);
+        assert!(ptr.is_null());
+        assert_eq!(dbuf_publish(handle), DBUF_BUSY);
+
+        assert_eq!(
+            dbuf_reader_release(reader, token + 1),
+            DBUF_INVALID_ARGUMENT
+        );
+        assert_eq!(dbuf_reader_release(reader, token), DBUF_OK);
+        assert_eq!(dbuf_reader_release(reader, token), DBUF_INVALID_ARGUMENT);
+        assert_eq!(dbuf_writer_buf(handle, &mut ptr, &mut len), DBUF_OK);
+
+        let token = dbuf_reader_acquire(reader, &mut read, &mut read_len);
+        assert_eq!(core::slice::from_raw_parts(read, read_len), [1, 2, 3, 4]);
+
+        // the reader keeps the buffer alive after the writer is destroyed
+        dbuf_destroy(handle);
+        assert_eq!(core::slice::from_raw_parts(read, read_len), [1, 2, 3, 4]);
+        assert_eq!(dbuf_reader_release(reader, token), DBUF_OK);
+        dbuf_reader_destroy(reader);
+    }
+}
+
+#[test]
+fn test_invalid_arguments() {
+    use core::ptr::null_mut;
+
+    assert!(dbuf_create(usize::MAX).is_null());
+
+    // SAFETY: null pointers are always allowed
+    unsafe {
+        let (mut ptr, mut len) = (null_mut(), 0);
+        assert_eq!(
+            dbuf_writer_buf(null_mut(), &mut ptr, &mut len),
+            DBUF_INVALID_ARGUMENT
+        );
+        assert_eq!(dbuf_publish(null_mut()), DBUF_INVALID_ARGUMENT);
+        assert!(dbuf_reader_create(core::ptr::null()).is_null());
+        let (mut read, mut read_len) = (core::ptr::null(), 0);
+        assert_eq!(dbuf_reader_acquire(null_mut(), &mut read, &mut read_len), 0);
+        assert_eq!(dbuf_reader_release(null_mut(), 1), DBUF_INVALID_ARGUMENT);
+        dbuf_destroy(null_mut());
+        dbuf_reader_destroy(null_mut());
+    }
+
+    assert_eq!(catch(DBUF_PANICKED, || panic!("caught")), DBUF_PANICKED);
+}
+
+#[test]
+fn test_producer_consumer() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// a raw pointer which can be sent to another thread
+    struct SendPtr<T>(*mut T);
+    // SAFETY: the handle and readers are only used by one thread at a time
+    unsafe impl<T> Send for SendPtr<T> {}
+
+    const PUBLISHES: u8 = 100;
+
+    let handle = SendPtr(dbuf_create(1024));
+    // SAFETY: no other thread uses the handle yet
+    let reader = SendPtr(unsafe { dbuf_reader_create(handle.0) });
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        let done = &done;
+        s.spawn(move || {
+            let reader = reader;
+            let mut last = 0;
+            while !done.load(Ordering::Acquire) {
+                let (mut ptr, mut len) = (core::ptr::null(), 0);
+                // SAFETY: the reader is only used by this thread, and the half is only read while it's locked
+                unsafe {
+                    let token = dbuf_reader_acquire(reader.0, &mut ptr, &mut len);
+                    let half = core::slice::from_raw_parts(ptr, len);
+                    assert!(half.iter().all(|&byte| byte == half[0]), "torn read");
+                    assert!(half[0] >= last, "went back in time");
+                    last = half[0];
+                    assert_eq!(dbuf_reader_release(reader.0, token), DBUF_OK);
+                }
+            }
+            // SAFETY: the reader isn't used afterwards
+            unsafe { dbuf_reader_destroy(reader.0) };
+        });
+
+        let handle = handle;
+        for i in 1..=PUBLISHES {
+            let (mut ptr, mut len) = (core::ptr::null_mut(), 0);
+            // SAFETY: the handle is only used by this thread, and the half is only written until the next publish
+            unsafe {
+                while dbuf_writer_buf(handle.0, &mut ptr, &mut len) == DBUF_BUSY {
+                    std::thread::yield_now();
+                }
+                core::slice::from_raw_parts_mut(ptr, len).fill(i);
+                assert_eq!(dbuf_publish(handle.0), DBUF_OK);
+            }
+        }
+        done.store(true, Ordering::Release);
+        // SAFETY: the handle isn't used afterwards
+        unsafe { dbuf_destroy(handle.0) };
+    });
+}
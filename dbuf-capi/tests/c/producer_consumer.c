@@ -0,0 +1,86 @@
+// A producer thread publishes buffers filled with a counter, while a consumer thread
+// checks that every buffer it reads is complete, that the counter never goes back,
+// and that it sees the last publish.
+//
+// build and run it with `just capi-test` from the repository root
+
+#include <pthread.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "dbuf.h"
+
+// like assert, but it's never compiled out
+#define CHECK(cond) \
+  do { \
+    if (!(cond)) { \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+      abort(); \
+    } \
+  } while (0)
+
+#define HALF_LEN 4096
+#define PUBLISHES 10000
+
+static void *consume(void *arg) {
+  dbuf_reader_t *reader = arg;
+  uint32_t last = 0;
+  unsigned long reads = 0;
+
+  while (last != PUBLISHES) {
+    const uint8_t *ptr;
+    size_t len;
+    dbuf_guard_t token = dbuf_reader_acquire(reader, &ptr, &len);
+    CHECK(token != 0);
+    CHECK(len == HALF_LEN);
+
+    uint32_t first;
+    memcpy(&first, ptr, sizeof first);
+    for (size_t i = 0; i < len; i += sizeof first) {
+      uint32_t value;
+      memcpy(&value, ptr + i, sizeof value);
+      CHECK(value == first && "torn read");
+    }
+    CHECK(first >= last && "went back in time");
+    last = first;
+    reads++;
+
+    CHECK(dbuf_reader_release(reader, token) == DBUF_OK);
+  }
+
+  dbuf_reader_destroy(reader);
+  printf("consumer: %lu reads, last value %u\n", reads, last);
+  return NULL;
+}
+
+int main(void) {
+  dbuf_handle_t *handle = dbuf_create(HALF_LEN);
+  CHECK(handle != NULL);
+  // readers are created before the handle is shared with other threads
+  dbuf_reader_t *reader = dbuf_reader_create(handle);
+  CHECK(reader != NULL);
+
+  pthread_t consumer;
+  CHECK(pthread_create(&consumer, NULL, consume, reader) == 0);
+
+  for (uint32_t i = 1; i <= PUBLISHES; i++) {
+    uint8_t *ptr;
+    size_t len;
+    while (dbuf_writer_buf(handle, &ptr, &len) == DBUF_BUSY) {
+      sched_yield();
+    }
+    CHECK(len == HALF_LEN);
+
+    for (size_t j = 0; j < len; j += sizeof i) {
+      memcpy(ptr + j, &i, sizeof i);
+    }
+    CHECK(dbuf_publish(handle) == DBUF_OK);
+  }
+
+  CHECK(pthread_join(consumer, NULL) == 0);
+  dbuf_destroy(handle);
+  printf("producer: %d publishes\n", PUBLISHES);
+  return 0;
+}
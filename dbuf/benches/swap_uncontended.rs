@@ -0,0 +1,42 @@
+//! the cost of a swap which doesn't have to wait for any readers
+//!
+//! This is the hot path of an [`OpWriter`](dbuf::op::OpWriter) which publishes every frame.
+//! Each strategy is measured without any readers, and with a reader which exists but doesn't
+//! hold a read guard, which is the usual state of a published map.
+//!
+//! run with `cargo bench -p dbuf --bench swap_uncontended`
+
+#![cfg(not(feature = "loom"))]
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use dbuf::{
+    ptrs::alloc::{LocalOwned, Owned},
+    raw::Writer,
+    strategy::{HazardStrategy, LocalStrategy, TrackingStrategy},
+};
+
+fn swap_uncontended(c: &mut Criterion) {
+    let mut group = c.benchmark_group("swap_uncontended");
+
+    let mut writer = Writer::new(Owned::<HazardStrategy, _>::from_buffers(0u64, 0));
+    group.bench_function("hazard", |b| b.iter(|| writer.swap_buffers()));
+    let _reader = writer.reader();
+    group.bench_function("hazard/idle reader", |b| b.iter(|| writer.swap_buffers()));
+
+    let mut writer = Writer::new(Owned::<TrackingStrategy, _>::from_buffers(0u64, 0));
+    group.bench_function("tracking", |b| b.iter(|| writer.swap_buffers()));
+    let _reader = writer.reader();
+    group.bench_function("tracking/idle reader", |b| b.iter(|| writer.swap_buffers()));
+
+    let mut writer = Writer::new(LocalOwned::<LocalStrategy, _>::from_buffers(0u64, 0));
+    group.bench_function("local", |b| b.iter(|| writer.try_swap_buffers().unwrap()));
+    let _reader = writer.reader();
+    group.bench_function("local/idle reader", |b| {
+        b.iter(|| writer.try_swap_buffers().unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, swap_uncontended);
+criterion_main!(benches);
@@ -0,0 +1,114 @@
+//! A writer thread which publishes a new config every 100ms, and readers which pick it up
+//!
+//! The readers hold weak references, so they shut down on their own once the writer is dropped.
+//! Each reader checks that it never sees an older config after a newer one, and that it never
+//! sees a config which is half updated.
+//!
+//! run with `cargo run -p dbuf --example config_hot_reload`,
+//! this also runs as part of `cargo test -p dbuf`
+
+use std::{thread, time::Duration};
+
+use dbuf::{
+    ptrs::alloc::Owned,
+    raw::{Reader, Writer},
+    strategy::HazardStrategy,
+};
+
+/// the number of configs the writer publishes after the initial one
+const RELOADS: u64 = 10;
+/// the number of reader threads
+const READERS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Config {
+    /// incremented on each reload
+    version: u64,
+    /// derived from the version, so that a reader can tell if it saw a torn update
+    name: String,
+    /// derived from the version too
+    max_connections: u64,
+}
+
+impl Config {
+    fn new(version: u64) -> Self {
+        Self {
+            version,
+            name: format!("config-v{version}"),
+            max_connections: 16 * (version + 1),
+        }
+    }
+
+    fn is_consistent(&self) -> bool {
+        *self == Self::new(self.version)
+    }
+}
+
+fn main() {
+    let mut writer = Writer::new(Owned::<HazardStrategy, _>::from_buffers(
+        Config::new(0),
+        Config::new(0),
+    ));
+
+    let readers = (0..READERS)
+        .map(|i| {
+            // a weak reader doesn't keep the config alive, so it notices when the writer is gone
+            let mut reader = writer.reader().into_weak();
+            thread::spawn(move || run_reader(i, &mut reader))
+        })
+        .collect::<Vec<_>>();
+
+    let writer_thread = thread::spawn(move || {
+        for version in 1..=RELOADS {
+            thread::sleep(Duration::from_millis(100));
+            // the writer buffer still holds the previous config, so replace all of it
+            *writer.split_mut().writer = Config::new(version);
+            writer.swap_buffers();
+            assert_eq!(writer.split().reader.version, version);
+        }
+        // give the readers time to see the last config,
+        // then dropping the writer is the shutdown signal
+        thread::sleep(Duration::from_millis(100));
+    });
+
+    writer_thread.join().unwrap();
+
+    for (i, reader) in readers.into_iter().enumerate() {
+        let (last_version, reads) = reader.join().unwrap();
+        println!("reader {i}: {reads} reads, last saw version {last_version}");
+        assert_eq!(last_version, RELOADS);
+        assert!(reads > 0);
+    }
+}
+
+/// read the config until the writer is dropped, returns the last version and number of reads
+fn run_reader(
+    i: usize,
+    reader: &mut Reader<dbuf::ptrs::alloc::OwnedWeak<HazardStrategy, dbuf::raw::RawDBuf<Config>>>,
+) -> (u64, u64) {
+    let mut last_version = 0;
+    let mut reads = 0;
+
+    // `checked_get` fails once the writer, and with it the only strong reference, is dropped
+    while let Ok(config) = reader.checked_get() {
+        assert!(config.is_consistent(), "reader {i} saw a torn config");
+        assert!(
+            config.version >= last_version,
+            "reader {i} went back from version {last_version} to {}",
+            config.version
+        );
+        last_version = config.version;
+        reads += 1;
+        drop(config);
+
+        // don't hold the guard while sleeping, otherwise the writer would wait for us
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    (last_version, reads)
+}
+
+#[test]
+fn config_hot_reload() {
+    main()
+}
@@ -0,0 +1,98 @@
+//! A writer which keeps an index of the published buffer, to validate operations before applying them
+//!
+//! The index is derived from the published buffer, so it has to be rebuilt after every swap.
+//! [`Writer::split_owned_cache`] hands out a [`ReadHalfToken`] which is stored next to the index,
+//! and [`Writer::published_with_token`] tells us if the index is still up to date, without
+//! holding a borrow of the writer between operations.
+//!
+//! run with `cargo run -p dbuf --example derived_index`,
+//! this also runs as part of `cargo test -p dbuf`
+
+use std::collections::HashSet;
+
+use dbuf::{
+    ptrs::alloc::{Owned, OwnedPtr},
+    raw::{RawDBuf, ReadHalfToken, Writer},
+    strategy::HazardStrategy,
+};
+
+type UserWriter = Writer<OwnedPtr<HazardStrategy, RawDBuf<Vec<String>>>>;
+
+/// the names in the published buffer, and the token it was built from
+#[derive(Default)]
+struct PublishedIndex {
+    /// the token of the published buffer the index was built from
+    token: Option<ReadHalfToken>,
+    /// the names in the published buffer
+    names: HashSet<String>,
+    /// the number of times the index was rebuilt
+    rebuilds: u32,
+}
+
+impl PublishedIndex {
+    /// rebuild the index if the buffers were swapped since it was built
+    fn refresh(&mut self, writer: &UserWriter) {
+        if let Some(token) = self.token {
+            if writer.published_with_token(token).is_some() {
+                return;
+            }
+        }
+
+        let token = writer.read_half_token();
+        // the token was just created, so it's always valid here
+        let published = writer.published_with_token(token).unwrap();
+        self.names = published.iter().cloned().collect();
+        self.token = Some(token);
+        self.rebuilds += 1;
+    }
+}
+
+/// add a user, unless a user with the same name was already published
+fn add_user(writer: &mut UserWriter, index: &mut PublishedIndex, name: &str) -> bool {
+    index.refresh(writer);
+    if index.names.contains(name) {
+        return false;
+    }
+
+    let (mut users, token) = writer.split_owned_cache();
+    users.push(name.to_string());
+    // changing the writer buffer doesn't invalidate the token, only swapping does
+    assert_eq!(index.token, Some(token));
+    true
+}
+
+fn main() {
+    let mut writer = Writer::new(Owned::<HazardStrategy, _>::from_buffers(
+        Vec::new(),
+        Vec::new(),
+    ));
+    let mut reader = writer.reader();
+    let mut index = PublishedIndex::default();
+
+    assert!(add_user(&mut writer, &mut index, "alice"));
+    assert!(add_user(&mut writer, &mut index, "bob"));
+    // nothing was published yet, so the index is still empty and doesn't catch the duplicate
+    assert!(add_user(&mut writer, &mut index, "alice"));
+    assert_eq!(index.rebuilds, 1);
+
+    writer.swap_buffers();
+    assert_eq!(reader.get().len(), 3);
+    // the new writer buffer is the old published buffer, bring it up to date
+    let split = writer.split_mut();
+    split.writer.clone_from(split.reader);
+
+    // the swap invalidated the token, so the index is rebuilt before validating
+    assert!(!add_user(&mut writer, &mut index, "alice"));
+    assert!(add_user(&mut writer, &mut index, "carol"));
+    assert_eq!(index.rebuilds, 2);
+
+    writer.swap_buffers();
+    assert!(!add_user(&mut writer, &mut index, "carol"));
+    assert_eq!(index.rebuilds, 3);
+    println!("published {:?}", *reader.get());
+}
+
+#[test]
+fn derived_index() {
+    main()
+}
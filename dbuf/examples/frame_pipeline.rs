@@ -0,0 +1,125 @@
+//! A producer which renders frames into a double buffer without ever waiting for the consumer
+//!
+//! The producer uses a [`DelayedWriter`], which starts a swap and comes back later to check if it
+//! finished. If the consumer is still reading the frame the producer wants to overwrite, the
+//! producer drops the new frame instead of blocking. The consumer stalls once, and the frames
+//! produced during the stall are counted as dropped.
+//!
+//! run with `cargo run -p dbuf --example frame_pipeline`,
+//! this also runs as part of `cargo test -p dbuf`
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use dbuf::{
+    delayed::DelayedWriter,
+    frame::{Frame, FramePair},
+    ptrs::alloc::Owned,
+    raw::{DstRawDbuf, Shared, Writer},
+    strategy::HazardStrategy,
+};
+
+/// the number of frames the producer renders
+const FRAMES: u64 = 200;
+/// the number of pixels in each frame
+const PIXELS: usize = 4096;
+/// the time between two frames
+const FRAME_TIME: Duration = Duration::from_millis(1);
+/// the frame the consumer stalls on
+const STALL_AT: u64 = 50;
+/// how long the consumer stalls
+const STALL: Duration = Duration::from_millis(30);
+
+/// the pixel value of the frame `seq`, so the consumer can check that the frame isn't torn
+fn pixel(seq: u64) -> u8 {
+    seq as u8
+}
+
+fn main() {
+    let frame = Frame::new_box(0, &[pixel(0); PIXELS]);
+    let buffers = DstRawDbuf::from_box(FramePair::new(&frame));
+    let mut writer = DelayedWriter::from(Writer::new(Owned::new(Shared::from_raw_parts(
+        HazardStrategy::new(),
+        buffers,
+    ))));
+    let mut reader = writer.reader();
+
+    let (stalled, is_stalled) = mpsc::channel();
+    let consumer = thread::spawn(move || {
+        let mut last_seq = 0;
+        let mut frames_seen = 0;
+        let mut has_stalled = false;
+
+        while last_seq != FRAMES {
+            let frame = reader.get();
+            assert!(frame.pixels.iter().all(|&p| p == pixel(frame.seq)));
+            assert!(frame.seq >= last_seq, "frames must arrive in order");
+            if frame.seq != last_seq {
+                frames_seen += 1;
+            }
+            last_seq = frame.seq;
+
+            if frame.seq >= STALL_AT && !has_stalled {
+                // a consumer which is slow to process a frame, while holding on to it
+                has_stalled = true;
+                stalled.send(()).unwrap();
+                thread::sleep(STALL);
+            }
+        }
+
+        frames_seen
+    });
+
+    let mut dropped = 0;
+    let mut slowest_step = Duration::ZERO;
+    let mut stall_observed = false;
+
+    for seq in 1..=FRAMES {
+        thread::sleep(FRAME_TIME);
+        stall_observed |= is_stalled.try_recv().is_ok();
+        let start = Instant::now();
+
+        if seq == FRAMES {
+            // make sure the last frame gets through, so the consumer can stop
+            writer.finish_swap();
+        }
+
+        // `try_writer_mut` only returns the writer if the last swap finished, so this never blocks
+        match writer.try_writer_mut() {
+            Some(writer) => {
+                let next = writer.split_mut().writer;
+                next.seq = seq;
+                next.pixels.fill(pixel(seq));
+            }
+            None => {
+                // the consumer is still reading the buffer we would render into
+                dropped += 1;
+                continue;
+            }
+        }
+        writer.start_buffer_swap();
+
+        if seq != FRAMES {
+            slowest_step = slowest_step.max(start.elapsed());
+        }
+    }
+
+    let frames_seen = consumer.join().unwrap();
+    println!("dropped {dropped} of {FRAMES} frames, the consumer saw {frames_seen}");
+    println!("the slowest producer step took {slowest_step:?}, the stall was {STALL:?}");
+
+    assert!(stall_observed);
+    // the frames produced while the consumer was stalled on a frame couldn't be rendered
+    assert!(dropped > 0);
+    assert!(frames_seen + dropped <= FRAMES);
+    // the producer never waited for the stalled consumer
+    assert!(slowest_step < STALL);
+}
+
+#[test]
+fn frame_pipeline() {
+    main()
+}
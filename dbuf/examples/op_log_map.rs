@@ -0,0 +1,148 @@
+//! A hand-rolled [`Operation`] over a `Vec`, replayed on both buffers by an [`OpWriter`]
+//!
+//! Every operation is applied to both buffers, once to the writer buffer before it's published,
+//! and once more to the other buffer after the readers left it. `apply` is used for the first
+//! time, which must leave the operation intact, and `apply_last` for the second time, which may
+//! consume it. So long as both are deterministic, the two buffers stay equal. The `Jitter`
+//! operation below isn't deterministic, and shows what goes wrong when it's not.
+//!
+//! run with `cargo run -p dbuf --example op_log_map`,
+//! this also runs as part of `cargo test -p dbuf`
+
+use std::{cmp::Reverse, collections::hash_map::RandomState, hash::BuildHasher, thread};
+
+use dbuf::{
+    op::{Consistency, OpWriter},
+    op_log::Operation,
+    ptrs::alloc::Owned,
+    raw::Writer,
+    strategy::HazardStrategy,
+};
+
+/// an entry in the list, a name and a score
+type Entry = (String, u32);
+
+enum ListOp {
+    /// add an entry to the end of the list
+    Push(String, u32),
+    /// add to the score of every entry with the given name
+    AddScore(String, u32),
+    /// remove the entry at the given index
+    Remove(usize),
+    /// sort by score, highest first, and keep the first `n` entries
+    KeepTop(usize),
+    /// DON'T DO THIS: add a random amount to each score
+    ///
+    /// Each buffer gets a different random amount, so after this is applied to both buffers,
+    /// readers see different scores depending on which buffer is published
+    Jitter,
+}
+
+impl Operation<Vec<Entry>> for ListOp {
+    fn apply(&mut self, buffer: &mut Vec<Entry>) {
+        match self {
+            // the operation is applied again later, so this has to clone the name
+            ListOp::Push(name, score) => buffer.push((name.clone(), *score)),
+            ListOp::AddScore(name, amount) => add_score(buffer, name, *amount),
+            ListOp::Remove(index) => {
+                buffer.remove(*index);
+            }
+            ListOp::KeepTop(n) => keep_top(buffer, *n),
+            ListOp::Jitter => jitter(buffer),
+        }
+    }
+
+    fn apply_last(self, buffer: &mut Vec<Entry>) {
+        match self {
+            // this is the last time, so the name can be moved into the buffer
+            ListOp::Push(name, score) => buffer.push((name, score)),
+            mut op => op.apply(buffer),
+        }
+    }
+}
+
+fn add_score(buffer: &mut [Entry], name: &str, amount: u32) {
+    buffer
+        .iter_mut()
+        .filter(|(entry, _)| entry == name)
+        .for_each(|(_, score)| *score += amount)
+}
+
+fn keep_top(buffer: &mut Vec<Entry>, n: usize) {
+    // a stable sort keeps ties in the same order in both buffers, an unstable sort may not
+    buffer.sort_by_key(|&(_, score)| Reverse(score));
+    buffer.truncate(n);
+}
+
+fn jitter(buffer: &mut [Entry]) {
+    // each `RandomState` has different keys, so this is different each time it's applied
+    let random = RandomState::new();
+    for (name, score) in buffer {
+        *score += (random.hash_one(&*name) % 100) as u32 + 1;
+    }
+}
+
+fn main() {
+    let mut writer = OpWriter::from(Writer::new(Owned::<HazardStrategy, _>::from_buffers(
+        Vec::new(),
+        Vec::new(),
+    )));
+    let mut reader = writer.reader();
+
+    writer.apply(ListOp::Push("alice".into(), 10));
+    writer.apply(ListOp::Push("bob".into(), 30));
+    writer.apply(ListOp::Push("carol".into(), 20));
+    writer.apply(ListOp::Push("dave".into(), 5));
+    // unpublished operations aren't visible to readers
+    assert!(reader.get().is_empty());
+
+    writer.publish();
+    assert_eq!(reader.get().len(), 4);
+
+    writer.apply(ListOp::AddScore("alice".into(), 25));
+    writer.apply(ListOp::Remove(3));
+    writer.apply(ListOp::KeepTop(2));
+    writer.publish();
+
+    let expected = [("alice".to_string(), 35), ("bob".to_string(), 30)];
+    // readers on other threads see the same list
+    let mut other = reader.clone();
+    let seen = thread::spawn(move || other.get().clone()).join().unwrap();
+    assert_eq!(seen, expected);
+
+    // the buffers can't be compared while there are unpublished operations
+    writer.apply(ListOp::AddScore("bob".into(), 5));
+    assert_eq!(writer.verify_buffers_eq(), Consistency::PendingOps);
+    writer.publish();
+    // but once they're published, both buffers got the same operations
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+    assert_eq!(
+        *reader.get(),
+        [("alice".to_string(), 35), ("bob".to_string(), 35)]
+    );
+
+    // now for the nondeterministic operation
+    writer.apply(ListOp::Jitter);
+    writer.publish();
+    let first = reader.get().clone();
+    // `publish` does nothing if there are no new operations, but `swap_buffers` always swaps
+    writer.swap_buffers();
+    let second = reader.get().clone();
+
+    // the two buffers were jittered by different amounts, so the list changes with each swap,
+    // even though no operations were applied in between
+    println!("after jitter, the buffers hold {first:?} and {second:?}");
+    assert_ne!(first, second);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Diverged);
+
+    // the writer buffer can be replaced with a copy of the published buffer to recover
+    writer.resync_writer_buffer(|writer, reader| writer.clone_from(reader));
+    writer.swap_buffers();
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+    assert_eq!(*reader.get(), second);
+}
+
+#[test]
+fn op_log_map() {
+    main()
+}
@@ -0,0 +1,59 @@
+//! Shows the events from the `tracing` feature for a publish which waits on a slow reader
+//!
+//! run with `cargo run -p dbuf --example trace_slow_reader --features tracing`
+
+use std::{sync::mpsc, thread, time::Duration};
+
+use dbuf::{
+    op::OpWriter, op_log::Operation, ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy,
+};
+
+/// push a value onto the buffer
+struct Push(u32);
+
+impl Operation<Vec<u32>> for Push {
+    fn apply(&mut self, buffer: &mut Vec<u32>) {
+        buffer.push(self.0)
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_thread_names(true)
+        .init();
+
+    dbuf::trace::set_slow_writer_threshold(Duration::from_millis(20));
+    dbuf::trace::set_slow_guard_threshold(Duration::from_millis(20));
+
+    let mut writer = OpWriter::from(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        Vec::new(),
+        Vec::new(),
+    )));
+    let mut reader = writer.reader();
+
+    let (locked, is_locked) = mpsc::channel();
+    let slow_reader = thread::Builder::new()
+        .name("slow reader".into())
+        .spawn(move || {
+            let guard = reader.get();
+            locked.send(()).unwrap();
+            // a reader which holds on to its guard while doing something slow
+            thread::sleep(Duration::from_millis(100));
+            guard.len()
+        })
+        .unwrap();
+
+    is_locked.recv().unwrap();
+
+    // the first publish swaps away from the reader immediately
+    writer.apply(Push(1));
+    writer.apply(Push(2));
+    writer.publish();
+
+    // but the second publish has to wait for the reader to leave the old buffer
+    writer.apply(Push(3));
+    writer.publish();
+
+    slow_reader.join().unwrap();
+}
@@ -0,0 +1,267 @@
+//! A value derived from the published buffer, which is only recomputed after a publish
+//!
+//! A common reader pattern is to derive something from the published buffer, like a routing
+//! table from a config, and reuse it until the next publish. [`CachedProjection`] does this
+//! by remembering which snapshot the value was computed from (see [`Reader::pin_epoch`]), and
+//! only taking a read guard and recomputing the value once a different snapshot is published.
+//!
+//! The value is owned, so no read guard is held after [`get`](CachedProjection::get) returns,
+//! and the writer can keep publishing.
+//!
+//! If the writer starts a publish while the value is being computed, the value is still
+//! returned, and it's labeled with the snapshot it was computed from. So the value may be
+//! behind by the publishes which started while it was computed, and the next call to
+//! [`get`](CachedProjection::get) recomputes it once, no matter how many publishes there were.
+//!
+//! ```
+//! use dbuf::{cached::CachedProjection, ptrs::alloc::Owned, raw::Writer, strategy::HazardStrategy};
+//!
+//! let mut writer = Writer::new(Owned::<HazardStrategy, _>::from_buffers(vec![1, 2], vec![1, 2]));
+//! let mut total = CachedProjection::new(writer.reader(), |buffer: &Vec<i32>| buffer.iter().sum::<i32>());
+//! assert_eq!(*total.get(), 3);
+//!
+//! writer.split_mut().writer.push(3);
+//! writer.swap_buffers();
+//! assert_eq!(*total.get(), 6);
+//! ```
+
+use crate::{
+    interface::{BufferOf, RawBuffersOf, StrongOf, WeakRef},
+    raw::{EpochPin, ReadError, Reader},
+};
+
+/// the buffer type read by a reader with the weak ref `W`
+type ReaderBufferOf<W> = BufferOf<RawBuffersOf<StrongOf<W>>>;
+
+/// A value derived from the published buffer, see the [module docs](self)
+pub struct CachedProjection<W: WeakRef, T, F = fn(&ReaderBufferOf<W>) -> T> {
+    /// the reader the value is computed from
+    reader: Reader<W>,
+    /// computes the value from the published buffer
+    project: F,
+    /// the value, and the snapshot it was computed from
+    cached: Option<(EpochPin, T)>,
+}
+
+impl<W: WeakRef, T, F: FnMut(&ReaderBufferOf<W>) -> T> CachedProjection<W, T, F> {
+    /// Create a cache of `project` applied to the published buffer
+    ///
+    /// The value is computed on the first call to [`get`](Self::get)
+    pub fn new(reader: Reader<W>, project: F) -> Self {
+        Self {
+            reader,
+            project,
+            cached: None,
+        }
+    }
+
+    /// get the value for the published buffer, recomputing it if the buffer was published since it was last computed
+    pub fn get(&mut self) -> &T
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+    {
+        match refresh(&mut self.reader, &mut self.cached, &mut self.project) {
+            Ok(value) => value,
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// get the value for the published buffer, see [`get`](Self::get)
+    ///
+    /// This fails with [`ReadError::WriterGone`] if the reader's weak ref can't be upgraded, then the cached value is kept
+    pub fn try_get(&mut self) -> Result<&T, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        refresh(&mut self.reader, &mut self.cached, &mut self.project).map_err(Into::into)
+    }
+}
+
+impl<W: WeakRef, T, F> CachedProjection<W, T, F> {
+    /// get the value for the published buffer, recomputing it with `f` instead of the
+    /// projection this was created with
+    ///
+    /// This is useful if the projection needs something from the caller's environment.
+    /// `f` is only called if the value needs to be recomputed
+    pub fn get_with(&mut self, mut f: impl FnMut(&ReaderBufferOf<W>) -> T) -> &T
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+    {
+        match refresh(&mut self.reader, &mut self.cached, &mut f) {
+            Ok(value) => value,
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// get the value for the published buffer, recomputing it with `f`, see [`get_with`](Self::get_with)
+    ///
+    /// This fails with [`ReadError::WriterGone`] if the reader's weak ref can't be upgraded, then the cached value is kept
+    pub fn try_get_with(
+        &mut self,
+        mut f: impl FnMut(&ReaderBufferOf<W>) -> T,
+    ) -> Result<&T, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        refresh(&mut self.reader, &mut self.cached, &mut f).map_err(Into::into)
+    }
+
+    /// the cached value, without checking if it's still current
+    pub fn cached(&self) -> Option<&T> {
+        self.cached.as_ref().map(|(_, value)| value)
+    }
+
+    /// forget the cached value, so that it's recomputed on the next get
+    pub fn invalidate(&mut self) {
+        self.cached = None;
+    }
+
+    /// the reader the value is computed from
+    pub fn reader(&self) -> &Reader<W> {
+        &self.reader
+    }
+
+    /// get the reader back, dropping the cached value
+    pub fn into_reader(self) -> Reader<W> {
+        self.reader
+    }
+}
+
+/// recompute the cached value if a different snapshot was published since it was computed
+fn refresh<'a, W: WeakRef, T>(
+    reader: &mut Reader<W>,
+    cached: &'a mut Option<(EpochPin, T)>,
+    project: &mut impl FnMut(&ReaderBufferOf<W>) -> T,
+) -> Result<&'a T, W::UpgradeError> {
+    loop {
+        let pin = reader.try_pin_epoch_raw()?;
+        if matches!(cached, Some((cached, _)) if *cached == pin) {
+            break;
+        }
+
+        // a publish may start between pinning the snapshot and locking it,
+        // then pin the new snapshot instead of computing a value which is already stale
+        if let Ok(guard) = reader.try_get_pinned_raw(&pin)? {
+            let value = project(&guard);
+            drop(guard);
+            *cached = Some((pin, value));
+            break;
+        }
+    }
+
+    match cached {
+        Some((_, value)) => Ok(value),
+        None => unreachable!("the value was just computed"),
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_cached_projection() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+    use core::cell::Cell;
+
+    let mut writer = crate::raw::Writer::new(Owned::<HazardStrategy, _>::from_buffers(1, 1));
+    let runs = Cell::new(0);
+    let mut cached = CachedProjection::new(writer.reader(), |&value: &i32| {
+        runs.set(runs.get() + 1);
+        value * 10
+    });
+
+    // repeated gets without publishes don't recompute the value
+    for _ in 0..3 {
+        assert_eq!(*cached.get(), 10);
+    }
+    assert_eq!(runs.get(), 1);
+
+    // changing the writer buffer doesn't either
+    *writer.split_mut().writer = 2;
+    assert_eq!(*cached.get(), 10);
+    assert_eq!(runs.get(), 1);
+
+    // one recomputation after several publishes
+    writer.swap_buffers();
+    writer.swap_buffers();
+    *writer.split_mut().writer = 3;
+    writer.swap_buffers();
+    assert_eq!(*cached.get(), 30);
+    assert_eq!(*cached.get(), 30);
+    assert_eq!(runs.get(), 2);
+
+    cached.invalidate();
+    assert_eq!(cached.cached(), None);
+    assert_eq!(*cached.get(), 30);
+    assert_eq!(runs.get(), 3);
+
+    // `get_with` only runs if the value is stale
+    let offset = 5;
+    assert_eq!(*cached.get_with(|&value| value + offset), 30);
+    writer.swap_buffers();
+    assert_eq!(*cached.get_with(|&value| value + offset), 6);
+    assert_eq!(runs.get(), 3);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_cached_projection_publish_while_computing() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+    use core::cell::Cell;
+
+    let mut writer = crate::raw::Writer::new(Owned::<HazardStrategy, _>::from_buffers(1, 1));
+    *writer.split_mut().writer = 2;
+    let mut cached = CachedProjection::new(writer.reader(), |&value: &i32| value);
+    let runs = Cell::new(0);
+    let mut swap = None;
+
+    // the writer starts a publish while the first value is computed
+    let value = *cached.get_with(|&value| {
+        runs.set(runs.get() + 1);
+        // SAFETY: the swap is finished below, before the writer is used again
+        swap = Some(unsafe { writer.try_start_buffer_swap() }.unwrap());
+        value
+    });
+    // the value is from the snapshot which was pinned when it was computed
+    assert_eq!(value, 1);
+
+    let mut swap = swap.unwrap();
+    // SAFETY: the swap was started by this writer
+    unsafe { writer.finish_swap(&mut swap) };
+    *writer.split_mut().writer = 3;
+    writer.swap_buffers();
+    *writer.split_mut().writer = 4;
+    writer.swap_buffers();
+
+    // so it's recomputed exactly once afterwards
+    let mut get = || {
+        *cached.get_with(|&value| {
+            runs.set(runs.get() + 1);
+            value
+        })
+    };
+    assert_eq!(get(), 4);
+    assert_eq!(get(), 4);
+    assert_eq!(runs.get(), 2);
+}
+
+#[test]
+#[cfg(feature = "std")]
+// `OwnedWithWeak` needs `Weak`, which loom doesn't have
+#[cfg(not(feature = "loom"))]
+fn test_cached_projection_weak() {
+    use crate::{ptrs::alloc::OwnedWithWeak, strategy::TrackingStrategy};
+
+    let mut writer =
+        crate::raw::Writer::new(OwnedWithWeak::<TrackingStrategy, _>::from_buffers(1, 1));
+    let mut cached = CachedProjection::new(writer.reader(), |&value: &i32| value);
+
+    assert_eq!(cached.try_get().ok(), Some(&1));
+    *writer.split_mut().writer = 2;
+    writer.swap_buffers();
+    drop(writer);
+
+    // the cached value is kept after the double buffer is dropped
+    assert!(cached.try_get().is_err());
+    assert_eq!(cached.cached(), Some(&1));
+}
@@ -0,0 +1,199 @@
+//! Diagnostics which are too slow to leave on outside of debugging
+//!
+//! With the `debug-checks` feature, a strategy can record a [`Backtrace`] and the [`ThreadId`] each time
+//! a read guard is acquired, and keep them until the guard is dropped. When a swap is stuck, the backtraces
+//! of the readers which are blocking it show which code path is holding (or leaked) a guard, and on which thread,
+//! see [`Writer::debug_guard_backtrace`](crate::raw::Writer::debug_guard_backtrace) and
+//! [`Writer::debug_guard_thread`](crate::raw::Writer::debug_guard_thread).
+//!
+//! Capturing is off by default. Turn it on for every strategy with [`capture_backtraces`], or for
+//! a single strategy with [`HazardStrategy::capture_backtraces`](crate::strategy::HazardStrategy::capture_backtraces).
+//! Only the [`HazardStrategy`](crate::strategy::HazardStrategy) records backtraces for now.
+//! Without the feature, none of this code exists.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::{
+    backtrace::Backtrace,
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread::{self, ThreadId},
+};
+
+/// true if every strategy should record backtraces
+static CAPTURE_ALL: AtomicBool = AtomicBool::new(false);
+
+/// Record a backtrace for each read guard acquired from any strategy
+///
+/// This is global for all double buffers, and is off by default
+pub fn capture_backtraces(on: bool) {
+    CAPTURE_ALL.store(on, Ordering::Relaxed)
+}
+
+/// the backtraces of the read guards of one strategy, keyed by the reader's id
+/// (see [`ActiveReaderInfo::id`](crate::interface::ActiveReaderInfo::id))
+pub(crate) struct GuardBacktraces {
+    /// true if this strategy should record backtraces, even if [`CAPTURE_ALL`] is off
+    enabled: AtomicBool,
+    /// the number of recorded backtraces, so that dropping a guard doesn't lock when there are none
+    recorded: AtomicUsize,
+    /// the backtraces of the read guards which are currently held
+    traces: Mutex<BTreeMap<usize, GuardRecord>>,
+}
+
+/// where and on which thread a read guard was acquired
+struct GuardRecord {
+    /// where the read guard was acquired
+    backtrace: Arc<Backtrace>,
+    /// the thread which acquired the read guard
+    thread: ThreadId,
+}
+
+impl GuardBacktraces {
+    /// no backtraces, and capturing is off
+    pub(crate) const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            recorded: AtomicUsize::new(0),
+            traces: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// lock the backtraces, ignoring poison since they're only diagnostics
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<usize, GuardRecord>> {
+        self.traces.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// turn capturing on or off for this strategy
+    pub(crate) fn set_enabled(&self, on: bool) {
+        self.enabled.store(on, Ordering::Relaxed)
+    }
+
+    /// true if capturing was turned on for this strategy
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// record where the read guard of reader `id` was acquired, if capturing is on
+    ///
+    /// this must be called after the reader acquired its slot, and before it's released
+    pub(crate) fn record(&self, id: usize) {
+        if !self.enabled.load(Ordering::Relaxed) && !CAPTURE_ALL.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let record = GuardRecord {
+            backtrace: Arc::new(Backtrace::force_capture()),
+            thread: thread::current().id(),
+        };
+        if self.lock().insert(id, record).is_none() {
+            self.recorded.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// forget the backtrace of reader `id`, before its slot is released
+    pub(crate) fn clear(&self, id: usize) {
+        if self.recorded.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        if self.lock().remove(&id).is_some() {
+            self.recorded.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// the backtrace of reader `id`, if it was recorded
+    pub(crate) fn get(&self, id: usize) -> Option<Arc<Backtrace>> {
+        self.lock().get(&id).map(|record| record.backtrace.clone())
+    }
+
+    /// the thread which holds the read guard of reader `id`, if it was recorded
+    pub(crate) fn thread(&self, id: usize) -> Option<ThreadId> {
+        self.lock().get(&id).map(|record| record.thread)
+    }
+}
+
+/// a leaked guard can be traced back to the function which acquired it
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_leaked_guard_backtrace() {
+    use crate::{
+        delayed::DelayedWriter,
+        ptrs::alloc::Owned,
+        raw::{RawDBuf, Shared, Writer},
+        strategy::HazardStrategy,
+    };
+
+    let strategy = HazardStrategy::new();
+    strategy.capture_backtraces(true);
+    let mut writer = DelayedWriter::new(Writer::new(Owned::new(Shared::from_raw_parts(
+        strategy,
+        RawDBuf::new(0, 0),
+    ))));
+    let mut reader = writer.reader();
+
+    core::mem::forget(reader.get());
+    // the guard is in the published buffer, so this swap can't finish
+    writer.start_buffer_swap();
+    let mut pauses = 0;
+    assert!(!writer.finish_swap_until(|| {
+        pauses += 1;
+        pauses < 4
+    }));
+
+    let readers = writer.debug_active_readers();
+    assert_eq!(readers.len(), 1);
+    let backtrace = writer
+        .debug_guard_backtrace(&readers[0])
+        .expect("the backtrace wasn't recorded");
+    let backtrace = std::format!("{backtrace}");
+    assert!(
+        backtrace.contains("test_leaked_guard_backtrace"),
+        "{backtrace}"
+    );
+}
+
+/// the stuck swap report says which thread holds the blocking guard, and where it was acquired
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_stuck_swap_report() {
+    use crate::{
+        raw::{RawDBuf, Shared, Writer},
+        strategy::HazardStrategy,
+    };
+    use std::sync::mpsc;
+
+    let strategy = HazardStrategy::new();
+    strategy.capture_backtraces(true);
+    let mut shared = Shared::from_raw_parts(strategy, RawDBuf::new(0, 0));
+    let mut writer = Writer::new(&mut shared);
+    let mut reader = writer.reader();
+
+    let (held_tx, held_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        let holder = s.spawn(move || {
+            let _guard = reader.get();
+            held_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+
+        held_rx.recv().unwrap();
+
+        // SAFETY: the swap is finished below
+        let mut swap = unsafe { writer.try_start_buffer_swap() }.unwrap();
+        writer
+            .block_on_swap_reporting(&mut swap, 1, |readers| {
+                assert_eq!(readers.len(), 1);
+                assert_eq!(readers[0].thread, Some(holder.thread().id()));
+                let backtrace = readers[0]
+                    .backtrace
+                    .as_ref()
+                    .expect("the backtrace wasn't recorded");
+                let backtrace = std::format!("{backtrace}");
+                assert!(backtrace.contains("test_stuck_swap_report"), "{backtrace}");
+                release_tx.send(()).unwrap();
+            })
+            .unwrap();
+    });
+}
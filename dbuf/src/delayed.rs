@@ -1,10 +1,14 @@
 //! A delayed writer which allowed you to safely start a swap
 
-use std::ops::Deref;
+use core::ops::Deref;
+#[cfg(feature = "alloc")]
+use std::boxed::Box;
 
+#[cfg(feature = "alloc")]
+use crate::interface::{BufferOf, RawBuffersOf};
 use crate::{
     interface::{CaptureOf, Strategy, StrategyOf, StrongRef, ValidationErrorOf, WriterTag},
-    raw::{Swap, Writer},
+    raw::{Swap, SwapStats, Writer},
 };
 
 /// A delayed writer which allows safely starting swaps
@@ -13,8 +17,30 @@ pub struct DelayedWriter<S, W = WriterTag<StrategyOf<S>>, C = CaptureOf<Strategy
     writer: Writer<S, W>,
     /// a potentially in-progress swap
     swap: Option<Swap<C>>,
+    /// called with the writer buffer after each finished swap, see [`DelayedWriter::set_post_swap`]
+    ///
+    /// this is boxed again, so that it's a thin pointer, and doesn't make every writer larger
+    #[cfg(feature = "alloc")]
+    post_swap: Option<Box<PostSwap<S, W>>>,
+    /// true if the in-progress swap swapped the buffers, so the post swap hook should run once it's finished
+    swapped: bool,
+    /// true if the in-progress swap was reverted, see [`DelayedWriter::revert_pending_swap`]
+    reverted: bool,
 }
 
+/// The error from [`DelayedWriter::revert_pending_swap`] when there is no swap to revert
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoPendingSwap;
+
+/// a post swap hook, see [`DelayedWriter::set_post_swap`]
+#[cfg(feature = "alloc")]
+#[allow(clippy::type_complexity)]
+struct PostSwap<S, W>(Box<dyn FnMut(&mut Writer<S, W>) + Send>);
+
+// SAFETY: the hook is only called through `&mut PostSwap`, so a shared `&PostSwap` can't do anything
+#[cfg(feature = "alloc")]
+unsafe impl<S, W> Sync for PostSwap<S, W> {}
+
 impl<S: StrongRef> From<Writer<S>> for DelayedWriter<S> {
     fn from(writer: Writer<S>) -> Self {
         Self::new(writer)
@@ -24,7 +50,89 @@ impl<S: StrongRef> From<Writer<S>> for DelayedWriter<S> {
 impl<S: StrongRef> DelayedWriter<S> {
     /// create a new delayed writer
     pub const fn new(writer: Writer<S>) -> Self {
-        DelayedWriter { writer, swap: None }
+        DelayedWriter {
+            writer,
+            swap: None,
+            #[cfg(feature = "alloc")]
+            post_swap: None,
+            swapped: false,
+            reverted: false,
+        }
+    }
+
+    /// Call `f` with the writer buffer after each finished swap
+    ///
+    /// After a swap, the writer buffer is the one readers were just using, so its cache lines
+    /// are likely in other cores' caches, and the first writes to it are slow. `f` runs exactly
+    /// once per finished swap, before the writer can be used again, so it can bring the buffer
+    /// back into this core's cache. This only replaces the previous hook.
+    ///
+    /// Quiescence (see [`DelayedWriter::start_quiescence`]) doesn't swap the buffers,
+    /// so it doesn't call `f`.
+    ///
+    /// For byte buffers, use [`touch_pages`](crate::warm::touch_pages). For maps, iterate over
+    /// the entries which will be written to next:
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use dbuf::{delayed::DelayedWriter, ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy};
+    ///
+    /// let mut writer = DelayedWriter::new(Writer::new(
+    ///     Owned::<TrackingStrategy, _>::from_buffers(HashMap::<u32, u64>::new(), HashMap::new()),
+    /// ));
+    ///
+    /// writer.set_post_swap(|map: &mut HashMap<u32, u64>| {
+    ///     for value in map.values_mut() {
+    ///         // SAFETY: `value` is a valid `&mut u64`
+    ///         unsafe { core::ptr::write_volatile(value, core::ptr::read_volatile(value)) }
+    ///     }
+    /// });
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn set_post_swap(
+        &mut self,
+        mut f: impl FnMut(&mut BufferOf<RawBuffersOf<S>>) + Send + 'static,
+    ) {
+        self.post_swap = Some(Box::new(PostSwap(Box::new(
+            move |writer: &mut Writer<S>| f(writer.split_mut().writer),
+        ))));
+    }
+
+    /// remove the hook set by [`DelayedWriter::set_post_swap`]
+    #[cfg(feature = "alloc")]
+    pub fn clear_post_swap(&mut self) {
+        self.post_swap = None;
+    }
+
+    /// forget the finished swap, and call the post swap hook if it swapped the buffers
+    fn swap_finished(&mut self) {
+        self.swap = None;
+
+        if core::mem::take(&mut self.swapped) {
+            #[cfg(feature = "alloc")]
+            if let Some(post_swap) = &mut self.post_swap {
+                (post_swap.0)(&mut self.writer)
+            }
+        }
+    }
+
+    /// the readers captured by a reverted swap have exited, so start waiting for
+    /// the readers which may have started reading the writer buffer while it was published
+    fn reverted_swap_finished(&mut self) {
+        self.swap = None;
+        self.reverted = false;
+
+        // SAFETY: the last swap is finished
+        let Ok(mut swap) = (unsafe { self.writer.try_start_quiescence() }) else {
+            unreachable!("only swaps of strategies which can't fail validation can be reverted")
+        };
+        swap.defuse_mut();
+        self.swap = Some(swap);
+    }
+
+    /// true if a swap which swapped the buffers is in progress, this is false for a quiescence or a reverted swap
+    pub(crate) fn has_pending_swap(&self) -> bool {
+        self.swap.is_some() && self.swapped
     }
 
     /// try to swap the buffers
@@ -51,14 +159,44 @@ impl<S: StrongRef> DelayedWriter<S> {
         }
 
         // SAFETY: DelayedWriter doesn't expose a `&mut Writer` if there is an in progress swap
-        let swap = unsafe { self.writer.try_start_buffer_swap()? };
+        let mut swap = unsafe { self.writer.try_start_buffer_swap()? };
 
+        // the delayed writer finishes its own swaps, or abandons them when it's dropped
+        swap.defuse_mut();
         // SAFETY: it's always safe to write to a `&mut _`
         unsafe { core::ptr::write(&mut self.swap, Some(swap)) };
+        self.swapped = true;
 
         Ok(())
     }
 
+    /// finish the last swap, and start a buffer swap where `flip` flips the flag for which buffer is the writer buffer
+    ///
+    /// see [`group`](crate::group)
+    ///
+    /// # Safety
+    ///
+    /// `flip` must flip the writer's flag, and nothing may flip it at the same time
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn start_buffer_swap_with(&mut self, flip: impl FnOnce())
+    where
+        StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        self.finish_swap();
+
+        // SAFETY: DelayedWriter doesn't expose a `&mut Writer` if there is an in progress swap,
+        // and the caller ensures that `flip` flips the flag
+        let mut swap = match unsafe { self.writer.try_start_buffer_swap_with(flip) } {
+            Ok(swap) => swap,
+            Err(inf) => match inf {},
+        };
+
+        swap.defuse_mut();
+        // SAFETY: it's always safe to write to a `&mut _`
+        unsafe { core::ptr::write(&mut self.swap, Some(swap)) };
+        self.swapped = true;
+    }
+
     /// start a buffer swap
     pub fn start_buffer_swap(&mut self)
     where
@@ -70,6 +208,99 @@ impl<S: StrongRef> DelayedWriter<S> {
         }
     }
 
+    /// Undo the swap which is in progress, so readers go back to the previously published buffer
+    ///
+    /// This is for a publish which turns out to be bad right after it was started, but the swap
+    /// doesn't need to be finished yet. The writer buffer is the one which was just published,
+    /// so it still has the bad changes.
+    ///
+    /// Readers which started a read guard while the swap was in progress may have seen the
+    /// reverted buffer, and may still be reading it. So the writer buffer is only available again
+    /// once every reader captured by the swap has exited, and after that, every reader which
+    /// holds a read guard at that point. Both are waited on like any other swap, by
+    /// [`finish_swap`](DelayedWriter::finish_swap) and [`is_swap_finished`](DelayedWriter::is_swap_finished),
+    /// and a swap started in the meantime is ignored like any other swap started while one is in progress.
+    ///
+    /// Returns an error if there is no swap in progress, or it was already reverted.
+    /// [`start_quiescence`](DelayedWriter::start_quiescence) doesn't swap the buffers, so it can't be reverted.
+    ///
+    /// ```
+    /// use dbuf::{delayed::DelayedWriter, ptrs::alloc::Owned, raw::Writer, strategy::HazardStrategy};
+    ///
+    /// let mut writer = DelayedWriter::new(Writer::new(Owned::<HazardStrategy, _>::from_buffers(1, 1)));
+    /// let mut canary = writer.reader();
+    /// let mut reader = writer.reader();
+    ///
+    /// *writer.finish_swap().split_mut().writer = 2;
+    /// writer.start_buffer_swap();
+    /// let canary = canary.get();
+    /// assert_eq!(*canary, 2);
+    ///
+    /// // the canary found a problem with the new version
+    /// writer.revert_pending_swap().unwrap();
+    /// assert_eq!(*reader.get(), 1);
+    ///
+    /// // the canary may still be reading the reverted buffer
+    /// assert!(writer.try_writer_mut().is_none());
+    /// drop(canary);
+    /// assert_eq!(*writer.try_writer_mut().unwrap().split().writer, 2);
+    /// ```
+    pub fn revert_pending_swap(&mut self) -> Result<(), NoPendingSwap>
+    where
+        StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        if self.swap.is_none() || !self.swapped {
+            return Err(NoPendingSwap);
+        }
+
+        // SAFETY: the swap flipped the buffers, and it's only finished once the readers
+        // which may be reading the writer buffer have exited
+        unsafe { self.writer.revert_buffer_swap() };
+        // the buffers are back where they were, so there is no swap for the post swap hook
+        self.swapped = false;
+        self.reverted = true;
+
+        Ok(())
+    }
+
+    /// try to start waiting for every reader which currently holds a read guard, without swapping the buffers
+    ///
+    /// if a swap is in progress, this finishes it first. See [`Writer::try_wait_for_quiescence`]
+    pub fn try_start_quiescence(&mut self) -> Result<(), ValidationErrorOf<StrategyOf<S>>> {
+        self.finish_swap();
+
+        // SAFETY: DelayedWriter doesn't expose a `&mut Writer` if there is an in progress swap
+        let mut swap = unsafe { self.writer.try_start_quiescence()? };
+
+        // the delayed writer finishes its own swaps, or abandons them when it's dropped
+        swap.defuse_mut();
+        // SAFETY: it's always safe to write to a `&mut _`
+        unsafe { core::ptr::write(&mut self.swap, Some(swap)) };
+
+        Ok(())
+    }
+
+    /// start waiting for every reader which currently holds a read guard, without swapping the buffers
+    ///
+    /// see [`DelayedWriter::try_start_quiescence`]
+    pub fn start_quiescence(&mut self)
+    where
+        StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        match self.try_start_quiescence() {
+            Ok(()) => (),
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// check if every read guard which was acquired before the last
+    /// [`start_quiescence`](DelayedWriter::start_quiescence) has been dropped
+    ///
+    /// this is the same as [`is_swap_finished`](DelayedWriter::is_swap_finished)
+    pub fn is_quiescent(&mut self) -> bool {
+        self.is_swap_finished()
+    }
+
     /// get a mutable reference to the inner writer if the swap is finished
     pub fn try_writer_mut(&mut self) -> Option<&mut Writer<S>> {
         if self.is_swap_finished() {
@@ -81,13 +312,51 @@ impl<S: StrongRef> DelayedWriter<S> {
 
     /// finish an in progress buffer swap
     pub fn finish_swap(&mut self) -> &mut Writer<S> {
-        if let Some(ref mut swap) = self.swap {
+        self.finish_swap_with_stats();
+        &mut self.writer
+    }
+
+    /// finish an in progress buffer swap, and report how long it had to wait for readers
+    ///
+    /// if there is no swap in progress this returns [`SwapStats::IMMEDIATE`]
+    pub fn finish_swap_with_stats(&mut self) -> SwapStats {
+        let mut stats = SwapStats::IMMEDIATE;
+
+        while let Some(ref mut swap) = self.swap {
             // SAFETY: this writer created the swap
-            unsafe { self.writer.finish_swap(swap) }
-            self.swap = None;
+            let swap_stats = unsafe { self.writer.finish_swap(swap) };
+            stats.pauses += swap_stats.pauses;
+            stats.finished_immediately &= swap_stats.finished_immediately;
+
+            if self.reverted {
+                self.reverted_swap_finished();
+            } else {
+                self.swap_finished();
+            }
         }
 
-        &mut self.writer
+        stats
+    }
+
+    /// finish an in progress buffer swap, or give up once `should_continue` returns false
+    ///
+    /// returns true if there is no swap in progress anymore,
+    /// see [`Writer::finish_swap_until`] for details
+    pub fn finish_swap_until(&mut self, mut should_continue: impl FnMut() -> bool) -> bool {
+        while let Some(ref mut swap) = self.swap {
+            // SAFETY: this writer created the swap
+            if !unsafe { self.writer.finish_swap_until(swap, &mut should_continue) } {
+                return false;
+            }
+
+            if self.reverted {
+                self.reverted_swap_finished();
+            } else {
+                self.swap_finished();
+            }
+        }
+
+        true
     }
 
     /// finish an in progress buffer swap
@@ -99,18 +368,20 @@ impl<S: StrongRef> DelayedWriter<S> {
 
     /// check if the swap is finished
     pub fn is_swap_finished(&mut self) -> bool {
-        match self.swap.as_mut() {
-            None => true,
-            Some(swap) => {
-                // SAFETY: this writer created the swap
-                if unsafe { self.writer.is_swap_finished(swap) } {
-                    self.swap = None;
-                    true
-                } else {
-                    false
-                }
+        while let Some(ref mut swap) = self.swap {
+            // SAFETY: this writer created the swap
+            if !unsafe { self.writer.is_swap_finished(swap) } {
+                return false;
+            }
+
+            if self.reverted {
+                self.reverted_swap_finished();
+            } else {
+                self.swap_finished();
             }
         }
+
+        true
     }
 }
 
@@ -154,3 +425,210 @@ fn test() {
 
     writer.into_finish_swap();
 }
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_quiescence() {
+    let mut shared = crate::raw::Shared::from_raw_parts(
+        crate::strategy::TrackingStrategy::new(),
+        crate::raw::RawDBuf::new(10, 20),
+    );
+    let mut writer = DelayedWriter::new(Writer::new(&mut shared));
+
+    let mut r1 = writer.reader();
+    let mut r2 = writer.reader();
+
+    let a = r1.get();
+    writer.start_quiescence();
+
+    // readers which start after the barrier don't block it
+    let b = r2.get();
+    assert!(core::ptr::eq(&*a, &*b));
+    assert!(!writer.is_quiescent());
+
+    drop(a);
+    assert!(writer.is_quiescent());
+    drop(b);
+
+    let split = writer.finish_swap().split();
+    assert_eq!(*split.writer, 10);
+    assert_eq!(*split.reader, 20);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_post_swap() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        vec,
+        vec::Vec,
+    };
+
+    let mut writer = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        vec![1_u8; 3 * 4096],
+        vec![2_u8; 3 * 4096],
+    )));
+    let calls = Arc::new(AtomicUsize::new(0));
+    writer.set_post_swap({
+        let calls = calls.clone();
+        move |buffer: &mut Vec<u8>| {
+            crate::warm::touch_pages(buffer);
+            calls.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    let mut reader = writer.reader();
+    let guard = reader.get();
+    writer.start_buffer_swap();
+
+    // the swap isn't finished until the reader exits the new writer buffer
+    assert!(!writer.is_swap_finished());
+    assert_eq!(calls.load(Ordering::Relaxed), 0);
+    drop(guard);
+
+    assert!(writer.is_swap_finished());
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+    assert!(writer.finish_swap().split().writer.iter().all(|&x| x == 2));
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    // waiting for quiescence doesn't swap the buffers
+    writer.start_quiescence();
+    writer.finish_swap();
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    writer.swap_buffers();
+    writer.try_swap_buffers().unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+    writer.clear_post_swap();
+    writer.swap_buffers();
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_post_swap_borrowed() {
+    let mut shared = crate::raw::Shared::from_raw_parts(
+        crate::strategy::TrackingStrategy::new(),
+        crate::raw::RawDBuf::new(10, 20),
+    );
+    let mut writer = DelayedWriter::new(Writer::new(&mut shared));
+    writer.set_post_swap(|buffer: &mut i32| *buffer += 1);
+
+    writer.swap_buffers();
+    let split = writer.finish_swap().split();
+    assert_eq!(*split.writer, 21);
+    assert_eq!(*split.reader, 10);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_revert_pending_swap() {
+    use crate::{
+        ptrs::alloc::Owned,
+        strategy::{HazardStrategy, TrackingStrategy},
+    };
+
+    fn check<St: Strategy<ValidationError = core::convert::Infallible> + Default>() {
+        let mut writer = DelayedWriter::new(Writer::new(Owned::<St, _>::from_buffers(1, 1)));
+        let mut old = writer.reader();
+        let mut new = writer.reader();
+        let mut late = writer.reader();
+
+        assert_eq!(writer.revert_pending_swap(), Err(NoPendingSwap));
+        *writer.finish_swap().split_mut().writer = 2;
+
+        // a reader which is still on the old version when the swap starts
+        let old_guard = old.get();
+        writer.start_buffer_swap();
+        // and one which sees the new version
+        let new_guard = new.get();
+        assert_eq!((*old_guard, *new_guard), (1, 2));
+
+        writer.revert_pending_swap().unwrap();
+        assert_eq!(writer.revert_pending_swap(), Err(NoPendingSwap));
+        assert_eq!(*late.get(), 1);
+        assert_eq!(*writer.split().reader, 1);
+        assert_eq!(*writer.split().writer, 2);
+
+        // starting a swap doesn't skip waiting for the reverted swap
+        writer.start_buffer_swap();
+        assert!(!writer.is_swap_finished());
+        drop(old_guard);
+        // the reader of the reverted buffer is still there
+        assert!(!writer.is_swap_finished());
+        let late_guard = late.get();
+        drop(new_guard);
+        assert!(writer.is_swap_finished());
+        assert_eq!(*late_guard, 1);
+        drop(late_guard);
+
+        // publishing again works as usual
+        *writer.finish_swap().split_mut().writer = 3;
+        writer.start_buffer_swap();
+        assert_eq!(*new.get(), 3);
+        assert_eq!(
+            writer.finish_swap_with_stats(),
+            crate::raw::SwapStats::IMMEDIATE
+        );
+        assert_eq!(*writer.split().writer, 1);
+
+        // quiescence doesn't swap the buffers, so there is nothing to revert
+        writer.start_quiescence();
+        assert_eq!(writer.revert_pending_swap(), Err(NoPendingSwap));
+    }
+
+    check::<HazardStrategy>();
+    check::<TrackingStrategy>();
+}
+
+#[test]
+#[cfg(feature = "loom")]
+fn test_loom_revert_pending_swap() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy, wait::SpinWait};
+    use loom::cell::UnsafeCell;
+
+    /// a buffer which lets loom check for data races between the writer and readers
+    struct Buffer(UnsafeCell<u32>);
+
+    // SAFETY: the double buffer ensures that the writer never writes while a reader is reading
+    unsafe impl Sync for Buffer {}
+
+    loom::model(|| {
+        let shared = crate::raw::Shared::new(
+            HazardStrategy::<SpinWait>::default(),
+            crate::raw::RawDBuf::new(Buffer(UnsafeCell::new(0)), Buffer(UnsafeCell::new(0))),
+        );
+        let mut writer = DelayedWriter::new(Writer::new(Owned::new(shared)));
+        let mut reader = writer.reader();
+
+        let thread = loom::thread::spawn(move || {
+            let guard = reader.get();
+            // SAFETY: the read guard keeps the writer from writing to this buffer
+            guard.0.with(|value| unsafe { *value })
+        });
+
+        writer.finish_swap().split_mut().writer.0.with_mut(|value| {
+            // SAFETY: there is no swap in progress, so no reader is reading the writer buffer
+            unsafe { *value = 1 }
+        });
+        writer.start_buffer_swap();
+        // the reader may have seen the new buffer, so writing to it
+        // before the reader exits would race with the read above
+        writer.revert_pending_swap().unwrap();
+        writer.finish_swap().split_mut().writer.0.with_mut(|value| {
+            // SAFETY: all readers have exited the writer buffer
+            unsafe { *value = 2 }
+        });
+
+        assert!(thread.join().unwrap() <= 1);
+    })
+}
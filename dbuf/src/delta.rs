@@ -0,0 +1,287 @@
+//! A delta based writer
+//!
+//! [`DeltaWriter`] lets you mutate the writer buffer in place while recording which parts changed
+//! in a [`Delta`]. When the buffers are swapped, the recorded delta is replayed onto the new writer
+//! buffer by copying the changed parts from the published buffer. This is the same idea as the
+//! [`OpLog`](crate::op_log::OpLog), but it records mutations after the fact instead of deferring them.
+//!
+//! This is useful for large buffers where each change only touches a small part of the buffer,
+//! since [`publish_if`](DeltaWriter::publish_if) can skip swapping until enough has changed.
+//!
+//! WARNING: if a change isn't recorded, then the two buffers will diverge.
+
+use core::ops::{Deref, Range};
+use std::vec::Vec;
+
+use crate::{
+    delayed::DelayedWriter,
+    interface::{BufferOf, RawBuffersOf, Strategy, StrategyOf, StrongRef},
+    raw::Writer,
+};
+
+/// A record of changes made to a buffer
+pub trait Delta<B: ?Sized>: Default {
+    /// A single change to the buffer
+    type Change;
+
+    /// record a change to the buffer
+    fn record(&mut self, change: Self::Change);
+
+    /// copy all of the recorded changes from `source` into `target`
+    fn apply_to(&self, source: &B, target: &mut B);
+
+    /// an estimate of how expensive it is to apply this delta
+    fn cost(&self) -> usize;
+}
+
+/// A [`Delta`] which tracks which ranges of a slice were changed
+///
+/// Overlapping and adjacent ranges are merged together
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DirtyRanges {
+    /// the sorted, disjoint, and non-adjacent dirty ranges
+    ranges: Vec<Range<usize>>,
+}
+
+impl DirtyRanges {
+    /// create an empty set of dirty ranges
+    pub const fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    /// the dirty ranges, in sorted order
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    /// mark the range as dirty
+    pub fn mark(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let Range { mut start, mut end } = range;
+        // the ranges which end before the new range starts (and aren't adjacent to it)
+        let first = self.ranges.partition_point(|r| r.end < start);
+        // the ranges which start before the new range ends (or are adjacent to it)
+        let last = self.ranges.partition_point(|r| r.start <= end);
+
+        if first < last {
+            start = start.min(self.ranges[first].start);
+            end = end.max(self.ranges[last - 1].end);
+        }
+
+        self.ranges
+            .splice(first..last, core::iter::once(start..end));
+    }
+
+    /// copy the dirty ranges from `source` into `target`
+    fn copy<T: Clone>(&self, source: &[T], target: &mut [T]) {
+        let len = source.len().min(target.len());
+
+        for range in &self.ranges {
+            if range.start >= len {
+                break;
+            }
+
+            let range = range.start..range.end.min(len);
+            target[range.clone()].clone_from_slice(&source[range]);
+        }
+    }
+}
+
+impl<T: Clone> Delta<[T]> for DirtyRanges {
+    type Change = Range<usize>;
+
+    fn record(&mut self, change: Self::Change) {
+        self.mark(change)
+    }
+
+    fn apply_to(&self, source: &[T], target: &mut [T]) {
+        self.copy(source, target)
+    }
+
+    fn cost(&self) -> usize {
+        self.ranges.iter().map(|range| range.len()).sum()
+    }
+}
+
+impl<T: Clone> Delta<Vec<T>> for DirtyRanges {
+    type Change = Range<usize>;
+
+    fn record(&mut self, change: Self::Change) {
+        self.mark(change)
+    }
+
+    /// copy the dirty ranges, and make the lengths of the two vectors match
+    fn apply_to(&self, source: &Vec<T>, target: &mut Vec<T>) {
+        target.truncate(source.len());
+        self.copy(source, target);
+        target.extend_from_slice(&source[target.len()..]);
+    }
+
+    fn cost(&self) -> usize {
+        self.ranges.iter().map(|range| range.len()).sum()
+    }
+}
+
+/// A writer which replays recorded changes onto the other buffer
+///
+/// see module docs for details
+pub struct DeltaWriter<S: StrongRef, D> {
+    /// the underlying writer
+    writer: DelayedWriter<S>,
+    /// the changes made to the writer buffer since the last publish
+    delta: D,
+    /// the changes which were published, but not yet applied to the writer buffer
+    published: Option<D>,
+}
+
+impl<S: StrongRef, D: Default> From<Writer<S>> for DeltaWriter<S, D> {
+    fn from(writer: Writer<S>) -> Self {
+        Self::new(writer)
+    }
+}
+
+impl<S: StrongRef, D: Default> DeltaWriter<S, D> {
+    /// create a new delta writer
+    ///
+    /// the two buffers should start out the same
+    pub fn new(writer: Writer<S>) -> Self {
+        Self {
+            writer: DelayedWriter::new(writer),
+            delta: D::default(),
+            published: None,
+        }
+    }
+}
+
+impl<S: StrongRef, D: Delta<BufferOf<RawBuffersOf<S>>>> DeltaWriter<S, D>
+where
+    StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+{
+    /// the changes made to the writer buffer since the last publish
+    pub fn delta(&self) -> &D {
+        &self.delta
+    }
+
+    /// finish any in progress swap, and apply the published changes to the writer buffer
+    fn catch_up(&mut self) -> &mut Writer<S> {
+        let writer = self.writer.finish_swap();
+        if let Some(published) = self.published.take() {
+            let split = writer.split_mut();
+            published.apply_to(split.reader, split.writer);
+        }
+        writer
+    }
+
+    /// mutate the writer buffer, every change must be recorded in the delta
+    pub fn mutate<R>(&mut self, f: impl FnOnce(&mut BufferOf<RawBuffersOf<S>>, &mut D) -> R) -> R {
+        self.catch_up();
+        // the swap was finished by `catch_up`, so this is just a cheap check
+        let writer = self.writer.finish_swap().split_mut().writer;
+        f(writer, &mut self.delta)
+    }
+
+    /// swap the buffers if the cost of the recorded changes is more than the threshold
+    ///
+    /// returns true if the buffers were swapped
+    pub fn publish_if(&mut self, threshold: usize) -> bool {
+        let should_publish = self.delta.cost() > threshold;
+        if should_publish {
+            self.publish();
+        }
+        should_publish
+    }
+
+    /// swap the buffers
+    ///
+    /// the recorded changes will be applied to the new writer buffer before the next mutation
+    pub fn publish(&mut self) {
+        self.catch_up();
+        self.writer.start_buffer_swap();
+        self.published = Some(core::mem::take(&mut self.delta));
+    }
+}
+
+impl<S: StrongRef, D> Deref for DeltaWriter<S, D> {
+    type Target = Writer<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.writer
+    }
+}
+
+#[test]
+#[allow(clippy::single_range_in_vec_init)]
+fn test_dirty_ranges() {
+    let mut dirty = DirtyRanges::new();
+
+    dirty.mark(10..20);
+    dirty.mark(0..5);
+    dirty.mark(30..40);
+    assert_eq!(dirty.ranges(), [0..5, 10..20, 30..40]);
+
+    dirty.mark(5..10);
+    assert_eq!(dirty.ranges(), [0..20, 30..40]);
+
+    dirty.mark(15..35);
+    assert_eq!(dirty.ranges(), [0..40]);
+
+    dirty.mark(50..50);
+    assert_eq!(dirty.ranges(), [0..40]);
+    assert_eq!(Delta::<[u8]>::cost(&dirty), 40);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_delta_writer() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(std::vec![0; 100], std::vec![0; 100]);
+    let mut writer = DeltaWriter::<_, DirtyRanges>::new(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    writer.mutate(|buffer, delta| {
+        buffer[10..15].fill(1);
+        delta.mark(10..15);
+    });
+
+    assert!(!writer.publish_if(10));
+    assert!(reader.get().iter().all(|&x| x == 0));
+
+    writer.mutate(|buffer, delta| {
+        buffer[50..60].fill(2);
+        delta.mark(50..60);
+        buffer.push(3);
+        delta.mark(100..101);
+    });
+
+    // readers never see a partially applied delta
+    let guard = reader.get();
+    assert!(guard.iter().all(|&x| x == 0));
+    drop(guard);
+
+    assert!(writer.publish_if(10));
+    let guard = reader.get();
+    assert_eq!(guard.len(), 101);
+    assert_eq!(guard[10..15], [1; 5]);
+    assert_eq!(guard[50..60], [2; 10]);
+    drop(guard);
+
+    writer.mutate(|buffer, delta| {
+        buffer[0] = 4;
+        delta.mark(0..1);
+    });
+
+    // the published changes were replayed onto the new writer buffer
+    let split = writer.split();
+    assert_eq!(split.writer[1..], split.reader[1..]);
+    assert_eq!(split.writer[0], 4);
+
+    writer.publish();
+    writer.mutate(|_, _| ());
+    let split = writer.split();
+    assert_eq!(split.writer, split.reader);
+}
@@ -0,0 +1,268 @@
+//! Type erased writers and readers
+//!
+//! [`Writer`](crate::raw::Writer) and [`Reader`] are generic over the buffer and strategy,
+//! so double buffers of different types can't be stored together. An [`ErasedWriter`] can only
+//! be swapped, the buffers stay behind the typed writer, and an [`ErasedReader`] hands out
+//! the reader buffer as a `&dyn Any` which can be downcast back to the buffer type.
+//!
+//! This makes it possible to keep a registry of different double buffers, publish all of them
+//! in a loop, and hand out readers which don't need to know the strategy.
+//!
+//! ```
+//! use dbuf::{delayed::DelayedWriter, erased::{ErasedReader, ErasedWriter}, ptrs::alloc::Owned, raw::Writer};
+//! use dbuf::strategy::TrackingStrategy;
+//!
+//! let mut positions = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers([0.0; 2], [0.0; 2])));
+//! let mut names = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(Vec::<String>::new(), Vec::new())));
+//!
+//! let mut reader = ErasedReader::new(names.reader());
+//! names.finish_swap().split_mut().writer.push("player".to_string());
+//! positions.finish_swap().split_mut().writer[0] = 1.0;
+//!
+//! let mut registry = vec![ErasedWriter::new(positions), ErasedWriter::new(names)];
+//! registry.iter_mut().for_each(ErasedWriter::publish);
+//! registry.iter_mut().for_each(ErasedWriter::finish_swap);
+//!
+//! reader.read_with(&mut |buffer| {
+//!     let names = buffer.downcast_ref::<Vec<String>>().unwrap();
+//!     assert_eq!(names, &["player"]);
+//! }).unwrap();
+//! ```
+//!
+//! Only sized `'static` buffers can be read as `&dyn Any`, so double buffers of
+//! slices (like [`SliceRawDbuf`](crate::raw::SliceRawDbuf)) can't be read through an [`ErasedReader`].
+
+use core::{any::Any, convert::Infallible};
+use std::boxed::Box;
+
+use crate::{
+    delayed::DelayedWriter,
+    group::GroupMember,
+    interface::{BufferOf, RawBuffersOf, Strategy, StrategyOf, StrongOf, StrongRef, WeakRef},
+    raw::{ReadError, Reader},
+};
+
+/// A writer whose buffer type and strategy were erased
+///
+/// This can only swap the buffers, see module docs for details
+pub struct ErasedWriter<'a> {
+    /// the underlying delayed writer
+    inner: Box<dyn GroupMember + 'a>,
+}
+
+/// A reader whose buffer type and strategy were erased
+///
+/// see module docs for details
+pub struct ErasedReader<'a> {
+    /// the underlying reader
+    inner: Box<dyn ErasedRead + 'a>,
+}
+
+/// an object safe reader, implemented for every [`Reader`] of a sized `'static` buffer
+trait ErasedRead {
+    /// read the reader buffer
+    fn read_with(&mut self, f: &mut dyn FnMut(&dyn Any)) -> Result<(), ReadError>;
+}
+
+impl<W: WeakRef> ErasedRead for Reader<W>
+where
+    BufferOf<RawBuffersOf<StrongOf<W>>>: Sized + Any,
+{
+    fn read_with(&mut self, f: &mut dyn FnMut(&dyn Any)) -> Result<(), ReadError> {
+        let guard = self.try_get_raw().map_err(|_| ReadError::WriterGone)?;
+        f(&*guard);
+        Ok(())
+    }
+}
+
+impl<'a> ErasedWriter<'a> {
+    /// erase the type of a writer
+    pub fn new<S: StrongRef + 'a>(writer: DelayedWriter<S>) -> Self
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+    {
+        Self {
+            inner: Box::new(writer),
+        }
+    }
+
+    /// start swapping the buffers, the last swap must already be finished
+    pub fn start_swap(&mut self) {
+        self.inner.start_swap()
+    }
+
+    /// check if the last swap is finished
+    pub fn is_swap_finished(&mut self) -> bool {
+        self.inner.is_swap_finished()
+    }
+
+    /// wait for the last swap to finish
+    pub fn finish_swap(&mut self) {
+        self.inner.finish_swap()
+    }
+
+    /// wait for the last swap to finish, then start a new swap
+    ///
+    /// The new swap is finished lazily, like [`SwapGroup::publish_all`](crate::group::SwapGroup::publish_all)
+    pub fn publish(&mut self) {
+        self.inner.finish_swap();
+        self.inner.start_swap();
+    }
+}
+
+impl<'a, S: StrongRef + 'a> From<DelayedWriter<S>> for ErasedWriter<'a>
+where
+    StrategyOf<S>: Strategy<ValidationError = Infallible>,
+{
+    fn from(writer: DelayedWriter<S>) -> Self {
+        Self::new(writer)
+    }
+}
+
+impl GroupMember for ErasedWriter<'_> {
+    fn which(&self) -> bool {
+        self.inner.which()
+    }
+
+    fn start_swap(&mut self) {
+        self.inner.start_swap()
+    }
+
+    fn is_swap_finished(&mut self) -> bool {
+        self.inner.is_swap_finished()
+    }
+
+    fn finish_swap(&mut self) {
+        self.inner.finish_swap()
+    }
+}
+
+impl<'a> ErasedReader<'a> {
+    /// erase the type of a reader
+    pub fn new<W: WeakRef + 'a>(reader: Reader<W>) -> Self
+    where
+        BufferOf<RawBuffersOf<StrongOf<W>>>: Sized + Any,
+    {
+        Self {
+            inner: Box::new(reader),
+        }
+    }
+
+    /// read the reader buffer, which can be downcast to the buffer type
+    ///
+    /// This holds a read guard while `f` runs, so `f` should be quick.
+    /// It fails with [`ReadError::WriterGone`] if the double buffer was dropped
+    pub fn read_with(&mut self, f: &mut dyn FnMut(&dyn Any)) -> Result<(), ReadError> {
+        self.inner.read_with(f)
+    }
+}
+
+impl<'a, W: WeakRef + 'a> From<Reader<W>> for ErasedReader<'a>
+where
+    BufferOf<RawBuffersOf<StrongOf<W>>>: Sized + Any,
+{
+    fn from(reader: Reader<W>) -> Self {
+        Self::new(reader)
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_erased_registry() {
+    use crate::{ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy};
+    use std::{string::String, vec, vec::Vec};
+
+    let mut ints = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        0, 0,
+    )));
+    let mut names = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        String::new(),
+        String::new(),
+    )));
+    let mut points = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        Vec::<(f32, f32)>::new(),
+        Vec::new(),
+    )));
+
+    let mut readers = vec![
+        ErasedReader::new(ints.reader().into_weak()),
+        ErasedReader::new(names.reader()),
+        ErasedReader::new(points.reader()),
+    ];
+
+    *ints.finish_swap().split_mut().writer = 1;
+    names.finish_swap().split_mut().writer.push_str("one");
+    points.finish_swap().split_mut().writer.push((1.0, 1.0));
+
+    let mut registry = vec![
+        ErasedWriter::new(ints),
+        ErasedWriter::new(names),
+        ErasedWriter::new(points),
+    ];
+    registry.iter_mut().for_each(ErasedWriter::publish);
+    registry.iter_mut().for_each(ErasedWriter::finish_swap);
+    assert!(registry.iter_mut().all(ErasedWriter::is_swap_finished));
+
+    let mut seen = 0;
+    for reader in &mut readers {
+        reader
+            .read_with(&mut |buffer| {
+                if let Some(&int) = buffer.downcast_ref::<i32>() {
+                    assert_eq!(int, 1);
+                } else if let Some(name) = buffer.downcast_ref::<String>() {
+                    assert_eq!(name, "one");
+                } else {
+                    let points = buffer.downcast_ref::<Vec<(f32, f32)>>().unwrap();
+                    assert_eq!(points, &[(1.0, 1.0)]);
+                }
+                seen += 1;
+            })
+            .unwrap();
+    }
+    assert_eq!(seen, 3);
+
+    // weak readers don't keep the double buffer alive, strong readers do
+    drop(registry);
+    assert_eq!(
+        readers[0].read_with(&mut |_| unreachable!()),
+        Err(ReadError::WriterGone)
+    );
+    assert_eq!(readers[1].read_with(&mut |_| ()), Ok(()));
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_erased_swap_group() {
+    use crate::{group::SwapGroup, ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy};
+    use std::vec::Vec;
+
+    let mut ints = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        0, 0,
+    )));
+    let mut bytes = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        Vec::<u8>::new(),
+        Vec::new(),
+    )));
+    let mut int_reader = ErasedReader::new(ints.reader());
+    let mut byte_reader = ErasedReader::new(bytes.reader());
+
+    *ints.finish_swap().split_mut().writer = 1;
+    bytes.finish_swap().split_mut().writer.push(1);
+
+    let mut registry = [ErasedWriter::new(ints), ErasedWriter::new(bytes)];
+
+    let mut group = SwapGroup::new();
+    registry.iter_mut().for_each(|writer| group.push(writer));
+    group.publish_all();
+    group.finish_all();
+    drop(group);
+
+    int_reader
+        .read_with(&mut |buffer| assert_eq!(buffer.downcast_ref::<i32>(), Some(&1)))
+        .unwrap();
+    byte_reader
+        .read_with(&mut |buffer| assert_eq!(buffer.downcast_ref::<Vec<u8>>().unwrap(), &[1]))
+        .unwrap();
+}
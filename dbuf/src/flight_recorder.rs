@@ -0,0 +1,766 @@
+//! A bounded log of swaps and read guards, to find out offline whether a reader overlapped the writer
+//!
+//! With the `flight-recorder` feature, a [`RecordedStrategy`] wraps any strategy and records
+//! each swap and each read guard into a [`FlightRecorder`], and an [`OpWriter`](crate::op::OpWriter)
+//! records each publish once it's given the recorder with
+//! [`set_flight_recorder`](crate::op::OpWriter::set_flight_recorder). The recorder only keeps the
+//! most recent events. Dump them on demand with [`FlightRecorder::dump`], or when any thread
+//! panics with [`FlightRecorder::dump_on_panic`].
+//!
+//! [`verify_dump`] checks a dump: every read guard which began before a swap started must end
+//! before that swap finishes, otherwise the reader may have read the buffer while the writer
+//! wrote to it. Events are recorded around the flip, so that this catches every such reader and
+//! never fails spuriously. A swap's start is recorded after the buffers are flipped, but before
+//! the readers are captured, and a guard's begin after the guard is acquired, but before the reader
+//! loads which buffer is published. So a guard which began after a swap started always reads the
+//! new buffer, and one which began before it may read either buffer, which the swap must wait for
+//! anyways since the strategy can't tell which buffer the reader will pick. A guard's end is
+//! recorded before it's released, and a swap's finish after the writer saw the readers exit.
+//!
+//! A dump is CSV with a header line, one event per line, oldest first
+//!
+//! | `event` | `a` | `b` |
+//! |-|-|-|
+//! | `swap_start` | the swap's epoch | 0 |
+//! | `swap_finish` | the swap's epoch | 0 |
+//! | `quiesce_start` | the last swap's epoch | 0 |
+//! | `quiesce_finish` | the last swap's epoch | 0 |
+//! | `publish` | the op writer's epoch | the number of published operations |
+//! | `guard_begin` | the reader's id | 0 |
+//! | `guard_end` | the reader's id | 0 |
+//!
+//! `seq` counts all events ever recorded, so a gap at the start shows how many were dropped.
+//! `at_ns` is the time since the recorder was created, and `thread` numbers the threads in the
+//! order they first recorded an event. Epochs count the swaps of the [`RecordedStrategy`].
+//! Swaps undone with [`revert_pending_swap`](crate::delayed::DelayedWriter::revert_pending_swap)
+//! aren't seen by the strategy, so a dump with reverted swaps can't be verified.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use std::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    io::{self, BufRead},
+    path::PathBuf,
+    string::String,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Instant,
+    vec::Vec,
+};
+
+use crate::interface::{ActiveReaderInfo, FreshCopy, Strategy, StrategyIntrospect, WouldBlock};
+
+/// the header line of a dump
+const HEADER: &str = "seq,at_ns,thread,event,a,b";
+
+/// the kind of a recorded event, see the [module docs](self) for its fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventKind {
+    /// a swap was started, after the buffers were flipped
+    SwapStart,
+    /// a swap was finished, all readers exited the new writer buffer
+    SwapFinish,
+    /// the writer started waiting for the current readers, without a swap
+    QuiesceStart,
+    /// the writer finished waiting for the current readers
+    QuiesceFinish,
+    /// an op writer published some operations
+    Publish,
+    /// a read guard was acquired, and the reader hasn't picked a buffer yet
+    GuardBegin,
+    /// a read guard is about to be released
+    GuardEnd,
+}
+
+impl EventKind {
+    /// every kind, to parse them
+    const ALL: [Self; 7] = [
+        Self::SwapStart,
+        Self::SwapFinish,
+        Self::QuiesceStart,
+        Self::QuiesceFinish,
+        Self::Publish,
+        Self::GuardBegin,
+        Self::GuardEnd,
+    ];
+
+    /// the name of the kind in a dump
+    fn name(self) -> &'static str {
+        match self {
+            Self::SwapStart => "swap_start",
+            Self::SwapFinish => "swap_finish",
+            Self::QuiesceStart => "quiesce_start",
+            Self::QuiesceFinish => "quiesce_finish",
+            Self::Publish => "publish",
+            Self::GuardBegin => "guard_begin",
+            Self::GuardEnd => "guard_end",
+        }
+    }
+}
+
+/// a single recorded event
+#[derive(Debug, Clone, Copy)]
+struct Event {
+    /// the number of events recorded before this one
+    seq: u64,
+    /// nanoseconds since the recorder was created
+    at_ns: u64,
+    /// the thread which recorded the event, see [`thread_number`]
+    thread: u64,
+    /// what happened
+    kind: EventKind,
+    /// the first field, see the [module docs](self)
+    a: u64,
+    /// the second field, see the [module docs](self)
+    b: u64,
+}
+
+/// the events which are still in the recorder
+struct Ring {
+    /// the most recent events, oldest first
+    events: VecDeque<Event>,
+    /// the `seq` of the next event
+    next_seq: u64,
+}
+
+/// A bounded ring of the most recent swaps, publishes, and read guards
+///
+/// see the [module docs](self)
+pub struct FlightRecorder {
+    /// the time `at_ns` is measured from
+    start: Instant,
+    /// the maximum number of events which are kept
+    capacity: usize,
+    /// the recorded events
+    ring: Mutex<Ring>,
+}
+
+/// the number of the current thread, threads are numbered in the order they first record an event
+fn thread_number() -> u64 {
+    /// the number of the next thread which records an event
+    static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+    std::thread_local! {
+        /// the number of this thread
+        static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+    }
+
+    THREAD.with(|thread| *thread)
+}
+
+impl FlightRecorder {
+    /// create a recorder which keeps the `capacity` most recent events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            capacity,
+            ring: Mutex::new(Ring {
+                events: VecDeque::with_capacity(capacity),
+                next_seq: 0,
+            }),
+        }
+    }
+
+    /// lock the ring, ignoring poison since it's only diagnostics
+    fn lock(&self) -> MutexGuard<'_, Ring> {
+        self.ring.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// record an event, and return its `seq`
+    fn record(&self, kind: EventKind, a: u64, b: u64) -> u64 {
+        let thread = thread_number();
+        let mut ring = self.lock();
+        // the time is read under the lock, so that it never goes back in the ring
+        let at_ns = u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        let seq = ring.next_seq;
+        ring.next_seq += 1;
+
+        if self.capacity == 0 {
+            return seq;
+        }
+        if ring.events.len() == self.capacity {
+            ring.events.pop_front();
+        }
+        ring.events.push_back(Event {
+            seq,
+            at_ns,
+            thread,
+            kind,
+            a,
+            b,
+        });
+        seq
+    }
+
+    /// record that an op writer published `ops` operations, in its epoch `epoch`
+    pub fn record_publish(&self, epoch: u64, ops: usize) {
+        self.record(EventKind::Publish, epoch, ops as u64);
+    }
+
+    /// the number of events which were recorded, including the ones which were dropped
+    pub fn recorded(&self) -> u64 {
+        self.lock().next_seq
+    }
+
+    /// Write the recorded events to `w`, in the format from the [module docs](self)
+    ///
+    /// The events are copied out first, so recording isn't blocked while writing
+    pub fn dump(&self, mut w: impl io::Write) -> io::Result<()> {
+        let events = Vec::from(self.lock().events.clone());
+
+        writeln!(w, "{HEADER}")?;
+        for event in events {
+            writeln!(
+                w,
+                "{},{},{},{},{},{}",
+                event.seq,
+                event.at_ns,
+                event.thread,
+                event.kind.name(),
+                event.a,
+                event.b
+            )?;
+        }
+        w.flush()
+    }
+
+    /// Dump the recorder to the file at `path` whenever a thread panics, then run the previous panic hook
+    ///
+    /// The file is overwritten by each panic. Errors while dumping are ignored
+    pub fn dump_on_panic(self: &Arc<Self>, path: impl Into<PathBuf>) {
+        let recorder = self.clone();
+        let path = path.into();
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if let Ok(file) = std::fs::File::create(&path) {
+                let _ = recorder.dump(io::BufWriter::new(file));
+            }
+            previous(info)
+        }));
+    }
+}
+
+impl fmt::Debug for FlightRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ring = self.lock();
+        f.debug_struct("FlightRecorder")
+            .field("capacity", &self.capacity)
+            .field("len", &ring.events.len())
+            .field("recorded", &ring.next_seq)
+            .finish()
+    }
+}
+
+/// A strategy which records its swaps and read guards into a [`FlightRecorder`]
+///
+/// Everything else is forwarded to the inner strategy. See the [module docs](self)
+pub struct RecordedStrategy<S> {
+    /// the strategy which does the actual synchronization
+    inner: S,
+    /// where the events are recorded
+    recorder: Arc<FlightRecorder>,
+    /// the number of swaps which were started
+    epoch: AtomicU64,
+    /// the id of the next reader tag
+    next_reader: AtomicU64,
+    /// true if a swap or quiescence was started, and its finish wasn't recorded yet
+    pending: AtomicBool,
+    /// true if the pending event is a quiescence instead of a swap
+    quiescing: AtomicBool,
+}
+
+/// the reader tag of a [`RecordedStrategy`]
+#[derive(Clone, Copy)]
+pub struct RecordedReaderTag<T> {
+    /// the inner strategy's tag
+    inner: T,
+    /// identifies the reader in the recorded events, `0` for dangling tags
+    id: u64,
+}
+
+/// the read guard of a [`RecordedStrategy`]
+pub struct RecordedGuard<G> {
+    /// the inner strategy's guard
+    inner: G,
+    /// the reader which acquired the guard
+    reader: u64,
+}
+
+impl<S: FreshCopy> FreshCopy for RecordedStrategy<S> {
+    fn fresh_copy(&self) -> Self {
+        // the recorder is part of the configuration, so the copy records into it too
+        Self::new(self.inner.fresh_copy(), self.recorder.clone())
+    }
+}
+
+impl<S> RecordedStrategy<S> {
+    /// record the swaps and read guards of `inner` into `recorder`
+    pub const fn new(inner: S, recorder: Arc<FlightRecorder>) -> Self {
+        Self {
+            inner,
+            recorder,
+            epoch: AtomicU64::new(0),
+            next_reader: AtomicU64::new(1),
+            pending: AtomicBool::new(false),
+            quiescing: AtomicBool::new(false),
+        }
+    }
+
+    /// the recorder which the events are recorded into
+    pub fn recorder(&self) -> &Arc<FlightRecorder> {
+        &self.recorder
+    }
+
+    /// the strategy which does the actual synchronization
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// wrap a reader tag of the inner strategy with a new id
+    fn wrap_reader_tag<T>(&self, inner: T) -> RecordedReaderTag<T> {
+        RecordedReaderTag {
+            inner,
+            id: self.next_reader.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// record the begin of a read guard which was just acquired
+    ///
+    /// This is called before the reader loads which buffer is published, see the [module docs](self)
+    fn record_begin<G>(&self, reader: u64, inner: G) -> RecordedGuard<G> {
+        self.recorder.record(EventKind::GuardBegin, reader, 0);
+        RecordedGuard { inner, reader }
+    }
+
+    /// record the start of a swap or quiescence, after the buffers were flipped
+    fn record_start(&self, kind: EventKind, epoch: u64) {
+        self.recorder.record(kind, epoch, 0);
+        self.quiescing
+            .store(kind == EventKind::QuiesceStart, Ordering::Relaxed);
+        self.pending.store(true, Ordering::Relaxed);
+    }
+}
+
+// SAFETY: this forwards everything to the inner strategy, and only records events around the calls
+unsafe impl<S: Strategy> Strategy for RecordedStrategy<S> {
+    type WriterTag = S::WriterTag;
+    type ReaderTag = RecordedReaderTag<S::ReaderTag>;
+    type Which = S::Which;
+    type ValidationToken = S::ValidationToken;
+    type ValidationError = S::ValidationError;
+    type Capture = S::Capture;
+    type ReaderGuard = RecordedGuard<S::ReaderGuard>;
+    type Pause = S::Pause;
+
+    unsafe fn create_writer_tag(&mut self) -> Self::WriterTag {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.create_writer_tag() }
+    }
+
+    unsafe fn create_reader_tag_from_writer(&self, parent: &Self::WriterTag) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        self.wrap_reader_tag(unsafe { self.inner.create_reader_tag_from_writer(parent) })
+    }
+
+    unsafe fn create_reader_tag_from_reader(&self, parent: &Self::ReaderTag) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        self.wrap_reader_tag(unsafe { self.inner.create_reader_tag_from_reader(&parent.inner) })
+    }
+
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        self.wrap_reader_tag(unsafe { self.inner.create_reader_tag() })
+    }
+
+    fn dangling_reader_tag() -> Self::ReaderTag {
+        RecordedReaderTag {
+            inner: S::dangling_reader_tag(),
+            id: 0,
+        }
+    }
+
+    fn validate_swap(
+        &self,
+        writer: &mut Self::WriterTag,
+    ) -> Result<Self::ValidationToken, Self::ValidationError> {
+        // the swap starts once the buffers are flipped, see `capture_readers`
+        self.inner.validate_swap(writer)
+    }
+
+    unsafe fn capture_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // the buffers were just flipped, so any guard which begins after this reads the new buffer
+        let epoch = self.epoch.fetch_add(1, Ordering::Relaxed) + 1;
+        self.record_start(EventKind::SwapStart, epoch);
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.capture_readers(writer, token) }
+    }
+
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        token: Self::ValidationToken,
+    ) -> Self::Capture {
+        self.record_start(EventKind::QuiesceStart, self.epoch.load(Ordering::Relaxed));
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.capture_current_readers(writer, token) }
+    }
+
+    unsafe fn have_readers_exited(
+        &self,
+        writer: &Self::WriterTag,
+        capture: &mut Self::Capture,
+    ) -> bool {
+        // SAFETY: forwarded from the caller
+        let exited = unsafe { self.inner.have_readers_exited(writer, capture) };
+        if exited && self.pending.swap(false, Ordering::Relaxed) {
+            let kind = if self.quiescing.load(Ordering::Relaxed) {
+                EventKind::QuiesceFinish
+            } else {
+                EventKind::SwapFinish
+            };
+            self.recorder
+                .record(kind, self.epoch.load(Ordering::Relaxed), 0);
+        }
+        exited
+    }
+
+    fn pause(&self, writer: &Self::WriterTag, pause: &mut Self::Pause) {
+        self.inner.pause(writer, pause)
+    }
+
+    fn has_readers(&self) -> bool {
+        self.inner.has_readers()
+    }
+
+    fn any_current_readers(&self) -> bool {
+        self.inner.any_current_readers()
+    }
+
+    unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
+        // SAFETY: forwarded from the caller
+        let guard = unsafe { self.inner.begin_read_guard(&mut reader.inner) };
+        self.record_begin(reader.id, guard)
+    }
+
+    unsafe fn try_begin_read_guard(
+        &self,
+        reader: &mut Self::ReaderTag,
+        pause: &mut Self::Pause,
+    ) -> Result<Self::ReaderGuard, WouldBlock> {
+        // SAFETY: forwarded from the caller
+        let guard = unsafe { self.inner.try_begin_read_guard(&mut reader.inner, pause) }?;
+        Ok(self.record_begin(reader.id, guard))
+    }
+
+    unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, guard: Self::ReaderGuard) {
+        self.recorder.record(EventKind::GuardEnd, guard.reader, 0);
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.end_read_guard(&mut reader.inner, guard.inner) }
+    }
+
+    unsafe fn preallocate_reader(&self, reader: &mut Self::ReaderTag) {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.preallocate_reader(&mut reader.inner) }
+    }
+}
+
+impl<S: StrategyIntrospect> StrategyIntrospect for RecordedStrategy<S> {
+    fn active_readers(&self, f: impl FnMut(ActiveReaderInfo)) {
+        self.inner.active_readers(f)
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn guard_backtrace(
+        &self,
+        reader: &ActiveReaderInfo,
+    ) -> Option<std::sync::Arc<std::backtrace::Backtrace>> {
+        self.inner.guard_backtrace(reader)
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn guard_thread(&self, reader: &ActiveReaderInfo) -> Option<std::thread::ThreadId> {
+        self.inner.guard_thread(reader)
+    }
+
+    fn maintain(&self) {
+        self.inner.maintain()
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        self.inner.bookkeeping_bytes()
+    }
+}
+
+/// The error returned from [`verify_dump`]
+#[derive(Debug)]
+pub enum Violation {
+    /// a read guard began before a swap started, and hadn't ended when the swap finished
+    ///
+    /// This also covers quiescence, which must wait for the same guards
+    Overlap {
+        /// the reader which held the guard
+        reader: u64,
+        /// the `seq` of the guard's begin
+        guard_begin: u64,
+        /// the `seq` of the swap's finish
+        swap_finish: u64,
+    },
+    /// the line isn't an event, lines are counted from 1
+    Malformed {
+        /// the line number
+        line: usize,
+    },
+    /// reading the dump failed
+    Io(io::Error),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Overlap {
+                reader,
+                guard_begin,
+                swap_finish,
+            } => write!(
+                f,
+                "the guard of reader {reader} from event {guard_begin} was still held when the swap finished at event {swap_finish}"
+            ),
+            Violation::Malformed { line } => write!(f, "line {line} isn't an event"),
+            Violation::Io(err) => write!(f, "could not read the dump: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
+
+/// parse a line of a dump
+fn parse_event(line: &str) -> Option<Event> {
+    let mut fields = line.split(',');
+    let mut number = || fields.next()?.parse::<u64>().ok();
+    let (seq, at_ns, thread) = (number()?, number()?, number()?);
+    let name = fields.next()?;
+    let kind = EventKind::ALL
+        .into_iter()
+        .find(|kind| kind.name() == name)?;
+    let mut number = || fields.next()?.parse::<u64>().ok();
+    let (a, b) = (number()?, number()?);
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(Event {
+        seq,
+        at_ns,
+        thread,
+        kind,
+        a,
+        b,
+    })
+}
+
+/// Check that no read guard in a dump from [`FlightRecorder::dump`] overlapped the writer
+///
+/// Every guard which began before a swap started must end before the swap finishes.
+/// Guards which began before the oldest event in the dump aren't checked
+pub fn verify_dump(reader: impl BufRead) -> Result<(), Violation> {
+    // the `seq`s of the begins of the guards which are currently held, by reader
+    let mut held = BTreeMap::<u64, Vec<u64>>::new();
+    // the guards which the current swap must wait for, as (reader, begin seq)
+    let mut waiting = Vec::<(u64, u64)>::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line: String = line.map_err(Violation::Io)?;
+        if index == 0 && line == HEADER {
+            continue;
+        }
+        let event = parse_event(&line).ok_or(Violation::Malformed { line: index + 1 })?;
+
+        match event.kind {
+            EventKind::GuardBegin => held.entry(event.a).or_default().push(event.seq),
+            EventKind::GuardEnd => {
+                // copies of a reader tag may hold guards at the same time, and the guards can't be told
+                // apart, so this assumes the oldest one ended, which never reports a false overlap
+                let Some(begins) = held.get_mut(&event.a) else {
+                    // the begin was dropped from the ring
+                    continue;
+                };
+                let begin = begins.remove(0);
+                if begins.is_empty() {
+                    held.remove(&event.a);
+                }
+                waiting.retain(|&guard| guard != (event.a, begin));
+            }
+            EventKind::SwapStart | EventKind::QuiesceStart => {
+                waiting = held
+                    .iter()
+                    .flat_map(|(&reader, begins)| begins.iter().map(move |&begin| (reader, begin)))
+                    .collect();
+            }
+            EventKind::SwapFinish | EventKind::QuiesceFinish => {
+                if let Some(&(reader, guard_begin)) = waiting.first() {
+                    return Err(Violation::Overlap {
+                        reader,
+                        guard_begin,
+                        swap_finish: event.seq,
+                    });
+                }
+            }
+            EventKind::Publish => (),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_recorded_swaps() {
+    use crate::{
+        ptrs::alloc::Owned,
+        raw::{RawDBuf, Shared, Writer},
+        strategy::HazardStrategy,
+    };
+
+    let recorder = Arc::new(FlightRecorder::new(64));
+    let strategy = RecordedStrategy::new(HazardStrategy::new(), recorder.clone());
+    let mut writer = Writer::new(Owned::new(Shared::from_raw_parts(
+        strategy,
+        RawDBuf::new(0, 0),
+    )));
+    let mut reader = writer.reader();
+
+    let guard = reader.get();
+    // the guard is in the published buffer, so it must end before the swap finishes
+    // SAFETY: the swap is finished before the writer is used again
+    let mut swap = unsafe { writer.try_start_buffer_swap() }.unwrap();
+    // SAFETY: the swap is from this writer
+    assert!(!unsafe { writer.is_swap_finished(&mut swap) });
+    drop(guard);
+    // SAFETY: the swap is from this writer
+    unsafe { writer.finish_swap(&mut swap) };
+    writer.wait_for_quiescence();
+
+    let mut dump = Vec::new();
+    recorder.dump(&mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    let events = dump
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').skip(3).collect::<Vec<_>>().join(","))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [
+            "guard_begin,1,0",
+            "swap_start,1,0",
+            "guard_end,1,0",
+            "swap_finish,1,0",
+            "quiesce_start,1,0",
+            "quiesce_finish,1,0",
+        ]
+    );
+    verify_dump(dump.as_bytes()).unwrap();
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_guard_before_flip_is_waited_for() {
+    use crate::strategy::HazardStrategy;
+
+    let recorder = Arc::new(FlightRecorder::new(64));
+    let mut strategy = RecordedStrategy::new(HazardStrategy::new(), recorder.clone());
+
+    // SAFETY: the tags are used according to the safety requirements of `Strategy`
+    unsafe {
+        let mut writer = strategy.create_writer_tag();
+        let mut reader = strategy.create_reader_tag_from_writer(&writer);
+
+        // the guard begins after the swap is validated but before the buffers are flipped,
+        // so it may read the old buffer, and must be recorded before the swap starts
+        let token = strategy.validate_swap(&mut writer).unwrap();
+        let guard = strategy.begin_read_guard(&mut reader);
+        let mut capture = strategy.capture_readers(&mut writer, token);
+        assert!(!strategy.have_readers_exited(&writer, &mut capture));
+        strategy.end_read_guard(&mut reader, guard);
+        assert!(strategy.have_readers_exited(&writer, &mut capture));
+    }
+
+    let mut dump = Vec::new();
+    recorder.dump(&mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    let events = dump
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').skip(3).collect::<Vec<_>>().join(","))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [
+            "guard_begin,1,0",
+            "swap_start,1,0",
+            "guard_end,1,0",
+            "swap_finish,1,0",
+        ]
+    );
+    verify_dump(dump.as_bytes()).unwrap();
+}
+
+#[test]
+fn test_verify_dump_finds_overlap() {
+    let dump = "seq,at_ns,thread,event,a,b
+7,10,1,guard_begin,3,0
+8,11,2,swap_start,1,0
+9,12,1,guard_begin,4,0
+10,13,2,swap_finish,1,0
+11,14,1,guard_end,3,0
+";
+    let Err(Violation::Overlap {
+        reader: 3,
+        guard_begin: 7,
+        swap_finish: 10,
+    }) = verify_dump(dump.as_bytes())
+    else {
+        panic!("the overlap wasn't found")
+    };
+
+    // a guard which began after the swap started reads the new buffer, so it may outlive the swap
+    let dump = "seq,at_ns,thread,event,a,b
+7,10,1,guard_begin,3,0
+8,11,2,swap_start,1,0
+9,12,1,guard_begin,4,0
+10,12,1,guard_end,3,0
+11,13,2,swap_finish,1,0
+12,14,1,guard_end,4,0
+";
+    verify_dump(dump.as_bytes()).unwrap();
+
+    assert!(matches!(
+        verify_dump("seq,at_ns,thread,event,a,b\n1,2,3,swap,4,5".as_bytes()),
+        Err(Violation::Malformed { line: 2 })
+    ));
+}
+
+#[test]
+fn test_ring_is_bounded() {
+    let recorder = FlightRecorder::new(2);
+    for epoch in 0..5 {
+        recorder.record_publish(epoch, 1);
+    }
+    assert_eq!(recorder.recorded(), 5);
+
+    let mut dump = Vec::new();
+    recorder.dump(&mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    let seqs = dump
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(seqs, ["3", "4"]);
+}
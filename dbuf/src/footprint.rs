@@ -0,0 +1,377 @@
+//! How much memory a double buffer holds
+//!
+//! A double buffer keeps two copies of its buffer, which is easy to forget about when planning
+//! capacity, and an [`OpWriter`](crate::op::OpWriter) also keeps the operations which weren't
+//! applied to both buffers yet. [`MemoryFootprint::footprint`] reports the bytes held by each of
+//! these, and by the strategy's bookkeeping (see [`StrategyIntrospect::bookkeeping_bytes`]),
+//! so they can be monitored.
+//!
+//! The buffers and operations report what they own on the heap with [`MemoryUsage`], or with a
+//! closure passed to [`Writer::footprint_with`]/[`OpWriter::footprint_with`](crate::op::OpWriter::footprint_with).
+//! These are estimates based on the capacity of collections, not on what the allocator reserved:
+//!
+//! * `Vec<T>`, `VecDeque<T>` and `String`: the capacity times the size of an element
+//! * `HashMap<K, V>` and `HashSet<T>`: see [`hash_table_bytes`]
+//! * `BTreeMap<K, V>` and `BTreeSet<T>`: the length doesn't tell how full each node is, so this
+//!   assumes that the nodes hold 8 of their 11 entries on average. This is within 20% for maps
+//!   built by inserting in any order, and their clones, but maps built with `collect` have full
+//!   nodes, and are overestimated by up to 60%
+//! * boxed closures, like [`ApplyToBoth`]: what they captured can't be seen, so they are
+//!   counted as [`CLOSURE_BYTES`]
+//!
+//! Everything a value owns is counted, so values which share an allocation (e.g. an `Arc`)
+//! should count it once, or not at all.
+//!
+//! ```
+//! use dbuf::{footprint::MemoryFootprint, op::OpWriter, ptrs::alloc::Owned, raw::Writer, strategy::HazardStrategy};
+//!
+//! let writer = OpWriter::<_, dbuf::op_log::ApplyToBoth<Vec<u64>>>::from(Writer::new(
+//!     Owned::<HazardStrategy, _>::from_buffers(Vec::<u64>::with_capacity(1000), Vec::with_capacity(1000)),
+//! ));
+//!
+//! let footprint = writer.footprint();
+//! // both buffers have room for 1000 elements
+//! assert!(footprint.buffers >= 2 * 1000 * 8);
+//! assert_eq!(footprint.total(), footprint.buffers + footprint.op_log + footprint.strategy);
+//! ```
+
+use core::mem::size_of;
+#[cfg(feature = "alloc")]
+use std::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    string::String,
+    vec::Vec,
+};
+
+#[cfg(feature = "alloc")]
+use crate::op_log::{ApplyToBoth, Boxed, OpLog};
+use crate::{
+    interface::{BufferOf, RawBuffersOf, StrategyIntrospect, StrategyOf, StrongRef},
+    op::OpWriter,
+    op_log::{ArrayOpLog, OpLogBackend},
+    raw::Writer,
+};
+
+/// The number of bytes a boxed closure is counted as, see the [module docs](self)
+pub const CLOSURE_BYTES: usize = 64;
+
+/// The bytes held by a double buffer, see [`MemoryFootprint`]
+///
+/// Each part includes the size of the value itself, so the buffers include both halves
+/// even if they are stored inline
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Footprint {
+    /// the bytes held by both buffers
+    pub buffers: usize,
+    /// the bytes held by the operation log, `0` for writers without one
+    pub op_log: usize,
+    /// the bytes held by the strategy, including its bookkeeping for the readers
+    pub strategy: usize,
+}
+
+impl Footprint {
+    /// the bytes held by all parts
+    pub fn total(&self) -> usize {
+        self.buffers + self.op_log + self.strategy
+    }
+}
+
+/// A writer which can report how many bytes its double buffer holds
+pub trait MemoryFootprint {
+    /// the bytes held by the buffers, the operation log and the strategy
+    fn footprint(&self) -> Footprint;
+}
+
+/// A value which can estimate how many bytes it owns on the heap, see the [module docs](self)
+pub trait MemoryUsage {
+    /// the number of bytes this value owns on the heap, not counting `size_of::<Self>()`
+    fn heap_bytes(&self) -> usize;
+}
+
+impl<S: StrongRef> MemoryFootprint for Writer<S>
+where
+    BufferOf<RawBuffersOf<S>>: MemoryUsage,
+    StrategyOf<S>: StrategyIntrospect,
+{
+    fn footprint(&self) -> Footprint {
+        self.footprint_with(MemoryUsage::heap_bytes)
+    }
+}
+
+impl<S: StrongRef, O, L: OpLogBackend<O> + MemoryUsage, V> MemoryFootprint for OpWriter<S, O, L, V>
+where
+    BufferOf<RawBuffersOf<S>>: MemoryUsage,
+    StrategyOf<S>: StrategyIntrospect,
+{
+    fn footprint(&self) -> Footprint {
+        self.footprint_with(MemoryUsage::heap_bytes)
+    }
+}
+
+/// The bytes of a hash table with room for `capacity` entries of type `T`
+///
+/// This is the layout of [`std::collections::HashMap`] (`T` is `(K, V)`) and
+/// [`std::collections::HashSet`]. An empty table doesn't allocate, otherwise:
+///
+/// * `buckets` is the capacity plus one (rounded up to a power of two) for less than 8 entries,
+///   and `capacity / 7 * 8` otherwise, since the table is at most 7/8 full
+/// * each bucket stores one entry, and one control byte
+/// * there are 16 more control bytes, so the last group can be read at once
+///
+/// so the bytes are `buckets * size_of::<T>() + buckets + 16`. The heap of the entries
+/// themselves isn't included
+pub fn hash_table_bytes<T>(capacity: usize) -> usize {
+    /// the number of control bytes which are read at once
+    const GROUP_WIDTH: usize = 16;
+
+    if capacity == 0 {
+        return 0;
+    }
+
+    let buckets = if capacity < 8 {
+        (capacity + 1).next_power_of_two()
+    } else {
+        capacity / 7 * 8
+    };
+
+    let entries = (buckets * size_of::<T>()).next_multiple_of(GROUP_WIDTH);
+    entries + buckets + GROUP_WIDTH
+}
+
+/// The estimated bytes of the nodes of a b-tree with `len` entries, see the [module docs](self)
+#[cfg(feature = "alloc")]
+fn btree_bytes<K, V>(len: usize) -> usize {
+    /// the most entries a node can hold
+    const CAPACITY: usize = 11;
+    /// how many entries a node holds on average
+    const FILLED: usize = 8;
+    /// the parent pointer, the index in the parent, and the length
+    const HEADER: usize = size_of::<usize>() + 4;
+
+    if len == 0 {
+        return 0;
+    }
+
+    let leaf = (HEADER + CAPACITY * (size_of::<K>() + size_of::<V>())).next_multiple_of(
+        align_of::<usize>()
+            .max(align_of::<K>())
+            .max(align_of::<V>()),
+    );
+    let internal = leaf + (CAPACITY + 1) * size_of::<usize>();
+
+    let leaves = len.div_ceil(FILLED);
+    let internals = (leaves - 1).div_ceil(FILLED - 1);
+    leaves * leaf + internals * internal
+}
+
+/// implement [`MemoryUsage`] for types which don't own anything on the heap
+macro_rules! no_heap {
+    ($($ty:ty),* $(,)?) => {$(
+        impl MemoryUsage for $ty {
+            fn heap_bytes(&self) -> usize {
+                0
+            }
+        }
+    )*};
+}
+
+no_heap!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    &'static str,
+);
+
+/// implement [`MemoryUsage`] for tuples of values which implement it
+macro_rules! tuple {
+    ($($name:ident)*) => {
+        impl<$($name: MemoryUsage),*> MemoryUsage for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn heap_bytes(&self) -> usize {
+                let ($($name,)*) = self;
+                0 $(+ $name.heap_bytes())*
+            }
+        }
+    };
+}
+
+tuple!(A);
+tuple!(A B);
+tuple!(A B C);
+tuple!(A B C D);
+
+impl<T: MemoryUsage> MemoryUsage for Option<T> {
+    fn heap_bytes(&self) -> usize {
+        self.as_ref().map_or(0, T::heap_bytes)
+    }
+}
+
+impl<T: MemoryUsage, const N: usize> MemoryUsage for [T; N] {
+    fn heap_bytes(&self) -> usize {
+        self.iter().map(T::heap_bytes).sum()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl MemoryUsage for String {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: MemoryUsage> MemoryUsage for Box<T> {
+    fn heap_bytes(&self) -> usize {
+        size_of::<T>() + T::heap_bytes(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: MemoryUsage> MemoryUsage for Vec<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(T::heap_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: MemoryUsage> MemoryUsage for VecDeque<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(T::heap_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<K: MemoryUsage, V: MemoryUsage> MemoryUsage for BTreeMap<K, V> {
+    fn heap_bytes(&self) -> usize {
+        btree_bytes::<K, V>(self.len())
+            + self
+                .iter()
+                .map(|(key, value)| key.heap_bytes() + value.heap_bytes())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: MemoryUsage> MemoryUsage for BTreeSet<T> {
+    fn heap_bytes(&self) -> usize {
+        btree_bytes::<T, ()>(self.len()) + self.iter().map(T::heap_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: MemoryUsage, V: MemoryUsage, S> MemoryUsage for std::collections::HashMap<K, V, S> {
+    fn heap_bytes(&self) -> usize {
+        hash_table_bytes::<(K, V)>(self.capacity())
+            + self
+                .iter()
+                .map(|(key, value)| key.heap_bytes() + value.heap_bytes())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: MemoryUsage, S> MemoryUsage for std::collections::HashSet<T, S> {
+    fn heap_bytes(&self) -> usize {
+        hash_table_bytes::<T>(self.capacity()) + self.iter().map(T::heap_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<B: ?Sized> MemoryUsage for ApplyToBoth<B> {
+    fn heap_bytes(&self) -> usize {
+        CLOSURE_BYTES
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<O: MemoryUsage> MemoryUsage for OpLog<O> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<O>() + self.ops_iter().map(O::heap_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<O: MemoryUsage> MemoryUsage for OpLog<O, Boxed> {
+    fn heap_bytes(&self) -> usize {
+        // the log only holds the pointers, and each operation has its own allocation
+        self.capacity() * size_of::<Box<O>>()
+            + self.len() * size_of::<O>()
+            + self.ops_iter().map(O::heap_bytes).sum::<usize>()
+    }
+}
+
+impl<O: MemoryUsage, const N: usize> MemoryUsage for ArrayOpLog<O, N> {
+    fn heap_bytes(&self) -> usize {
+        // the operations are stored inline
+        self.ops_iter().map(O::heap_bytes).sum()
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_hash_table_bytes() {
+    assert_eq!(hash_table_bytes::<u64>(0), 0);
+    // 4 buckets
+    assert_eq!(hash_table_bytes::<u64>(3), 32 + 4 + 16);
+    // 8 buckets
+    assert_eq!(hash_table_bytes::<u64>(7), 64 + 8 + 16);
+    // 16 buckets
+    assert_eq!(hash_table_bytes::<u64>(14), 128 + 16 + 16);
+
+    let map = (0..100u64)
+        .map(|i| (i, i))
+        .collect::<std::collections::HashMap<_, _>>();
+    // 128 buckets
+    assert_eq!(map.heap_bytes(), 128 * 16 + 128 + 16);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_footprint() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+    use std::vec;
+
+    let mut writer =
+        OpWriter::<_, ApplyToBoth<Vec<u32>>>::from(Writer::new(
+            Owned::<HazardStrategy, _>::from_buffers(vec![0; 100], vec![0; 100]),
+        ));
+    let empty = writer.footprint();
+    assert_eq!(empty.buffers, 2 * size_of::<Vec<u32>>() + 2 * 400);
+    assert_eq!(empty.op_log, size_of::<OpLog<ApplyToBoth<Vec<u32>>>>());
+    assert_eq!(empty.strategy, size_of::<HazardStrategy>());
+
+    writer.apply_to_both(|buffer: &mut Vec<u32>| buffer.push(1));
+    let mut reader = writer.reader();
+    let guard = reader.get();
+    let footprint = writer.footprint();
+    assert!(footprint.op_log > empty.op_log + CLOSURE_BYTES);
+    // the reader allocated a node
+    assert!(footprint.strategy > empty.strategy);
+    drop(guard);
+
+    // the closure sizer is called with both buffers
+    let mut calls = 0;
+    let custom = writer.footprint_with(|_| {
+        calls += 1;
+        1
+    });
+    assert_eq!(calls, 2);
+    assert_eq!(custom.buffers, 2 * size_of::<Vec<u32>>() + 2);
+}
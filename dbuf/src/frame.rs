@@ -0,0 +1,209 @@
+//! double buffering a header followed by a slice, with [`DstRawDbuf`]
+//!
+//! A [`Frame`] is a sequence number followed by its pixels. Both frames of a [`FramePair`] have
+//! the same number of pixels, and are stored back to back in one allocation. Readers see the
+//! header and pixels of one frame, so the sequence number always matches the pixels.
+//!
+//! ```
+//! use dbuf::{
+//!     frame::{Frame, FramePair},
+//!     ptrs::alloc::Owned,
+//!     raw::{DstRawDbuf, Shared, Writer},
+//!     strategy::HazardStrategy,
+//! };
+//!
+//! let frame = Frame::new_box(0, &[0; 16]);
+//! let buffers = DstRawDbuf::from_box(FramePair::new(&frame));
+//! let mut writer = Writer::new(Owned::new(Shared::from_raw_parts(HazardStrategy::new(), buffers)));
+//! let mut reader = writer.reader();
+//!
+//! let next = writer.split_mut().writer;
+//! next.seq = 1;
+//! next.pixels.fill(1);
+//! writer.swap_buffers();
+//!
+//! let frame = reader.get();
+//! assert_eq!(frame.seq, 1);
+//! assert!(frame.pixels.iter().all(|&pixel| pixel == 1));
+//! ```
+//!
+//! [`DstRawDbuf`]: crate::raw::DstRawDbuf
+
+use core::{mem, ptr};
+use std::{boxed::Box, vec};
+
+use crate::raw::SplitDst;
+
+/// A frame with a sequence number
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// the sequence number of the frame
+    pub seq: u64,
+    /// the pixels of the frame
+    pub pixels: [u8],
+}
+
+/// Two frames with the same number of pixels, see module docs for details
+#[repr(C)]
+pub struct FramePair {
+    /// the number of pixels in each frame, this is never written after construction
+    pixels: usize,
+    /// the two frames, each of them is [`words`] long
+    frames: [u64],
+}
+
+/// the number of `u64`s a frame with `pixels` pixels takes up, including padding
+const fn words(pixels: usize) -> usize {
+    1 + pixels.div_ceil(mem::size_of::<u64>())
+}
+
+/// create a frame pointer from a pointer to the start of the frame
+fn frame_ptr(start: *mut u64, pixels: usize) -> *mut Frame {
+    // the slice length becomes the length of the `pixels` field
+    ptr::slice_from_raw_parts_mut(start.cast::<u8>(), pixels) as *mut Frame
+}
+
+impl Frame {
+    /// Create a new frame
+    pub fn new_box(seq: u64, pixels: &[u8]) -> Box<Self> {
+        // a boxed slice of words has the same layout as the frame, since it's `repr(C)`
+        let words = vec![0_u64; words(pixels.len())].into_boxed_slice();
+        let frame = frame_ptr(Box::into_raw(words).cast::<u64>(), pixels.len());
+
+        // SAFETY: the frame was allocated with the same size and alignment as `Frame`
+        // and it's fully initialized
+        let mut frame = unsafe { Box::from_raw(frame) };
+        frame.seq = seq;
+        frame.pixels.copy_from_slice(pixels);
+        frame
+    }
+}
+
+impl FramePair {
+    /// Create a pair of frames which are both copies of `frame`
+    pub fn new(frame: &Frame) -> Box<Self> {
+        let pixels = frame.pixels.len();
+        let frames_len = 2 * words(pixels);
+
+        // one word for the pixel count, followed by both frames
+        let words = vec![0_u64; 1 + frames_len].into_boxed_slice();
+        // the slice length becomes the length of the `frames` field
+        let pair = ptr::slice_from_raw_parts_mut(Box::into_raw(words).cast::<u64>(), frames_len)
+            as *mut Self;
+
+        // SAFETY: the pair was allocated with the same size and alignment as `FramePair`
+        // and it's fully initialized
+        let mut pair = unsafe { Box::from_raw(pair) };
+        pair.pixels = pixels;
+
+        // SAFETY: the pair is valid
+        let (front, back) = unsafe { Self::split(&mut *pair) };
+        for half in [front, back] {
+            // SAFETY: the halves are valid, and we have unique access to the pair
+            let half = unsafe { &mut *half };
+            half.seq = frame.seq;
+            half.pixels.copy_from_slice(&frame.pixels);
+        }
+
+        pair
+    }
+
+    /// The number of pixels in each frame
+    pub fn pixels(&self) -> usize {
+        self.pixels
+    }
+}
+
+// SAFETY:
+// * each frame is `words(pixels)` long, and `frames` is twice that long, so both frames are in
+//   bounds, aligned to a `u64` like `Frame`, and disjoint
+// * the pixel count is outside both frames, and is never written after construction
+unsafe impl SplitDst for FramePair {
+    type Half = Frame;
+
+    unsafe fn split(this: *mut Self) -> (*mut Frame, *mut Frame) {
+        // SAFETY: the caller ensures that `this` is valid, and the pixel count isn't
+        // written after construction, so it can't race with writes to the frames
+        let pixels = unsafe { (*this).pixels };
+        // SAFETY: the caller ensures that `this` is valid
+        let start = unsafe { ptr::addr_of_mut!((*this).frames) }.cast::<u64>();
+
+        // SAFETY: `frames` is two frames long, so the second frame is in bounds
+        let back = unsafe { start.add(words(pixels)) };
+        (frame_ptr(start, pixels), frame_ptr(back, pixels))
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_frame_layout() {
+    for pixels in [0, 1, 7, 8, 9, 100] {
+        let frame = Frame::new_box(3, &vec![5; pixels]);
+        assert_eq!(mem::size_of_val(&*frame), words(pixels) * 8);
+        assert_eq!(mem::align_of_val(&*frame), mem::align_of::<u64>());
+        assert_eq!(frame.seq, 3);
+        assert_eq!(frame.pixels.len(), pixels);
+
+        let mut pair = FramePair::new(&frame);
+        assert_eq!(pair.pixels(), pixels);
+        assert_eq!(
+            mem::size_of_val(&*pair),
+            mem::size_of::<u64>() + 2 * mem::size_of_val(&*frame)
+        );
+
+        // SAFETY: the pair is valid
+        let (front, back) = unsafe { FramePair::split(&mut *pair) };
+        // SAFETY: the halves are valid and disjoint
+        let (front, back) = unsafe { (&mut *front, &mut *back) };
+        assert_eq!(front, &*frame);
+        assert_eq!(back, &*frame);
+
+        // writing to one frame doesn't change the other
+        front.seq = 4;
+        front.pixels.fill(6);
+        assert_eq!(back, &*frame);
+        assert_eq!(pair.pixels(), pixels);
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_frame_dbuf() {
+    use crate::{
+        ptrs::alloc::Owned,
+        raw::{DstRawDbuf, Shared, Writer},
+        strategy::TrackingStrategy,
+    };
+
+    let frame = Frame::new_box(0, &[0; 10]);
+    let buffers = DstRawDbuf::from_box(FramePair::new(&frame));
+    let mut writer = Writer::new(Owned::new(Shared::from_raw_parts(
+        TrackingStrategy::new(),
+        buffers,
+    )));
+    let mut reader = writer.reader();
+
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            for _ in 0..100 {
+                let frame = reader.get();
+                // the pixels always match the sequence number they were published with
+                let pixel = (frame.seq % 256) as u8;
+                assert!(frame.pixels.iter().all(|&p| p == pixel));
+            }
+        });
+
+        for seq in 1..100 {
+            let next = writer.split_mut().writer;
+            next.seq = seq;
+            next.pixels.fill((seq % 256) as u8);
+            writer.swap_buffers();
+        }
+    });
+
+    assert_eq!(writer.split().reader.seq, 99);
+    assert_eq!(writer.split().writer.seq, 98);
+}
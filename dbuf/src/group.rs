@@ -0,0 +1,322 @@
+//! Publishing several double buffers together
+//!
+//! Swapping a few writers one after another can't guarantee that readers see a consistent set
+//! of buffers. A reader may read the first buffer after it was swapped, and the second buffer
+//! right before it's swapped, combining two different publishes.
+//!
+//! A [`SwapGroup`] publishes all of its members together, and a [`GroupReader`] only hands out
+//! buffers which are from the same publish. This works because [`SwapGroup::publish_all`]
+//! finishes the last swap of *every* member before starting any new swap. So while a reader
+//! holds a guard to one member, no member can be swapped twice, and a reader only has to check
+//! that every guard agrees on the [`which`](crate::raw::ReadGuard::which) flag. If they don't,
+//! then a publish was in progress, and the reader drops the guards and tries again.
+//!
+//! WARNING: all members must start with the same writer buffer, and must only ever be swapped
+//! through the group. Otherwise the members will never agree, and the [`GroupReader`] will spin forever.
+//!
+//! # Shared flag
+//!
+//! A [`SwapGroup`] flips each member's flag in turn, so a reader which catches a publish in
+//! progress has to retry. Double buffers can instead share a single flag for which buffer is the
+//! writer buffer, by creating their shared state with [`SharedFlag::link`]. A [`FlagGroup`] then
+//! validates the swap of every member, flips the shared flag once, and only then captures the
+//! readers of every member. So every member is published at the same instant, and readers
+//! (including a [`GroupReader`]) only see a mix of publishes if they load the flag more than once.
+//!
+//! Linked double buffers use the [`Grouped`] strategy, which only allows swaps while their
+//! [`FlagGroup`] is publishing, and a [`FlagGroup`] refuses to publish unless it holds every
+//! writer which is linked to the flag. Otherwise another writer could be writing to
+//! the buffer which its readers are switched to.
+
+use core::convert::Infallible;
+#[cfg(feature = "alloc")]
+use std::{boxed::Box, vec::Vec};
+
+#[cfg(feature = "alloc")]
+mod shared_flag;
+
+#[cfg(feature = "alloc")]
+pub use shared_flag::{FlagGroup, GroupFlag, Grouped, GroupedWriterTag, LinkedMember, SharedFlag};
+
+use crate::{
+    delayed::DelayedWriter,
+    interface::{BufferOf, RawBuffersOf, Strategy, StrategyOf, StrongOf, StrongRef, WeakRef},
+    raw::Reader,
+};
+
+/// A writer which can be published as part of a [`SwapGroup`]
+///
+/// This is object safe, so different kinds of writers can be in the same group
+pub trait GroupMember {
+    /// which buffer is the writer buffer, see [`Writer::which`](crate::raw::Writer::which)
+    fn which(&self) -> bool;
+
+    /// start swapping the buffers, the last swap must already be finished
+    fn start_swap(&mut self);
+
+    /// check if the last swap is finished
+    fn is_swap_finished(&mut self) -> bool;
+
+    /// wait for the last swap to finish
+    fn finish_swap(&mut self);
+}
+
+impl<S: StrongRef> GroupMember for DelayedWriter<S>
+where
+    StrategyOf<S>: Strategy<ValidationError = Infallible>,
+{
+    fn which(&self) -> bool {
+        crate::raw::Writer::which(self)
+    }
+
+    fn start_swap(&mut self) {
+        debug_assert!(
+            DelayedWriter::is_swap_finished(self),
+            "the last swap must be finished before starting a new one"
+        );
+        self.start_buffer_swap()
+    }
+
+    fn is_swap_finished(&mut self) -> bool {
+        DelayedWriter::is_swap_finished(self)
+    }
+
+    fn finish_swap(&mut self) {
+        DelayedWriter::finish_swap(self);
+    }
+}
+
+impl<M: ?Sized + GroupMember> GroupMember for &mut M {
+    fn which(&self) -> bool {
+        M::which(self)
+    }
+
+    fn start_swap(&mut self) {
+        M::start_swap(self)
+    }
+
+    fn is_swap_finished(&mut self) -> bool {
+        M::is_swap_finished(self)
+    }
+
+    fn finish_swap(&mut self) {
+        M::finish_swap(self)
+    }
+}
+
+/// A set of writers which are always published together
+///
+/// see module docs for details
+#[cfg(feature = "alloc")]
+pub struct SwapGroup<'a> {
+    /// the writers in the group
+    members: Vec<Box<dyn GroupMember + 'a>>,
+}
+
+#[cfg(feature = "alloc")]
+impl Default for SwapGroup<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> SwapGroup<'a> {
+    /// create an empty group
+    pub const fn new() -> Self {
+        Self {
+            members: Vec::new(),
+        }
+    }
+
+    /// add a writer to the group
+    ///
+    /// # Panics
+    ///
+    /// if the writer's writer buffer doesn't match the rest of the group
+    pub fn push(&mut self, member: impl GroupMember + 'a) {
+        let mut member = Box::new(member);
+        member.finish_swap();
+
+        if let Some(first) = self.members.first_mut() {
+            first.finish_swap();
+            assert_eq!(
+                first.which(),
+                member.which(),
+                "all members of a swap group must start with the same writer buffer"
+            );
+        }
+
+        self.members.push(member);
+    }
+
+    /// the number of writers in the group
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns true if there are no writers in the group
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// swap the buffers of every writer
+    ///
+    /// This waits for the last swap of every writer to finish before starting any new swap,
+    /// then starts all the swaps back to back. The new swaps are finished lazily, on the next
+    /// call to [`publish_all`](Self::publish_all) or [`finish_all`](Self::finish_all).
+    pub fn publish_all(&mut self) {
+        self.finish_all();
+        self.members
+            .iter_mut()
+            .for_each(|member| member.start_swap());
+    }
+
+    /// wait for the last swap of every writer to finish
+    pub fn finish_all(&mut self) {
+        self.members
+            .iter_mut()
+            .for_each(|member| member.finish_swap());
+    }
+
+    /// check if the last swap of every writer is finished
+    pub fn is_finished(&mut self) -> bool {
+        self.members
+            .iter_mut()
+            .all(|member| member.is_swap_finished())
+    }
+}
+
+/// A set of readers which only see buffers from the same publish of a [`SwapGroup`]
+///
+/// The readers are stored as a tuple, see module docs for details
+pub struct GroupReader<R> {
+    /// the readers of each member of the group
+    readers: R,
+}
+
+impl<R> GroupReader<R> {
+    /// create a group reader from a tuple of readers
+    ///
+    /// the readers must be in the same order as the writers in the [`SwapGroup`]
+    pub const fn new(readers: R) -> Self {
+        Self { readers }
+    }
+
+    /// get the readers back
+    pub fn into_inner(self) -> R {
+        self.readers
+    }
+}
+
+/// implement [`GroupReader::read`] for a tuple of readers
+macro_rules! group_reader {
+    ($($reader:ident: $weak:ident),*) => {
+        impl<$($weak),*> GroupReader<($(Reader<$weak>,)*)>
+        where
+            $($weak: WeakRef<UpgradeError = Infallible>,)*
+        {
+            /// read a consistent set of buffers
+            ///
+            /// This retries until every buffer is from the same publish
+            pub fn read<T>(
+                &mut self,
+                f: impl FnOnce(($(&BufferOf<RawBuffersOf<StrongOf<$weak>>>,)*)) -> T,
+            ) -> T {
+                loop {
+                    let ($($reader,)*) = &mut self.readers;
+                    $(let $reader = $reader.get();)*
+
+                    let which = [$($reader.which()),*];
+                    if which.iter().all(|&flag| flag == which[0]) {
+                        return f(($(&*$reader,)*));
+                    }
+
+                    core::hint::spin_loop();
+                }
+            }
+        }
+    };
+}
+
+group_reader!(a: A, b: B);
+group_reader!(a: A, b: B, c: C);
+group_reader!(a: A, b: B, c: C, d: D);
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_swap_group() {
+    use crate::{ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy};
+
+    /// create a writer for a new double buffer
+    fn writer(
+    ) -> DelayedWriter<crate::ptrs::alloc::OwnedPtr<TrackingStrategy, crate::raw::RawDBuf<u32>>>
+    {
+        DelayedWriter::new(Writer::new(Owned::from_buffers(0, 0)))
+    }
+
+    /// a member which yields after each flip, so that readers are likely to see a partial publish
+    struct Slow<M>(M);
+
+    impl<M: GroupMember> GroupMember for Slow<M> {
+        fn which(&self) -> bool {
+            self.0.which()
+        }
+
+        fn start_swap(&mut self) {
+            self.0.start_swap();
+            std::thread::yield_now();
+        }
+
+        fn is_swap_finished(&mut self) -> bool {
+            self.0.is_swap_finished()
+        }
+
+        fn finish_swap(&mut self) {
+            self.0.finish_swap()
+        }
+    }
+
+    let mut transforms = writer();
+    let mut visibility = writer();
+    let mut materials = writer();
+
+    let mut reader =
+        GroupReader::new((transforms.reader(), visibility.reader(), materials.reader()));
+
+    const FRAMES: u32 = 500;
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut last = 0;
+            while last != FRAMES {
+                let (a, b, c) = reader.read(|(&a, &b, &c)| (a, b, c));
+                assert!(a == b && b == c, "torn read: {a} {b} {c}");
+                assert!(last <= a);
+                last = a;
+            }
+        });
+
+        for frame in 1..=FRAMES {
+            for writer in [&mut transforms, &mut visibility, &mut materials] {
+                *writer.finish_swap().split_mut().writer = frame;
+            }
+
+            let mut group = SwapGroup::new();
+            group.push(Slow(&mut transforms));
+            group.push(Slow(&mut visibility));
+            group.push(Slow(&mut materials));
+            group.publish_all();
+        }
+    });
+
+    // swapping a member outside of the group means the members don't agree on the writer buffer anymore
+    visibility.swap_buffers();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut group = SwapGroup::new();
+        group.push(&mut transforms);
+        group.push(&mut visibility);
+    }));
+    assert!(result.is_err());
+}
@@ -0,0 +1,596 @@
+//! Groups of double buffers which share their flag for which buffer is the writer buffer
+//!
+//! see [`group`](crate::group) for details
+
+#[cfg(not(feature = "loom"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+use std::{boxed::Box, sync::Arc, vec::Vec};
+
+use crate::{
+    delayed::DelayedWriter,
+    interface::{ActiveReaderInfo, CheapReaderTag, Strategy, StrategyIntrospect, StrongRef, Which},
+    raw::{AtomicFlag, Shared},
+};
+
+/// set in [`Link::writers`] while a [`FlagGroup`] is publishing
+const PUBLISHING: usize = 1 << (usize::BITS - 1);
+
+/// The state shared by every double buffer linked to a [`SharedFlag`]
+struct Link {
+    /// which buffer is the writer buffer, for every linked double buffer
+    flag: AtomicFlag,
+    /// the number of writers of linked double buffers, and [`PUBLISHING`]
+    writers: AtomicUsize,
+}
+
+/// A flag for which buffer is the writer buffer, which may be shared with other double buffers
+///
+/// This is the [`Which`] of [`Grouped`] strategies. A flag which isn't linked to a [`SharedFlag`]
+/// behaves like an [`AtomicFlag`]. A linked flag can only be flipped by a [`FlagGroup`], and
+/// flipping it any other way panics.
+pub struct GroupFlag(FlagKind);
+
+/// see [`GroupFlag`]
+enum FlagKind {
+    /// a flag which only belongs to this double buffer
+    Own(AtomicFlag),
+    /// a flag shared by all double buffers linked to the same [`SharedFlag`]
+    Linked(Arc<Link>),
+}
+
+impl GroupFlag {
+    /// the link this flag belongs to, if any
+    fn link(&self) -> Option<&Arc<Link>> {
+        match &self.0 {
+            FlagKind::Own(_) => None,
+            FlagKind::Linked(link) => Some(link),
+        }
+    }
+}
+
+// SAFETY:
+//
+// * `load` and `load_unsync` may not mutate the value
+//      * both only load the flag
+// * `flip` must switch which the value returned from `load` and `load_unsync`
+//      * an own flag is flipped, and flipping a linked flag panics. A linked flag is only flipped by a
+//        `FlagGroup`, which calls `Writer::try_start_buffer_swap_with` for every member instead
+// * `flip` must syncronize with `load`, i.e. all `flip`s must have a happens before relation with `load`
+//      * `AtomicFlag` syncronizes `flip` with `load`
+unsafe impl Which for GroupFlag {
+    #[cfg(feature = "loom")]
+    const INIT: Self = panic!("use the new function");
+    #[allow(clippy::declare_interior_mutable_const)]
+    #[cfg(not(feature = "loom"))]
+    const INIT: Self = Self(FlagKind::Own(AtomicFlag::INIT));
+
+    #[cfg(feature = "loom")]
+    fn new() -> Self {
+        Self(FlagKind::Own(AtomicFlag::new()))
+    }
+
+    #[inline]
+    unsafe fn load_unsync(&self) -> bool {
+        match &self.0 {
+            // SAFETY: guaranteed by the caller
+            FlagKind::Own(flag) => unsafe { flag.load_unsync() },
+            // other writers may be in the middle of a group's publish, so this has to syncronize
+            FlagKind::Linked(link) => link.flag.load(),
+        }
+    }
+
+    #[inline]
+    fn load(&self) -> bool {
+        match &self.0 {
+            FlagKind::Own(flag) => flag.load(),
+            FlagKind::Linked(link) => link.flag.load(),
+        }
+    }
+
+    #[inline]
+    fn flip(&self) {
+        match &self.0 {
+            FlagKind::Own(flag) => flag.flip(),
+            FlagKind::Linked(_) => {
+                panic!("a double buffer with a shared flag can only be swapped by its `FlagGroup`")
+            }
+        }
+    }
+}
+
+/// A flag for which buffer is the writer buffer, which is shared by many double buffers
+///
+/// Double buffers are linked to the flag with [`SharedFlag::link`], and their writers
+/// are published together by a [`FlagGroup`] from [`SharedFlag::group`].
+pub struct SharedFlag {
+    /// the shared state
+    link: Arc<Link>,
+}
+
+impl Default for SharedFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedFlag {
+    /// create a new flag, which isn't linked to any double buffer yet
+    pub fn new() -> Self {
+        Self {
+            link: Arc::new(Link {
+                #[cfg(not(feature = "loom"))]
+                flag: AtomicFlag::INIT,
+                #[cfg(feature = "loom")]
+                flag: AtomicFlag::new(),
+                writers: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// create the shared state of a double buffer which is linked to this flag
+    ///
+    /// The writer buffer is picked by the current value of the flag, so both buffers should start out the same.
+    pub fn link<S: Strategy, B>(&self, strategy: S, buffers: B) -> Shared<Grouped<S>, B> {
+        let strategy = Grouped {
+            inner: strategy,
+            link: self.link.clone(),
+        };
+        let which = GroupFlag(FlagKind::Linked(self.link.clone()));
+        Shared::from_raw_parts_linked(strategy, buffers, which)
+    }
+
+    /// create an empty group to publish the writers linked to this flag
+    pub fn group<'a>(&self) -> FlagGroup<'a> {
+        FlagGroup {
+            link: self.link.clone(),
+            members: Vec::new(),
+        }
+    }
+}
+
+/// A strategy for double buffers which are linked to a [`SharedFlag`]
+///
+/// This forwards everything to the inner strategy, but it only allows swaps during
+/// [`FlagGroup::publish_all`]
+pub struct Grouped<S> {
+    /// the inner strategy
+    inner: S,
+    /// the shared flag's state
+    link: Arc<Link>,
+}
+
+impl<S> Grouped<S> {
+    /// the inner strategy
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+/// The writer tag of [`Grouped`], which counts the writers of linked double buffers
+pub struct GroupedWriterTag<T> {
+    /// the inner writer tag
+    inner: T,
+    /// the shared flag's state
+    link: Arc<Link>,
+}
+
+impl<T> Drop for GroupedWriterTag<T> {
+    fn drop(&mut self) {
+        // Release to syncronize with `FlagGroup::publish_all`, so the writer's last writes
+        // happen before its buffers are flipped without it
+        self.link.writers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+// SAFETY: this forwards everything to the inner strategy. The flag is only flipped by `FlagGroup::publish_all`,
+// between each member's `validate_swap` and `capture_readers`
+unsafe impl<S: Strategy> Strategy for Grouped<S> {
+    type WriterTag = GroupedWriterTag<S::WriterTag>;
+    type ReaderTag = S::ReaderTag;
+    type Which = GroupFlag;
+    type ValidationToken = S::ValidationToken;
+    type ValidationError = S::ValidationError;
+    type Capture = S::Capture;
+    type ReaderGuard = S::ReaderGuard;
+    type Pause = S::Pause;
+
+    const READER_TAG_NEEDS_CONSTRUCTION: bool = S::READER_TAG_NEEDS_CONSTRUCTION;
+
+    unsafe fn create_writer_tag(&mut self) -> Self::WriterTag {
+        // wait for a publish to finish, since it must have every writer in the group
+        let mut writers = self.link.writers.load(Ordering::Relaxed);
+        loop {
+            if writers & PUBLISHING != 0 {
+                core::hint::spin_loop();
+                writers = self.link.writers.load(Ordering::Relaxed);
+                continue;
+            }
+
+            match self.link.writers.compare_exchange_weak(
+                writers,
+                writers + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => writers = current,
+            }
+        }
+
+        GroupedWriterTag {
+            // SAFETY: forwarded from the caller
+            inner: unsafe { self.inner.create_writer_tag() },
+            link: self.link.clone(),
+        }
+    }
+
+    unsafe fn create_reader_tag_from_writer(&self, parent: &Self::WriterTag) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.create_reader_tag_from_writer(&parent.inner) }
+    }
+
+    unsafe fn create_reader_tag_from_reader(&self, parent: &Self::ReaderTag) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.create_reader_tag_from_reader(parent) }
+    }
+
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.create_reader_tag() }
+    }
+
+    fn dangling_reader_tag() -> Self::ReaderTag {
+        S::dangling_reader_tag()
+    }
+
+    fn validate_swap(
+        &self,
+        writer: &mut Self::WriterTag,
+    ) -> Result<Self::ValidationToken, Self::ValidationError> {
+        assert!(
+            self.link.writers.load(Ordering::Relaxed) & PUBLISHING != 0,
+            "a double buffer with a shared flag can only be swapped by its `FlagGroup`"
+        );
+        self.inner.validate_swap(&mut writer.inner)
+    }
+
+    unsafe fn capture_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.capture_readers(&mut writer.inner, token) }
+    }
+
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.capture_current_readers(&mut writer.inner, token) }
+    }
+
+    unsafe fn have_readers_exited(
+        &self,
+        writer: &Self::WriterTag,
+        capture: &mut Self::Capture,
+    ) -> bool {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.have_readers_exited(&writer.inner, capture) }
+    }
+
+    fn pause(&self, writer: &Self::WriterTag, pause: &mut Self::Pause) {
+        self.inner.pause(&writer.inner, pause)
+    }
+
+    fn has_readers(&self) -> bool {
+        self.inner.has_readers()
+    }
+
+    fn any_current_readers(&self) -> bool {
+        self.inner.any_current_readers()
+    }
+
+    unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.begin_read_guard(reader) }
+    }
+
+    unsafe fn try_begin_read_guard(
+        &self,
+        reader: &mut Self::ReaderTag,
+        pause: &mut Self::Pause,
+    ) -> Result<Self::ReaderGuard, crate::interface::WouldBlock> {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.try_begin_read_guard(reader, pause) }
+    }
+
+    unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, guard: Self::ReaderGuard) {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.end_read_guard(reader, guard) }
+    }
+
+    unsafe fn preallocate_reader(&self, reader: &mut Self::ReaderTag) {
+        // SAFETY: forwarded from the caller
+        unsafe { self.inner.preallocate_reader(reader) }
+    }
+}
+
+impl<S: CheapReaderTag> CheapReaderTag for Grouped<S> {}
+
+impl<S: StrategyIntrospect> StrategyIntrospect for Grouped<S> {
+    fn active_readers(&self, f: impl FnMut(ActiveReaderInfo)) {
+        self.inner.active_readers(f)
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn guard_backtrace(
+        &self,
+        reader: &ActiveReaderInfo,
+    ) -> Option<std::sync::Arc<std::backtrace::Backtrace>> {
+        self.inner.guard_backtrace(reader)
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn guard_thread(&self, reader: &ActiveReaderInfo) -> Option<std::thread::ThreadId> {
+        self.inner.guard_thread(reader)
+    }
+
+    fn maintain(&self) {
+        self.inner.maintain()
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        self.inner.bookkeeping_bytes()
+    }
+}
+
+/// A writer of a double buffer which is linked to a [`SharedFlag`]
+///
+/// # Safety
+///
+/// * `flag` must return the writer's flag for which buffer is the writer buffer
+/// * `start_swap_with` must start a swap of the writer, and call `flip` exactly once, after
+///   validating the swap and before capturing the readers
+pub unsafe trait LinkedMember {
+    /// the writer's flag for which buffer is the writer buffer
+    fn flag(&self) -> &GroupFlag;
+
+    /// finish the last swap, and start a new swap which calls `flip` to flip the flag
+    fn start_swap_with(&mut self, flip: &mut dyn FnMut());
+
+    /// check if the last swap is finished
+    fn is_swap_finished(&mut self) -> bool;
+
+    /// wait for the last swap to finish
+    fn finish_swap(&mut self);
+}
+
+// SAFETY: the flag is the writer's flag, and `start_buffer_swap_with` calls `flip`
+// between `validate_swap` and `capture_readers`
+unsafe impl<S, I> LinkedMember for DelayedWriter<S>
+where
+    S: StrongRef<Strategy = Grouped<I>>,
+    I: Strategy<ValidationError = core::convert::Infallible>,
+{
+    fn flag(&self) -> &GroupFlag {
+        self.which_flag()
+    }
+
+    fn start_swap_with(&mut self, flip: &mut dyn FnMut()) {
+        // SAFETY: `flip` flips the shared flag, and the group holds every writer linked to it
+        unsafe { self.start_buffer_swap_with(flip) }
+    }
+
+    fn is_swap_finished(&mut self) -> bool {
+        DelayedWriter::is_swap_finished(self)
+    }
+
+    fn finish_swap(&mut self) {
+        DelayedWriter::finish_swap(self);
+    }
+}
+
+// SAFETY: forwarded to `M`
+unsafe impl<M: ?Sized + LinkedMember> LinkedMember for &mut M {
+    fn flag(&self) -> &GroupFlag {
+        M::flag(self)
+    }
+
+    fn start_swap_with(&mut self, flip: &mut dyn FnMut()) {
+        M::start_swap_with(self, flip)
+    }
+
+    fn is_swap_finished(&mut self) -> bool {
+        M::is_swap_finished(self)
+    }
+
+    fn finish_swap(&mut self) {
+        M::finish_swap(self)
+    }
+}
+
+/// The writers linked to a [`SharedFlag`], which are published by flipping the flag once
+///
+/// see [module docs](crate::group) for details
+pub struct FlagGroup<'a> {
+    /// the shared flag's state
+    link: Arc<Link>,
+    /// the writers in the group
+    members: Vec<Box<dyn LinkedMember + 'a>>,
+}
+
+/// clears [`PUBLISHING`] when it's dropped, even if a member panicked
+struct PublishGuard<'a>(&'a Link);
+
+impl Drop for PublishGuard<'_> {
+    fn drop(&mut self) {
+        self.0.writers.fetch_and(!PUBLISHING, Ordering::Release);
+    }
+}
+
+impl<'a> FlagGroup<'a> {
+    /// add a writer to the group
+    ///
+    /// # Panics
+    ///
+    /// if the writer isn't linked to the group's flag
+    pub fn push(&mut self, member: impl LinkedMember + 'a) {
+        assert!(
+            member
+                .flag()
+                .link()
+                .is_some_and(|link| Arc::ptr_eq(link, &self.link)),
+            "the writer isn't linked to the group's flag"
+        );
+        self.members.push(Box::new(member));
+    }
+
+    /// the number of writers in the group
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns true if there are no writers in the group
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// swap the buffers of every writer, by flipping the shared flag once
+    ///
+    /// This waits for the last swap of every writer to finish, then validates the swap of every writer,
+    /// flips the flag, and captures the readers of every writer. The new swaps are finished lazily,
+    /// on the next call to [`publish_all`](Self::publish_all) or [`finish_all`](Self::finish_all).
+    ///
+    /// # Panics
+    ///
+    /// if a writer which is linked to the flag isn't in the group, since its buffers would be flipped
+    /// while it may be writing to them
+    pub fn publish_all(&mut self) {
+        self.finish_all();
+
+        let count = self.members.len();
+        // Acquire to syncronize with the writer tags which were dropped
+        assert!(
+            self.link
+                .writers
+                .compare_exchange(
+                    count,
+                    count | PUBLISHING,
+                    Ordering::Acquire,
+                    Ordering::Relaxed
+                )
+                .is_ok(),
+            "every writer linked to the shared flag must be in the group"
+        );
+        let _guard = PublishGuard(&self.link);
+
+        let flag = &self.link.flag;
+        start_nested(&mut self.members, &mut || flag.flip());
+    }
+
+    /// wait for the last swap of every writer to finish
+    pub fn finish_all(&mut self) {
+        self.members
+            .iter_mut()
+            .for_each(|member| member.finish_swap());
+    }
+
+    /// check if the last swap of every writer is finished
+    pub fn is_finished(&mut self) -> bool {
+        self.members
+            .iter_mut()
+            .all(|member| member.is_swap_finished())
+    }
+}
+
+/// start the swaps of all `members`, and flip the flag once every swap was validated
+fn start_nested(members: &mut [Box<dyn LinkedMember + '_>], flip: &mut dyn FnMut()) {
+    match members.split_first_mut() {
+        Some((first, rest)) => first.start_swap_with(&mut || start_nested(rest, flip)),
+        None => flip(),
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_flag_group() {
+    use crate::{
+        group::GroupReader,
+        ptrs::alloc::{Owned, OwnedPtr},
+        raw::{RawDBuf, Writer},
+        strategy::HazardStrategy,
+    };
+
+    let flag = SharedFlag::new();
+
+    /// create a writer for a new double buffer which is linked to the flag
+    fn writer(flag: &SharedFlag) -> DelayedWriter<OwnedPtr<Grouped<HazardStrategy>, RawDBuf<u32>>> {
+        let shared = flag.link(HazardStrategy::new(), RawDBuf::new(0, 0));
+        DelayedWriter::new(Writer::new(Owned::new(shared)))
+    }
+
+    let mut transforms = writer(&flag);
+    let mut visibility = writer(&flag);
+    let mut materials = writer(&flag);
+
+    let mut reader =
+        GroupReader::new((transforms.reader(), visibility.reader(), materials.reader()));
+
+    const FRAMES: u32 = 500;
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut last = 0;
+            while last != FRAMES {
+                let (a, b, c) = reader.read(|(&a, &b, &c)| (a, b, c));
+                assert!(a == b && b == c, "torn read: {a} {b} {c}");
+                assert!(last <= a);
+                last = a;
+            }
+        });
+
+        for frame in 1..=FRAMES {
+            for writer in [&mut transforms, &mut visibility, &mut materials] {
+                *writer.finish_swap().split_mut().writer = frame;
+            }
+
+            let mut group = flag.group();
+            group.push(&mut transforms);
+            group.push(&mut visibility);
+            group.push(&mut materials);
+            group.publish_all();
+        }
+    });
+
+    // every member flips together
+    assert!(transforms.which() == visibility.which() && visibility.which() == materials.which());
+
+    // members can't be swapped outside of the group
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        visibility.swap_buffers();
+    }));
+    assert!(result.is_err());
+
+    // and the group must have every member
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut group = flag.group();
+        group.push(&mut transforms);
+        group.push(&mut visibility);
+        group.publish_all();
+    }));
+    assert!(result.is_err());
+
+    // once a writer is dropped, the rest of the group can be published without it
+    drop(materials);
+    let mut group = flag.group();
+    group.push(&mut transforms);
+    group.push(&mut visibility);
+    group.publish_all();
+    group.finish_all();
+}
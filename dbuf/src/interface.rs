@@ -88,6 +88,8 @@ pub unsafe trait WeakRef: Clone {
     type Strong: StrongRef<Weak = Self>;
     /// The error when upgrading to a strong reference
     type UpgradeError;
+    /// How read guards keep the shared buffer alive, see [`GuardStorage`]
+    type GuardStorage: GuardStorage<Self>;
 
     /// Upgrade to a strong ref
     ///
@@ -100,6 +102,53 @@ pub unsafe trait WeakRef: Clone {
     fn as_ref(&self) -> Option<&<Self::Strong as Deref>::Target> {
         None
     }
+
+    /// Get a pointer to the shared buffer, without upgrading
+    ///
+    /// This must be the same for all clones of a weak ref, and must not change
+    /// after the shared buffer is dropped
+    fn as_ptr(this: &Self) -> *const <Self::Strong as Deref>::Target;
+
+    /// Check if both weak refs point to the same shared buffer
+    fn ptr_eq(this: &Self, other: &Self) -> bool {
+        core::ptr::eq(Self::as_ptr(this), Self::as_ptr(other))
+    }
+}
+
+/// How a read guard keeps the shared buffer alive while it's reading
+///
+/// If the weak ref is a strong ref itself (like `&Shared` or `OwnedPtr`) then the guard only needs
+/// to point at the shared buffer, see [`BorrowedStorage`](crate::ptrs::BorrowedStorage).
+/// Otherwise it needs to hold the upgraded strong ref, see [`UpgradedStorage`](crate::ptrs::UpgradedStorage).
+///
+/// # Safety
+///
+/// * `shared` must return the shared buffer that the weak ref passed to `new` points to
+/// * the shared buffer must stay valid until `Self` is dropped, as long as that weak ref is alive
+pub unsafe trait GuardStorage<W: WeakRef>: Sized {
+    /// create the storage for a read guard from the reader's weak ref
+    fn new(weak: &W) -> Result<Self, W::UpgradeError>;
+
+    /// get the shared buffer
+    ///
+    /// # Safety
+    ///
+    /// the weak ref passed to `new` must still be alive
+    unsafe fn shared(&self) -> &Shared<StrategyOf<W::Strong>, RawBuffersOf<W::Strong>>;
+}
+
+/// A reference to the underlying shared buffer whose writer may not exist yet
+///
+/// # Safety
+///
+/// * if `try_weak` returns `Some` then the writer tag must have already been created
+/// * once `try_weak` returns `Some` it must always return `Some`
+pub unsafe trait PendingRef {
+    /// The weak reference this resolves to once the writer exists
+    type Weak: WeakRef;
+
+    /// Get a weak reference to the shared buffer if the writer exists
+    fn try_weak(&self) -> Option<Self::Weak>;
 }
 
 /// The raw unsyncronized double buffer
@@ -115,6 +164,28 @@ pub unsafe trait RawBuffers {
 
     /// get a pointer to the two buffers
     fn get(&self, which: bool) -> (*mut Self::Buffer, *const Self::Buffer);
+
+    /// Returns true if the two buffers have the same shape, so they may be swapped freely
+    ///
+    /// After a swap readers see the buffer which used to be the writer buffer, so any difference
+    /// between the two (like the length of a slice) becomes visible to them. Buffers with
+    /// differently shaped halves must return false, and then [`Writer`](crate::raw::Writer)
+    /// refuses to swap them in debug builds. Readers which cache something derived from a
+    /// snapshot (like its `len()`) should key it on the snapshot's [`BufferId`](crate::raw::BufferId)
+    fn halves_interchangeable(&self) -> bool {
+        true
+    }
+}
+
+/// Raw buffers which can be created from two buffers
+///
+/// This allows code which is generic over the layout of the buffers to create them
+pub trait FromBuffers: RawBuffers
+where
+    Self::Buffer: Sized,
+{
+    /// create the raw buffers from the front and back buffers
+    fn from_buffers(front: Self::Buffer, back: Self::Buffer) -> Self;
 }
 
 /// The syncronization strategy
@@ -126,6 +197,10 @@ pub unsafe trait Strategy {
     /// The writer tag, will be owned by the writer and can identify writers to the strategy
     type WriterTag;
     /// The reader tag, will be owned by the reader and can identify readers to the strategy
+    ///
+    /// If the reader tag is `Copy`, then copies of one tag may hold read guards at the same time
+    /// (see [`Reader::copy_tag`](crate::raw::Reader::copy_tag)), so `end_read_guard` must only
+    /// rely on the guard to find out what to release, and not on any state cached in the tag
     type ReaderTag;
     /// The type of boolean flag to use
     type Which: Which;
@@ -166,6 +241,21 @@ pub unsafe trait Strategy {
     /// the reader tag must be managed by this strategy
     unsafe fn create_reader_tag_from_reader(&self, parent: &Self::ReaderTag) -> Self::ReaderTag;
 
+    /// Creates a reader tag managed by this strategy without a parent tag
+    ///
+    /// This is used by readers which were created before the writer, see [`Reader::new_pending`](crate::raw::Reader::new_pending).
+    /// The default implementation panics, so strategies which don't override it can't be used with pending readers
+    ///
+    /// # Safety
+    ///
+    /// a writer tag must have already been created by this strategy
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        panic!(
+            "{} can't create a reader tag without a parent tag",
+            core::any::type_name::<Self>()
+        )
+    }
+
     /// Creates a reader tag not managed by this strategy out of thin air
     fn dangling_reader_tag() -> Self::ReaderTag;
 
@@ -187,6 +277,21 @@ pub unsafe trait Strategy {
         validation_token: Self::ValidationToken,
     ) -> Self::Capture;
 
+    /// Capture all the readers that currently hold a read guard, without swapping the buffers
+    ///
+    /// Once `have_readers_exited` returns true for this capture, every read guard which was
+    /// acquired before this call has been dropped.
+    ///
+    /// # Safety
+    ///
+    /// * The validation token must have come from a call to `validate_swap` right before this
+    /// * Must poll `have_readers_exited` until it returns true before calling `validate_swap` again
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture;
+
     /// Check if all the readers captured at the specified capture point have exited
     ///
     /// # Safety
@@ -202,6 +307,24 @@ pub unsafe trait Strategy {
     /// Pause the current thread while waiting for readers to exit
     fn pause(&self, _writer: &Self::WriterTag, _pause: &mut Self::Pause) {}
 
+    /// Returns false if no reader tag was ever created from this strategy
+    ///
+    /// This is a hint for writers, strategies which don't keep track of their readers always return true.
+    /// It must return true if a reader tag created from this strategy is still alive, but may return true
+    /// after all readers were dropped.
+    fn has_readers(&self) -> bool {
+        true
+    }
+
+    /// Returns true if a reader may currently hold a read guard, so a swap started now may have to wait
+    ///
+    /// This is advisory, since a reader may begin a read guard right after this returns false.
+    /// It should return true while a read guard is held, but may return true without one.
+    /// Strategies which can't tell fall back to [`has_readers`](Strategy::has_readers)
+    fn any_current_readers(&self) -> bool {
+        self.has_readers()
+    }
+
     /// begin a read guard, this locks the buffer and allows `capture_readers` to see which readers are actively reading
     ///
     /// # Panics
@@ -213,6 +336,28 @@ pub unsafe trait Strategy {
     /// the reader tag may not be dangling
     unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard;
 
+    /// try to begin a read guard, without blocking for an unbounded amount of time
+    ///
+    /// If beginning the guard would block, then this may pause at most once (using `pause` to
+    /// track any backoff state between calls) before returning [`WouldBlock`].
+    /// The default implementation calls `begin_read_guard`, so it never fails
+    ///
+    /// # Panics
+    ///
+    /// may panic if `begin_read_guard` is called twice before calling `end_read_guard`
+    ///
+    /// # Safety
+    ///
+    /// the reader tag may not be dangling
+    unsafe fn try_begin_read_guard(
+        &self,
+        reader: &mut Self::ReaderTag,
+        _pause: &mut Self::Pause,
+    ) -> Result<Self::ReaderGuard, WouldBlock> {
+        // SAFETY: the caller ensures that the reader tag isn't dangling
+        Ok(unsafe { self.begin_read_guard(reader) })
+    }
+
     /// end the read guard for the given reader
     ///
     /// # Safety
@@ -220,6 +365,87 @@ pub unsafe trait Strategy {
     /// * the reader must have been created by this strategy
     /// * the reader specified must have created the guard
     unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, guard: Self::ReaderGuard);
+
+    /// allocate anything the reader will need for its read guards up front,
+    /// so that `begin_read_guard` doesn't allocate later
+    ///
+    /// The default implementation does nothing, for strategies which never allocate in `begin_read_guard`
+    ///
+    /// # Safety
+    ///
+    /// the reader tag may not be dangling
+    unsafe fn preallocate_reader(&self, _reader: &mut Self::ReaderTag) {}
+}
+
+/// The error returned from [`Strategy::try_begin_read_guard`] if beginning a read guard would block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldBlock;
+
+/// What a strategy knows about a reader which currently holds a read guard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActiveReaderInfo {
+    /// identifies the reader's slot in the strategy (a node address, or an index)
+    ///
+    /// this is `0` if the strategy can't tell the reader apart from other readers
+    pub id: usize,
+    /// the generation the reader started reading in, or `0` if the strategy doesn't track generations
+    pub generation: usize,
+}
+
+/// A strategy whose reader tags are cheap to create and drop, so a fresh tag can be made for every read
+///
+/// This is used by [`SharedReader`](crate::raw::SharedReader), which doesn't keep a tag around.
+/// Strategies which allocate for each tag, like [`TrackingStrategy`](crate::strategy::TrackingStrategy),
+/// shouldn't implement this
+pub trait CheapReaderTag: Strategy {}
+
+/// A strategy which can report which readers are currently reading,
+/// this is useful to find out who is blocking a stuck swap
+pub trait StrategyIntrospect: Strategy {
+    /// call `f` for each reader which currently holds a read guard
+    fn active_readers(&self, f: impl FnMut(ActiveReaderInfo));
+
+    /// where the reader acquired its read guard, if the strategy recorded it
+    ///
+    /// see [`debug_checks`](crate::debug_checks). By default this returns `None`
+    #[cfg(feature = "debug-checks")]
+    fn guard_backtrace(
+        &self,
+        _reader: &ActiveReaderInfo,
+    ) -> Option<std::sync::Arc<std::backtrace::Backtrace>> {
+        None
+    }
+
+    /// the thread which acquired the reader's read guard, if the strategy recorded it
+    ///
+    /// see [`debug_checks`](crate::debug_checks). By default this returns `None`
+    #[cfg(feature = "debug-checks")]
+    fn guard_thread(&self, _reader: &ActiveReaderInfo) -> Option<std::thread::ThreadId> {
+        None
+    }
+
+    /// clean up after readers which were dropped, without waiting for the next swap
+    ///
+    /// This is meant to be called periodically from the writer, see [`Writer::maintain`](crate::raw::Writer::maintain).
+    /// By default this does nothing
+    fn maintain(&self) {}
+
+    /// the number of bytes the strategy allocated to keep track of readers
+    ///
+    /// This is used for the [`Footprint`](crate::footprint::Footprint) of a writer,
+    /// and doesn't include the size of the strategy itself. By default this returns `0`
+    fn bookkeeping_bytes(&self) -> usize {
+        0
+    }
+}
+
+/// A strategy or waiter which can make an unused copy of itself with the same configuration
+///
+/// This is used to rebuild a writer while the old readers still use the old strategy,
+/// see [`Writer::fresh_strategy`](crate::raw::Writer::fresh_strategy)
+pub trait FreshCopy {
+    /// a copy of `self` with the same configuration, which isn't used by any readers or writers
+    fn fresh_copy(&self) -> Self;
 }
 
 /// A token for which buffer is on top
@@ -277,6 +503,33 @@ pub unsafe trait Which: Sized {
     fn flip(&self);
 }
 
+/// A buffer which may be copied out while the writer is writing to it,
+/// see [`Reader::optimistic_read`](crate::raw::Reader::optimistic_read)
+///
+/// The writer writes to its buffer with plain, non-atomic writes, while optimistic reads copy
+/// the buffer with relaxed atomic loads. So a copy made while the writer is writing may mix
+/// bytes from before and after the write, and it's only thrown away after it was made.
+///
+/// # Safety
+///
+/// Every bit pattern of the size of `Self` must be a valid `Self`, so
+/// that a torn copy is still a valid value. For example integers and arrays of integers,
+/// but not `bool`, `char`, references, or types with padding.
+pub unsafe trait TearableRead: Copy {}
+
+/// implement `TearableRead` for types where every bit pattern is valid
+macro_rules! tearable {
+    ($($ty:ty)*) => {$(
+        // SAFETY: every bit pattern is a valid integer or float
+        unsafe impl TearableRead for $ty {}
+    )*};
+}
+
+tearable!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+// SAFETY: arrays have no padding between elements, and every element may be torn
+unsafe impl<T: TearableRead, const N: usize> TearableRead for [T; N] {}
+
 /// A strategy for parking threads
 pub trait WaitStrategy {
     /// A value which can be used to store state between subsequent calls to park
@@ -21,11 +21,36 @@ pub mod wait;
 
 pub mod interface;
 
+pub mod cached;
+
+#[cfg(feature = "debug-checks")]
+pub mod debug_checks;
 pub mod delayed;
 #[cfg(feature = "alloc")]
-pub mod op;
+pub mod delta;
 #[cfg(feature = "alloc")]
+pub mod erased;
+#[cfg(feature = "flight-recorder")]
+pub mod flight_recorder;
+pub mod footprint;
+#[cfg(feature = "alloc")]
+pub mod frame;
+pub mod group;
+pub mod op;
 pub mod op_log;
+#[cfg(feature = "std")]
+pub mod poison;
+#[cfg(feature = "alloc")]
+pub mod pool;
+#[cfg(feature = "alloc")]
+pub mod profiles;
+#[cfg(feature = "alloc")]
+pub mod resilient;
+#[cfg(feature = "tracing")]
+pub mod trace;
+pub mod warm;
+
+pub use raw::ReadError;
 
 #[doc(hidden)]
 pub mod macros {
@@ -38,9 +63,16 @@ pub mod macros {
 
     #[cold]
     pub fn assert_send_sync<T: Send + Sync>() {}
+
+    #[cfg(feature = "alloc")]
+    pub fn leak<T>(value: T) -> &'static mut T {
+        std::boxed::Box::leak(std::boxed::Box::new(value))
+    }
 }
 
+/// Create a writer to a double buffer in a `static`, the shared state must be built in a const context
 ///
+/// This panics if it's called more than once, see [`try_static_writer`] and [`lazy_static_writer`]
 #[macro_export]
 macro_rules! static_writer {
     (static $name:ident: $shared_ty:ty = $shared:expr) => {{
@@ -64,13 +96,16 @@ macro_rules! static_writer {
 
         // SAFETY: we ensure that we're the only one to access SHARED by guarding access to FLAG
         // ONLY the first call to `static_writer` will be able to get here, so we have unqiue access
-        let shared: &mut $crate::Shared<_, _> = unsafe { &mut SHARED };
+        let shared: &mut $crate::raw::Shared<_, _> =
+            unsafe { &mut *$crate::macros::core::ptr::addr_of_mut!(SHARED) };
 
         $crate::raw::Writer::new(shared)
     }};
 }
 
+/// Create a writer to a double buffer in a `static`, see [`static_writer`]
 ///
+/// This returns `None` if it's called more than once
 #[macro_export]
 macro_rules! try_static_writer {
     (static $name:ident: $shared_ty:ty = $shared:expr) => {{
@@ -93,7 +128,65 @@ macro_rules! try_static_writer {
         } else {
             // SAFETY: we ensure that we're the only one to access SHARED by guarding access to FLAG
             // ONLY the first call to `static_writer` will be able to get here, so we have unqiue access
-            let shared: &mut $crate::raw::Shared<_, _> = unsafe { &mut SHARED };
+            let shared: &mut $crate::raw::Shared<_, _> = unsafe { &mut *$crate::macros::core::ptr::addr_of_mut!(SHARED) };
+
+            Some($crate::raw::Writer::new(shared))
+        }
+    }};
+}
+
+/// Like [`static_writer`], but the shared state is created when the writer is first created,
+/// and leaked so it lives for the rest of the program
+///
+/// [`static_writer`] needs the shared state to be built in a const context. Most strategies
+/// and buffers can be (outside of `loom`), but not ones which allocate their initial buffers
+/// or a `MockStrategy` with a script. This gives a `Writer<&'static Shared<_, _>>` either way,
+/// same as [`static_writer`]
+///
+/// ```
+/// use dbuf::{raw::{RawDBuf, Shared}, strategy::TrackingStrategy};
+///
+/// let mut writer = dbuf::lazy_static_writer!(
+///     static SHARED: Shared<TrackingStrategy, RawDBuf<Vec<u32>>> =
+///         Shared::from_raw_parts(TrackingStrategy::new(), RawDBuf::new(vec![1, 2], vec![1, 2]))
+/// );
+/// writer.split_mut().writer.push(3);
+/// writer.swap_buffers();
+/// assert_eq!(*writer.reader().get(), [1, 2, 3]);
+/// ```
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! lazy_static_writer {
+    (static $name:ident: $shared_ty:ty = $shared:expr) => {
+        match $crate::try_lazy_static_writer!(static $name: $shared_ty = $shared) {
+            Some(writer) => writer,
+            None => $crate::macros::static_writer_failed(),
+        }
+    };
+}
+
+/// Like [`try_static_writer`], but the shared state is created when the writer is first created,
+/// see [`lazy_static_writer`]
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! try_lazy_static_writer {
+    (static $name:ident: $shared_ty:ty = $shared:expr) => {{
+        static FLAG: $crate::macros::core::sync::atomic::AtomicBool =
+            $crate::macros::core::sync::atomic::AtomicBool::new(true);
+
+        if FLAG
+            .compare_exchange(
+                true,
+                false,
+                $crate::macros::core::sync::atomic::Ordering::Relaxed,
+                $crate::macros::core::sync::atomic::Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            None
+        } else {
+            // only the first call gets here, so the shared state is only created and leaked once
+            let shared: &'static mut $shared_ty = $crate::macros::leak($shared);
 
             Some($crate::raw::Writer::new(shared))
         }
@@ -102,6 +195,7 @@ macro_rules! try_static_writer {
 
 #[doc(hidden)]
 #[test]
+#[cfg(not(feature = "loom"))]
 fn test_static_writer() {
     let count = 2;
     let waiter = std::sync::Arc::new(std::sync::Barrier::new(count));
@@ -126,3 +220,53 @@ fn test_static_writer() {
             == 1
     );
 }
+
+/// Generate disjoint mutable views of the fields of a buffer
+///
+/// This creates a struct with a `&mut` to each of the listed fields of the writer buffer,
+/// and a `reader` field with the reader buffer, and implements [`FieldSplit`](crate::raw::FieldSplit)
+/// for the buffer so that [`Writer::split_fields`](crate::raw::Writer::split_fields) can be used
+///
+/// ```
+/// struct World {
+///     positions: Vec<(f32, f32)>,
+///     names: Vec<String>,
+/// }
+///
+/// dbuf::project! {
+///     pub struct WorldViews<'a> for World {
+///         pub positions: Vec<(f32, f32)>,
+///         pub names: Vec<String>,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! project {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $views:ident<$lt:lifetime> for $buffer:ty {
+            $($field_vis:vis $field:ident: $field_ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $views<$lt> {
+            $(
+                #[doc = concat!("the writer buffer's `", stringify!($field), "`")]
+                $field_vis $field: &$lt mut $field_ty,
+            )*
+            /// the reader buffer
+            $vis reader: &$lt $buffer,
+        }
+
+        impl<$lt> $crate::raw::FieldSplit<$lt> for $buffer {
+            type Views = $views<$lt>;
+
+            fn split_fields(writer: &$lt mut Self, reader: &$lt Self) -> Self::Views {
+                $views {
+                    $($field: &mut writer.$field,)*
+                    reader,
+                }
+            }
+        }
+    };
+}
@@ -1,6 +1,7 @@
 //! An operation based writer
 //!
-//! [`OpWriter`] is literally just a pair of [`OpLog`] and a [`DelayedWriter`].
+//! [`OpWriter`] is literally just a pair of [`OpLog`] and a [`DelayedWriter`]. (or any other
+//! [`OpLogBackend`], e.g. an [`ArrayOpLog`](crate::op_log::ArrayOpLog) when allocation isn't available)
 //! This allows it to keep swaps fast (by delaying them until all readers have exited)
 //! and keep the two halves consistent using the [`OpLog`] to keep track of which operations
 //! have been applied to which buffers.
@@ -11,54 +12,696 @@
 //!
 //! WARNING: if any operation panics, then the [`OpWriter`] makes no guarntees about the consistency of the two buffers.
 //! The only guarntee is that there will be no undefined behavior. (certain [`Operation`]s may provided further guarntees)
+//!
+//! To prevent readers from seeing the inconsistent buffers, a panicking operation poisons the [`OpWriter`].
+//! While poisoned, the [`OpWriter`] refuses to swap the buffers until the poison is cleared with
+//! [`clear_poison_with`](OpWriter::clear_poison_with), which repairs the writer buffer from the reader buffer.
+//!
+//! A [`Validator`] can check the writer buffer before each publish, and refuse to publish it,
+//! see [`OpWriter::with_validator`].
 
-use std::{convert::Infallible, ops::Deref};
+use core::{convert::Infallible, marker::PhantomData, ops::Deref};
+#[cfg(feature = "alloc")]
+use std::vec::Vec;
+#[cfg(feature = "std")]
+use std::{boxed::Box, collections::VecDeque, time::Instant};
 
+#[cfg(feature = "alloc")]
+use crate::op_log::{ApplyToBoth, Boxed, OpLog, OpStorage};
 use crate::{
-    delayed::DelayedWriter,
-    interface::{BufferOf, CaptureOf, RawBuffersOf, Strategy, StrategyOf, StrongRef, WriterTag},
-    op_log::{OpLog, Operation},
-    raw::Writer,
+    delayed::{DelayedWriter, NoPendingSwap},
+    footprint::{Footprint, MemoryUsage},
+    interface::{
+        BufferOf, CaptureOf, RawBuffersOf, Strategy, StrategyIntrospect, StrategyOf, StrongRef,
+        ValidationErrorOf, WriterTag,
+    },
+    op_log::{OpIter, OpIterMut, OpLogBackend, Operation, NOT_CONTIGUOUS},
+    raw::{ReadError, SwapStats, SwapTotals, Writer},
 };
 
 /// An operation based writer
 ///
-/// see module docs and [`OpLog`] for details
-pub struct OpWriter<S, O, W = WriterTag<StrategyOf<S>>, C = CaptureOf<StrategyOf<S>>> {
+/// see module docs and [`OpLogBackend`] for details
+pub struct OpWriter<
+    S,
+    O,
+    #[cfg(feature = "alloc")] L = OpLog<O>,
+    #[cfg(not(feature = "alloc"))] L,
+    V = NoValidator,
+    W = WriterTag<StrategyOf<S>>,
+    C = CaptureOf<StrategyOf<S>>,
+> {
     /// the underlying writer
     writer: DelayedWriter<S, W, C>,
     /// the operation log
-    op_log: OpLog<O>,
+    op_log: L,
+    /// the stats from the last finished swap
+    last_publish_stats: SwapStats,
+    /// the totals of the stats of all finished swaps
+    publish_totals: SwapTotals,
+    /// the number of times the buffers were swapped
+    epoch: u64,
+    /// true if an operation panicked while being applied
+    poisoned: bool,
+    /// checks the writer buffer before each publish
+    validator: V,
+    /// true if the validator rejected the writer buffer, or the last publish was reverted,
+    /// so it already has the applied operations
+    rejected: bool,
+    /// the checks done after each publish, if strict mode is on
+    strict: Option<StrictMode<S, W, C>>,
+    /// the most recent publishes, this is only allocated once the history is enabled,
+    /// so that it doesn't make every op writer larger
+    #[cfg(feature = "std")]
+    history: Option<Box<PublishHistory>>,
+    /// the type of operations in the log
+    _op: PhantomData<O>,
+}
+
+/// The result of comparing the two buffers of an [`OpWriter`]
+///
+/// see [`OpWriter::verify_buffers_eq`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    /// the two buffers are equal
+    Consistent,
+    /// the two buffers are different, even though the same operations were applied to both
+    Diverged,
+    /// there are unpublished operations, so the buffers can't be compared
+    PendingOps,
+}
+
+/// A view of the published buffer, the writer buffer, and the unpublished operations
+///
+/// see [`OpWriter::diff`]
+#[derive(Debug)]
+pub struct OpDiff<'a, B: ?Sized, O> {
+    /// the buffer the readers can see
+    published: &'a B,
+    /// the buffer the writer will apply the unpublished operations to
+    write_buffer: &'a B,
+    /// the unpublished operations
+    unapplied_ops: OpIter<'a, O>,
+}
+
+impl<'a, B: ?Sized, O> OpDiff<'a, B, O> {
+    /// the buffer the readers can see
+    pub fn published(&self) -> &'a B {
+        self.published
+    }
+
+    /// the buffer the writer will apply the unpublished operations to
+    pub fn write_buffer(&self) -> &'a B {
+        self.write_buffer
+    }
+
+    /// the operations which will be applied on the next publish
+    ///
+    /// # Panics
+    ///
+    /// if the operations are [boxed](crate::op_log::Boxed), see [`unapplied_iter`](Self::unapplied_iter)
+    pub fn unapplied_ops(&self) -> &'a [O] {
+        self.unapplied_ops.as_slice().expect(NOT_CONTIGUOUS)
+    }
+
+    /// the operations which will be applied on the next publish, no matter how they are stored
+    pub fn unapplied_iter(&self) -> OpIter<'a, O> {
+        self.unapplied_ops.clone()
+    }
+}
+
+/// A check on the writer buffer which runs right before each publish
+///
+/// see [`OpWriter::with_validator`]
+pub trait Validator<B: ?Sized> {
+    /// the error returned if the writer buffer shouldn't be published
+    type Error;
+
+    /// check the writer buffer, after the operations were applied to it
+    fn validate(&mut self, buffer: &B) -> Result<(), Self::Error>;
+}
+
+/// The default [`Validator`], which accepts every writer buffer
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoValidator;
+
+impl<B: ?Sized> Validator<B> for NoValidator {
+    type Error = Infallible;
+
+    #[inline]
+    fn validate(&mut self, _buffer: &B) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl<B: ?Sized, E, F: FnMut(&B) -> Result<(), E>> Validator<B> for F {
+    type Error = E;
+
+    fn validate(&mut self, buffer: &B) -> Result<(), Self::Error> {
+        self(buffer)
+    }
+}
+
+/// The error returned from [`OpWriter::try_publish_validated`]
+///
+/// `SE` is the strategy's [`ValidationError`](Strategy::ValidationError)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishError<E, SE = Infallible> {
+    /// an operation panicked, see [`PoisonedError`]
+    Poisoned,
+    /// the validator rejected the writer buffer, so it wasn't published
+    Invalid(E),
+    /// the strategy couldn't start the swap, so the writer buffer wasn't published,
+    /// see [`Strategy::validate_swap`]
+    Strategy(SE),
+}
+
+/// The error returned when trying to swap the buffers of a poisoned [`OpWriter`]
+pub struct PoisonedError;
+
+impl core::fmt::Debug for PoisonedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("an operation panicked, so the OpWriter's buffers may be out of sync")
+    }
+}
+
+impl From<PoisonedError> for ReadError {
+    fn from(PoisonedError: PoisonedError) -> Self {
+        Self::Poisoned
+    }
+}
+
+/// The error returned when an [`OpWriter`] is created from two buffers which aren't equal
+///
+/// see [`OpWriter::from_writer_checked`]
+pub struct BuffersDiffer;
+
+impl core::fmt::Debug for BuffersDiffer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the two buffers must start out equal, but they were different")
+    }
+}
+
+/// The state needed to rebuild an [`OpWriter`] on a replica of the double buffer
+///
+/// see [`OpWriter::sync_state`] and [`OpWriter::from_sync_state`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpSyncState {
+    /// which buffer is the writer buffer, see [`Writer::which`]
+    pub which: bool,
+    /// the number of operations in the log which were applied to the previous buffer
+    pub applied: usize,
+    /// the number of times the buffers were swapped
+    pub epoch: u64,
+}
+
+/// A publish of an [`OpWriter`], see [`OpWriter::recent_publishes`]
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublishRecord {
+    /// when the buffers were swapped
+    pub at: Instant,
+    /// the number of operations which were published
+    pub ops_applied: usize,
+    /// the number of times the writer paused waiting for readers to exit the writer buffer
+    /// before the operations could be applied, see [`SwapStats::pauses`]
+    pub pauses: u32,
+    /// the epoch after the publish, see [`OpWriter::epoch`]
+    pub epoch: u64,
+}
+
+/// a ring of the most recent [`PublishRecord`]s
+#[cfg(feature = "std")]
+struct PublishHistory {
+    /// the records, oldest first
+    records: VecDeque<PublishRecord>,
+    /// the maximum number of records, 0 disables the history
+    capacity: usize,
+    /// the current time, tests replace this to count how often it's called
+    now: fn() -> Instant,
+    /// records each publish, see [`OpWriter::set_flight_recorder`]
+    #[cfg(feature = "flight-recorder")]
+    recorder: Option<std::sync::Arc<crate::flight_recorder::FlightRecorder>>,
+}
+
+#[cfg(feature = "std")]
+impl PublishHistory {
+    /// a disabled history
+    const fn new() -> Self {
+        Self {
+            records: VecDeque::new(),
+            capacity: 0,
+            now: Instant::now,
+            #[cfg(feature = "flight-recorder")]
+            recorder: None,
+        }
+    }
+
+    /// change the maximum number of records, dropping the oldest ones which don't fit
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let excess = self.records.len().saturating_sub(capacity);
+        self.records.drain(..excess);
+        self.records.shrink_to(capacity);
+    }
+
+    /// record a publish, if the history is enabled
+    fn push(&mut self, ops_applied: usize, pauses: u32, epoch: u64) {
+        #[cfg(feature = "flight-recorder")]
+        if let Some(recorder) = &self.recorder {
+            recorder.record_publish(epoch, ops_applied);
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(PublishRecord {
+            at: (self.now)(),
+            ops_applied,
+            pauses,
+            epoch,
+        });
+    }
+}
+
+/// the checks an [`OpWriter`] does after each publish in strict mode, see [`OpWriter::set_strict_mode`]
+struct StrictMode<S, W, C> {
+    /// compares the two buffers, or [`skip_buffers_eq`] if the buffers can't be compared
+    buffers_eq: BuffersEq<S, W, C>,
+}
+
+/// compares the two buffers of a writer, see [`buffers_eq`]
+type BuffersEq<S, W, C> = fn(&mut DelayedWriter<S, W, C>) -> bool;
+
+impl<S, W, C> Clone for StrictMode<S, W, C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S, W, C> Copy for StrictMode<S, W, C> {}
+
+/// true if the two buffers are equal, this finishes any in progress swap
+fn buffers_eq<S: StrongRef>(writer: &mut DelayedWriter<S>) -> bool
+where
+    BufferOf<RawBuffersOf<S>>: PartialEq,
+{
+    let split = writer.finish_swap().split();
+    split.writer == split.reader
+}
+
+/// the buffer comparison in strict mode if the buffers can't be compared
+///
+/// This is a function instead of an `Option`, so that strict mode doesn't make every op writer larger
+fn skip_buffers_eq<S, W, C>(_: &mut DelayedWriter<S, W, C>) -> bool {
+    true
 }
 
+#[cfg(feature = "alloc")]
 impl<S: StrongRef, O> From<DelayedWriter<S>> for OpWriter<S, O> {
     fn from(writer: DelayedWriter<S>) -> Self {
         Self::from_raw_parts(writer, OpLog::new())
     }
 }
 
+#[cfg(feature = "alloc")]
 impl<S: StrongRef, O> From<Writer<S>> for OpWriter<S, O> {
     fn from(writer: Writer<S>) -> Self {
         Self::from_raw_parts(writer.into(), OpLog::new())
     }
 }
 
-impl<S: StrongRef, O> OpWriter<S, O> {
+impl<S: StrongRef, O, L: OpLogBackend<O>> OpWriter<S, O, L> {
     /// create an op writer from raw parts
-    pub const fn from_raw_parts(writer: DelayedWriter<S>, op_log: OpLog<O>) -> Self {
-        Self { writer, op_log }
+    pub const fn from_raw_parts(writer: DelayedWriter<S>, op_log: L) -> Self {
+        Self {
+            writer,
+            op_log,
+            last_publish_stats: SwapStats::IMMEDIATE,
+            publish_totals: SwapTotals::ZERO,
+            epoch: 0,
+            poisoned: false,
+            validator: NoValidator,
+            rejected: false,
+            strict: None,
+            #[cfg(feature = "std")]
+            history: None,
+            _op: PhantomData,
+        }
+    }
+}
+
+impl<S: StrongRef, O, L: OpLogBackend<O>, V> OpWriter<S, O, L, V> {
+    /// check the writer buffer with `validator` before each publish
+    ///
+    /// If the validator rejects the writer buffer, then it isn't published,
+    /// see [`try_publish_validated`](Self::try_publish_validated)
+    pub fn with_validator<V2>(self, validator: V2) -> OpWriter<S, O, L, V2>
+    where
+        V2: Validator<BufferOf<RawBuffersOf<S>>>,
+    {
+        OpWriter {
+            writer: self.writer,
+            op_log: self.op_log,
+            last_publish_stats: self.last_publish_stats,
+            publish_totals: self.publish_totals,
+            epoch: self.epoch,
+            poisoned: self.poisoned,
+            validator,
+            rejected: self.rejected,
+            strict: self.strict,
+            #[cfg(feature = "std")]
+            history: self.history,
+            _op: PhantomData,
+        }
+    }
+
+    /// the state needed to rebuild this op writer on a replica of the double buffer
+    ///
+    /// see [`OpWriter::from_sync_state`]
+    pub fn sync_state(&self) -> OpSyncState {
+        OpSyncState {
+            which: self.writer.which(),
+            applied: self.op_log.applied(),
+            epoch: self.epoch,
+        }
+    }
+
+    /// Try to move the op writer onto a different writer to the same buffers, keeping the operation log
+    ///
+    /// `f` is given the writer once any in progress swap is finished, and must either return a writer
+    /// to the same buffers or give back the writer. This can be used to change the strategy,
+    /// see [`Shared::map_strategy`](crate::raw::Shared::map_strategy)
+    ///
+    /// # Panics
+    ///
+    /// if the new writer's [`which`](Writer::which) flag doesn't match the old writer's
+    pub fn try_map_writer<S2: StrongRef>(
+        mut self,
+        f: impl FnOnce(Writer<S>) -> Result<Writer<S2>, Writer<S>>,
+    ) -> Result<OpWriter<S2, O, L, V>, Self> {
+        self.finish_publish_swap();
+        let writer = self.writer.into_finish_swap();
+        let which = writer.which();
+
+        match f(writer) {
+            Ok(writer) => {
+                assert_eq!(
+                    writer.which(),
+                    which,
+                    "the new writer's writer buffer doesn't match"
+                );
+
+                Ok(OpWriter {
+                    writer: DelayedWriter::new(writer),
+                    op_log: self.op_log,
+                    last_publish_stats: self.last_publish_stats,
+                    publish_totals: self.publish_totals,
+                    epoch: self.epoch,
+                    poisoned: self.poisoned,
+                    validator: self.validator,
+                    rejected: self.rejected,
+                    // the buffer comparison is specific to the old pointer type
+                    strict: self.strict.map(|_| StrictMode {
+                        buffers_eq: skip_buffers_eq,
+                    }),
+                    #[cfg(feature = "std")]
+                    history: self.history,
+                    _op: PhantomData,
+                })
+            }
+            Err(writer) => Err(Self {
+                writer: DelayedWriter::new(writer),
+                ..self
+            }),
+        }
+    }
+
+    /// true if an operation panicked while being applied
+    ///
+    /// see [`clear_poison_with`](Self::clear_poison_with)
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Mark the op writer as not poisoned without repairing the buffers
+    ///
+    /// The two buffers may be out of sync, so readers may observe alternating states
+    /// after each swap. Prefer [`clear_poison_with`](Self::clear_poison_with)
+    pub fn clear_poison_unchecked(&mut self) {
+        if self.poisoned {
+            // there is no swap in progress, since the op writer was poisoned before starting it
+            self.writer.finish_swap().set_poisoned(false);
+        }
+        self.poisoned = false;
+    }
+
+    /// the number of times the buffers were swapped
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// All operations in the log, starting with the ones which were applied to the previous buffer
+    ///
+    /// # Panics
+    ///
+    /// if the operations are [boxed](crate::op_log::Boxed), see [`OpLogBackend::ops`]
+    pub fn ops(&self) -> &[O] {
+        self.op_log.ops()
+    }
+
+    /// Call `f` with the writer buffer after each finished swap, before operations are applied to it
+    ///
+    /// see [`DelayedWriter::set_post_swap`]
+    #[cfg(feature = "alloc")]
+    pub fn set_post_swap(
+        &mut self,
+        f: impl FnMut(&mut BufferOf<RawBuffersOf<S>>) + Send + 'static,
+    ) {
+        self.writer.set_post_swap(f)
     }
 
     /// deconstruct the op writer into it's raw parts
-    pub fn into_raw_parts(self) -> (DelayedWriter<S>, OpLog<O>) {
+    pub fn into_raw_parts(self) -> (DelayedWriter<S>, L) {
         (self.writer, self.op_log)
     }
 
     /// All operations which haven't yet been applied
+    ///
+    /// # Panics
+    ///
+    /// if the operations are [boxed](crate::op_log::Boxed), see [`unapplied_iter`](Self::unapplied_iter)
     pub fn unapplied(&self) -> &[O] {
         self.op_log.unapplied()
     }
 
+    /// All operations which haven't yet been applied, see [`OpLog::unapplied_mut`]
+    ///
+    /// # Panics
+    ///
+    /// if the operations are [boxed](crate::op_log::Boxed), see [`unapplied_iter_mut`](Self::unapplied_iter_mut)
+    pub fn unapplied_mut(&mut self) -> &mut [O] {
+        self.op_log.unapplied_mut()
+    }
+
+    /// All operations which haven't yet been applied
+    pub fn unapplied_iter(&self) -> OpIter<'_, O> {
+        self.op_log.unapplied_iter()
+    }
+
+    /// All operations which haven't yet been applied, see [`OpLog::unapplied_iter_mut`]
+    pub fn unapplied_iter_mut(&mut self) -> OpIterMut<'_, O> {
+        self.op_log.unapplied_iter_mut()
+    }
+
+    /// The number of operations which haven't yet been applied
+    pub fn unapplied_len(&self) -> usize {
+        self.op_log.unapplied_len()
+    }
+
+    /// The stats from the last finished publish
+    ///
+    /// publishes are finished lazily at the start of the next swap,
+    /// so this reports on the publish before the latest one
+    pub fn last_publish_stats(&self) -> SwapStats {
+        self.last_publish_stats
+    }
+
+    /// The totals of the stats of every finished publish, see [`last_publish_stats`](Self::last_publish_stats)
+    ///
+    /// A frame pacer can compare the totals between two points in time, to see how often
+    /// publishes had to wait for readers in between
+    pub fn publish_totals(&self) -> SwapTotals {
+        self.publish_totals
+    }
+
+    /// How many bytes the two buffers, the operation log and the strategy hold,
+    /// see [`footprint`](crate::footprint)
+    ///
+    /// `heap_bytes` is called with each buffer, see [`Writer::footprint_with`]
+    pub fn footprint_with(
+        &self,
+        heap_bytes: impl FnMut(&BufferOf<RawBuffersOf<S>>) -> usize,
+    ) -> Footprint
+    where
+        StrategyOf<S>: StrategyIntrospect,
+        L: MemoryUsage,
+    {
+        Footprint {
+            op_log: core::mem::size_of::<L>() + self.op_log.heap_bytes(),
+            ..self.writer.footprint_with(heap_bytes)
+        }
+    }
+
+    /// Keep a record of the last `capacity` publishes, see [`recent_publishes`](Self::recent_publishes)
+    ///
+    /// A capacity of 0 disables the history, which is the default.
+    /// If the history is longer than `capacity`, then the oldest records are dropped
+    #[cfg(feature = "std")]
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        match &mut self.history {
+            #[cfg(feature = "flight-recorder")]
+            Some(history) if history.recorder.is_some() => history.set_capacity(capacity),
+            Some(_) if capacity == 0 => self.history = None,
+            Some(history) => history.set_capacity(capacity),
+            None if capacity == 0 => (),
+            None => self.history_mut().set_capacity(capacity),
+        }
+    }
+
+    /// the publish history, which is allocated if it wasn't enabled yet
+    #[cfg(feature = "std")]
+    fn history_mut(&mut self) -> &mut PublishHistory {
+        self.history
+            .get_or_insert_with(|| Box::new(PublishHistory::new()))
+    }
+
+    /// Record each publish into `recorder`, or stop recording them with `None`
+    ///
+    /// The swaps and read guards are recorded by a [`RecordedStrategy`](crate::flight_recorder::RecordedStrategy),
+    /// see [`flight_recorder`](crate::flight_recorder)
+    #[cfg(feature = "flight-recorder")]
+    pub fn set_flight_recorder(
+        &mut self,
+        recorder: Option<std::sync::Arc<crate::flight_recorder::FlightRecorder>>,
+    ) {
+        if recorder.is_some() || self.history.is_some() {
+            self.history_mut().recorder = recorder;
+        }
+    }
+
+    /// The most recent publishes, oldest first
+    ///
+    /// This is empty unless the history was enabled with [`set_history_capacity`](Self::set_history_capacity)
+    ///
+    /// ```
+    /// # #[cfg(not(feature = "loom"))] {
+    /// use dbuf::{op::OpWriter, op_log::Operation, ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy};
+    /// use std::{fmt::Write, time::Instant};
+    ///
+    /// struct Add(i32);
+    ///
+    /// impl Operation<i32> for Add {
+    ///     fn apply(&mut self, buffer: &mut i32) {
+    ///         *buffer += self.0
+    ///     }
+    /// }
+    ///
+    /// let shared = Owned::<TrackingStrategy, _>::from_buffers(0, 0);
+    /// let mut writer = OpWriter::from(Writer::new(shared));
+    /// writer.set_history_capacity(16);
+    ///
+    /// for i in 0..3 {
+    ///     writer.apply(Add(i));
+    ///     writer.publish();
+    /// }
+    ///
+    /// // what an operator endpoint might report
+    /// let now = Instant::now();
+    /// let mut report = String::new();
+    /// for record in writer.recent_publishes() {
+    ///     writeln!(
+    ///         report,
+    ///         "epoch {}: {} ops, {} pauses, {:?} ago",
+    ///         record.epoch,
+    ///         record.ops_applied,
+    ///         record.pauses,
+    ///         now.saturating_duration_since(record.at),
+    ///     )
+    ///     .unwrap();
+    /// }
+    ///
+    /// assert_eq!(report.lines().count(), 3);
+    /// assert!(report.starts_with("epoch 1: 1 ops"));
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn recent_publishes(&self) -> impl Iterator<Item = PublishRecord> + '_ {
+        self.history
+            .iter()
+            .flat_map(|history| history.records.iter().copied())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: StrongRef, O> OpWriter<S, O> {
+    /// create an op writer, after checking that the two buffers are equal
+    ///
+    /// The [`OpWriter`] only keeps the buffers consistent if they start out indistinguishable.
+    /// If they don't, then readers will see different values depending on which buffer is
+    /// published, forever. The [`From`] impls don't check this, since it may be expensive.
+    pub fn from_writer_checked(writer: Writer<S>) -> Result<Self, BuffersDiffer>
+    where
+        BufferOf<RawBuffersOf<S>>: PartialEq,
+    {
+        let split = writer.split();
+        if split.writer != split.reader {
+            return Err(BuffersDiffer);
+        }
+
+        Ok(Self::from(writer))
+    }
+
+    /// rebuild an op writer on a replica of the double buffer
+    ///
+    /// The buffers managed by `writer` must match the buffers of the op writer which
+    /// created the `state`, and `ops` must be that op writer's [`ops`](Self::ops)
+    ///
+    /// # Panics
+    ///
+    /// if the writer's [`which`](Writer::which) flag doesn't match the state
+    /// or there are fewer operations than the state says were applied
+    pub fn from_sync_state(writer: DelayedWriter<S>, ops: Vec<O>, state: OpSyncState) -> Self {
+        assert_eq!(
+            writer.which(),
+            state.which,
+            "the replica's writer buffer doesn't match the sync state"
+        );
+
+        Self {
+            writer,
+            op_log: OpLog::from_raw_parts(ops, state.applied),
+            last_publish_stats: SwapStats::IMMEDIATE,
+            publish_totals: SwapTotals::ZERO,
+            epoch: state.epoch,
+            poisoned: false,
+            validator: NoValidator,
+            rejected: false,
+            strict: None,
+            #[cfg(feature = "std")]
+            history: None,
+            _op: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: StrongRef, O, V, St: OpStorage> OpWriter<S, O, OpLog<O, St>, V> {
     /// Shrinks the capacity of the vector with a lower bound.
     ///
     /// The capacity will remain at least as large as both the length
@@ -81,37 +724,1611 @@ impl<S: StrongRef, O> OpWriter<S, O> {
     pub fn reserve(&mut self, additional: usize) {
         self.op_log.reserve(additional)
     }
+
+    /// Rewrite the operations which haven't yet been applied, see [`OpLog::rewrite_unapplied`]
+    ///
+    /// ```
+    /// use dbuf::{op::OpWriter, op_log::Operation, ptrs::alloc::Owned, raw::Writer, strategy::HazardStrategy};
+    ///
+    /// struct Push(i32);
+    ///
+    /// impl Operation<Vec<i32>> for Push {
+    ///     fn apply(&mut self, buffer: &mut Vec<i32>) {
+    ///         buffer.push(self.0)
+    ///     }
+    /// }
+    ///
+    /// let mut writer = OpWriter::from(Writer::new(Owned::<HazardStrategy, _>::from_buffers(vec![], vec![])));
+    /// writer.apply(Push(2));
+    /// writer.apply(Push(1));
+    /// writer.apply(Push(2));
+    /// writer.rewrite_unapplied(|ops| {
+    ///     ops.sort_by_key(|op| op.0);
+    ///     ops.dedup_by_key(|op| op.0);
+    /// });
+    /// writer.swap_buffers();
+    /// assert_eq!(*writer.reader().get(), [1, 2]);
+    /// ```
+    pub fn rewrite_unapplied(&mut self, f: impl FnOnce(&mut Vec<St::Slot<O>>)) {
+        self.op_log.rewrite_unapplied(f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: StrongRef, O, V> OpWriter<S, O, OpLog<O>, V> {
+    /// Store each operation in its own allocation, see [`Boxed`]
+    ///
+    /// This makes pushing and publishing large operations cheaper, since only a pointer is moved.
+    /// The operations which are already in the log are moved into boxes too.
+    ///
+    /// ```
+    /// use dbuf::{op::OpWriter, op_log::Operation, ptrs::alloc::Owned, raw::Writer, strategy::HazardStrategy};
+    ///
+    /// struct Push([u8; 256]);
+    ///
+    /// impl Operation<Vec<u8>> for Push {
+    ///     fn apply(&mut self, buffer: &mut Vec<u8>) {
+    ///         buffer.extend_from_slice(&self.0)
+    ///     }
+    /// }
+    ///
+    /// let shared = Owned::<HazardStrategy, _>::from_buffers(vec![], vec![]);
+    /// let mut writer = OpWriter::from(Writer::new(shared)).with_boxed_ops();
+    /// writer.apply(Push([1; 256]));
+    /// assert_eq!(writer.unapplied_iter().len(), 1);
+    /// writer.publish();
+    /// assert_eq!(writer.reader().get().len(), 256);
+    /// ```
+    pub fn with_boxed_ops(self) -> OpWriter<S, O, OpLog<O, Boxed>, V> {
+        OpWriter {
+            writer: self.writer,
+            op_log: self.op_log.into_boxed(),
+            last_publish_stats: self.last_publish_stats,
+            publish_totals: self.publish_totals,
+            epoch: self.epoch,
+            poisoned: self.poisoned,
+            validator: self.validator,
+            rejected: self.rejected,
+            strict: self.strict,
+            #[cfg(feature = "std")]
+            history: self.history,
+            _op: PhantomData,
+        }
+    }
+
+    /// Move the op writer to a different writer, and convert the operations which haven't been published yet
+    ///
+    /// This finishes any in progress swap, and catches up the writer buffer, so that both buffers
+    /// are the same. Then `f` gets the writer and the unpublished operations, and must convert the
+    /// buffers and the operations in the same way, or give both back unchanged. The epoch, validator
+    /// and history are kept. If `f` fails, then the op writer is given back, with the writer buffer
+    /// caught up.
+    ///
+    /// # Panics
+    ///
+    /// if the op writer is poisoned, or the last publish was rejected by the validator or the strategy, since then
+    /// the writer buffer has operations which aren't published yet
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn try_migrate<S2: StrongRef, O2>(
+        mut self,
+        f: impl FnOnce(Writer<S>, Vec<O>) -> Result<(Writer<S2>, Vec<O2>), (Writer<S>, Vec<O>)>,
+    ) -> Result<OpWriter<S2, O2, OpLog<O2>, V>, Self>
+    where
+        O: Operation<BufferOf<RawBuffersOf<S>>>,
+    {
+        assert!(
+            !self.poisoned,
+            "could not migrate the op writer: {:?}, see `OpWriter::clear_poison_with`",
+            PoisonedError
+        );
+        assert!(
+            !self.rejected,
+            "could not migrate the op writer: the last publish was rejected"
+        );
+
+        self.finish_publish_swap();
+        let mut writer = self.writer.into_finish_swap();
+        // the reader buffer has the applied operations, so afterwards the buffers are the same
+        self.op_log.catch_up(writer.split_mut().writer);
+        let which = writer.which();
+        let (ops, applied) = self.op_log.into_raw_parts();
+        debug_assert_eq!(applied, 0);
+
+        match f(writer, ops) {
+            Ok((writer, ops)) => {
+                assert_eq!(
+                    writer.which(),
+                    which,
+                    "the new writer's writer buffer doesn't match"
+                );
+
+                Ok(OpWriter {
+                    writer: DelayedWriter::new(writer),
+                    op_log: OpLog::from_vec(ops),
+                    last_publish_stats: self.last_publish_stats,
+                    publish_totals: self.publish_totals,
+                    epoch: self.epoch,
+                    poisoned: false,
+                    validator: self.validator,
+                    rejected: false,
+                    // the buffer comparison is specific to the old pointer type
+                    strict: self.strict.map(|_| StrictMode {
+                        buffers_eq: skip_buffers_eq,
+                    }),
+                    #[cfg(feature = "std")]
+                    history: self.history,
+                    _op: PhantomData,
+                })
+            }
+            Err((writer, ops)) => Err(Self {
+                writer: DelayedWriter::new(writer),
+                op_log: OpLog::from_vec(ops),
+                ..self
+            }),
+        }
+    }
 }
 
-impl<S: StrongRef, O: Operation<BufferOf<RawBuffersOf<S>>>> OpWriter<S, O>
+impl<S: StrongRef, O: Operation<BufferOf<RawBuffersOf<S>>>, L: OpLogBackend<O>, V>
+    OpWriter<S, O, L, V>
 where
-    StrategyOf<S>: Strategy<ValidationError = Infallible>,
+    V: Validator<BufferOf<RawBuffersOf<S>>>,
 {
     /// apply an operation to the op writer
-    pub fn apply(&mut self, op: O) {
+    pub fn apply(&mut self, op: O)
+    where
+        L: OpLogBackend<O, PushError = Infallible>,
+    {
+        match self.op_log.push(op) {
+            Ok(()) => (),
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// apply a closure to both buffers, each buffer gets its own clone of `f`
+    ///
+    /// This is for changes which are just a closure, and can run on each buffer independently,
+    /// like shrinking the buffers. The op type only needs to be able to hold an [`ApplyToBoth`]
+    #[cfg(feature = "alloc")]
+    pub fn apply_to_both<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut BufferOf<RawBuffersOf<S>>) + Clone + Send + 'static,
+        O: From<ApplyToBoth<BufferOf<RawBuffersOf<S>>>>,
+        L: OpLogBackend<O, PushError = Infallible>,
+    {
+        self.apply(O::from(ApplyToBoth::new(f)))
+    }
+
+    /// apply an operation to the op writer, or return an error if the op log is full
+    ///
+    /// publishing makes room in the op log
+    pub fn try_apply(&mut self, op: O) -> Result<(), L::PushError> {
         self.op_log.push(op)
     }
 
     /// swap buffers if there are some unapplied operations
-    pub fn publish(&mut self) {
-        if !self.unapplied().is_empty() {
-            self.swap_buffers();
+    ///
+    /// # Panics
+    ///
+    /// if the op writer is poisoned, or the validator rejects the writer buffer
+    pub fn publish(&mut self)
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+        V::Error: core::fmt::Debug,
+    {
+        match self.try_publish_validated() {
+            Ok(()) => (),
+            Err(PublishError::Poisoned) => panic!(
+                "could not publish: {:?}, see `OpWriter::clear_poison_with`",
+                PoisonedError
+            ),
+            Err(PublishError::Invalid(err)) => {
+                panic!("could not publish: the validator rejected the writer buffer: {err:?}")
+            }
+            Err(PublishError::Strategy(inf)) => match inf {},
+        }
+    }
+
+    /// swap buffers if there are some unapplied operations, or return the strategy's error if
+    /// it couldn't start the swap
+    ///
+    /// Returns true if the buffers were swapped. This is for strategies which can fail to swap,
+    /// like [`LocalStrategy`](crate::strategy::LocalStrategy) while a read guard is held.
+    /// If the swap fails the operations stay applied to the writer buffer, like after the
+    /// validator rejects it, so the next publish only applies the new ones, and both buffers
+    /// still get every operation.
+    ///
+    /// # Panics
+    ///
+    /// if the op writer is poisoned, or the validator rejects the writer buffer
+    pub fn publish_checked(&mut self) -> Result<bool, ValidationErrorOf<StrategyOf<S>>>
+    where
+        V::Error: core::fmt::Debug,
+    {
+        if self.op_log.unapplied_len() == 0 && !self.poisoned && !self.rejected {
+            return Ok(false);
+        }
+
+        self.swap_buffers_checked()?;
+        Ok(true)
+    }
+
+    /// swap buffers if there are some unapplied operations
+    pub fn try_publish(&mut self) -> Result<(), PoisonedError>
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+        V: Validator<BufferOf<RawBuffersOf<S>>, Error = Infallible>,
+    {
+        match self.try_publish_validated() {
+            Ok(()) => Ok(()),
+            Err(PublishError::Poisoned) => Err(PoisonedError),
+            Err(PublishError::Invalid(inf) | PublishError::Strategy(inf)) => match inf {},
         }
     }
 
-    /// swap the underlying buffers and apply any unapplied operations
-    pub fn swap_buffers(&mut self) {
+    /// swap buffers if there are some unapplied operations, and the validator accepts the writer buffer
+    ///
+    /// If the validator rejects the writer buffer, then the buffers aren't swapped and readers
+    /// keep seeing the last published buffer. The operations stay applied to the writer buffer,
+    /// so apply more operations which fix it and publish again. Those operations are checked
+    /// together with the rejected ones. The same happens if the strategy can't start the swap,
+    /// then publishing again once it can publishes the operations.
+    #[allow(clippy::type_complexity)]
+    pub fn try_publish_validated(
+        &mut self,
+    ) -> Result<(), PublishError<V::Error, ValidationErrorOf<StrategyOf<S>>>> {
+        if self.op_log.unapplied_len() == 0 && !self.poisoned && !self.rejected {
+            Ok(())
+        } else {
+            self.try_swap_buffers_validated()
+        }
+    }
+
+    /// Undo the last publish while its swap is still in progress, so readers go back to the previously published buffer
+    ///
+    /// see [`DelayedWriter::revert_pending_swap`] for what readers may have seen, and when the
+    /// writer buffer is available again. The reverted operations stay applied to the writer buffer,
+    /// just like after the validator rejects a publish. So apply operations which undo them,
+    /// and publish again, then both buffers get all of the operations. To drop the reverted
+    /// operations instead, use [`revert_publish_with`](Self::revert_publish_with).
+    ///
+    /// In strict mode every swap is finished before the publish returns, so there is nothing to revert.
+    pub fn revert_publish(&mut self) -> Result<(), NoPendingSwap>
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+    {
+        self.writer.revert_pending_swap()?;
+        // the writer buffer has all operations in the log, and the published buffer has none of them,
+        // so the next publish only applies the new ones, like after a rejected publish
+        self.rejected = true;
+        Ok(())
+    }
+
+    /// Undo the last publish while its swap is still in progress, and drop the reverted operations
+    ///
+    /// This reverts the swap like [`revert_publish`](Self::revert_publish), then waits for readers
+    /// which may still be reading the reverted buffer. Then `restore` is called with the writer buffer
+    /// and the published buffer, and must make the writer buffer indistinguishable from the published buffer.
+    /// The reverted operations are dropped, so they are never published again. The operations which
+    /// were applied after the reverted publish are kept, and published on the next publish.
+    ///
+    /// In strict mode every swap is finished before the publish returns, so there is nothing to revert.
+    pub fn revert_publish_with(
+        &mut self,
+        restore: impl FnOnce(&mut BufferOf<RawBuffersOf<S>>, &BufferOf<RawBuffersOf<S>>),
+    ) -> Result<(), NoPendingSwap>
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+    {
+        self.writer.revert_pending_swap()?;
         let writer = self.writer.finish_swap();
-        let writer = writer.split_mut().writer;
-        self.op_log.apply(writer);
-        self.writer.start_buffer_swap();
+
+        // if `restore` panics, then the writer buffer may be anything, see `clear_poison_with`
+        self.poisoned = true;
+        let mut writer = scopeguard::guard(writer, |writer| writer.set_poisoned(true));
+        let split = writer.split_mut();
+        restore(split.writer, split.reader);
+        scopeguard::ScopeGuard::into_inner(writer);
+        self.poisoned = false;
+
+        // the writer buffer matches the published buffer again, which never had the reverted operations
+        self.op_log.discard_applied();
+        self.rejected = false;
+        Ok(())
     }
-}
 
-impl<S: StrongRef, O> Deref for OpWriter<S, O> {
-    type Target = Writer<S>;
+    /// publish any unapplied operations and drop the op writer, without waiting on stuck readers
+    ///
+    /// Before publishing, this waits for readers to exit the writer buffer until `should_continue`
+    /// returns false, in which case the unapplied operations are discarded. The swap which publishes
+    /// the operations isn't waited on at all, since nothing will write to the old buffer anymore.
+    ///
+    /// Returns true if all operations were published.
+    ///
+    /// Abandoning a swap is safe: the buffers were already flipped when the swap started, so readers
+    /// which are still reading the old buffer can keep reading it, and their guards keep the
+    /// buffers alive after the writer is dropped.
+    pub fn shutdown(mut self, should_continue: impl FnMut() -> bool) -> bool {
+        if !self.writer.finish_swap_until(should_continue) {
+            return false;
+        }
 
-    fn deref(&self) -> &Self::Target {
-        &self.writer
+        self.try_publish_validated().is_ok()
+    }
+
+    /// swap the underlying buffers and apply any unapplied operations
+    ///
+    /// # Panics
+    ///
+    /// if the op writer is poisoned, or the validator rejects the writer buffer
+    pub fn swap_buffers(&mut self)
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+        V::Error: core::fmt::Debug,
+    {
+        match self.swap_buffers_checked() {
+            Ok(()) => (),
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// swap the underlying buffers and apply any unapplied operations, or return the strategy's
+    /// error if it couldn't start the swap
+    ///
+    /// see [`publish_checked`](Self::publish_checked) for what happens if the swap fails
+    ///
+    /// # Panics
+    ///
+    /// if the op writer is poisoned, or the validator rejects the writer buffer
+    pub fn swap_buffers_checked(&mut self) -> Result<(), ValidationErrorOf<StrategyOf<S>>>
+    where
+        V::Error: core::fmt::Debug,
+    {
+        match self.try_swap_buffers_validated() {
+            Ok(()) => Ok(()),
+            Err(PublishError::Poisoned) => panic!(
+                "could not swap buffers: {:?}, see `OpWriter::clear_poison_with`",
+                PoisonedError
+            ),
+            Err(PublishError::Invalid(err)) => {
+                panic!("could not swap buffers: the validator rejected the writer buffer: {err:?}")
+            }
+            Err(PublishError::Strategy(err)) => Err(err),
+        }
+    }
+
+    /// swap the underlying buffers and apply any unapplied operations
+    pub fn try_swap_buffers(&mut self) -> Result<(), PoisonedError>
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+        V: Validator<BufferOf<RawBuffersOf<S>>, Error = Infallible>,
+    {
+        match self.try_swap_buffers_validated() {
+            Ok(()) => Ok(()),
+            Err(PublishError::Poisoned) => Err(PoisonedError),
+            Err(PublishError::Invalid(inf) | PublishError::Strategy(inf)) => match inf {},
+        }
     }
+
+    /// swap the underlying buffers and apply any unapplied operations, if the validator accepts the writer buffer
+    ///
+    /// see [`try_publish_validated`](Self::try_publish_validated) for what happens if the validator rejects it,
+    /// or the strategy can't start the swap
+    #[allow(clippy::type_complexity)]
+    pub fn try_swap_buffers_validated(
+        &mut self,
+    ) -> Result<(), PublishError<V::Error, ValidationErrorOf<StrategyOf<S>>>> {
+        if self.poisoned {
+            return Err(PublishError::Poisoned);
+        }
+
+        // if the last publish was rejected, then its swap was already finished, if it was reverted,
+        // then it wasn't a publish, and in strict mode every swap is finished before the publish returns
+        if !self.rejected && self.strict.is_none() {
+            self.finish_publish_swap();
+        }
+        let writer = self.writer.finish_swap();
+        let ops = self.op_log.unapplied_len();
+        // if an operation panics, then this will stay poisoned, and readers are told about it
+        self.poisoned = true;
+        let mut writer = scopeguard::guard(writer, |writer| writer.set_poisoned(true));
+        if self.rejected {
+            self.op_log.apply_unapplied(writer.split_mut().writer);
+        } else {
+            self.op_log.apply(writer.split_mut().writer);
+        }
+        let writer = scopeguard::ScopeGuard::into_inner(writer);
+        self.poisoned = false;
+
+        // the operations are in the writer buffer now, even if it's rejected
+        self.rejected = true;
+        self.validator
+            .validate(writer.split().writer)
+            .map_err(PublishError::Invalid)?;
+        self.writer
+            .try_start_buffer_swap()
+            .map_err(PublishError::Strategy)?;
+        self.rejected = false;
+        self.epoch += 1;
+
+        #[cfg(feature = "std")]
+        if let Some(history) = &mut self.history {
+            history.push(ops, self.last_publish_stats.pauses, self.epoch);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(ops, "published operations");
+
+        self.finish_strict_publish(ops);
+
+        Ok(())
+    }
+
+    /// in strict mode, wait for the swap which was just started, and bring the writer buffer up to date
+    ///
+    /// see [`set_strict_mode`](Self::set_strict_mode)
+    fn finish_strict_publish(&mut self, ops: usize) {
+        let Some(strict) = self.strict else {
+            return;
+        };
+
+        self.finish_publish_swap();
+        let writer = self.writer.finish_swap();
+
+        // if an operation panics, then this will stay poisoned, and readers are told about it
+        self.poisoned = true;
+        let mut writer = scopeguard::guard(writer, |writer| writer.set_poisoned(true));
+        self.op_log.catch_up(writer.split_mut().writer);
+        let writer = scopeguard::ScopeGuard::into_inner(writer);
+        self.poisoned = false;
+
+        let which = writer.which();
+        assert!(
+            self.op_log.is_empty(),
+            "strict mode: {} operations are still in the log after publish {}",
+            self.op_log.len(),
+            self.epoch,
+        );
+
+        assert!(
+            (strict.buffers_eq)(&mut self.writer),
+            "strict mode: the buffers diverged after publish {} which applied {ops} operations, \
+                the writer buffer (which = {which}) doesn't match the published buffer, \
+                some operation isn't deterministic",
+            self.epoch,
+        );
+    }
+
+    /// view the published buffer, the writer buffer, and the unpublished operations
+    ///
+    /// This finishes any in progress swap (waiting for readers to exit the writer buffer)
+    /// and applies the last published operations to the writer buffer, so the writer buffer
+    /// matches the published buffer (unless the op writer is poisoned)
+    pub fn diff(&mut self) -> OpDiff<'_, BufferOf<RawBuffersOf<S>>, O> {
+        self.finish_publish_swap();
+        let writer = self.writer.finish_swap();
+
+        // if poisoned, the writer buffer may have some of the operations applied already
+        // and if the last publish was rejected, it has all of them
+        if !self.poisoned && !self.rejected {
+            self.op_log.catch_up(writer.split_mut().writer);
+        }
+
+        let split = writer.split();
+        OpDiff {
+            published: split.reader,
+            write_buffer: split.writer,
+            unapplied_ops: self.op_log.unapplied_iter(),
+        }
+    }
+
+    /// Check that the two buffers are equal
+    ///
+    /// This finishes any in progress swap (waiting for readers to exit the writer buffer)
+    /// and applies the last published operations to the writer buffer, which would
+    /// otherwise only happen on the next swap.
+    ///
+    /// The comparison is only meaningful if there are no unpublished operations, otherwise
+    /// this returns [`Consistency::PendingOps`]
+    pub fn verify_buffers_eq(&mut self) -> Consistency
+    where
+        BufferOf<RawBuffersOf<S>>: PartialEq,
+    {
+        if self.op_log.unapplied_len() != 0 || self.rejected {
+            return Consistency::PendingOps;
+        }
+
+        let diff = self.diff();
+        if diff.write_buffer == diff.published {
+            Consistency::Consistent
+        } else {
+            Consistency::Diverged
+        }
+    }
+
+    /// Assert that the two buffers haven't diverged, only when debug assertions are enabled
+    ///
+    /// see [`verify_buffers_eq`](Self::verify_buffers_eq)
+    pub fn debug_assert_consistent(&mut self)
+    where
+        BufferOf<RawBuffersOf<S>>: PartialEq,
+    {
+        if cfg!(debug_assertions) {
+            assert_ne!(
+                self.verify_buffers_eq(),
+                Consistency::Diverged,
+                "the two buffers diverged, some operation isn't deterministic"
+            );
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: StrongRef, O: Operation<BufferOf<RawBuffersOf<S>>>, V, St: OpStorage>
+    OpWriter<S, O, OpLog<O, St>, V>
+where
+    V: Validator<BufferOf<RawBuffersOf<S>>>,
+{
+    /// publish the unapplied operations which match `pred`, and keep the others for a later publish
+    ///
+    /// see [`try_publish_matching_validated`](Self::try_publish_matching_validated)
+    ///
+    /// # Panics
+    ///
+    /// if the op writer is poisoned, or the validator rejects the writer buffer
+    pub fn publish_matching(&mut self, pred: impl FnMut(&O) -> bool) -> usize
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+        V::Error: core::fmt::Debug,
+    {
+        match self.try_publish_matching_validated(pred) {
+            Ok(published) => published,
+            Err(PublishError::Poisoned) => panic!(
+                "could not publish: {:?}, see `OpWriter::clear_poison_with`",
+                PoisonedError
+            ),
+            Err(PublishError::Invalid(err)) => {
+                panic!("could not publish: the validator rejected the writer buffer: {err:?}")
+            }
+            Err(PublishError::Strategy(inf)) => match inf {},
+        }
+    }
+
+    /// publish the unapplied operations which match `pred`, and keep the others for a later publish,
+    /// if the validator accepts the writer buffer
+    ///
+    /// The unapplied operations are split with [`OpLog::partition_unapplied`], the matching ones are
+    /// published like [`try_publish_validated`](Self::try_publish_validated) does, and the others
+    /// stay unapplied, in the same order. Returns how many operations were published.
+    ///
+    /// This is only correct if each kept operation gives the same result whether it's applied before
+    /// or after each published operation, e.g. because they change different keys of a map. Otherwise
+    /// the buffers end up different than if the operations were published in order, and this can't
+    /// be checked here.
+    ///
+    /// If the last publish was rejected, then all unapplied operations are in the writer buffer already,
+    /// so all of them are published. If an operation panics, then the kept operations are dropped, just
+    /// like the other unpublished operations once the poison is cleared.
+    #[allow(clippy::type_complexity)]
+    pub fn try_publish_matching_validated(
+        &mut self,
+        pred: impl FnMut(&O) -> bool,
+    ) -> Result<usize, PublishError<V::Error, ValidationErrorOf<StrategyOf<S>>>> {
+        if self.rejected || self.poisoned {
+            let published = self.op_log.unapplied_len();
+            return self.try_publish_validated().map(|()| published);
+        }
+
+        let published = self.op_log.partition_unapplied(pred);
+        let mut kept = Vec::new();
+        self.op_log
+            .rewrite_unapplied(|ops| kept = ops.split_off(published));
+        let result = self.try_publish_validated();
+        // the published operations count as applied now, even if the publish was rejected,
+        // so the kept ones are applied by the next publish
+        self.op_log.rewrite_unapplied(|ops| ops.append(&mut kept));
+        result.map(|()| published)
+    }
+}
+
+impl<S: StrongRef, O, L: OpLogBackend<O>, V> OpWriter<S, O, L, V> {
+    /// wait until every read guard which was acquired before this call has been dropped
+    ///
+    /// This finishes any in progress swap, but doesn't publish the unapplied operations.
+    /// see [`Writer::wait_for_quiescence`]
+    pub fn wait_for_quiescence(&mut self) -> SwapStats
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+    {
+        self.finish_publish_swap();
+        self.writer.finish_swap().wait_for_quiescence()
+    }
+
+    /// Make every publish fully synchronous, to rule out the delayed swap when chasing a bug
+    ///
+    /// In strict mode, each publish (or swap) applies the operations, swaps the buffers, and then
+    /// blocks until readers have left the old buffer, instead of leaving that for the next publish.
+    /// The published operations are applied to the new writer buffer right away, so both buffers
+    /// hold the same value between publishes, and [`last_publish_stats`](Self::last_publish_stats)
+    /// reports on the latest publish. Turning strict mode on finishes any in progress swap.
+    ///
+    /// Readers see the same values as without strict mode, but publishing is slower.
+    /// see [`set_strict_mode_checked`](Self::set_strict_mode_checked) to also compare the buffers
+    pub fn set_strict_mode(&mut self, on: bool) {
+        self.start_strict_mode();
+        self.strict = on.then_some(StrictMode {
+            buffers_eq: skip_buffers_eq,
+        });
+    }
+
+    /// Turn strict mode on or off, and compare the two buffers after each publish
+    ///
+    /// see [`set_strict_mode`](Self::set_strict_mode) and [`verify_buffers_eq`](Self::verify_buffers_eq)
+    ///
+    /// # Panics
+    ///
+    /// publishing panics if the buffers are different after the publish, with the epoch, the number
+    /// of operations which were published, and which buffer is the writer buffer
+    pub fn set_strict_mode_checked(&mut self, on: bool)
+    where
+        BufferOf<RawBuffersOf<S>>: PartialEq,
+    {
+        self.start_strict_mode();
+        self.strict = on.then_some(StrictMode {
+            buffers_eq: buffers_eq::<S>,
+        });
+    }
+
+    /// true if strict mode is on, see [`set_strict_mode`](Self::set_strict_mode)
+    pub fn is_strict_mode(&self) -> bool {
+        self.strict.is_some()
+    }
+
+    /// finish the swap of the last publish, if it's in progress, and record its stats
+    fn finish_publish_swap(&mut self) {
+        if self.writer.has_pending_swap() {
+            let stats = self.writer.finish_swap_with_stats();
+            self.last_publish_stats = stats;
+            self.publish_totals.add(stats);
+        }
+    }
+
+    /// finish any in progress swap, if strict mode was off
+    fn start_strict_mode(&mut self) {
+        // if the last publish was rejected, then its swap was already finished
+        if self.strict.is_none() && !self.rejected {
+            self.finish_publish_swap();
+        }
+    }
+
+    /// Overwrite the writer buffer with a copy of the published buffer
+    ///
+    /// This finishes any in progress swap and applies the last published operations to the writer
+    /// buffer, then calls `copy` with the writer buffer and the published buffer. `copy` must make
+    /// the writer buffer indistinguishable from the published buffer. Unlike
+    /// [`clear_poison_with`](Self::clear_poison_with), the unpublished operations are kept, and
+    /// they are applied to the copy on the next publish.
+    ///
+    /// This is for writer buffers which are out of date on purpose, for example to avoid
+    /// building both buffers up front
+    ///
+    /// # Panics
+    ///
+    /// if the op writer is poisoned, or the last publish was rejected by the validator or the strategy, since then
+    /// the writer buffer has operations which aren't published yet
+    pub fn resync_writer_buffer(
+        &mut self,
+        copy: impl FnOnce(&mut BufferOf<RawBuffersOf<S>>, &BufferOf<RawBuffersOf<S>>),
+    ) where
+        O: Operation<BufferOf<RawBuffersOf<S>>>,
+    {
+        assert!(
+            !self.poisoned,
+            "could not resync the writer buffer: {:?}, see `OpWriter::clear_poison_with`",
+            PoisonedError
+        );
+        assert!(
+            !self.rejected,
+            "could not resync the writer buffer: the last publish was rejected"
+        );
+
+        self.finish_publish_swap();
+        let writer = self.writer.finish_swap();
+        // the copy has the published operations, so this only empties the applied part of the log
+        self.op_log.catch_up(writer.split_mut().writer);
+        let split = writer.split_mut();
+        copy(split.writer, split.reader);
+    }
+
+    /// Repair the writer buffer from the reader buffer and clear the poison
+    ///
+    /// `restore` is called with the writer buffer and the reader buffer, and must make the
+    /// writer buffer indistinguishable from the reader buffer. All operations which haven't
+    /// been published yet are discarded.
+    ///
+    /// If the op writer isn't poisoned this does nothing
+    pub fn clear_poison_with(
+        &mut self,
+        restore: impl FnOnce(&mut BufferOf<RawBuffersOf<S>>, &BufferOf<RawBuffersOf<S>>),
+    ) {
+        if !self.poisoned {
+            return;
+        }
+
+        let writer = self.writer.finish_swap();
+        let split = writer.split_mut();
+        restore(split.writer, split.reader);
+        writer.set_poisoned(false);
+        self.op_log.clear();
+        self.poisoned = false;
+        self.rejected = false;
+    }
+}
+
+impl<S: StrongRef, O, L, V> Deref for OpWriter<S, O, L, V> {
+    type Target = Writer<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.writer
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_sync_state() {
+    use crate::{
+        ptrs::alloc::Owned,
+        raw::{RawDBuf, Shared},
+        strategy::TrackingStrategy,
+    };
+
+    #[derive(Clone)]
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+
+    for i in 0..3 {
+        writer.apply(Push(i));
+        writer.apply(Push(i * 10));
+        writer.publish();
+    }
+    writer.apply(Push(100));
+
+    let state = writer.sync_state();
+    assert_eq!(state.epoch, 3);
+
+    // transfer both buffers in the order they are stored in
+    let split = writer.split();
+    let (first, second) = if state.which {
+        (split.reader.clone(), split.writer.clone())
+    } else {
+        (split.writer.clone(), split.reader.clone())
+    };
+
+    let shared = Shared::from_raw_parts_with_which(
+        TrackingStrategy::new(),
+        RawDBuf::new(first, second),
+        state.which,
+    );
+    let replica = Writer::new(Owned::new(shared));
+    let mut replica = OpWriter::from_sync_state(replica.into(), writer.ops().to_vec(), state);
+    assert_eq!(replica.sync_state(), state);
+
+    for i in 3..6 {
+        for writer in [&mut writer, &mut replica] {
+            writer.apply(Push(i));
+            writer.publish();
+        }
+
+        assert_eq!(writer.split().reader, replica.split().reader);
+        assert_eq!(writer.sync_state(), replica.sync_state());
+    }
+
+    assert_eq!(*writer.split().reader, [0, 0, 1, 10, 2, 20, 100, 3, 4, 5]);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_poison() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    enum Op {
+        Push(i32),
+        Panic,
+    }
+
+    impl Operation<Vec<i32>> for Op {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            match self {
+                Op::Push(x) => buffer.push(*x),
+                Op::Panic => panic!("panicking op"),
+            }
+        }
+    }
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    writer.apply(Op::Push(0));
+    writer.publish();
+
+    writer.apply(Op::Push(1));
+    writer.apply(Op::Panic);
+    writer.apply(Op::Push(2));
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| writer.publish()));
+    assert!(result.is_err());
+    assert!(writer.is_poisoned());
+    assert!(writer.try_swap_buffers().is_err());
+    assert!(writer.try_publish().is_err());
+
+    // the half applied buffer was never published, and readers know that they're reading stale data
+    assert_eq!(*reader.get(), [0]);
+    assert!(reader.is_poisoned());
+
+    writer.clear_poison_with(|writer, reader| writer.clone_from(reader));
+    assert!(!writer.is_poisoned());
+    assert!(!reader.is_poisoned());
+    assert!(writer.unapplied().is_empty());
+
+    writer.apply(Op::Push(3));
+    writer.publish();
+    assert_eq!(*reader.get(), [0, 3]);
+
+    // both buffers are back in sync, so readers don't see alternating states
+    for _ in 0..3 {
+        writer.swap_buffers();
+        assert_eq!(*reader.get(), [0, 3]);
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_verify_buffers_eq() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    enum Op {
+        Push(i32),
+        // pushes a different value each time it's applied
+        PushCounter(i32),
+    }
+
+    impl Operation<Vec<i32>> for Op {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            match self {
+                Op::Push(x) => buffer.push(*x),
+                Op::PushCounter(x) => {
+                    *x += 1;
+                    buffer.push(*x)
+                }
+            }
+        }
+    }
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+
+    writer.apply(Op::Push(0));
+    assert_eq!(writer.verify_buffers_eq(), Consistency::PendingOps);
+
+    writer.publish();
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+    writer.debug_assert_consistent();
+
+    writer.apply(Op::PushCounter(0));
+    writer.publish();
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Diverged);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_from_writer_checked() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(std::vec![1], std::vec![2]);
+    assert!(OpWriter::<_, ()>::from_writer_checked(Writer::new(shared)).is_err());
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(std::vec![1], std::vec![1]);
+    let writer = OpWriter::<_, ()>::from_writer_checked(Writer::new(shared)).unwrap();
+    assert_eq!(*writer.reader().get(), [1]);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_shutdown() {
+    use crate::{
+        ptrs::alloc::OwnedWithWeak,
+        raw::{RawDBuf, Shared},
+        strategy::mock::{MockEvent, MockStrategy},
+    };
+
+    #[derive(Clone)]
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let mock = MockStrategy::new();
+    let shared = OwnedWithWeak::new(Shared::from_raw_parts(
+        mock.clone(),
+        RawDBuf::new(Vec::new(), Vec::new()),
+    ));
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    writer.apply(Push(1));
+    writer.publish();
+    let guard = reader.checked_get().unwrap();
+
+    // this swap can't finish while the reader is stuck
+    writer.apply(Push(2));
+    writer.publish();
+
+    writer.apply(Push(3));
+    let mut pauses = 0;
+    assert!(!writer.shutdown(|| {
+        pauses += 1;
+        pauses < 3
+    }));
+    assert_eq!(pauses, 3);
+    let events = mock.events();
+    assert_eq!(events.iter().filter(|&e| *e == MockEvent::Pause).count(), 2);
+    assert_eq!(events.last(), Some(&MockEvent::HaveReadersExited(false)));
+
+    // the writer is gone, but the guard keeps the buffers alive
+    assert_eq!(*guard, [1]);
+    drop(guard);
+    assert!(reader.checked_get().is_err());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_validator() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    enum Op {
+        Push(i32),
+        Sort,
+    }
+
+    impl Operation<Vec<i32>> for Op {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            match self {
+                Op::Push(x) => buffer.push(*x),
+                Op::Sort => buffer.sort(),
+            }
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Unsorted;
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared)).with_validator(|buffer: &Vec<i32>| {
+        if buffer.windows(2).all(|pair| pair[0] <= pair[1]) {
+            Ok(())
+        } else {
+            Err(Unsorted)
+        }
+    });
+    let mut reader = writer.reader();
+
+    writer.apply(Op::Push(1));
+    writer.apply(Op::Push(3));
+    writer.publish();
+    assert_eq!(*reader.get(), [1, 3]);
+
+    writer.apply(Op::Push(2));
+    assert_eq!(
+        writer.try_publish_validated(),
+        Err(PublishError::Invalid(Unsorted))
+    );
+    // readers stay on the last published buffer
+    assert_eq!(*reader.get(), [1, 3]);
+    assert_eq!(writer.epoch(), 1);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::PendingOps);
+    assert_eq!(writer.diff().write_buffer, &[1, 3, 2]);
+
+    // trying again doesn't apply the rejected operations twice
+    assert_eq!(
+        writer.try_publish_validated(),
+        Err(PublishError::Invalid(Unsorted))
+    );
+    assert_eq!(writer.diff().write_buffer, &[1, 3, 2]);
+
+    writer.apply(Op::Sort);
+    assert_eq!(writer.try_publish_validated(), Ok(()));
+    assert_eq!(*reader.get(), [1, 2, 3]);
+
+    // both buffers got all of the operations
+    for _ in 0..3 {
+        writer.swap_buffers();
+        assert_eq!(*reader.get(), [1, 2, 3]);
+    }
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_publish_checked() {
+    use crate::{ptrs::alloc::Owned, strategy::LocalStrategy};
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let shared = Owned::<LocalStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    writer.apply(Push(1));
+    assert_eq!(writer.publish_checked().ok(), Some(true));
+    assert_eq!(writer.publish_checked().ok(), Some(false));
+
+    // the local strategy can't swap while a read guard is held
+    let guard = reader.get();
+    writer.apply(Push(2));
+    assert!(writer.publish_checked().is_err());
+    assert!(writer.publish_checked().is_err());
+    assert_eq!(*guard, [1]);
+    assert_eq!(writer.epoch(), 1);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::PendingOps);
+    // the operation was only applied once
+    assert_eq!(writer.diff().write_buffer, &[1, 2]);
+    drop(guard);
+
+    writer.apply(Push(3));
+    assert_eq!(writer.publish_checked().ok(), Some(true));
+    assert_eq!(*reader.get(), [1, 2, 3]);
+    assert_eq!(writer.epoch(), 2);
+
+    // both buffers got all of the operations
+    for _ in 0..3 {
+        writer.swap_buffers_checked().unwrap();
+        assert_eq!(*reader.get(), [1, 2, 3]);
+    }
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_publish_history() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    writer.set_history_capacity(3);
+
+    for i in 0..5 {
+        for j in 0..=i {
+            writer.apply(Push(j));
+        }
+        writer.publish();
+    }
+
+    let records = writer.recent_publishes().collect::<Vec<_>>();
+    assert_eq!(
+        records.iter().map(|r| r.epoch).collect::<Vec<_>>(),
+        [3, 4, 5]
+    );
+    assert_eq!(
+        records.iter().map(|r| r.ops_applied).collect::<Vec<_>>(),
+        [3, 4, 5]
+    );
+    assert!(records.windows(2).all(|w| w[0].at <= w[1].at));
+
+    // shrinking drops the oldest records
+    writer.set_history_capacity(1);
+    assert_eq!(
+        writer
+            .recent_publishes()
+            .map(|r| r.epoch)
+            .collect::<Vec<_>>(),
+        [5]
+    );
+
+    writer.set_history_capacity(2);
+    writer.swap_buffers();
+    let records = writer.recent_publishes().collect::<Vec<_>>();
+    assert_eq!(records.iter().map(|r| r.epoch).collect::<Vec<_>>(), [5, 6]);
+    assert_eq!(records[1].ops_applied, 0);
+
+    writer.set_history_capacity(0);
+    assert_eq!(writer.recent_publishes().count(), 0);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_disabled_history_reads_no_clock() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// the number of times [`counting_now`] was called
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    /// the current time, counting the calls
+    fn counting_now() -> Instant {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        Instant::now()
+    }
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    writer.history_mut().now = counting_now;
+
+    for i in 0..10 {
+        writer.apply(Push(i));
+        writer.publish();
+    }
+    assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+    assert_eq!(writer.recent_publishes().count(), 0);
+
+    writer.set_history_capacity(4);
+    writer.apply(Push(10));
+    writer.publish();
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_publish_totals() {
+    use crate::{
+        ptrs::alloc::OwnedWithWeak,
+        raw::{RawDBuf, Shared},
+        strategy::mock::MockStrategy,
+    };
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let mock = MockStrategy::new();
+    let shared = OwnedWithWeak::new(Shared::from_raw_parts(
+        mock.clone(),
+        RawDBuf::new(Vec::new(), Vec::new()),
+    ));
+    let mut writer = OpWriter::from(Writer::new(shared));
+    assert_eq!(writer.publish_totals(), SwapTotals::ZERO);
+
+    for i in 0..4 {
+        if i == 1 {
+            mock.hold_next_capture_for(2);
+        }
+        if i == 2 {
+            mock.hold_next_capture_for(3);
+        }
+        writer.apply(Push(i));
+        writer.publish();
+    }
+
+    // each publish is finished by the next one, so the last one is still in progress
+    assert_eq!(
+        writer.publish_totals(),
+        SwapTotals {
+            swaps: 3,
+            pauses: 5,
+        }
+    );
+    assert_eq!(writer.last_publish_stats().pauses, 3);
+
+    // quiescence isn't a publish, but it finishes the last one
+    writer.wait_for_quiescence();
+    assert_eq!(writer.publish_totals().swaps, 4);
+    assert_eq!(writer.last_publish_stats(), SwapStats::IMMEDIATE);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_strict_mode() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let new_writer = || {
+        let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+        OpWriter::from(Writer::new(shared))
+    };
+    let mut normal = new_writer();
+    let mut strict = new_writer();
+    let mut normal_reader = normal.reader();
+    let mut strict_reader = strict.reader();
+
+    normal.apply(Push(-1));
+    normal.publish();
+    strict.apply(Push(-1));
+    strict.publish();
+
+    // turning strict mode on finishes the in progress swap
+    strict.set_strict_mode_checked(true);
+    assert!(strict.is_strict_mode());
+    assert!(strict.writer.is_swap_finished());
+
+    for i in 0..10 {
+        normal.apply(Push(i));
+        strict.apply(Push(i));
+        if i % 3 == 0 {
+            normal.publish();
+            strict.publish();
+
+            // the publish is finished, and the writer buffer is up to date
+            assert!(strict.writer.is_swap_finished());
+            assert!(strict.ops().is_empty());
+            let split = strict.split();
+            assert_eq!(split.writer, split.reader);
+        }
+
+        assert_eq!(*normal_reader.get(), *strict_reader.get());
+    }
+
+    strict.set_strict_mode(false);
+    assert!(!strict.is_strict_mode());
+    normal.publish();
+    strict.publish();
+    assert_eq!(*normal_reader.get(), *strict_reader.get());
+    assert_eq!(normal.verify_buffers_eq(), Consistency::Consistent);
+    assert_eq!(strict.verify_buffers_eq(), Consistency::Consistent);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[should_panic = "strict mode: the buffers diverged after publish 1 which applied 1 operations"]
+fn test_strict_mode_catches_divergence() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    /// pushes a different value to each buffer
+    struct Count(i32);
+
+    impl Operation<Vec<i32>> for Count {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            self.0 += 1;
+            buffer.push(self.0)
+        }
+    }
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    writer.set_strict_mode_checked(true);
+    writer.apply(Count(0));
+    writer.publish();
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_resync_writer_buffer() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    // the writer buffer starts out empty, and is only filled in on demand
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), std::vec![1, 2]);
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+    assert_eq!(*reader.get(), [1, 2]);
+
+    writer.apply(Push(3));
+    writer.resync_writer_buffer(|writer, reader| writer.clone_from(reader));
+    // the copy doesn't count as an operation
+    assert_eq!(writer.unapplied().len(), 1);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::PendingOps);
+
+    writer.publish();
+    assert_eq!(*reader.get(), [1, 2, 3]);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+
+    // after a publish, the published operations aren't applied twice
+    writer.apply(Push(4));
+    writer.publish();
+    writer.resync_writer_buffer(|writer, reader| writer.clone_from(reader));
+    writer.apply(Push(5));
+    writer.publish();
+    assert_eq!(*reader.get(), [1, 2, 3, 4, 5]);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_apply_to_both() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let shared = Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::<_, ApplyToBoth<Vec<i32>>>::from(Writer::new(shared));
+
+    let mut count = 0;
+    writer.apply_to_both(move |buffer: &mut Vec<i32>| {
+        // each buffer starts from the same count
+        count += 1;
+        buffer.push(count);
+    });
+    writer.publish();
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+
+    let split = writer.split();
+    assert_eq!(*split.reader, [1]);
+    assert_eq!(*split.writer, [1]);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_revert_publish() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    enum Op {
+        Push(i32),
+        Pop,
+    }
+
+    impl Operation<Vec<i32>> for Op {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            match self {
+                Op::Push(x) => buffer.push(*x),
+                Op::Pop => {
+                    buffer.pop();
+                }
+            }
+        }
+    }
+
+    let shared = Owned::<HazardStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    assert_eq!(writer.revert_publish(), Err(NoPendingSwap));
+    writer.apply(Op::Push(1));
+    writer.publish();
+    writer.apply(Op::Push(2));
+    writer.publish();
+    assert_eq!(*reader.get(), [1, 2]);
+
+    writer.revert_publish().unwrap();
+    assert_eq!(*reader.get(), [1]);
+    assert_eq!(writer.revert_publish(), Err(NoPendingSwap));
+    assert_eq!(writer.verify_buffers_eq(), Consistency::PendingOps);
+
+    // the writer buffer still has the reverted push
+    writer.apply(Op::Pop);
+    writer.apply(Op::Push(3));
+    writer.publish();
+    assert_eq!(*reader.get(), [1, 3]);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_revert_publish_with() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let shared = Owned::<HazardStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    let restore = |writer: &mut Vec<i32>, reader: &Vec<i32>| writer.clone_from(reader);
+    assert_eq!(writer.revert_publish_with(restore), Err(NoPendingSwap));
+    writer.apply(Push(1));
+    writer.publish();
+    writer.apply(Push(2));
+    writer.publish();
+    writer.apply(Push(3));
+    assert_eq!(*reader.get(), [1, 2]);
+
+    writer.revert_publish_with(restore).unwrap();
+    assert_eq!(*reader.get(), [1]);
+    assert_eq!(writer.revert_publish_with(restore), Err(NoPendingSwap));
+
+    // the reverted push is gone, but the one after it is still published
+    writer.publish();
+    assert_eq!(*reader.get(), [1, 3]);
+    writer.apply(Push(4));
+    writer.publish();
+    assert_eq!(*reader.get(), [1, 3, 4]);
+    writer.publish();
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_rewrite_unapplied() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    #[derive(Debug, PartialEq)]
+    enum Op {
+        Push(i32),
+        Clear,
+    }
+
+    impl Operation<Vec<i32>> for Op {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            match self {
+                Op::Push(x) => buffer.push(*x),
+                Op::Clear => buffer.clear(),
+            }
+        }
+    }
+
+    let shared = Owned::<HazardStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    writer.apply(Op::Push(1));
+    writer.publish();
+    writer.apply(Op::Push(2));
+    writer.apply(Op::Clear);
+    writer.apply(Op::Push(3));
+    assert_eq!(writer.ops().len(), 4);
+
+    // the applied push isn't part of the rewrite
+    writer.rewrite_unapplied(|ops| {
+        assert_eq!(*ops, [Op::Push(2), Op::Clear, Op::Push(3)]);
+        // everything before a clear is redundant
+        if let Some(clear) = ops.iter().rposition(|op| *op == Op::Clear) {
+            ops.drain(..clear);
+        }
+        ops.push(Op::Push(4));
+    });
+    assert_eq!(
+        writer.ops(),
+        [Op::Push(1), Op::Clear, Op::Push(3), Op::Push(4)]
+    );
+
+    for op in writer.unapplied_mut() {
+        if let Op::Push(x) = op {
+            *x *= 10;
+        }
+    }
+
+    writer.publish();
+    assert_eq!(*reader.get(), [30, 40]);
+    writer.apply(Op::Push(5));
+    writer.publish();
+    assert_eq!(*reader.get(), [30, 40, 5]);
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_rewrite_unapplied_panic() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    struct Push(i32);
+
+    impl Operation<Vec<i32>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<i32>) {
+            buffer.push(self.0)
+        }
+    }
+
+    let shared = Owned::<HazardStrategy, _>::from_buffers(Vec::new(), Vec::new());
+    let mut writer = OpWriter::from(Writer::new(shared));
+
+    writer.apply(Push(1));
+    writer.publish();
+    writer.apply(Push(2));
+    writer.apply(Push(3));
+
+    // the log keeps what's left of the rewrite
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        writer.rewrite_unapplied(|ops| {
+            ops.remove(0);
+            panic!()
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(writer.ops().len(), 2);
+
+    writer.publish();
+    assert_eq!(*writer.reader().get(), [1, 3]);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_publish_matching() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    /// add to one of the counters
+    #[derive(Debug, PartialEq)]
+    struct Add(usize, i32);
+
+    impl Operation<[i32; 2]> for Add {
+        fn apply(&mut self, buffer: &mut [i32; 2]) {
+            buffer[self.0] += self.1
+        }
+    }
+
+    let shared = Owned::<HazardStrategy, _>::from_buffers([0; 2], [0; 2]);
+    let mut writer = OpWriter::from(Writer::new(shared));
+    let mut reader = writer.reader();
+
+    writer.apply(Add(0, 1));
+    writer.apply(Add(1, 10));
+    writer.apply(Add(0, 2));
+    writer.apply(Add(1, 20));
+
+    assert_eq!(writer.publish_matching(|op| op.0 == 0), 2);
+    assert_eq!(*reader.get(), [3, 0]);
+    assert_eq!(writer.unapplied(), [Add(1, 10), Add(1, 20)]);
+
+    writer.apply(Add(0, 4));
+    assert_eq!(writer.publish_matching(|op| op.0 == 0), 1);
+    assert_eq!(*reader.get(), [7, 0]);
+    assert_eq!(writer.unapplied(), [Add(1, 10), Add(1, 20)]);
+
+    assert_eq!(writer.publish_matching(|_| true), 2);
+    assert_eq!(*reader.get(), [7, 30]);
+    writer.publish();
+    assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
 }
@@ -30,8 +30,19 @@
 //! If an operation panics, then subsequent operations may be skipped or dropped. This is to allow for
 //! more optimized operation application during non-panic situations, but may make other double buffered
 //! data structures built atop this out of sync! So be careful to not panic during operation application.
+//! (see [`OpWriter`](crate::op::OpWriter) for how it detects and recovers from panicking operations)
+//!
+//! Without the `alloc` feature, [`ArrayOpLog`] provides the same bookkeeping with a fixed capacity.
+//! Both implement [`OpLogBackend`], which is what [`OpWriter`](crate::op::OpWriter) is generic over.
+//!
+//! If the operations are large, e.g. an enum whose biggest variant is rarely used, then an
+//! [`OpLog`] with [`Boxed`] storage keeps each of them in its own allocation, so that pushing
+//! and removing them only moves a pointer. Since the operations aren't next to each other,
+//! they can only be iterated over, see [`OpLogBackend::unapplied_iter`].
 
-use std::vec::Vec;
+use core::mem::MaybeUninit;
+#[cfg(feature = "alloc")]
+use std::{boxed::Box, vec::Vec};
 
 /// An operation that can be applied to a buffer
 ///
@@ -46,14 +57,410 @@ pub trait Operation<B: ?Sized>: Sized {
     }
 }
 
+/// An operation which runs a closure on each buffer, see [`OpWriter::apply_to_both`](crate::op::OpWriter::apply_to_both)
+///
+/// Each buffer gets a fresh clone of the closure, so a closure which keeps some state
+/// makes the same changes to both buffers
+#[cfg(feature = "alloc")]
+pub struct ApplyToBoth<B: ?Sized> {
+    /// runs a clone of the closure on the buffer
+    f: Box<dyn FnMut(&mut B) + Send>,
+}
+
+#[cfg(feature = "alloc")]
+impl<B: ?Sized> ApplyToBoth<B> {
+    /// Create an operation which runs a clone of `f` on each buffer
+    pub fn new<F: FnMut(&mut B) + Clone + Send + 'static>(f: F) -> Self {
+        Self {
+            f: Box::new(move |buffer| f.clone()(buffer)),
+        }
+    }
+}
+
+// SAFETY: the closure is only reachable through `&mut ApplyToBoth`,
+// so a shared reference can't be used to do anything with it
+#[cfg(feature = "alloc")]
+unsafe impl<B: ?Sized> Sync for ApplyToBoth<B> {}
+
+#[cfg(feature = "alloc")]
+impl<B: ?Sized> Operation<B> for ApplyToBoth<B> {
+    fn apply(&mut self, buffer: &mut B) {
+        (self.f)(buffer)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<B: ?Sized> core::fmt::Debug for ApplyToBoth<B> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ApplyToBoth").finish_non_exhaustive()
+    }
+}
+
+/// The storage of an operation log, see [`OpLog`] and [`ArrayOpLog`]
+///
+/// The operations are stored in order, and the first [`applied`](Self::applied)
+/// operations were applied to the previous buffer
+pub trait OpLogBackend<O> {
+    /// the error returned when the log can't hold another operation
+    type PushError;
+
+    /// Appends an operation to the back of the log
+    fn push(&mut self, op: O) -> Result<(), Self::PushError>;
+
+    /// All operations in the log, starting with the ones which were applied to the previous buffer
+    ///
+    /// # Panics
+    ///
+    /// if the operations aren't stored next to each other, like in an [`OpLog`] with [`Boxed`] storage
+    fn ops(&self) -> &[O];
+
+    /// The number of operations which have been applied to the previous buffer
+    fn applied(&self) -> usize;
+
+    /// All operations which haven't yet been applied, see [`OpLog::unapplied_mut`]
+    ///
+    /// # Panics
+    ///
+    /// if the operations aren't stored next to each other, like in an [`OpLog`] with [`Boxed`] storage
+    fn unapplied_mut(&mut self) -> &mut [O];
+
+    /// Remove all operations from the log
+    fn clear(&mut self);
+
+    /// apply the operations which were applied to the previous buffer to the given buffer
+    ///
+    /// after this, the two buffers only differ by the unapplied operations
+    fn catch_up<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>;
+
+    /// Remove the operations which were applied to the previous buffer, without applying them
+    ///
+    /// This is for when the previous buffer doesn't have them anymore, see [`OpWriter::revert_publish_with`](crate::op::OpWriter::revert_publish_with)
+    fn discard_applied(&mut self);
+
+    /// apply all operations to the given buffer
+    fn apply<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>;
+
+    /// apply the operations which haven't been applied yet to the given buffer
+    ///
+    /// after this, they count as applied to the previous buffer.
+    /// This is for a buffer which already has the applied operations
+    fn apply_unapplied<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>;
+
+    /// The number of operations in the log
+    fn len(&self) -> usize {
+        self.ops().len()
+    }
+
+    /// Returns true if there are no operations in the log
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// All operations which haven't yet been applied
+    ///
+    /// # Panics
+    ///
+    /// if the operations aren't stored next to each other, like in an [`OpLog`] with [`Boxed`] storage
+    fn unapplied(&self) -> &[O] {
+        &self.ops()[self.applied()..]
+    }
+
+    /// All operations which haven't yet been applied
+    ///
+    /// Unlike [`unapplied`](Self::unapplied), this works no matter how the operations are stored
+    fn unapplied_iter(&self) -> OpIter<'_, O> {
+        OpIter::from(self.unapplied())
+    }
+
+    /// All operations which haven't yet been applied, see [`OpLog::unapplied_mut`]
+    ///
+    /// Unlike [`unapplied_mut`](Self::unapplied_mut), this works no matter how the operations are stored
+    fn unapplied_iter_mut(&mut self) -> OpIterMut<'_, O> {
+        OpIterMut::from(self.unapplied_mut())
+    }
+
+    /// The number of operations which haven't yet been applied
+    fn unapplied_len(&self) -> usize {
+        self.len() - self.applied()
+    }
+}
+
+/// the panic message when the operations of an op log are borrowed as a slice, but they are boxed
+pub(crate) const NOT_CONTIGUOUS: &str =
+    "the operations are boxed, so they can't be borrowed as a slice, use `unapplied_iter` instead";
+
+/// An iterator over the operations in an op log, see [`OpLogBackend::unapplied_iter`]
+pub struct OpIter<'a, O> {
+    /// the operations, and how they are stored
+    slots: Slots<'a, O>,
+}
+
+/// An iterator over the operations in an op log, see [`OpLogBackend::unapplied_iter_mut`]
+pub struct OpIterMut<'a, O> {
+    /// the operations, and how they are stored
+    slots: SlotsMut<'a, O>,
+}
+
+/// the operations iterated over by [`OpIter`]
+enum Slots<'a, O> {
+    /// operations which are stored in the log
+    Inline(core::slice::Iter<'a, O>),
+    /// operations which are each stored in a box
+    #[cfg(feature = "alloc")]
+    Boxed(core::slice::Iter<'a, Box<O>>),
+}
+
+/// the operations iterated over by [`OpIterMut`]
+enum SlotsMut<'a, O> {
+    /// operations which are stored in the log
+    Inline(core::slice::IterMut<'a, O>),
+    /// operations which are each stored in a box
+    #[cfg(feature = "alloc")]
+    Boxed(core::slice::IterMut<'a, Box<O>>),
+}
+
+impl<'a, O> From<&'a [O]> for OpIter<'a, O> {
+    fn from(ops: &'a [O]) -> Self {
+        Self {
+            slots: Slots::Inline(ops.iter()),
+        }
+    }
+}
+
+impl<'a, O> From<&'a mut [O]> for OpIterMut<'a, O> {
+    fn from(ops: &'a mut [O]) -> Self {
+        Self {
+            slots: SlotsMut::Inline(ops.iter_mut()),
+        }
+    }
+}
+
+impl<'a, O> OpIter<'a, O> {
+    /// the remaining operations, if they are stored next to each other
+    pub(crate) fn as_slice(&self) -> Option<&'a [O]> {
+        match &self.slots {
+            Slots::Inline(iter) => Some(iter.as_slice()),
+            #[cfg(feature = "alloc")]
+            Slots::Boxed(_) => None,
+        }
+    }
+}
+
+impl<O> Clone for OpIter<'_, O> {
+    fn clone(&self) -> Self {
+        let slots = match &self.slots {
+            Slots::Inline(iter) => Slots::Inline(iter.clone()),
+            #[cfg(feature = "alloc")]
+            Slots::Boxed(iter) => Slots::Boxed(iter.clone()),
+        };
+        Self { slots }
+    }
+}
+
+impl<O: core::fmt::Debug> core::fmt::Debug for OpIter<'_, O> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+/// forward an iterator method to the iterator over the slots, and unbox boxed operations
+macro_rules! forward {
+    ($slots:ident: $self:expr, |$iter:ident| $e:expr, |$op:ident| $unbox:expr) => {
+        match $self {
+            $slots::Inline($iter) => $e,
+            #[cfg(feature = "alloc")]
+            $slots::Boxed($iter) => $e.map(|$op| $unbox),
+        }
+    };
+    ($slots:ident: $self:expr, |$iter:ident| $e:expr) => {
+        match $self {
+            $slots::Inline($iter) => $e,
+            #[cfg(feature = "alloc")]
+            $slots::Boxed($iter) => $e,
+        }
+    };
+}
+
+impl<'a, O> Iterator for OpIter<'a, O> {
+    type Item = &'a O;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        forward!(Slots: &mut self.slots, |iter| iter.next(), |op| &**op)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        forward!(Slots: &mut self.slots, |iter| iter.nth(n), |op| &**op)
+    }
+}
+
+impl<O> DoubleEndedIterator for OpIter<'_, O> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        forward!(Slots: &mut self.slots, |iter| iter.next_back(), |op| &**op)
+    }
+}
+
+impl<O> ExactSizeIterator for OpIter<'_, O> {
+    fn len(&self) -> usize {
+        forward!(Slots: &self.slots, |iter| iter.len())
+    }
+}
+
+impl<O> core::iter::FusedIterator for OpIter<'_, O> {}
+
+impl<'a, O> Iterator for OpIterMut<'a, O> {
+    type Item = &'a mut O;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        forward!(SlotsMut: &mut self.slots, |iter| iter.next(), |op| &mut **op)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        forward!(SlotsMut: &mut self.slots, |iter| iter.nth(n), |op| &mut **op)
+    }
+}
+
+impl<O> DoubleEndedIterator for OpIterMut<'_, O> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        forward!(SlotsMut: &mut self.slots, |iter| iter.next_back(), |op| &mut **op)
+    }
+}
+
+impl<O> ExactSizeIterator for OpIterMut<'_, O> {
+    fn len(&self) -> usize {
+        forward!(SlotsMut: &self.slots, |iter| iter.len())
+    }
+}
+
+impl<O> core::iter::FusedIterator for OpIterMut<'_, O> {}
+
+/// How an [`OpLog`] stores its operations, either [`Inline`] or [`Boxed`]
+#[cfg(feature = "alloc")]
+pub trait OpStorage {
+    /// how a single operation is stored
+    type Slot<O>;
+
+    /// store an operation
+    fn store<O>(op: O) -> Self::Slot<O>;
+
+    /// take the operation back out of its slot
+    fn take<O>(slot: Self::Slot<O>) -> O;
+
+    /// iterate over the stored operations
+    fn iter<O>(slots: &[Self::Slot<O>]) -> OpIter<'_, O>;
+
+    /// iterate over the stored operations
+    fn iter_mut<O>(slots: &mut [Self::Slot<O>]) -> OpIterMut<'_, O>;
+
+    /// the stored operations, if they are stored next to each other
+    fn as_slice<O>(slots: &[Self::Slot<O>]) -> Option<&[O]>;
+
+    /// the stored operations, if they are stored next to each other
+    fn as_mut_slice<O>(slots: &mut [Self::Slot<O>]) -> Option<&mut [O]>;
+}
+
+/// Store the operations directly in the [`OpLog`], this is the default
+#[cfg(feature = "alloc")]
+pub enum Inline {}
+
+/// Store each operation of an [`OpLog`] in its own allocation, see [`OpLog::new_boxed`]
+///
+/// This makes the log's elements as small as a pointer, no matter how large the operations are.
+/// Pushing costs an allocation, but pushing and removing operations only moves the pointer.
+/// Applying an operation for the last time still moves the operation out of its box, so
+/// [`Operation::apply_last`] can reuse its parts
+#[cfg(feature = "alloc")]
+pub enum Boxed {}
+
+#[cfg(feature = "alloc")]
+impl OpStorage for Inline {
+    type Slot<O> = O;
+
+    fn store<O>(op: O) -> Self::Slot<O> {
+        op
+    }
+
+    fn take<O>(slot: Self::Slot<O>) -> O {
+        slot
+    }
+
+    fn iter<O>(slots: &[Self::Slot<O>]) -> OpIter<'_, O> {
+        OpIter::from(slots)
+    }
+
+    fn iter_mut<O>(slots: &mut [Self::Slot<O>]) -> OpIterMut<'_, O> {
+        OpIterMut::from(slots)
+    }
+
+    fn as_slice<O>(slots: &[Self::Slot<O>]) -> Option<&[O]> {
+        Some(slots)
+    }
+
+    fn as_mut_slice<O>(slots: &mut [Self::Slot<O>]) -> Option<&mut [O]> {
+        Some(slots)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl OpStorage for Boxed {
+    type Slot<O> = Box<O>;
+
+    fn store<O>(op: O) -> Self::Slot<O> {
+        Box::new(op)
+    }
+
+    fn take<O>(slot: Self::Slot<O>) -> O {
+        *slot
+    }
+
+    fn iter<O>(slots: &[Self::Slot<O>]) -> OpIter<'_, O> {
+        OpIter {
+            slots: Slots::Boxed(slots.iter()),
+        }
+    }
+
+    fn iter_mut<O>(slots: &mut [Self::Slot<O>]) -> OpIterMut<'_, O> {
+        OpIterMut {
+            slots: SlotsMut::Boxed(slots.iter_mut()),
+        }
+    }
+
+    fn as_slice<O>(_: &[Self::Slot<O>]) -> Option<&[O]> {
+        None
+    }
+
+    fn as_mut_slice<O>(_: &mut [Self::Slot<O>]) -> Option<&mut [O]> {
+        None
+    }
+}
+
 /// an operation log which tracks which operations were applied to which buffer
-pub struct OpLog<O> {
+///
+/// The operations are stored [`Inline`] by default, see [`OpLog::new_boxed`] for large operations
+#[cfg(feature = "alloc")]
+pub struct OpLog<O, St: OpStorage = Inline> {
     /// the list of in progress operations
-    ops: Vec<O>,
+    ops: Vec<St::Slot<O>>,
     /// the number of operations that have been applied to the previous buffer
     applied: usize,
 }
 
+#[cfg(feature = "alloc")]
 impl<O> OpLog<O> {
     /// create a new op log
     pub const fn new() -> Self {
@@ -65,6 +472,93 @@ impl<O> OpLog<O> {
         Self { ops, applied: 0 }
     }
 
+    /// create an op log where the first `applied` operations were already applied to the previous buffer
+    ///
+    /// # Panics
+    ///
+    /// if `applied` is larger than the number of operations
+    pub fn from_raw_parts(ops: Vec<O>, applied: usize) -> Self {
+        assert!(
+            applied <= ops.len(),
+            "cannot have applied more operations than there are in the log"
+        );
+        Self { ops, applied }
+    }
+
+    /// split the op log into all of its operations and the number which were applied to the previous buffer
+    pub fn into_raw_parts(self) -> (Vec<O>, usize) {
+        (self.ops, self.applied)
+    }
+
+    /// All operations in the log, starting with the ones which were applied to the previous buffer
+    #[deprecated(note = "use `ops_iter`, which also works with boxed operations")]
+    pub fn ops(&self) -> &[O] {
+        &self.ops
+    }
+
+    /// All operations which haven't yet been applied
+    #[deprecated(note = "use `unapplied_iter`, which also works with boxed operations")]
+    pub fn unapplied(&self) -> &[O] {
+        &self.ops[self.applied..]
+    }
+
+    /// All operations which haven't yet been applied
+    ///
+    /// These haven't been applied to either buffer, so they can be changed freely.
+    /// The operations which were applied to the previous buffer can't be changed, since
+    /// they must be applied the same way to the writer buffer
+    #[deprecated(note = "use `unapplied_iter_mut`, which also works with boxed operations")]
+    pub fn unapplied_mut(&mut self) -> &mut [O] {
+        &mut self.ops[self.applied..]
+    }
+
+    /// Move each operation into its own allocation, see [`Boxed`]
+    pub fn into_boxed(self) -> OpLog<O, Boxed> {
+        OpLog {
+            ops: self.ops.into_iter().map(Box::new).collect(),
+            applied: self.applied,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<O> OpLog<O, Boxed> {
+    /// create a new op log which stores each operation in its own allocation, see [`Boxed`]
+    pub const fn new_boxed() -> Self {
+        Self {
+            ops: Vec::new(),
+            applied: 0,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<O, St: OpStorage> OpLog<O, St> {
+    /// All operations in the log, starting with the ones which were applied to the previous buffer
+    pub fn ops_iter(&self) -> OpIter<'_, O> {
+        St::iter(&self.ops)
+    }
+
+    /// The number of operations in the log
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns true if there are no operations in the log
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// The number of operations which have been applied to the previous buffer
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    /// The number of operations the log can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.ops.capacity()
+    }
+
     /// Shrinks the capacity of the vector with a lower bound.
     ///
     /// The capacity will remain at least as large as both the length
@@ -90,12 +584,95 @@ impl<O> OpLog<O> {
 
     /// Appends an element to the back of the `OpLog`.
     pub fn push(&mut self, op: O) {
-        self.ops.push(op)
+        self.ops.push(St::store(op))
     }
 
     /// All operations which haven't yet been applied
-    pub fn unapplied(&self) -> &[O] {
-        &self.ops[self.applied..]
+    pub fn unapplied_iter(&self) -> OpIter<'_, O> {
+        St::iter(&self.ops[self.applied..])
+    }
+
+    /// All operations which haven't yet been applied
+    ///
+    /// These haven't been applied to either buffer, so they can be changed freely.
+    /// The operations which were applied to the previous buffer can't be changed, since
+    /// they must be applied the same way to the writer buffer
+    pub fn unapplied_iter_mut(&mut self) -> OpIterMut<'_, O> {
+        St::iter_mut(&mut self.ops[self.applied..])
+    }
+
+    /// Rewrite the operations which haven't yet been applied, for example to remove
+    /// redundant operations or reorder them
+    ///
+    /// `f` gets the unapplied operations as a `Vec`, so it can also remove and add operations.
+    /// The operations which were applied to the previous buffer aren't in the `Vec`, so they
+    /// can't be changed. After the rewrite, each operation is applied to both buffers like any other,
+    /// so the buffers stay the same, as long as the operations themselves are deterministic.
+    /// If `f` panics, the log keeps whatever is left in the `Vec`. With [`Boxed`] storage,
+    /// the `Vec` holds the boxed operations
+    pub fn rewrite_unapplied(&mut self, f: impl FnOnce(&mut Vec<St::Slot<O>>)) {
+        if self.applied == 0 {
+            return f(&mut self.ops);
+        }
+
+        let unapplied = self.ops.split_off(self.applied);
+        let mut unapplied =
+            scopeguard::guard(unapplied, |mut unapplied| self.ops.append(&mut unapplied));
+        f(&mut unapplied)
+    }
+
+    /// Move the unapplied operations which match `pred` in front of the ones which don't,
+    /// and return how many matched
+    ///
+    /// The operations in each group stay in the same order. `pred` is called once for each
+    /// unapplied operation, in order, and if it panics the log isn't changed.
+    /// see [`OpWriter::publish_matching`](crate::op::OpWriter::publish_matching)
+    pub fn partition_unapplied(&mut self, mut pred: impl FnMut(&O) -> bool) -> usize {
+        let matches = self.unapplied_iter().map(&mut pred).collect::<Vec<_>>();
+        let matching = matches.iter().filter(|&&matches| matches).count();
+
+        let unapplied = self.ops.split_off(self.applied);
+        let mut rest = Vec::with_capacity(unapplied.len() - matching);
+        for (op, matches) in unapplied.into_iter().zip(matches) {
+            if matches {
+                self.ops.push(op)
+            } else {
+                rest.push(op)
+            }
+        }
+        self.ops.append(&mut rest);
+
+        matching
+    }
+
+    /// Remove all operations from the log
+    pub fn clear(&mut self) {
+        self.ops.clear();
+        self.applied = 0;
+    }
+
+    /// apply the operations which were applied to the previous buffer to the given buffer
+    ///
+    /// after this, the two buffers only differ by the unapplied operations
+    pub fn catch_up<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        // take applied first so that a panicking operation leaves the log with
+        // only the operations that haven't been applied to any buffer yet
+        let applied = core::mem::take(&mut self.applied);
+
+        for op in self.ops.drain(..applied) {
+            St::take(op).apply_last(buffer);
+        }
+    }
+
+    /// Remove the operations which were applied to the previous buffer, without applying them
+    ///
+    /// This is for when the previous buffer doesn't have them anymore, see [`OpWriter::revert_publish_with`](crate::op::OpWriter::revert_publish_with)
+    pub fn discard_applied(&mut self) {
+        let applied = core::mem::take(&mut self.applied);
+        self.ops.drain(..applied);
     }
 
     /// apply all operations to the given buffer
@@ -103,20 +680,609 @@ impl<O> OpLog<O> {
     where
         O: Operation<B>,
     {
-        for op in self.ops.drain(..self.applied) {
+        self.catch_up(buffer);
+        self.apply_unapplied(buffer);
+    }
+
+    /// apply the operations which haven't been applied yet to the given buffer
+    ///
+    /// after this, they count as applied to the previous buffer.
+    /// This is for a buffer which already has the applied operations
+    pub fn apply_unapplied<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        let applied = core::mem::replace(&mut self.applied, self.ops.len());
+
+        for op in St::iter_mut(&mut self.ops[applied..]) {
+            op.apply(buffer)
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<O, St: OpStorage> Default for OpLog<O, St> {
+    fn default() -> Self {
+        Self {
+            ops: Vec::new(),
+            applied: 0,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<O, St: OpStorage> OpLogBackend<O> for OpLog<O, St> {
+    type PushError = core::convert::Infallible;
+
+    fn push(&mut self, op: O) -> Result<(), Self::PushError> {
+        self.push(op);
+        Ok(())
+    }
+
+    fn ops(&self) -> &[O] {
+        St::as_slice(&self.ops).expect(NOT_CONTIGUOUS)
+    }
+
+    fn applied(&self) -> usize {
+        self.applied()
+    }
+
+    fn unapplied_mut(&mut self) -> &mut [O] {
+        St::as_mut_slice(&mut self.ops[self.applied..]).expect(NOT_CONTIGUOUS)
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn catch_up<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        self.catch_up(buffer)
+    }
+
+    fn discard_applied(&mut self) {
+        self.discard_applied()
+    }
+
+    fn apply<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        self.apply(buffer)
+    }
+
+    fn apply_unapplied<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        self.apply_unapplied(buffer)
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn unapplied_iter(&self) -> OpIter<'_, O> {
+        self.unapplied_iter()
+    }
+
+    fn unapplied_iter_mut(&mut self) -> OpIterMut<'_, O> {
+        self.unapplied_iter_mut()
+    }
+}
+
+/// The error returned when pushing to a full [`ArrayOpLog`], this hands back the operation
+pub struct OpLogFull<O>(pub O);
+
+impl<O> core::fmt::Debug for OpLogFull<O> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the op log is full, publish to make room for more operations")
+    }
+}
+
+/// an operation log with a fixed capacity, which doesn't need to allocate
+///
+/// This has the same semantics as [`OpLog`], except that [`push`](Self::push)
+/// fails once there are `N` operations in the log. Publishing (or [`catch_up`](Self::catch_up))
+/// removes the operations which were applied to both buffers, and makes room for more.
+///
+/// This isn't a ring buffer, the operations always start at the front of the array, so that they
+/// can be borrowed as a slice (see [`ops`](Self::ops)). So [`catch_up`](Self::catch_up) moves the
+/// unapplied operations to the front, which is cheap for the handful of operations between publishes
+/// that this is meant for.
+pub struct ArrayOpLog<O, const N: usize> {
+    /// the operations, only the first `len` are initialized
+    ops: [MaybeUninit<O>; N],
+    /// the number of initialized operations
+    len: usize,
+    /// the number of operations that have been applied to the previous buffer
+    applied: usize,
+}
+
+impl<O, const N: usize> ArrayOpLog<O, N> {
+    /// create a new op log
+    pub const fn new() -> Self {
+        Self {
+            // SAFETY: an array of `MaybeUninit` doesn't need to be initialized
+            ops: unsafe { MaybeUninit::uninit().assume_init() },
+            len: 0,
+            applied: 0,
+        }
+    }
+
+    /// All operations in the log, starting with the ones which were applied to the previous buffer
+    #[deprecated(note = "use `ops_iter`, like with an `OpLog`")]
+    pub fn ops(&self) -> &[O] {
+        self.ops_ref()
+    }
+
+    /// All operations in the log, starting with the ones which were applied to the previous buffer
+    pub fn ops_iter(&self) -> OpIter<'_, O> {
+        OpIter::from(self.ops_ref())
+    }
+
+    /// the operations in the log
+    fn ops_ref(&self) -> &[O] {
+        // SAFETY: the first `len` operations are initialized
+        unsafe { core::slice::from_raw_parts(self.ops.as_ptr().cast(), self.len) }
+    }
+
+    /// the operations in the log
+    fn ops_mut(&mut self) -> &mut [O] {
+        // SAFETY: the first `len` operations are initialized
+        unsafe { core::slice::from_raw_parts_mut(self.ops.as_mut_ptr().cast(), self.len) }
+    }
+
+    /// The number of operations which have been applied to the previous buffer
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    /// The maximum number of operations this log can hold
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Appends an element to the back of the `ArrayOpLog`, or hands it back if the log is full
+    pub fn push(&mut self, op: O) -> Result<(), OpLogFull<O>> {
+        match self.ops.get_mut(self.len) {
+            Some(slot) => {
+                slot.write(op);
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(OpLogFull(op)),
+        }
+    }
+
+    /// All operations which haven't yet been applied
+    #[deprecated(note = "use `unapplied_iter`, like with an `OpLog`")]
+    pub fn unapplied(&self) -> &[O] {
+        &self.ops_ref()[self.applied..]
+    }
+
+    /// All operations which haven't yet been applied, see [`OpLog::unapplied_mut`]
+    #[deprecated(note = "use `unapplied_iter_mut`, like with an `OpLog`")]
+    pub fn unapplied_mut(&mut self) -> &mut [O] {
+        let applied = self.applied;
+        &mut self.ops_mut()[applied..]
+    }
+
+    /// All operations which haven't yet been applied
+    pub fn unapplied_iter(&self) -> OpIter<'_, O> {
+        OpIter::from(&self.ops_ref()[self.applied..])
+    }
+
+    /// All operations which haven't yet been applied, see [`OpLog::unapplied_iter_mut`]
+    pub fn unapplied_iter_mut(&mut self) -> OpIterMut<'_, O> {
+        let applied = self.applied;
+        OpIterMut::from(&mut self.ops_mut()[applied..])
+    }
+
+    /// Remove all operations from the log
+    pub fn clear(&mut self) {
+        let ops: *mut [O] = self.ops_mut();
+        // set the length first, so that a panicking drop leaks the rest instead of double dropping
+        self.len = 0;
+        self.applied = 0;
+        // SAFETY: the ops were initialized, and aren't reachable from `self` anymore
+        unsafe { ops.drop_in_place() }
+    }
+
+    /// apply the operations which were applied to the previous buffer to the given buffer
+    ///
+    /// after this, the two buffers only differ by the unapplied operations
+    pub fn catch_up<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        let applied = core::mem::take(&mut self.applied);
+        let len = core::mem::take(&mut self.len);
+
+        // the applied operations are moved out one by one, so if one of them panics, then the
+        // rest are dropped and the unapplied operations are still moved to the front of the log
+        let mut guard = scopeguard::guard((self, 0), |(this, next)| {
+            let ptr = this.ops.as_mut_ptr().cast::<O>();
+
+            // SAFETY: the ops in `next..applied` are initialized and haven't been moved out yet
+            // and the log is empty, so they can't be dropped twice
+            unsafe {
+                core::ptr::slice_from_raw_parts_mut(ptr.add(next), applied - next).drop_in_place()
+            }
+
+            // SAFETY: the ops in `applied..len` are initialized, so they can be moved to the front
+            unsafe { core::ptr::copy(ptr.add(applied), ptr, len - applied) };
+            this.len = len - applied;
+        });
+
+        while guard.1 < applied {
+            let (this, next) = &mut *guard;
+            // SAFETY: the op is initialized, and `next` is incremented so it won't be read again
+            let op = unsafe { this.ops[*next].assume_init_read() };
+            *next += 1;
             op.apply_last(buffer);
         }
+    }
+
+    /// Remove the operations which were applied to the previous buffer, without applying them
+    ///
+    /// This is for when the previous buffer doesn't have them anymore, see [`OpWriter::revert_publish_with`](crate::op::OpWriter::revert_publish_with)
+    pub fn discard_applied(&mut self) {
+        let applied = core::mem::take(&mut self.applied);
+        let len = core::mem::take(&mut self.len);
+        let ptr = self.ops.as_mut_ptr().cast::<O>();
+
+        // the unapplied operations are moved to the front of the log, even if dropping one of the others panics
+        let _guard = scopeguard::guard(&mut self.len, |this_len| {
+            // SAFETY: the ops in `applied..len` are initialized, so they can be moved to the front
+            unsafe { core::ptr::copy(ptr.add(applied), ptr, len - applied) };
+            *this_len = len - applied;
+        });
+
+        // SAFETY: the ops in `..applied` are initialized, and the log is empty, so they can't be dropped twice
+        unsafe { core::ptr::slice_from_raw_parts_mut(ptr, applied).drop_in_place() }
+    }
+
+    /// apply all operations to the given buffer
+    pub fn apply<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        self.catch_up(buffer);
+        self.apply_unapplied(buffer);
+    }
 
-        self.applied = self.ops.len();
+    /// apply the operations which haven't been applied yet to the given buffer
+    ///
+    /// after this, they count as applied to the previous buffer.
+    /// This is for a buffer which already has the applied operations
+    pub fn apply_unapplied<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        let applied = core::mem::replace(&mut self.applied, self.len);
 
-        for op in self.ops.iter_mut() {
+        for op in &mut self.ops_mut()[applied..] {
             op.apply(buffer)
         }
     }
 }
 
-impl<O> Default for OpLog<O> {
+impl<O, const N: usize> Default for ArrayOpLog<O, N> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+impl<O, const N: usize> Drop for ArrayOpLog<O, N> {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+impl<O, const N: usize> OpLogBackend<O> for ArrayOpLog<O, N> {
+    type PushError = OpLogFull<O>;
+
+    fn push(&mut self, op: O) -> Result<(), Self::PushError> {
+        self.push(op)
+    }
+
+    fn ops(&self) -> &[O] {
+        self.ops_ref()
+    }
+
+    fn applied(&self) -> usize {
+        self.applied()
+    }
+
+    fn unapplied_mut(&mut self) -> &mut [O] {
+        let applied = self.applied;
+        &mut self.ops_mut()[applied..]
+    }
+
+    fn clear(&mut self) {
+        self.clear()
+    }
+
+    fn catch_up<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        self.catch_up(buffer)
+    }
+
+    fn discard_applied(&mut self) {
+        self.discard_applied()
+    }
+
+    fn apply<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        self.apply(buffer)
+    }
+
+    fn apply_unapplied<B: ?Sized>(&mut self, buffer: &mut B)
+    where
+        O: Operation<B>,
+    {
+        self.apply_unapplied(buffer)
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+/// an op which counts how many times it was dropped
+struct CountDrops<'a>(&'a core::cell::Cell<usize>, i32);
+
+#[cfg(all(test, not(feature = "loom")))]
+impl Drop for CountDrops<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1)
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+impl Operation<[i32; 4]> for CountDrops<'_> {
+    fn apply(&mut self, buffer: &mut [i32; 4]) {
+        buffer[self.1 as usize] += 1;
+        if self.1 == 3 {
+            panic!("panicking op")
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+#[cfg(not(feature = "loom"))]
+fn test_array_op_writer() {
+    use crate::{
+        delayed::DelayedWriter,
+        op::OpWriter,
+        raw::{RawDBuf, Shared, Writer},
+        strategy::HazardStrategy,
+    };
+
+    /// add to an element of the buffer
+    struct Add(usize);
+
+    impl Operation<[i32; 4]> for Add {
+        fn apply(&mut self, buffer: &mut [i32; 4]) {
+            buffer[self.0] += 1
+        }
+    }
+
+    let mut shared = Shared::from_raw_parts(HazardStrategy::new(), RawDBuf::new([0; 4], [0; 4]));
+    let writer = DelayedWriter::new(Writer::new(&mut shared));
+    let mut writer =
+        OpWriter::<_, _, ArrayOpLog<Add, 2>>::from_raw_parts(writer, ArrayOpLog::new());
+
+    assert!(writer.try_apply(Add(0)).is_ok());
+    assert!(writer.try_apply(Add(1)).is_ok());
+    let OpLogFull(op) = writer.try_apply(Add(2)).unwrap_err();
+    assert_eq!(op.0, 2);
+
+    writer.publish();
+    assert_eq!(*writer.split().reader, [1, 1, 0, 0]);
+
+    // the log is still full, since the ops need to be applied to the other buffer
+    assert!(writer.try_apply(Add(2)).is_err());
+    writer.swap_buffers();
+    assert_eq!(writer.ops().len(), 0);
+    assert!(writer.try_apply(Add(2)).is_ok());
+    writer.publish();
+    assert_eq!(*writer.split().reader, [1, 1, 1, 0]);
+    assert_eq!(writer.unapplied().len(), 0);
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+#[allow(deprecated)]
+fn test_array_op_log_drops() {
+    let drops = core::cell::Cell::new(0);
+    let mut buffer = [0; 4];
+
+    let mut log = ArrayOpLog::<_, 4>::new();
+    for i in 0..3 {
+        assert!(log.push(CountDrops(&drops, i)).is_ok());
+    }
+    log.apply(&mut buffer);
+    assert_eq!(drops.get(), 0);
+
+    assert!(log.push(CountDrops(&drops, 0)).is_ok());
+    // the applied ops are applied for the last time and dropped, the rest move to the front
+    log.catch_up(&mut buffer);
+    assert_eq!(drops.get(), 3);
+    assert_eq!(log.ops().len(), 1);
+    assert_eq!(log.ops()[0].1, 0);
+    assert_eq!(buffer, [2, 2, 2, 0]);
+
+    let Err(OpLogFull(op)) = (|| {
+        for i in 0..4 {
+            log.push(CountDrops(&drops, i))?;
+        }
+        Ok(())
+    })() else {
+        unreachable!()
+    };
+    // the rejected op is handed back
+    drop(op);
+    assert_eq!(drops.get(), 4);
+
+    drop(log);
+    assert_eq!(drops.get(), 8);
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+#[allow(deprecated)]
+fn test_array_op_log_discard_applied() {
+    let drops = core::cell::Cell::new(0);
+    let mut buffer = [0; 4];
+
+    let mut log = ArrayOpLog::<_, 4>::new();
+    for i in 0..3 {
+        assert!(log.push(CountDrops(&drops, i)).is_ok());
+    }
+    log.apply(&mut buffer);
+    assert!(log.push(CountDrops(&drops, 3)).is_ok());
+
+    // the applied ops are dropped without being applied again, the rest move to the front
+    log.discard_applied();
+    assert_eq!(drops.get(), 3);
+    assert_eq!(log.applied(), 0);
+    assert_eq!(log.ops().len(), 1);
+    assert_eq!(log.ops()[0].1, 3);
+    assert_eq!(buffer, [1, 1, 1, 0]);
+
+    drop(log);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[allow(deprecated)]
+fn test_array_op_log_panic() {
+    let drops = core::cell::Cell::new(0);
+    let mut buffer = [0; 4];
+
+    let mut log = ArrayOpLog::<_, 4>::new();
+    for i in [0, 3, 1] {
+        assert!(log.push(CountDrops(&drops, i)).is_ok());
+    }
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| log.apply(&mut buffer)));
+    assert!(result.is_err());
+    assert_eq!(drops.get(), 0);
+
+    assert!(log.push(CountDrops(&drops, 2)).is_ok());
+
+    // the panicking op is applied again, and the ops after it are dropped
+    // but the unapplied op is kept
+    let result =
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| log.catch_up(&mut buffer)));
+    assert!(result.is_err());
+    assert_eq!(drops.get(), 3);
+    assert_eq!(log.ops().len(), 1);
+    assert_eq!(log.ops()[0].1, 2);
+
+    drop(log);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_op_storage_publish_cycle() {
+    use crate::{
+        op::{Consistency, OpWriter},
+        ptrs::alloc::Owned,
+        raw::Writer,
+        strategy::HazardStrategy,
+    };
+    use std::string::String;
+
+    /// pushes a copy of the string, and the string itself the last time
+    struct Push(String);
+
+    impl Operation<Vec<String>> for Push {
+        fn apply(&mut self, buffer: &mut Vec<String>) {
+            buffer.push(self.0.clone())
+        }
+
+        fn apply_last(self, buffer: &mut Vec<String>) {
+            buffer.push(self.0)
+        }
+    }
+
+    fn publish_cycle<St: OpStorage>(op_log: OpLog<Push, St>) {
+        let shared = Owned::<HazardStrategy, _>::from_buffers(Vec::new(), Vec::new());
+        let mut writer = OpWriter::from_raw_parts(Writer::new(shared).into(), op_log);
+        let mut reader = writer.reader();
+
+        let moved = String::from("moved");
+        let ptr = moved.as_ptr();
+        writer.apply(Push(moved));
+        writer.apply(Push(String::from("second")));
+        writer.unapplied_iter_mut().last().unwrap().0.push('!');
+        assert_eq!(
+            writer.unapplied_iter().map(|op| &*op.0).collect::<Vec<_>>(),
+            ["moved", "second!"]
+        );
+
+        writer.publish();
+        assert_eq!(*reader.get(), ["moved", "second!"]);
+        assert_ne!(reader.get()[0].as_ptr(), ptr);
+
+        // the next swap applies the operations to the other buffer for the last time,
+        // which moves the string instead of copying it
+        writer.swap_buffers();
+        assert_eq!(reader.get()[0].as_ptr(), ptr);
+        assert_eq!(writer.unapplied_len(), 0);
+        assert_eq!(writer.verify_buffers_eq(), Consistency::Consistent);
+    }
+
+    publish_cycle(OpLog::new());
+    publish_cycle(OpLog::new_boxed());
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+#[cfg(not(feature = "loom"))]
+fn test_boxed_op_log() {
+    let drops = core::cell::Cell::new(0);
+    let mut buffer = [0; 4];
+
+    let mut log = OpLog::new_boxed();
+    log.push(CountDrops(&drops, 0));
+    log.apply(&mut buffer);
+    for i in [1, 2, 1] {
+        log.push(CountDrops(&drops, i));
+    }
+    assert_eq!(log.partition_unapplied(|op| op.1 == 1), 2);
+    log.rewrite_unapplied(|ops| ops.truncate(1));
+    assert_eq!(drops.get(), 2);
+    assert_eq!(log.unapplied_iter().map(|op| op.1).collect::<Vec<_>>(), [1]);
+    assert_eq!(log.ops_iter().len(), 2);
+
+    log.apply(&mut buffer);
+    assert_eq!(buffer, [2, 1, 0, 0]);
+    // the first op was applied for the last time
+    assert_eq!(drops.get(), 3);
+    assert_eq!(log.len(), 1);
+
+    drop(log);
+    assert_eq!(drops.get(), 4);
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+#[cfg(not(feature = "loom"))]
+#[should_panic = "the operations are boxed, so they can't be borrowed as a slice"]
+fn test_boxed_op_log_slice() {
+    let mut log = OpLog::new_boxed();
+    log.push(0);
+    OpLogBackend::unapplied(&log);
+}
@@ -0,0 +1,209 @@
+//! A writer which refuses to publish a half updated buffer
+//!
+//! If the writer panics while updating the writer buffer, and the panic is caught, then the
+//! writer buffer may be left half updated. Publishing it would show readers a state which
+//! was never meant to be seen. Like a poisoned `Mutex`, a [`PoisonDetect`] remembers the panic
+//! and refuses to swap the buffers until the writer buffer is repaired with
+//! [`clear_poison_with`](PoisonDetect::clear_poison_with).
+//!
+//! Only updates made through an [`UpdateScope`] are tracked. Readers can check
+//! [`Reader::is_poisoned`](crate::raw::Reader::is_poisoned) to find out that the
+//! published buffer is stale because the writer is poisoned.
+
+use core::{
+    convert::Infallible,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    interface::{BufferOf, RawBuffersOf, Strategy, StrategyOf, StrongRef, ValidationErrorOf},
+    raw::{SplitMut, Writer},
+};
+
+/// A writer which refuses to publish the writer buffer after a panic while updating it
+///
+/// see module docs for details
+pub struct PoisonDetect<S: StrongRef> {
+    /// the underlying writer
+    writer: Writer<S>,
+}
+
+/// A RAII guard which poisons the writer if it's dropped while panicking
+///
+/// see [`PoisonDetect::update_scope`]
+pub struct UpdateScope<'a, S: StrongRef> {
+    /// the writer being updated
+    writer: &'a mut Writer<S>,
+}
+
+/// The error returned from [`PoisonDetect::try_swap_buffers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapError<E> {
+    /// the writer panicked while updating the writer buffer, see [`PoisonDetect::clear_poison_with`]
+    Poisoned,
+    /// the strategy couldn't swap the buffers
+    Validation(E),
+}
+
+impl<S: StrongRef> From<Writer<S>> for PoisonDetect<S> {
+    fn from(writer: Writer<S>) -> Self {
+        Self::new(writer)
+    }
+}
+
+impl<S: StrongRef> PoisonDetect<S> {
+    /// create a new poison detecting writer
+    pub const fn new(writer: Writer<S>) -> Self {
+        Self { writer }
+    }
+
+    /// get the underlying writer back
+    ///
+    /// The raw writer doesn't check the poison flag, so it will publish a poisoned writer buffer
+    pub fn into_inner(self) -> Writer<S> {
+        self.writer
+    }
+
+    /// start updating the writer buffer
+    ///
+    /// If the scope is dropped while panicking, the writer is poisoned
+    pub fn update_scope(&mut self) -> UpdateScope<'_, S> {
+        UpdateScope {
+            writer: &mut self.writer,
+        }
+    }
+
+    /// Mark the writer buffer as consistent without repairing it
+    ///
+    /// Prefer [`clear_poison_with`](Self::clear_poison_with) unless the writer buffer
+    /// was already repaired, or will be completely overwritten
+    pub fn clear_poison(&mut self) {
+        self.writer.set_poisoned(false);
+    }
+
+    /// Repair the writer buffer from the reader buffer and clear the poison
+    ///
+    /// `restore` is called with the writer buffer and the reader buffer.
+    /// If the writer isn't poisoned this does nothing
+    pub fn clear_poison_with(
+        &mut self,
+        restore: impl FnOnce(&mut BufferOf<RawBuffersOf<S>>, &BufferOf<RawBuffersOf<S>>),
+    ) {
+        if !self.writer.is_poisoned() {
+            return;
+        }
+
+        let split = self.writer.split_mut();
+        restore(split.writer, split.reader);
+        self.writer.set_poisoned(false);
+    }
+
+    /// Swap the two buffers, unless the writer is poisoned
+    pub fn try_swap_buffers(&mut self) -> Result<(), SwapError<ValidationErrorOf<StrategyOf<S>>>> {
+        if self.writer.is_poisoned() {
+            return Err(SwapError::Poisoned);
+        }
+
+        self.writer
+            .try_swap_buffers()
+            .map_err(SwapError::Validation)
+    }
+
+    /// Swap the two buffers
+    ///
+    /// # Panics
+    ///
+    /// if the writer is poisoned
+    pub fn swap_buffers(&mut self)
+    where
+        StrategyOf<S>: Strategy<ValidationError = Infallible>,
+    {
+        match self.try_swap_buffers() {
+            Ok(()) => (),
+            Err(SwapError::Poisoned) => panic!(
+                "could not swap buffers: the writer panicked while updating the writer buffer, \
+                see `PoisonDetect::clear_poison_with`"
+            ),
+            Err(SwapError::Validation(inf)) => match inf {},
+        }
+    }
+}
+
+impl<S: StrongRef> Deref for PoisonDetect<S> {
+    type Target = Writer<S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.writer
+    }
+}
+
+impl<S: StrongRef> UpdateScope<'_, S> {
+    /// split the writer into the reader buffer and the writer buffer being updated
+    pub fn split_mut(&mut self) -> SplitMut<'_, BufferOf<RawBuffersOf<S>>> {
+        self.writer.split_mut()
+    }
+}
+
+impl<S: StrongRef> Deref for UpdateScope<'_, S> {
+    type Target = BufferOf<RawBuffersOf<S>>;
+
+    fn deref(&self) -> &Self::Target {
+        self.writer.split().writer
+    }
+}
+
+impl<S: StrongRef> DerefMut for UpdateScope<'_, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.writer.split_mut().writer
+    }
+}
+
+impl<S: StrongRef> Drop for UpdateScope<'_, S> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.writer.set_poisoned(true);
+        }
+    }
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+fn test_poison_detect() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let mut writer = PoisonDetect::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        std::vec![0; 4],
+        std::vec![0; 4],
+    )));
+    let mut reader = writer.reader();
+
+    for i in 0..4 {
+        writer.update_scope()[i] = 1;
+    }
+    writer.swap_buffers();
+    assert!(!reader.is_poisoned());
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut scope = writer.update_scope();
+        for i in 0..4 {
+            scope[i] = 2;
+            if i == 1 {
+                panic!("halfway through")
+            }
+        }
+    }));
+    assert!(result.is_err());
+
+    // the half updated writer buffer is never published
+    assert_eq!(writer.try_swap_buffers(), Err(SwapError::Poisoned));
+    assert!(reader.is_poisoned());
+    let guard = reader.get();
+    assert!(guard.poisoned());
+    assert_eq!(*guard, [1; 4]);
+    drop(guard);
+
+    writer.clear_poison_with(|writer, reader| writer.clone_from(reader));
+    assert!(!reader.is_poisoned());
+    writer.swap_buffers();
+    assert_eq!(*reader.get(), [1; 4]);
+}
@@ -0,0 +1,220 @@
+//! A pool of readers which can be handed out without allocating
+//!
+//! Creating a reader may allocate, for example [`TrackingStrategy`](crate::strategy::TrackingStrategy)
+//! allocates for every reader tag, and [`HazardStrategy`](crate::strategy::HazardStrategy) allocates
+//! a node the first time a reader reads. A [`ReaderPool`] creates all of its readers up front, and
+//! preallocates them with [`Reader::preallocate`]. After that, [`ReaderPool::acquire`], dropping the
+//! [`PooledReader`], and reading from the pooled readers don't allocate.
+//!
+//! ```
+//! use dbuf::{pool::ReaderPool, ptrs::alloc::Owned, raw::Writer, strategy::HazardStrategy};
+//!
+//! let mut writer = Writer::new(Owned::<HazardStrategy, _>::from_buffers(0, 0));
+//! let pool = ReaderPool::new(&writer, 2);
+//!
+//! let mut reader = pool.acquire().unwrap();
+//! std::thread::spawn(move || assert_eq!(*reader.get(), 0)).join().unwrap();
+//!
+//! // the reader went back to the pool when the thread dropped it
+//! assert_eq!(pool.available(), 2);
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use std::sync::Arc;
+
+use crate::{
+    interface::{StrongRef, WeakRef},
+    raw::{Reader, Writer},
+};
+
+/// A fixed size pool of readers, see module docs for details
+pub struct ReaderPool<W: WeakRef> {
+    /// the readers, shared with every [`PooledReader`]
+    slots: Arc<[Slot<W>]>,
+}
+
+/// A reader which is returned to its [`ReaderPool`] when dropped
+pub struct PooledReader<W: WeakRef> {
+    /// the pool the reader came from
+    slots: Arc<[Slot<W>]>,
+    /// the index of the reader's slot in the pool
+    index: usize,
+}
+
+/// a reader in the pool
+struct Slot<W: WeakRef> {
+    /// true while the reader is handed out
+    taken: AtomicBool,
+    /// the reader, this is only accessed by whoever set `taken`
+    reader: UnsafeCell<Reader<W>>,
+}
+
+// SAFETY: the reader is only accessed by the thread which set `taken`, so it's only ever
+// accessed from one thread at a time, and may be moved to other threads
+unsafe impl<W: WeakRef> Sync for Slot<W> where Reader<W>: Send {}
+
+impl<W: WeakRef> ReaderPool<W> {
+    /// Create a pool of `capacity` readers of `writer`'s double buffer
+    ///
+    /// This is the only time the pool allocates
+    pub fn new<S: StrongRef<Weak = W>>(writer: &Writer<S>, capacity: usize) -> Self {
+        let slots = (0..capacity)
+            .map(|_| {
+                let mut reader = writer.reader();
+                reader.preallocate();
+                Slot {
+                    taken: AtomicBool::new(false),
+                    reader: UnsafeCell::new(reader),
+                }
+            })
+            .collect();
+
+        Self { slots }
+    }
+
+    /// Take a reader out of the pool, or `None` if all readers are in use
+    ///
+    /// This is wait-free, and doesn't allocate
+    pub fn acquire(&self) -> Option<PooledReader<W>> {
+        let index = self.slots.iter().position(|slot| {
+            // check before the rmw, so that a busy pool doesn't contend on the slots
+            !slot.taken.load(Ordering::Relaxed)
+                && slot
+                    .taken
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+        })?;
+
+        Some(PooledReader {
+            slots: self.slots.clone(),
+            index,
+        })
+    }
+
+    /// The number of readers in the pool, including those that are in use
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The number of readers which are not in use
+    ///
+    /// This may be out of date as soon as it returns if readers are acquired or released concurrently
+    pub fn available(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| !slot.taken.load(Ordering::Relaxed))
+            .count()
+    }
+}
+
+impl<W: WeakRef> Deref for PooledReader<W> {
+    type Target = Reader<W>;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: this pooled reader set `taken`, so it has exclusive access to the reader
+        unsafe { &*self.slots[self.index].reader.get() }
+    }
+}
+
+impl<W: WeakRef> DerefMut for PooledReader<W> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: this pooled reader set `taken`, so it has exclusive access to the reader
+        unsafe { &mut *self.slots[self.index].reader.get() }
+    }
+}
+
+impl<W: WeakRef> Drop for PooledReader<W> {
+    fn drop(&mut self) {
+        // Release so that all uses of the reader happen before the next `acquire` of this slot
+        self.slots[self.index].taken.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[cfg(test)]
+/// check that acquiring, reading from, and releasing pooled readers doesn't allocate
+fn check_no_allocations<S>(strategy: S)
+where
+    S: crate::interface::Strategy<ValidationError = core::convert::Infallible> + Send + Sync,
+    S::ReaderTag: Send,
+    S::Which: Send + Sync,
+{
+    use crate::{ptrs::alloc::Owned, raw::Shared, strategy::alloc_count::allocations};
+
+    let mut writer = Writer::new(Owned::new(Shared::from_raw_parts(
+        strategy,
+        crate::raw::RawDBuf::new(0, 0),
+    )));
+    let pool = ReaderPool::new(&writer, 4);
+    let barrier = std::sync::Barrier::new(4);
+
+    // the thread handles allocate, so check the allocations on each thread
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            let (pool, barrier) = (&pool, &barrier);
+            s.spawn(move || {
+                barrier.wait();
+
+                let start = allocations();
+                for _ in 0..100 {
+                    let mut reader = pool.acquire().unwrap();
+                    // every reader reads at the same time, which would need a new node for
+                    // hazard pointers if the readers weren't preallocated
+                    let guard = reader.get();
+                    barrier.wait();
+                    assert_eq!(*guard, 0);
+                    drop(guard);
+                    drop(reader);
+                    barrier.wait();
+                }
+                assert_eq!(allocations(), start);
+            });
+        }
+    });
+
+    // all readers are back in the pool, and none of them are still reading
+    assert_eq!(pool.available(), 4);
+    writer.swap_buffers();
+}
+
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_hazard_no_allocations() {
+    check_no_allocations(crate::strategy::HazardStrategy::new());
+}
+
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_tracking_no_allocations() {
+    check_no_allocations(crate::strategy::TrackingStrategy::new());
+}
+
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_exhausted_pool() {
+    let writer = Writer::new(crate::ptrs::alloc::Owned::<
+        crate::strategy::TrackingStrategy,
+        _,
+    >::from_buffers(0, 0));
+    let pool = ReaderPool::new(&writer, 2);
+
+    let a = pool.acquire().unwrap();
+    let b = pool.acquire().unwrap();
+    assert!(pool.acquire().is_none());
+    assert_eq!(pool.available(), 0);
+
+    drop(a);
+    assert_eq!(pool.available(), 1);
+    let _c = pool.acquire().unwrap();
+    assert!(pool.acquire().is_none());
+    drop(b);
+    assert_eq!(pool.capacity(), 2);
+}
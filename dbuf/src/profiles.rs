@@ -0,0 +1,226 @@
+//! Strategies named after the workload they're recommended for
+//!
+//! Each profile is a type alias for a [`Strategy`](crate::interface::Strategy), together with a
+//! module with a constructor, and the pointers which own a double buffer using that strategy.
+//! Naming a profile instead of a concrete strategy keeps code compiling when the recommended
+//! strategy for that workload changes, as long as it only relies on the [`Strategy`](crate::interface::Strategy),
+//! [`Default`] and [`DefaultOwned`](crate::interface::DefaultOwned) impls of the profile.
+//!
+//! ```
+//! # #[cfg(not(feature = "loom"))] {
+//! use dbuf::{profiles::{self, ReadMostly}, raw::Writer};
+//!
+//! let mut writer = Writer::new(profiles::read_mostly::Owned::from_buffers(0, 0));
+//! let mut reader = writer.reader();
+//!
+//! *writer.split_mut().writer = 1;
+//! writer.swap_buffers();
+//! assert_eq!(*reader.get(), 1);
+//!
+//! let _strategy: ReadMostly = profiles::read_mostly::new();
+//! # }
+//! ```
+
+use crate::{
+    strategy::{AdaptiveStrategy, HazardStrategy, LocalHazardStrategy},
+    wait::{DefaultWait, SpinWait},
+};
+
+/// Many readers, which are much more frequent than swaps
+///
+/// Currently a [`HazardStrategy`] which spins, then parks the writer
+pub type ReadMostly = HazardStrategy<DefaultWait>;
+
+/// A writer which shouldn't be descheduled while waiting for readers to exit
+///
+/// Currently a [`HazardStrategy`] which only spins, so readers should hold their guards briefly
+pub type LowLatencyWriter = HazardStrategy<SpinWait>;
+
+/// Readers which only hold their guards for a few instructions, like a single map lookup
+///
+/// Currently a [`HazardStrategy`] which yields to the OS while it waits, see [`YieldWait`](crate::wait::YieldWait)
+#[cfg(feature = "std")]
+pub type BriefReads = HazardStrategy<crate::wait::YieldWait>;
+
+/// Readers and the writer are all on the same thread
+///
+/// Currently a [`LocalHazardStrategy`]
+pub type SingleThread = LocalHazardStrategy;
+
+/// The writer only waits for readers which are actually reading the writer buffer
+///
+/// Currently a [`TrackingStrategy`](crate::strategy::TrackingStrategy)
+#[cfg(feature = "std")]
+pub type Precise = crate::strategy::TrackingStrategy;
+
+/// Few readers most of the time, but readers may contend occasionally
+///
+/// Currently an [`AdaptiveStrategy`]
+pub type Balanced = AdaptiveStrategy<DefaultWait>;
+
+/// The constructor and pointers for [`ReadMostly`]
+pub mod read_mostly {
+    use super::ReadMostly;
+    #[cfg(not(feature = "loom"))]
+    use crate::raw::RawDBuf;
+
+    /// Create the strategy
+    pub fn new() -> ReadMostly {
+        ReadMostly::default()
+    }
+
+    /// A double buffer which uses this profile, see [`Owned`](crate::ptrs::alloc::Owned)
+    #[cfg(not(feature = "loom"))]
+    pub type Owned<T> = crate::ptrs::alloc::Owned<ReadMostly, RawDBuf<T>>;
+
+    /// A double buffer which uses this profile, see [`OwnedWithWeak`](crate::ptrs::alloc::OwnedWithWeak)
+    #[cfg(not(feature = "loom"))]
+    pub type OwnedWithWeak<T> = crate::ptrs::alloc::OwnedWithWeak<ReadMostly, RawDBuf<T>>;
+}
+
+/// The constructor and pointers for [`LowLatencyWriter`]
+pub mod low_latency_writer {
+    use super::LowLatencyWriter;
+    #[cfg(not(feature = "loom"))]
+    use crate::raw::RawDBuf;
+
+    /// Create the strategy
+    pub fn new() -> LowLatencyWriter {
+        LowLatencyWriter::default()
+    }
+
+    /// A double buffer which uses this profile, see [`Owned`](crate::ptrs::alloc::Owned)
+    #[cfg(not(feature = "loom"))]
+    pub type Owned<T> = crate::ptrs::alloc::Owned<LowLatencyWriter, RawDBuf<T>>;
+
+    /// A double buffer which uses this profile, see [`OwnedWithWeak`](crate::ptrs::alloc::OwnedWithWeak)
+    #[cfg(not(feature = "loom"))]
+    pub type OwnedWithWeak<T> = crate::ptrs::alloc::OwnedWithWeak<LowLatencyWriter, RawDBuf<T>>;
+}
+
+/// The constructor and pointers for [`BriefReads`]
+#[cfg(feature = "std")]
+pub mod brief_reads {
+    use super::BriefReads;
+    #[cfg(not(feature = "loom"))]
+    use crate::raw::RawDBuf;
+
+    /// Create the strategy
+    pub fn new() -> BriefReads {
+        BriefReads::default()
+    }
+
+    /// A double buffer which uses this profile, see [`Owned`](crate::ptrs::alloc::Owned)
+    #[cfg(not(feature = "loom"))]
+    pub type Owned<T> = crate::ptrs::alloc::Owned<BriefReads, RawDBuf<T>>;
+
+    /// A double buffer which uses this profile, see [`OwnedWithWeak`](crate::ptrs::alloc::OwnedWithWeak)
+    #[cfg(not(feature = "loom"))]
+    pub type OwnedWithWeak<T> = crate::ptrs::alloc::OwnedWithWeak<BriefReads, RawDBuf<T>>;
+}
+
+/// The constructor and pointers for [`SingleThread`]
+///
+/// The pointers aren't thread-safe, like the strategy
+pub mod single_thread {
+    use super::SingleThread;
+    use crate::raw::RawDBuf;
+
+    /// Create the strategy
+    pub fn new() -> SingleThread {
+        SingleThread::default()
+    }
+
+    /// A double buffer which uses this profile, see [`LocalOwned`](crate::ptrs::alloc::LocalOwned)
+    pub type Owned<T> = crate::ptrs::alloc::LocalOwned<SingleThread, RawDBuf<T>>;
+
+    /// A double buffer which uses this profile, see [`LocalOwnedWithWeak`](crate::ptrs::alloc::LocalOwnedWithWeak)
+    pub type OwnedWithWeak<T> = crate::ptrs::alloc::LocalOwnedWithWeak<SingleThread, RawDBuf<T>>;
+}
+
+/// The constructor and pointers for [`Precise`]
+#[cfg(feature = "std")]
+pub mod precise {
+    use super::Precise;
+    #[cfg(not(feature = "loom"))]
+    use crate::raw::RawDBuf;
+
+    /// Create the strategy
+    pub fn new() -> Precise {
+        Precise::default()
+    }
+
+    /// A double buffer which uses this profile, see [`Owned`](crate::ptrs::alloc::Owned)
+    #[cfg(not(feature = "loom"))]
+    pub type Owned<T> = crate::ptrs::alloc::Owned<Precise, RawDBuf<T>>;
+
+    /// A double buffer which uses this profile, see [`OwnedWithWeak`](crate::ptrs::alloc::OwnedWithWeak)
+    #[cfg(not(feature = "loom"))]
+    pub type OwnedWithWeak<T> = crate::ptrs::alloc::OwnedWithWeak<Precise, RawDBuf<T>>;
+}
+
+/// The constructor and pointers for [`Balanced`]
+pub mod balanced {
+    use super::Balanced;
+    #[cfg(not(feature = "loom"))]
+    use crate::raw::RawDBuf;
+
+    /// Create the strategy
+    pub fn new() -> Balanced {
+        Balanced::default()
+    }
+
+    /// A double buffer which uses this profile, see [`Owned`](crate::ptrs::alloc::Owned)
+    #[cfg(not(feature = "loom"))]
+    pub type Owned<T> = crate::ptrs::alloc::Owned<Balanced, RawDBuf<T>>;
+
+    /// A double buffer which uses this profile, see [`OwnedWithWeak`](crate::ptrs::alloc::OwnedWithWeak)
+    #[cfg(not(feature = "loom"))]
+    pub type OwnedWithWeak<T> = crate::ptrs::alloc::OwnedWithWeak<Balanced, RawDBuf<T>>;
+}
+
+/// every profile can own a double buffer, with and without weak readers
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_profiles() {
+    use crate::{
+        interface::{DefaultOwned, Strategy, WeakRef},
+        raw::{RawDBuf, ReadError, Writer},
+    };
+    use core::convert::Infallible;
+
+    /// swap a double buffer built by `strategy`, and read it from a weak reader
+    fn check<S>(strategy: S)
+    where
+        S: DefaultOwned<RawDBuf<u32>> + Default + Strategy<ValidationError = Infallible>,
+        <S::WeakRef as WeakRef>::UpgradeError: Into<ReadError>,
+    {
+        let mut writer = Writer::new(strategy.build_with_weak(RawDBuf::new(0, 0)));
+        let mut reader = writer.reader();
+        *writer.split_mut().writer = 1;
+        writer.swap_buffers();
+        assert_eq!(reader.checked_get().ok().as_deref(), Some(&1));
+
+        let mut writer = Writer::new(S::default().build(RawDBuf::new(0, 0)));
+        let mut reader = writer.reader();
+        *writer.split_mut().writer = 2;
+        writer.swap_buffers();
+        assert_eq!(*reader.get(), 2);
+    }
+
+    check(read_mostly::new());
+    check(low_latency_writer::new());
+    check(brief_reads::new());
+    check(single_thread::new());
+    check(precise::new());
+    check(balanced::new());
+
+    // the pointer aliases can own a double buffer too
+    Writer::new(read_mostly::OwnedWithWeak::from_buffers(0, 0)).swap_buffers();
+    Writer::new(low_latency_writer::Owned::from_buffers(0, 0)).swap_buffers();
+    Writer::new(brief_reads::OwnedWithWeak::from_buffers(0, 0)).swap_buffers();
+    Writer::new(single_thread::OwnedWithWeak::from_buffers(0, 0)).swap_buffers();
+    Writer::new(precise::Owned::from_buffers(0, 0)).swap_buffers();
+    Writer::new(balanced::OwnedWithWeak::from_buffers(0, 0)).swap_buffers();
+}
@@ -1,13 +1,81 @@
 //! Strong and Weak reference implementations
 
+use core::ptr::NonNull;
+
 use crate::{
-    interface::{IntoStrongRef, RawBuffers, Strategy, StrongRef, WeakRef},
+    interface::{
+        GuardStorage, IntoStrongRef, RawBuffers, RawBuffersOf, Strategy, StrategyOf, StrongRef,
+        WeakRef,
+    },
     raw::Shared,
 };
 
 #[cfg(feature = "alloc")]
 pub mod alloc;
 
+/// The [`GuardStorage`] for weak refs which are strong refs themselves
+///
+/// The reader's weak ref already keeps the shared buffer alive, so this is just a pointer to it
+pub struct BorrowedStorage<W: StrongRef> {
+    /// the shared buffer
+    ptr: NonNull<Shared<StrategyOf<W>, RawBuffersOf<W>>>,
+}
+
+/// The [`GuardStorage`] for weak refs which need to be upgraded
+///
+/// This holds the strong ref to keep the shared buffer alive
+pub struct UpgradedStorage<W: WeakRef> {
+    /// the upgraded strong ref
+    strong: W::Strong,
+}
+
+// SAFETY: this is semantically a `&Shared`
+unsafe impl<W: StrongRef> Send for BorrowedStorage<W> where
+    Shared<StrategyOf<W>, RawBuffersOf<W>>: Sync
+{
+}
+// SAFETY: this is semantically a `&Shared`
+unsafe impl<W: StrongRef> Sync for BorrowedStorage<W> where
+    Shared<StrategyOf<W>, RawBuffersOf<W>>: Sync
+{
+}
+
+// SAFETY:
+// * the pointer is from derefencing the weak ref, which is also the strong ref
+// * `Deref::deref` cannot change which value it points to, and moving the strong ref doesn't
+//      invalidate pointers into it, so the pointer is valid for as long as the weak ref is alive
+unsafe impl<W: WeakRef<Strong = W> + StrongRef> GuardStorage<W> for BorrowedStorage<W> {
+    #[inline]
+    fn new(weak: &W) -> Result<Self, W::UpgradeError> {
+        Ok(Self {
+            ptr: NonNull::from(&**weak),
+        })
+    }
+
+    #[inline]
+    unsafe fn shared(&self) -> &Shared<StrategyOf<W>, RawBuffersOf<W>> {
+        // SAFETY: the caller ensures that the weak ref is still alive
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+// SAFETY:
+// * `WeakRef::upgrade` must alias with the weak ref
+// * the strong ref keeps the shared buffer alive until `Self` is dropped
+unsafe impl<W: WeakRef> GuardStorage<W> for UpgradedStorage<W> {
+    #[inline]
+    fn new(weak: &W) -> Result<Self, W::UpgradeError> {
+        Ok(Self {
+            strong: W::upgrade(weak)?,
+        })
+    }
+
+    #[inline]
+    unsafe fn shared(&self) -> &Shared<StrategyOf<W::Strong>, RawBuffersOf<W::Strong>> {
+        &self.strong
+    }
+}
+
 // SAFETY: the result of `into_strong` does not alias with any other pointer
 // because `&mut _` doesn't alias with any other pointer
 unsafe impl<'a, S: Strategy, B: ?Sized + RawBuffers> IntoStrongRef for &'a mut Shared<S, B> {
@@ -50,6 +118,7 @@ unsafe impl<S: Strategy, B: ?Sized + RawBuffers> StrongRef for &Shared<S, B> {
 unsafe impl<S: Strategy, B: ?Sized + RawBuffers> WeakRef for &Shared<S, B> {
     type Strong = Self;
     type UpgradeError = core::convert::Infallible;
+    type GuardStorage = BorrowedStorage<Self>;
 
     fn upgrade(this: &Self) -> Result<Self::Strong, Self::UpgradeError> {
         Ok(*this)
@@ -58,4 +127,8 @@ unsafe impl<S: Strategy, B: ?Sized + RawBuffers> WeakRef for &Shared<S, B> {
     fn as_ref(&self) -> Option<&<Self::Strong as core::ops::Deref>::Target> {
         Some(self)
     }
+
+    fn as_ptr(this: &Self) -> *const <Self::Strong as core::ops::Deref>::Target {
+        *this
+    }
 }
@@ -1,6 +1,8 @@
 //! ptrs that need to allocate
 
-use core::ops::Deref;
+#[cfg(not(feature = "loom"))]
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::{cell::Cell, ops::Deref};
 #[cfg(feature = "loom")]
 use loom::sync::Arc;
 use std::rc::{Rc, Weak};
@@ -8,19 +10,35 @@ use std::rc::{Rc, Weak};
 use std::sync::{Arc, Weak as AWeak};
 
 use crate::{
-    interface::{IntoStrongRef, RawBuffers, Strategy, StrongRef, WeakRef, WhichOf},
-    raw::Shared,
+    interface::{IntoStrongRef, PendingRef, RawBuffers, Strategy, StrongRef, WeakRef, WhichOf},
+    raw::{ReadError, Shared},
 };
 
 /// An unique owned strong ptr to a double buffer
+///
+/// the second field is set once the writer exists, and is shared with the [`PendingWeak`] ptrs
 #[cfg(not(feature = "loom"))]
-pub struct OwnedWithWeak<S, B, W = WhichOf<S>>(Arc<Shared<S, B, W>>);
+pub struct OwnedWithWeak<S, B, W = WhichOf<S>>(Arc<Shared<S, B, W>>, Arc<AtomicBool>);
 
 #[cfg(not(feature = "loom"))]
 impl<S: Strategy, B: RawBuffers> OwnedWithWeak<S, B> {
     /// create a new owned ptr
     pub fn new(shared: Shared<S, B>) -> Self {
-        Self(Arc::new(shared))
+        Self(Arc::new(shared), Arc::default())
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+impl<S, B, W> OwnedWithWeak<S, B, W> {
+    /// create a weak ptr to the double buffer before the writer exists
+    ///
+    /// the pending weak ptr can only be turned into an [`OwnedWeak`] once
+    /// a writer has been created from this ptr, see [`Reader::new_pending`](crate::raw::Reader::new_pending)
+    pub fn downgrade(&self) -> PendingWeak<S, B, W> {
+        PendingWeak {
+            ptr: Arc::downgrade(&self.0),
+            ready: self.1.clone(),
+        }
     }
 }
 
@@ -42,7 +60,7 @@ impl<S, B, W> TryFrom<Arc<Shared<S, B, W>>> for OwnedWithWeak<S, B, W> {
 
     fn try_from(mut value: Arc<Shared<S, B, W>>) -> Result<Self, Self::Error> {
         if Arc::get_mut(&mut value).is_some() {
-            Ok(Self(value))
+            Ok(Self(value, Arc::default()))
         } else {
             Err(value)
         }
@@ -79,6 +97,8 @@ unsafe impl<S: Strategy, B: RawBuffers> IntoStrongRef for OwnedWithWeak<S, B> {
     }
 
     fn into_strong(self) -> Self::Strong {
+        // Release so that pending readers see the writer tag when they create their tag
+        self.1.store(true, Ordering::Release);
         OwnedStrong(self.0)
     }
 }
@@ -90,6 +110,15 @@ pub struct OwnedStrong<S, B, W = WhichOf<S>>(Arc<Shared<S, B, W>>);
 #[cfg(not(feature = "loom"))]
 pub struct OwnedWeak<S, B, W = WhichOf<S>>(AWeak<Shared<S, B, W>>);
 
+/// A weak ptr to a shared double buffer whose writer may not exist yet
+#[cfg(not(feature = "loom"))]
+pub struct PendingWeak<S, B, W = WhichOf<S>> {
+    /// the weak ptr, which can't be upgraded until the writer exists
+    ptr: AWeak<Shared<S, B, W>>,
+    /// set once the writer exists
+    ready: Arc<AtomicBool>,
+}
+
 /// The error representing a failed upgrade from OwnedWeak to OwnedStrong
 #[cfg(not(feature = "loom"))]
 pub struct UpgradeError;
@@ -101,6 +130,13 @@ impl core::fmt::Debug for UpgradeError {
     }
 }
 
+#[cfg(not(feature = "loom"))]
+impl From<UpgradeError> for ReadError {
+    fn from(UpgradeError: UpgradeError) -> Self {
+        Self::WriterGone
+    }
+}
+
 #[cfg(not(feature = "loom"))]
 impl<S, B, W> Deref for OwnedStrong<S, B, W> {
     type Target = Shared<S, B, W>;
@@ -117,6 +153,36 @@ impl<S, B, W> Clone for OwnedWeak<S, B, W> {
     }
 }
 
+#[cfg(not(feature = "loom"))]
+impl<S, B, W> Clone for PendingWeak<S, B, W> {
+    fn clone(&self) -> Self {
+        Self {
+            ptr: self.ptr.clone(),
+            ready: self.ready.clone(),
+        }
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+// SAFETY:
+//
+// * if `try_weak` returns `Some` then the writer tag must have already been created
+//      * `ready` is only set in `into_strong`, which is called after the writer tag is created
+// * once `try_weak` returns `Some` it must always return `Some`
+//      * `ready` is never reset
+unsafe impl<S: Strategy, B: RawBuffers> PendingRef for PendingWeak<S, B> {
+    type Weak = OwnedWeak<S, B>;
+
+    fn try_weak(&self) -> Option<Self::Weak> {
+        // Acquire to syncronize with `into_strong`
+        if self.ready.load(Ordering::Acquire) {
+            Some(OwnedWeak(self.ptr.clone()))
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg(not(feature = "loom"))]
 // SAFETY:
 //
@@ -143,19 +209,39 @@ unsafe impl<S: Strategy, B: RawBuffers> StrongRef for OwnedStrong<S, B> {
 unsafe impl<S: Strategy, B: RawBuffers> WeakRef for OwnedWeak<S, B> {
     type Strong = OwnedStrong<S, B>;
     type UpgradeError = UpgradeError;
+    type GuardStorage = crate::ptrs::UpgradedStorage<Self>;
 
     fn upgrade(this: &Self) -> Result<Self::Strong, Self::UpgradeError> {
         AWeak::upgrade(&this.0).ok_or(UpgradeError).map(OwnedStrong)
     }
+
+    fn as_ptr(this: &Self) -> *const <Self::Strong as core::ops::Deref>::Target {
+        AWeak::as_ptr(&this.0)
+    }
 }
 
 /// An unique owned strong ptr to a double buffer
-pub struct LocalOwnedWithWeak<S, B, W = WhichOf<S>>(Rc<Shared<S, B, W>>);
+///
+/// the second field is set once the writer exists, and is shared with the [`LocalPendingWeak`] ptrs
+pub struct LocalOwnedWithWeak<S, B, W = WhichOf<S>>(Rc<Shared<S, B, W>>, Rc<Cell<bool>>);
 
 impl<S: Strategy, B: RawBuffers> LocalOwnedWithWeak<S, B> {
     /// create a new owned ptr
     pub fn new(shared: Shared<S, B>) -> Self {
-        Self(Rc::new(shared))
+        Self(Rc::new(shared), Rc::default())
+    }
+}
+
+impl<S, B, W> LocalOwnedWithWeak<S, B, W> {
+    /// create a weak ptr to the double buffer before the writer exists
+    ///
+    /// the pending weak ptr can only be turned into an [`LocalOwnedWeak`] once
+    /// a writer has been created from this ptr, see [`Reader::new_pending`](crate::raw::Reader::new_pending)
+    pub fn downgrade(&self) -> LocalPendingWeak<S, B, W> {
+        LocalPendingWeak {
+            ptr: Rc::downgrade(&self.0),
+            ready: self.1.clone(),
+        }
     }
 }
 
@@ -175,7 +261,7 @@ impl<S, B, W> TryFrom<Rc<Shared<S, B, W>>> for LocalOwnedWithWeak<S, B, W> {
 
     fn try_from(mut value: Rc<Shared<S, B, W>>) -> Result<Self, Self::Error> {
         if Rc::get_mut(&mut value).is_some() {
-            Ok(Self(value))
+            Ok(Self(value, Rc::default()))
         } else {
             Err(value)
         }
@@ -211,6 +297,7 @@ unsafe impl<S: Strategy, B: RawBuffers> IntoStrongRef for LocalOwnedWithWeak<S,
     }
 
     fn into_strong(self) -> Self::Strong {
+        self.1.set(true);
         LocalOwnedStrong(self.0)
     }
 }
@@ -220,6 +307,14 @@ pub struct LocalOwnedStrong<S, B, W = WhichOf<S>>(Rc<Shared<S, B, W>>);
 /// An owned weak ptr to a shared double buffer
 pub struct LocalOwnedWeak<S, B, W = WhichOf<S>>(Weak<Shared<S, B, W>>);
 
+/// A weak ptr to a shared double buffer whose writer may not exist yet
+pub struct LocalPendingWeak<S, B, W = WhichOf<S>> {
+    /// the weak ptr, which can't be upgraded until the writer exists
+    ptr: Weak<Shared<S, B, W>>,
+    /// set once the writer exists
+    ready: Rc<Cell<bool>>,
+}
+
 /// The error representing a failed upgrade from LocalOwnedWeak to LocalOwnedStrong
 pub struct LocalUpgradeError;
 
@@ -229,6 +324,12 @@ impl core::fmt::Debug for LocalUpgradeError {
     }
 }
 
+impl From<LocalUpgradeError> for ReadError {
+    fn from(LocalUpgradeError: LocalUpgradeError) -> Self {
+        Self::WriterGone
+    }
+}
+
 impl<S, B, W> Deref for LocalOwnedStrong<S, B, W> {
     type Target = Shared<S, B, W>;
 
@@ -243,6 +344,33 @@ impl<S, B, W> Clone for LocalOwnedWeak<S, B, W> {
     }
 }
 
+impl<S, B, W> Clone for LocalPendingWeak<S, B, W> {
+    fn clone(&self) -> Self {
+        Self {
+            ptr: self.ptr.clone(),
+            ready: self.ready.clone(),
+        }
+    }
+}
+
+// SAFETY:
+//
+// * if `try_weak` returns `Some` then the writer tag must have already been created
+//      * `ready` is only set in `into_strong`, which is called after the writer tag is created
+// * once `try_weak` returns `Some` it must always return `Some`
+//      * `ready` is never reset
+unsafe impl<S: Strategy, B: RawBuffers> PendingRef for LocalPendingWeak<S, B> {
+    type Weak = LocalOwnedWeak<S, B>;
+
+    fn try_weak(&self) -> Option<Self::Weak> {
+        if self.ready.get() {
+            Some(LocalOwnedWeak(self.ptr.clone()))
+        } else {
+            None
+        }
+    }
+}
+
 // SAFETY:
 //
 // * `Deref::deref` cannot change which value it points to
@@ -267,12 +395,17 @@ unsafe impl<S: Strategy, B: RawBuffers> StrongRef for LocalOwnedStrong<S, B> {
 unsafe impl<S: Strategy, B: RawBuffers> WeakRef for LocalOwnedWeak<S, B> {
     type Strong = LocalOwnedStrong<S, B>;
     type UpgradeError = LocalUpgradeError;
+    type GuardStorage = crate::ptrs::UpgradedStorage<Self>;
 
     fn upgrade(this: &Self) -> Result<Self::Strong, Self::UpgradeError> {
         Weak::upgrade(&this.0)
             .ok_or(LocalUpgradeError)
             .map(LocalOwnedStrong)
     }
+
+    fn as_ptr(this: &Self) -> *const <Self::Strong as core::ops::Deref>::Target {
+        Weak::as_ptr(&this.0)
+    }
 }
 
 /// An unique owned strong ptr to a double buffer
@@ -352,6 +485,94 @@ impl<S, B, W> Deref for OwnedPtr<S, B, W> {
     }
 }
 
+impl<S, B, W> OwnedPtr<S, B, W> {
+    /// take the shared state out of the ptr if this is the only ptr to it
+    pub fn try_unwrap(this: Self) -> Result<Shared<S, B, W>, Self> {
+        Arc::try_unwrap(this.0).map_err(Self)
+    }
+}
+
+impl<S: Strategy, B: RawBuffers> crate::raw::Writer<OwnedPtr<S, B>> {
+    /// take the shared state out of the writer
+    ///
+    /// This fails if there are any other strong ptrs to the shared state, which includes all readers.
+    /// Any swap started with [`try_start_buffer_swap`](crate::raw::Writer::try_start_buffer_swap)
+    /// must be finished first.
+    pub fn try_into_shared(self) -> Result<Shared<S, B>, Self> {
+        self.try_map_ptr(OwnedPtr::try_unwrap)
+    }
+}
+
+impl<S: Strategy, B> crate::raw::Writer<OwnedPtr<S, crate::raw::RawDBuf<B>>> {
+    /// Change the type of both buffers, keeping the strategy, which buffer is the writer buffer, and the writer
+    ///
+    /// Readers can't read a different type, so this fails if there are any other strong ptrs to the
+    /// shared state, which includes all readers. Readers for the new buffers can be created from the
+    /// returned writer. Any swap started with [`try_start_buffer_swap`](crate::raw::Writer::try_start_buffer_swap)
+    /// must be finished first.
+    ///
+    /// `f` is called with the writer buffer first, then the reader buffer
+    #[allow(clippy::type_complexity)]
+    pub fn map_buffers<B2>(
+        self,
+        mut f: impl FnMut(B) -> B2,
+    ) -> Result<crate::raw::Writer<OwnedPtr<S, crate::raw::RawDBuf<B2>>>, Self> {
+        let which = self.which();
+        // SAFETY: the new ptr has the strategy of the old one
+        unsafe {
+            self.try_map_ptr_keep_tag(|ptr| {
+                let shared = OwnedPtr::try_unwrap(ptr)?;
+                let shared = shared.map_buffers(|buffers| map_raw_dbuf(buffers, which, &mut f));
+                Ok(OwnedPtr(Arc::new(shared)))
+            })
+        }
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+impl<S: Strategy, B> crate::raw::Writer<OwnedStrong<S, crate::raw::RawDBuf<B>>> {
+    /// Change the type of both buffers, keeping the strategy, which buffer is the writer buffer, and the writer
+    ///
+    /// Readers can't read a different type, so this fails if any reader holds a read guard. All other readers
+    /// only have weak ptrs, which fail to upgrade afterwards, so they see the double buffer as dropped.
+    /// Readers for the new buffers can be created from the returned writer. Any swap started with
+    /// [`try_start_buffer_swap`](crate::raw::Writer::try_start_buffer_swap) must be finished first.
+    ///
+    /// `f` is called with the writer buffer first, then the reader buffer
+    #[allow(clippy::type_complexity)]
+    pub fn map_buffers<B2>(
+        self,
+        mut f: impl FnMut(B) -> B2,
+    ) -> Result<crate::raw::Writer<OwnedStrong<S, crate::raw::RawDBuf<B2>>>, Self> {
+        let which = self.which();
+        // SAFETY: the new ptr has the strategy of the old one
+        unsafe {
+            self.try_map_ptr_keep_tag(|ptr| {
+                let shared = Arc::try_unwrap(ptr.0).map_err(OwnedStrong)?;
+                let shared = shared.map_buffers(|buffers| map_raw_dbuf(buffers, which, &mut f));
+                Ok(OwnedStrong(Arc::new(shared)))
+            })
+        }
+    }
+}
+
+/// map both buffers, the writer buffer first
+fn map_raw_dbuf<B, B2>(
+    buffers: crate::raw::RawDBuf<B>,
+    which: bool,
+    f: &mut impl FnMut(B) -> B2,
+) -> crate::raw::RawDBuf<B2> {
+    let [front, back] = buffers.into_inner();
+    // `which` is false if the front buffer is the writer buffer
+    if which {
+        let back = f(back);
+        crate::raw::RawDBuf::new(f(front), back)
+    } else {
+        let front = f(front);
+        crate::raw::RawDBuf::new(front, f(back))
+    }
+}
+
 impl<S, B, W> Clone for OwnedPtr<S, B, W> {
     fn clone(&self) -> Self {
         Self(self.0.clone())
@@ -382,6 +603,7 @@ unsafe impl<S: Strategy, B: RawBuffers> StrongRef for OwnedPtr<S, B> {
 unsafe impl<S: Strategy, B: RawBuffers> WeakRef for OwnedPtr<S, B> {
     type Strong = Self;
     type UpgradeError = core::convert::Infallible;
+    type GuardStorage = crate::ptrs::BorrowedStorage<Self>;
 
     fn upgrade(this: &Self) -> Result<Self::Strong, Self::UpgradeError> {
         Ok(Self::clone(this))
@@ -390,6 +612,25 @@ unsafe impl<S: Strategy, B: RawBuffers> WeakRef for OwnedPtr<S, B> {
     fn as_ref(&self) -> Option<&<Self::Strong as core::ops::Deref>::Target> {
         Some(self)
     }
+
+    fn as_ptr(this: &Self) -> *const <Self::Strong as core::ops::Deref>::Target {
+        &**this
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+impl<S: Strategy, B: RawBuffers> crate::raw::Reader<OwnedPtr<S, B>> {
+    /// Convert this reader into one that doesn't keep the double buffer alive
+    ///
+    /// Once all other strong ptrs to the double buffer are dropped,
+    /// the buffers are dropped and the returned reader will fail to upgrade
+    pub fn into_weak(self) -> crate::raw::Reader<OwnedWeak<S, B>> {
+        let (tag, ptr) = self.into_raw_parts();
+        let ptr = OwnedWeak(Arc::downgrade(&ptr.0));
+        // SAFETY: the reader tag was created by the strategy in this allocation
+        // and the weak ptr points to the same allocation
+        unsafe { crate::raw::Reader::from_raw_parts(tag, ptr) }
+    }
 }
 
 /// An unique LocalOwned strong ptr to a double buffer
@@ -499,6 +740,7 @@ unsafe impl<S: Strategy, B: RawBuffers> StrongRef for LocalOwnedPtr<S, B> {
 unsafe impl<S: Strategy, B: RawBuffers> WeakRef for LocalOwnedPtr<S, B> {
     type Strong = Self;
     type UpgradeError = core::convert::Infallible;
+    type GuardStorage = crate::ptrs::BorrowedStorage<Self>;
 
     fn upgrade(this: &Self) -> Result<Self::Strong, Self::UpgradeError> {
         Ok(Self::clone(this))
@@ -507,6 +749,24 @@ unsafe impl<S: Strategy, B: RawBuffers> WeakRef for LocalOwnedPtr<S, B> {
     fn as_ref(&self) -> Option<&<Self::Strong as core::ops::Deref>::Target> {
         Some(self)
     }
+
+    fn as_ptr(this: &Self) -> *const <Self::Strong as core::ops::Deref>::Target {
+        &**this
+    }
+}
+
+impl<S: Strategy, B: RawBuffers> crate::raw::Reader<LocalOwnedPtr<S, B>> {
+    /// Convert this reader into one that doesn't keep the double buffer alive
+    ///
+    /// Once all other strong ptrs to the double buffer are dropped,
+    /// the buffers are dropped and the returned reader will fail to upgrade
+    pub fn into_weak(self) -> crate::raw::Reader<LocalOwnedWeak<S, B>> {
+        let (tag, ptr) = self.into_raw_parts();
+        let ptr = LocalOwnedWeak(Rc::downgrade(&ptr.0));
+        // SAFETY: the reader tag was created by the strategy in this allocation
+        // and the weak ptr points to the same allocation
+        unsafe { crate::raw::Reader::from_raw_parts(tag, ptr) }
+    }
 }
 
 #[test]
@@ -537,12 +797,12 @@ fn test_op_writer() {
 
     let mut reader = writer.reader();
 
-    assert_eq!(*reader.try_get().unwrap(), 0);
+    assert_eq!(*reader.checked_get().unwrap(), 0);
 
     writer.apply(Op::Add(10));
     writer.apply(Op::Mul(10));
 
-    assert_eq!(*reader.try_get().unwrap(), 0);
+    assert_eq!(*reader.checked_get().unwrap(), 0);
     assert_eq!(*writer.split().writer, 0);
     assert_eq!(*writer.split().reader, 0);
 
@@ -550,18 +810,86 @@ fn test_op_writer() {
     writer.apply(Op::Add(10));
 
     let mut reader2 = reader.clone();
-    let guard = reader2.try_get().unwrap();
+    let guard = reader2.checked_get().unwrap();
 
-    assert_eq!(*reader.try_get().unwrap(), 100);
+    assert_eq!(*reader.checked_get().unwrap(), 100);
     assert_eq!(*guard, 100);
     assert_eq!(*writer.split().writer, 0);
     assert_eq!(*writer.split().reader, 100);
 
     writer.swap_buffers();
 
-    assert_eq!(*reader.try_get().unwrap(), 110);
+    assert_eq!(*reader.checked_get().unwrap(), 110);
     assert_eq!(*guard, 100);
-    assert!(!core::ptr::eq::<i32>(&*guard, &*reader.try_get().unwrap()));
+    assert!(!core::ptr::eq::<i32>(
+        &*guard,
+        &*reader.checked_get().unwrap()
+    ));
     assert_eq!(*writer.split().writer, 100);
     assert_eq!(*writer.split().reader, 110);
 }
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_map_buffers() {
+    use crate::strategy::HazardStrategy;
+    use std::string::ToString;
+
+    let mut writer = crate::raw::Writer::new(Owned::<HazardStrategy, _>::from_buffers(1, 1));
+    *writer.split_mut().writer = 2;
+    writer.swap_buffers();
+    assert!(writer.which());
+    let mut reader = writer.reader();
+
+    // the reader still uses the old type
+    let writer = writer
+        .map_buffers(|value: i32| value.to_string())
+        .err()
+        .unwrap();
+    assert_eq!(*reader.get(), 2);
+    drop(reader);
+
+    let mut calls = std::vec::Vec::new();
+    let mut writer = writer
+        .map_buffers(|value| {
+            calls.push(value);
+            value.to_string()
+        })
+        .ok()
+        .unwrap();
+    // the writer buffer is mapped first, and stays the writer buffer
+    assert_eq!(calls, [1, 2]);
+    assert!(writer.which());
+    assert_eq!(*writer.split().writer, "1");
+    assert_eq!(*writer.reader().get(), "2");
+
+    writer.split_mut().writer.push('0');
+    writer.swap_buffers();
+    assert_eq!(*writer.reader().get(), "10");
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_map_buffers_weak() {
+    use crate::strategy::TrackingStrategy;
+
+    let writer = crate::raw::Writer::new(OwnedWithWeak::<TrackingStrategy, _>::from_buffers(1, 1));
+    let mut reader = writer.reader();
+
+    // a reader which holds a read guard keeps the old buffers alive
+    let guard = reader.checked_get().unwrap();
+    let writer = writer.map_buffers(|value: i32| value * 10).err().unwrap();
+    drop(guard);
+
+    // but weak readers don't, they see the double buffer as dropped afterwards
+    let mut writer = writer.map_buffers(|value| value * 10).ok().unwrap();
+    assert!(reader.checked_get().is_err());
+
+    let mut reader = writer.reader();
+    assert_eq!(*reader.checked_get().unwrap(), 10);
+    *writer.split_mut().writer = 20;
+    writer.swap_buffers();
+    assert_eq!(*reader.checked_get().unwrap(), 20);
+}
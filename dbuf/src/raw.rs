@@ -1,17 +1,25 @@
 //! the raw building blocks of a double buffer
 
-use crate::interface::{RawBuffers, Strategy, Which, WhichOf};
+use crate::interface::{FromBuffers, RawBuffers, Strategy, Which, WhichOf};
 #[cfg(not(feature = "loom"))]
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::{cell::UnsafeCell, ptr};
 #[cfg(feature = "loom")]
-use loom::sync::atomic::{AtomicBool, Ordering};
+use loom::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 mod reader;
 mod writer;
 
-pub use reader::{ReadGuard, Reader};
-pub use writer::{Split, SplitMut, Swap, Writer};
+pub use reader::{
+    BufferId, Busy, EpochPin, OwnedReadGuard, PendingReader, ReadError, ReadGuard, Reader,
+    SharedId, SharedReader, SnapshotRetired, ZoomGuard, OPTIMISTIC_READ_RETRIES,
+};
+#[cfg(feature = "alloc")]
+pub use writer::BlockingReader;
+pub use writer::{
+    DiffGuard, FieldSplit, ReadHalfToken, Split, SplitMut, Swap, SwapGuard, SwapStats, SwapTotals,
+    WriteHalf, Writer, WrongWriter,
+};
 
 /// A default thead-safe shared state for a double buffer
 #[cfg(feature = "alloc")]
@@ -23,6 +31,10 @@ pub struct Shared<S, B: ?Sized, W = WhichOf<S>> {
     strategy: S,
     /// a boolean flag for which buffer is in front
     which: W,
+    /// true if the writer panicked while updating the writer buffer, see [`PoisonDetect`](crate::poison::PoisonDetect)
+    poisoned: W,
+    /// incremented after every flip of `which`, see [`Reader::optimistic_read`]
+    epoch: AtomicUsize,
     /// the buffers theselves
     buffers: B,
 }
@@ -30,12 +42,22 @@ pub struct Shared<S, B: ?Sized, W = WhichOf<S>> {
 #[cfg(feature = "alloc")]
 impl<T> SyncShared<T> {
     /// Create a shared state from two buffers
+    #[cfg(not(feature = "loom"))]
     pub const fn from_buffers(front: T, back: T) -> Self {
         Self::from_raw_parts(
             crate::strategy::HazardStrategy::new(),
             RawDBuf::new(front, back),
         )
     }
+
+    /// Create a shared state from two buffers
+    #[cfg(feature = "loom")]
+    pub fn from_buffers(front: T, back: T) -> Self {
+        Self::from_raw_parts(
+            crate::strategy::HazardStrategy::new(),
+            RawDBuf::new(front, back),
+        )
+    }
 }
 
 impl<S: Strategy, B> Shared<S, B> {
@@ -45,21 +67,109 @@ impl<S: Strategy, B> Shared<S, B> {
         Self {
             strategy,
             which: Which::INIT,
+            poisoned: Which::INIT,
+            epoch: AtomicUsize::new(0),
             buffers,
         }
     }
 
+    /// Create a new shared state to manage the double buffer
+    #[cfg(feature = "loom")]
+    pub fn from_raw_parts(strategy: S, buffers: B) -> Self {
+        Self::new(strategy, buffers)
+    }
+
+    /// Create a new shared state to manage the double buffer
+    /// with a specific buffer as the writer buffer
+    ///
+    /// `Shared::from_raw_parts` is the same as passing `false` for `which`
+    pub fn from_raw_parts_with_which(strategy: S, buffers: B, which: bool) -> Self {
+        let shared = Self::from_raw_parts(strategy, buffers);
+        if which {
+            // there are no readers or writers yet, so nothing can observe the flip
+            shared.which.flip();
+        }
+        shared
+    }
+
+    /// Create a new shared state to manage the double buffer, with a specific flag for which buffer is the writer buffer
+    ///
+    /// see [`SharedFlag::link`](crate::group::SharedFlag::link)
+    #[cfg(feature = "alloc")]
+    pub(crate) fn from_raw_parts_linked(strategy: S, buffers: B, which: WhichOf<S>) -> Self {
+        Self {
+            strategy,
+            which,
+            #[cfg(not(feature = "loom"))]
+            poisoned: Which::INIT,
+            #[cfg(feature = "loom")]
+            poisoned: Which::new(),
+            epoch: AtomicUsize::new(0),
+            buffers,
+        }
+    }
+
+    /// Change the strategy which syncronizes the double buffer, without touching the buffers
+    ///
+    /// This keeps the buffers and which one is the writer buffer. Since the shared state is taken by value,
+    /// there can't be any readers or writers using the old strategy. Writer and reader tags
+    /// for the new strategy are created fresh from the new shared state.
+    #[cfg(not(feature = "loom"))]
+    pub fn map_strategy<S2: Strategy>(self, f: impl FnOnce(S) -> S2) -> Shared<S2, B> {
+        let Self {
+            strategy,
+            which,
+            poisoned,
+            epoch: _,
+            buffers,
+        } = self;
+        // SAFETY: we own the shared state, so nothing can flip the flags
+        let (which, poisoned) = unsafe { (which.load_unsync(), poisoned.load_unsync()) };
+        let shared = Shared::from_raw_parts_with_which(f(strategy), buffers, which);
+        if poisoned {
+            shared.poisoned.flip();
+        }
+        shared
+    }
+
     /// Create a new shared state to manage the double buffer
     #[cfg(feature = "loom")]
     pub fn new(strategy: S, buffers: B) -> Self {
         Self {
             strategy,
             which: Which::new(),
+            poisoned: Which::new(),
+            epoch: AtomicUsize::new(0),
             buffers,
         }
     }
 }
 
+impl<S, B, W> Shared<S, B, W> {
+    /// Change the buffers, without touching the strategy
+    ///
+    /// This keeps the strategy instance and which buffer is the writer buffer, so `f` should keep the
+    /// order of the buffers, like [`RawDBuf::map`]. Since the shared state is taken by value, there can't
+    /// be any readers or writers using it, but the writer tag which was created from this strategy is still
+    /// valid for the new shared state.
+    pub fn map_buffers<B2>(self, f: impl FnOnce(B) -> B2) -> Shared<S, B2, W> {
+        let Self {
+            strategy,
+            which,
+            poisoned,
+            epoch,
+            buffers,
+        } = self;
+        Shared {
+            strategy,
+            which,
+            poisoned,
+            epoch,
+            buffers: f(buffers),
+        }
+    }
+}
+
 /// a sized raw double buffer
 ///
 /// it contains two instances of T which are the two buffers
@@ -74,6 +184,25 @@ unsafe impl<T: Send> Send for RawDBuf<T> {}
 // * (T: Sync) we allow getting a shared refrence to T from a shared reference to Self
 unsafe impl<T: Send + Sync> Sync for RawDBuf<T> {}
 
+/// a sized raw double buffer where each buffer is on its own cache lines
+///
+/// The buffers of a [`RawDBuf`] are next to each other, so if `T` is small the reader buffer
+/// and the writer buffer may share a cache line, and writes will slow down readers (false sharing).
+/// This pads each buffer to 128 bytes, which is enough for the prefetchers of most modern cpus.
+pub struct PaddedRawDBuf<T>(UnsafeCell<[CachePadded<T>; 2]>);
+
+/// a value which is aligned to a cache line
+#[repr(C, align(128))]
+struct CachePadded<T>(T);
+
+// SAFETY:
+// * (T: Send) we allow getting a mutable refrence to T from a mutable reference to Self
+unsafe impl<T: Send> Send for PaddedRawDBuf<T> {}
+// SAFETY:
+// * (T: Send) we allow getting a mutable refrence to T from a shared reference to Self
+// * (T: Sync) we allow getting a shared refrence to T from a shared reference to Self
+unsafe impl<T: Send + Sync> Sync for PaddedRawDBuf<T> {}
+
 /// a slice raw double buffer
 ///
 /// the
@@ -88,11 +217,77 @@ unsafe impl<T: ?Sized + Send> Send for SliceRawDbuf<T> {}
 // * (T: Sync) we allow getting a shared refrence to T from a shared reference to Self
 unsafe impl<T: ?Sized + Send + Sync> Sync for SliceRawDbuf<T> {}
 
+/// A dynamically sized type which holds both buffers of a double buffer
+///
+/// This generalizes how [`SliceRawDbuf`] splits a slice in half to other unsized types,
+/// like a header followed by a slice (see [`frame`](crate::frame) for an example)
+///
+/// # Safety
+///
+/// * the two pointers returned from `split` must be valid, aligned, in bounds of `this` and disjoint
+/// * `split` must always return the same pointers for the same `this`
+/// * `split` may only read the parts of `this` outside both halves, which must never be written
+///   after `this` was created, since the halves may be written to while `split` is called
+pub unsafe trait SplitDst {
+    /// the type of each buffer
+    type Half: ?Sized;
+
+    /// get pointers to the front and back buffers
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a valid `Self`
+    unsafe fn split(this: *mut Self) -> (*mut Self::Half, *mut Self::Half);
+}
+
+/// a raw double buffer of a custom dynamically sized type
+///
+/// The two buffers are found with [`SplitDst`]
+#[repr(transparent)]
+pub struct DstRawDbuf<T: ?Sized>(UnsafeCell<T>);
+
+// SAFETY:
+// * (T: Send) we allow getting a mutable refrence to T from a mutable reference to Self
+unsafe impl<T: ?Sized + Send> Send for DstRawDbuf<T> {}
+// SAFETY:
+// * (T: Send) we allow getting a mutable refrence to T from a shared reference to Self
+// * (T: Sync) we allow getting a shared refrence to T from a shared reference to Self
+unsafe impl<T: ?Sized + Send + Sync> Sync for DstRawDbuf<T> {}
+
 impl<T> RawDBuf<T> {
     /// Create a new sized raw double buffer
     pub const fn new(front: T, back: T) -> Self {
         Self(UnsafeCell::new([front, back]))
     }
+
+    /// Get both buffers, in the same order as [`new`](Self::new)
+    pub fn into_inner(self) -> [T; 2] {
+        self.0.into_inner()
+    }
+
+    /// Change the type of both buffers, keeping their order, so the writer buffer stays the writer buffer
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> RawDBuf<U> {
+        RawDBuf(UnsafeCell::new(self.into_inner().map(f)))
+    }
+}
+
+impl<T> FromBuffers for RawDBuf<T> {
+    fn from_buffers(front: T, back: T) -> Self {
+        Self::new(front, back)
+    }
+}
+
+impl<T> PaddedRawDBuf<T> {
+    /// Create a new padded raw double buffer
+    pub const fn new(front: T, back: T) -> Self {
+        Self(UnsafeCell::new([CachePadded(front), CachePadded(back)]))
+    }
+}
+
+impl<T> FromBuffers for PaddedRawDBuf<T> {
+    fn from_buffers(front: T, back: T) -> Self {
+        Self::new(front, back)
+    }
 }
 
 impl<T, const N: usize> SliceRawDbuf<[T; N]> {
@@ -115,6 +310,35 @@ impl<T> SliceRawDbuf<[T]> {
         // Safety: Self has the same representation as [T]
         unsafe { &mut *(slice as *mut [T] as *mut Self) }
     }
+
+    /// Create a new slice raw double buffer from a box with both buffers
+    ///
+    /// The length of the slice must be even. The box is a [`RawBuffers`] too, so it can be used directly in a [`Shared`]
+    #[cfg(feature = "alloc")]
+    pub fn from_box(slice: std::boxed::Box<[T]>) -> std::boxed::Box<Self> {
+        assert!(slice.len().is_multiple_of(2));
+        let ptr = std::boxed::Box::into_raw(slice);
+        // Safety: Self has the same representation as [T], so it has the same layout
+        unsafe { std::boxed::Box::from_raw(ptr as *mut Self) }
+    }
+}
+
+impl<T: ?Sized> DstRawDbuf<T> {
+    /// Create a new raw double buffer from a reference to both buffers
+    pub fn from_mut(buffers: &mut T) -> &mut Self {
+        // Safety: Self has the same representation as T
+        unsafe { &mut *(buffers as *mut T as *mut Self) }
+    }
+
+    /// Create a new raw double buffer from a box with both buffers
+    ///
+    /// The box is a [`RawBuffers`] too, so it can be used directly in a [`Shared`]
+    #[cfg(feature = "alloc")]
+    pub fn from_box(buffers: std::boxed::Box<T>) -> std::boxed::Box<Self> {
+        let ptr = std::boxed::Box::into_raw(buffers);
+        // Safety: Self has the same representation as T, so it has the same layout
+        unsafe { std::boxed::Box::from_raw(ptr as *mut Self) }
+    }
 }
 
 // Safety:
@@ -136,29 +360,101 @@ unsafe impl<T> RawBuffers for RawDBuf<T> {
 // * the two pointers returned from get are always valid
 // * they are disjoint
 // * the data is not dereferenced
-unsafe impl<T> RawBuffers for SliceRawDbuf<[T]> {
-    type Buffer = [T];
+unsafe impl<T> RawBuffers for PaddedRawDBuf<T> {
+    type Buffer = T;
 
     fn get(&self, which: bool) -> (*mut Self::Buffer, *const Self::Buffer) {
-        let ptr = self.0.get();
+        let ptr = self.0.get().cast::<CachePadded<T>>();
+
+        // Safety: booleans are always 0 or 1 which is always in bounds of an array length 2
+        // and `CachePadded` is `repr(C)` so the value is at the start of it
+        unsafe {
+            (
+                ptr.add(usize::from(which)).cast::<T>(),
+                ptr.add(usize::from(!which)).cast::<T>(),
+            )
+        }
+    }
+}
+
+// Safety:
+// * the two halves are always valid, disjoint and in bounds
+// * the data is not dereferenced, only the length of the slice is read
+unsafe impl<T> SplitDst for [T] {
+    type Half = [T];
 
-        // Safety: scalling slice len doesn't access the data segment of the ptr
-        // so there's no data races possible
-        let len = unsafe { (*ptr).len() };
+    unsafe fn split(this: *mut Self) -> (*mut Self::Half, *mut Self::Half) {
+        // the length is read from the pointer metadata, so the data isn't accessed
+        let len = this.len();
 
-        let ptr = ptr.cast::<T>();
+        let ptr = this.cast::<T>();
         let half = len / 2;
 
-        // Safety: booleans are always 0 or 1 which is always in bounds of an array length 2
+        // Safety: `half` is at most half of the length, so both halves are in bounds
         unsafe {
             (
-                ptr::slice_from_raw_parts_mut(ptr.add(half * usize::from(which)), half),
-                ptr::slice_from_raw_parts(ptr.add(half * usize::from(!which)), half),
+                ptr::slice_from_raw_parts_mut(ptr, half),
+                ptr::slice_from_raw_parts_mut(ptr.add(half), half),
             )
         }
     }
 }
 
+/// pick the writer and reader buffers out of the front and back buffers
+fn pick<T: ?Sized>((front, back): (*mut T, *mut T), which: bool) -> (*mut T, *const T) {
+    if which {
+        (back, front)
+    } else {
+        (front, back)
+    }
+}
+
+// Safety:
+// * the two pointers returned from get are always valid
+// * they are disjoint
+// * the data is not dereferenced
+unsafe impl<T> RawBuffers for SliceRawDbuf<[T]> {
+    type Buffer = [T];
+
+    fn get(&self, which: bool) -> (*mut Self::Buffer, *const Self::Buffer) {
+        // Safety: the pointer came from a reference, so it's valid
+        pick(unsafe { <[T] as SplitDst>::split(self.0.get()) }, which)
+    }
+
+    fn halves_interchangeable(&self) -> bool {
+        // Safety: the pointer came from a reference, so it's valid
+        let (front, back) = unsafe { <[T] as SplitDst>::split(self.0.get()) };
+        // the length is read from the pointer metadata, so the data isn't accessed
+        front.len() == back.len()
+    }
+}
+
+// Safety:
+// * the two pointers returned from get are always valid and disjoint, guaranteed by `SplitDst`
+// * the data is not dereferenced, guaranteed by `SplitDst`
+unsafe impl<T: ?Sized + SplitDst> RawBuffers for DstRawDbuf<T> {
+    type Buffer = T::Half;
+
+    fn get(&self, which: bool) -> (*mut Self::Buffer, *const Self::Buffer) {
+        // Safety: the pointer came from a reference, so it's valid
+        pick(unsafe { T::split(self.0.get()) }, which)
+    }
+}
+
+// Safety: forwarded to the boxed buffers, which don't move when the box is moved
+#[cfg(feature = "alloc")]
+unsafe impl<B: ?Sized + RawBuffers> RawBuffers for std::boxed::Box<B> {
+    type Buffer = B::Buffer;
+
+    fn get(&self, which: bool) -> (*mut Self::Buffer, *const Self::Buffer) {
+        B::get(self, which)
+    }
+
+    fn halves_interchangeable(&self) -> bool {
+        B::halves_interchangeable(self)
+    }
+}
+
 /// A thread-safe flag
 pub struct Flag(core::cell::Cell<bool>);
 
@@ -232,3 +528,163 @@ unsafe impl Which for AtomicFlag {
         self.0.fetch_xor(true, Ordering::Release);
     }
 }
+
+/// checks that every buffer type hands out the correct half after any number of swaps,
+/// with two halves which have the same shape but different contents
+#[cfg(all(test, feature = "std", not(feature = "loom")))]
+mod half_selection {
+    use std::{boxed::Box, vec::Vec};
+
+    use crate::{
+        interface::{RawBuffers, Strategy},
+        op_log::{OpLog, Operation},
+        strategy::{
+            AdaptiveStrategy, HazardStrategy, LocalHazardStrategy, LocalStrategy,
+            LocalTrackingStrategy, TrackingStrategy,
+        },
+    };
+
+    use super::{PaddedRawDBuf, RawDBuf, Shared, SliceRawDbuf, Writer};
+
+    /// the most swaps in each check, enough to see both halves in both roles twice
+    const SWAPS: usize = 5;
+
+    /// set the first element of a buffer
+    struct SetFirst(i32);
+
+    impl<B: ?Sized + AsMut<[i32]>> Operation<B> for SetFirst {
+        fn apply(&mut self, buffer: &mut B) {
+            buffer.as_mut()[0] = self.0;
+        }
+    }
+
+    /// the initial contents of the front and back buffers
+    const HALVES: [[i32; 2]; 2] = [[1, 10], [2, 20]];
+
+    /// swap `swaps` times, and check that the writer, the reader and the op log
+    /// all agree on which half is which
+    fn check<S: Strategy, B: ?Sized + RawBuffers>(shared: &mut Shared<S, B>, swaps: usize)
+    where
+        B::Buffer: AsRef<[i32]> + AsMut<[i32]>,
+    {
+        assert!(shared.buffers.halves_interchangeable());
+
+        let mut writer = Writer::new(shared);
+        let mut reader = writer.reader();
+        for _ in 0..swaps {
+            writer.try_swap_buffers().unwrap();
+        }
+
+        // the writer starts out with the front buffer
+        let writer_half = swaps % 2;
+        let split = writer.split();
+        assert_eq!(split.writer.as_ref(), HALVES[writer_half]);
+        assert_eq!(split.reader.as_ref(), HALVES[1 - writer_half]);
+        let (writer_id, reader_id) = (split.writer_id(), split.reader_id());
+
+        let guard = reader.get();
+        assert_eq!((*guard).as_ref(), HALVES[1 - writer_half]);
+        assert_eq!(guard.buffer_id(), reader_id);
+        drop(guard);
+
+        // the op log applies each op to the writer buffer, then to the other half after the swap
+        let mut op_log = OpLog::new();
+        op_log.push(SetFirst(-1));
+        op_log.apply(writer.split_mut().writer);
+        writer.try_swap_buffers().unwrap();
+
+        let guard = reader.get();
+        assert_eq!(guard.buffer_id(), writer_id);
+        assert_eq!((*guard).as_ref(), [-1, HALVES[writer_half][1]]);
+        drop(guard);
+
+        let split = writer.split_mut();
+        assert_eq!(split.writer_id(), reader_id);
+        assert_eq!(split.writer.as_ref(), HALVES[1 - writer_half]);
+        op_log.apply(split.writer);
+        assert_eq!(split.writer.as_ref(), [-1, HALVES[1 - writer_half][1]]);
+        assert!(op_log.is_empty());
+    }
+
+    /// check each buffer type with a fresh strategy from `strategy`
+    fn check_buffers<S: Strategy>(strategy: impl Fn() -> S) {
+        for swaps in 0..=SWAPS {
+            let [front, back] = HALVES;
+            check(
+                &mut Shared::from_raw_parts(strategy(), RawDBuf::new(front, back)),
+                swaps,
+            );
+            check(
+                &mut Shared::from_raw_parts(strategy(), PaddedRawDBuf::new(front, back)),
+                swaps,
+            );
+            check(
+                &mut Shared::from_raw_parts(strategy(), Box::new(RawDBuf::new(front, back))),
+                swaps,
+            );
+
+            let mut shared = Shared::from_raw_parts(
+                strategy(),
+                SliceRawDbuf::from_array([front[0], front[1], back[0], back[1]]),
+            );
+            check(&mut shared as &mut Shared<_, SliceRawDbuf<[i32]>>, swaps);
+        }
+    }
+
+    #[test]
+    fn test_local() {
+        check_buffers(LocalStrategy::new)
+    }
+
+    #[test]
+    fn test_local_tracking() {
+        check_buffers(LocalTrackingStrategy::new)
+    }
+
+    #[test]
+    fn test_local_hazard() {
+        check_buffers(LocalHazardStrategy::new)
+    }
+
+    #[test]
+    fn test_tracking() {
+        check_buffers(TrackingStrategy::new)
+    }
+
+    #[test]
+    fn test_hazard() {
+        check_buffers(HazardStrategy::new)
+    }
+
+    #[test]
+    fn test_adaptive() {
+        check_buffers(AdaptiveStrategy::new)
+    }
+
+    /// two buffers of different lengths
+    struct Uneven(RawDBuf<Vec<i32>>);
+
+    // SAFETY: forwarded to `RawDBuf`
+    unsafe impl RawBuffers for Uneven {
+        type Buffer = Vec<i32>;
+
+        fn get(&self, which: bool) -> (*mut Self::Buffer, *const Self::Buffer) {
+            self.0.get(which)
+        }
+
+        fn halves_interchangeable(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "halves have different shapes"]
+    fn test_uneven_halves() {
+        let mut shared = Shared::from_raw_parts(
+            LocalStrategy::new(),
+            Uneven(RawDBuf::new(Vec::from([1]), Vec::from([2, 3]))),
+        );
+        Writer::new(&mut shared).try_swap_buffers().unwrap();
+    }
+}
@@ -1,12 +1,21 @@
 //! a reader to a double buffer
 
-use core::{marker::PhantomData, mem::ManuallyDrop, ops::Deref, ptr::NonNull};
+use core::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::Deref,
+    ptr::NonNull,
+    slice::{Chunks, SliceIndex},
+};
 
 use crate::interface::{
-    BufferOf, RawBuffers, RawBuffersOf, ReaderGuardOf, ReaderTagOf, Strategy, StrategyOf, StrongOf,
-    StrongRef, WeakRef, Which,
+    BufferOf, CheapReaderTag, GuardStorage, PendingRef, RawBuffers, RawBuffersOf, ReaderGuardOf,
+    ReaderTagOf, Strategy, StrategyOf, StrongOf, StrongRef, TearableRead, WeakOf, WeakRef, Which,
+    WouldBlock,
 };
 
+use super::Ordering;
+
 /// A reader to a double buffer
 pub struct Reader<W, R = ReaderTagOf<StrategyOf<StrongOf<W>>>> {
     /// the reader tag which identifies this reader to the strategy
@@ -15,15 +24,195 @@ pub struct Reader<W, R = ReaderTagOf<StrategyOf<StrongOf<W>>>> {
     ptr: W,
 }
 
+/// A reader which can read through a shared reference
+///
+/// [`Reader`] needs `&mut self` to read, because it keeps a reader tag which the strategy may use
+/// to cache per-reader state. So a single reader can't be used from two futures which are
+/// polled at the same time, for example with `join!`. A `SharedReader` doesn't keep a tag,
+/// instead every read creates a fresh one, which is only possible for strategies where that
+/// is cheap, see [`CheapReaderTag`]
+///
+/// Each read returns an [`OwnedReadGuard`], which is `Send` if the buffer is `Sync`, so it can
+/// be held across an `.await`. The writer can't finish a swap while the guard is alive, so
+/// holding it across a long `.await` stalls the writer.
+///
+/// A fresh tag can't use any per-reader cache, so reads are a bit slower than with a [`Reader`]
+pub struct SharedReader<W: WeakRef> {
+    /// the reader which the tag of each read is created from, this is never used to read
+    reader: Reader<W>,
+}
+
+/// A reader to a double buffer whose writer may not exist yet
+///
+/// see [`Reader::new_pending`]
+pub struct PendingReader<P: PendingRef> {
+    /// the reader once the writer exists, or the pending ref until then
+    state: Result<Reader<P::Weak>, P>,
+}
+
 /// A RAII guard which locks the double buffer and allows reading into it
 pub struct ReadGuard<'a, S: StrongRef, B: ?Sized = BufferOf<RawBuffersOf<S>>> {
     /// The buffer we're reading into
     buffer: SharedRef<B>,
     /// the raw read guard which locks the double buffer
-    /// only used in `Drop`
+    /// and remembers which buffer is locked
+    _raw: RawReadGuard<'a, S>,
+}
+
+/// A RAII guard which locks the double buffer and allows reading into a projection of it
+/// while keeping access to the whole buffer
+///
+/// Both the projection and the whole buffer are from the same snapshot
+pub struct ZoomGuard<'a, S: StrongRef, B: ?Sized, T: ?Sized> {
+    /// The whole buffer we're reading into
+    parent: SharedRef<B>,
+    /// The projection of the buffer we're reading into
+    zoomed: SharedRef<T>,
+    /// the raw read guard which locks the double buffer
+    /// and remembers which buffer is locked
     _raw: RawReadGuard<'a, S>,
 }
 
+/// A read guard which owns its reader, so it isn't tied to a borrow of the reader
+///
+/// This can be held across an `.await`, or moved to another thread. see [`Reader::into_guard`]
+pub struct OwnedReadGuard<W: WeakRef, B: ?Sized = BufferOf<RawBuffersOf<StrongOf<W>>>> {
+    /// The buffer we're reading into
+    buffer: SharedRef<B>,
+    /// the buffer which is locked, kept separately so that it survives `map`
+    buffer_id: BufferId,
+    /// the reader guard token which the strategy can use to track which readers reading
+    guard: ManuallyDrop<ReaderGuardOf<StrategyOf<StrongOf<W>>>>,
+    /// keeps the shared state alive, this must be dropped before the reader
+    storage: W::GuardStorage,
+    /// the reader which owns the lock
+    reader: Reader<W>,
+}
+
+/// A good number of retries for [`Reader::optimistic_read_with_retries`] to take a read guard after
+pub const OPTIMISTIC_READ_RETRIES: usize = 64;
+
+/// The reason [`Reader::try_get_bounded`] failed, see [`ReadError::Busy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Busy;
+
+impl core::fmt::Display for Busy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the read lock couldn't be acquired in time")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Busy {}
+
+/// A published snapshot of a double buffer, see [`Reader::pin_epoch`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochPin {
+    /// the double buffer which was pinned
+    shared: SharedId,
+    /// the epoch when the snapshot was pinned
+    epoch: usize,
+    /// the published buffer when the snapshot was pinned
+    which: bool,
+}
+
+/// The reason [`Reader::get_pinned`] failed, see [`ReadError::SnapshotRetired`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotRetired;
+
+impl core::fmt::Display for SnapshotRetired {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("the writer started a swap after the snapshot was pinned")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SnapshotRetired {}
+
+/// The error returned when a reader can't read, from every fallible read in this crate
+///
+/// Each failure has its own variant, so code which reads through several kinds of readers
+/// only needs one match. More variants may be added as readers gain new ways to fail.
+/// The reads which upgrade the reader's weak ref need the [`WeakRef::UpgradeError`] to
+/// convert into this, which the weak refs in this crate do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadError {
+    /// the double buffer was dropped, so the reader's weak ref couldn't be upgraded
+    WriterGone,
+    /// the double buffer wasn't published yet
+    NotYetPublished,
+    /// the writer panicked while writing, see [`PoisonedError`](crate::op::PoisonedError)
+    Poisoned,
+    /// the read lock couldn't be acquired in time, see [`Reader::try_get_bounded`]
+    Busy(Busy),
+    /// the pinned snapshot was retired, see [`Reader::get_pinned`]
+    SnapshotRetired(SnapshotRetired),
+}
+
+impl core::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::WriterGone => f.write_str("the double buffer was dropped"),
+            Self::NotYetPublished => f.write_str("the double buffer wasn't published yet"),
+            Self::Poisoned => f.write_str("the writer panicked while writing"),
+            Self::Busy(busy) => busy.fmt(f),
+            Self::SnapshotRetired(retired) => retired.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Busy(busy) => Some(busy),
+            Self::SnapshotRetired(retired) => Some(retired),
+            _ => None,
+        }
+    }
+}
+
+impl From<Busy> for ReadError {
+    fn from(busy: Busy) -> Self {
+        Self::Busy(busy)
+    }
+}
+
+impl From<SnapshotRetired> for ReadError {
+    fn from(retired: SnapshotRetired) -> Self {
+        Self::SnapshotRetired(retired)
+    }
+}
+
+impl From<core::convert::Infallible> for ReadError {
+    fn from(inf: core::convert::Infallible) -> Self {
+        match inf {}
+    }
+}
+
+/// An opaque identifier for one of the two buffers of a double buffer
+///
+/// This is stable for as long as the shared state stays in the same place,
+/// for example for the life of an [`Owned`](crate::ptrs::alloc::Owned) allocation.
+/// So it can be used to cache data derived from a specific buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+/// An opaque identifier for the shared state of a double buffer, see [`Reader::shared_id`]
+///
+/// Readers of the same double buffer have the same id. The id stays the same after the
+/// double buffer is dropped, for as long as the reader is alive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SharedId(usize);
+
+impl BufferId {
+    /// get the identifier for the buffer at the given address
+    pub(crate) fn new<T: ?Sized>(ptr: *const T) -> Self {
+        Self(ptr.cast::<()>() as usize)
+    }
+}
+
 /// A RAII guard which locks the double buffer and allows reading into it
 #[repr(transparent)]
 pub struct SharedRef<B: ?Sized> {
@@ -48,12 +237,18 @@ impl<S: StrongRef, B: ?Sized> Deref for ReadGuard<'_, S, B> {
 struct RawReadGuard<'a, S: StrongRef> {
     /// the reader which owns the lock
     tag: &'a mut ReaderTagOf<StrategyOf<S>>,
-    /// a strong ref to the shared state to keep it alive
-    strong_ref: Result<S, &'a StrategyOf<S>>,
+    /// keeps the shared state alive, this is just a pointer if the reader's weak ref is already strong
+    storage: <WeakOf<S> as WeakRef>::GuardStorage,
     /// the reader guard token which the strategy can use to track which readers reading
     guard: ManuallyDrop<ReaderGuardOf<StrategyOf<S>>>,
+    /// the buffer which is locked, kept separately so that it survives `map`
+    buffer_id: BufferId,
     /// a lifetime to ensure that no other reads happen at the same time
     lifetime: PhantomData<&'a S>,
+    /// when the guard was acquired, to report guards which are held for a long time,
+    /// or `None` if they aren't reported, see [`trace`](crate::trace)
+    #[cfg(all(feature = "tracing", feature = "std"))]
+    acquired: Option<std::time::Instant>,
 }
 
 impl<S: StrongRef> Drop for RawReadGuard<'_, S> {
@@ -61,13 +256,14 @@ impl<S: StrongRef> Drop for RawReadGuard<'_, S> {
         // SAFETY: the guard is created in `Reader::try_get` and never touched until here so it's still valid
         let guard = unsafe { ManuallyDrop::take(&mut self.guard) };
 
-        let strategy = match self.strong_ref {
-            Ok(ref strong_ref) => &strong_ref.strategy,
-            Err(strategy) => strategy,
-        };
+        // SAFETY: the reader's weak ref is borrowed for `'a`, so it's still alive
+        let strategy = unsafe { &self.storage.shared().strategy };
 
         // SAFETY: the reader (self.tag) was the one that created the guard by construction of `Self`
         unsafe { strategy.end_read_guard(self.tag, guard) }
+
+        #[cfg(all(feature = "tracing", feature = "std"))]
+        crate::trace::check_read_guard(self.acquired);
     }
 }
 
@@ -82,19 +278,41 @@ impl<W: WeakRef> Reader<W> {
         Self { tag, ptr }
     }
 
+    /// Create a reader to a double buffer whose writer may not exist yet
+    ///
+    /// The reader tag is only created once the writer exists, see [`PendingReader::try_reader`]
+    pub fn new_pending<P: PendingRef<Weak = W>>(pending: P) -> PendingReader<P> {
+        PendingReader {
+            state: Err(pending),
+        }
+    }
+
+    /// Deconstruct the reader into it's tag and ptr
+    pub fn into_raw_parts(self) -> (ReaderTagOf<StrategyOf<StrongOf<W>>>, W) {
+        (self.tag, self.ptr)
+    }
+
     /// get a read lock on the double buffer
+    #[deprecated(note = "use `checked_get`, which fails with a `ReadError`")]
     pub fn try_get(&mut self) -> Result<ReadGuard<'_, StrongOf<W>>, W::UpgradeError> {
-        let strong_ref;
-        let shared = match self.ptr.as_ref() {
-            Some(shared) => {
-                strong_ref = Err(&shared.strategy);
-                shared
-            }
-            _ => {
-                strong_ref = Ok(W::upgrade(&self.ptr)?);
-                strong_ref.as_ref().ok().unwrap()
-            }
-        };
+        self.try_get_raw()
+    }
+
+    /// get a read lock on the double buffer
+    ///
+    /// This fails with [`ReadError::WriterGone`] if the reader's weak ref can't be upgraded
+    pub fn checked_get(&mut self) -> Result<ReadGuard<'_, StrongOf<W>>, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        self.try_get_raw().map_err(Into::into)
+    }
+
+    /// get a read lock on the double buffer, or the error from upgrading the weak ref
+    pub(crate) fn try_get_raw(&mut self) -> Result<ReadGuard<'_, StrongOf<W>>, W::UpgradeError> {
+        let storage = W::GuardStorage::new(&self.ptr)?;
+        // SAFETY: the weak ref is borrowed for as long as the guard is alive
+        let shared = unsafe { storage.shared() };
 
         // first begin the guard *before* loading which buffer is for reads
         // to avoid racing with the writer
@@ -102,109 +320,1774 @@ impl<W: WeakRef> Reader<W> {
         // SAFETY: the upgrade succeeded so the reader tag isn't dangling
         let guard = unsafe { shared.strategy.begin_read_guard(&mut self.tag) };
 
-        let which = shared.which.load();
+        // SAFETY: the guard was just started on `storage`'s strategy by this reader
+        Ok(unsafe { self.finish_get(storage, guard) })
+    }
+
+    /// get a read lock on the double buffer, giving up if acquiring it would
+    /// need to pause more than `max_pauses` times
+    ///
+    /// Most strategies never block when acquiring a read lock, so this is the same
+    /// as [`get`](Self::get) for them. see [`Strategy::try_begin_read_guard`]
+    ///
+    /// This fails with [`ReadError::Busy`]
+    pub fn try_get_bounded(
+        &mut self,
+        max_pauses: usize,
+    ) -> Result<ReadGuard<'_, StrongOf<W>>, ReadError>
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+    {
+        let storage = match W::GuardStorage::new(&self.ptr) {
+            Ok(storage) => storage,
+            Err(inf) => match inf {},
+        };
+        // SAFETY: the weak ref is borrowed for as long as the guard is alive
+        let shared = unsafe { storage.shared() };
+
+        let mut pause = Default::default();
+        let mut pauses = 0;
+        let guard = loop {
+            // SAFETY: the upgrade succeeded so the reader tag isn't dangling
+            match unsafe {
+                shared
+                    .strategy
+                    .try_begin_read_guard(&mut self.tag, &mut pause)
+            } {
+                Ok(guard) => break guard,
+                Err(WouldBlock) if pauses < max_pauses => pauses += 1,
+                Err(WouldBlock) => return Err(ReadError::Busy(Busy)),
+            }
+        };
+
+        // SAFETY: the guard was just started on `storage`'s strategy by this reader
+        Ok(unsafe { self.finish_get(storage, guard) })
+    }
+
+    /// load the buffer for reads, and wrap it in a read guard
+    ///
+    /// # Safety
+    ///
+    /// `guard` must have just been started by this reader on `storage`'s strategy
+    unsafe fn finish_get(
+        &mut self,
+        storage: W::GuardStorage,
+        guard: ReaderGuardOf<StrategyOf<StrongOf<W>>>,
+    ) -> ReadGuard<'_, StrongOf<W>> {
+        // SAFETY: the weak ref is borrowed for as long as the guard is alive
+        let which = unsafe { storage.shared() }.which.load();
+
+        // SAFETY: the caller ensures that the guard was just started, and `which` was loaded after that
+        unsafe { self.finish_get_with(storage, guard, which) }
+    }
+
+    /// wrap the buffer selected by `which` in a read guard
+    ///
+    /// # Safety
+    ///
+    /// `guard` must have just been started by this reader on `storage`'s strategy,
+    /// and `which` must have been loaded after the guard was started
+    unsafe fn finish_get_with(
+        &mut self,
+        storage: W::GuardStorage,
+        guard: ReaderGuardOf<StrategyOf<StrongOf<W>>>,
+        which: bool,
+    ) -> ReadGuard<'_, StrongOf<W>> {
+        // SAFETY: the weak ref is borrowed for as long as the guard is alive
+        let shared = unsafe { storage.shared() };
+
         let (_writer, reader) = shared.buffers.get(which);
 
-        Ok(ReadGuard {
+        ReadGuard {
             buffer: SharedRef {
-                // SAFETY: the reader ptr is valid for as long as the `strong_ref` is alive
+                // SAFETY: the reader ptr is valid for as long as the `storage` is alive
                 ptr: unsafe { NonNull::new_unchecked(reader as *mut _) },
             },
             _raw: RawReadGuard {
                 tag: &mut self.tag,
-                strong_ref,
+                storage,
                 guard: ManuallyDrop::new(guard),
+                buffer_id: BufferId::new(reader),
                 lifetime: PhantomData,
+                #[cfg(all(feature = "tracing", feature = "std"))]
+                acquired: crate::trace::start_read_guard(),
+            },
+        }
+    }
+
+    /// get a read lock on the double buffer which owns this reader
+    ///
+    /// This fails with [`ReadError::WriterGone`] if the reader's weak ref can't be upgraded
+    pub fn try_into_guard(self) -> Result<OwnedReadGuard<W>, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        self.try_into_guard_raw().map_err(Into::into)
+    }
+
+    /// get a read lock on the double buffer which owns this reader, or the error from upgrading the weak ref
+    fn try_into_guard_raw(mut self) -> Result<OwnedReadGuard<W>, W::UpgradeError> {
+        let storage = W::GuardStorage::new(&self.ptr)?;
+        // SAFETY: the weak ref is owned by the guard, and outlives the storage
+        let shared = unsafe { storage.shared() };
+
+        // first begin the guard *before* loading which buffer is for reads
+        // to avoid racing with the writer
+        //
+        // SAFETY: the upgrade succeeded so the reader tag isn't dangling
+        let guard = unsafe { shared.strategy.begin_read_guard(&mut self.tag) };
+
+        let which = shared.which.load();
+        let (_writer, reader) = shared.buffers.get(which);
+
+        Ok(OwnedReadGuard {
+            buffer: SharedRef {
+                // SAFETY: the reader ptr is valid for as long as the `storage` is alive
+                ptr: unsafe { NonNull::new_unchecked(reader as *mut _) },
             },
+            buffer_id: BufferId::new(reader),
+            guard: ManuallyDrop::new(guard),
+            storage,
+            reader: self,
         })
     }
 
+    /// get a read lock on the double buffer which owns this reader
+    ///
+    /// Use [`OwnedReadGuard::into_reader`] to release the lock and get the reader back
+    pub fn into_guard(self) -> OwnedReadGuard<W>
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+    {
+        match self.try_into_guard_raw() {
+            Ok(guard) => guard,
+            Err(inf) => match inf {},
+        }
+    }
+
     /// get a read lock on the double buffer
     pub fn get(&mut self) -> ReadGuard<'_, StrongOf<W>>
     where
         W: WeakRef<UpgradeError = core::convert::Infallible>,
     {
-        match self.try_get() {
+        match self.try_get_raw() {
             Ok(guard) => guard,
             Err(inf) => match inf {},
         }
     }
 
-    /// Clones the reader without attemping to upgrade the pointer
-    pub fn copy_tag(&self) -> Self
+    /// Pin the published snapshot, so that several read guards can see the same snapshot
+    ///
+    /// This doesn't lock the double buffer, so the writer can keep swapping. Each call to
+    /// [`get_pinned`](Self::get_pinned) either returns the pinned snapshot or fails, so a pass
+    /// which takes several read guards never mixes two versions of the buffer.
+    pub fn pin_epoch(&self) -> EpochPin
     where
-        W: Clone,
-        ReaderTagOf<StrategyOf<StrongOf<W>>>: Copy,
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
     {
-        Self {
-            tag: self.tag,
-            ptr: self.ptr.clone(),
+        match self.try_pin_epoch_raw() {
+            Ok(pin) => pin,
+            Err(inf) => match inf {},
         }
     }
-}
 
-impl<W: WeakRef> Copy for Reader<W>
-where
-    W: Copy,
-    ReaderTagOf<StrategyOf<StrongOf<W>>>: Copy,
-{
-}
-impl<W: WeakRef> Clone for Reader<W> {
-    fn clone(&self) -> Self {
-        if <StrategyOf<StrongOf<W>> as Strategy>::READER_TAG_NEEDS_CONSTRUCTION {
-            let strong;
-            let shared = if let Some(shared) = <W as WeakRef>::as_ref(&self.ptr) {
-                Some(shared)
-            } else if let Ok(ptr) = W::upgrade(&self.ptr) {
-                strong = ptr;
-                Some(&*strong)
-            } else {
-                None
-            };
+    /// Pin the published snapshot, see [`pin_epoch`](Self::pin_epoch)
+    ///
+    /// This fails with [`ReadError::WriterGone`] if the reader's weak ref can't be upgraded
+    pub fn try_pin_epoch(&self) -> Result<EpochPin, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        self.try_pin_epoch_raw().map_err(Into::into)
+    }
 
-            if let Some(shared) = shared {
-                // Safety: the writer is owned by this strategy as it was created by this strategy
-                let tag = unsafe { shared.strategy.create_reader_tag_from_reader(&self.tag) };
-                // Safety: the writer is owned by this strategy as it was created by this strategy
-                return unsafe { Self::from_raw_parts(tag, self.ptr.clone()) };
-            }
-        }
+    /// Pin the published snapshot, or return the error from upgrading the weak ref
+    pub(crate) fn try_pin_epoch_raw(&self) -> Result<EpochPin, W::UpgradeError> {
+        let storage = W::GuardStorage::new(&self.ptr)?;
+        // SAFETY: the weak ref is borrowed for as long as the storage is alive
+        let (epoch, which) = load_published(unsafe { storage.shared() });
 
-        let tag = <StrategyOf<StrongOf<W>> as Strategy>::dangling_reader_tag();
-        // Safety: this reader tag will never be used because the writer is dead
-        unsafe { Self::from_raw_parts(tag, self.ptr.clone()) }
+        Ok(EpochPin {
+            shared: self.shared_id(),
+            epoch,
+            which,
+        })
     }
-}
 
-impl<'a, S: StrongRef, B: ?Sized> ReadGuard<'a, S, B> {
-    /// Map the contained type
-    pub fn map<T: ?Sized>(self, f: impl FnOnce(&B) -> &T) -> ReadGuard<'a, S, T> {
-        // SAFETY: the raw guard ensure that the writer can't write to this buffer
-        let ptr = f(unsafe { self.buffer.ptr.as_ref() });
+    /// get a read lock on the pinned snapshot, see [`pin_epoch`](Self::pin_epoch)
+    ///
+    /// This fails with [`ReadError::SnapshotRetired`] once the writer starts a swap after the snapshot was pinned, even if the
+    /// swap hasn't finished yet. A new read guard doesn't stop the writer from finishing the
+    /// swap, so the retired buffer could be written to while it's being read. To keep reading
+    /// a snapshot across swaps, hold a single read guard instead.
+    ///
+    /// # Panics
+    ///
+    /// if the pin is from a different double buffer
+    pub fn get_pinned(&mut self, pin: &EpochPin) -> Result<ReadGuard<'_, StrongOf<W>>, ReadError>
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+    {
+        self.try_get_pinned(pin)
+    }
 
-        ReadGuard {
-            buffer: SharedRef {
-                ptr: NonNull::from(ptr),
-            },
-            _raw: self._raw,
+    /// get a read lock on the pinned snapshot, see [`get_pinned`](Self::get_pinned)
+    ///
+    /// This also fails with [`ReadError::WriterGone`] if the reader's weak ref can't be upgraded
+    ///
+    /// # Panics
+    ///
+    /// if the pin is from a different double buffer
+    pub fn try_get_pinned(
+        &mut self,
+        pin: &EpochPin,
+    ) -> Result<ReadGuard<'_, StrongOf<W>>, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        match self.try_get_pinned_raw(pin) {
+            Ok(guard) => guard.map_err(ReadError::SnapshotRetired),
+            Err(err) => Err(err.into()),
         }
     }
 
-    /// Map the contained type
-    pub fn try_map<T: ?Sized>(
-        self,
-        f: impl FnOnce(&B) -> Option<&T>,
-    ) -> Result<ReadGuard<'a, S, T>, Self> {
-        // SAFETY: the raw guard ensure that the writer can't write to this buffer
-        if let Some(ptr) = f(unsafe { self.buffer.ptr.as_ref() }) {
-            Ok(ReadGuard {
-                buffer: SharedRef {
-                    ptr: NonNull::from(ptr),
-                },
-                _raw: self._raw,
-            })
-        } else {
-            Err(self)
+    /// get a read lock on the pinned snapshot, the outer error is from upgrading the reader's weak ref
+    ///
+    /// # Panics
+    ///
+    /// if the pin is from a different double buffer
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn try_get_pinned_raw(
+        &mut self,
+        pin: &EpochPin,
+    ) -> Result<Result<ReadGuard<'_, StrongOf<W>>, SnapshotRetired>, W::UpgradeError> {
+        assert_eq!(
+            pin.shared,
+            self.shared_id(),
+            "the pin is from a different double buffer"
+        );
+
+        let storage = W::GuardStorage::new(&self.ptr)?;
+        // SAFETY: the weak ref is borrowed for as long as the guard is alive
+        let shared = unsafe { storage.shared() };
+
+        // SAFETY: the upgrade succeeded so the reader tag isn't dangling
+        let guard = unsafe { shared.strategy.begin_read_guard(&mut self.tag) };
+
+        // the buffer is loaded after the guard was started, so it's locked like in `try_get`
+        let (epoch, which) = load_published(shared);
+        if (epoch, which) != (pin.epoch, pin.which) {
+            // SAFETY: this reader just started the guard on this strategy
+            unsafe { shared.strategy.end_read_guard(&mut self.tag, guard) };
+            return Ok(Err(SnapshotRetired));
+        }
+
+        // SAFETY: the guard was just started on `storage`'s strategy by this reader,
+        // and `which` was loaded after that
+        Ok(Ok(unsafe { self.finish_get_with(storage, guard, which) }))
+    }
+
+    /// true if the writer panicked while updating the writer buffer, and hasn't recovered yet
+    ///
+    /// The published buffer is from before the panic, so it's consistent but may be stale.
+    /// see [`PoisonDetect`](crate::poison::PoisonDetect)
+    pub fn is_poisoned(&self) -> bool
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+    {
+        let storage = match W::GuardStorage::new(&self.ptr) {
+            Ok(storage) => storage,
+            Err(inf) => match inf {},
+        };
+        // SAFETY: the weak ref is borrowed for as long as the storage is alive
+        unsafe { storage.shared() }.poisoned.load()
+    }
+
+    /// Copy the published buffer without taking a read guard, and pass the copy to `f`
+    ///
+    /// This is a seqlock-style read: the reader never blocks the writer and isn't tracked
+    /// by the strategy, so the writer may be writing to the buffer while it's being copied.
+    /// Torn copies are detected by checking that no swap started while copying, and
+    /// then the copy is retried. So `f` only ever sees a consistent snapshot of
+    /// some published buffer, and is called exactly once.
+    ///
+    /// This spins while the writer keeps swapping, so it's best suited to small buffers
+    /// which are read much more often than they are swapped. A writer which keeps swapping could
+    /// make the copy retry forever, see [`optimistic_read_with_retries`](Self::optimistic_read_with_retries)
+    /// to take a read guard instead after a number of retries.
+    pub fn optimistic_read<R>(&self, f: impl FnOnce(&BufferOf<RawBuffersOf<StrongOf<W>>>) -> R) -> R
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+        BufferOf<RawBuffersOf<StrongOf<W>>>: TearableRead,
+    {
+        loop {
+            if let Some(copy) = self.optimistic_copy(OPTIMISTIC_READ_RETRIES) {
+                return f(&copy);
+            }
         }
     }
+
+    /// Copy the published buffer without taking a read guard, and take a read guard after
+    /// `max_retries` retries, see [`optimistic_read`](Self::optimistic_read)
+    ///
+    /// The read guard may block the writer for as long as the copy takes, but unlike
+    /// [`optimistic_read`](Self::optimistic_read) this can't retry forever, see [`OPTIMISTIC_READ_RETRIES`]
+    pub fn optimistic_read_with_retries<R>(
+        &mut self,
+        max_retries: usize,
+        f: impl FnOnce(&BufferOf<RawBuffersOf<StrongOf<W>>>) -> R,
+    ) -> R
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+        BufferOf<RawBuffersOf<StrongOf<W>>>: TearableRead,
+    {
+        let copy = match self.optimistic_copy(max_retries) {
+            Some(copy) => copy,
+            None => *self.get(),
+        };
+        f(&copy)
+    }
+
+    /// Copy the published buffer without taking a read guard, and give up after `max_retries` retries
+    ///
+    /// Unlike [`optimistic_read`](Self::optimistic_read) this never blocks the writer,
+    /// and returns `None` without calling `f` if the writer kept swapping
+    pub fn try_optimistic_read<R>(
+        &self,
+        max_retries: usize,
+        f: impl FnOnce(&BufferOf<RawBuffersOf<StrongOf<W>>>) -> R,
+    ) -> Option<R>
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+        BufferOf<RawBuffersOf<StrongOf<W>>>: TearableRead,
+    {
+        self.optimistic_copy(max_retries).map(|copy| f(&copy))
+    }
+
+    /// copy the published buffer without taking a read guard, retrying at most `max_retries` times
+    fn optimistic_copy(&self, max_retries: usize) -> Option<BufferOf<RawBuffersOf<StrongOf<W>>>>
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+        BufferOf<RawBuffersOf<StrongOf<W>>>: TearableRead,
+    {
+        let storage = match W::GuardStorage::new(&self.ptr) {
+            Ok(storage) => storage,
+            Err(inf) => match inf {},
+        };
+        // SAFETY: the weak ref is borrowed for as long as the storage is alive
+        let shared = unsafe { storage.shared() };
+
+        for retry in 0..=max_retries {
+            if retry != 0 {
+                #[cfg(not(feature = "loom"))]
+                core::hint::spin_loop();
+                // loom needs to know that this thread is waiting on the writer
+                #[cfg(feature = "loom")]
+                loom::thread::yield_now();
+            }
+
+            // syncronizes with the increment in `try_start_buffer_swap`, so that
+            // everything written to the buffer before it was published is visible
+            let epoch = shared.epoch.load(Ordering::Acquire);
+            let (_writer, reader) = shared.buffers.get(shared.which.load());
+
+            // SAFETY: the pointer is valid for reads and aligned as long as the shared state is alive
+            let copy = unsafe { atomic_copy(reader) };
+
+            // the writer's writes to the buffer aren't atomic, so a fence can't syncronize with
+            // them. Instead, read the epoch with a read-modify-write, which always sees the latest
+            // increment. If the epoch didn't change, the writer's next increment acquires this
+            // release, so the copy happens before the writer writes to this buffer again
+            if shared.epoch.fetch_add(0, Ordering::Release) == epoch {
+                return Some(copy);
+            }
+        }
+
+        None
+    }
+
+    /// Convert this reader into one which can read through a shared reference
+    pub fn into_shared(self) -> SharedReader<W> {
+        SharedReader { reader: self }
+    }
+
+    /// Allocate anything the strategy needs for this reader's read guards up front
+    ///
+    /// Afterwards, reading doesn't allocate for any of the strategies in this crate,
+    /// see [`Strategy::preallocate_reader`]. This does nothing if the double buffer was dropped
+    pub fn preallocate(&mut self) {
+        if let Ok(storage) = W::GuardStorage::new(&self.ptr) {
+            // SAFETY: the weak ref is borrowed for as long as the storage is alive
+            let shared = unsafe { storage.shared() };
+            // SAFETY: the upgrade succeeded so the reader tag isn't dangling
+            unsafe { shared.strategy.preallocate_reader(&mut self.tag) }
+        }
+    }
+
+    /// Create another reader to the same double buffer
+    ///
+    /// Unlike `clone`, this fails with [`ReadError::WriterGone`] if the double buffer was already
+    /// dropped, instead of returning a reader which can never read
+    pub fn try_fork(&self) -> Result<Self, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        self.try_fork_raw().map_err(Into::into)
+    }
+
+    /// Create another reader to the same double buffer, or return the error from upgrading the weak ref
+    fn try_fork_raw(&self) -> Result<Self, W::UpgradeError> {
+        let storage = W::GuardStorage::new(&self.ptr)?;
+        // SAFETY: the weak ref is borrowed for as long as the storage is alive
+        let shared = unsafe { storage.shared() };
+
+        // SAFETY: the upgrade succeeded so the reader tag isn't dangling
+        let tag = unsafe { shared.strategy.create_reader_tag_from_reader(&self.tag) };
+        // SAFETY: the tag was just created by the strategy that the weak ref points to
+        Ok(unsafe { Self::from_raw_parts(tag, self.ptr.clone()) })
+    }
+
+    /// Check if both readers read from the same double buffer
+    ///
+    /// This doesn't upgrade the readers, so it works even if the double buffer was dropped
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        W::ptr_eq(&self.ptr, &other.ptr)
+    }
+
+    /// get an identifier for the double buffer this reader reads from
+    ///
+    /// This can be used as a key to deduplicate readers, see [`SharedId`]
+    pub fn shared_id(&self) -> SharedId {
+        SharedId(W::as_ptr(&self.ptr).cast::<()>() as usize)
+    }
+
+    /// Clones the reader without attemping to upgrade the pointer
+    ///
+    /// This copies the reader tag as is, so any per-reader cache in the tag is shared with this reader.
+    /// Prefer `clone` for readers which will read at the same time as this one
+    pub fn copy_tag(&self) -> Self
+    where
+        W: Clone,
+        ReaderTagOf<StrategyOf<StrongOf<W>>>: Copy,
+    {
+        Self {
+            tag: self.tag,
+            ptr: self.ptr.clone(),
+        }
+    }
+}
+
+/// copy the buffer with relaxed atomic loads, since the writer may be writing to it at the same time
+///
+/// `TearableRead` ensures that the copy is a valid value, even if it mixes bytes from before and after a write.
+/// The copy is done a word at a time if the buffer's size and alignment allow it, and a byte at a time otherwise.
+///
+/// # Safety
+///
+/// `src` must be valid for reads and aligned
+unsafe fn atomic_copy<T: TearableRead>(src: *const T) -> T {
+    use core::mem::{align_of, size_of, MaybeUninit};
+    use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+    /// copy `len` values of `A` from `src` to `dst` with relaxed loads
+    ///
+    /// # Safety
+    ///
+    /// `src` must be valid for reads of `len` values, and `dst` must be valid for writes of `len` values
+    unsafe fn copy_with<A, V>(src: *const A, dst: *mut V, len: usize, load: impl Fn(&A) -> V) {
+        for i in 0..len {
+            // SAFETY: guaranteed by the caller
+            unsafe { dst.add(i).write(load(&*src.add(i))) }
+        }
+    }
+
+    let mut copy = MaybeUninit::<T>::uninit();
+    let dst = copy.as_mut_ptr();
+
+    if align_of::<T>() >= align_of::<AtomicUsize>()
+        && size_of::<T>().is_multiple_of(size_of::<usize>())
+    {
+        // SAFETY: `T` is aligned for `usize`, and made of whole words. Atomics have the same layout as
+        // the integers, and the buffer is behind an `UnsafeCell` so it may be accessed through shared refs
+        unsafe {
+            copy_with(
+                src.cast::<AtomicUsize>(),
+                dst.cast::<usize>(),
+                size_of::<T>() / size_of::<usize>(),
+                |word| word.load(Ordering::Relaxed),
+            )
+        }
+    } else {
+        // SAFETY: see above, every type is aligned for bytes
+        unsafe {
+            copy_with(
+                src.cast::<AtomicU8>(),
+                dst.cast::<u8>(),
+                size_of::<T>(),
+                |byte| byte.load(Ordering::Relaxed),
+            )
+        }
+    }
+
+    // SAFETY: every byte was copied, and `TearableRead` types have no padding,
+    // and every bit pattern is valid
+    unsafe { copy.assume_init() }
+}
+
+/// load the epoch together with the published buffer
+///
+/// The writer flips the buffers before incrementing the epoch, so `which` may already be
+/// flipped for the next epoch. But if the epoch didn't change while loading `which`, then
+/// `which` was flipped at most once after that epoch started. So two loads which return the
+/// same epoch and buffer saw the same flip, and the buffer wasn't written to in between.
+fn load_published<S: Strategy, B: ?Sized>(shared: &super::Shared<S, B>) -> (usize, bool) {
+    loop {
+        // syncronizes with the increment in `try_start_buffer_swap`
+        let epoch = shared.epoch.load(Ordering::Acquire);
+        // `load` is an acquire, so the second load of the epoch can't happen before it
+        let which = shared.which.load();
+
+        if shared.epoch.load(Ordering::Relaxed) == epoch {
+            return (epoch, which);
+        }
+
+        #[cfg(not(feature = "loom"))]
+        core::hint::spin_loop();
+        // loom needs to know that this thread is waiting on the writer
+        #[cfg(feature = "loom")]
+        loom::thread::yield_now();
+    }
+}
+
+impl<W: WeakRef> Copy for Reader<W>
+where
+    W: Copy,
+    ReaderTagOf<StrategyOf<StrongOf<W>>>: Copy,
+{
+}
+impl<W: WeakRef> Clone for Reader<W> {
+    /// Create another reader to the same double buffer
+    ///
+    /// If the double buffer was already dropped, this silently returns a reader which
+    /// can never read. Use [`Reader::try_fork`], which fails with [`ReadError::WriterGone`],
+    /// to find out about that instead
+    fn clone(&self) -> Self {
+        if <StrategyOf<StrongOf<W>> as Strategy>::READER_TAG_NEEDS_CONSTRUCTION {
+            let strong;
+            let shared = if let Some(shared) = <W as WeakRef>::as_ref(&self.ptr) {
+                Some(shared)
+            } else if let Ok(ptr) = W::upgrade(&self.ptr) {
+                strong = ptr;
+                Some(&*strong)
+            } else {
+                None
+            };
+
+            if let Some(shared) = shared {
+                // Safety: the writer is owned by this strategy as it was created by this strategy
+                let tag = unsafe { shared.strategy.create_reader_tag_from_reader(&self.tag) };
+                // Safety: the writer is owned by this strategy as it was created by this strategy
+                return unsafe { Self::from_raw_parts(tag, self.ptr.clone()) };
+            }
+        }
+
+        let tag = <StrategyOf<StrongOf<W>> as Strategy>::dangling_reader_tag();
+        // Safety: this reader tag will never be used because the writer is dead
+        unsafe { Self::from_raw_parts(tag, self.ptr.clone()) }
+    }
+}
+
+impl<W: WeakRef> SharedReader<W>
+where
+    StrategyOf<StrongOf<W>>: CheapReaderTag,
+{
+    /// get a read lock on the double buffer with a fresh reader tag
+    ///
+    /// This fails with [`ReadError::WriterGone`] if the reader's weak ref can't be upgraded
+    pub fn try_get(&self) -> Result<OwnedReadGuard<W>, ReadError>
+    where
+        W::UpgradeError: Into<ReadError>,
+    {
+        self.try_get_raw().map_err(Into::into)
+    }
+
+    /// get a read lock on the double buffer with a fresh reader tag, or the error from upgrading the weak ref
+    fn try_get_raw(&self) -> Result<OwnedReadGuard<W>, W::UpgradeError> {
+        self.reader.try_fork_raw()?.try_into_guard_raw()
+    }
+
+    /// get a read lock on the double buffer with a fresh reader tag
+    pub fn get(&self) -> OwnedReadGuard<W>
+    where
+        W: WeakRef<UpgradeError = core::convert::Infallible>,
+    {
+        match self.try_get_raw() {
+            Ok(guard) => guard,
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// Check if both readers read from the same double buffer
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.reader.ptr_eq(&other.reader)
+    }
+
+    /// Convert back into a reader which needs `&mut self` to read
+    pub fn into_reader(self) -> Reader<W> {
+        self.reader
+    }
+}
+
+impl<W: WeakRef> Clone for SharedReader<W> {
+    fn clone(&self) -> Self {
+        Self {
+            reader: self.reader.clone(),
+        }
+    }
+}
+
+impl<P: PendingRef> PendingReader<P> {
+    /// Returns true if the writer exists, and this reader is ready to read
+    pub fn is_ready(&self) -> bool {
+        match &self.state {
+            Ok(_) => true,
+            Err(pending) => pending.try_weak().is_some(),
+        }
+    }
+
+    /// Get the reader if the writer exists, creating the reader tag the first time it does
+    pub fn try_reader(&mut self) -> Option<&mut Reader<P::Weak>> {
+        if let Err(pending) = &self.state {
+            let ptr = pending.try_weak()?;
+
+            let strong;
+            let shared = if let Some(shared) = <P::Weak as WeakRef>::as_ref(&ptr) {
+                Some(shared)
+            } else if let Ok(strong_ref) = WeakRef::upgrade(&ptr) {
+                strong = strong_ref;
+                Some(&*strong)
+            } else {
+                None
+            };
+
+            let reader = match shared {
+                // SAFETY: `try_weak` returned `Some`, so the writer tag was already created
+                // and the reader tag is managed by this strategy as it was created by this strategy
+                Some(shared) => unsafe {
+                    let tag = shared.strategy.create_reader_tag();
+                    Reader::from_raw_parts(tag, ptr)
+                },
+                None => {
+                    let tag = <StrategyOf<StrongOf<P::Weak>> as Strategy>::dangling_reader_tag();
+                    // SAFETY: this reader tag will never be used because the writer is dead
+                    unsafe { Reader::from_raw_parts(tag, ptr) }
+                }
+            };
+
+            self.state = Ok(reader);
+        }
+
+        self.state.as_mut().ok()
+    }
+
+    /// Get the reader if the writer exists, otherwise give back the pending reader
+    pub fn into_reader(mut self) -> Result<Reader<P::Weak>, Self> {
+        self.try_reader();
+
+        match self.state {
+            Ok(reader) => Ok(reader),
+            Err(pending) => Err(Self {
+                state: Err(pending),
+            }),
+        }
+    }
+}
+
+impl<S: StrongRef, T> ReadGuard<'_, S, [T]> {
+    /// the number of elements in the buffer
+    pub fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    /// Returns true if the buffer has no elements
+    pub fn is_empty(&self) -> bool {
+        <[T]>::is_empty(self)
+    }
+
+    /// get an element or subslice of the buffer, or `None` if it's out of bounds
+    ///
+    /// Unlike [`map`](Self::map), this borrows the guard, so it can be called repeatedly
+    pub fn get<I: SliceIndex<[T]>>(&self, index: I) -> Option<&I::Output> {
+        <[T]>::get(self, index)
+    }
+
+    /// iterate over the buffer in chunks of `chunk_size` elements, the last chunk may be shorter
+    ///
+    /// # Panics
+    ///
+    /// if `chunk_size` is zero
+    pub fn chunks(&self, chunk_size: usize) -> Chunks<'_, T> {
+        <[T]>::chunks(self, chunk_size)
+    }
+}
+
+impl<W: WeakRef, B: ?Sized> Drop for OwnedReadGuard<W, B> {
+    fn drop(&mut self) {
+        // SAFETY: the guard is created in `Reader::try_into_guard` and only taken here or in `into_reader`
+        // which doesn't run this destructor
+        let guard = unsafe { ManuallyDrop::take(&mut self.guard) };
+
+        // SAFETY: the reader's weak ref is owned by this guard, so it's still alive
+        let strategy = unsafe { &self.storage.shared().strategy };
+
+        // SAFETY: the reader (self.reader) was the one that created the guard by construction of `Self`
+        unsafe { strategy.end_read_guard(&mut self.reader.tag, guard) }
+    }
+}
+
+impl<W: WeakRef, B: ?Sized> Deref for OwnedReadGuard<W, B> {
+    type Target = B;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the guard ensures that the writer can't write to this buffer
+        unsafe { self.buffer.ptr.as_ref() }
+    }
+}
+
+impl<W: WeakRef, B: ?Sized> AsRef<B> for OwnedReadGuard<W, B> {
+    fn as_ref(&self) -> &B {
+        self
+    }
+}
+
+impl<W: WeakRef, B: ?Sized> OwnedReadGuard<W, B> {
+    /// The identity of the buffer this guard is reading from
+    ///
+    /// This is the same even after [`map`](Self::map)ping the guard
+    pub fn buffer_id(&self) -> BufferId {
+        self.buffer_id
+    }
+
+    /// Returns true if this guard is reading from the buffer identified by `id`
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self.buffer_id == id
+    }
+
+    /// Map the contained type
+    pub fn map<T: ?Sized>(self, f: impl FnOnce(&B) -> &T) -> OwnedReadGuard<W, T> {
+        // SAFETY: the guard ensures that the writer can't write to this buffer
+        let ptr = NonNull::from(f(unsafe { self.buffer.ptr.as_ref() }));
+        // SAFETY: the buffer is replaced by one derived from it, which is valid for as long as the guard
+        unsafe { self.with_buffer(ptr) }
+    }
+
+    /// Map the contained type
+    pub fn try_map<T: ?Sized>(
+        self,
+        f: impl FnOnce(&B) -> Option<&T>,
+    ) -> Result<OwnedReadGuard<W, T>, Self> {
+        self.try_map_or_else(|buffer| f(buffer).ok_or(()))
+            .map_err(|(guard, ())| guard)
+    }
+
+    /// Map the contained type, or give back the guard together with the reason `f` failed
+    ///
+    /// The read lock is kept on failure, so the guard can be mapped again with a different closure
+    pub fn try_map_or_else<T: ?Sized, E>(
+        self,
+        f: impl FnOnce(&B) -> Result<&T, E>,
+    ) -> Result<OwnedReadGuard<W, T>, (Self, E)> {
+        // SAFETY: the guard ensures that the writer can't write to this buffer
+        match f(unsafe { self.buffer.ptr.as_ref() }) {
+            Ok(ptr) => {
+                let ptr = NonNull::from(ptr);
+                // SAFETY: the buffer is replaced by one derived from it, which is valid for as long as the guard
+                Ok(unsafe { self.with_buffer(ptr) })
+            }
+            Err(err) => Err((self, err)),
+        }
+    }
+
+    /// Keep the guard only if `pred` accepts the buffer
+    pub fn filter(self, pred: impl FnOnce(&B) -> bool) -> Result<Self, Self> {
+        if pred(&self) {
+            Ok(self)
+        } else {
+            Err(self)
+        }
+    }
+
+    /// release the read lock, and get the reader back
+    pub fn into_reader(self) -> Reader<W> {
+        let (guard, storage, reader) = self.into_raw_parts();
+        // SAFETY: the reader's weak ref is still alive
+        let strategy = unsafe { &storage.shared().strategy };
+        let mut reader = reader;
+        // SAFETY: the reader was the one that created the guard by construction of `Self`
+        unsafe { strategy.end_read_guard(&mut reader.tag, guard) }
+        drop(storage);
+        reader
+    }
+
+    /// replace the buffer this guard points to
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads for as long as the read lock is held
+    unsafe fn with_buffer<T: ?Sized>(self, ptr: NonNull<T>) -> OwnedReadGuard<W, T> {
+        let buffer_id = self.buffer_id;
+        let (guard, storage, reader) = self.into_raw_parts();
+        OwnedReadGuard {
+            buffer: SharedRef { ptr },
+            buffer_id,
+            guard: ManuallyDrop::new(guard),
+            storage,
+            reader,
+        }
+    }
+
+    /// take apart the guard without releasing the read lock
+    #[allow(clippy::type_complexity)]
+    fn into_raw_parts(
+        self,
+    ) -> (
+        ReaderGuardOf<StrategyOf<StrongOf<W>>>,
+        W::GuardStorage,
+        Reader<W>,
+    ) {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never used again or dropped, so each field is moved out exactly once
+        unsafe {
+            (
+                ManuallyDrop::take(&mut this.guard),
+                core::ptr::read(&this.storage),
+                core::ptr::read(&this.reader),
+            )
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<W: WeakRef> OwnedReadGuard<W, [u8]>
+where
+    Self: Send + 'static,
+{
+    /// Turn the guard into [`Bytes`](bytes::Bytes) without copying the buffer
+    ///
+    /// The read lock is held until the last clone of the `Bytes` is dropped,
+    /// so the writer's next swap waits for all of them
+    pub fn freeze(self) -> bytes::Bytes {
+        bytes::Bytes::from_owner(self)
+    }
+}
+
+impl<S: StrongRef, B: ?Sized, T: ?Sized> Deref for ZoomGuard<'_, S, B, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.zoomed()
+    }
+}
+
+impl<'a, S: StrongRef, B: ?Sized> ReadGuard<'a, S, B> {
+    /// The identity of the buffer this guard is reading from
+    ///
+    /// This is the same even after [`map`](Self::map)ping the guard
+    pub fn buffer_id(&self) -> BufferId {
+        self._raw.buffer_id
+    }
+
+    /// Returns true if this guard is reading from the buffer identified by `id`
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self._raw.buffer_id == id
+    }
+
+    /// The value of the writer's [`which`](crate::raw::Writer::which) flag when this guard was acquired
+    ///
+    /// This is the same even after [`map`](Self::map)ping the guard
+    pub fn which(&self) -> bool {
+        // SAFETY: the reader's weak ref is borrowed for `'a`, so it's still alive
+        let shared = unsafe { self._raw.storage.shared() };
+        let (_writer, reader) = shared.buffers.get(false);
+        BufferId::new(reader) != self._raw.buffer_id
+    }
+
+    /// true if the writer panicked while updating the writer buffer, and hasn't recovered yet
+    ///
+    /// This guard is reading the buffer which was published before the panic.
+    /// see [`Reader::is_poisoned`]
+    pub fn poisoned(&self) -> bool {
+        // SAFETY: the reader's weak ref is borrowed for `'a`, so it's still alive
+        unsafe { self._raw.storage.shared() }.poisoned.load()
+    }
+
+    /// Map the contained type, while keeping access to the original buffer
+    pub fn map_with_parent<T: ?Sized>(self, f: impl FnOnce(&B) -> &T) -> ZoomGuard<'a, S, B, T> {
+        // SAFETY: the raw guard ensure that the writer can't write to this buffer
+        let ptr = f(unsafe { self.buffer.ptr.as_ref() });
+
+        ZoomGuard {
+            zoomed: SharedRef {
+                ptr: NonNull::from(ptr),
+            },
+            parent: self.buffer,
+            _raw: self._raw,
+        }
+    }
+
+    /// Map the contained type
+    pub fn map<T: ?Sized>(self, f: impl FnOnce(&B) -> &T) -> ReadGuard<'a, S, T> {
+        // SAFETY: the raw guard ensure that the writer can't write to this buffer
+        let ptr = f(unsafe { self.buffer.ptr.as_ref() });
+
+        ReadGuard {
+            buffer: SharedRef {
+                ptr: NonNull::from(ptr),
+            },
+            _raw: self._raw,
+        }
+    }
+
+    /// Map the contained type
+    pub fn try_map<T: ?Sized>(
+        self,
+        f: impl FnOnce(&B) -> Option<&T>,
+    ) -> Result<ReadGuard<'a, S, T>, Self> {
+        self.try_map_or_else(|buffer| f(buffer).ok_or(()))
+            .map_err(|(guard, ())| guard)
+    }
+
+    /// Map the contained type, or give back the guard together with the reason `f` failed
+    ///
+    /// The read lock is kept on failure, so the guard can be mapped again with a different closure
+    pub fn try_map_or_else<T: ?Sized, E>(
+        self,
+        f: impl FnOnce(&B) -> Result<&T, E>,
+    ) -> Result<ReadGuard<'a, S, T>, (Self, E)> {
+        // SAFETY: the raw guard ensure that the writer can't write to this buffer
+        match f(unsafe { self.buffer.ptr.as_ref() }) {
+            Ok(ptr) => Ok(ReadGuard {
+                buffer: SharedRef {
+                    ptr: NonNull::from(ptr),
+                },
+                _raw: self._raw,
+            }),
+            Err(err) => Err((self, err)),
+        }
+    }
+
+    /// Keep the guard only if `pred` accepts the buffer
+    pub fn filter(self, pred: impl FnOnce(&B) -> bool) -> Result<Self, Self> {
+        if pred(&self) {
+            Ok(self)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<'a, S: StrongRef, B: ?Sized, T: ?Sized> ZoomGuard<'a, S, B, T> {
+    /// The identity of the buffer this guard is reading from
+    pub fn buffer_id(&self) -> BufferId {
+        self._raw.buffer_id
+    }
+
+    /// Returns true if this guard is reading from the buffer identified by `id`
+    pub fn same_buffer(&self, id: BufferId) -> bool {
+        self._raw.buffer_id == id
+    }
+
+    /// The projection of the buffer
+    pub fn zoomed(&self) -> &T {
+        // SAFETY: the raw guard ensure that the writer can't write to this buffer
+        unsafe { self.zoomed.ptr.as_ref() }
+    }
+
+    /// The whole buffer
+    pub fn parent(&self) -> &B {
+        // SAFETY: the raw guard ensure that the writer can't write to this buffer
+        unsafe { self.parent.ptr.as_ref() }
+    }
+
+    /// Move to a different projection of the buffer without releasing the lock
+    pub fn rezoom<U: ?Sized>(self, f: impl FnOnce(&B) -> &U) -> ZoomGuard<'a, S, B, U> {
+        ReadGuard {
+            buffer: self.parent,
+            _raw: self._raw,
+        }
+        .map_with_parent(f)
+    }
+
+    /// Drop the projection and go back to reading the whole buffer
+    pub fn into_parent(self) -> ReadGuard<'a, S, B> {
+        ReadGuard {
+            buffer: self.parent,
+            _raw: self._raw,
+        }
+    }
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_zoom_guard() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalStrategy::new(),
+        super::RawDBuf::new((0, 1), (0, 1)),
+    );
+    let mut writer = super::Writer::new(&mut shared);
+    let mut reader = writer.reader();
+
+    let zoom = reader.get().map_with_parent(|pair| &pair.0);
+    let parent: *const (i32, i32) = zoom.parent();
+    assert_eq!(*zoom, 0);
+
+    let zoom = zoom.rezoom(|pair| &pair.1);
+    assert!(core::ptr::eq(parent, zoom.parent()));
+    assert_eq!(*zoom.zoomed(), 1);
+
+    // the zoom guard holds the read lock
+    assert!(writer.try_swap_buffers().is_err());
+
+    let guard = zoom.into_parent();
+    assert!(core::ptr::eq(parent, &*guard));
+    drop(guard);
+
+    // and dropping it releases the only read lock
+    assert!(writer.try_swap_buffers().is_ok());
+}
+
+#[test]
+#[cfg(feature = "std")]
+// `OwnedWithWeak` needs `Weak`, which loom doesn't have
+#[cfg(not(feature = "loom"))]
+fn test_pending_reader() {
+    use crate::{ptrs::alloc::OwnedWithWeak, strategy::TrackingStrategy};
+    use std::sync::mpsc;
+
+    let owned = OwnedWithWeak::<TrackingStrategy, _>::from_buffers(0, 0);
+    let mut reader = Reader::new_pending(owned.downgrade());
+    let reader2 = Reader::new_pending(owned.downgrade());
+
+    // the writer doesn't exist yet, so the readers can't read
+    assert!(!reader.is_ready());
+    assert!(reader.try_reader().is_none());
+
+    let (ready, wait_ready) = mpsc::channel();
+    let (done, wait_done) = mpsc::channel();
+    let thread = std::thread::spawn(move || {
+        let mut writer = super::Writer::new(owned);
+        *writer.split_mut().writer = 10;
+        writer.swap_buffers();
+        ready.send(()).unwrap();
+        wait_done.recv().unwrap();
+    });
+
+    wait_ready.recv().unwrap();
+    assert!(reader.is_ready());
+    assert_eq!(*reader.try_reader().unwrap().checked_get().unwrap(), 10);
+
+    let mut reader2 = reader2.into_reader().ok().unwrap();
+    assert_eq!(*reader2.checked_get().unwrap(), 10);
+
+    done.send(()).unwrap();
+    thread.join().unwrap();
+
+    // once the writer is dropped, the reader can't upgrade any more
+    assert!(reader2.checked_get().is_err());
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_buffer_id() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalStrategy::new(),
+        super::RawDBuf::new((0, 1), (0, 1)),
+    );
+    let mut writer = super::Writer::new(&mut shared);
+    let mut reader = writer.reader();
+
+    let split = writer.split();
+    let (first, second) = (split.reader_id(), split.writer_id());
+    assert_ne!(first, second);
+
+    let guard = reader.get();
+    assert_eq!(guard.buffer_id(), first);
+    // the id is of the whole buffer, not the projection
+    let guard = guard.map(|pair| &pair.1);
+    assert!(guard.same_buffer(first));
+    drop(guard);
+
+    writer.try_swap_buffers().unwrap();
+    assert_eq!(writer.split_mut().writer_id(), first);
+    assert_eq!(reader.get().buffer_id(), second);
+
+    writer.try_swap_buffers().unwrap();
+    assert_eq!(reader.get().buffer_id(), first);
+}
+
+#[test]
+#[cfg(feature = "alloc")]
+#[cfg(not(feature = "loom"))]
+fn test_read_guard_size() {
+    use crate::{
+        ptrs::alloc::{OwnedPtr, OwnedStrong},
+        raw::{RawDBuf, Shared},
+        strategy::{HazardStrategy, LocalStrategy},
+    };
+    use core::mem::size_of;
+
+    /// when the guard was acquired, if slow read guards are reported
+    #[cfg(all(feature = "tracing", feature = "std"))]
+    const TIMED: usize = size_of::<Option<std::time::Instant>>();
+    /// when the guard was acquired, if slow read guards are reported
+    #[cfg(not(all(feature = "tracing", feature = "std")))]
+    const TIMED: usize = 0;
+
+    /// a pointer to the buffer, the reader tag, the shared buffer, and the buffer id
+    const BORROWED: usize = 4 * size_of::<usize>() + TIMED;
+
+    /// the hazard strategy's reader guard holds the node it marked
+    const HAZARD: usize = BORROWED + size_of::<usize>();
+
+    // the strategy's reader guard is zero-sized, and the weak ref is already strong,
+    // so the guard only needs to point at the shared buffer
+    assert_eq!(
+        size_of::<ReadGuard<&Shared<LocalStrategy, RawDBuf<u32>>>>(),
+        BORROWED
+    );
+    assert_eq!(
+        size_of::<ReadGuard<&Shared<HazardStrategy, RawDBuf<u32>>>>(),
+        HAZARD
+    );
+    assert_eq!(
+        size_of::<ReadGuard<OwnedPtr<HazardStrategy, RawDBuf<u32>>>>(),
+        HAZARD
+    );
+    // the strong ref is a single pointer
+    assert_eq!(
+        size_of::<ReadGuard<OwnedStrong<HazardStrategy, RawDBuf<u32>>>>(),
+        HAZARD
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_try_get_bounded() {
+    use crate::strategy::LocalStrategy;
+    use core::cell::Cell;
+
+    /// a strategy which only allows one reader at a time, to test the bounded acquire
+    struct OneReader<'a> {
+        /// the strategy which does the actual tracking
+        inner: LocalStrategy,
+        /// true if the only reader slot is taken
+        busy: Cell<bool>,
+        /// the number of times a reader paused waiting for the slot
+        pauses: &'a Cell<usize>,
+    }
+
+    // SAFETY: this forwards everything to `LocalStrategy`, and only adds an extra way to fail
+    unsafe impl Strategy for OneReader<'_> {
+        type WriterTag = <LocalStrategy as Strategy>::WriterTag;
+        type ReaderTag = <LocalStrategy as Strategy>::ReaderTag;
+        type Which = <LocalStrategy as Strategy>::Which;
+        type ValidationToken = <LocalStrategy as Strategy>::ValidationToken;
+        type ValidationError = <LocalStrategy as Strategy>::ValidationError;
+        type Capture = <LocalStrategy as Strategy>::Capture;
+        type ReaderGuard = <LocalStrategy as Strategy>::ReaderGuard;
+        type Pause = ();
+
+        unsafe fn create_writer_tag(&mut self) -> Self::WriterTag {
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.create_writer_tag() }
+        }
+
+        unsafe fn create_reader_tag_from_writer(
+            &self,
+            parent: &Self::WriterTag,
+        ) -> Self::ReaderTag {
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.create_reader_tag_from_writer(parent) }
+        }
+
+        unsafe fn create_reader_tag_from_reader(
+            &self,
+            parent: &Self::ReaderTag,
+        ) -> Self::ReaderTag {
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.create_reader_tag_from_reader(parent) }
+        }
+
+        unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.create_reader_tag() }
+        }
+
+        fn dangling_reader_tag() -> Self::ReaderTag {
+            LocalStrategy::dangling_reader_tag()
+        }
+
+        fn validate_swap(
+            &self,
+            writer: &mut Self::WriterTag,
+        ) -> Result<Self::ValidationToken, Self::ValidationError> {
+            self.inner.validate_swap(writer)
+        }
+
+        unsafe fn capture_readers(
+            &self,
+            writer: &mut Self::WriterTag,
+            token: Self::ValidationToken,
+        ) -> Self::Capture {
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.capture_readers(writer, token) }
+        }
+
+        unsafe fn capture_current_readers(
+            &self,
+            writer: &mut Self::WriterTag,
+            token: Self::ValidationToken,
+        ) -> Self::Capture {
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.capture_current_readers(writer, token) }
+        }
+
+        unsafe fn have_readers_exited(
+            &self,
+            writer: &Self::WriterTag,
+            capture: &mut Self::Capture,
+        ) -> bool {
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.have_readers_exited(writer, capture) }
+        }
+
+        unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
+            assert!(!self.busy.replace(true), "this would block forever");
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.begin_read_guard(reader) }
+        }
+
+        unsafe fn try_begin_read_guard(
+            &self,
+            reader: &mut Self::ReaderTag,
+            _pause: &mut Self::Pause,
+        ) -> Result<Self::ReaderGuard, WouldBlock> {
+            if self.busy.get() {
+                self.pauses.set(self.pauses.get() + 1);
+                return Err(WouldBlock);
+            }
+
+            // SAFETY: forwarded from the caller
+            Ok(unsafe { self.begin_read_guard(reader) })
+        }
+
+        unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, guard: Self::ReaderGuard) {
+            self.busy.set(false);
+            // SAFETY: forwarded from the caller
+            unsafe { self.inner.end_read_guard(reader, guard) }
+        }
+    }
+
+    let pauses = Cell::new(0);
+    let mut shared = super::Shared::from_raw_parts(
+        OneReader {
+            inner: LocalStrategy::new(),
+            busy: Cell::new(false),
+            pauses: &pauses,
+        },
+        super::RawDBuf::new(0, 0),
+    );
+    let writer = super::Writer::new(&mut shared);
+    let mut reader = writer.reader();
+    let mut other = writer.reader();
+
+    let guard = reader.try_get_bounded(0).unwrap();
+    assert_eq!(pauses.get(), 0);
+
+    // the only slot is taken, so this gives up instead of blocking
+    assert_eq!(other.try_get_bounded(3).err(), Some(ReadError::Busy(Busy)));
+    assert_eq!(pauses.get(), 4);
+
+    drop(guard);
+    assert_eq!(*other.try_get_bounded(0).unwrap(), 0);
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_slice_guard() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalStrategy::new(),
+        super::SliceRawDbuf::from_array([0; 10]),
+    );
+    let shared: &mut super::Shared<_, super::SliceRawDbuf<[i32]>> = &mut shared;
+    let mut writer = super::Writer::new(shared);
+    let mut reader = writer.reader();
+
+    for (i, chunk) in writer.split_mut().writer_chunks_mut(2).enumerate() {
+        chunk.fill(i as i32);
+    }
+    assert_eq!(writer.split().writer_chunks(2).len(), 3);
+    writer.try_swap_buffers().unwrap();
+
+    let guard = reader.get();
+    assert_eq!(guard.len(), 5);
+    assert_eq!(guard.get(1..3), Some(&[0, 1][..]));
+    assert_eq!(guard.get(4), Some(&2));
+    assert!(guard.get(4..6).is_none());
+    // the guard is only borrowed, so it can be projected again
+    assert!(guard.chunks(2).eq([&[0, 0][..], &[1, 1], &[2]]));
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_owned_read_guard() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalStrategy::new(),
+        super::RawDBuf::new((0, 1), (0, 1)),
+    );
+    let mut writer = super::Writer::new(&mut shared);
+    let reader = writer.reader();
+
+    let guard = reader.into_guard();
+    let id = guard.buffer_id();
+    let guard = guard.map(|pair| &pair.1);
+    assert_eq!(*guard, 1);
+    assert!(guard.same_buffer(id));
+
+    // the owned guard holds the read lock
+    assert!(writer.try_swap_buffers().is_err());
+
+    let mut reader = guard.into_reader();
+    // and getting the reader back releases it
+    assert!(writer.try_swap_buffers().is_ok());
+    assert!(!reader.get().same_buffer(id));
+}
+
+#[test]
+#[cfg(feature = "bytes")]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_freeze() {
+    use crate::{
+        delayed::DelayedWriter, ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy,
+    };
+
+    let mut writer = DelayedWriter::new(Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        std::vec![1_u8, 2, 3],
+        std::vec![1, 2, 3],
+    )));
+
+    let bytes = writer.reader().into_guard().map(|buf| &buf[..]).freeze();
+    let clone = bytes.slice(1..);
+    assert_eq!(&bytes[..], [1, 2, 3]);
+    assert_eq!(&clone[..], [2, 3]);
+
+    writer.start_buffer_swap();
+    assert!(!writer.is_swap_finished());
+
+    // the swap waits for every clone of the bytes
+    drop(bytes);
+    assert!(!writer.is_swap_finished());
+    drop(clone);
+    assert!(writer.is_swap_finished());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_reader_identity() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let a = super::Writer::new(Owned::<TrackingStrategy, _>::from_buffers(0, 0));
+    let b = super::Writer::new(Owned::<TrackingStrategy, _>::from_buffers(0, 0));
+
+    let a1 = a.reader();
+    let a2 = a1.clone();
+    assert!(a1.ptr_eq(&a2));
+    assert!(a1.ptr_eq(&a.reader()));
+    assert_eq!(a1.shared_id(), a2.shared_id());
+
+    let b1 = b.reader();
+    assert!(!a1.ptr_eq(&b1));
+    assert_ne!(a1.shared_id(), b1.shared_id());
+
+    // the id outlives the double buffer
+    let id = b1.shared_id();
+    let b2 = b1.clone();
+    drop(b);
+    assert_eq!(b1.shared_id(), id);
+    assert!(b1.ptr_eq(&b2));
+
+    let mut weak = a.reader().into_weak();
+    assert!(weak.checked_get().is_ok());
+    let id = weak.shared_id();
+    assert_eq!(id, a1.shared_id());
+    drop((a, a1, a2));
+    assert!(weak.checked_get().is_err());
+    assert_eq!(weak.shared_id(), id);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_try_fork() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let mut writer = super::Writer::new(Owned::<TrackingStrategy, _>::from_buffers(0, 0));
+    let strong = writer.reader();
+    let weak = writer.reader().into_weak();
+
+    // the writer is alive
+    let mut fork = weak.try_fork().unwrap();
+    *writer.split_mut().writer = 1;
+    writer.try_swap_buffers().unwrap();
+    assert_eq!(*fork.checked_get().unwrap(), 1);
+    drop(fork);
+
+    // the writer is gone, but the buffer is kept alive by a strong reader
+    drop(writer);
+    let mut fork = weak.try_fork().unwrap();
+    assert_eq!(*fork.checked_get().unwrap(), 1);
+    drop(fork);
+
+    // the buffer is gone
+    drop(strong);
+    assert!(weak.try_fork().is_err());
+    // clone can't report that, so the clone can never read
+    assert!(weak.clone().checked_get().is_err());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_optimistic_read() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let mut writer = super::Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        [0u64; 2], [0; 2],
+    ));
+    let reader = writer.reader();
+
+    assert_eq!(reader.optimistic_read(|buffer| *buffer), [0, 0]);
+
+    let thread = std::thread::spawn(move || {
+        let mut last = 0;
+        for _ in 0..10_000 {
+            let [a, b] = reader.optimistic_read(|&buffer| buffer);
+            assert_eq!(a, b, "torn read");
+            assert!(a >= last);
+            last = a;
+        }
+    });
+
+    for i in 1..=10_000 {
+        *writer.split_mut().writer = [i, i];
+        writer.try_swap_buffers().unwrap();
+    }
+
+    thread.join().unwrap();
+    assert_eq!(
+        writer.reader().optimistic_read(|buffer| *buffer),
+        [10_000; 2]
+    );
+}
+
+#[test]
+fn test_atomic_copy() {
+    // copied a word at a time
+    let words = [1u64, u64::MAX, 3];
+    // SAFETY: the pointer is from a reference
+    assert_eq!(unsafe { atomic_copy(&words) }, words);
+
+    // copied a byte at a time
+    let bytes = [1u8, 2, 3, 255, 5];
+    // SAFETY: the pointer is from a reference
+    assert_eq!(unsafe { atomic_copy(&bytes) }, bytes);
+    let halves = [1u16, 2, 3];
+    // SAFETY: the pointer is from a reference
+    assert_eq!(unsafe { atomic_copy(&halves) }, halves);
+}
+
+#[test]
+#[cfg(feature = "loom")]
+fn test_loom_optimistic_read() {
+    use crate::strategy::TrackingStrategy;
+
+    loom::model(|| {
+        let shared = crate::raw::Shared::new(
+            TrackingStrategy::new(),
+            crate::raw::RawDBuf::new([0u32; 2], [0; 2]),
+        );
+        let mut writer = crate::raw::Writer::new(crate::ptrs::alloc::Owned::new(shared));
+        let reader = writer.reader();
+
+        let thread = loom::thread::spawn(move || {
+            reader.optimistic_read(|&[a, b]| assert_eq!(a, b, "torn read"))
+        });
+
+        for i in 1..3 {
+            let buffer = writer.split_mut().writer;
+            buffer[0] = i;
+            loom::thread::yield_now();
+            buffer[1] = i;
+            writer.swap_buffers();
+        }
+
+        thread.join().unwrap();
+    })
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+#[cfg(feature = "alloc")]
+fn test_copy_tag_overlapping_guards() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalHazardStrategy::new(),
+        super::RawDBuf::new(0, 0),
+    );
+    let mut writer = super::Writer::new(&mut shared);
+    let mut reader = writer.reader();
+    let mut copy = reader.copy_tag();
+
+    let a = reader.get();
+    let b = copy.get();
+    drop(b);
+    drop(a);
+
+    // both guards released their own node, so nothing is left blocking the swap
+    writer.try_swap_buffers().unwrap();
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_shared_reader() {
+    fn assert_send<T: Send>(_: &T) {}
+
+    let writer = super::Writer::new(crate::ptrs::alloc::Owned::<
+        crate::strategy::HazardStrategy,
+        _,
+    >::from_buffers(0, 0));
+    let reader = writer.reader().into_shared();
+
+    // two guards from the same shared reader at once
+    let a = reader.get();
+    let b = reader.clone().get();
+    assert_send(&a);
+    assert_eq!((*a, *b), (0, 0));
+
+    let mut writer = crate::delayed::DelayedWriter::from(writer);
+    writer.start_buffer_swap();
+    assert!(!writer.is_swap_finished());
+    drop(a);
+    assert!(!writer.is_swap_finished());
+    drop(b);
+    assert!(writer.is_swap_finished());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_pin_epoch() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    let mut writer = super::Writer::new(Owned::<HazardStrategy, _>::from_buffers(0, 0));
+    let mut reader = writer.reader();
+
+    *writer.split_mut().writer = 1;
+    writer.swap_buffers();
+
+    let pin = reader.pin_epoch();
+    assert_eq!(*reader.get_pinned(&pin).unwrap(), 1);
+    // the writer buffer can change without retiring the snapshot
+    *writer.split_mut().writer = 2;
+    assert_eq!(*reader.get_pinned(&pin).unwrap(), 1);
+
+    writer.swap_buffers();
+    assert_eq!(
+        reader.get_pinned(&pin).err(),
+        Some(ReadError::SnapshotRetired(SnapshotRetired))
+    );
+
+    // the pinned buffer is published again, but with different contents
+    *writer.split_mut().writer = 3;
+    writer.swap_buffers();
+    assert_eq!(*reader.get(), 3);
+    assert_eq!(
+        reader.get_pinned(&pin).err(),
+        Some(ReadError::SnapshotRetired(SnapshotRetired))
+    );
+
+    let pin = reader.pin_epoch();
+    assert_eq!(*reader.get_pinned(&pin).unwrap(), 3);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[should_panic = "the pin is from a different double buffer"]
+fn test_pin_other_buffer() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    let a = super::Writer::new(Owned::<HazardStrategy, _>::from_buffers(0, 0));
+    let b = super::Writer::new(Owned::<HazardStrategy, _>::from_buffers(0, 0));
+
+    let pin = a.reader().pin_epoch();
+    let _ = b.reader().get_pinned(&pin);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_pin_retirement_race() {
+    use crate::{ptrs::alloc::Owned, strategy::TrackingStrategy};
+
+    let mut writer = super::Writer::new(Owned::<TrackingStrategy, _>::from_buffers(
+        [0u64; 2], [0; 2],
+    ));
+    let mut reader = writer.reader();
+
+    let thread = std::thread::spawn(move || {
+        for _ in 0..1_000 {
+            let pin = reader.pin_epoch();
+            let Ok(first) = reader.get_pinned(&pin).map(|guard| *guard) else {
+                continue;
+            };
+            assert_eq!(first[0], first[1], "torn read");
+
+            let mut was_retired = false;
+            for _ in 0..10 {
+                match reader.get_pinned(&pin) {
+                    // every guard sees the same snapshot
+                    Ok(guard) => {
+                        assert!(!was_retired, "a retired snapshot came back");
+                        assert_eq!(*guard, first);
+                    }
+                    Err(ReadError::SnapshotRetired(SnapshotRetired)) => was_retired = true,
+                    Err(err) => panic!("{err}"),
+                }
+            }
+        }
+    });
+
+    for i in 1..=10_000 {
+        *writer.split_mut().writer = [i, i];
+        writer.swap_buffers();
+    }
+
+    thread.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "loom")]
+fn test_loom_pin_epoch() {
+    use crate::strategy::TrackingStrategy;
+
+    loom::model(|| {
+        let shared =
+            crate::raw::Shared::new(TrackingStrategy::new(), crate::raw::RawDBuf::new(0u32, 0));
+        let mut writer = crate::raw::Writer::new(crate::ptrs::alloc::Owned::new(shared));
+        let mut reader = writer.reader();
+
+        let thread = loom::thread::spawn(move || {
+            let pin = reader.pin_epoch();
+            let first = reader.get_pinned(&pin).map(|guard| *guard);
+            let second = reader.get_pinned(&pin).map(|guard| *guard);
+            if let Ok(second) = second {
+                assert_eq!(first, Ok(second));
+            }
+        });
+
+        *writer.split_mut().writer = 1;
+        writer.swap_buffers();
+
+        thread.join().unwrap();
+    })
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_try_map_or_else() {
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    let writer = super::Writer::new(Owned::<HazardStrategy, _>::from_buffers(
+        [1, 2, 3],
+        [1, 2, 3],
+    ));
+    let mut reader = writer.reader();
+
+    let guard = reader.get();
+    let Err((guard, err)) = guard.try_map_or_else(|buffer| buffer.get(5).ok_or("out of bounds"))
+    else {
+        panic!("the index is out of bounds")
+    };
+    assert_eq!(err, "out of bounds");
+
+    // the guard is still locked, so a different projection can be tried
+    let mut swap = crate::delayed::DelayedWriter::from(writer);
+    swap.start_buffer_swap();
+    let guard = guard
+        .try_map_or_else(|buffer| buffer.get(1).ok_or("out of bounds"))
+        .ok()
+        .unwrap();
+    assert_eq!(*guard, 2);
+    assert!(!swap.is_swap_finished());
+
+    let guard = guard.filter(|&x| x == 3).err().unwrap();
+    assert!(!swap.is_swap_finished());
+    assert_eq!(guard.filter(|&x| x == 2).ok().as_deref(), Some(&2));
+    assert!(swap.is_swap_finished());
+
+    let guard = reader.into_guard();
+    let (guard, ()) = guard.try_map_or_else(|_| Err::<&u8, _>(())).err().unwrap();
+    let guard = guard.filter(|buffer| buffer.len() == 3).ok().unwrap();
+    let guard = guard
+        .try_map_or_else(|buffer| buffer.last().ok_or(()))
+        .ok()
+        .unwrap();
+    assert_eq!(*guard, 3);
+    assert!(guard.filter(|&x| x == 4).is_err());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_read_error() {
+    use std::{error::Error, string::ToString};
+
+    use crate::{ptrs::alloc::Owned, strategy::HazardStrategy};
+
+    let mut writer = super::Writer::new(Owned::<HazardStrategy, _>::from_buffers(0, 0));
+    let mut weak = writer.reader().into_weak();
+    let pin = weak.try_pin_epoch().unwrap();
+    writer.swap_buffers();
+    assert_eq!(
+        weak.try_get_pinned(&pin).err(),
+        Some(ReadError::SnapshotRetired(SnapshotRetired))
+    );
+
+    // every fallible read of a weak reader fails the same way once the writer is gone
+    drop(writer);
+    assert_eq!(weak.checked_get().err(), Some(ReadError::WriterGone));
+    assert_eq!(weak.try_fork().err(), Some(ReadError::WriterGone));
+    assert_eq!(weak.try_pin_epoch().err(), Some(ReadError::WriterGone));
+    assert_eq!(weak.try_get_pinned(&pin).err(), Some(ReadError::WriterGone));
+    assert_eq!(weak.try_into_guard().err(), Some(ReadError::WriterGone));
+
+    assert_eq!(
+        ReadError::from(crate::ptrs::alloc::UpgradeError),
+        ReadError::WriterGone
+    );
+    assert_eq!(
+        ReadError::from(crate::op::PoisonedError),
+        ReadError::Poisoned
+    );
+    assert_eq!(ReadError::from(Busy), ReadError::Busy(Busy));
+
+    // the payload is the source, and is displayed the same way
+    let err = ReadError::SnapshotRetired(SnapshotRetired);
+    assert_eq!(err.to_string(), SnapshotRetired.to_string());
+    assert!(err.source().unwrap().is::<SnapshotRetired>());
+    assert!(ReadError::WriterGone.source().is_none());
 }
@@ -1,11 +1,25 @@
 //! the writer to a double buffer
 
+use crate::footprint::Footprint;
+#[cfg(feature = "alloc")]
+use crate::interface::ActiveReaderInfo;
 use crate::interface::{
-    BufferOf, CaptureOf, IntoStrongRef, RawBuffers, RawBuffersOf, Strategy, StrategyOf, StrongRef,
-    ValidationErrorOf, WeakOf, Which, WriterTag,
+    BufferOf, CaptureOf, FreshCopy, IntoStrongRef, RawBuffers, RawBuffersOf, Strategy,
+    StrategyIntrospect, StrategyOf, StrongRef, ValidationErrorOf, WeakOf, Which, WhichOf,
+    WriterTag,
 };
+#[cfg(feature = "alloc")]
+use std::vec::Vec;
 
-use super::Reader;
+use core::{
+    slice::{Chunks, ChunksMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{BufferId, Reader};
+
+/// the id of the next writer, used to tie swaps to the writer which started them
+static NEXT_WRITER_ID: AtomicUsize = AtomicUsize::new(0);
 
 /// The writer to a double buffer
 pub struct Writer<S, W = WriterTag<StrategyOf<S>>> {
@@ -13,6 +27,8 @@ pub struct Writer<S, W = WriterTag<StrategyOf<S>>> {
     tag: W,
     /// a strong pointer to the double buffer's shared state
     ptr: S,
+    /// a unique id for this writer
+    id: usize,
 }
 
 /// The two buffers
@@ -35,10 +51,283 @@ pub struct SplitMut<'a, T: ?Sized> {
     pub writer: &'a mut T,
 }
 
-/// The two buffers
+impl<T: ?Sized> Split<'_, T> {
+    /// The identity of the reader buffer
+    pub fn reader_id(&self) -> BufferId {
+        BufferId::new(self.reader)
+    }
+
+    /// The identity of the writer buffer
+    pub fn writer_id(&self) -> BufferId {
+        BufferId::new(self.writer)
+    }
+}
+
+impl<T: ?Sized> SplitMut<'_, T> {
+    /// The identity of the reader buffer
+    pub fn reader_id(&self) -> BufferId {
+        BufferId::new(self.reader)
+    }
+
+    /// The identity of the writer buffer
+    pub fn writer_id(&self) -> BufferId {
+        BufferId::new(&*self.writer)
+    }
+}
+
+impl<'a, T> Split<'a, [T]> {
+    /// iterate over the reader buffer in chunks of `chunk_size` elements
+    ///
+    /// # Panics
+    ///
+    /// if `chunk_size` is zero
+    pub fn reader_chunks(&self, chunk_size: usize) -> Chunks<'a, T> {
+        self.reader.chunks(chunk_size)
+    }
+
+    /// iterate over the writer buffer in chunks of `chunk_size` elements
+    ///
+    /// # Panics
+    ///
+    /// if `chunk_size` is zero
+    pub fn writer_chunks(&self, chunk_size: usize) -> Chunks<'a, T> {
+        self.writer.chunks(chunk_size)
+    }
+}
+
+impl<'a, T> SplitMut<'a, [T]> {
+    /// iterate over the reader buffer in chunks of `chunk_size` elements
+    ///
+    /// # Panics
+    ///
+    /// if `chunk_size` is zero
+    pub fn reader_chunks(&self, chunk_size: usize) -> Chunks<'a, T> {
+        self.reader.chunks(chunk_size)
+    }
+
+    /// iterate mutably over the writer buffer in chunks of `chunk_size` elements
+    ///
+    /// # Panics
+    ///
+    /// if `chunk_size` is zero
+    pub fn writer_chunks_mut(&mut self, chunk_size: usize) -> ChunksMut<'_, T> {
+        self.writer.chunks_mut(chunk_size)
+    }
+}
+
+/// The writer buffer, see [`Writer::split_owned_cache`]
+#[derive(Debug)]
+pub struct WriteHalf<'a, T: ?Sized> {
+    /// the writer buffer
+    buffer: &'a mut T,
+}
+
+/// A token for the published buffer, which can be turned back into a reference
+/// until the next swap, see [`Writer::split_owned_cache`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReadHalfToken {
+    /// the published buffer
+    buffer: BufferId,
+    /// the epoch of the double buffer when the token was created
+    epoch: usize,
+    /// the writer which created the token
+    writer: usize,
+}
+
+impl<T: ?Sized> core::ops::Deref for WriteHalf<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.buffer
+    }
+}
+
+impl<T: ?Sized> core::ops::DerefMut for WriteHalf<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.buffer
+    }
+}
+
+impl<'a, T: ?Sized> WriteHalf<'a, T> {
+    /// The identity of the writer buffer
+    pub fn id(&self) -> BufferId {
+        BufferId::new(&*self.buffer)
+    }
+
+    /// Get the writer buffer for the rest of the borrow
+    pub fn into_mut(self) -> &'a mut T {
+        self.buffer
+    }
+}
+
+impl ReadHalfToken {
+    /// The identity of the published buffer when the token was created
+    pub fn buffer_id(&self) -> BufferId {
+        self.buffer
+    }
+}
+
+/// A buffer which can be split into disjoint mutable views of its fields
+///
+/// see [`project!`](crate::project) for an easy way to implement this
+pub trait FieldSplit<'a> {
+    /// the views of the writer buffer's fields
+    type Views;
+
+    /// split the writer buffer into disjoint views of its fields
+    fn split_fields(writer: &'a mut Self, reader: &'a Self) -> Self::Views;
+}
+
+/// A view of the published buffer and the pending writer buffer at the same time
+///
+/// see [`Writer::diff_guard`]
+#[derive(Debug)]
+pub struct DiffGuard<'a, T: ?Sized> {
+    /// the buffer the readers can see
+    published: &'a T,
+    /// the buffer the writer is modifying
+    pending: &'a T,
+}
+
+/// A reader which was blocking a stuck swap, see [`Writer::finish_swap_reporting`]
+#[cfg(feature = "alloc")]
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct BlockingReader {
+    /// what the strategy knows about the reader, see [`Writer::debug_active_readers`]
+    pub info: ActiveReaderInfo,
+    /// the thread which acquired the read guard, if it was recorded, see [`Writer::debug_guard_thread`]
+    #[cfg(feature = "debug-checks")]
+    pub thread: Option<std::thread::ThreadId>,
+    /// where the read guard was acquired, if it was recorded, see [`Writer::debug_guard_backtrace`]
+    #[cfg(feature = "debug-checks")]
+    pub backtrace: Option<std::sync::Arc<std::backtrace::Backtrace>>,
+}
+
+/// Statistics about how a swap finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapStats {
+    /// the number of times the writer paused while waiting for readers to exit
+    pub pauses: u32,
+    /// true if all readers had already exited the write buffer when the swap was first checked
+    pub finished_immediately: bool,
+}
+
+impl SwapStats {
+    /// the stats of a swap which didn't need to wait for any readers
+    pub const IMMEDIATE: Self = Self {
+        pauses: 0,
+        finished_immediately: true,
+    };
+}
+
+/// Running totals of the [`SwapStats`] of many swaps
+///
+/// Compare two totals to see how many pauses the swaps in between needed on average
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SwapTotals {
+    /// the number of finished swaps
+    pub swaps: u64,
+    /// the number of times the writer paused while waiting for readers to exit, over all swaps
+    pub pauses: u64,
+}
+
+impl SwapTotals {
+    /// the totals before any swap finished
+    pub const ZERO: Self = Self {
+        swaps: 0,
+        pauses: 0,
+    };
+
+    /// add the stats of a finished swap to the totals
+    pub fn add(&mut self, stats: SwapStats) {
+        self.swaps += 1;
+        self.pauses += u64::from(stats.pauses);
+    }
+}
+
+/// An in progress swap
+///
+/// A swap must be finished (or [`defuse`](Swap::defuse)d) before it's dropped,
+/// this is checked when debug assertions are enabled
+#[must_use = "a swap must be finished before the writer can be used again"]
 pub struct Swap<C> {
     /// the capture token which represents all the readers
     capture: C,
+    /// the id of the writer which started the swap
+    owner: usize,
+    /// true if the swap may be dropped, i.e. it's finished or was intentionally abandoned
+    defused: bool,
+}
+
+impl<C> Swap<C> {
+    /// Abandon the swap without waiting for the readers to exit the writer buffer
+    ///
+    /// This only silences the check on drop, it doesn't lift the safety requirements of
+    /// [`Writer::try_start_buffer_swap`]. The readers may still be in the writer buffer, so
+    /// the writer may not call any `&mut self` methods, and should just be dropped.
+    /// This is fine for all strategies in this crate, since their captures don't hold
+    /// anything which the writer needs to release
+    pub fn defuse(mut self) {
+        self.defused = true;
+    }
+
+    /// Allow this swap to be dropped without finishing it
+    ///
+    /// only for swaps which are owned alongside their writer, see [`DelayedWriter`](crate::delayed::DelayedWriter)
+    pub(crate) fn defuse_mut(&mut self) {
+        self.defused = true;
+    }
+}
+
+impl<C> Drop for Swap<C> {
+    fn drop(&mut self) {
+        // don't turn a panic into an abort, dropping the swap while unwinding is fine
+        #[cfg(feature = "std")]
+        if std::thread::panicking() {
+            return;
+        }
+
+        debug_assert!(
+            self.defused,
+            "a swap was dropped before it was finished, use `Swap::defuse` to abandon it"
+        );
+    }
+}
+
+/// The error returned when a [`Swap`] is used with a writer which didn't start it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongWriter;
+
+/// A guard for an in progress swap, see [`Writer::swap_with_guard`]
+pub struct SwapGuard<'a, S: StrongRef> {
+    /// the writer which started the swap
+    writer: &'a Writer<S>,
+    /// the in progress swap
+    swap: &'a mut Swap<CaptureOf<StrategyOf<S>>>,
+}
+
+/// finishes the swap when it's dropped, unless it already finished
+///
+/// This keeps a panic while waiting for the readers from leaving the swap unfinished. Unlike a
+/// `scopeguard`, it doesn't check the swap again once it finished, so the swaps which finished
+/// normally don't pay for it
+struct FinishOnDrop<'a, S: StrongRef> {
+    /// the writer which started the swap
+    writer: &'a Writer<S>,
+    /// the in progress swap
+    swap: Swap<CaptureOf<StrategyOf<S>>>,
+}
+
+impl<S: StrongRef> Drop for FinishOnDrop<'_, S> {
+    fn drop(&mut self) {
+        if !self.swap.defused {
+            // SAFETY: the guard is only created with the writer which started the swap
+            unsafe {
+                self.writer.finish_swap(&mut self.swap);
+            }
+        }
+    }
 }
 
 impl<S: StrongRef> Writer<S> {
@@ -47,7 +336,40 @@ impl<S: StrongRef> Writer<S> {
         // Safety: we just created a strong ref, so this is the first time create writer tag is called
         let tag = unsafe { ptr.get_mut().strategy.create_writer_tag() };
         let ptr = ptr.into_strong();
-        Self { tag, ptr }
+        let id = NEXT_WRITER_ID.fetch_add(1, Ordering::Relaxed);
+        Self { tag, ptr, id }
+    }
+
+    /// try to convert the writer's strong ref into something else, giving back the writer on failure
+    ///
+    /// the writer tag is dropped on success
+    #[cfg(feature = "alloc")]
+    pub(crate) fn try_map_ptr<T>(self, f: impl FnOnce(S) -> Result<T, S>) -> Result<T, Self> {
+        let Self { tag, ptr, id } = self;
+        f(ptr).map_err(|ptr| Self { tag, ptr, id })
+    }
+
+    /// try to convert the writer's strong ref into a strong ref to the same strategy, keeping the writer tag
+    ///
+    /// The new writer gets a new id, since it may manage different buffers
+    ///
+    /// # Safety
+    ///
+    /// on success, the strategy of the new strong ref must be the strategy of the old one (it may have been moved)
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn try_map_ptr_keep_tag<S2: StrongRef<Strategy = StrategyOf<S>>>(
+        self,
+        f: impl FnOnce(S) -> Result<S2, S>,
+    ) -> Result<Writer<S2>, Self> {
+        let Self { tag, ptr, id } = self;
+        match f(ptr) {
+            Ok(ptr) => Ok(Writer {
+                tag,
+                ptr,
+                id: NEXT_WRITER_ID.fetch_add(1, Ordering::Relaxed),
+            }),
+            Err(ptr) => Err(Self { tag, ptr, id }),
+        }
     }
 
     /// Create a new reader to the double buffer
@@ -58,6 +380,29 @@ impl<S: StrongRef> Writer<S> {
         unsafe { Reader::from_raw_parts(tag, S::downgrade(&self.ptr)) }
     }
 
+    /// the flag for which buffer is the writer buffer
+    ///
+    /// `false` means the writer buffer is the first buffer of the [`RawBuffers`]
+    pub fn which(&self) -> bool {
+        // SAFETY: which can't race with `try_start_buffer_swap` because `try_start_buffer_swap`
+        // takes `&mut self` which can't be called at the same time as `&self` methods
+        unsafe { self.ptr.which.load_unsync() }
+    }
+
+    /// true if the writer buffer may be half updated because the writer panicked
+    ///
+    /// This is only ever set by [`PoisonDetect`](crate::poison::PoisonDetect) and [`OpWriter`](crate::op::OpWriter)
+    pub fn is_poisoned(&self) -> bool {
+        self.ptr.poisoned.load()
+    }
+
+    /// set the poison flag which readers can see
+    pub(crate) fn set_poisoned(&mut self, poisoned: bool) {
+        if self.ptr.poisoned.load() != poisoned {
+            self.ptr.poisoned.flip();
+        }
+    }
+
     /// split the writer into the two read-only buffers
     pub fn split(&self) -> Split<'_, BufferOf<RawBuffersOf<S>>> {
         let shared = &*self.ptr;
@@ -78,6 +423,17 @@ impl<S: StrongRef> Writer<S> {
         }
     }
 
+    /// view the published buffer and the pending writer buffer at the same time
+    ///
+    /// This takes `&mut self` so the writer buffer can't be modified while the guard is alive
+    pub fn diff_guard(&mut self) -> DiffGuard<'_, BufferOf<RawBuffersOf<S>>> {
+        let split = self.split_mut();
+        DiffGuard {
+            published: split.reader,
+            pending: split.writer,
+        }
+    }
+
     /// split the writer into the two read-only buffers
     pub fn split_mut(&mut self) -> SplitMut<'_, BufferOf<RawBuffersOf<S>>> {
         let shared = &*self.ptr;
@@ -98,21 +454,101 @@ impl<S: StrongRef> Writer<S> {
         }
     }
 
+    /// split the writer into the writer buffer and a token for the published buffer
+    ///
+    /// Unlike [`split_mut`](Self::split_mut), the token doesn't borrow the writer, so it can be
+    /// stored alongside something derived from the published buffer, like an index. Later on,
+    /// [`published_with_token`](Self::published_with_token) turns the token back into a reference
+    /// to the published buffer, or returns `None` once the buffers were swapped, which means that
+    /// anything derived from the published buffer is out of date.
+    pub fn split_owned_cache(
+        &mut self,
+    ) -> (WriteHalf<'_, BufferOf<RawBuffersOf<S>>>, ReadHalfToken) {
+        let token = self.read_half_token();
+        let split = self.split_mut();
+        (
+            WriteHalf {
+                buffer: split.writer,
+            },
+            token,
+        )
+    }
+
+    /// a token for the published buffer, see [`split_owned_cache`](Self::split_owned_cache)
+    pub fn read_half_token(&self) -> ReadHalfToken {
+        ReadHalfToken {
+            buffer: self.split().reader_id(),
+            epoch: self.epoch(),
+            writer: self.id,
+        }
+    }
+
+    /// The published buffer, if the buffers weren't swapped since `token` was created
+    ///
+    /// This also returns `None` if `token` came from a different writer.
+    /// see [`split_owned_cache`](Self::split_owned_cache)
+    pub fn published_with_token(&self, token: ReadHalfToken) -> Option<&BufferOf<RawBuffersOf<S>>> {
+        let reader = self.split().reader;
+        let is_current = token.writer == self.id
+            && token.epoch == self.epoch()
+            && token.buffer == BufferId::new(reader);
+        is_current.then_some(reader)
+    }
+
+    /// split the writer buffer into disjoint mutable views of its fields, along with the reader buffer
+    ///
+    /// see [`FieldSplit`] for details
+    pub fn split_fields<'a>(&'a mut self) -> <BufferOf<RawBuffersOf<S>> as FieldSplit<'a>>::Views
+    where
+        BufferOf<RawBuffersOf<S>>: FieldSplit<'a>,
+    {
+        let split = self.split_mut();
+        FieldSplit::split_fields(split.writer, split.reader)
+    }
+
     /// Swap the two buffers
     pub fn try_swap_buffers(&mut self) -> Result<(), ValidationErrorOf<StrategyOf<S>>> {
-        // SAFETY: we call `finish_swap`
-        let swap = unsafe { self.try_start_buffer_swap()? };
+        self.try_swap_buffers_with_stats()?;
+        Ok(())
+    }
 
-        // SAFETY: this swap was just created by this writer which means
-        // it was created by this strategy with this writer tag.
-        unsafe {
-            let mut guard =
-                scopeguard::guard((self, swap), |(this, mut swap)| this.finish_swap(&mut swap));
-            let (this, swap) = &mut *guard;
+    /// Swap the two buffers, and report how long the swap had to wait for readers
+    pub fn try_swap_buffers_with_stats(
+        &mut self,
+    ) -> Result<SwapStats, ValidationErrorOf<StrategyOf<S>>> {
+        // SAFETY: the readers are checked right away, and the swap is finished below if they didn't exit yet
+        let mut capture = unsafe { self.start_buffer_swap()? };
+
+        // the fast path: no reader is in the writer buffer, so there's no need for a `Swap` which
+        // tracks the readers, and no need to finish it if a pause panics
+        //
+        // SAFETY: the capture was just created by this writer
+        if unsafe {
+            self.ptr
+                .strategy
+                .have_readers_exited(&self.tag, &mut capture)
+        } {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(
+                epoch = self.epoch(),
+                pauses = 0,
+                finished_immediately = true,
+                "finished buffer swap"
+            );
+
+            return Ok(SwapStats::IMMEDIATE);
+        }
 
-            this.finish_swap(swap)
+        let mut guard = FinishOnDrop {
+            swap: Swap {
+                capture,
+                owner: self.id,
+                defused: false,
+            },
+            writer: self,
         };
-        Ok(())
+        // SAFETY: this swap was just started by this writer
+        Ok(unsafe { guard.writer.finish_swap(&mut guard.swap) })
     }
 
     /// Swap the two buffers
@@ -126,8 +562,66 @@ impl<S: StrongRef> Writer<S> {
         }
     }
 
+    /// Swap the two buffers, only if `pred` accepts the writer buffer
+    ///
+    /// Returns true if the buffers were swapped
+    pub fn try_swap_buffers_if(
+        &mut self,
+        pred: impl FnOnce(&BufferOf<RawBuffersOf<S>>) -> bool,
+    ) -> Result<bool, ValidationErrorOf<StrategyOf<S>>> {
+        if !pred(self.split().writer) {
+            return Ok(false);
+        }
+
+        self.try_swap_buffers()?;
+        Ok(true)
+    }
+
+    /// Swap the two buffers, only if `pred` accepts the writer buffer
+    ///
+    /// Returns true if the buffers were swapped
+    pub fn swap_buffers_if(&mut self, pred: impl FnOnce(&BufferOf<RawBuffersOf<S>>) -> bool) -> bool
+    where
+        StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        match self.try_swap_buffers_if(pred) {
+            Ok(swapped) => swapped,
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// Swap the two buffers, and report how long the swap had to wait for readers
+    pub fn swap_buffers_with_stats(&mut self) -> SwapStats
+    where
+        StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        match self.try_swap_buffers_with_stats() {
+            Ok(stats) => stats,
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// Returns false if no reader was ever created for this double buffer
+    ///
+    /// This may return true after all readers were dropped, and some strategies always
+    /// return true, see [`Strategy::has_readers`]
+    pub fn has_readers(&self) -> bool {
+        self.ptr.strategy.has_readers()
+    }
+
+    /// Returns true if a swap started now may have to wait for readers
+    ///
+    /// This doesn't change anything, so it can be used to decide if this is a good time to swap.
+    /// It's advisory, since a reader may begin a read guard right after this returns false, and
+    /// the swap then waits for that reader. see [`Strategy::any_current_readers`]
+    pub fn would_swap_block(&self) -> bool {
+        self.ptr.strategy.any_current_readers()
+    }
+
     /// try to start a buffer swap
     ///
+    /// see [`Writer::try_swap_with_guard`] for a safe way to do work while the swap is in progress
+    ///
     /// # Safety
     ///
     /// You must either poll `is_swap_finished` until it returns true or
@@ -136,10 +630,87 @@ impl<S: StrongRef> Writer<S> {
     pub unsafe fn try_start_buffer_swap(
         &mut self,
     ) -> Result<Swap<CaptureOf<StrategyOf<S>>>, ValidationErrorOf<StrategyOf<S>>> {
+        // SAFETY: guaranteed by caller
+        let capture = unsafe { self.start_buffer_swap()? };
+
+        Ok(Swap {
+            capture,
+            owner: self.id,
+            defused: false,
+        })
+    }
+
+    /// try to start a buffer swap, where `flip` flips the flag for which buffer is the writer buffer
+    ///
+    /// This is for writers whose flag is shared with other writers, see [`group`](crate::group)
+    ///
+    /// # Safety
+    ///
+    /// * the same as [`Writer::try_start_buffer_swap`]
+    /// * `flip` must flip the writer's flag, and nothing may flip it at the same time
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn try_start_buffer_swap_with(
+        &mut self,
+        flip: impl FnOnce(),
+    ) -> Result<Swap<CaptureOf<StrategyOf<S>>>, ValidationErrorOf<StrategyOf<S>>> {
+        // SAFETY: guaranteed by caller
+        let capture = unsafe { self.start_buffer_swap_with(|_| flip())? };
+
+        Ok(Swap {
+            capture,
+            owner: self.id,
+            defused: false,
+        })
+    }
+
+    /// the flag for which buffer is the writer buffer
+    #[cfg(feature = "alloc")]
+    pub(crate) fn which_flag(&self) -> &WhichOf<StrategyOf<S>> {
+        &self.ptr.which
+    }
+
+    /// flip the buffers, and capture the readers which may still be in the writer buffer
+    ///
+    /// # Safety
+    ///
+    /// You must poll `have_readers_exited` with the capture until it returns true
+    /// before calling any other methods that take `&mut self`
+    #[inline]
+    unsafe fn start_buffer_swap(
+        &mut self,
+    ) -> Result<CaptureOf<StrategyOf<S>>, ValidationErrorOf<StrategyOf<S>>> {
+        // SAFETY: guaranteed by caller, and `flip` flips the flag
+        unsafe { self.start_buffer_swap_with(Which::flip) }
+    }
+
+    /// flip the buffers with `flip`, and capture the readers which may still be in the writer buffer
+    ///
+    /// # Safety
+    ///
+    /// * You must poll `have_readers_exited` with the capture until it returns true
+    ///   before calling any other methods that take `&mut self`
+    /// * `flip` must flip the flag, and nothing may flip it at the same time
+    #[inline]
+    unsafe fn start_buffer_swap_with(
+        &mut self,
+        flip: impl FnOnce(&WhichOf<StrategyOf<S>>),
+    ) -> Result<CaptureOf<StrategyOf<S>>, ValidationErrorOf<StrategyOf<S>>> {
         let shared = &*self.ptr;
+        debug_assert!(
+            shared.buffers.halves_interchangeable(),
+            "tried to swap a double buffer whose halves have different shapes, see `RawBuffers::halves_interchangeable`"
+        );
         let validation_token = shared.strategy.validate_swap(&mut self.tag)?;
 
-        shared.which.flip();
+        flip(&shared.which);
+        // tell optimistic readers that the buffer they're copying may be written to.
+        // This acquires the re-check of any optimistic read which still saw the old epoch,
+        // so its copy happens before the writes to the new writer buffer, and releases the
+        // writes before the swap to readers which load the new epoch
+        shared.epoch.fetch_add(1, Ordering::AcqRel);
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(epoch = self.epoch(), "started buffer swap");
 
         // SAFETY:
         //
@@ -147,50 +718,908 @@ impl<S: StrongRef> Writer<S> {
         //      * we flip the buffers in between calling `validate_swap` and `capture_readers`
         // * Must poll `have_readers_exited` until it returns true before calling `validate_swap` again
         //      * guarnteed by caller
-        let capture = unsafe {
+        Ok(unsafe {
             shared
                 .strategy
                 .capture_readers(&mut self.tag, validation_token)
+        })
+    }
+
+    /// flip the buffers back after [`Writer::try_start_buffer_swap`], so readers see the previous buffer again
+    ///
+    /// # Safety
+    ///
+    /// The last swap must have flipped the buffers. The writer buffer may still be read by readers
+    /// which started while it was published, so it must not be written to until every reader
+    /// which holds a read guard afterwards has exited, see [`DelayedWriter::revert_pending_swap`](crate::delayed::DelayedWriter::revert_pending_swap)
+    pub(crate) unsafe fn revert_buffer_swap(&mut self) {
+        let shared = &*self.ptr;
+
+        shared.which.flip();
+        // the writer buffer may be written to again, so optimistic readers have to retry,
+        // this orders their copies the same way as the increment in `start_buffer_swap_with`
+        shared.epoch.fetch_add(1, Ordering::AcqRel);
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(epoch = self.epoch(), "reverted buffer swap");
+    }
+
+    /// try to start waiting for every reader which currently holds a read guard, without swapping the buffers
+    ///
+    /// The returned swap can be polled and finished just like the one from [`Writer::try_start_buffer_swap`].
+    /// Once it's finished, every read guard which was acquired before this call has been dropped
+    ///
+    /// # Safety
+    ///
+    /// You must either poll `is_swap_finished` until it returns true or
+    /// call `finish_swap` with the `swap` before calling any other methods
+    /// that take `&mut self`
+    pub unsafe fn try_start_quiescence(
+        &mut self,
+    ) -> Result<Swap<CaptureOf<StrategyOf<S>>>, ValidationErrorOf<StrategyOf<S>>> {
+        let shared = &*self.ptr;
+        let validation_token = shared.strategy.validate_swap(&mut self.tag)?;
+
+        // SAFETY:
+        //
+        // * The validation token must have come from a call to `validate_swap` right before this
+        //      * we just called `validate_swap`
+        // * Must poll `have_readers_exited` until it returns true before calling `validate_swap` again
+        //      * guarnteed by caller
+        let capture = unsafe {
+            shared
+                .strategy
+                .capture_current_readers(&mut self.tag, validation_token)
         };
 
-        Ok(Swap { capture })
+        Ok(Swap {
+            capture,
+            owner: self.id,
+            defused: false,
+        })
+    }
+
+    /// Wait until every read guard which was acquired before this call has been dropped
+    ///
+    /// This is a quiescence barrier: after a publish, it proves that no reader can still reference
+    /// anything which is only reachable from the previous version. Unlike swapping twice, this
+    /// doesn't flip the buffers
+    pub fn try_wait_for_quiescence(
+        &mut self,
+    ) -> Result<SwapStats, ValidationErrorOf<StrategyOf<S>>> {
+        // SAFETY: the swap is finished below
+        let swap = unsafe { self.try_start_quiescence()? };
+
+        let mut guard = FinishOnDrop { writer: self, swap };
+        // SAFETY: this swap was just started by this writer
+        Ok(unsafe { guard.writer.finish_swap(&mut guard.swap) })
+    }
+
+    /// Wait until every read guard which was acquired before this call has been dropped
+    ///
+    /// see [`Writer::try_wait_for_quiescence`]
+    pub fn wait_for_quiescence(&mut self) -> SwapStats
+    where
+        StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        match self.try_wait_for_quiescence() {
+            Ok(stats) => stats,
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// Swap the two buffers, and run `f` while waiting for the readers to exit the writer buffer
+    ///
+    /// The swap is always finished before this returns (even if `f` panics)
+    pub fn try_swap_with_guard<R>(
+        &mut self,
+        f: impl FnOnce(&mut SwapGuard<'_, S>) -> R,
+    ) -> Result<R, ValidationErrorOf<StrategyOf<S>>> {
+        // SAFETY: the swap is finished when the guard below is dropped
+        let swap = unsafe { self.try_start_buffer_swap()? };
+
+        let mut guard = FinishOnDrop { writer: self, swap };
+
+        Ok(f(&mut SwapGuard {
+            writer: guard.writer,
+            swap: &mut guard.swap,
+        }))
+    }
+
+    /// Swap the two buffers, and run `f` while waiting for the readers to exit the writer buffer
+    ///
+    /// The swap is always finished before this returns (even if `f` panics)
+    pub fn swap_with_guard<R>(&mut self, f: impl FnOnce(&mut SwapGuard<'_, S>) -> R) -> R
+    where
+        StrategyOf<S>: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        match self.try_swap_with_guard(f) {
+            Ok(value) => value,
+            Err(inf) => match inf {},
+        }
     }
 
     /// Check if all readers have exited the write buffer
     ///
+    /// returns an error if the swap wasn't started by this writer
+    pub fn poll_swap(
+        &self,
+        swap: &mut Swap<CaptureOf<StrategyOf<S>>>,
+    ) -> Result<bool, WrongWriter> {
+        if swap.owner != self.id {
+            return Err(WrongWriter);
+        }
+
+        // SAFETY: the swap was created by this writer
+        Ok(unsafe { self.is_swap_finished(swap) })
+    }
+
+    /// Wait until all readers have exited the write buffer
+    ///
+    /// returns an error if the swap wasn't started by this writer
+    pub fn block_on_swap(
+        &self,
+        swap: &mut Swap<CaptureOf<StrategyOf<S>>>,
+    ) -> Result<SwapStats, WrongWriter> {
+        if swap.owner != self.id {
+            return Err(WrongWriter);
+        }
+
+        // SAFETY: the swap was created by this writer
+        Ok(unsafe { self.finish_swap(swap) })
+    }
+
+    /// Wait until all readers have exited the write buffer, or until `should_continue` returns false
+    ///
+    /// returns an error if the swap wasn't started by this writer,
+    /// see [`Writer::finish_swap_until`] for details
+    pub fn block_on_swap_until(
+        &self,
+        swap: &mut Swap<CaptureOf<StrategyOf<S>>>,
+        should_continue: impl FnMut() -> bool,
+    ) -> Result<bool, WrongWriter> {
+        if swap.owner != self.id {
+            return Err(WrongWriter);
+        }
+
+        // SAFETY: the swap was created by this writer
+        Ok(unsafe { self.finish_swap_until(swap, should_continue) })
+    }
+
+    /// Wait until all readers have exited the write buffer, reporting the active readers
+    /// if the swap is stuck
+    ///
+    /// returns an error if the swap wasn't started by this writer,
+    /// see [`Writer::finish_swap_reporting`] for details
+    #[cfg(feature = "alloc")]
+    pub fn block_on_swap_reporting(
+        &self,
+        swap: &mut Swap<CaptureOf<StrategyOf<S>>>,
+        stuck_after: u32,
+        report: impl FnOnce(Vec<BlockingReader>),
+    ) -> Result<SwapStats, WrongWriter>
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        if swap.owner != self.id {
+            return Err(WrongWriter);
+        }
+
+        // SAFETY: the swap was created by this writer
+        Ok(unsafe { self.finish_swap_reporting(swap, stuck_after, report) })
+    }
+
+    /// The readers which currently hold a read guard to either buffer
+    ///
+    /// useful for finding out which readers are blocking a stuck swap
+    #[cfg(feature = "alloc")]
+    pub fn debug_active_readers(&self) -> Vec<ActiveReaderInfo>
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        let mut readers = Vec::new();
+        self.ptr.strategy.active_readers(|info| readers.push(info));
+        readers
+    }
+
+    /// Where a reader from [`debug_active_readers`](Self::debug_active_readers) acquired its read guard
+    ///
+    /// This is only recorded while backtraces are captured, see [`debug_checks`](crate::debug_checks)
+    #[cfg(feature = "debug-checks")]
+    pub fn debug_guard_backtrace(
+        &self,
+        reader: &ActiveReaderInfo,
+    ) -> Option<std::sync::Arc<std::backtrace::Backtrace>>
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        self.ptr.strategy.guard_backtrace(reader)
+    }
+
+    /// Which thread acquired the read guard of a reader from [`debug_active_readers`](Self::debug_active_readers)
+    ///
+    /// This is only recorded while backtraces are captured, see [`debug_checks`](crate::debug_checks)
+    #[cfg(feature = "debug-checks")]
+    pub fn debug_guard_thread(&self, reader: &ActiveReaderInfo) -> Option<std::thread::ThreadId>
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        self.ptr.strategy.guard_thread(reader)
+    }
+
+    /// The readers which currently hold a read guard, with everything the strategy recorded about them
+    ///
+    /// see [`debug_active_readers`](Self::debug_active_readers) and [`finish_swap_reporting`](Self::finish_swap_reporting)
+    #[cfg(feature = "alloc")]
+    pub fn debug_blocking_readers(&self) -> Vec<BlockingReader>
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        let mut readers = Vec::new();
+        self.ptr.strategy.active_readers(|info| {
+            readers.push(BlockingReader {
+                info,
+                #[cfg(feature = "debug-checks")]
+                thread: self.ptr.strategy.guard_thread(&info),
+                #[cfg(feature = "debug-checks")]
+                backtrace: self.ptr.strategy.guard_backtrace(&info),
+            })
+        });
+        readers
+    }
+
+    /// A new strategy with the same configuration as this writer's strategy, see [`FreshCopy`]
+    ///
+    /// This is useful to rebuild the double buffer, while the old readers keep using this strategy
+    pub fn fresh_strategy(&self) -> StrategyOf<S>
+    where
+        StrategyOf<S>: FreshCopy,
+    {
+        self.ptr.strategy.fresh_copy()
+    }
+
+    /// Let the strategy clean up after readers which were dropped
+    ///
+    /// Some strategies only do this while swapping, so this is useful for writers which
+    /// rarely swap but have many short lived readers. see [`StrategyIntrospect::maintain`]
+    pub fn maintain(&self)
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        self.ptr.strategy.maintain()
+    }
+
+    /// How many bytes the two buffers and the strategy hold, see [`footprint`](crate::footprint)
+    ///
+    /// `heap_bytes` is called with each buffer, and returns how many bytes it owns on the heap.
+    /// Use [`MemoryFootprint::footprint`](crate::footprint::MemoryFootprint::footprint)
+    /// if the buffer implements [`MemoryUsage`](crate::footprint::MemoryUsage)
+    pub fn footprint_with(
+        &self,
+        mut heap_bytes: impl FnMut(&BufferOf<RawBuffersOf<S>>) -> usize,
+    ) -> Footprint
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        let split = self.split();
+        Footprint {
+            buffers: core::mem::size_of_val(split.writer)
+                + core::mem::size_of_val(split.reader)
+                + heap_bytes(split.writer)
+                + heap_bytes(split.reader),
+            op_log: 0,
+            strategy: core::mem::size_of::<StrategyOf<S>>() + self.ptr.strategy.bookkeeping_bytes(),
+        }
+    }
+
+    /// Check if all readers have exited the write buffer
+    ///
+    /// see [`Writer::poll_swap`] for a safe version
+    ///
     /// # Safety
     ///
     /// the swap should have been created by `self`
     pub unsafe fn is_swap_finished(&self, swap: &mut Swap<CaptureOf<StrategyOf<S>>>) -> bool {
         // SAFETY: this swap was created by this writer which means
         // it was created by this strategy with this writer tag.
-        unsafe {
+        let finished = unsafe {
             self.ptr
                 .strategy
                 .have_readers_exited(&self.tag, &mut swap.capture)
+        };
+        swap.defused |= finished;
+        finished
+    }
+
+    /// Wait until all readers have exited the write buffer
+    ///
+    /// see [`Writer::block_on_swap`] for a safe version
+    ///
+    /// # Safety
+    ///
+    /// the swap should have been created by `self`
+    pub unsafe fn finish_swap(&self, swap: &mut Swap<CaptureOf<StrategyOf<S>>>) -> SwapStats {
+        // SAFETY: guaranteed by caller
+        let stats = if unsafe { self.is_swap_finished(swap) } {
+            SwapStats::IMMEDIATE
+        } else {
+            SwapStats {
+                pauses: self.finish_swap_slow(swap),
+                finished_immediately: false,
+            }
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            epoch = self.epoch(),
+            pauses = stats.pauses,
+            finished_immediately = stats.finished_immediately,
+            "finished buffer swap"
+        );
+
+        stats
+    }
+
+    /// Wait until all readers have exited the write buffer, or until `should_continue` returns false
+    ///
+    /// `should_continue` is checked between each pause. Returns true if the swap finished,
+    /// otherwise the swap is still in progress and can be resumed later, or abandoned with
+    /// [`Swap::defuse`] and then dropping the writer.
+    ///
+    /// see [`Writer::block_on_swap_until`] for a safe version
+    ///
+    /// # Safety
+    ///
+    /// the swap should have been created by `self`
+    pub unsafe fn finish_swap_until(
+        &self,
+        swap: &mut Swap<CaptureOf<StrategyOf<S>>>,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> bool {
+        let mut pause = Default::default();
+        // SAFETY: guaranteed by caller
+        while !unsafe { self.is_swap_finished(swap) } {
+            if !should_continue() {
+                return false;
+            }
+
+            self.ptr.strategy.pause(&self.tag, &mut pause);
         }
+        true
     }
 
-    /// Check if all readers have exited the write buffer
+    /// Wait until all readers have exited the write buffer
+    ///
+    /// if the swap still isn't finished after `stuck_after` pauses, then `report` is called once
+    /// with the readers which currently hold a read guard, and the threads which hold them and where
+    /// they acquired them if the strategy recorded them (see [`Writer::debug_blocking_readers`])
+    ///
+    /// see [`Writer::block_on_swap_reporting`] for a safe version
     ///
     /// # Safety
     ///
     /// the swap should have been created by `self`
-    pub unsafe fn finish_swap(&self, swap: &mut Swap<CaptureOf<StrategyOf<S>>>) {
+    #[cfg(feature = "alloc")]
+    pub unsafe fn finish_swap_reporting(
+        &self,
+        swap: &mut Swap<CaptureOf<StrategyOf<S>>>,
+        stuck_after: u32,
+        report: impl FnOnce(Vec<BlockingReader>),
+    ) -> SwapStats
+    where
+        StrategyOf<S>: StrategyIntrospect,
+    {
+        let mut report = Some(report);
+        let mut pause = Default::default();
+        let mut pauses = 0_u32;
         // SAFETY: guaranteed by caller
-        if !unsafe { self.is_swap_finished(swap) } {
-            self.finish_swap_slow(swap)
+        while !unsafe { self.is_swap_finished(swap) } {
+            if pauses >= stuck_after {
+                if let Some(report) = report.take() {
+                    report(self.debug_blocking_readers());
+                }
+            }
+
+            self.ptr.strategy.pause(&self.tag, &mut pause);
+            pauses = pauses.saturating_add(1);
+        }
+
+        SwapStats {
+            pauses,
+            finished_immediately: pauses == 0,
         }
     }
 
     #[cold]
     #[inline(never)]
     /// Drop slow to reduce the code size of `finish_swap`
-    fn finish_swap_slow(&self, swap: &mut Swap<CaptureOf<StrategyOf<S>>>) {
+    ///
+    /// returns the number of pauses
+    fn finish_swap_slow(&self, swap: &mut Swap<CaptureOf<StrategyOf<S>>>) -> u32 {
+        #[cfg(all(feature = "tracing", feature = "std"))]
+        let mut slow_writer = crate::trace::SlowWriter::new();
         let mut pause = Default::default();
+        let mut pauses = 0_u32;
         // SAFETY: guaranteed by caller
         while !unsafe { self.is_swap_finished(swap) } {
-            self.ptr.strategy.pause(&self.tag, &mut pause)
+            self.ptr.strategy.pause(&self.tag, &mut pause);
+            pauses = pauses.saturating_add(1);
+
+            // the strategies back off further the longer they wait, so only report
+            // when the number of pauses doubles
+            #[cfg(feature = "tracing")]
+            if pauses.is_power_of_two() {
+                tracing::debug!(epoch = self.epoch(), pauses, "still waiting for readers");
+            }
+
+            #[cfg(all(feature = "tracing", feature = "std"))]
+            slow_writer.check(self.epoch(), pauses);
+        }
+        pauses
+    }
+
+    /// the number of swaps started by this writer's double buffer, used to tie events together
+    /// and to check [`ReadHalfToken`]s
+    fn epoch(&self) -> usize {
+        // only the writer increments the epoch, so this is always up to date
+        self.ptr.epoch.load(Ordering::Relaxed)
+    }
+}
+
+impl<'a, T: ?Sized> DiffGuard<'a, T> {
+    /// the buffer the readers can see
+    pub fn published(&self) -> &'a T {
+        self.published
+    }
+
+    /// the buffer the writer is modifying
+    pub fn pending(&self) -> &'a T {
+        self.pending
+    }
+}
+
+impl<S: StrongRef> SwapGuard<'_, S> {
+    /// the writer which started the swap
+    pub fn writer(&self) -> &Writer<S> {
+        self.writer
+    }
+
+    /// Check if all readers have exited the write buffer
+    pub fn poll(&mut self) -> bool {
+        // SAFETY: the swap guard is only created with the writer which started the swap
+        unsafe { self.writer.is_swap_finished(self.swap) }
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_swap_stats() {
+    use crate::strategy::mock::MockStrategy;
+
+    let mock = MockStrategy::new();
+    let mut shared = super::Shared::from_raw_parts(mock.clone(), super::RawDBuf::new(0, 0));
+    let mut writer = Writer::new(&mut shared);
+
+    let stats = writer.swap_buffers_with_stats();
+    assert!(stats.finished_immediately);
+    assert_eq!(stats.pauses, 0);
+
+    // a reader which stays in the writer buffer while the writer pauses twice
+    mock.hold_next_capture_for(2);
+    let stats = writer.swap_buffers_with_stats();
+
+    assert!(!stats.finished_immediately);
+    assert_eq!(stats.pauses, 2);
+}
+
+#[test]
+#[cfg(feature = "std")]
+// `OwnedWithWeak` needs `Weak`, which loom doesn't have
+#[cfg(not(feature = "loom"))]
+fn test_swap_owner() {
+    use crate::{ptrs::alloc::OwnedWithWeak, strategy::TrackingStrategy};
+
+    let mut writer = Writer::new(OwnedWithWeak::<TrackingStrategy, _>::from_buffers(0, 0));
+    let other = Writer::new(OwnedWithWeak::<TrackingStrategy, _>::from_buffers(0, 0));
+    let mut reader = writer.reader();
+
+    let guard = reader.checked_get().unwrap();
+    // SAFETY: the swap is finished before calling any other `&mut self` methods
+    let mut swap = unsafe { writer.try_start_buffer_swap().unwrap() };
+
+    assert_eq!(other.poll_swap(&mut swap), Err(WrongWriter));
+    assert_eq!(other.block_on_swap(&mut swap), Err(WrongWriter));
+    assert_eq!(writer.poll_swap(&mut swap), Ok(false));
+
+    drop(guard);
+    assert_eq!(writer.poll_swap(&mut swap), Ok(true));
+    assert!(writer.block_on_swap(&mut swap).is_ok());
+
+    let guard = reader.checked_get().unwrap();
+    let value = writer.swap_with_guard(move |swap| {
+        assert!(!swap.poll());
+        assert_eq!(*swap.writer().split().reader, 0);
+        drop(guard);
+        assert!(swap.poll());
+        10
+    });
+    assert_eq!(value, 10);
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_diff_guard() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalStrategy::new(),
+        super::RawDBuf::new(0, 0),
+    );
+    let mut writer = Writer::new(&mut shared);
+
+    *writer.split_mut().writer = 10;
+
+    let diff = writer.diff_guard();
+    assert_eq!(*diff.published(), 0);
+    assert_eq!(*diff.pending(), 10);
+
+    writer.try_swap_buffers().unwrap();
+
+    let diff = writer.diff_guard();
+    assert_eq!(*diff.published(), 10);
+    assert_eq!(*diff.pending(), 0);
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_split_fields() {
+    /// a buffer with independent parts
+    #[derive(Debug, PartialEq)]
+    struct World {
+        /// the positions of things
+        positions: [u32; 2],
+        /// the number of things
+        count: u32,
+    }
+
+    crate::project! {
+        /// the views into [`World`]
+        struct WorldViews<'a> for World {
+            positions: [u32; 2],
+            count: u32,
         }
     }
+
+    let world = || World {
+        positions: [0, 0],
+        count: 0,
+    };
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalStrategy::new(),
+        super::RawDBuf::new(world(), world()),
+    );
+    let mut writer = Writer::new(&mut shared);
+
+    let views = writer.split_fields();
+    let WorldViews {
+        positions,
+        count,
+        reader,
+    } = views;
+
+    // both fields can be borrowed mutably at the same time
+    positions[1] = 10;
+    *count += 1;
+    assert_eq!(*reader, world());
+
+    let split = writer.split();
+    assert_eq!(split.writer.positions, [0, 10]);
+    assert_eq!(split.writer.count, 1);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_debug_active_readers() {
+    use std::sync::mpsc;
+
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::HazardStrategy::new(),
+        super::RawDBuf::new(0, 0),
+    );
+    let mut writer = Writer::new(&mut shared);
+    let mut reader = writer.reader();
+    assert!(writer.debug_active_readers().is_empty());
+
+    let guard = reader.get();
+    let active = writer.debug_active_readers();
+    assert_eq!(active.len(), 1);
+    drop(guard);
+    assert!(writer.debug_active_readers().is_empty());
+
+    let (held_tx, held_rx) = mpsc::channel();
+    let (release_tx, release_rx) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            let _guard = reader.get();
+            held_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+
+        held_rx.recv().unwrap();
+
+        // SAFETY: the swap is finished below
+        let mut swap = unsafe { writer.try_start_buffer_swap() }.unwrap();
+        let stats = writer
+            .block_on_swap_reporting(&mut swap, 2, |readers| {
+                // the same reader node is reused by the blocking reader
+                let readers = readers.iter().map(|reader| reader.info).collect::<Vec<_>>();
+                assert_eq!(readers, active);
+                release_tx.send(()).unwrap();
+            })
+            .unwrap();
+        assert!(stats.pauses >= 2);
+    });
+
+    assert!(writer.debug_active_readers().is_empty());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_wait_for_quiescence() {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    };
+
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::HazardStrategy::new(),
+        super::RawDBuf::new(0, 1),
+    );
+    let mut writer = Writer::new(&mut shared);
+    let mut reader = writer.reader();
+
+    // no readers, so this finishes immediately
+    assert!(writer.wait_for_quiescence().finished_immediately);
+
+    let which = writer.which();
+    let released = AtomicBool::new(false);
+    let (held_tx, held_rx) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let guard = reader.get();
+            held_tx.send(()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            released.store(true, Ordering::Relaxed);
+            drop(guard);
+        });
+
+        held_rx.recv().unwrap();
+        writer.wait_for_quiescence();
+        assert!(released.load(Ordering::Relaxed));
+    });
+
+    // the buffers weren't swapped
+    assert_eq!(writer.which(), which);
+    assert_eq!(*writer.split().reader, 1);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_abandoned_swap() {
+    use crate::strategy::{
+        AdaptiveStrategy, HazardStrategy, LocalHazardStrategy, LocalStrategy,
+        LocalTrackingStrategy, TrackingStrategy,
+    };
+
+    /// start a swap while a reader is reading, then abandon it and drop the writer
+    macro_rules! abandon {
+        ($strategy:ty) => {{
+            let mut shared =
+                super::Shared::from_raw_parts(<$strategy>::default(), super::RawDBuf::new(0, 1));
+            let writer = Writer::new(&mut shared);
+            let mut reader = writer.reader();
+
+            let guard = reader.get();
+            // the writer is moved into this block, so it's dropped right after the swap is abandoned
+            let swapped = {
+                let mut writer = writer;
+                // SAFETY: the writer is dropped right after the swap is abandoned
+                match unsafe { writer.try_start_buffer_swap() } {
+                    Ok(mut swap) => {
+                        assert_eq!(writer.poll_swap(&mut swap), Ok(false));
+                        swap.defuse();
+                        true
+                    }
+                    // local strategies refuse to swap while there are readers
+                    Err(_) => false,
+                }
+            };
+
+            assert_eq!(*guard, 1);
+            drop(guard);
+            assert_eq!(*reader.get(), if swapped { 0 } else { 1 });
+        }};
+    }
+
+    abandon!(LocalStrategy);
+    abandon!(LocalTrackingStrategy);
+    abandon!(LocalHazardStrategy);
+    abandon!(TrackingStrategy);
+    abandon!(HazardStrategy);
+    abandon!(AdaptiveStrategy);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[cfg(debug_assertions)]
+#[should_panic = "a swap was dropped before it was finished"]
+fn test_dropped_swap() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::TrackingStrategy::new(),
+        super::RawDBuf::new(0, 1),
+    );
+    let mut writer = Writer::new(&mut shared);
+    let mut reader = writer.reader();
+
+    let _guard = reader.get();
+    // SAFETY: the writer isn't used after the swap is dropped
+    let swap = unsafe { writer.try_start_buffer_swap() };
+    drop(swap);
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_swap_buffers_if() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::LocalStrategy::new(),
+        super::RawDBuf::new(0, 0),
+    );
+    let mut writer = Writer::new(&mut shared);
+    let mut reader = writer.reader();
+
+    *writer.split_mut().writer = -1;
+    assert!(matches!(writer.try_swap_buffers_if(|&x| x >= 0), Ok(false)));
+    assert_eq!(*reader.get(), 0);
+
+    *writer.split_mut().writer = 1;
+    assert!(matches!(writer.try_swap_buffers_if(|&x| x >= 0), Ok(true)));
+    assert_eq!(*reader.get(), 1);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_has_readers() {
+    use crate::{
+        delayed::DelayedWriter,
+        ptrs::alloc::Owned,
+        strategy::{HazardStrategy, TrackingStrategy},
+    };
+
+    /// swap without readers, then check that a new reader is still waited on
+    fn check<S: crate::interface::Strategy<ValidationError = core::convert::Infallible>>(
+        strategy: S,
+    ) {
+        let mut writer = Writer::new(Owned::new(super::Shared::from_raw_parts(
+            strategy,
+            super::RawDBuf::new(0, 0),
+        )));
+        assert!(!writer.has_readers());
+        for i in 1..=3 {
+            *writer.split_mut().writer = i;
+            writer.swap_buffers();
+        }
+        assert!(!writer.has_readers());
+
+        let mut reader = writer.reader();
+        assert!(writer.has_readers());
+
+        let mut writer = DelayedWriter::from(writer);
+        let guard = reader.get();
+        assert_eq!(*guard, 3);
+        writer.start_buffer_swap();
+        assert!(!writer.is_swap_finished());
+        drop(guard);
+        assert!(writer.is_swap_finished());
+
+        // dropped readers may still count
+        drop(reader);
+        assert!(writer.has_readers());
+    }
+
+    check(TrackingStrategy::new());
+    check(HazardStrategy::new());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_would_swap_block() {
+    use crate::strategy::{
+        AdaptiveStrategy, HazardStrategy, LocalHazardStrategy, LocalStrategy,
+        LocalTrackingStrategy, TrackingStrategy,
+    };
+
+    fn check<S: Strategy>(strategy: S) {
+        let mut shared = super::Shared::from_raw_parts(strategy, super::RawDBuf::new(0, 0));
+        let mut writer = Writer::new(&mut shared);
+        assert!(!writer.would_swap_block());
+
+        // readers without a read guard don't block
+        let mut reader = writer.reader();
+        let mut other = writer.reader();
+        assert!(!writer.would_swap_block());
+
+        let guard = reader.get();
+        assert!(writer.would_swap_block());
+        let other_guard = other.get();
+        drop(guard);
+        assert!(writer.would_swap_block());
+        drop(other_guard);
+        assert!(!writer.would_swap_block());
+
+        // only swapping when it wouldn't block never waits on the guard held by this thread
+        let mut swaps = 0;
+        for i in 0..6 {
+            let guard = (i % 2 == 1).then(|| reader.get());
+            if !writer.would_swap_block() {
+                assert!(writer.try_swap_buffers().is_ok());
+                swaps += 1;
+            }
+            drop(guard);
+        }
+        assert_eq!(swaps, 3);
+    }
+
+    check(LocalStrategy::new());
+    check(LocalHazardStrategy::new());
+    check(LocalTrackingStrategy::new());
+    check(TrackingStrategy::new());
+    check(HazardStrategy::new());
+    check(AdaptiveStrategy::new());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_split_owned_cache() {
+    let mut shared = super::Shared::from_raw_parts(
+        crate::strategy::TrackingStrategy::new(),
+        super::RawDBuf::new(std::vec![1, 2], std::vec![1, 2]),
+    );
+    let mut writer = Writer::new(&mut shared);
+
+    let (mut write_half, token) = writer.split_owned_cache();
+    write_half.push(3);
+    // the token is still valid after the writer buffer was changed
+    assert_eq!(writer.published_with_token(token), Some(&std::vec![1, 2]));
+    assert_eq!(writer.read_half_token(), token);
+
+    writer.swap_buffers();
+    assert_eq!(writer.published_with_token(token), None);
+    let token = writer.read_half_token();
+    assert_eq!(
+        writer.published_with_token(token),
+        Some(&std::vec![1, 2, 3])
+    );
+
+    // tokens from other writers are rejected, even if they happen to match
+    let mut other = super::Shared::from_raw_parts(
+        crate::strategy::TrackingStrategy::new(),
+        super::RawDBuf::new(0, 0),
+    );
+    let other = Writer::new(&mut other);
+    assert_eq!(
+        other.published_with_token(ReadHalfToken {
+            writer: other.id,
+            ..token
+        }),
+        None
+    );
+    assert!(other.published_with_token(token).is_none());
 }
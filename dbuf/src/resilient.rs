@@ -0,0 +1,245 @@
+//! A reader which keeps serving the last snapshot it read after the double buffer is gone
+//!
+//! A weak reader fails to read once the double buffer is dropped, for example while its owner
+//! is being redeployed. For something like a dashboard, showing the last known value is better
+//! than showing an error. [`ResilientReader`] keeps a clone of the last buffer it read, and
+//! returns it marked as stale if the reader can't read anymore.
+//!
+//! Every successful read clones the buffer, while holding the read guard. Cloning a large
+//! buffer on every call is expensive, and keeps the writer from finishing a swap for longer,
+//! so with [`set_refresh_if_epoch_changed`](ResilientReader::set_refresh_if_epoch_changed)
+//! the buffer is only cloned after a different snapshot was published (see [`Reader::pin_epoch`]).
+//!
+//! Each resilient reader keeps one clone of the buffer, which is reused by the next clone
+//! unless a [`Snapshot`] was turned into an [`Arc`]. After the double buffer is dropped,
+//! that clone is the only copy of the buffer which is kept alive.
+//!
+//! ```
+//! use dbuf::{ptrs::alloc::OwnedWithWeak, raw::Writer, resilient::ResilientReader, strategy::HazardStrategy};
+//!
+//! let mut writer = Writer::new(OwnedWithWeak::<HazardStrategy, _>::from_buffers(1, 1));
+//! let mut reader = ResilientReader::new(writer.reader()).unwrap();
+//!
+//! *writer.split_mut().writer = 2;
+//! writer.swap_buffers();
+//! assert_eq!(*reader.get_or_last(), 2);
+//!
+//! *writer.split_mut().writer = 3;
+//! writer.swap_buffers();
+//! drop(writer);
+//!
+//! let snapshot = reader.get_or_last();
+//! assert_eq!(*snapshot, 2);
+//! assert!(snapshot.is_stale());
+//! ```
+
+use core::ops::Deref;
+use std::sync::Arc;
+
+use crate::{
+    interface::{RawBuffers, RawBuffersOf, StrongOf, WeakRef},
+    raw::{EpochPin, ReadError, Reader},
+};
+
+/// A reader which falls back to the last snapshot it read, see the [module docs](self)
+pub struct ResilientReader<W: WeakRef, B> {
+    /// the reader the snapshots are cloned from
+    reader: Reader<W>,
+    /// the last buffer which was read
+    last: Arc<B>,
+    /// the snapshot `last` was cloned from, if the clone is only refreshed after a publish
+    pinned: Option<EpochPin>,
+    /// whether the buffer is only cloned after a different snapshot was published
+    refresh_if_epoch_changed: bool,
+}
+
+/// The buffer returned from [`ResilientReader::get_or_last`]
+pub struct Snapshot<'a, B> {
+    /// the clone of the buffer
+    last: &'a Arc<B>,
+    /// whether the reader failed to read, so this is the last buffer it read before
+    is_stale: bool,
+}
+
+impl<W: WeakRef, B: Clone> ResilientReader<W, B>
+where
+    RawBuffersOf<StrongOf<W>>: RawBuffers<Buffer = B>,
+    W::UpgradeError: Into<ReadError>,
+{
+    /// Create a resilient reader, and clone the published buffer
+    ///
+    /// This fails if the reader can't read, because then there is nothing to fall back to
+    pub fn new(mut reader: Reader<W>) -> Result<Self, ReadError> {
+        let last = Arc::new(B::clone(&*reader.checked_get()?));
+        Ok(Self {
+            reader,
+            last,
+            pinned: None,
+            refresh_if_epoch_changed: false,
+        })
+    }
+
+    /// only clone the buffer after a different snapshot was published, instead of on every
+    /// call to [`get_or_last`](Self::get_or_last)
+    pub fn set_refresh_if_epoch_changed(&mut self, on: bool) {
+        self.refresh_if_epoch_changed = on;
+        self.pinned = None;
+    }
+
+    /// get a clone of the published buffer, or the last buffer which was read if the reader
+    /// can't read anymore
+    ///
+    /// Then the snapshot is [stale](Snapshot::is_stale)
+    pub fn get_or_last(&mut self) -> Snapshot<'_, B> {
+        let is_stale = if self.refresh_if_epoch_changed {
+            self.refresh_pinned().is_err()
+        } else {
+            self.refresh().is_err()
+        };
+
+        Snapshot {
+            last: &self.last,
+            is_stale,
+        }
+    }
+
+    /// clone the published buffer
+    fn refresh(&mut self) -> Result<(), ReadError> {
+        let guard = self.reader.checked_get()?;
+        store(&mut self.last, &guard);
+        Ok(())
+    }
+
+    /// clone the published buffer if it's a different snapshot than the last one
+    fn refresh_pinned(&mut self) -> Result<(), ReadError> {
+        loop {
+            let pin = self.reader.try_pin_epoch()?;
+            if self.pinned == Some(pin) {
+                return Ok(());
+            }
+
+            // a publish may start between pinning the snapshot and locking it,
+            // then pin the new snapshot instead of cloning one which is already stale
+            match self.reader.try_get_pinned(&pin) {
+                Ok(guard) => {
+                    store(&mut self.last, &guard);
+                    drop(guard);
+                    self.pinned = Some(pin);
+                    return Ok(());
+                }
+                Err(ReadError::SnapshotRetired(_)) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl<W: WeakRef, B> ResilientReader<W, B> {
+    /// the last buffer which was read, without trying to read
+    pub fn last(&self) -> &Arc<B> {
+        &self.last
+    }
+
+    /// the reader the snapshots are cloned from
+    pub fn reader(&self) -> &Reader<W> {
+        &self.reader
+    }
+
+    /// get the reader back, dropping the last buffer
+    pub fn into_reader(self) -> Reader<W> {
+        self.reader
+    }
+}
+
+/// clone `buffer` into `last`, reusing its allocation if it isn't shared
+fn store<B: Clone>(last: &mut Arc<B>, buffer: &B) {
+    match Arc::get_mut(last) {
+        Some(last) => last.clone_from(buffer),
+        None => *last = Arc::new(buffer.clone()),
+    }
+}
+
+impl<B> Snapshot<'_, B> {
+    /// whether the reader failed to read, so this is the last buffer it read before
+    pub fn is_stale(&self) -> bool {
+        self.is_stale
+    }
+
+    /// keep the buffer after the resilient reader is used again
+    ///
+    /// This makes the next read allocate a new clone
+    pub fn into_arc(self) -> Arc<B> {
+        self.last.clone()
+    }
+}
+
+impl<B> Deref for Snapshot<'_, B> {
+    type Target = B;
+
+    fn deref(&self) -> &Self::Target {
+        self.last
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+// `OwnedWithWeak` needs `Weak`, which loom doesn't have
+#[cfg(not(feature = "loom"))]
+fn test_resilient_reader() {
+    use crate::{ptrs::alloc::OwnedWithWeak, strategy::TrackingStrategy};
+    use std::vec;
+
+    let mut writer = crate::raw::Writer::new(OwnedWithWeak::<TrackingStrategy, _>::from_buffers(
+        vec![1],
+        vec![1],
+    ));
+    let mut reader = ResilientReader::new(writer.reader()).unwrap();
+
+    let snapshot = reader.get_or_last();
+    assert_eq!(*snapshot, [1]);
+    assert!(!snapshot.is_stale());
+
+    writer.split_mut().writer.push(2);
+    writer.swap_buffers();
+    let old = reader.last().clone();
+    assert_eq!(*reader.get_or_last(), [1, 2]);
+    // the old clone is shared, so it's kept as is
+    assert_eq!(*old, [1]);
+    drop(old);
+
+    drop(writer);
+    let snapshot = reader.get_or_last();
+    assert_eq!(*snapshot, [1, 2]);
+    assert!(snapshot.is_stale());
+    // only the resilient reader keeps the buffer alive
+    assert_eq!(Arc::strong_count(reader.last()), 1);
+    assert!(reader.reader().clone().checked_get().is_err());
+}
+
+#[test]
+#[cfg(feature = "std")]
+// `OwnedWithWeak` needs `Weak`, which loom doesn't have
+#[cfg(not(feature = "loom"))]
+fn test_resilient_reader_refresh_if_epoch_changed() {
+    use crate::{ptrs::alloc::OwnedWithWeak, strategy::HazardStrategy};
+
+    let mut writer =
+        crate::raw::Writer::new(OwnedWithWeak::<HazardStrategy, _>::from_buffers(1, 1));
+    let mut reader = ResilientReader::new(writer.reader()).unwrap();
+    reader.set_refresh_if_epoch_changed(true);
+
+    let first = reader.get_or_last().into_arc();
+    // no publish, so the clone isn't refreshed
+    *writer.split_mut().writer = 2;
+    assert!(Arc::ptr_eq(&first, &reader.get_or_last().into_arc()));
+
+    writer.swap_buffers();
+    let second = reader.get_or_last().into_arc();
+    assert_eq!(*second, 2);
+    assert!(!Arc::ptr_eq(&first, &second));
+
+    drop(writer);
+    let snapshot = reader.get_or_last();
+    assert!(snapshot.is_stale());
+    assert_eq!(*snapshot, 2);
+}
@@ -1,5 +1,44 @@
 //! various strategies for sycronizing a double buffer
+//!
+//! # Progress guarantees
+//!
+//! The writer only ever waits for readers, readers never wait for the writer. So no matter how
+//! often the writer publishes, acquiring a read guard takes a bounded number of steps for each of the
+//! strategies here, and never retries because the writer published. The details are in each strategy's docs.
+//!
+//! * [`HazardStrategy`]: lock-free. Acquiring a guard is one compare-exchange on the reader's cached node,
+//!   or a walk over the list of nodes if that node is busy. Only other readers which push a node to the
+//!   list at the same time can make a reader retry, and then one of them made progress
+//! * [`TrackingStrategy`]: wait-free. Acquiring and releasing a guard is one increment of the reader's
+//!   own counter. Creating a reader takes a lock which the writer takes briefly in each swap
+//! * [`AdaptiveStrategy`]: wait-free in counter mode (one increment of a shared counter), and the same as
+//!   [`HazardStrategy`] once it inflated
+//! * [`AtomicCounterStrategy`]: wait-free (one increment of a shared counter). But the writer waits for
+//!   every guard, even the ones acquired after the swap, so readers which keep holding guards can starve it
+//! * [`LocalStrategy`], [`LocalHazardStrategy`] and [`LocalTrackingStrategy`]: the readers and the writer
+//!   are on the same thread, so they can't starve each other. Swapping while a guard is held panics instead
+//!
+//! [`Reader::optimistic_read`](crate::raw::Reader::optimistic_read) doesn't take a guard, and has
+//! to retry if the writer published while it was reading. A writer which publishes faster than the
+//! buffer can be copied could make it retry forever.
+//! [`Reader::optimistic_read_with_retries`](crate::raw::Reader::optimistic_read_with_retries) takes
+//! a read guard instead after a number of retries, so it has the same guarantees as the strategy.
+//!
+//! Except for [`AtomicCounterStrategy`], the writer doesn't starve either: each swap only waits for
+//! the readers which held a guard when the buffers were flipped, not for readers which acquired a guard
+//! after that. So readers which keep acquiring guards, with some reader holding a guard at any time,
+//! only delay the writer by the time it takes for the captured guards to be released. [`AdaptiveStrategy`] in counter mode can't tell
+//! readers apart, so it inflates when a swap waits too long, and then gives the same guarantee.
+//!
+//! The `progress` tests check these, by counting how many guards readers acquire while a writer publishes
+//! in a loop, and how many times the writer publishes while readers acquire guards in a loop. They measure
+//! throughput, so they're ignored by default, run them with `cargo test --test progress -- --ignored`
 
+#[cfg(feature = "alloc")]
+pub mod adaptive;
+pub mod atomic_counter;
+#[cfg(feature = "alloc")]
+pub mod dyn_strategy;
 #[cfg(feature = "alloc")]
 pub mod hazard;
 pub mod local;
@@ -7,9 +46,16 @@ pub mod local;
 pub mod local_hazard;
 #[cfg(feature = "alloc")]
 pub mod local_tracking;
+#[cfg(all(feature = "std", any(test, feature = "test-utils")))]
+pub mod mock;
 #[cfg(feature = "std")]
 pub mod tracking;
 
+#[cfg(feature = "alloc")]
+pub use adaptive::AdaptiveStrategy;
+pub use atomic_counter::AtomicCounterStrategy;
+#[cfg(feature = "alloc")]
+pub use dyn_strategy::{BoxedStrategy, DynStrategy};
 #[cfg(feature = "alloc")]
 pub use hazard::HazardStrategy;
 pub use local::LocalStrategy;
@@ -19,3 +65,161 @@ pub use local_hazard::LocalHazardStrategy;
 pub use local_tracking::LocalTrackingStrategy;
 #[cfg(feature = "std")]
 pub use tracking::TrackingStrategy;
+
+/// counts the allocations made by each thread, to check that strategies don't allocate on every swap
+#[cfg(all(test, feature = "std", not(feature = "loom")))]
+pub(crate) mod alloc_count {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    /// the global allocator which counts allocations
+    struct CountingAlloc;
+
+    #[global_allocator]
+    /// the global allocator for tests
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    std::thread_local! {
+        /// the number of allocations made by this thread
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    // SAFETY: this forwards to the system allocator
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            // the thread local may already be destroyed if this thread is exiting
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            // SAFETY: forwarded from the caller
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            // SAFETY: forwarded from the caller
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    /// the number of allocations made by this thread so far
+    pub fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+}
+
+/// checks that no sequence of safe calls leaves a phantom reader behind,
+/// which would stop the writer from ever swapping again
+#[cfg(all(test, feature = "std", not(feature = "loom")))]
+mod guard_accounting {
+    use std::{vec, vec::Vec};
+
+    use crate::{
+        interface::Strategy,
+        raw::{RawDBuf, Reader, Shared, Writer},
+    };
+
+    /// the number of readers in each check
+    const READERS: usize = 4;
+
+    /// assert that the writer can swap without waiting on any reader
+    fn assert_no_readers<S: Strategy>(writer: &mut Writer<&Shared<S, RawDBuf<i32>>>) {
+        // SAFETY: the swap is finished or abandoned before the writer is used again
+        let Ok(mut swap) = (unsafe { writer.try_start_buffer_swap() }) else {
+            panic!("a phantom reader stopped the writer from swapping")
+        };
+
+        // SAFETY: the swap was started by this writer
+        if !unsafe { writer.is_swap_finished(&mut swap) } {
+            swap.defuse();
+            panic!("the writer is waiting on a phantom reader")
+        }
+    }
+
+    /// acquire and release guards in many different orders, and check that the writer
+    /// can swap afterwards
+    fn check<S: Strategy>(strategy: S) {
+        let mut shared = Shared::from_raw_parts(strategy, RawDBuf::new(0, 0));
+        let mut writer = Writer::new(&mut shared);
+        let mut readers = (0..READERS).map(|_| writer.reader()).collect::<Vec<_>>();
+
+        for order in [[0, 1, 2, 3], [3, 2, 1, 0], [1, 3, 0, 2]] {
+            let mut guards = readers
+                .iter_mut()
+                .map(|reader| Some(reader.get()))
+                .collect::<Vec<_>>();
+            for i in order {
+                guards[i] = None;
+            }
+            drop(guards);
+            assert_no_readers(&mut writer);
+        }
+
+        // a reader can read many times before the writer swaps
+        for _ in 0..3 {
+            let guards = readers.iter_mut().map(Reader::get).collect::<Vec<_>>();
+            drop(guards);
+        }
+        assert_no_readers(&mut writer);
+
+        // owned guards drop their readers at the same time
+        let mut guards = readers
+            .drain(..)
+            .map(Reader::into_guard)
+            .collect::<Vec<_>>();
+        guards.reverse();
+        let readers = guards
+            .drain(..2)
+            .map(|guard| guard.into_reader())
+            .collect::<Vec<_>>();
+        drop(guards);
+        assert_no_readers(&mut writer);
+
+        // the reader which created the guard is dropped before the guard
+        let guards = readers
+            .iter()
+            .map(|reader| reader.clone().into_guard())
+            .collect::<Vec<_>>();
+        drop(readers);
+        drop(guards);
+        assert_no_readers(&mut writer);
+
+        // readers which never read
+        drop(vec![writer.reader(), writer.reader()]);
+        assert_no_readers(&mut writer);
+    }
+
+    #[test]
+    fn test_local() {
+        check(super::LocalStrategy::new())
+    }
+
+    #[test]
+    fn test_local_tracking() {
+        check(super::LocalTrackingStrategy::new())
+    }
+
+    #[test]
+    fn test_local_hazard() {
+        check(super::LocalHazardStrategy::new())
+    }
+
+    #[test]
+    fn test_tracking() {
+        check(super::TrackingStrategy::new())
+    }
+
+    #[test]
+    fn test_hazard() {
+        check(super::HazardStrategy::new())
+    }
+
+    #[test]
+    fn test_adaptive() {
+        check(super::AdaptiveStrategy::new())
+    }
+
+    #[test]
+    fn test_mock() {
+        check(super::mock::MockStrategy::new())
+    }
+}
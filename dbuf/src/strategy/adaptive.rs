@@ -0,0 +1,547 @@
+//! An adaptive strategy
+//!
+//! The [`AdaptiveStrategy`] starts out in counter mode, where all readers share a single
+//! counter of active readers, and the writer waits for the counter to reach zero after each swap.
+//! This is very cheap when there are only a few readers, but under contention the writer
+//! may need to wait a long time for the counter to reach zero (since readers which started
+//! reading from the new buffer also increment the counter).
+//!
+//! So once the writer observes sustained waiting, or too many active readers at once, it
+//! inflates into hazard mode. After that, all new read guards use the [`HazardStrategy`].
+//! This transition is one-way. Too many active readers inflate at the start of the next swap,
+//! but a swap which waits too long inflates right away, since readers which keep acquiring guards
+//! could keep the counter above zero forever, and then there wouldn't be a next swap.
+//!
+//! Readers which loaded the mode before the transition may still use the counter, so the writer
+//! always waits for the counter to reach zero, even in hazard mode. Since no new readers use the
+//! counter after the transition, this is only the readers which were in-flight during the transition.
+//!
+//! ## Progress
+//!
+//! In counter mode, acquiring and releasing a read guard is one increment of the shared counter, so it's wait-free.
+//! After inflating, guards are acquired like in a [`HazardStrategy`], which is lock-free. The writer can't
+//! make readers wait in either mode. But the writer may have to wait a long time in counter mode, which is why it inflates.
+//! Once it inflated, new guards don't hold up the swap, so the writer only waits for the guards which
+//! were already held, like in a [`HazardStrategy`].
+
+#[cfg(not(feature = "loom"))]
+use core::sync::atomic::{fence, AtomicBool, AtomicU32, Ordering};
+#[cfg(feature = "loom")]
+use loom::sync::atomic::{fence, AtomicBool, AtomicU32, Ordering};
+
+use crate::{
+    interface::{
+        ActiveReaderInfo, CheapReaderTag, FreshCopy, Strategy, StrategyIntrospect, WaitStrategy,
+    },
+    strategy::hazard::{self, HazardStrategy},
+    wait::DefaultWait,
+};
+
+/// the number of times the writer may pause during a single swap before inflating
+const INFLATE_AFTER_PAUSES: u32 = 16;
+/// the number of concurrently active readers in counter mode before inflating
+const MAX_COUNTER_READERS: u32 = 4;
+
+/// An adaptive strategy
+///
+/// a counter based strategy which inflates to a [`HazardStrategy`] under contention
+///
+/// see module level docs for details
+pub struct AdaptiveStrategy<W = DefaultWait> {
+    /// the number of active readers in counter mode
+    count: AtomicU32,
+    /// if true, then new readers use the hazard strategy
+    inflated: AtomicBool,
+    /// if true, then the writer should inflate at the start of the next swap
+    should_inflate: AtomicBool,
+    /// the strategy used in hazard mode
+    hazard: HazardStrategy<W>,
+}
+
+impl AdaptiveStrategy {
+    /// Create a new adaptive strategy
+    #[cfg(not(feature = "loom"))]
+    pub const fn new() -> Self {
+        Self::with_wait_strategy(DefaultWait::new())
+    }
+}
+
+impl<W: Default> Default for AdaptiveStrategy<W> {
+    fn default() -> Self {
+        Self::with_wait_strategy(W::default())
+    }
+}
+
+impl<W: FreshCopy> FreshCopy for AdaptiveStrategy<W> {
+    fn fresh_copy(&self) -> Self {
+        Self {
+            count: AtomicU32::new(0),
+            inflated: AtomicBool::new(false),
+            should_inflate: AtomicBool::new(false),
+            hazard: self.hazard.fresh_copy(),
+        }
+    }
+}
+
+impl<W> AdaptiveStrategy<W> {
+    /// Create a new [`AdaptiveStrategy`] with the given [`WaitStrategy`]
+    #[cfg(not(feature = "loom"))]
+    pub const fn with_wait_strategy(park: W) -> Self {
+        Self {
+            count: AtomicU32::new(0),
+            inflated: AtomicBool::new(false),
+            should_inflate: AtomicBool::new(false),
+            hazard: HazardStrategy::with_wait_strategy(park),
+        }
+    }
+
+    /// Create a new [`AdaptiveStrategy`] with the given [`WaitStrategy`]
+    #[cfg(feature = "loom")]
+    pub fn with_wait_strategy(park: W) -> Self {
+        Self {
+            count: AtomicU32::new(0),
+            inflated: AtomicBool::new(false),
+            should_inflate: AtomicBool::new(false),
+            hazard: HazardStrategy::with_wait_strategy(park),
+        }
+    }
+
+    /// Returns true if this strategy has inflated into hazard mode
+    pub fn is_inflated(&self) -> bool {
+        self.inflated.load(Ordering::Relaxed)
+    }
+
+    /// Record a backtrace each time a read guard is acquired in hazard mode, see [`debug_checks`](crate::debug_checks)
+    ///
+    /// Readers in counter mode can't be told apart, so they don't record backtraces
+    #[cfg(feature = "debug-checks")]
+    pub fn capture_backtraces(&self, on: bool) {
+        self.hazard.capture_backtraces(on)
+    }
+}
+
+/// the writer tag for [`AdaptiveStrategy`]
+pub struct WriterTag(hazard::WriterTag);
+/// the reader tag for [`AdaptiveStrategy`]
+///
+/// this is only used by read guards in hazard mode
+#[derive(Clone)]
+pub struct ReaderTag(hazard::ReaderTag);
+/// the validation token for [`AdaptiveStrategy`]
+pub struct ValidationToken(Option<hazard::ValidationToken>);
+/// the capture token for [`AdaptiveStrategy`]
+pub struct Capture(Option<hazard::Capture>);
+/// the reader guard for [`AdaptiveStrategy`]
+pub struct ReaderGuard(Option<hazard::ReaderGuard>);
+/// the pause state for [`AdaptiveStrategy`]
+#[derive(Default)]
+pub struct Pause<S> {
+    /// the pause state of the waiting strategy
+    state: S,
+    /// the number of times the writer paused
+    pauses: u32,
+}
+
+// SAFETY: FIXME
+unsafe impl<W: WaitStrategy> Strategy for AdaptiveStrategy<W> {
+    type WriterTag = WriterTag;
+    type ReaderTag = ReaderTag;
+    type Which = crate::raw::AtomicFlag;
+    type ValidationToken = ValidationToken;
+    type ValidationError = core::convert::Infallible;
+    type Capture = Capture;
+    type ReaderGuard = ReaderGuard;
+    type Pause = Pause<W::State>;
+
+    const READER_TAG_NEEDS_CONSTRUCTION: bool = false;
+
+    unsafe fn create_writer_tag(&mut self) -> Self::WriterTag {
+        // SAFETY: the caller ensures that this is only called once
+        WriterTag(unsafe { self.hazard.create_writer_tag() })
+    }
+
+    unsafe fn create_reader_tag_from_writer(&self, parent: &Self::WriterTag) -> Self::ReaderTag {
+        // SAFETY: the caller ensures that the parent is a writer tag from this strategy
+        ReaderTag(unsafe { self.hazard.create_reader_tag_from_writer(&parent.0) })
+    }
+
+    unsafe fn create_reader_tag_from_reader(&self, parent: &Self::ReaderTag) -> Self::ReaderTag {
+        // SAFETY: the caller ensures that the parent is a reader tag from this strategy
+        ReaderTag(unsafe { self.hazard.create_reader_tag_from_reader(&parent.0) })
+    }
+
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        // SAFETY: the caller ensures that a writer tag was already created
+        ReaderTag(unsafe { self.hazard.create_reader_tag() })
+    }
+
+    fn dangling_reader_tag() -> Self::ReaderTag {
+        ReaderTag(HazardStrategy::<W>::dangling_reader_tag())
+    }
+
+    fn validate_swap(
+        &self,
+        writer: &mut Self::WriterTag,
+    ) -> Result<Self::ValidationToken, Self::ValidationError> {
+        // only the writer changes the mode, so we don't need to syncronize with readers here
+        if self.should_inflate.load(Ordering::Relaxed) {
+            self.inflated.store(true, Ordering::Relaxed);
+        }
+
+        if self.is_inflated() {
+            match self.hazard.validate_swap(&mut writer.0) {
+                Ok(token) => Ok(ValidationToken(Some(token))),
+                Err(inf) => match inf {},
+            }
+        } else {
+            Ok(ValidationToken(None))
+        }
+    }
+
+    unsafe fn capture_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // pairs with the fence in `begin_read_guard`: either the writer sees the reader's
+        // increment in `have_readers_exited`, or the reader sees the flipped buffer
+        fence(Ordering::SeqCst);
+
+        // SAFETY: the caller ensures that the token came from `validate_swap` right before the swap
+        Capture(
+            token
+                .0
+                .map(|token| unsafe { self.hazard.capture_readers(&mut writer.0, token) }),
+        )
+    }
+
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // pairs with the fence in `begin_read_guard`, so that the writer sees the increment
+        // of every reader which already holds a read guard
+        fence(Ordering::SeqCst);
+
+        // SAFETY: the counter waits for every active reader, and in hazard mode
+        // `HazardStrategy::capture_current_readers` has the same requirements as this
+        Capture(
+            token
+                .0
+                .map(|token| unsafe { self.hazard.capture_current_readers(&mut writer.0, token) }),
+        )
+    }
+
+    unsafe fn have_readers_exited(
+        &self,
+        writer: &Self::WriterTag,
+        capture: &mut Self::Capture,
+    ) -> bool {
+        if let Some(capture) = &mut capture.0 {
+            // SAFETY: the caller ensures that the capture came from `capture_readers`
+            if !unsafe { self.hazard.have_readers_exited(&writer.0, capture) } {
+                return false;
+            }
+        }
+
+        // even in hazard mode, there may be in-flight readers which started in counter mode
+        let count = self.count.load(Ordering::Relaxed);
+
+        if count > MAX_COUNTER_READERS {
+            self.should_inflate.store(true, Ordering::Relaxed);
+        }
+
+        if count == 0 {
+            // syncronize with `end_read_guard`, so that all reads happen before the writer
+            // touches the buffer
+            fence(Ordering::Acquire);
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
+        // Acquire to syncronize with the writer inflating in `pause`, see there for details
+        if self.inflated.load(Ordering::Acquire) {
+            // SAFETY: the caller ensures that the reader tag is from this strategy
+            return ReaderGuard(Some(unsafe { self.hazard.begin_read_guard(&mut reader.0) }));
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        // pairs with the fence in `capture_readers`, see there for details
+        fence(Ordering::SeqCst);
+
+        ReaderGuard(None)
+    }
+
+    unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, guard: Self::ReaderGuard) {
+        match guard.0 {
+            // SAFETY: the caller ensures that the guard came from `begin_read_guard` with this reader tag
+            Some(guard) => unsafe { self.hazard.end_read_guard(&mut reader.0, guard) },
+            None => {
+                // Release to syncronize with `have_readers_exited`
+                if self.count.fetch_sub(1, Ordering::Release) == 1 {
+                    self.hazard.wait_strategy().notify();
+                }
+            }
+        }
+    }
+
+    fn pause(&self, writer: &Self::WriterTag, pause: &mut Self::Pause) {
+        pause.pauses = pause.pauses.saturating_add(1);
+
+        if pause.pauses > INFLATE_AFTER_PAUSES {
+            // inflate now instead of at the next swap, otherwise readers which keep acquiring guards
+            // can keep the counter above zero, and this swap would never finish.
+            //
+            // Release to syncronize with `begin_read_guard`. This is only called after the buffers were
+            // flipped (or the current readers were captured), so any reader which sees this store also
+            // sees the flipped buffers, and it doesn't need to be waited on in this swap.
+            self.inflated.store(true, Ordering::Release);
+        }
+
+        self.hazard.pause(&writer.0, &mut pause.state);
+    }
+
+    fn has_readers(&self) -> bool {
+        // every reader tag is created by the hazard strategy
+        self.hazard.has_readers()
+    }
+
+    fn any_current_readers(&self) -> bool {
+        // readers which loaded the mode before inflating may still use the counter
+        self.count.load(Ordering::Relaxed) != 0 || self.hazard.any_current_readers()
+    }
+}
+
+// the reader tags are hazard reader tags
+impl<W: WaitStrategy> CheapReaderTag for AdaptiveStrategy<W> {}
+
+impl<W: WaitStrategy> StrategyIntrospect for AdaptiveStrategy<W> {
+    /// readers in counter mode can't be told apart, so they are all reported with an id of `0`
+    fn active_readers(&self, mut f: impl FnMut(ActiveReaderInfo)) {
+        for _ in 0..self.count.load(Ordering::Relaxed) {
+            f(ActiveReaderInfo {
+                id: 0,
+                generation: 0,
+            })
+        }
+
+        self.hazard.active_readers(f)
+    }
+
+    /// only readers in hazard mode record backtraces, readers in counter mode can't be told apart
+    #[cfg(feature = "debug-checks")]
+    fn guard_backtrace(
+        &self,
+        reader: &ActiveReaderInfo,
+    ) -> Option<std::sync::Arc<std::backtrace::Backtrace>> {
+        self.hazard.guard_backtrace(reader)
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn guard_thread(&self, reader: &ActiveReaderInfo) -> Option<std::thread::ThreadId> {
+        self.hazard.guard_thread(reader)
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        // readers in counter mode don't allocate
+        self.hazard.bookkeeping_bytes()
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+impl<B: crate::interface::RawBuffers, W: WaitStrategy> crate::interface::DefaultOwned<B>
+    for AdaptiveStrategy<W>
+{
+    type IntoStrongRefWithWeak = crate::ptrs::alloc::OwnedWithWeak<Self, B>;
+    type StrongRefWithWeak = crate::ptrs::alloc::OwnedStrong<Self, B>;
+    type WeakRef = crate::ptrs::alloc::OwnedWeak<Self, B>;
+
+    type IntoStrongRef = crate::ptrs::alloc::Owned<Self, B>;
+    type StrongRef = crate::ptrs::alloc::OwnedPtr<Self, B>;
+
+    fn build_with_weak(self, buffers: B) -> Self::IntoStrongRefWithWeak {
+        crate::ptrs::alloc::OwnedWithWeak::new(crate::raw::Shared::from_raw_parts(self, buffers))
+    }
+
+    fn build(self, buffers: B) -> Self::IntoStrongRef {
+        crate::ptrs::alloc::Owned::new(crate::raw::Shared::from_raw_parts(self, buffers))
+    }
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+fn test_adaptive() {
+    let mut shared =
+        crate::raw::Shared::from_raw_parts(AdaptiveStrategy::new(), crate::raw::RawDBuf::new(0, 0));
+    let mut writer = crate::raw::Writer::new(&mut shared);
+
+    let mut reader = writer.reader();
+    *writer.split_mut().writer = 10;
+    let mut reader2 = reader.clone();
+    let a = reader.get();
+
+    let mut writer = crate::delayed::DelayedWriter::from(writer);
+    writer.start_buffer_swap();
+
+    // readers which start after the swap still hold up the writer in counter mode
+    let b = reader2.get();
+    assert_eq!(*b, 10);
+    drop(a);
+    assert!(!writer.is_swap_finished());
+    drop(b);
+    assert!(writer.is_swap_finished());
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+fn test_adaptive_inflate() {
+    let mut strategy = AdaptiveStrategy::new();
+
+    // SAFETY: the tags are used according to the safety requirements of `Strategy`
+    unsafe {
+        let mut writer = strategy.create_writer_tag();
+        let mut readers = (0..=MAX_COUNTER_READERS)
+            .map(|_| strategy.create_reader_tag_from_writer(&writer))
+            .collect::<std::vec::Vec<_>>();
+        let guards = readers
+            .iter_mut()
+            .map(|reader| strategy.begin_read_guard(reader))
+            .collect::<std::vec::Vec<_>>();
+
+        let token = strategy.validate_swap(&mut writer).unwrap();
+        let mut capture = strategy.capture_readers(&mut writer, token);
+        assert!(!strategy.have_readers_exited(&writer, &mut capture));
+        assert!(!strategy.is_inflated());
+
+        // new read guards are counted until the next swap
+        let mut late = strategy.create_reader_tag_from_writer(&writer);
+        let late_guard = strategy.begin_read_guard(&mut late);
+
+        for (reader, guard) in readers.iter_mut().zip(guards) {
+            strategy.end_read_guard(reader, guard);
+        }
+        assert!(!strategy.have_readers_exited(&writer, &mut capture));
+        strategy.end_read_guard(&mut late, late_guard);
+        assert!(strategy.have_readers_exited(&writer, &mut capture));
+
+        // the writer inflates at the start of the next swap
+        let late_guard = strategy.begin_read_guard(&mut late);
+        let token = strategy.validate_swap(&mut writer).unwrap();
+        assert!(strategy.is_inflated());
+        let mut capture = strategy.capture_readers(&mut writer, token);
+
+        // the in-flight counter reader is still waited on
+        assert!(!strategy.have_readers_exited(&writer, &mut capture));
+        let hazard_guard = strategy.begin_read_guard(&mut readers[0]);
+        strategy.end_read_guard(&mut late, late_guard);
+        assert!(strategy.have_readers_exited(&writer, &mut capture));
+
+        // and hazard readers are waited on in the next swap
+        let token = strategy.validate_swap(&mut writer).unwrap();
+        let mut capture = strategy.capture_readers(&mut writer, token);
+        assert!(!strategy.have_readers_exited(&writer, &mut capture));
+        strategy.end_read_guard(&mut readers[0], hazard_guard);
+        assert!(strategy.have_readers_exited(&writer, &mut capture));
+    }
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+fn test_adaptive_inflate_while_waiting() {
+    let mut strategy = AdaptiveStrategy::new();
+
+    // SAFETY: the tags are used according to the safety requirements of `Strategy`
+    unsafe {
+        let mut writer = strategy.create_writer_tag();
+        let mut first = strategy.create_reader_tag_from_writer(&writer);
+        let mut second = strategy.create_reader_tag_from_writer(&writer);
+        let mut held = strategy.begin_read_guard(&mut first);
+
+        let token = strategy.validate_swap(&mut writer).unwrap();
+        let mut capture = strategy.capture_readers(&mut writer, token);
+        let mut pause = Pause::default();
+
+        // the readers always hold a guard, so the counter never reaches zero
+        for _ in 0..INFLATE_AFTER_PAUSES {
+            assert!(!strategy.have_readers_exited(&writer, &mut capture));
+            let next = strategy.begin_read_guard(&mut second);
+            strategy.end_read_guard(&mut first, held);
+            held = next;
+            core::mem::swap(&mut first, &mut second);
+            strategy.pause(&writer, &mut pause);
+        }
+        assert!(!strategy.is_inflated());
+
+        // so the writer inflates during the swap, and new guards don't hold it up
+        strategy.pause(&writer, &mut pause);
+        assert!(strategy.is_inflated());
+        let next = strategy.begin_read_guard(&mut second);
+        assert!(!strategy.have_readers_exited(&writer, &mut capture));
+        strategy.end_read_guard(&mut first, held);
+        assert!(strategy.have_readers_exited(&writer, &mut capture));
+        strategy.end_read_guard(&mut second, next);
+    }
+}
+
+#[test]
+#[cfg(feature = "debug-checks")]
+#[cfg(not(feature = "loom"))]
+fn test_adaptive_guard_backtrace() {
+    let mut strategy = AdaptiveStrategy::new();
+    strategy.capture_backtraces(true);
+
+    // SAFETY: the tags are used according to the safety requirements of `Strategy`
+    unsafe {
+        let mut writer = strategy.create_writer_tag();
+        let mut readers = (0..=MAX_COUNTER_READERS)
+            .map(|_| strategy.create_reader_tag_from_writer(&writer))
+            .collect::<std::vec::Vec<_>>();
+        let guards = readers
+            .iter_mut()
+            .map(|reader| strategy.begin_read_guard(reader))
+            .collect::<std::vec::Vec<_>>();
+
+        // counter readers don't have a backtrace
+        let mut active = std::vec::Vec::new();
+        strategy.active_readers(|info| active.push(info));
+        assert_eq!(active.len(), MAX_COUNTER_READERS as usize + 1);
+        assert!(strategy.guard_backtrace(&active[0]).is_none());
+
+        // too many readers, so the next swap inflates
+        let token = strategy.validate_swap(&mut writer).unwrap();
+        let mut capture = strategy.capture_readers(&mut writer, token);
+        assert!(!strategy.have_readers_exited(&writer, &mut capture));
+        for (reader, guard) in readers.iter_mut().zip(guards) {
+            strategy.end_read_guard(reader, guard);
+        }
+        assert!(strategy.have_readers_exited(&writer, &mut capture));
+
+        let token = strategy.validate_swap(&mut writer).unwrap();
+        assert!(strategy.is_inflated());
+        let mut capture = strategy.capture_readers(&mut writer, token);
+        assert!(strategy.have_readers_exited(&writer, &mut capture));
+
+        let guard = strategy.begin_read_guard(&mut readers[0]);
+        let mut active = std::vec::Vec::new();
+        strategy.active_readers(|info| active.push(info));
+        assert_eq!(active.len(), 1);
+        let backtrace = strategy
+            .guard_backtrace(&active[0])
+            .expect("the backtrace wasn't recorded");
+        let backtrace = std::format!("{backtrace}");
+        assert!(
+            backtrace.contains("test_adaptive_guard_backtrace"),
+            "{backtrace}"
+        );
+        assert_eq!(
+            strategy.guard_thread(&active[0]),
+            Some(std::thread::current().id())
+        );
+        strategy.end_read_guard(&mut readers[0], guard);
+    }
+}
@@ -0,0 +1,329 @@
+//! a sync strategy which only counts how many active readers there are
+//!
+//! This is the sync version of [`LocalStrategy`](crate::strategy::LocalStrategy): all readers
+//! share a single atomic counter, and after each swap the writer waits for it to reach zero.
+//! It doesn't allocate, and the tags and guards are empty, so it's `Send` and `Sync` everywhere,
+//! even without the `alloc` feature. Atomics are available on every target this crate supports,
+//! including `wasm32-unknown-unknown` without the atomics target feature, where they compile to
+//! plain loads and stores.
+//!
+//! ## Progress
+//!
+//! Acquiring and releasing a read guard is one increment of the shared counter, so it's wait-free.
+//! But the writer can't tell which readers are reading from which buffer, so it also waits for
+//! readers which acquired a guard after the swap. Readers which keep acquiring guards, with some
+//! reader holding a guard at any time, can keep the writer waiting forever. Use
+//! [`AdaptiveStrategy`](crate::strategy::AdaptiveStrategy) if that's possible.
+//!
+//! On a single thread, swapping while a guard is held waits forever, since the guard can't be
+//! released while the writer waits. [`LocalStrategy`](crate::strategy::LocalStrategy) panics
+//! instead, but it isn't `Send`.
+
+#[cfg(not(feature = "loom"))]
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "loom")]
+use loom::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use crate::{
+    interface::{
+        ActiveReaderInfo, CheapReaderTag, FreshCopy, Strategy, StrategyIntrospect, WaitStrategy,
+    },
+    wait::SpinWait,
+};
+
+/// A sync strategy which only counts how many active readers there are
+///
+/// see module level docs for details
+pub struct AtomicCounterStrategy<W = SpinWait> {
+    /// the number of active readers
+    active_readers: AtomicUsize,
+    /// the waiting strategy used while readers are still active
+    wait: W,
+}
+
+impl AtomicCounterStrategy {
+    /// Create a new atomic counter strategy
+    #[cfg(not(feature = "loom"))]
+    pub const fn new() -> Self {
+        Self::with_wait_strategy(SpinWait::new())
+    }
+}
+
+impl<W: Default> Default for AtomicCounterStrategy<W> {
+    fn default() -> Self {
+        Self::with_wait_strategy(W::default())
+    }
+}
+
+impl<W: FreshCopy> FreshCopy for AtomicCounterStrategy<W> {
+    fn fresh_copy(&self) -> Self {
+        Self::with_wait_strategy(self.wait.fresh_copy())
+    }
+}
+
+impl<W> AtomicCounterStrategy<W> {
+    /// Create a new [`AtomicCounterStrategy`] with the given [`WaitStrategy`]
+    #[cfg(not(feature = "loom"))]
+    pub const fn with_wait_strategy(wait: W) -> Self {
+        Self {
+            active_readers: AtomicUsize::new(0),
+            wait,
+        }
+    }
+
+    /// Create a new [`AtomicCounterStrategy`] with the given [`WaitStrategy`]
+    #[cfg(feature = "loom")]
+    pub fn with_wait_strategy(wait: W) -> Self {
+        Self {
+            active_readers: AtomicUsize::new(0),
+            wait,
+        }
+    }
+}
+
+/// the writer tag for [`AtomicCounterStrategy`]
+pub struct WriterTag(());
+/// the reader tag for [`AtomicCounterStrategy`]
+#[derive(Clone, Copy)]
+pub struct ReaderTag(());
+/// the validation token for [`AtomicCounterStrategy`]
+pub struct ValidationToken(());
+/// the capture token for [`AtomicCounterStrategy`]
+pub struct Capture(());
+/// the reader guard for [`AtomicCounterStrategy`]
+pub struct ReaderGuard(());
+
+// SAFETY: FIXME
+unsafe impl<W: WaitStrategy> Strategy for AtomicCounterStrategy<W> {
+    type WriterTag = WriterTag;
+    type ReaderTag = ReaderTag;
+    type Which = crate::raw::AtomicFlag;
+    type ValidationToken = ValidationToken;
+    type ValidationError = core::convert::Infallible;
+    type Capture = Capture;
+    type ReaderGuard = ReaderGuard;
+    type Pause = W::State;
+
+    const READER_TAG_NEEDS_CONSTRUCTION: bool = false;
+
+    #[inline]
+    unsafe fn create_writer_tag(&mut self) -> Self::WriterTag {
+        WriterTag(())
+    }
+
+    #[inline]
+    unsafe fn create_reader_tag_from_writer(&self, _parent: &Self::WriterTag) -> Self::ReaderTag {
+        ReaderTag(())
+    }
+
+    #[inline]
+    unsafe fn create_reader_tag_from_reader(&self, _parent: &Self::ReaderTag) -> Self::ReaderTag {
+        ReaderTag(())
+    }
+
+    #[inline]
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        ReaderTag(())
+    }
+
+    #[inline]
+    fn dangling_reader_tag() -> Self::ReaderTag {
+        ReaderTag(())
+    }
+
+    #[inline]
+    fn validate_swap(
+        &self,
+        _writer: &mut Self::WriterTag,
+    ) -> Result<Self::ValidationToken, Self::ValidationError> {
+        Ok(ValidationToken(()))
+    }
+
+    #[inline]
+    unsafe fn capture_readers(
+        &self,
+        _: &mut Self::WriterTag,
+        _: Self::ValidationToken,
+    ) -> Self::Capture {
+        // pairs with the fence in `begin_read_guard`: either the writer sees the reader's
+        // increment in `have_readers_exited`, or the reader sees the flipped buffer
+        fence(Ordering::SeqCst);
+        Capture(())
+    }
+
+    #[inline]
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: the counter includes every reader which holds a read guard, regardless of
+        // which buffer it's reading from, so it doesn't rely on the buffers being flipped
+        unsafe { self.capture_readers(writer, validation_token) }
+    }
+
+    #[inline]
+    unsafe fn have_readers_exited(
+        &self,
+        _writer: &Self::WriterTag,
+        _capture: &mut Self::Capture,
+    ) -> bool {
+        if self.active_readers.load(Ordering::Relaxed) == 0 {
+            // syncronize with `end_read_guard`, so that all reads happen before the writer
+            // touches the buffer
+            fence(Ordering::Acquire);
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    unsafe fn begin_read_guard(&self, _reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
+        let count = self.active_readers.fetch_add(1, Ordering::Relaxed);
+        // the count can only overflow if guards are leaked, and then the writer would
+        // see zero readers while some of them are still reading
+        if count == usize::MAX {
+            self.active_readers.fetch_sub(1, Ordering::Relaxed);
+            panic!("tried to create too many active readers")
+        }
+        // pairs with the fence in `capture_readers`, see there for details
+        fence(Ordering::SeqCst);
+        ReaderGuard(())
+    }
+
+    #[inline]
+    unsafe fn end_read_guard(&self, _reader: &mut Self::ReaderTag, _guard: Self::ReaderGuard) {
+        // Release to syncronize with `have_readers_exited`
+        if self.active_readers.fetch_sub(1, Ordering::Release) == 1 {
+            self.wait.notify();
+        }
+    }
+
+    fn pause(&self, _writer: &Self::WriterTag, pause: &mut Self::Pause) {
+        self.wait.wait(pause);
+    }
+
+    fn any_current_readers(&self) -> bool {
+        self.active_readers.load(Ordering::Relaxed) != 0
+    }
+}
+
+// the reader tags are empty
+impl<W: WaitStrategy> CheapReaderTag for AtomicCounterStrategy<W> {}
+
+impl<W: WaitStrategy> StrategyIntrospect for AtomicCounterStrategy<W> {
+    /// readers can't be told apart, so they are all reported with an id of `0`
+    fn active_readers(&self, mut f: impl FnMut(ActiveReaderInfo)) {
+        for _ in 0..self.active_readers.load(Ordering::Relaxed) {
+            f(ActiveReaderInfo {
+                id: 0,
+                generation: 0,
+            })
+        }
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(all(feature = "alloc", not(feature = "loom")))]
+impl<B: crate::interface::RawBuffers, W: WaitStrategy> crate::interface::DefaultOwned<B>
+    for AtomicCounterStrategy<W>
+{
+    type IntoStrongRefWithWeak = crate::ptrs::alloc::OwnedWithWeak<Self, B>;
+    type StrongRefWithWeak = crate::ptrs::alloc::OwnedStrong<Self, B>;
+    type WeakRef = crate::ptrs::alloc::OwnedWeak<Self, B>;
+
+    type IntoStrongRef = crate::ptrs::alloc::Owned<Self, B>;
+    type StrongRef = crate::ptrs::alloc::OwnedPtr<Self, B>;
+
+    fn build_with_weak(self, buffers: B) -> Self::IntoStrongRefWithWeak {
+        crate::ptrs::alloc::OwnedWithWeak::new(crate::raw::Shared::from_raw_parts(self, buffers))
+    }
+
+    fn build(self, buffers: B) -> Self::IntoStrongRef {
+        crate::ptrs::alloc::Owned::new(crate::raw::Shared::from_raw_parts(self, buffers))
+    }
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+fn test_atomic_counter() {
+    let mut shared = crate::raw::Shared::from_raw_parts(
+        AtomicCounterStrategy::new(),
+        crate::raw::RawDBuf::new(0, 0),
+    );
+    let mut writer = crate::raw::Writer::new(&mut shared);
+
+    let mut reader = writer.reader();
+
+    let split_mut = writer.split_mut();
+    *split_mut.writer = 10;
+    assert_eq!(*reader.get(), 0);
+
+    writer.try_swap_buffers().unwrap();
+
+    assert_eq!(*reader.get(), 10);
+    let split_mut = writer.split_mut();
+    *split_mut.writer = 20;
+    assert_eq!(*reader.get(), 10);
+
+    writer.try_swap_buffers().unwrap();
+
+    assert_eq!(*reader.get(), 20);
+
+    let mut reader2 = reader;
+    let a = reader.get();
+
+    // SAFETY: we don't call any &mut self methods on writer any more
+    let mut swap = unsafe { writer.try_start_buffer_swap() }.unwrap();
+
+    // SAFETY: we created the swap above
+    assert!(!unsafe { writer.is_swap_finished(&mut swap) });
+
+    // the counter can't tell that this reader is reading from the new buffer
+    let b = reader2.get();
+    drop(a);
+    // SAFETY: we created the swap above
+    assert!(!unsafe { writer.is_swap_finished(&mut swap) });
+
+    drop(b);
+    // SAFETY: we created the swap above
+    assert!(unsafe { writer.is_swap_finished(&mut swap) });
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_atomic_counter_threads() {
+    use crate::{ptrs::alloc::Owned, raw::Writer};
+
+    /// the strategy, tags and guards don't contain any thread-local state
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let mut writer = Writer::new(Owned::<AtomicCounterStrategy, _>::from_buffers(0u64, 0));
+    assert_send_sync(&writer);
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let mut reader = writer.reader();
+            assert_send_sync(&reader);
+
+            scope.spawn(move || {
+                let mut last = 0;
+                while last != 1000 {
+                    let value = *reader.get();
+                    assert!(value >= last);
+                    last = value;
+                }
+            });
+        }
+
+        for i in 1..=1000 {
+            *writer.split_mut().writer = i;
+            writer.swap_buffers();
+        }
+    });
+}
@@ -0,0 +1,707 @@
+//! an object safe strategy, so that double buffers of different types share the strategy's code
+//!
+//! Everything in [`raw`](crate::raw) is generic over both the strategy and the buffer, so each
+//! buffer type gets its own copy of the strategy's code, inlined into guards and swaps.
+//! [`DynStrategy`] is an object safe subset of [`Strategy`], which is implemented for every
+//! thread-safe strategy, and [`BoxedStrategy`] implements [`Strategy`] by calling through its
+//! vtable. So the double buffers which use a [`BoxedStrategy`] share one copy of the strategy's
+//! code, at the cost of a virtual call for each step of a read guard or a swap.
+//!
+//! Tags, guards, and captures are stored in an [`Erased`], which holds up to three words inline,
+//! and boxes anything larger. None of the built-in strategies need to box.
+//!
+//! This is opt-in, the strategies are still used directly by default. Whether it makes a binary
+//! smaller depends on how much of the strategy's code was duplicated in the first place:
+//! swaps get smaller, but read guards hold an [`Erased`] which needs drop glue in each function
+//! which can unwind. So measure before switching.
+//!
+//! ```
+//! use dbuf::{
+//!     ptrs::alloc::Owned,
+//!     raw::{RawDBuf, Writer},
+//!     strategy::{dyn_strategy::DynShared, HazardStrategy, TrackingStrategy},
+//! };
+//!
+//! // both double buffers use the same strategy code, even though the buffers differ
+//! let mut numbers = Writer::new(Owned::new(DynShared::from_raw_parts(
+//!     Box::new(HazardStrategy::new()),
+//!     RawDBuf::new(0, 0),
+//! )));
+//! let mut names = Writer::new(Owned::new(DynShared::from_raw_parts(
+//!     Box::new(HazardStrategy::new()),
+//!     RawDBuf::new(String::new(), String::new()),
+//! )));
+//! // and the strategy can be picked at runtime
+//! let mut flags = Writer::new(Owned::new(DynShared::from_raw_parts(
+//!     Box::new(TrackingStrategy::new()),
+//!     RawDBuf::new(false, false),
+//! )));
+//!
+//! let mut reader = names.reader();
+//! names.split_mut().writer.push_str("hello");
+//! names.swap_buffers();
+//! assert_eq!(*reader.get(), "hello");
+//!
+//! *numbers.split_mut().writer = 1;
+//! numbers.swap_buffers();
+//! *flags.split_mut().writer = true;
+//! flags.swap_buffers();
+//! assert_eq!((*numbers.reader().get(), *flags.reader().get()), (1, true));
+//! ```
+
+use core::{
+    any::TypeId,
+    convert::Infallible,
+    mem::{self, ManuallyDrop, MaybeUninit},
+};
+use std::boxed::Box;
+
+use crate::{
+    interface::{Strategy, WouldBlock},
+    raw::{AtomicFlag, Shared},
+};
+
+/// A boxed [`DynStrategy`], which implements [`Strategy`]
+pub type BoxedStrategy = Box<dyn DynStrategy>;
+
+/// The shared state of a double buffer which uses a [`BoxedStrategy`]
+pub type DynShared<B> = Shared<BoxedStrategy, B>;
+
+/// the number of words an [`Erased`] can hold inline
+const INLINE_WORDS: usize = 3;
+
+/// the storage for an [`Erased`]
+type Storage = MaybeUninit<[usize; INLINE_WORDS]>;
+
+/// A value whose type is only known to the strategy which created it
+///
+/// Values which fit in three words are stored inline, larger ones are boxed
+pub struct Erased {
+    /// the value, or a box holding the value
+    storage: Storage,
+    /// drops the value in `storage`, `None` if there is nothing to drop
+    drop_value: Option<unsafe fn(*mut Storage)>,
+}
+
+// SAFETY: only values which are `Send` and `Sync` are erased,
+// see the bounds on the `DynStrategy` impl
+unsafe impl Send for Erased {}
+// SAFETY: only values which are `Send` and `Sync` are erased,
+// see the bounds on the `DynStrategy` impl
+unsafe impl Sync for Erased {}
+
+/// drop the `T` which is stored in `storage`
+///
+/// # Safety
+///
+/// `storage` must hold an initialized `T`, which isn't used afterwards
+unsafe fn drop_storage<T>(storage: *mut Storage) {
+    // SAFETY: the caller ensures that storage holds a `T`
+    unsafe { storage.cast::<T>().drop_in_place() }
+}
+
+impl Erased {
+    /// true if a `T` can be stored inline
+    const fn fits_inline<T>() -> bool {
+        mem::size_of::<T>() <= mem::size_of::<Storage>()
+            && mem::align_of::<T>() <= mem::align_of::<Storage>()
+    }
+
+    /// an erased value which holds nothing, only used for dangling reader tags
+    const fn empty() -> Self {
+        Self {
+            storage: MaybeUninit::uninit(),
+            drop_value: None,
+        }
+    }
+
+    /// erase a value
+    fn new<T>(value: T) -> Self {
+        if Self::fits_inline::<T>() {
+            Self::new_inline(value)
+        } else {
+            Self::new_inline(Box::new(value))
+        }
+    }
+
+    /// erase a value which fits inline
+    fn new_inline<T>(value: T) -> Self {
+        assert!(Self::fits_inline::<T>());
+        let mut storage = Storage::uninit();
+        // SAFETY: `T` fits in the storage, checked above
+        unsafe { storage.as_mut_ptr().cast::<T>().write(value) };
+        Self {
+            storage,
+            drop_value: if mem::needs_drop::<T>() {
+                Some(drop_storage::<T>)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// get a reference to the value
+    ///
+    /// # Safety
+    ///
+    /// this must have been created from a `T`
+    unsafe fn get<T>(&self) -> &T {
+        let ptr = self.storage.as_ptr();
+        if Self::fits_inline::<T>() {
+            // SAFETY: the caller ensures that this holds a `T`, which is stored inline
+            unsafe { &*ptr.cast::<T>() }
+        } else {
+            // SAFETY: the caller ensures that this holds a `T`, which is boxed
+            unsafe { &*ptr.cast::<Box<T>>() }
+        }
+    }
+
+    /// get a mutable reference to the value
+    ///
+    /// # Safety
+    ///
+    /// this must have been created from a `T`
+    unsafe fn get_mut<T>(&mut self) -> &mut T {
+        let ptr = self.storage.as_mut_ptr();
+        if Self::fits_inline::<T>() {
+            // SAFETY: the caller ensures that this holds a `T`, which is stored inline
+            unsafe { &mut *ptr.cast::<T>() }
+        } else {
+            // SAFETY: the caller ensures that this holds a `T`, which is boxed
+            unsafe { &mut *ptr.cast::<Box<T>>() }
+        }
+    }
+
+    /// get the value back
+    ///
+    /// # Safety
+    ///
+    /// this must have been created from a `T`
+    unsafe fn into_inner<T>(self) -> T {
+        let this = ManuallyDrop::new(self);
+        let ptr = this.storage.as_ptr();
+        if Self::fits_inline::<T>() {
+            // SAFETY: the caller ensures that this holds a `T`, which is stored inline,
+            // and `this` won't be dropped, so the value is moved out
+            unsafe { ptr.cast::<T>().read() }
+        } else {
+            // SAFETY: the caller ensures that this holds a `T`, which is boxed,
+            // and `this` won't be dropped, so the box is moved out
+            *unsafe { ptr.cast::<Box<T>>().read() }
+        }
+    }
+}
+
+impl Drop for Erased {
+    fn drop(&mut self) {
+        if let Some(drop_value) = self.drop_value {
+            // SAFETY: `drop_value` is only set along with the value it drops
+            unsafe { drop_value(&mut self.storage) }
+        }
+    }
+}
+
+/// The writer tag of a [`BoxedStrategy`]
+///
+/// This remembers which strategy created it, since it's passed to safe methods
+pub struct ErasedWriterTag {
+    /// the type of the strategy which created the tag
+    strategy: TypeId,
+    /// the strategy's writer tag
+    tag: Erased,
+}
+
+impl ErasedWriterTag {
+    /// get the writer tag of `S`
+    ///
+    /// # Panics
+    ///
+    /// if the tag wasn't created by an `S`
+    fn get<S: Strategy + 'static>(&self) -> &S::WriterTag {
+        assert_eq!(
+            self.strategy,
+            TypeId::of::<S>(),
+            "tried to use a writer tag with a different strategy"
+        );
+        // SAFETY: the tag was created by an `S`, which always erases its writer tag
+        unsafe { self.tag.get() }
+    }
+
+    /// get the writer tag of `S`
+    ///
+    /// # Panics
+    ///
+    /// if the tag wasn't created by an `S`
+    fn get_mut<S: Strategy + 'static>(&mut self) -> &mut S::WriterTag {
+        assert_eq!(
+            self.strategy,
+            TypeId::of::<S>(),
+            "tried to use a writer tag with a different strategy"
+        );
+        // SAFETY: the tag was created by an `S`, which always erases its writer tag
+        unsafe { self.tag.get_mut() }
+    }
+}
+
+/// The pause state of a [`BoxedStrategy`]
+///
+/// The strategy's pause state is created the first time it's used
+#[derive(Default)]
+pub struct ErasedPause {
+    /// the type of the strategy which created the state, and the state
+    state: Option<(TypeId, Erased)>,
+}
+
+impl ErasedPause {
+    /// get the pause state of `S`, creating it if this was empty or used by another strategy
+    fn get<S: Strategy + 'static>(&mut self) -> &mut S::Pause
+    where
+        S::Pause: Send + Sync,
+    {
+        let strategy = TypeId::of::<S>();
+        if !matches!(&self.state, Some((id, _)) if *id == strategy) {
+            self.state = None;
+        }
+        let (_, state) = self
+            .state
+            .get_or_insert_with(|| (strategy, Erased::new(S::Pause::default())));
+        // SAFETY: the state was created by an `S`, which always erases its pause state
+        unsafe { state.get_mut() }
+    }
+}
+
+/// mod to seal [`DynStrategy`]
+mod seal {
+    /// the seal for [`DynStrategy`](super::DynStrategy)
+    pub trait Seal {}
+}
+
+/// An object safe subset of [`Strategy`], see the module docs for details
+///
+/// This is implemented for every [`Strategy`] which is `Send + Sync + 'static`,
+/// uses an [`AtomicFlag`], can't fail validation, and whose associated types are `Send + Sync`.
+/// It can't be implemented outside of this crate.
+///
+/// Each method forwards to the method of the same name on [`Strategy`], and has the same
+/// safety requirements. On top of those, every [`Erased`] passed in must have been
+/// created by the same method of this strategy which creates that kind of value.
+pub trait DynStrategy: Send + Sync + seal::Seal {
+    /// see [`Strategy::create_writer_tag`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn create_writer_tag(&mut self) -> ErasedWriterTag;
+
+    /// see [`Strategy::create_reader_tag_from_writer`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn create_reader_tag_from_writer(&self, parent: &ErasedWriterTag) -> Erased;
+
+    /// see [`Strategy::create_reader_tag_from_reader`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn create_reader_tag_from_reader(&self, parent: &Erased) -> Erased;
+
+    /// see [`Strategy::create_reader_tag`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn create_reader_tag(&self) -> Erased;
+
+    /// see [`Strategy::validate_swap`]
+    ///
+    /// # Panics
+    ///
+    /// if the writer tag was created by a different strategy
+    fn validate_swap(&self, writer: &mut ErasedWriterTag) -> Erased;
+
+    /// see [`Strategy::capture_readers`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn capture_readers(&self, writer: &mut ErasedWriterTag, token: Erased) -> Erased;
+
+    /// see [`Strategy::capture_current_readers`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn capture_current_readers(&self, writer: &mut ErasedWriterTag, token: Erased)
+        -> Erased;
+
+    /// see [`Strategy::have_readers_exited`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn have_readers_exited(&self, writer: &ErasedWriterTag, capture: &mut Erased) -> bool;
+
+    /// see [`Strategy::pause`]
+    ///
+    /// # Panics
+    ///
+    /// if the writer tag was created by a different strategy
+    fn pause(&self, writer: &ErasedWriterTag, pause: &mut ErasedPause);
+
+    /// see [`Strategy::has_readers`]
+    fn has_readers(&self) -> bool;
+
+    /// see [`Strategy::any_current_readers`]
+    fn any_current_readers(&self) -> bool;
+
+    /// see [`Strategy::begin_read_guard`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn begin_read_guard(&self, reader: &mut Erased) -> Erased;
+
+    /// see [`Strategy::try_begin_read_guard`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn try_begin_read_guard(
+        &self,
+        reader: &mut Erased,
+        pause: &mut ErasedPause,
+    ) -> Result<Erased, WouldBlock>;
+
+    /// see [`Strategy::end_read_guard`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn end_read_guard(&self, reader: &mut Erased, guard: Erased);
+
+    /// see [`Strategy::preallocate_reader`]
+    ///
+    /// # Safety
+    ///
+    /// see the trait docs
+    unsafe fn preallocate_reader(&self, reader: &mut Erased);
+}
+
+impl<S> seal::Seal for S
+where
+    S: Strategy<Which = AtomicFlag, ValidationError = Infallible> + Send + Sync + 'static,
+    S::WriterTag: Send + Sync,
+    S::ReaderTag: Send + Sync,
+    S::ValidationToken: Send + Sync,
+    S::Capture: Send + Sync,
+    S::ReaderGuard: Send + Sync,
+    S::Pause: Send + Sync,
+{
+}
+
+impl<S> DynStrategy for S
+where
+    S: Strategy<Which = AtomicFlag, ValidationError = Infallible> + Send + Sync + 'static,
+    S::WriterTag: Send + Sync,
+    S::ReaderTag: Send + Sync,
+    S::ValidationToken: Send + Sync,
+    S::Capture: Send + Sync,
+    S::ReaderGuard: Send + Sync,
+    S::Pause: Send + Sync,
+{
+    unsafe fn create_writer_tag(&mut self) -> ErasedWriterTag {
+        ErasedWriterTag {
+            strategy: TypeId::of::<S>(),
+            // SAFETY: forwarded from the caller
+            tag: Erased::new(unsafe { S::create_writer_tag(self) }),
+        }
+    }
+
+    unsafe fn create_reader_tag_from_writer(&self, parent: &ErasedWriterTag) -> Erased {
+        // SAFETY: forwarded from the caller
+        Erased::new(unsafe { S::create_reader_tag_from_writer(self, parent.get::<S>()) })
+    }
+
+    unsafe fn create_reader_tag_from_reader(&self, parent: &Erased) -> Erased {
+        // SAFETY: the caller ensures that the parent is a reader tag of this strategy
+        Erased::new(unsafe { S::create_reader_tag_from_reader(self, parent.get()) })
+    }
+
+    unsafe fn create_reader_tag(&self) -> Erased {
+        // SAFETY: forwarded from the caller
+        Erased::new(unsafe { S::create_reader_tag(self) })
+    }
+
+    fn validate_swap(&self, writer: &mut ErasedWriterTag) -> Erased {
+        let Ok(token) = S::validate_swap(self, writer.get_mut::<S>());
+        Erased::new(token)
+    }
+
+    unsafe fn capture_readers(&self, writer: &mut ErasedWriterTag, token: Erased) -> Erased {
+        // SAFETY: the caller ensures that the token came from this strategy's `validate_swap`
+        let token = unsafe { token.into_inner() };
+        // SAFETY: forwarded from the caller
+        Erased::new(unsafe { S::capture_readers(self, writer.get_mut::<S>(), token) })
+    }
+
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut ErasedWriterTag,
+        token: Erased,
+    ) -> Erased {
+        // SAFETY: the caller ensures that the token came from this strategy's `validate_swap`
+        let token = unsafe { token.into_inner() };
+        // SAFETY: forwarded from the caller
+        Erased::new(unsafe { S::capture_current_readers(self, writer.get_mut::<S>(), token) })
+    }
+
+    unsafe fn have_readers_exited(&self, writer: &ErasedWriterTag, capture: &mut Erased) -> bool {
+        // SAFETY: the caller ensures that the capture came from this strategy,
+        // the rest is forwarded from the caller
+        unsafe { S::have_readers_exited(self, writer.get::<S>(), capture.get_mut()) }
+    }
+
+    fn pause(&self, writer: &ErasedWriterTag, pause: &mut ErasedPause) {
+        S::pause(self, writer.get::<S>(), pause.get::<S>())
+    }
+
+    fn has_readers(&self) -> bool {
+        S::has_readers(self)
+    }
+
+    fn any_current_readers(&self) -> bool {
+        S::any_current_readers(self)
+    }
+
+    unsafe fn begin_read_guard(&self, reader: &mut Erased) -> Erased {
+        // SAFETY: the caller ensures that the reader tag came from this strategy,
+        // the rest is forwarded from the caller
+        Erased::new(unsafe { S::begin_read_guard(self, reader.get_mut()) })
+    }
+
+    unsafe fn try_begin_read_guard(
+        &self,
+        reader: &mut Erased,
+        pause: &mut ErasedPause,
+    ) -> Result<Erased, WouldBlock> {
+        // SAFETY: the caller ensures that the reader tag came from this strategy,
+        // the rest is forwarded from the caller
+        unsafe { S::try_begin_read_guard(self, reader.get_mut(), pause.get::<S>()) }
+            .map(Erased::new)
+    }
+
+    unsafe fn end_read_guard(&self, reader: &mut Erased, guard: Erased) {
+        // SAFETY: the caller ensures that the reader tag and guard came from this strategy
+        let guard = unsafe { guard.into_inner() };
+        // SAFETY: the caller ensures that the reader tag came from this strategy,
+        // the rest is forwarded from the caller
+        unsafe { S::end_read_guard(self, reader.get_mut(), guard) }
+    }
+
+    unsafe fn preallocate_reader(&self, reader: &mut Erased) {
+        // SAFETY: the caller ensures that the reader tag came from this strategy,
+        // the rest is forwarded from the caller
+        unsafe { S::preallocate_reader(self, reader.get_mut()) }
+    }
+}
+
+// SAFETY: this forwards to the boxed strategy, and `DynStrategy` upholds
+// the same requirements as `Strategy` for each method
+unsafe impl Strategy for BoxedStrategy {
+    type WriterTag = ErasedWriterTag;
+    type ReaderTag = Erased;
+    type Which = AtomicFlag;
+    type ValidationToken = Erased;
+    type ValidationError = Infallible;
+    type Capture = Erased;
+    type ReaderGuard = Erased;
+    type Pause = ErasedPause;
+
+    #[inline]
+    unsafe fn create_writer_tag(&mut self) -> Self::WriterTag {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).create_writer_tag() }
+    }
+
+    #[inline]
+    unsafe fn create_reader_tag_from_writer(&self, parent: &Self::WriterTag) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).create_reader_tag_from_writer(parent) }
+    }
+
+    #[inline]
+    unsafe fn create_reader_tag_from_reader(&self, parent: &Self::ReaderTag) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller, reader tags only come from this strategy
+        unsafe { (**self).create_reader_tag_from_reader(parent) }
+    }
+
+    #[inline]
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).create_reader_tag() }
+    }
+
+    #[inline]
+    fn dangling_reader_tag() -> Self::ReaderTag {
+        Erased::empty()
+    }
+
+    #[inline]
+    fn validate_swap(
+        &self,
+        writer: &mut Self::WriterTag,
+    ) -> Result<Self::ValidationToken, Self::ValidationError> {
+        Ok((**self).validate_swap(writer))
+    }
+
+    #[inline]
+    unsafe fn capture_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).capture_readers(writer, validation_token) }
+    }
+
+    #[inline]
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).capture_current_readers(writer, validation_token) }
+    }
+
+    #[inline]
+    unsafe fn have_readers_exited(
+        &self,
+        writer: &Self::WriterTag,
+        capture: &mut Self::Capture,
+    ) -> bool {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).have_readers_exited(writer, capture) }
+    }
+
+    #[inline]
+    fn pause(&self, writer: &Self::WriterTag, pause: &mut Self::Pause) {
+        (**self).pause(writer, pause)
+    }
+
+    #[inline]
+    fn has_readers(&self) -> bool {
+        (**self).has_readers()
+    }
+
+    #[inline]
+    fn any_current_readers(&self) -> bool {
+        (**self).any_current_readers()
+    }
+
+    #[inline]
+    unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).begin_read_guard(reader) }
+    }
+
+    #[inline]
+    unsafe fn try_begin_read_guard(
+        &self,
+        reader: &mut Self::ReaderTag,
+        pause: &mut Self::Pause,
+    ) -> Result<Self::ReaderGuard, WouldBlock> {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).try_begin_read_guard(reader, pause) }
+    }
+
+    #[inline]
+    unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, guard: Self::ReaderGuard) {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).end_read_guard(reader, guard) }
+    }
+
+    #[inline]
+    unsafe fn preallocate_reader(&self, reader: &mut Self::ReaderTag) {
+        // SAFETY: forwarded from the caller
+        unsafe { (**self).preallocate_reader(reader) }
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_builtin_strategies_fit_inline() {
+    use super::{AdaptiveStrategy, HazardStrategy, TrackingStrategy};
+
+    fn fits<S: Strategy>() -> bool {
+        Erased::fits_inline::<S::WriterTag>()
+            && Erased::fits_inline::<S::ReaderTag>()
+            && Erased::fits_inline::<S::ValidationToken>()
+            && Erased::fits_inline::<S::Capture>()
+            && Erased::fits_inline::<S::ReaderGuard>()
+            && Erased::fits_inline::<S::Pause>()
+    }
+
+    assert!(fits::<HazardStrategy>());
+    assert!(fits::<AdaptiveStrategy>());
+    assert!(fits::<TrackingStrategy>());
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_boxed_strategy() {
+    use super::{AdaptiveStrategy, HazardStrategy, TrackingStrategy};
+    use crate::{
+        delayed::DelayedWriter,
+        ptrs::alloc::Owned,
+        raw::{RawDBuf, Writer},
+    };
+
+    fn check(strategy: BoxedStrategy) {
+        let mut writer = DelayedWriter::from(Writer::new(Owned::new(DynShared::from_raw_parts(
+            strategy,
+            RawDBuf::new(std::vec![0], std::vec![0]),
+        ))));
+        let mut reader = writer.reader();
+        let mut other = reader.clone();
+
+        writer.finish_swap().split_mut().writer.push(1);
+        writer.start_buffer_swap();
+        assert_eq!(*reader.get(), [0, 1]);
+
+        // the other reader holds the published buffer while the writer swaps away from it
+        writer.finish_swap().split_mut().writer.push(2);
+        let guard = other.checked_get().unwrap();
+        writer.start_buffer_swap();
+        assert!(writer.try_writer_mut().is_none());
+        drop(guard);
+        assert!(writer.try_writer_mut().is_some());
+        assert_eq!(*reader.get(), [0, 2]);
+
+        drop(reader);
+        drop(other);
+        writer.finish_swap().swap_buffers();
+    }
+
+    check(Box::new(HazardStrategy::new()));
+    check(Box::new(AdaptiveStrategy::new()));
+    check(Box::new(TrackingStrategy::new()));
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+#[should_panic = "tried to use a writer tag with a different strategy"]
+fn test_boxed_strategy_wrong_writer_tag() {
+    use super::{HazardStrategy, TrackingStrategy};
+
+    let mut hazard: BoxedStrategy = Box::new(HazardStrategy::new());
+    let tracking: BoxedStrategy = Box::<TrackingStrategy>::default();
+    // SAFETY: the tag is only used with the wrong strategy, which panics
+    let mut tag = unsafe { Strategy::create_writer_tag(&mut hazard) };
+    let _ = Strategy::validate_swap(&tracking, &mut tag);
+}
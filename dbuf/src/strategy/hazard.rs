@@ -51,43 +51,93 @@
 //! once it find sa node it will update it's local cache. Then when the read ends, it will
 //! clear out the active reader in it's cache (but keep it in the cache).
 //!
+//! Cloned readers start out with an empty cache, otherwise the parent and child would
+//! contend on the same node until one of them finds a different node.
+//!
 //! ### Swaps
 //!
 //! When the writer wants to swap
 //! * the [`HazardStrategy`] will increment the generation counter (by 2 to stay odd)
 //! * the writer will swap the buffers
 //! * the [`HazardStrategy`] iterate over the entire list and setup the `next_captured` sub-sequence of
-//! readers which are still in the previous generation.
+//! readers which are not in the new generation.
 //! * while this subsequence is non-empty the [`HazardStrategy`] will iterate over the sub-sequence and remove
-//! elements from the sub-sequence which have are `EMPTY` or not in the same generation.
+//! elements from the sub-sequence which have are `EMPTY` or in the new generation.
+//!
+//! Readers are usually in the previous generation, but a reader may load the generation before a swap
+//! and only store it in its node after that swap finished. Then the reader is two or more generations old,
+//! and may be reading either buffer, so the next swap must wait for it too.
+//!
+//! ### Lazy generations
+//!
+//! Every reader loads the generation, so incrementing it invalidates that cache line for all readers.
+//! If no reader started a read guard since the last swap, then there is nothing to capture, so
+//! the increment and the list walk are skipped. Readers set `read_since_swap` with an `AcqRel`
+//! read-modify-write after storing their generation in their node, and before loading which buffer
+//! to read. The writer clears `read_since_swap` with an `AcqRel` read-modify-write before the increment.
+//! Read-modify-writes always see the latest value, so a reader never sees a mark from before a swap
+//! cleared it, and no fences are needed.
+//!
+//! If `read_since_swap` was clear, then the writer swaps the buffers without incrementing the generation,
+//! and reads `read_since_swap` again afterwards with another `AcqRel` read-modify-write.
+//! * if it's still clear, then any reader which sets it later syncronizes with that read,
+//!   so it sees the swapped buffers, and there are no readers in the new writer buffer
+//! * if a reader set it in the meantime, then the writer syncronizes with that reader, so it sees the
+//!   reader's node. The writer increments the generation after all and captures every reader which isn't
+//!   in the new generation. This may capture readers of the new reader buffer, which only makes the swap wait
+//!
+//! ## Progress
+//!
+//! Readers never wait for the writer, and never retry because of it. Acquiring a guard is a single
+//! compare-exchange on the reader's cached node, and a swap of `read_since_swap`. If that node is in use,
+//! then the reader walks the list looking for a free node, and only pushes a new node if there isn't one. Pushing may retry if another
+//! reader pushed at the same time, so acquiring a guard is lock-free, and wait-free when the cached node is free.
+//! Releasing a guard is a single store.
+//!
+//! The writer waits for the readers it captured, so a reader which holds a guard forever blocks the writer forever.
 
-use core::ptr;
 #[cfg(not(feature = "loom"))]
-use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
+use core::{alloc::Layout, ptr};
 #[cfg(feature = "loom")]
-use loom::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
-use std::boxed::Box;
+use loom::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
 
 use crate::{
-    interface::{Strategy, WaitStrategy},
+    interface::{
+        ActiveReaderInfo, CheapReaderTag, FreshCopy, Strategy, StrategyIntrospect, WaitStrategy,
+    },
     wait::DefaultWait,
 };
 
+/// the allocator for the reader nodes
+mod node_alloc;
 /// thread id info
 mod thread;
 
+pub use node_alloc::{GlobalNodeAlloc, NodeAlloc};
+
 /// A hazard pointer strategy
 ///
 /// a lock-free synchronization strategy
 ///
 /// see module level docs for details
-pub struct HazardStrategy<W = DefaultWait> {
+pub struct HazardStrategy<W = DefaultWait, A: NodeAlloc = GlobalNodeAlloc> {
     /// the head of the append-only linked list of possibly active readers
     ptr: AtomicPtr<ActiveReader>,
     /// the current generation
     generation: AtomicU32,
+    /// true once a reader tag was created, see [`Strategy::has_readers`]
+    has_readers: AtomicBool,
+    /// true if a reader started a read guard since the generation was last incremented,
+    /// see module docs for details
+    read_since_swap: AtomicBool,
     /// the waiting strategy
     wait: W,
+    /// the allocator for the reader nodes
+    alloc: A,
+    /// where the read guards were acquired, see [`HazardStrategy::capture_backtraces`]
+    #[cfg(feature = "debug-checks")]
+    backtraces: crate::debug_checks::GuardBacktraces,
 }
 
 /// a link in the linked list of possibly active readers
@@ -123,14 +173,45 @@ struct ActiveReader {
 
 impl HazardStrategy {
     /// Create a new hazard strategy
+    #[cfg(not(feature = "loom"))]
     pub const fn new() -> Self {
         Self::with_wait_strategy(crate::wait::DefaultWait::new())
     }
+
+    /// Create a new hazard strategy
+    #[cfg(feature = "loom")]
+    pub fn new() -> Self {
+        Self::with_wait_strategy(crate::wait::DefaultWait::new())
+    }
 }
 
-impl<W: Default> Default for HazardStrategy<W> {
+impl<A: NodeAlloc> HazardStrategy<DefaultWait, A> {
+    /// Create a new hazard strategy which allocates its reader nodes with `alloc`, see [`NodeAlloc`]
+    #[cfg(not(feature = "loom"))]
+    pub const fn with_allocator(alloc: A) -> Self {
+        Self::with_wait_strategy_and_allocator(DefaultWait::new(), alloc)
+    }
+
+    /// Create a new hazard strategy which allocates its reader nodes with `alloc`, see [`NodeAlloc`]
+    #[cfg(feature = "loom")]
+    pub fn with_allocator(alloc: A) -> Self {
+        Self::with_wait_strategy_and_allocator(DefaultWait::new(), alloc)
+    }
+}
+
+impl<W: Default, A: NodeAlloc + Default> Default for HazardStrategy<W, A> {
     fn default() -> Self {
-        Self::with_wait_strategy(W::default())
+        Self::with_wait_strategy_and_allocator(W::default(), A::default())
+    }
+}
+
+impl<W: FreshCopy, A: NodeAlloc + Clone> FreshCopy for HazardStrategy<W, A> {
+    fn fresh_copy(&self) -> Self {
+        let strategy =
+            Self::with_wait_strategy_and_allocator(self.wait.fresh_copy(), self.alloc.clone());
+        #[cfg(feature = "debug-checks")]
+        strategy.capture_backtraces(self.backtraces.is_enabled());
+        strategy
     }
 }
 
@@ -138,23 +219,60 @@ impl<W> HazardStrategy<W> {
     /// Create a new [`HazardStrategy`] with the given [`WaitStrategy`]
     #[cfg(not(feature = "loom"))]
     pub const fn with_wait_strategy(park: W) -> Self {
+        Self::with_wait_strategy_and_allocator(park, GlobalNodeAlloc)
+    }
+
+    /// Create a new [`HazardStrategy`] with the given [`WaitStrategy`]
+    #[cfg(feature = "loom")]
+    pub fn with_wait_strategy(park: W) -> Self {
+        Self::with_wait_strategy_and_allocator(park, GlobalNodeAlloc)
+    }
+}
+
+impl<W, A: NodeAlloc> HazardStrategy<W, A> {
+    /// Create a new [`HazardStrategy`] with the given [`WaitStrategy`] and [`NodeAlloc`]
+    #[cfg(not(feature = "loom"))]
+    pub const fn with_wait_strategy_and_allocator(park: W, alloc: A) -> Self {
         Self {
             ptr: AtomicPtr::new(ptr::null_mut()),
             generation: AtomicU32::new(1),
+            has_readers: AtomicBool::new(false),
+            read_since_swap: AtomicBool::new(false),
             wait: park,
+            alloc,
+            #[cfg(feature = "debug-checks")]
+            backtraces: crate::debug_checks::GuardBacktraces::new(),
         }
     }
 
-    /// Create a new [`HazardStrategy`] with the given [`WaitStrategy`]
+    /// Create a new [`HazardStrategy`] with the given [`WaitStrategy`] and [`NodeAlloc`]
     #[cfg(feature = "loom")]
-    pub fn with_park_strategy(park: W) -> Self {
+    pub fn with_wait_strategy_and_allocator(park: W, alloc: A) -> Self {
         Self {
             ptr: AtomicPtr::new(ptr::null_mut()),
             generation: AtomicU32::new(1),
+            has_readers: AtomicBool::new(false),
+            read_since_swap: AtomicBool::new(false),
             wait: park,
+            alloc,
+            #[cfg(feature = "debug-checks")]
+            backtraces: crate::debug_checks::GuardBacktraces::new(),
         }
     }
 
+    /// Record a backtrace each time a read guard is acquired, see [`debug_checks`](crate::debug_checks)
+    ///
+    /// The backtraces are reported by [`Writer::debug_guard_backtrace`](crate::raw::Writer::debug_guard_backtrace)
+    #[cfg(feature = "debug-checks")]
+    pub fn capture_backtraces(&self, on: bool) {
+        self.backtraces.set_enabled(on)
+    }
+
+    /// the waiting strategy
+    pub(crate) fn wait_strategy(&self) -> &W {
+        &self.wait
+    }
+
     /// create a new reader tag
     fn create_reader() -> ReaderTag {
         ReaderTag {
@@ -166,6 +284,9 @@ impl<W> HazardStrategy<W> {
 /// the writer tag for [`HazardStrategy`]
 pub struct WriterTag(());
 /// the reader tag for [`HazardStrategy`]
+///
+/// Copies of a tag share the cached node, so cloned readers get a new tag instead,
+/// see module docs for details
 #[derive(Clone, Copy)]
 pub struct ReaderTag {
     /// the node which the reader last used as active reader
@@ -175,6 +296,8 @@ pub struct ReaderTag {
 pub struct ValidationToken {
     /// the generation that we captured
     generation: u32,
+    /// true if the generation wasn't incremented because there were no reads since the last swap
+    lazy: bool,
 }
 /// the capture token for [`HazardStrategy`]
 pub struct Capture {
@@ -184,7 +307,10 @@ pub struct Capture {
     start: *mut ActiveReader,
 }
 /// the reader guard for [`HazardStrategy`]
-pub struct ReaderGuard(());
+///
+/// this holds the node which the guard stamped, instead of relying on the reader tag's cache,
+/// since the cache may be replaced if another guard is started with the same tag
+pub struct ReaderGuard(*mut ActiveReader);
 
 // SAFETY: ReaderTag follows the normal rules for data access
 // so we can implement Send and Sync for it
@@ -200,8 +326,15 @@ unsafe impl Send for Capture {}
 // so we can implement Send and Sync for it
 unsafe impl Sync for Capture {}
 
+// SAFETY: ReaderGuard only points to a node in the linked list, which is owned by the strategy
+// so we can implement Send and Sync for it
+unsafe impl Send for ReaderGuard {}
+// SAFETY: ReaderGuard only points to a node in the linked list, which is owned by the strategy
+// so we can implement Send and Sync for it
+unsafe impl Sync for ReaderGuard {}
+
 // SAFETY: FIXME
-unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
+unsafe impl<W: WaitStrategy, A: NodeAlloc> Strategy for HazardStrategy<W, A> {
     type WriterTag = WriterTag;
     type ReaderTag = ReaderTag;
     type Which = crate::raw::AtomicFlag;
@@ -218,10 +351,18 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
     }
 
     unsafe fn create_reader_tag_from_writer(&self, _parent: &Self::WriterTag) -> Self::ReaderTag {
+        self.has_readers.store(true, Ordering::Relaxed);
         Self::create_reader()
     }
 
     unsafe fn create_reader_tag_from_reader(&self, _parent: &Self::ReaderTag) -> Self::ReaderTag {
+        // the parent already set `has_readers`
+        // don't copy the parent's cached node, see module docs for details
+        Self::create_reader()
+    }
+
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        self.has_readers.store(true, Ordering::Relaxed);
         Self::create_reader()
     }
 
@@ -233,6 +374,22 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
         &self,
         _: &mut Self::WriterTag,
     ) -> Result<Self::ValidationToken, Self::ValidationError> {
+        // if there were no reads since the last swap, then there are no readers to tell apart,
+        // so don't invalidate the generation in every reader's cache. This must be cleared
+        // before the increment, see module docs for details. It's only cleared if it's set,
+        // so that swaps without reads don't need a read-modify-write. If the load is stale,
+        // then `capture_readers` sees the mark instead.
+        //
+        // AcqRel to syncronize with `mark_read`, see module docs for details
+        if !self.read_since_swap.load(Ordering::Relaxed)
+            || !self.read_since_swap.swap(false, Ordering::AcqRel)
+        {
+            return Ok(ValidationToken {
+                generation: self.generation.load(Ordering::Relaxed),
+                lazy: true,
+            });
+        }
+
         // increment the generation before swapping the buffers so that if a reader
         // sees the old generation, then it's guranteed that they have the old buffer
         // we use AcqRel here because:
@@ -240,15 +397,36 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
         // * Release: all subsequent readers should see this generation increment
         let generation = self.generation.fetch_add(2, Ordering::AcqRel);
 
-        Ok(ValidationToken { generation })
+        Ok(ValidationToken {
+            generation,
+            lazy: false,
+        })
     }
 
     unsafe fn capture_readers(
         &self,
         _: &mut Self::WriterTag,
-        ValidationToken { generation }: Self::ValidationToken,
+        ValidationToken { generation, lazy }: Self::ValidationToken,
     ) -> Self::Capture {
-        // create a sub-sequence of nodes which are in the given generation
+        let generation = if lazy {
+            // pairs with the read-modify-write in `mark_read`: either we see the reader's node
+            // and mark, or the reader sees the swapped buffers, see module docs for details.
+            // This only reads the flag, but it must be a read-modify-write to see the latest mark
+            if !self.read_since_swap.fetch_or(false, Ordering::AcqRel) {
+                return Capture {
+                    generation: 0,
+                    start: ptr::null_mut(),
+                };
+            }
+
+            // a reader started while the buffers were swapped, so it may be reading either buffer
+            // increment the generation now, and capture every reader which started before that
+            self.generation.fetch_add(2, Ordering::AcqRel)
+        } else {
+            generation
+        };
+
+        // create a sub-sequence of nodes which are not in the new generation
 
         // use an Acquire load to syncronize with `load_read_guard_slow`
         let head = self.ptr.load(Ordering::Acquire);
@@ -275,7 +453,7 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
             // Release ordering to store generation
             let current = active_reader.generation.load(Ordering::Acquire);
 
-            if current == generation {
+            if is_captured(current, generation) {
                 if sub_sequence_start.is_null() {
                     // if this is the first node, then set it to the start
                     sub_sequence_start = ptr;
@@ -312,14 +490,31 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
         // because that has shared access to the writer tag.
         unsafe { (*sub_sequence_prev).next_captured = ptr::null_mut() }
 
+        // start at the first captured node, the head's `next_captured` is only
+        // part of the sub-sequence if the head itself was captured
         Capture {
             generation,
-            start: head,
+            start: sub_sequence_start,
         }
     }
 
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: `validate_swap` bumped the generation, so every reader which holds a read guard
+        // is in an older generation, which is exactly what `capture_readers` captures
+        unsafe { self.capture_readers(writer, validation_token) }
+    }
+
     unsafe fn have_readers_exited(&self, _: &Self::WriterTag, capture: &mut Self::Capture) -> bool {
-        // here we iterate over the capture sub-sequence and remove nodes which are no longer in the previous generation
+        // here we iterate over the capture sub-sequence and remove nodes which are empty or in the new generation
+
+        // the capture is empty if there were no reads since the last swap, or it already finished
+        if capture.start.is_null() {
+            return true;
+        }
 
         // SAFETY: this ptr is guarnteed to be a sublist of `self.ptr.load(_)`
         // because we got it in `capture_readers`
@@ -335,15 +530,13 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
             let next = active_reader.next_captured;
             let reader_generation = current;
 
-            // these conditions should always be true given the unsafe requirement on `capture_readers`
+            // generations are always odd, and zero means the node is empty
             debug_assert!(
-                reader_generation == 0
-                    || reader_generation == generation
-                    || reader_generation == generation.wrapping_add(2),
-                "invalid generation pair {generation} / {reader_generation}"
+                reader_generation == 0 || reader_generation % 2 == 1,
+                "invalid generation {reader_generation}"
             );
 
-            if reader_generation == generation {
+            if is_captured(reader_generation, generation) {
                 // if the reader is still in the buffer, then update the capture sub-sequence
                 // to the current node (because all previous nodes are out of the sub-sequence,
                 // if they were not, we would have exitted earlier)
@@ -382,7 +575,12 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
                 Ordering::Release,
                 Ordering::Relaxed,
             ) {
-                Ok(_) => return ReaderGuard(()),
+                Ok(_) => {
+                    self.mark_read();
+                    #[cfg(feature = "debug-checks")]
+                    self.backtraces.record(reader.node as usize);
+                    return ReaderGuard(reader.node);
+                }
                 Err(_generation) => {}
             }
         }
@@ -391,15 +589,22 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
         // this minimizes contention and should improve throughput at the expense of a little memory
         let node = self.load_read_guard(generation);
         reader.node = node;
+        self.mark_read();
+        #[cfg(feature = "debug-checks")]
+        self.backtraces.record(node as usize);
 
-        ReaderGuard(())
+        ReaderGuard(node)
     }
 
-    unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, _: Self::ReaderGuard) {
+    unsafe fn end_read_guard(&self, _: &mut Self::ReaderTag, guard: Self::ReaderGuard) {
+        // forget the backtrace before the node can be reused by another reader
+        #[cfg(feature = "debug-checks")]
+        self.backtraces.clear(guard.0 as usize);
+
         // SAFETY: we never remove links from the linked list
         // and we only create valid links for `ReaderGuard`
         // so the link in the guard is still valid
-        unsafe { (*reader.node).generation.store(0, Ordering::Release) };
+        unsafe { (*guard.0).generation.store(0, Ordering::Release) };
 
         self.wait.notify();
     }
@@ -407,9 +612,98 @@ unsafe impl<W: WaitStrategy> Strategy for HazardStrategy<W> {
     fn pause(&self, _writer: &Self::WriterTag, pause: &mut Self::Pause) {
         self.wait.wait(pause);
     }
+
+    unsafe fn preallocate_reader(&self, reader: &mut Self::ReaderTag) {
+        // give the reader its own free node, so that its read guards find it in the cache.
+        // reusing a free node from the list could share it with another reader
+        if reader.node.is_null() {
+            reader.node = self.load_read_guard_slow(0);
+        }
+    }
+
+    fn has_readers(&self) -> bool {
+        self.has_readers.load(Ordering::Relaxed)
+    }
+
+    fn any_current_readers(&self) -> bool {
+        if !self.has_readers() {
+            return false;
+        }
+
+        let mut ptr = self.ptr.load(Ordering::Acquire);
+
+        // SAFETY: we never remove links from the linked list so the ptr is either null or valid
+        while let Some(active_reader) = unsafe { ptr.as_ref() } {
+            // empty nodes have a generation of zero
+            if active_reader.generation.load(Ordering::Relaxed) != 0 {
+                return true;
+            }
+
+            ptr = active_reader.next;
+        }
+
+        false
+    }
 }
 
-impl<B: crate::interface::RawBuffers> crate::interface::DefaultOwned<B> for HazardStrategy {
+// creating a reader tag doesn't allocate, the first read guard of a tag reuses a free node
+impl<W: WaitStrategy, A: NodeAlloc> CheapReaderTag for HazardStrategy<W, A> {}
+
+impl<W: WaitStrategy, A: NodeAlloc> StrategyIntrospect for HazardStrategy<W, A> {
+    fn active_readers(&self, mut f: impl FnMut(ActiveReaderInfo)) {
+        let mut ptr = self.ptr.load(Ordering::Acquire);
+
+        // SAFETY: we never remove links from the linked list so the ptr is either null or valid
+        while let Some(active_reader) = unsafe { ptr.as_ref() } {
+            let generation = active_reader.generation.load(Ordering::Relaxed);
+
+            if generation != 0 {
+                f(ActiveReaderInfo {
+                    id: ptr as usize,
+                    generation: generation as usize,
+                })
+            }
+
+            ptr = active_reader.next;
+        }
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn guard_backtrace(
+        &self,
+        reader: &ActiveReaderInfo,
+    ) -> Option<std::sync::Arc<std::backtrace::Backtrace>> {
+        self.backtraces.get(reader.id)
+    }
+
+    #[cfg(feature = "debug-checks")]
+    fn guard_thread(&self, reader: &ActiveReaderInfo) -> Option<std::thread::ThreadId> {
+        self.backtraces.thread(reader.id)
+    }
+
+    fn maintain(&self) {
+        // empty nodes are reused by other readers, and are only freed when the
+        // strategy is dropped, so there's nothing to clean up here yet
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        let mut nodes = 0;
+        let mut ptr = self.ptr.load(Ordering::Acquire);
+
+        // SAFETY: we never remove links from the linked list so the ptr is either null or valid
+        while let Some(active_reader) = unsafe { ptr.as_ref() } {
+            nodes += 1;
+            ptr = active_reader.next;
+        }
+
+        nodes * Layout::new::<ActiveReader>().size()
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+impl<B: crate::interface::RawBuffers, W: WaitStrategy, A: NodeAlloc>
+    crate::interface::DefaultOwned<B> for HazardStrategy<W, A>
+{
     type IntoStrongRefWithWeak = crate::ptrs::alloc::OwnedWithWeak<Self, B>;
     type StrongRefWithWeak = crate::ptrs::alloc::OwnedStrong<Self, B>;
     type WeakRef = crate::ptrs::alloc::OwnedWeak<Self, B>;
@@ -426,7 +720,29 @@ impl<B: crate::interface::RawBuffers> crate::interface::DefaultOwned<B> for Haza
     }
 }
 
-impl<W> HazardStrategy<W> {
+/// check if a reader in `reader_generation` must be waited on by a swap which validated in `generation`
+///
+/// Usually a reader is in the previous generation, but a reader may load the generation right
+/// before a swap and only stamp its node after that swap finished. Then it's in an older generation,
+/// and may be reading either buffer. So every reader that isn't in the new generation is captured.
+fn is_captured(reader_generation: u32, generation: u32) -> bool {
+    reader_generation != 0 && reader_generation != generation.wrapping_add(2)
+}
+
+impl<W, A: NodeAlloc> HazardStrategy<W, A> {
+    /// tell the writer that a reader started a read guard since the last swap
+    ///
+    /// this must be called after the reader stored `generation` in its node,
+    /// and before it loads which buffer to read, see module docs for details
+    #[inline]
+    fn mark_read(&self) {
+        // this must be a read-modify-write even if the flag is already set, a plain load may
+        // see the flag from before a swap cleared it, and then that swap wouldn't see our node
+        //
+        // AcqRel to pair with the read-modify-writes in `validate_swap` and `capture_readers`
+        self.read_since_swap.swap(true, Ordering::AcqRel);
+    }
+
     /// Load the reader guard from the linked list because the reader node cache failed
     #[cold]
     fn load_read_guard(&self, generation: u32) -> *mut ActiveReader {
@@ -481,12 +797,21 @@ impl<W> HazardStrategy<W> {
     #[cold]
     fn load_read_guard_slow(&self, generation: u32) -> *mut ActiveReader {
         // the list is full so allocate a new node to push onto the head of the list
-        let active_reader = Box::into_raw(Box::new(ActiveReader {
-            next: ptr::null_mut(),
-            next_captured: ptr::null_mut(),
-            generation: AtomicU32::new(generation),
-            affinity: thread::ThreadId::current(),
-        }));
+        let layout = Layout::new::<ActiveReader>();
+        let active_reader = match self.alloc.alloc(layout) {
+            Some(block) => block.cast::<ActiveReader>().as_ptr(),
+            None => std::alloc::handle_alloc_error(layout),
+        };
+
+        // SAFETY: `NodeAlloc` ensures that the block is valid for writes of an `ActiveReader`
+        unsafe {
+            active_reader.write(ActiveReader {
+                next: ptr::null_mut(),
+                next_captured: ptr::null_mut(),
+                generation: AtomicU32::new(generation),
+                affinity: thread::ThreadId::current(),
+            })
+        };
 
         let mut ptr = self.ptr.load(Ordering::Acquire);
 
@@ -510,7 +835,7 @@ impl<W> HazardStrategy<W> {
     }
 }
 
-impl<W> Drop for HazardStrategy<W> {
+impl<W, A: NodeAlloc> Drop for HazardStrategy<W, A> {
     fn drop(&mut self) {
         #[cfg(feature = "loom")]
         let mut ptr = self.ptr.with_mut(|a| *a);
@@ -523,8 +848,15 @@ impl<W> Drop for HazardStrategy<W> {
             let next = unsafe { (*ptr).next };
 
             // SAFETY: we never remove links from the linked list so the ptr is either null or valid
-            // and we checked that the current link is non-null
-            unsafe { Box::from_raw(ptr) };
+            // and we checked that the current link is non-null. Every node was allocated by
+            // `load_read_guard_slow` from `self.alloc` with this layout
+            unsafe {
+                ptr::drop_in_place(ptr);
+                self.alloc.dealloc(
+                    ptr::NonNull::new_unchecked(ptr).cast(),
+                    Layout::new::<ActiveReader>(),
+                );
+            }
 
             ptr = next;
         }
@@ -594,7 +926,7 @@ mod test {
         use crate::wait::SpinWait;
 
         loom::model(|| {
-            let mut shared = crate::raw::Shared::new(
+            let shared = crate::raw::Shared::new(
                 super::HazardStrategy::<SpinWait>::default(),
                 crate::raw::RawDBuf::new(0, 0),
             );
@@ -646,4 +978,258 @@ mod test {
 
         // assert!(writer.is_swap_finished(&mut swap));
     }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_guard_held_across_two_swaps() {
+        let mut shared = crate::raw::Shared::from_raw_parts(
+            super::HazardStrategy::new(),
+            crate::raw::RawDBuf::new(0, 0),
+        );
+        let mut writer = crate::delayed::DelayedWriter::from(crate::raw::Writer::new(&mut shared));
+        let mut reader = writer.reader();
+
+        // the guard is taken after the flip, so it's in the new reader buffer
+        // and the first swap doesn't wait for it
+        writer.start_buffer_swap();
+        let guard = reader.get();
+        assert!(writer.is_swap_finished());
+
+        // the second swap targets the buffer the guard is reading
+        writer.start_buffer_swap();
+        assert!(!writer.is_swap_finished());
+        assert!(!writer.is_swap_finished());
+
+        drop(guard);
+        assert!(writer.is_swap_finished());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_idle_head_doesnt_hide_readers() {
+        let mut shared = crate::raw::Shared::from_raw_parts(
+            super::HazardStrategy::new(),
+            crate::raw::RawDBuf::new(0, 0),
+        );
+        let mut writer = crate::delayed::DelayedWriter::from(crate::raw::Writer::new(&mut shared));
+        let mut reader = writer.reader();
+        let mut idle = writer.reader();
+
+        let guard = reader.get();
+        // the idle reader's node is pushed in front of the busy reader's node
+        drop(idle.get());
+
+        writer.start_buffer_swap();
+        assert!(!writer.is_swap_finished());
+
+        drop(guard);
+        assert!(writer.is_swap_finished());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_stale_generation_is_captured() {
+        use crate::interface::Strategy;
+        use core::sync::atomic::Ordering;
+
+        let mut strategy = super::HazardStrategy::new();
+
+        // SAFETY: the tags are only used with this strategy, and every swap is finished before the next one
+        unsafe {
+            let mut writer = strategy.create_writer_tag();
+            let mut reader = strategy.create_reader_tag();
+
+            // give the reader a node
+            let guard = strategy.begin_read_guard(&mut reader);
+            strategy.end_read_guard(&mut reader, guard);
+
+            let Ok(token) = strategy.validate_swap(&mut writer);
+            let mut capture = strategy.capture_readers(&mut writer, token);
+            assert!(strategy.have_readers_exited(&writer, &mut capture));
+
+            // a reader which loaded the generation before the first swap, but only
+            // stamped its node after the swap finished, so it may be reading either buffer
+            // `mark_read` always marks the read, so the next swap captures it
+            (*reader.node).generation.store(1, Ordering::Release);
+            strategy.read_since_swap.store(true, Ordering::Relaxed);
+
+            let Ok(token) = strategy.validate_swap(&mut writer);
+            let mut capture = strategy.capture_readers(&mut writer, token);
+            assert!(!strategy.have_readers_exited(&writer, &mut capture));
+
+            (*reader.node).generation.store(0, Ordering::Release);
+            assert!(strategy.have_readers_exited(&writer, &mut capture));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_overlapping_guards_on_one_tag() {
+        use crate::interface::Strategy;
+
+        let mut strategy = super::HazardStrategy::new();
+
+        // SAFETY: the tags are only used with this strategy, and every swap is finished before the next one
+        unsafe {
+            let mut writer = strategy.create_writer_tag();
+            let mut reader = strategy.create_reader_tag();
+
+            // the second guard can't use the tag's cached node, so it replaces the cache
+            let a = strategy.begin_read_guard(&mut reader);
+            let b = strategy.begin_read_guard(&mut reader);
+            strategy.end_read_guard(&mut reader, b);
+            strategy.end_read_guard(&mut reader, a);
+
+            // if ending `a` cleared the cached node instead of its own, this would never finish
+            let Ok(token) = strategy.validate_swap(&mut writer);
+            let mut capture = strategy.capture_readers(&mut writer, token);
+            assert!(strategy.have_readers_exited(&writer, &mut capture));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_node_allocator() {
+        use super::{GlobalNodeAlloc, NodeAlloc};
+        use core::{
+            alloc::Layout,
+            ptr::NonNull,
+            sync::atomic::{AtomicUsize, Ordering},
+        };
+        use std::vec::Vec;
+
+        /// counts the nodes allocated and freed
+        #[derive(Default)]
+        struct CountingNodeAlloc {
+            /// the number of allocated nodes
+            allocated: AtomicUsize,
+            /// the number of freed nodes
+            freed: AtomicUsize,
+        }
+
+        // SAFETY: forwards to the global allocator
+        unsafe impl NodeAlloc for CountingNodeAlloc {
+            fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+                self.allocated.fetch_add(1, Ordering::Relaxed);
+                GlobalNodeAlloc.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.freed.fetch_add(1, Ordering::Relaxed);
+                // SAFETY: forwarded from the caller
+                unsafe { GlobalNodeAlloc.dealloc(ptr, layout) }
+            }
+        }
+
+        let alloc = CountingNodeAlloc::default();
+        let mut shared = crate::raw::Shared::from_raw_parts(
+            super::HazardStrategy::with_allocator(&alloc),
+            crate::raw::RawDBuf::new(0, 0),
+        );
+        let writer = crate::raw::Writer::new(&mut shared);
+        let mut readers = (0..4).map(|_| writer.reader()).collect::<Vec<_>>();
+
+        // each guard which is held at the same time needs its own node
+        let guards = readers
+            .iter_mut()
+            .map(|reader| reader.get())
+            .collect::<Vec<_>>();
+        assert_eq!(alloc.allocated.load(Ordering::Relaxed), 4);
+        drop(guards);
+
+        // and the nodes are reused afterwards
+        let guards = readers
+            .iter_mut()
+            .map(|reader| reader.get())
+            .collect::<Vec<_>>();
+        assert_eq!(alloc.allocated.load(Ordering::Relaxed), 4);
+        drop(guards);
+
+        assert_eq!(alloc.freed.load(Ordering::Relaxed), 0);
+        drop(readers);
+        drop(shared);
+        assert_eq!(alloc.freed.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_lazy_generation() {
+        use crate::interface::Strategy;
+        use core::sync::atomic::Ordering;
+
+        let mut strategy = super::HazardStrategy::new();
+
+        // SAFETY: the tags are only used with this strategy, and every swap is finished before the next one
+        unsafe {
+            let mut writer = strategy.create_writer_tag();
+            let mut reader = strategy.create_reader_tag();
+
+            let guard = strategy.begin_read_guard(&mut reader);
+            strategy.end_read_guard(&mut reader, guard);
+
+            // there was a read, so the generation is incremented
+            let Ok(token) = strategy.validate_swap(&mut writer);
+            let mut capture = strategy.capture_readers(&mut writer, token);
+            assert!(strategy.have_readers_exited(&writer, &mut capture));
+            assert_eq!(strategy.generation.load(Ordering::Relaxed), 3);
+
+            // no reads since the last swap, so the generation is left alone
+            for _ in 0..3 {
+                let Ok(token) = strategy.validate_swap(&mut writer);
+                let mut capture = strategy.capture_readers(&mut writer, token);
+                assert!(strategy.have_readers_exited(&writer, &mut capture));
+            }
+            assert_eq!(strategy.generation.load(Ordering::Relaxed), 3);
+
+            // a reader which starts in the middle of a lazy swap forces the increment
+            let Ok(token) = strategy.validate_swap(&mut writer);
+            let guard = strategy.begin_read_guard(&mut reader);
+            let mut capture = strategy.capture_readers(&mut writer, token);
+            assert_eq!(strategy.generation.load(Ordering::Relaxed), 5);
+            assert!(!strategy.have_readers_exited(&writer, &mut capture));
+            strategy.end_read_guard(&mut reader, guard);
+            assert!(strategy.have_readers_exited(&writer, &mut capture));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "loom")]
+    fn test_loom_lazy_generation() {
+        use crate::wait::SpinWait;
+        use loom::cell::UnsafeCell;
+
+        /// a buffer which lets loom check for data races between the writer and readers
+        struct Buffer(UnsafeCell<u32>);
+
+        // SAFETY: the double buffer ensures that the writer never writes while a reader is reading
+        unsafe impl Sync for Buffer {}
+
+        loom::model(|| {
+            let shared = crate::raw::Shared::new(
+                super::HazardStrategy::<SpinWait>::default(),
+                crate::raw::RawDBuf::new(Buffer(UnsafeCell::new(0)), Buffer(UnsafeCell::new(0))),
+            );
+            let mut writer = crate::raw::Writer::new(crate::ptrs::alloc::Owned::new(shared));
+            let mut reader = writer.reader();
+
+            let thread = loom::thread::spawn(move || {
+                let guard = reader.get();
+                // SAFETY: the read guard keeps the writer from writing to this buffer
+                guard.0.with(|value| unsafe { *value })
+            });
+
+            // depending on when the reader starts, either swap may skip the generation increment
+            // if a skipped increment misses the reader, then loom will report one of these writes
+            // as racing with the read above
+            for i in 1..=2 {
+                writer.swap_buffers();
+                writer.split_mut().writer.0.with_mut(|value| {
+                    // SAFETY: all readers have exited the writer buffer
+                    unsafe { *value = i }
+                });
+            }
+
+            assert!(thread.join().unwrap() <= 1);
+        })
+    }
 }
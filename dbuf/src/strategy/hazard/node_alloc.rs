@@ -0,0 +1,69 @@
+use core::{alloc::Layout, ptr::NonNull};
+
+/// The allocator for the reader nodes of a [`HazardStrategy`](super::HazardStrategy)
+///
+/// A [`HazardStrategy`](super::HazardStrategy) allocates a node each time more readers hold a
+/// read guard at the same time than ever before, and frees all of them when it's dropped.
+/// This is the only allocation the strategy makes at runtime, so on a constrained heap it can be
+/// routed to a dedicated pool with [`HazardStrategy::with_allocator`](super::HazardStrategy::with_allocator).
+///
+/// The owning pointers in [`ptrs::alloc`](crate::ptrs::alloc) always use the global allocator,
+/// but they only allocate once, when the double buffer is created.
+///
+/// # Safety
+///
+/// A block returned from [`alloc`](NodeAlloc::alloc) must be valid for reads and writes of
+/// `layout`, and stay valid until it's passed to [`dealloc`](NodeAlloc::dealloc)
+pub unsafe trait NodeAlloc {
+    /// Allocate a block which fits `layout`, or return `None` if there isn't enough memory
+    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>>;
+
+    /// Free a block
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned from [`alloc`](NodeAlloc::alloc) on this allocator with
+    /// the same `layout`, and must not have been freed yet
+    unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// The global allocator, the default [`NodeAlloc`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GlobalNodeAlloc;
+
+// SAFETY: the global allocator hands out blocks valid for `layout` until they are freed
+unsafe impl NodeAlloc for GlobalNodeAlloc {
+    #[inline]
+    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        if layout.size() == 0 {
+            // the global allocator doesn't support zero-sized blocks, but any aligned pointer will do
+            return NonNull::new(layout.align() as *mut u8);
+        }
+
+        // SAFETY: the layout isn't zero-sized
+        NonNull::new(unsafe { std::alloc::alloc(layout) })
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            // SAFETY: the caller ensures that `ptr` was allocated with `layout` by `alloc`,
+            // which used the global allocator since the layout isn't zero-sized
+            unsafe { std::alloc::dealloc(ptr.as_ptr(), layout) }
+        }
+    }
+}
+
+// SAFETY: forwards to the underlying allocator
+unsafe impl<A: NodeAlloc + ?Sized> NodeAlloc for &A {
+    #[inline]
+    fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        A::alloc(self, layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: NonNull<u8>, layout: Layout) {
+        // SAFETY: forwarded from the caller
+        unsafe { A::dealloc(self, ptr, layout) }
+    }
+}
@@ -2,7 +2,7 @@
 
 use core::cell::Cell;
 
-use crate::interface::Strategy;
+use crate::interface::{FreshCopy, Strategy};
 
 /// An optimized local strategy which only counts how many active readers there are
 pub struct LocalStrategy {
@@ -25,6 +25,12 @@ impl Default for LocalStrategy {
     }
 }
 
+impl FreshCopy for LocalStrategy {
+    fn fresh_copy(&self) -> Self {
+        Self::new()
+    }
+}
+
 /// the writer tag for [`LocalStrategy`]
 pub struct WriterTag(());
 /// the reader tag for [`LocalStrategy`]
@@ -73,6 +79,11 @@ unsafe impl Strategy for LocalStrategy {
         ReaderTag(())
     }
 
+    #[inline]
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        ReaderTag(())
+    }
+
     #[inline]
     fn dangling_reader_tag() -> Self::ReaderTag {
         ReaderTag(())
@@ -99,6 +110,16 @@ unsafe impl Strategy for LocalStrategy {
         Capture(())
     }
 
+    #[inline]
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: there are no readers, since `validate_swap` checked that there are no active readers
+        unsafe { self.capture_readers(writer, validation_token) }
+    }
+
     #[inline]
     unsafe fn have_readers_exited(
         &self,
@@ -122,13 +143,20 @@ unsafe impl Strategy for LocalStrategy {
     #[inline]
     unsafe fn end_read_guard(&self, _reader: &mut Self::ReaderTag, _guard: Self::ReaderGuard) {
         let count = self.active_readers.get();
-        self.active_readers.set(count - 1);
+        // a guard which was never started would wrap the count around, and stop the writer
+        // from swapping forever, so don't let it go below zero
+        debug_assert_ne!(count, 0, "ended a read guard which was never started");
+        self.active_readers.set(count.saturating_sub(1));
     }
 
     #[cold]
     fn pause(&self, _writer: &Self::WriterTag, _pause: &mut Self::Pause) {
         panic!("cannot pause a local strategy")
     }
+
+    fn any_current_readers(&self) -> bool {
+        self.active_readers.get() != 0
+    }
 }
 
 #[cfg(feature = "alloc")]
@@ -152,6 +180,7 @@ impl<B: crate::interface::RawBuffers> crate::interface::DefaultOwned<B> for Loca
 }
 
 #[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
 fn test_local() {
     let mut shared =
         crate::raw::Shared::from_raw_parts(LocalStrategy::new(), crate::raw::RawDBuf::new(0, 0));
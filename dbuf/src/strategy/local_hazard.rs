@@ -58,7 +58,7 @@
 use core::{cell::Cell, ptr};
 use std::boxed::Box;
 
-use crate::interface::Strategy;
+use crate::interface::{ActiveReaderInfo, FreshCopy, Strategy, StrategyIntrospect};
 
 /// A hazard pointer strategy
 ///
@@ -119,6 +119,12 @@ impl Default for LocalHazardStrategy {
     }
 }
 
+impl FreshCopy for LocalHazardStrategy {
+    fn fresh_copy(&self) -> Self {
+        Self::new()
+    }
+}
+
 /// the writer tag for [`LocalHazardStrategy`]
 pub struct WriterTag(());
 /// the reader tag for [`LocalHazardStrategy`]
@@ -164,6 +170,11 @@ unsafe impl Strategy for LocalHazardStrategy {
         self.create_reader()
     }
 
+    #[inline]
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        self.create_reader()
+    }
+
     #[inline]
     fn dangling_reader_tag() -> Self::ReaderTag {
         ReaderTag(())
@@ -230,10 +241,19 @@ unsafe impl Strategy for LocalHazardStrategy {
         // because that has shared access to the writer tag.
         unsafe { (*prev).next_captured = ptr::null_mut() }
 
-        Capture {
-            generation,
-            start: head,
-        }
+        // start at the first captured node, the head's `next_captured` is only
+        // part of the sub-sequence if the head itself was captured
+        Capture { generation, start }
+    }
+
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: `validate_swap` bumped the generation, so every reader which holds a read guard
+        // is in the previous generation, which is exactly what `capture_readers` captures
+        unsafe { self.capture_readers(writer, validation_token) }
     }
 
     #[inline]
@@ -256,10 +276,11 @@ unsafe impl Strategy for LocalHazardStrategy {
 
             let reader_generation = current;
 
+            // readers can't race with swaps, so every reader is either empty, in the captured generation, or in the new generation
             debug_assert!(
                 reader_generation == 0
                     || reader_generation == generation
-                    || reader_generation == generation.wrapping_add(1),
+                    || reader_generation == generation.wrapping_add(2),
                 "invalid generation pair {generation} / {reader_generation}"
             );
 
@@ -318,6 +339,54 @@ unsafe impl Strategy for LocalHazardStrategy {
             "cannot swap buffers using local hazard strategy while there are readers in the buffer"
         )
     }
+
+    fn any_current_readers(&self) -> bool {
+        let mut ptr = self.ptr.get();
+
+        // SAFETY: we never remove links from the linked list so the ptr is either null or valid
+        while let Some(active_reader) = unsafe { ptr.as_ref() } {
+            if active_reader.generation.get() != 0 {
+                return true;
+            }
+
+            ptr = active_reader.next;
+        }
+
+        false
+    }
+}
+
+impl StrategyIntrospect for LocalHazardStrategy {
+    fn active_readers(&self, mut f: impl FnMut(ActiveReaderInfo)) {
+        let mut ptr = self.ptr.get();
+
+        // SAFETY: we never remove links from the linked list so the ptr is either null or valid
+        while let Some(active_reader) = unsafe { ptr.as_ref() } {
+            let generation = active_reader.generation.get();
+
+            if generation != 0 {
+                f(ActiveReaderInfo {
+                    id: ptr as usize,
+                    generation: generation as usize,
+                })
+            }
+
+            ptr = active_reader.next;
+        }
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        let mut nodes = 0;
+        let mut ptr = self.ptr.get();
+
+        // SAFETY: we never remove links from the linked list so the ptr is either null or valid
+        while let Some(active_reader) = unsafe { ptr.as_ref() } {
+            nodes += 1;
+            ptr = active_reader.next;
+        }
+
+        nodes * core::mem::size_of::<ActiveReader>()
+    }
 }
 
 impl LocalHazardStrategy {
@@ -349,7 +418,7 @@ impl Drop for LocalHazardStrategy {
 
             // SAFETY: we never remove links from the linked list so the ptr is either null or valid
             // and we checked that the current link is non-null
-            unsafe { Box::from_raw(ptr) };
+            drop(unsafe { Box::from_raw(ptr) });
 
             ptr = next;
         }
@@ -379,6 +448,7 @@ impl<B: crate::interface::RawBuffers> crate::interface::DefaultOwned<B> for Loca
 mod test {
 
     #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
     fn test_local_tracking() {
         let mut shared = crate::raw::Shared::from_raw_parts(
             super::LocalHazardStrategy::new(),
@@ -429,4 +499,51 @@ mod test {
 
         // assert!(writer.is_swap_finished(&mut swap));
     }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_guard_held_across_two_swaps() {
+        let mut shared = crate::raw::Shared::from_raw_parts(
+            super::LocalHazardStrategy::new(),
+            crate::raw::RawDBuf::new(0, 0),
+        );
+        let mut writer = crate::delayed::DelayedWriter::from(crate::raw::Writer::new(&mut shared));
+        let mut reader = writer.reader();
+
+        // the guard is taken after the flip, so it's in the new reader buffer
+        // and the first swap doesn't wait for it
+        writer.start_buffer_swap();
+        let guard = reader.get();
+        assert!(writer.is_swap_finished());
+
+        // the second swap targets the buffer the guard is reading
+        writer.start_buffer_swap();
+        assert!(!writer.is_swap_finished());
+        assert!(!writer.is_swap_finished());
+
+        drop(guard);
+        assert!(writer.is_swap_finished());
+    }
+
+    #[test]
+    #[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+    fn test_idle_head_doesnt_hide_readers() {
+        let mut shared = crate::raw::Shared::from_raw_parts(
+            super::LocalHazardStrategy::new(),
+            crate::raw::RawDBuf::new(0, 0),
+        );
+        let mut writer = crate::delayed::DelayedWriter::from(crate::raw::Writer::new(&mut shared));
+        let mut reader = writer.reader();
+        let mut idle = writer.reader();
+
+        let guard = reader.get();
+        // the idle reader's node is pushed in front of the busy reader's node
+        drop(idle.get());
+
+        writer.start_buffer_swap();
+        assert!(!writer.is_swap_finished());
+
+        drop(guard);
+        assert!(writer.is_swap_finished());
+    }
 }
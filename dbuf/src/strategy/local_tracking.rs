@@ -3,7 +3,7 @@
 use core::cell::Cell;
 use std::vec::Vec;
 
-use crate::interface::Strategy;
+use crate::interface::{ActiveReaderInfo, FreshCopy, Strategy, StrategyIntrospect};
 
 /// the index type used to identify readers
 type Index = usize;
@@ -14,14 +14,17 @@ pub struct LocalTrackingStrategy {
     active_readers: Cell<slab::Slab<Index>>,
     /// the next index type
     index: Cell<Index>,
+    /// the storage of the last finished capture, so that swaps don't need to allocate
+    spare_capture: Cell<Vec<(usize, Index)>>,
 }
 
 impl LocalTrackingStrategy {
     /// Create a new local strategy
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             active_readers: Cell::new(slab::Slab::new()),
             index: Cell::new(0),
+            spare_capture: Cell::new(Vec::new()),
         }
     }
 }
@@ -32,6 +35,12 @@ impl Default for LocalTrackingStrategy {
     }
 }
 
+impl FreshCopy for LocalTrackingStrategy {
+    fn fresh_copy(&self) -> Self {
+        Self::new()
+    }
+}
+
 /// the writer tag for [`LocalTrackingStrategy`]
 pub struct WriterTag(());
 /// the reader tag for [`LocalTrackingStrategy`]
@@ -92,6 +101,11 @@ unsafe impl Strategy for LocalTrackingStrategy {
         self.create_reader_tag()
     }
 
+    #[inline]
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        self.create_reader_tag()
+    }
+
     #[inline]
     fn dangling_reader_tag() -> Self::ReaderTag {
         ReaderTag {
@@ -116,7 +130,7 @@ unsafe impl Strategy for LocalTrackingStrategy {
         // SAFETY: capture_readers isn't reentrant or Sync so there can't be more than one `&mut` to active_readers
         let active_readers = unsafe { &mut *self.active_readers.as_ptr() };
 
-        let mut capture = Vec::new();
+        let mut capture = self.spare_capture.take();
 
         capture.reserve(active_readers.len());
         for (guard_index, &index) in active_readers.iter() {
@@ -126,6 +140,16 @@ unsafe impl Strategy for LocalTrackingStrategy {
         Capture(capture)
     }
 
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: `capture_readers` captures every reader which holds a read guard, regardless of
+        // which buffer it's reading from, so it doesn't rely on the buffers being flipped
+        unsafe { self.capture_readers(writer, validation_token) }
+    }
+
     unsafe fn have_readers_exited(
         &self,
         _writer: &Self::WriterTag,
@@ -138,7 +162,14 @@ unsafe impl Strategy for LocalTrackingStrategy {
             .0
             .retain(|&(guard_index, index)| active_readers.get(guard_index) == Some(&index));
 
-        capture.0.is_empty()
+        let is_empty = capture.0.is_empty();
+
+        // give back the storage for the next capture, unless it was already given back
+        if is_empty && capture.0.capacity() != 0 {
+            capture.0 = self.spare_capture.replace(core::mem::take(&mut capture.0));
+        }
+
+        is_empty
     }
 
     #[inline]
@@ -167,6 +198,49 @@ unsafe impl Strategy for LocalTrackingStrategy {
     fn pause(&self, _writer: &Self::WriterTag, _pause: &mut Self::Pause) {
         panic!("cannot swap buffers using local tracking strategy while there are readers in the buffer")
     }
+
+    fn any_current_readers(&self) -> bool {
+        // SAFETY: active_readers isn't reentrant or Sync so there can't be more than one `&mut` to active_readers
+        let active_readers = unsafe { &*self.active_readers.as_ptr() };
+        !active_readers.is_empty()
+    }
+}
+
+impl StrategyIntrospect for LocalTrackingStrategy {
+    fn active_readers(&self, f: impl FnMut(ActiveReaderInfo)) {
+        // collect the readers first, so that `f` may begin or end read guards
+        // SAFETY: active_readers isn't reentrant or Sync so there can't be more than one `&mut` to active_readers
+        let active_readers = unsafe { &*self.active_readers.as_ptr() };
+        let readers = active_readers
+            .iter()
+            .map(|(_, &index)| ActiveReaderInfo {
+                id: index,
+                generation: 0,
+            })
+            .collect::<Vec<_>>();
+
+        readers.into_iter().for_each(f)
+    }
+
+    fn maintain(&self) {
+        // dropped readers aren't stored, but the slab keeps its peak capacity,
+        // and so does the spare capture storage
+        let mut active_readers = self.active_readers.take();
+        active_readers.shrink_to_fit();
+        self.active_readers.set(active_readers);
+        self.spare_capture.take();
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        // SAFETY: active_readers isn't reentrant or Sync so there can't be more than one `&mut` to active_readers
+        let active_readers = unsafe { &*self.active_readers.as_ptr() };
+        let spare_capture = self.spare_capture.take();
+        let spare = spare_capture.capacity();
+        self.spare_capture.set(spare_capture);
+
+        // a slab entry is either the next free slot or the index, and a tag
+        (active_readers.capacity() + spare) * core::mem::size_of::<(usize, Index)>()
+    }
 }
 
 impl<B: crate::interface::RawBuffers> crate::interface::DefaultOwned<B> for LocalTrackingStrategy {
@@ -189,6 +263,7 @@ impl<B: crate::interface::RawBuffers> crate::interface::DefaultOwned<B> for Loca
 }
 
 #[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
 fn test_local_tracking() {
     let mut shared = crate::raw::Shared::from_raw_parts(
         LocalTrackingStrategy::new(),
@@ -228,3 +303,36 @@ fn test_local_tracking() {
     // SAFETY: we created the swap above
     assert!(unsafe { writer.is_swap_finished(&mut swap) });
 }
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_swaps_reuse_capture() {
+    use super::alloc_count::allocations;
+
+    let mut shared = crate::raw::Shared::from_raw_parts(
+        LocalTrackingStrategy::new(),
+        crate::raw::RawDBuf::new(0, 0),
+    );
+    let mut writer = crate::raw::Writer::new(&mut shared);
+    let mut readers = [writer.reader(), writer.reader(), writer.reader()];
+
+    let mut swap_while_reading = || {
+        let guards = readers.each_mut().map(|reader| reader.get());
+        // SAFETY: the swap is finished below, before the writer is used again
+        let mut swap = unsafe { writer.try_start_buffer_swap() }.unwrap();
+        // SAFETY: the swap was started by this writer
+        assert!(!unsafe { writer.is_swap_finished(&mut swap) });
+        drop(guards);
+        // SAFETY: the swap was started by this writer
+        assert!(unsafe { writer.is_swap_finished(&mut swap) });
+    };
+
+    // the first swap allocates the capture, and the slab of active readers
+    swap_while_reading();
+    let before = allocations();
+    for _ in 0..100 {
+        swap_while_reading();
+    }
+    assert_eq!(allocations(), before);
+}
@@ -0,0 +1,458 @@
+//! a scripted strategy for testing code which is generic over [`Strategy`]
+//!
+//! [`MockStrategy`] is a real strategy, so it can be used with [`Writer`](crate::raw::Writer),
+//! [`Reader`](crate::raw::Reader), [`DelayedWriter`](crate::delayed::DelayedWriter) and
+//! [`OpWriter`](crate::op::OpWriter) like any other strategy. The difference is that the test
+//! decides when the writer may see the readers exit, instead of spawning threads and sleeping
+//! until a reader is in the right place:
+//!
+//! * [`hold_next_capture`](MockStrategy::hold_next_capture) keeps the next swap waiting until
+//!   [`release_capture`](MockStrategy::release_capture) is called, as if a reader was stuck
+//! * [`hold_next_capture_for`](MockStrategy::hold_next_capture_for) keeps the next swap waiting
+//!   for a number of pauses, so blocking swaps still finish on a single thread
+//! * [`fail_next_validation`](MockStrategy::fail_next_validation) makes the next swap fail
+//! * [`events`](MockStrategy::events) is a log of everything the writer and readers did
+//!
+//! Read guards still work as usual, a swap waits for every guard which was active when it started.
+//!
+//! This is the recommended way to test how code handles a publish which is stuck on readers.
+//! It's only available with the `test-utils` feature.
+//!
+//! The strategy is cloned into the double buffer, and every clone controls the same state
+//!
+//! ```
+//! # #[cfg(feature = "test-utils")] {
+//! use dbuf::{
+//!     delayed::DelayedWriter,
+//!     raw::{RawDBuf, Shared, Writer},
+//!     strategy::mock::{MockEvent, MockStrategy},
+//! };
+//!
+//! let mock = MockStrategy::new();
+//! let mut shared = Shared::from_raw_parts(mock.clone(), RawDBuf::new(0, 0));
+//! let mut writer = DelayedWriter::new(Writer::new(&mut shared));
+//!
+//! mock.hold_next_capture();
+//! writer.start_buffer_swap();
+//! assert!(!writer.is_swap_finished());
+//!
+//! mock.release_capture();
+//! assert!(writer.is_swap_finished());
+//! assert_eq!(
+//!     mock.take_events()[1..],
+//!     [
+//!         MockEvent::ValidateSwap { ok: true },
+//!         MockEvent::CaptureReaders { guards: vec![], held: true },
+//!         MockEvent::HaveReadersExited(false),
+//!         MockEvent::HaveReadersExited(true),
+//!     ]
+//! );
+//! # }
+//! ```
+
+use core::convert::Infallible;
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    vec::Vec,
+};
+
+use crate::interface::{ActiveReaderInfo, CheapReaderTag, Strategy, StrategyIntrospect};
+
+/// A strategy where the test controls when readers exit, see module docs for details
+pub struct MockStrategy<E = Infallible> {
+    /// the state shared by all clones of this strategy
+    state: Arc<Mutex<State<E>>>,
+}
+
+/// the state of a [`MockStrategy`]
+struct State<E> {
+    /// the id of the next reader tag
+    next_reader: usize,
+    /// the id of the next read guard
+    next_guard: usize,
+    /// the reader and guard ids of the guards which haven't ended yet
+    active: Vec<(usize, usize)>,
+    /// how the next capture will be held
+    hold_next: Option<Hold>,
+    /// how the current capture is held
+    held: Option<Hold>,
+    /// the error the next validation returns
+    validation_error: Option<E>,
+    /// everything which happened so far
+    events: Vec<MockEvent>,
+}
+
+/// how long a capture is held
+#[derive(Clone, Copy)]
+enum Hold {
+    /// until [`MockStrategy::release_capture`] is called
+    UntilReleased,
+    /// until the writer pauses this many more times
+    Pauses(u32),
+}
+
+/// Something which happened to a [`MockStrategy`]
+///
+/// readers and guards are numbered in the order they were created, starting at zero
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockEvent {
+    /// the writer was created
+    CreateWriterTag,
+    /// a reader was created
+    CreateReaderTag {
+        /// the new reader
+        reader: usize,
+    },
+    /// a reader started reading
+    BeginReadGuard {
+        /// the reader
+        reader: usize,
+        /// the new guard
+        guard: usize,
+    },
+    /// a reader stopped reading
+    EndReadGuard {
+        /// the reader
+        reader: usize,
+        /// the guard which ended
+        guard: usize,
+    },
+    /// the writer started a swap (or quiescence), `ok` is false if the validation failed
+    ValidateSwap {
+        /// true if the swap may start
+        ok: bool,
+    },
+    /// the writer swapped the buffers, and captured the readers
+    CaptureReaders {
+        /// the guards the swap waits for
+        guards: Vec<usize>,
+        /// true if the capture was held by the test
+        held: bool,
+    },
+    /// the writer captured the readers without swapping the buffers
+    CaptureCurrentReaders {
+        /// the guards the writer waits for
+        guards: Vec<usize>,
+        /// true if the capture was held by the test
+        held: bool,
+    },
+    /// the writer checked if the captured readers exited
+    HaveReadersExited(bool),
+    /// the writer paused while waiting for readers
+    Pause,
+}
+
+impl MockStrategy {
+    /// Create a new mock strategy, which never fails validation unless told to
+    pub fn new() -> Self {
+        Self::with_validation_error()
+    }
+}
+
+impl Default for MockStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> Clone for MockStrategy<E> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<E> MockStrategy<E> {
+    /// Create a new mock strategy, which can fail validation with an `E`
+    pub fn with_validation_error() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                next_reader: 0,
+                next_guard: 0,
+                active: Vec::new(),
+                hold_next: None,
+                held: None,
+                validation_error: None,
+                events: Vec::new(),
+            })),
+        }
+    }
+
+    /// lock the state, ignoring poison so that a failed assert in one test thread
+    /// doesn't hide the real error behind a poisoned lock
+    fn state(&self) -> MutexGuard<'_, State<E>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Keep the next swap (or quiescence) waiting until [`release_capture`](Self::release_capture) is called
+    ///
+    /// A blocking swap will wait forever unless another thread releases the capture,
+    /// so use [`hold_next_capture_for`](Self::hold_next_capture_for) or
+    /// [`DelayedWriter`](crate::delayed::DelayedWriter) in single threaded tests
+    pub fn hold_next_capture(&self) {
+        self.state().hold_next = Some(Hold::UntilReleased);
+    }
+
+    /// Keep the next swap (or quiescence) waiting until the writer paused `pauses` times
+    pub fn hold_next_capture_for(&self, pauses: u32) {
+        self.state().hold_next = Some(Hold::Pauses(pauses));
+    }
+
+    /// Let the held capture finish once its readers exit
+    ///
+    /// this also cancels a [`hold_next_capture`](Self::hold_next_capture) which wasn't used yet
+    pub fn release_capture(&self) {
+        let mut state = self.state();
+        state.hold_next = None;
+        state.held = None;
+    }
+
+    /// Make the next validation fail with `err`
+    pub fn fail_next_validation(&self, err: E) {
+        self.state().validation_error = Some(err);
+    }
+
+    /// The number of read guards which haven't ended yet
+    pub fn active_guards(&self) -> usize {
+        self.state().active.len()
+    }
+
+    /// Everything which happened so far
+    pub fn events(&self) -> Vec<MockEvent> {
+        self.state().events.clone()
+    }
+
+    /// Everything which happened since the last call to `take_events`
+    pub fn take_events(&self) -> Vec<MockEvent> {
+        core::mem::take(&mut self.state().events)
+    }
+}
+
+impl<E> State<E> {
+    /// create a new reader tag
+    fn create_reader_tag(&mut self) -> ReaderTag {
+        let id = self.next_reader;
+        self.next_reader += 1;
+        self.events.push(MockEvent::CreateReaderTag { reader: id });
+        ReaderTag { id }
+    }
+
+    /// capture all active guards, and hold the capture if the test asked for it
+    fn capture(&mut self) -> Capture {
+        // holding for zero pauses is the same as not holding
+        self.held = self
+            .hold_next
+            .take()
+            .filter(|hold| !matches!(hold, Hold::Pauses(0)));
+        Capture {
+            guards: self.active.iter().map(|&(_, guard)| guard).collect(),
+            held: self.held.is_some(),
+        }
+    }
+}
+
+/// the writer tag for [`MockStrategy`]
+pub struct WriterTag(());
+/// the reader tag for [`MockStrategy`]
+pub struct ReaderTag {
+    /// the id of this reader
+    id: usize,
+}
+/// the validation token for [`MockStrategy`]
+pub struct ValidationToken(());
+/// the capture token for [`MockStrategy`]
+pub struct Capture {
+    /// the guards which were active when the capture was taken
+    guards: Vec<usize>,
+    /// true if the capture was held by the test
+    held: bool,
+}
+/// the reader guard for [`MockStrategy`]
+pub struct ReaderGuard {
+    /// the id of this guard
+    id: usize,
+}
+
+// SAFETY: every access to the state is behind the same lock, so a capture sees every guard which
+// began before it. A guard which begins after the capture takes the lock after the writer flipped
+// the buffers, so it reads from the new reader buffer. A capture only exits once all of its guards
+// have ended.
+unsafe impl<E: core::fmt::Debug> Strategy for MockStrategy<E> {
+    type WriterTag = WriterTag;
+    type ReaderTag = ReaderTag;
+    type Which = crate::raw::AtomicFlag;
+    type ValidationToken = ValidationToken;
+    type ValidationError = E;
+    type Capture = Capture;
+    type ReaderGuard = ReaderGuard;
+    type Pause = ();
+
+    unsafe fn create_writer_tag(&mut self) -> Self::WriterTag {
+        self.state().events.push(MockEvent::CreateWriterTag);
+        WriterTag(())
+    }
+
+    unsafe fn create_reader_tag_from_writer(&self, _parent: &Self::WriterTag) -> Self::ReaderTag {
+        self.state().create_reader_tag()
+    }
+
+    unsafe fn create_reader_tag_from_reader(&self, _parent: &Self::ReaderTag) -> Self::ReaderTag {
+        self.state().create_reader_tag()
+    }
+
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        self.state().create_reader_tag()
+    }
+
+    fn dangling_reader_tag() -> Self::ReaderTag {
+        ReaderTag { id: usize::MAX }
+    }
+
+    fn validate_swap(
+        &self,
+        _writer: &mut Self::WriterTag,
+    ) -> Result<Self::ValidationToken, Self::ValidationError> {
+        let mut state = self.state();
+        let result = match state.validation_error.take() {
+            Some(err) => Err(err),
+            None => Ok(ValidationToken(())),
+        };
+        state
+            .events
+            .push(MockEvent::ValidateSwap { ok: result.is_ok() });
+        result
+    }
+
+    unsafe fn capture_readers(
+        &self,
+        _writer: &mut Self::WriterTag,
+        ValidationToken(()): Self::ValidationToken,
+    ) -> Self::Capture {
+        let mut state = self.state();
+        let capture = state.capture();
+        state.events.push(MockEvent::CaptureReaders {
+            guards: capture.guards.clone(),
+            held: capture.held,
+        });
+        capture
+    }
+
+    unsafe fn capture_current_readers(
+        &self,
+        _writer: &mut Self::WriterTag,
+        ValidationToken(()): Self::ValidationToken,
+    ) -> Self::Capture {
+        let mut state = self.state();
+        let capture = state.capture();
+        state.events.push(MockEvent::CaptureCurrentReaders {
+            guards: capture.guards.clone(),
+            held: capture.held,
+        });
+        capture
+    }
+
+    unsafe fn have_readers_exited(
+        &self,
+        _writer: &Self::WriterTag,
+        capture: &mut Self::Capture,
+    ) -> bool {
+        let mut state = self.state();
+
+        let exited = !(capture.held && state.held.is_some()) && {
+            let active = &state.active;
+            capture
+                .guards
+                .retain(|guard| active.iter().any(|&(_, active)| active == *guard));
+            capture.guards.is_empty()
+        };
+
+        state.events.push(MockEvent::HaveReadersExited(exited));
+        exited
+    }
+
+    fn pause(&self, _writer: &Self::WriterTag, _pause: &mut Self::Pause) {
+        let mut state = self.state();
+        state.events.push(MockEvent::Pause);
+        match state.held {
+            Some(Hold::Pauses(0 | 1)) => state.held = None,
+            Some(Hold::Pauses(ref mut pauses)) => *pauses -= 1,
+            Some(Hold::UntilReleased) | None => {
+                // another thread may need to release the capture or end a guard
+                drop(state);
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    fn has_readers(&self) -> bool {
+        self.state().next_reader != 0
+    }
+
+    fn any_current_readers(&self) -> bool {
+        !self.state().active.is_empty()
+    }
+
+    unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
+        let mut state = self.state();
+        let id = state.next_guard;
+        state.next_guard += 1;
+        state.active.push((reader.id, id));
+        state.events.push(MockEvent::BeginReadGuard {
+            reader: reader.id,
+            guard: id,
+        });
+        ReaderGuard { id }
+    }
+
+    unsafe fn end_read_guard(&self, _reader: &mut Self::ReaderTag, guard: Self::ReaderGuard) {
+        let mut state = self.state();
+        let index = state
+            .active
+            .iter()
+            .position(|&(_, active)| active == guard.id)
+            .expect("ended a read guard which was never started");
+        let (reader, guard) = state.active.swap_remove(index);
+        state.events.push(MockEvent::EndReadGuard { reader, guard });
+    }
+}
+
+impl<E: core::fmt::Debug> CheapReaderTag for MockStrategy<E> {}
+
+impl<E: core::fmt::Debug> StrategyIntrospect for MockStrategy<E> {
+    fn active_readers(&self, mut f: impl FnMut(ActiveReaderInfo)) {
+        for &(id, _) in &self.state().active {
+            f(ActiveReaderInfo { id, generation: 0 })
+        }
+    }
+}
+
+#[cfg(not(feature = "loom"))]
+#[test]
+fn test_fail_validation() {
+    use crate::raw::{RawDBuf, Shared, Writer};
+
+    let mock = MockStrategy::<&str>::with_validation_error();
+    let mut shared = Shared::from_raw_parts(mock.clone(), RawDBuf::new(0, 1));
+    let mut writer = Writer::new(&mut shared);
+
+    mock.fail_next_validation("stalled");
+    assert_eq!(writer.try_swap_buffers(), Err("stalled"));
+    assert_eq!(*writer.split().reader, 1);
+
+    writer.try_swap_buffers().unwrap();
+    assert_eq!(*writer.split().reader, 0);
+    assert_eq!(
+        mock.events()[..5],
+        [
+            MockEvent::CreateWriterTag,
+            MockEvent::ValidateSwap { ok: false },
+            MockEvent::ValidateSwap { ok: true },
+            MockEvent::CaptureReaders {
+                guards: Vec::new(),
+                held: false
+            },
+            MockEvent::HaveReadersExited(true),
+        ]
+    );
+}
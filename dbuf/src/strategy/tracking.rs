@@ -1,29 +1,81 @@
 //! an sync strategy which precisely which readers are actually reading from the buffer
-
-use core::sync::atomic::{AtomicUsize, Ordering};
-use std::{sync::Arc, thread_local, time::Duration, vec::Vec};
-
-#[cfg(feature = "parking_lot")]
+//!
+//! ## Progress
+//!
+//! Acquiring and releasing a read guard is one increment of the reader's own counter, so it's wait-free,
+//! and readers never wait for the writer. Releasing a guard also notifies the writer, which doesn't block.
+//!
+//! Creating a reader pushes it to a list of new readers, which has its own lock. The writer only takes
+//! that lock to move the new readers into the list of readers, so a swap can't make creating a reader
+//! wait for long, and new readers can't make a swap wait: the writer only waits for the readers which
+//! held a guard when it captured them. Each new reader is still one push under a lock, so readers
+//! should be created up front, not for each read.
+
+#[cfg(not(feature = "loom"))]
+use core::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "loom")]
+use loom::sync::{
+    atomic::{fence, AtomicBool, AtomicUsize, Ordering},
+    Arc, Condvar, Mutex,
+};
+#[cfg(not(feature = "loom"))]
+use std::{sync::Arc, thread_local};
+use std::{time::Duration, vec::Vec};
+
+#[cfg(all(feature = "parking_lot", not(feature = "loom")))]
 use parking_lot::{Condvar, Mutex};
-#[cfg(not(feature = "parking_lot"))]
-use std::sync::{Condvar, Mutex, PoisonError};
+#[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+use std::sync::PoisonError;
+#[cfg(all(not(feature = "parking_lot"), not(feature = "loom")))]
+use std::sync::{Condvar, Mutex};
 
-use crate::interface::Strategy;
+use crate::interface::{ActiveReaderInfo, FreshCopy, Strategy, StrategyIntrospect};
 
 /// A sync strategy which allows
 pub struct TrackingStrategy {
     /// the number of active readers
     readers: Mutex<Vec<Arc<AtomicUsize>>>,
+    /// the readers which were created since the last time `readers` was locked
+    ///
+    /// This is a separate lock, so creating a reader never waits for a capture, see `lock_readers`
+    registered: Mutex<Vec<Arc<AtomicUsize>>>,
+    /// true while `registered` may not be empty, so swaps can skip its lock
+    has_registered: AtomicBool,
     /// a condvar to wait for readers
     cv: Condvar,
+    /// the storage of the last finished capture, so that swaps don't need to allocate
+    spare_capture: Mutex<Vec<(usize, Arc<AtomicUsize>)>>,
+    /// true once a reader tag was created, so swaps without readers can skip the lock
+    has_readers: AtomicBool,
 }
 
 impl TrackingStrategy {
-    /// Create a new local strategy
+    /// Create a new tracking strategy
+    ///
+    /// This is const, so the strategy can be put in a `static`, since `Mutex::new` and
+    /// `Condvar::new` are const in `std` since Rust 1.63 (and always were in `parking_lot`)
+    #[cfg(not(feature = "loom"))]
+    pub const fn new() -> Self {
+        Self {
+            readers: Mutex::new(Vec::new()),
+            registered: Mutex::new(Vec::new()),
+            has_registered: AtomicBool::new(false),
+            cv: Condvar::new(),
+            spare_capture: Mutex::new(Vec::new()),
+            has_readers: AtomicBool::new(false),
+        }
+    }
+
+    /// Create a new tracking strategy
+    #[cfg(feature = "loom")]
     pub fn new() -> Self {
         Self {
             readers: Mutex::new(Vec::new()),
+            registered: Mutex::new(Vec::new()),
+            has_registered: AtomicBool::new(false),
             cv: Condvar::new(),
+            spare_capture: Mutex::new(Vec::new()),
+            has_readers: AtomicBool::new(false),
         }
     }
 }
@@ -34,6 +86,12 @@ impl Default for TrackingStrategy {
     }
 }
 
+impl FreshCopy for TrackingStrategy {
+    fn fresh_copy(&self) -> Self {
+        Self::new()
+    }
+}
+
 /// the writer tag for [`TrackingStrategy`]
 pub struct WriterTag(());
 /// the reader tag for [`TrackingStrategy`]
@@ -54,12 +112,72 @@ impl TrackingStrategy {
         let tag = ReaderTag {
             generation: Arc::new(AtomicUsize::new(0)),
         };
+        #[allow(unused_mut)]
+        let mut registered = self.registered.lock();
+        #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+        let mut registered = registered.unwrap_or_else(PoisonError::into_inner);
+        registered.push(tag.generation.clone());
+        // this is set while `registered` is locked, so `lock_readers` can't clear it without
+        // moving this reader, and it's ordered before the reader's first read guard like `has_readers`
+        self.has_registered.store(true, Ordering::Relaxed);
+        // this is ordered before the reader's first read guard, see `capture_readers`
+        self.has_readers.store(true, Ordering::Relaxed);
+        tag
+    }
+
+    /// lock the list of readers, and move the newly created readers into it
+    ///
+    /// New readers are pushed to `registered` instead of `readers`, so creating a reader only waits
+    /// for other readers being created, and for this move, never for a capture walking the list.
+    /// A capture which already locked `readers` can't see new readers, which is fine because they
+    /// will see the buffers flip (see `capture_readers`), and it can't be extended by them either.
+    /// `registered` is only locked if a reader was created since the last move.
+    fn lock_readers(&self) -> impl core::ops::DerefMut<Target = Vec<Arc<AtomicUsize>>> + '_ {
         #[allow(unused_mut)]
         let mut readers = self.readers.lock();
-        #[cfg(not(feature = "parking_lot"))]
+        #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
         let mut readers = readers.unwrap_or_else(PoisonError::into_inner);
-        readers.push(tag.generation.clone());
-        tag
+
+        if self.has_registered.load(Ordering::Relaxed) {
+            #[allow(unused_mut)]
+            let mut registered = self.registered.lock();
+            #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+            let mut registered = registered.unwrap_or_else(PoisonError::into_inner);
+            // this keeps `registered`'s storage, so creating readers doesn't need to allocate each time
+            readers.append(&mut registered);
+            self.has_registered.store(false, Ordering::Relaxed);
+            drop(registered);
+        }
+
+        readers
+    }
+
+    /// remove the readers which were dropped, and return how many were removed
+    ///
+    /// This also happens whenever the buffers are swapped, so it's only needed
+    /// if readers are created and dropped often between swaps
+    pub fn collect_garbage(&self) -> usize {
+        let mut readers = self.lock_readers();
+
+        let len = readers.len();
+        readers.retain(|tag| Arc::strong_count(tag) != 1);
+        let removed = len - readers.len();
+
+        // give back the memory if most of the readers are gone
+        if readers.len() < readers.capacity() / 4 {
+            let len = readers.len();
+            readers.shrink_to(len * 2);
+
+            // the storage of the new readers kept growing along with this list
+            #[allow(unused_mut)]
+            let mut registered = self.registered.lock();
+            #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+            let mut registered = registered.unwrap_or_else(PoisonError::into_inner);
+            let len = registered.len();
+            registered.shrink_to(len * 2);
+        }
+
+        removed
     }
 }
 
@@ -89,9 +207,17 @@ unsafe impl Strategy for TrackingStrategy {
         self.create_reader_tag()
     }
 
+    #[inline]
+    unsafe fn create_reader_tag(&self) -> Self::ReaderTag {
+        self.create_reader_tag()
+    }
+
     #[inline]
     fn dangling_reader_tag() -> Self::ReaderTag {
-        std::thread_local! {
+        #[cfg(feature = "loom")]
+        use loom::thread_local;
+
+        thread_local! {
             static DANGLING: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0))
         }
         ReaderTag {
@@ -112,12 +238,20 @@ unsafe impl Strategy for TrackingStrategy {
         _: &mut Self::WriterTag,
         _: Self::ValidationToken,
     ) -> Self::Capture {
-        let mut capture = Vec::new();
+        // SeqCst to pair with the fence in `begin_read_guard`, this ensures that either we see the
+        // reader's generation change below, or the reader sees the buffers flip
+        fence(Ordering::SeqCst);
+
+        // a reader tag is created before its first read guard, so by the same argument as above,
+        // if we don't see any reader tag then every reader will see the buffers flip
+        if !self.has_readers.load(Ordering::Relaxed) {
+            return Capture(Vec::new());
+        }
 
-        #[allow(unused_mut)]
-        let mut readers = self.readers.lock();
-        #[cfg(not(feature = "parking_lot"))]
-        let mut readers = readers.unwrap_or_else(PoisonError::into_inner);
+        // the spare storage is only taken once an active reader is found, so that swaps
+        // which don't have to wait for any reader skip its lock
+        let mut capture = Vec::new();
+        let mut readers = self.lock_readers();
 
         readers.retain(|tag| {
             if Arc::strong_count(tag) == 1 {
@@ -126,6 +260,14 @@ unsafe impl Strategy for TrackingStrategy {
                 let generation = tag.load(Ordering::Acquire);
 
                 if generation % 2 == 1 {
+                    if capture.capacity() == 0 {
+                        #[allow(unused_mut)]
+                        let mut spare = self.spare_capture.lock();
+                        #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+                        let mut spare = spare.unwrap_or_else(PoisonError::into_inner);
+                        capture = core::mem::take(&mut *spare);
+                    }
+
                     capture.push((generation, tag.clone()))
                 }
 
@@ -136,12 +278,25 @@ unsafe impl Strategy for TrackingStrategy {
         Capture(capture)
     }
 
+    unsafe fn capture_current_readers(
+        &self,
+        writer: &mut Self::WriterTag,
+        validation_token: Self::ValidationToken,
+    ) -> Self::Capture {
+        // SAFETY: `capture_readers` captures every reader which holds a read guard, regardless of
+        // which buffer it's reading from, so it doesn't rely on the buffers being flipped
+        unsafe { self.capture_readers(writer, validation_token) }
+    }
+
     unsafe fn have_readers_exited(
         &self,
         _writer: &Self::WriterTag,
         capture: &mut Self::Capture,
     ) -> bool {
         // SAFETY: have_readers_exited isn't reentrant or Sync so there can't be more than one `&mut` to active_readers
+        //
+        // Relaxed is enough here because we only need the readers' `end_read_guard` stores to happen before
+        // we report that all readers have exited, which is handled by the fence below
         capture
             .0
             .retain(|(generation, tag)| *generation == tag.load(Ordering::Relaxed));
@@ -149,7 +304,22 @@ unsafe impl Strategy for TrackingStrategy {
         let is_empty = capture.0.is_empty();
 
         if is_empty {
-            core::sync::atomic::fence(Ordering::Release);
+            // Acquire to syncronize with the Release in `end_read_guard`. Each of the loads above which
+            // observed a reader exit read from one of those stores, so this fence makes all of the
+            // readers' reads of the old buffer happen before the writer's next write to it.
+            //
+            // This must not be a Release fence, that would only order the writer's *earlier* accesses
+            // and would let the writer race with readers which are still reading from the buffer
+            fence(Ordering::Acquire);
+
+            // give back the storage for the next capture, unless it was already given back
+            if capture.0.capacity() != 0 {
+                #[allow(unused_mut)]
+                let mut spare = self.spare_capture.lock();
+                #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+                let mut spare = spare.unwrap_or_else(PoisonError::into_inner);
+                core::mem::swap(&mut *spare, &mut capture.0);
+            }
         }
 
         is_empty
@@ -158,15 +328,38 @@ unsafe impl Strategy for TrackingStrategy {
     #[inline]
     unsafe fn begin_read_guard(&self, reader: &mut Self::ReaderTag) -> Self::ReaderGuard {
         reader.generation.fetch_add(1, Ordering::Release);
+        // SeqCst to pair with the fence in `capture_readers`. Without it, the load of which buffer
+        // to read could happen before this store is visible to the writer, so the writer could miss
+        // this reader while it reads from the buffer that was just swapped out
+        fence(Ordering::SeqCst);
         ReaderGuard(())
     }
 
     #[inline]
     unsafe fn end_read_guard(&self, reader: &mut Self::ReaderTag, _guard: Self::ReaderGuard) {
+        // Release so that all reads from the buffer happen before the writer observes this reader exiting
+        // see `have_readers_exited` for the matching Acquire
         reader.generation.fetch_add(1, Ordering::Release);
         self.cv.notify_one();
     }
 
+    fn has_readers(&self) -> bool {
+        self.has_readers.load(Ordering::Relaxed)
+    }
+
+    fn any_current_readers(&self) -> bool {
+        if !self.has_readers() {
+            return false;
+        }
+
+        let readers = self.lock_readers();
+
+        // a reader's generation is odd while it holds a read guard
+        readers
+            .iter()
+            .any(|tag| tag.load(Ordering::Relaxed) % 2 == 1)
+    }
+
     fn pause(&self, _writer: &Self::WriterTag, pause: &mut usize) {
         /// the max number of growth iterations
         const MAX_ITERATIONS: usize = 20;
@@ -179,20 +372,79 @@ unsafe impl Strategy for TrackingStrategy {
 
         #[allow(unused_mut)]
         let mut readers = self.readers.lock();
-        #[cfg(not(feature = "parking_lot"))]
+        #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
         let readers = readers.unwrap_or_else(PoisonError::into_inner);
 
         let timeout = MAX_TIMEOUT * (1 << pause_time) / (1 << MAX_ITERATIONS);
 
         #[allow(clippy::let_underscore_lock)]
-        #[cfg(not(feature = "parking_lot"))]
+        #[cfg(all(not(feature = "parking_lot"), not(feature = "loom")))]
         let _ = self.cv.wait_timeout(readers, timeout);
 
-        #[cfg(feature = "parking_lot")]
+        // loom doesn't model timeouts, and a reader may exit between `have_readers_exited`
+        // and waiting on the condvar, so just yield to the readers instead
+        #[cfg(feature = "loom")]
+        {
+            drop((readers, timeout));
+            loom::thread::yield_now();
+        }
+
+        #[cfg(all(feature = "parking_lot", not(feature = "loom")))]
         let _ = self.cv.wait_for(&mut readers, timeout);
     }
 }
 
+impl StrategyIntrospect for TrackingStrategy {
+    fn active_readers(&self, f: impl FnMut(ActiveReaderInfo)) {
+        // collect the readers first, so that `f` may create new readers without deadlocking
+        let readers = self.lock_readers();
+
+        let active = readers
+            .iter()
+            .filter_map(|tag| {
+                let generation = tag.load(Ordering::Relaxed);
+                (generation % 2 == 1).then_some(ActiveReaderInfo {
+                    id: Arc::as_ptr(tag) as usize,
+                    generation,
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(readers);
+
+        active.into_iter().for_each(f)
+    }
+
+    fn maintain(&self) {
+        self.collect_garbage();
+    }
+
+    fn bookkeeping_bytes(&self) -> usize {
+        /// each reader's generation is in its own `Arc`, with the two reference counts
+        const TAG_BYTES: usize = 3 * core::mem::size_of::<usize>();
+
+        let readers = self.lock_readers();
+        let tags = readers.len();
+        let readers = readers.capacity();
+
+        #[allow(unused_mut)]
+        let mut registered = self.registered.lock();
+        #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+        let registered = registered.unwrap_or_else(PoisonError::into_inner);
+        let registered = registered.capacity();
+
+        #[allow(unused_mut)]
+        let mut spare = self.spare_capture.lock();
+        #[cfg(any(feature = "loom", not(feature = "parking_lot")))]
+        let spare = spare.unwrap_or_else(PoisonError::into_inner);
+        let spare = spare.capacity();
+
+        (readers + registered) * core::mem::size_of::<Arc<AtomicUsize>>()
+            + spare * core::mem::size_of::<(usize, Arc<AtomicUsize>)>()
+            + tags * TAG_BYTES
+    }
+}
+
+#[cfg(not(feature = "loom"))]
 impl<B: crate::interface::RawBuffers> crate::interface::DefaultOwned<B> for TrackingStrategy {
     type IntoStrongRefWithWeak = crate::ptrs::alloc::OwnedWithWeak<Self, B>;
     type StrongRefWithWeak = crate::ptrs::alloc::OwnedStrong<Self, B>;
@@ -282,3 +534,98 @@ fn test_local_tracking() {
     // SAFETY: we created the swap above
     assert!(unsafe { writer.is_swap_finished(&mut swap) });
 }
+
+#[test]
+#[cfg(not(feature = "loom"))]
+fn test_swaps_reuse_capture() {
+    use super::alloc_count::allocations;
+
+    let mut shared =
+        crate::raw::Shared::from_raw_parts(TrackingStrategy::new(), crate::raw::RawDBuf::new(0, 0));
+    let mut writer = crate::delayed::DelayedWriter::new(crate::raw::Writer::new(&mut shared));
+    let mut readers = [writer.reader(), writer.reader(), writer.reader()];
+
+    let mut swap_while_reading = || {
+        let guards = readers.each_mut().map(|reader| reader.get());
+        writer.start_buffer_swap();
+        assert!(!writer.is_swap_finished());
+        drop(guards);
+        writer.finish_swap();
+    };
+
+    // the first swap allocates the capture
+    swap_while_reading();
+    let before = allocations();
+    for _ in 0..100 {
+        swap_while_reading();
+    }
+    assert_eq!(allocations(), before);
+}
+
+#[test]
+#[cfg(not(feature = "loom"))]
+fn test_maintain_collects_dropped_readers() {
+    let strategy = TrackingStrategy::new();
+    let readers = || {
+        let readers = strategy.lock_readers();
+        let registered = strategy.registered.lock();
+        #[cfg(not(feature = "parking_lot"))]
+        let registered = registered.unwrap();
+        (readers.len(), readers.capacity() + registered.capacity())
+    };
+
+    let reader = strategy.create_reader_tag();
+    for _ in 0..10_000 {
+        drop(strategy.create_reader_tag());
+    }
+    assert_eq!(readers().0, 10_001);
+
+    // without swapping the buffers, the dropped readers are still there until the writer maintains the strategy
+    strategy.maintain();
+    // and the memory is given back
+    let (len, capacity) = readers();
+    assert_eq!(len, 1);
+    assert!(capacity < 100);
+    assert_eq!(strategy.collect_garbage(), 0);
+
+    drop(reader);
+    assert_eq!(strategy.collect_garbage(), 1);
+}
+
+#[test]
+#[cfg(feature = "loom")]
+fn test_loom_tracking() {
+    use loom::cell::UnsafeCell;
+
+    /// a buffer which lets loom check for data races between the writer and readers
+    struct Buffer(UnsafeCell<u32>);
+
+    // SAFETY: the double buffer ensures that the writer never writes while a reader is reading
+    unsafe impl Sync for Buffer {}
+
+    loom::model(|| {
+        let shared = crate::raw::Shared::new(
+            TrackingStrategy::new(),
+            crate::raw::RawDBuf::new(Buffer(UnsafeCell::new(0)), Buffer(UnsafeCell::new(0))),
+        );
+        let mut writer = crate::raw::Writer::new(crate::ptrs::alloc::Owned::new(shared));
+        let mut reader = writer.reader();
+
+        let thread = loom::thread::spawn(move || {
+            let guard = reader.get();
+            // SAFETY: the read guard keeps the writer from writing to this buffer
+            guard.0.with(|value| unsafe { *value })
+        });
+
+        writer.swap_buffers();
+
+        // if `have_readers_exited` doesn't syncronize with `end_read_guard`
+        // then loom will report this write as racing with the read above
+        writer.split_mut().writer.0.with_mut(|value| {
+            // SAFETY: all readers have exited the writer buffer
+            unsafe { *value = 1 }
+        });
+
+        assert_eq!(thread.join().unwrap(), 0);
+    })
+}
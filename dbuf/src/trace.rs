@@ -0,0 +1,118 @@
+//! Events for the [`tracing`] crate
+//!
+//! With the `tracing` feature, writers emit events at the interesting points of a swap.
+//! All events use fields instead of formatted messages, so they are cheap when filtered out,
+//! and without the feature none of this code exists.
+//!
+//! | event | level | fields |
+//! |-|-|-|
+//! | a swap was started | `TRACE` | `epoch` |
+//! | the writer is still waiting for readers after 1, 2, 4, 8, ... pauses | `DEBUG` | `epoch`, `pauses` |
+//! | the writer waited for readers for longer than [`set_slow_writer_threshold`] | `DEBUG` | `epoch`, `pauses`, `waited_us` |
+//! | a swap was finished | `TRACE` | `epoch`, `pauses`, `finished_immediately` |
+//! | an [`OpWriter`](crate::op::OpWriter) published some operations | `TRACE` | `ops` |
+//! | a read guard was held for longer than [`set_slow_guard_threshold`] | `DEBUG` | `held_us` |
+//!
+//! The `epoch` is incremented by every swap, so it ties together the events of a single swap.
+//! The slow writer and slow guard events need the `std` feature to measure time. Only borrowing
+//! read guards are timed, guards from [`Reader::into_guard`](crate::raw::Reader::into_guard)
+//! are expected to be held for a long time.
+
+#[cfg(feature = "std")]
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// the threshold for slow writers in microseconds
+#[cfg(feature = "std")]
+static SLOW_WRITER_US: AtomicU64 = AtomicU64::new(10_000);
+/// the threshold for slow read guards in microseconds
+#[cfg(feature = "std")]
+static SLOW_GUARD_US: AtomicU64 = AtomicU64::new(10_000);
+
+/// Report writers which wait on readers for longer than `threshold` to finish a swap
+///
+/// This is global for all double buffers, and defaults to 10ms
+#[cfg(feature = "std")]
+pub fn set_slow_writer_threshold(threshold: Duration) {
+    SLOW_WRITER_US.store(as_micros(threshold), Ordering::Relaxed)
+}
+
+/// Report read guards which are held for longer than `threshold`
+///
+/// This is global for all double buffers, and defaults to 10ms
+#[cfg(feature = "std")]
+pub fn set_slow_guard_threshold(threshold: Duration) {
+    SLOW_GUARD_US.store(as_micros(threshold), Ordering::Relaxed)
+}
+
+/// the duration in microseconds, saturating at `u64::MAX`
+#[cfg(feature = "std")]
+fn as_micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+/// reports a writer which waited on readers for too long, at most once per swap
+#[cfg(feature = "std")]
+pub(crate) struct SlowWriter {
+    /// when the writer started waiting
+    start: Instant,
+    /// true if the writer was already reported
+    reported: bool,
+}
+
+#[cfg(feature = "std")]
+impl SlowWriter {
+    /// start timing a writer
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            reported: false,
+        }
+    }
+
+    /// report the writer if it waited for too long
+    pub(crate) fn check(&mut self, epoch: usize, pauses: u32) {
+        if self.reported {
+            return;
+        }
+
+        let waited_us = as_micros(self.start.elapsed());
+        if waited_us >= SLOW_WRITER_US.load(Ordering::Relaxed) {
+            self.reported = true;
+            tracing::debug!(
+                epoch,
+                pauses,
+                waited_us,
+                "slow readers are blocking the writer"
+            );
+        }
+    }
+}
+
+/// when a read guard was acquired, or `None` if the slow guard event is filtered out
+///
+/// Reading the clock isn't free, so this only reads it if the event could be emitted
+#[cfg(feature = "std")]
+#[inline]
+pub(crate) fn start_read_guard() -> Option<Instant> {
+    if tracing::enabled!(tracing::Level::DEBUG) {
+        Some(Instant::now())
+    } else {
+        None
+    }
+}
+
+/// report a read guard which was acquired at `acquired`, if it was held for too long
+#[cfg(feature = "std")]
+#[inline]
+pub(crate) fn check_read_guard(acquired: Option<Instant>) {
+    let Some(acquired) = acquired else { return };
+    let held_us = as_micros(acquired.elapsed());
+    if held_us >= SLOW_GUARD_US.load(Ordering::Relaxed) {
+        tracing::debug!(held_us, "a read guard was held for a long time");
+    }
+}
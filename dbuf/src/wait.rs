@@ -1,6 +1,6 @@
 //! various waiting strategeis
 
-use crate::interface::WaitStrategy;
+use crate::interface::{FreshCopy, WaitStrategy};
 #[cfg(feature = "std")]
 use once_cell::sync::OnceCell;
 
@@ -8,6 +8,19 @@ use once_cell::sync::OnceCell;
 /// This waiter will do nothing on wait
 pub struct NoopWait;
 
+impl NoopWait {
+    /// Create a new [`NoopWait`]
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl FreshCopy for NoopWait {
+    fn fresh_copy(&self) -> Self {
+        Self
+    }
+}
+
 impl WaitStrategy for NoopWait {
     type State = ();
 
@@ -20,18 +33,38 @@ impl WaitStrategy for NoopWait {
 
 #[derive(Default)]
 /// This waiter will spin using exponential backoff
+///
+/// The `n`th wait spins for `2^n` iterations, up to `2^10`
 pub struct SpinWait;
 
+impl SpinWait {
+    /// Create a new [`SpinWait`]
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl FreshCopy for SpinWait {
+    fn fresh_copy(&self) -> Self {
+        Self
+    }
+}
+
 impl WaitStrategy for SpinWait {
     type State = u32;
 
     fn wait(&self, counter: &mut Self::State) -> bool {
         let count = *counter;
-        *counter = count.wrapping_add(1).max(10);
+        *counter = count.wrapping_add(1).min(10);
 
+        #[cfg(not(feature = "loom"))]
         for _ in 0..1 << count {
             core::hint::spin_loop()
         }
+        // loom needs to know that this thread is waiting on another one, and spinning
+        // longer doesn't change which interleavings it checks
+        #[cfg(feature = "loom")]
+        loom::hint::spin_loop();
 
         count == 10
     }
@@ -39,6 +72,54 @@ impl WaitStrategy for SpinWait {
     fn notify(&self) {}
 }
 
+/// This waiter will yield the rest of its time slice to the OS on wait
+///
+/// Parking and unparking a thread costs more than a reader which only holds its guard for a few
+/// instructions, and spinning burns a whole core. Yielding lets other threads run, including the
+/// reader the writer is waiting for, and comes back as soon as the scheduler allows. There is no
+/// backoff, so a reader which holds its guard for milliseconds keeps the writer cycling through
+/// the scheduler, see [`Budgeted`] to park after a number of yields.
+///
+/// The state counts the yields of the current swap
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct YieldWait;
+
+#[cfg(feature = "std")]
+impl YieldWait {
+    /// Create a new [`YieldWait`]
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(feature = "std")]
+impl FreshCopy for YieldWait {
+    fn fresh_copy(&self) -> Self {
+        Self
+    }
+}
+
+#[cfg(feature = "std")]
+impl WaitStrategy for YieldWait {
+    type State = u32;
+
+    fn wait(&self, yields: &mut Self::State) -> bool {
+        *yields = yields.saturating_add(1);
+
+        #[cfg(feature = "loom")]
+        loom::thread::yield_now();
+        #[cfg(not(feature = "loom"))]
+        std::thread::yield_now();
+
+        // each yield is about as long as the last one, there's nothing to back off
+        true
+    }
+
+    // the writer is never blocked, so it checks the readers again as soon as it's scheduled
+    fn notify(&self) {}
+}
+
 /// This waiter will park the thread on wait
 #[cfg(feature = "std")]
 pub struct ThreadParker {
@@ -81,6 +162,13 @@ impl Default for ThreadParker {
     }
 }
 
+#[cfg(feature = "std")]
+impl FreshCopy for ThreadParker {
+    fn fresh_copy(&self) -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(feature = "std")]
 impl WaitStrategy for ThreadParker {
     type State = ();
@@ -108,21 +196,236 @@ impl WaitStrategy for ThreadParker {
     }
 }
 
+/// This waiter will park the thread on wait, and call a hook if a swap waits for too long
+///
+/// The writer waits for readers to leave the old buffer. If a reader runs on a thread with a lower
+/// priority, then the writer may be stuck behind it for a long time. This can't fix that, but it
+/// tells the application about it, which may boost the readers' priority, log it, or shed load.
+///
+/// Each wait parks the thread until a reader leaves, or until `timeout` passes, so the writer
+/// wakes up regularly even if no reader notifies it. After `threshold` waits in the same swap,
+/// each wait calls `on_long_wait` with the number of waits so far in that swap
+///
+/// ```
+/// use dbuf::{strategy::HazardStrategy, wait::PriorityAwareParker};
+/// use std::time::Duration;
+///
+/// let strategy = HazardStrategy::with_wait_strategy(PriorityAwareParker::new(
+///     10,
+///     Duration::from_millis(1),
+///     |waits| eprintln!("the writer has been waiting on readers for {waits} waits"),
+/// ));
+/// ```
+#[cfg(feature = "std")]
+pub struct PriorityAwareParker<F = fn(u32)> {
+    /// true if a reader left since the last wait
+    notified: core::sync::atomic::AtomicBool,
+    /// the lock for `cv`
+    mutex: std::sync::Mutex<()>,
+    /// wakes the writer when a reader leaves
+    cv: std::sync::Condvar,
+    /// the number of waits in a swap before `on_long_wait` is called
+    threshold: u32,
+    /// the longest a single wait parks the thread for
+    timeout: std::time::Duration,
+    /// called on each wait after the first `threshold` waits of a swap
+    on_long_wait: F,
+}
+
+#[cfg(feature = "std")]
+impl<F: Fn(u32)> PriorityAwareParker<F> {
+    /// Create a parker which parks for at most `timeout` at a time,
+    /// and calls `on_long_wait` after `threshold` waits in the same swap
+    pub const fn new(threshold: u32, timeout: std::time::Duration, on_long_wait: F) -> Self {
+        Self {
+            notified: core::sync::atomic::AtomicBool::new(false),
+            mutex: std::sync::Mutex::new(()),
+            cv: std::sync::Condvar::new(),
+            threshold,
+            timeout,
+            on_long_wait,
+        }
+    }
+
+    /// the number of waits in a swap before the hook is called
+    pub const fn threshold(&self) -> u32 {
+        self.threshold
+    }
+
+    /// the longest a single wait parks the thread for
+    pub const fn timeout(&self) -> std::time::Duration {
+        self.timeout
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: Fn(u32) + Clone> FreshCopy for PriorityAwareParker<F> {
+    fn fresh_copy(&self) -> Self {
+        Self::new(self.threshold, self.timeout, self.on_long_wait.clone())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: Fn(u32)> WaitStrategy for PriorityAwareParker<F> {
+    /// the number of waits in this swap
+    type State = u32;
+
+    #[cold]
+    fn wait(&self, waits: &mut Self::State) -> bool {
+        *waits = waits.saturating_add(1);
+
+        let deadline = std::time::Instant::now() + self.timeout;
+        let mut lock = self
+            .mutex
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        // a spurious wake up goes back to sleep until the deadline, so that it doesn't count
+        // as another wait. Readers don't take the lock to notify, so a notify may be missed,
+        // then the writer sleeps until the deadline, and checks the readers again after that
+        while !self
+            .notified
+            .swap(false, core::sync::atomic::Ordering::Acquire)
+        {
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            lock = self
+                .cv
+                .wait_timeout(lock, deadline - now)
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .0;
+        }
+
+        drop(lock);
+
+        if *waits > self.threshold {
+            (self.on_long_wait)(*waits);
+        }
+
+        true
+    }
+
+    fn notify(&self) {
+        self.notified
+            .store(true, core::sync::atomic::Ordering::Release);
+        self.cv.notify_one();
+    }
+}
+
+/// This waiter delegates the first `first_count` waits of each swap to `first`,
+/// and the rest to `then`
+///
+/// ```
+/// use dbuf::{strategy::HazardStrategy, wait::{Budgeted, SpinWait, ThreadParker, YieldWait}};
+///
+/// // spin for the first 8 waits, then park the thread
+/// let strategy = HazardStrategy::with_wait_strategy(Budgeted::new(SpinWait, 8, ThreadParker::new()));
+///
+/// // yield for the first 32 waits, then park the thread
+/// # #[cfg(not(feature = "loom"))]
+/// let strategy = HazardStrategy::with_wait_strategy(Budgeted::new(YieldWait, 32, ThreadParker::new()));
+/// ```
+pub struct Budgeted<A, B> {
+    /// the waiter for the first `first_count` waits
+    first: A,
+    /// the number of waits to delegate to `first`
+    first_count: u32,
+    /// the waiter for the rest of the waits
+    then: B,
+}
+
+/// The state of [`Budgeted`]
+#[derive(Default)]
+pub struct BudgetedState<A, B> {
+    /// the number of waits delegated to `first`
+    count: u32,
+    /// the state of `first`
+    first: A,
+    /// the state of `then`
+    then: B,
+}
+
+impl<A, B> Budgeted<A, B> {
+    /// create a new waiter which uses `first` for the first `first_count` waits of each swap, then `then`
+    pub const fn new(first: A, first_count: u32, then: B) -> Self {
+        Self {
+            first,
+            first_count,
+            then,
+        }
+    }
+
+    /// the number of waits delegated to the first waiter
+    pub const fn first_count(&self) -> u32 {
+        self.first_count
+    }
+}
+
+impl<A: FreshCopy, B: FreshCopy> FreshCopy for Budgeted<A, B> {
+    fn fresh_copy(&self) -> Self {
+        Self::new(
+            self.first.fresh_copy(),
+            self.first_count,
+            self.then.fresh_copy(),
+        )
+    }
+}
+
+impl<A: WaitStrategy, B: WaitStrategy> WaitStrategy for Budgeted<A, B> {
+    type State = BudgetedState<A::State, B::State>;
+
+    fn wait(&self, state: &mut Self::State) -> bool {
+        if state.count < self.first_count {
+            state.count += 1;
+            self.first.wait(&mut state.first);
+            // the second waiter is expected to wait for longer
+            false
+        } else {
+            self.then.wait(&mut state.then)
+        }
+    }
+
+    fn notify(&self) {
+        self.first.notify();
+        self.then.notify();
+    }
+}
+
 /// This waiter will spin for using exponential backoff, then park the thread
 #[cfg(feature = "std")]
 pub struct AdaptiveWait {
-    /// the thread parker
-    thread: ThreadParker,
+    /// spin, then park
+    inner: Budgeted<SpinWait, ThreadParker>,
 }
 
 #[cfg(feature = "std")]
 impl AdaptiveWait {
+    /// the default number of waits to spin for before parking
+    pub const DEFAULT_SPIN_BUDGET: u32 = 10;
+
     /// create a new adaptive waiter
     pub const fn new() -> Self {
+        Self::with_spin_budget(Self::DEFAULT_SPIN_BUDGET)
+    }
+
+    /// create a new adaptive waiter which spins for the first `spins` waits of each swap,
+    /// then parks the thread
+    ///
+    /// The `n`th spin is `2^n` iterations of [`spin_loop`](core::hint::spin_loop) (up to `2^10`),
+    /// so a budget of 0 always parks, and each extra wait roughly doubles the time spent spinning
+    pub const fn with_spin_budget(spins: u32) -> Self {
         Self {
-            thread: ThreadParker::new(),
+            inner: Budgeted::new(SpinWait, spins, ThreadParker::new()),
         }
     }
+
+    /// the number of waits to spin for before parking
+    pub const fn spin_budget(&self) -> u32 {
+        self.inner.first_count()
+    }
 }
 
 #[cfg(feature = "std")]
@@ -132,28 +435,30 @@ impl Default for AdaptiveWait {
     }
 }
 
+#[cfg(feature = "std")]
+impl FreshCopy for AdaptiveWait {
+    fn fresh_copy(&self) -> Self {
+        Self::with_spin_budget(self.spin_budget())
+    }
+}
+
 #[cfg(feature = "std")]
 impl WaitStrategy for AdaptiveWait {
-    type State = u32;
+    type State = <Budgeted<SpinWait, ThreadParker> as WaitStrategy>::State;
 
     #[cold]
-    fn wait(&self, counter: &mut Self::State) -> bool {
-        if SpinWait.wait(counter) {
-            self.thread.wait(&mut ());
-
-            true
-        } else {
-            false
-        }
+    fn wait(&self, state: &mut Self::State) -> bool {
+        self.inner.wait(state)
     }
 
     fn notify(&self) {
-        self.thread.notify();
+        self.inner.notify();
     }
 }
 
 /// This waiter will spin for using exponential backoff, then park the thread
 ///
+/// Without the `std` feature there is no thread to park, so it only spins like [`SpinWait`].
 /// This behavior is subject to change
 pub struct DefaultWait {
     /// the inner parker type
@@ -171,22 +476,39 @@ impl DefaultWait {
     }
 }
 
-#[cfg(feature = "std")]
 impl Default for DefaultWait {
     fn default() -> Self {
         Self::new()
     }
 }
 
+impl FreshCopy for DefaultWait {
+    fn fresh_copy(&self) -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            adaptive: self.adaptive.fresh_copy(),
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl WaitStrategy for DefaultWait {
+    type State = <SpinWait as WaitStrategy>::State;
+
+    #[inline]
+    fn wait(&self, counter: &mut Self::State) -> bool {
+        SpinWait.wait(counter)
+    }
+
+    fn notify(&self) {}
+}
+
 #[cfg(feature = "std")]
 impl WaitStrategy for DefaultWait {
-    type State = u32;
+    type State = <AdaptiveWait as WaitStrategy>::State;
 
     #[inline]
     fn wait(&self, counter: &mut Self::State) -> bool {
-        #[cfg(not(feature = "std"))]
-        SpinWait.park(counter);
-        #[cfg(feature = "std")]
         self.adaptive.wait(counter)
     }
 
@@ -195,3 +517,210 @@ impl WaitStrategy for DefaultWait {
         self.adaptive.notify();
     }
 }
+
+#[cfg(test)]
+/// a waiter which counts its waits and notifies
+#[derive(Default)]
+struct CountingWait {
+    /// the number of waits
+    waits: core::cell::Cell<u32>,
+    /// the number of notifies
+    notifies: core::cell::Cell<u32>,
+    /// the value returned from wait
+    saturated: bool,
+}
+
+#[cfg(test)]
+impl WaitStrategy for CountingWait {
+    type State = u32;
+
+    fn wait(&self, state: &mut Self::State) -> bool {
+        *state += 1;
+        self.waits.set(self.waits.get() + 1);
+        self.saturated
+    }
+
+    fn notify(&self) {
+        self.notifies.set(self.notifies.get() + 1);
+    }
+}
+
+#[test]
+#[cfg_attr(feature = "loom", ignore = "when using loom: ignore normal tests")]
+fn test_spin_wait_saturates() {
+    let mut counter = 0;
+
+    for count in 0..10 {
+        assert!(!SpinWait.wait(&mut counter));
+        assert_eq!(counter, count + 1);
+    }
+
+    for _ in 0..3 {
+        assert!(SpinWait.wait(&mut counter));
+        assert_eq!(counter, 10);
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_yield_wait() {
+    let mut yields = 0;
+    for count in 1..=3 {
+        assert!(YieldWait.wait(&mut yields));
+        assert_eq!(yields, count);
+    }
+
+    // a writer which yields still sees the reader leave without a notify
+    let mut writer = crate::raw::Writer::new(crate::ptrs::alloc::Owned::new(
+        crate::raw::Shared::from_raw_parts(
+            crate::strategy::HazardStrategy::with_wait_strategy(YieldWait),
+            crate::raw::RawDBuf::new(0, 0),
+        ),
+    ));
+    let mut reader = writer.reader();
+    let guard = reader.get();
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard);
+        });
+        *writer.split_mut().writer = 1;
+        assert!(writer.swap_buffers_with_stats().pauses > 0);
+    });
+    assert_eq!(*reader.get(), 1);
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_priority_aware_parker() {
+    use std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    let long_waits = Mutex::new(std::vec::Vec::new());
+    let parker = PriorityAwareParker::new(2, Duration::from_millis(1), |waits| {
+        long_waits.lock().unwrap().push(waits)
+    });
+
+    // waits time out without a notify, and only the ones after the threshold call the hook
+    let mut state = 0;
+    for _ in 0..4 {
+        assert!(parker.wait(&mut state));
+    }
+    assert_eq!(*long_waits.lock().unwrap(), [3, 4]);
+
+    // a notify wakes the next wait up right away, even with a long timeout
+    let parker = PriorityAwareParker::new(0, Duration::from_secs(60), |_| ());
+    parker.notify();
+    let start = Instant::now();
+    parker.wait(&mut 0);
+    assert!(start.elapsed() < Duration::from_secs(30));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_fresh_copy_keeps_config() {
+    use std::time::Duration;
+
+    let wait = AdaptiveWait::with_spin_budget(3);
+    assert_eq!(wait.fresh_copy().spin_budget(), 3);
+
+    let parker = PriorityAwareParker::new(2, Duration::from_millis(5), |_| ());
+    let parker = parker.fresh_copy();
+    assert_eq!(parker.threshold(), 2);
+    assert_eq!(parker.timeout(), Duration::from_millis(5));
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(not(feature = "loom"))]
+fn test_priority_aware_parker_slow_reader() {
+    use std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    let long_waits = Mutex::new(std::vec::Vec::new());
+    let mut shared = crate::raw::Shared::from_raw_parts(
+        crate::strategy::HazardStrategy::with_wait_strategy(PriorityAwareParker::new(
+            3,
+            Duration::from_millis(1),
+            |waits| long_waits.lock().unwrap().push(waits),
+        )),
+        crate::raw::RawDBuf::new(0, 0),
+    );
+    let mut writer = crate::raw::Writer::new(&mut shared);
+    let mut reader = writer.reader();
+    let guard = reader.get();
+    let released = Mutex::new(None);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            // a slow reader holds its guard while the writer swaps
+            std::thread::sleep(Duration::from_millis(50));
+            *released.lock().unwrap() = Some(Instant::now());
+            drop(guard);
+        });
+        *writer.split_mut().writer = 1;
+        writer.swap_buffers();
+    });
+
+    let swapped = Instant::now();
+    let released = released.lock().unwrap().unwrap();
+    assert!(swapped.duration_since(released) < Duration::from_millis(40));
+
+    // the hook fires repeatedly while the reader is stuck, with increasing counts
+    let long_waits = long_waits.lock().unwrap().clone();
+    assert!(long_waits.len() > 1, "{long_waits:?}");
+    assert_eq!(long_waits[0], 4);
+    assert!(long_waits.windows(2).all(|waits| waits[0] < waits[1]));
+    assert_eq!(*reader.get(), 1);
+}
+
+#[test]
+fn test_budgeted() {
+    let wait = Budgeted::new(
+        CountingWait::default(),
+        3,
+        CountingWait {
+            saturated: true,
+            ..Default::default()
+        },
+    );
+    let mut state = BudgetedState::default();
+
+    for count in 1..=3 {
+        assert!(!wait.wait(&mut state));
+        assert_eq!((wait.first.waits.get(), wait.then.waits.get()), (count, 0));
+    }
+
+    for count in 1..=2 {
+        assert!(wait.wait(&mut state));
+        assert_eq!((wait.first.waits.get(), wait.then.waits.get()), (3, count));
+    }
+    assert_eq!((state.count, state.first, state.then), (3, 3, 2));
+
+    // each swap starts with a fresh state, so it gets the full budget again
+    let mut state = BudgetedState::default();
+    assert!(!wait.wait(&mut state));
+    assert_eq!((wait.first.waits.get(), wait.then.waits.get()), (4, 2));
+
+    wait.notify();
+    assert_eq!(
+        (wait.first.notifies.get(), wait.then.notifies.get()),
+        (1, 1)
+    );
+}
+
+#[test]
+fn test_budgeted_without_budget() {
+    let wait = Budgeted::new(CountingWait::default(), 0, CountingWait::default());
+    let mut state = BudgetedState::default();
+
+    wait.wait(&mut state);
+    wait.wait(&mut state);
+    assert_eq!((wait.first.waits.get(), wait.then.waits.get()), (0, 2));
+}
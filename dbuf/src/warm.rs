@@ -0,0 +1,28 @@
+//! Helpers to warm up the writer buffer after a swap
+//!
+//! see [`DelayedWriter::set_post_swap`](crate::delayed::DelayedWriter::set_post_swap)
+
+/// the smallest page size of common platforms
+const PAGE_SIZE: usize = 4096;
+
+/// Touch one byte in every page of `buffer`
+///
+/// This brings the pages into the TLB, and the touched cache lines into this core's cache.
+/// Each byte is read and written back, so that its cache line is owned by this core and the
+/// next write to it doesn't need to wait for other cores.
+///
+/// ```
+/// use dbuf::{delayed::DelayedWriter, ptrs::alloc::Owned, raw::Writer, strategy::TrackingStrategy};
+///
+/// let mut writer = DelayedWriter::new(Writer::new(
+///     Owned::<TrackingStrategy, _>::from_buffers(vec![0_u8; 1 << 20], vec![0_u8; 1 << 20]),
+/// ));
+/// writer.set_post_swap(|buffer: &mut Vec<u8>| dbuf::warm::touch_pages(buffer));
+/// ```
+pub fn touch_pages(buffer: &mut [u8]) {
+    for byte in buffer.iter_mut().step_by(PAGE_SIZE) {
+        // SAFETY: `byte` is a valid `&mut u8`, and volatile accesses stop the compiler from
+        // removing the write back of the same value
+        unsafe { core::ptr::write_volatile(byte, core::ptr::read_volatile(byte)) }
+    }
+}
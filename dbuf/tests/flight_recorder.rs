@@ -0,0 +1,156 @@
+//! record stress runs with a flight recorder, and check that no reader overlapped the writer
+//!
+//! Each test runs reader threads which keep acquiring guards while the writer publishes,
+//! like the tests in `progress.rs`, then verifies the dump of the recorder
+
+#![cfg(all(feature = "flight-recorder", not(feature = "loom")))]
+
+use std::{
+    hint::black_box,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use dbuf::{
+    flight_recorder::{verify_dump, FlightRecorder, RecordedStrategy},
+    interface::Strategy,
+    op::OpWriter,
+    op_log::Operation,
+    ptrs::alloc::Owned,
+    raw::{RawDBuf, Shared, Writer},
+    strategy::{AdaptiveStrategy, HazardStrategy, TrackingStrategy},
+};
+
+/// how long the writer publishes in a loop
+const RUN: Duration = Duration::from_millis(200);
+/// the number of reader threads
+const READERS: usize = 2;
+/// the number of events the recorder keeps, enough for a few thousand publishes
+const CAPACITY: usize = 1 << 16;
+
+type Buffer = [u64; 4];
+
+/// record `READERS` threads reading while the writer publishes, and return the dump
+fn record_run<S>(strategy: S) -> String
+where
+    S: Strategy<ValidationError = core::convert::Infallible>,
+    S::ReaderTag: Send,
+    S: Sync,
+    S::Which: Sync,
+{
+    let recorder = Arc::new(FlightRecorder::new(CAPACITY));
+    let mut shared = Shared::from_raw_parts(
+        RecordedStrategy::new(strategy, recorder.clone()),
+        RawDBuf::new([0; 4], [0; 4]),
+    );
+    let mut writer = Writer::new(&mut shared);
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        for _ in 0..READERS {
+            let mut reader = writer.reader();
+            let done = &done;
+            s.spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let [a, b, c, d] = *black_box(reader.get());
+                    assert!(a == b && b == c && c == d, "torn read");
+                }
+            });
+        }
+
+        let start = std::time::Instant::now();
+        let mut publishes = 0;
+        while start.elapsed() < RUN {
+            publishes += 1;
+            *writer.split_mut().writer = [publishes; 4];
+            writer.swap_buffers();
+        }
+        writer.wait_for_quiescence();
+        done.store(true, Ordering::Relaxed);
+    });
+
+    assert!(recorder.recorded() > 0);
+    let mut dump = Vec::new();
+    recorder.dump(&mut dump).unwrap();
+    String::from_utf8(dump).unwrap()
+}
+
+/// check that a recorded run has swaps and guards, and passes [`verify_dump`]
+fn check_run<S>(strategy: S)
+where
+    S: Strategy<ValidationError = core::convert::Infallible>,
+    S::ReaderTag: Send,
+    S: Sync,
+    S::Which: Sync,
+{
+    let dump = record_run(strategy);
+    assert!(dump.contains(",swap_finish,"));
+    assert!(dump.contains(",guard_end,"));
+    if let Err(violation) = verify_dump(dump.as_bytes()) {
+        panic!(
+            "{} readers overlapped the writer: {violation}",
+            std::any::type_name::<S>()
+        );
+    }
+}
+
+#[test]
+fn hazard_run_verifies() {
+    check_run(HazardStrategy::new())
+}
+
+#[test]
+fn tracking_run_verifies() {
+    check_run(TrackingStrategy::new())
+}
+
+#[test]
+fn adaptive_run_verifies() {
+    check_run(AdaptiveStrategy::new())
+}
+
+/// adds to every element
+struct Add(u64);
+
+impl Operation<Buffer> for Add {
+    fn apply(&mut self, buffer: &mut Buffer) {
+        buffer.iter_mut().for_each(|x| *x += self.0)
+    }
+}
+
+#[test]
+fn op_writer_records_publishes() {
+    let recorder = Arc::new(FlightRecorder::new(CAPACITY));
+    let strategy = RecordedStrategy::new(HazardStrategy::new(), recorder.clone());
+    let mut writer = OpWriter::from(Writer::new(Owned::new(Shared::from_raw_parts(
+        strategy,
+        RawDBuf::new([0; 4], [0; 4]),
+    ))));
+    writer.set_flight_recorder(Some(recorder.clone()));
+    let mut reader = writer.reader();
+
+    writer.apply(Add(1));
+    writer.apply(Add(2));
+    writer.publish();
+    let guard = reader.get();
+    writer.apply(Add(3));
+    writer.publish();
+    drop(guard);
+    writer.swap_buffers();
+    assert_eq!(*reader.get(), [6; 4]);
+
+    let mut dump = Vec::new();
+    recorder.dump(&mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    let publishes = dump
+        .lines()
+        .filter(|line| line.contains(",publish,"))
+        .map(|line| line.split(',').skip(4).collect::<Vec<_>>().join(","))
+        .collect::<Vec<_>>();
+    // swapping the buffers publishes too, even without any operations
+    assert_eq!(publishes, ["1,2", "2,1", "3,0"]);
+    verify_dump(dump.as_bytes()).unwrap();
+}
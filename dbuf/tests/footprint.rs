@@ -0,0 +1,177 @@
+//! compare the estimated footprint of double buffers with what they actually allocated
+//!
+//! A counting global allocator keeps track of the bytes allocated by each thread,
+//! so the tests can run in parallel. The estimates should be within 20%
+
+#![cfg(all(feature = "std", not(feature = "loom")))]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
+};
+
+use dbuf::{
+    footprint::{MemoryFootprint, MemoryUsage},
+    interface::Strategy,
+    op::OpWriter,
+    op_log::Operation,
+    ptrs::alloc::Owned,
+    raw::Writer,
+    strategy::{HazardStrategy, TrackingStrategy},
+};
+
+/// forwards to the system allocator, and counts the bytes which are allocated by each thread
+struct CountingAlloc;
+
+thread_local! {
+    /// the bytes allocated by this thread which weren't freed yet
+    static ALLOCATED: Cell<isize> = const { Cell::new(0) };
+}
+
+/// add `bytes` to this thread's count, unless the thread is being torn down
+fn count(bytes: isize) {
+    let _ = ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + bytes));
+}
+
+// SAFETY: forwards to the system allocator
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size() as isize);
+        // SAFETY: forwarded from the caller
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(-(layout.size() as isize));
+        // SAFETY: forwarded from the caller
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// the bytes allocated by this thread while running `f`, which weren't freed yet
+fn allocated_by<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.with(Cell::get);
+    let value = f();
+    let after = ALLOCATED.with(Cell::get);
+    (value, (after - before) as usize)
+}
+
+/// check that the footprint is within 20% of what was allocated
+///
+/// The footprint includes the size of the writer's inline parts, which may not be on the heap
+#[track_caller]
+fn assert_close(estimate: usize, allocated: usize) {
+    let error = estimate.abs_diff(allocated) as f64 / allocated as f64;
+    assert!(
+        error <= 0.2,
+        "estimated {estimate} bytes, but {allocated} bytes were allocated ({:.0}% off)",
+        error * 100.0
+    );
+}
+
+/// an op writer over `buffer`, whose operations are closures
+fn op_writer<B: Clone + Send + 'static, S: Strategy + Default>(
+    buffer: B,
+) -> OpWriter<dbuf::ptrs::alloc::OwnedPtr<S, dbuf::raw::RawDBuf<B>>, dbuf::op_log::ApplyToBoth<B>> {
+    OpWriter::from(Writer::new(Owned::from_buffers(buffer.clone(), buffer)))
+}
+
+#[test]
+fn vec_buffers() {
+    let (writer, allocated) =
+        allocated_by(|| op_writer::<_, HazardStrategy>((0..10_000u64).collect::<Vec<_>>()));
+    let footprint = writer.footprint();
+    assert_close(footprint.total(), allocated);
+    assert_close(footprint.buffers, 2 * 10_000 * 8);
+}
+
+#[test]
+fn hash_map_buffers() {
+    let (writer, allocated) = allocated_by(|| {
+        let map = (0..1000u64)
+            .map(|i| (i, i.to_string().repeat(4)))
+            .collect::<HashMap<_, _>>();
+        op_writer::<_, HazardStrategy>(map)
+    });
+    assert_close(writer.footprint().total(), allocated);
+}
+
+#[test]
+fn btree_map_buffers() {
+    let (ascending, allocated) = allocated_by(|| {
+        let mut map = BTreeMap::new();
+        for i in 0..10_000u64 {
+            map.insert(i, i);
+        }
+        map
+    });
+    assert_close(
+        size_of::<BTreeMap<u64, u64>>() + ascending.heap_bytes(),
+        allocated,
+    );
+    // a clone has the same nodes
+    let (_, allocated) = allocated_by(|| ascending.clone());
+    assert_close(
+        size_of::<BTreeMap<u64, u64>>() + ascending.heap_bytes(),
+        allocated,
+    );
+
+    // a simple linear congruential generator, so the keys are inserted out of order
+    let mut key = 1u64;
+    let (random, allocated) = allocated_by(|| {
+        let mut map = BTreeMap::new();
+        for _ in 0..10_000 {
+            key = key.wrapping_mul(6364136223846793005).wrapping_add(1);
+            map.insert(key >> 32, [key; 2]);
+        }
+        map
+    });
+    assert_close(
+        size_of::<BTreeMap<u64, [u64; 2]>>() + random.heap_bytes(),
+        allocated,
+    );
+}
+
+/// pushes to the buffer
+struct Push(u64);
+
+impl Operation<Vec<u64>> for Push {
+    fn apply(&mut self, buffer: &mut Vec<u64>) {
+        buffer.push(self.0)
+    }
+}
+
+impl MemoryUsage for Push {
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+#[test]
+fn op_log_and_readers() {
+    let ((writer, readers), allocated) = allocated_by(|| {
+        let mut writer = OpWriter::<_, Push>::from(Writer::new(
+            Owned::<TrackingStrategy, _>::from_buffers(Vec::new(), Vec::new()),
+        ));
+        for i in 0..1000 {
+            writer.apply(Push(i));
+        }
+        writer.publish();
+        for i in 0..1000 {
+            writer.apply(Push(i));
+        }
+        let readers = (0..100).map(|_| writer.reader()).collect::<Vec<_>>();
+        (writer, readers)
+    });
+
+    let footprint = writer.footprint();
+    assert!(footprint.op_log >= 2000 * size_of::<Push>());
+    assert!(footprint.strategy > 100 * size_of::<usize>());
+    // the readers themselves aren't part of the footprint
+    let readers_size = readers.capacity() * size_of_val(&readers[0]);
+    assert_close(footprint.total(), allocated - readers_size);
+}
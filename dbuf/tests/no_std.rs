@@ -0,0 +1,70 @@
+//! the op writer with an [`ArrayOpLog`] only needs `core`, run this with
+//! `cargo test -p dbuf --no-default-features --test no_std` to check that it works without `alloc`
+
+#![no_std]
+#![cfg(not(feature = "loom"))]
+
+use dbuf::{
+    delayed::DelayedWriter,
+    op::OpWriter,
+    op_log::{ArrayOpLog, OpLogFull, Operation},
+    raw::{RawDBuf, Shared, Writer},
+    strategy::AtomicCounterStrategy,
+};
+
+/// a fused sensor reading
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct State {
+    /// the sum of all samples
+    sum: i32,
+    /// the number of samples
+    samples: u32,
+}
+
+/// add a sample to the state
+struct Sample(i32);
+
+impl Operation<State> for Sample {
+    fn apply(&mut self, buffer: &mut State) {
+        buffer.sum += self.0;
+        buffer.samples += 1;
+    }
+}
+
+#[test]
+fn array_op_writer_without_alloc() {
+    let mut shared = Shared::from_raw_parts(
+        AtomicCounterStrategy::new(),
+        RawDBuf::new(State::default(), State::default()),
+    );
+    let writer = DelayedWriter::new(Writer::new(&mut shared));
+    let mut writer =
+        OpWriter::<_, _, ArrayOpLog<Sample, 3>>::from_raw_parts(writer, ArrayOpLog::new());
+    let mut reader = writer.reader();
+
+    for sample in [1, 2, 3] {
+        assert!(writer.try_apply(Sample(sample)).is_ok());
+    }
+    // a full log hands the op back instead of allocating
+    let OpLogFull(op) = writer.try_apply(Sample(4)).unwrap_err();
+    assert_eq!(op.0, 4);
+
+    writer.publish();
+    assert_eq!(*reader.get(), State { sum: 6, samples: 3 });
+
+    // the other buffer still needs the ops, until the next swap catches it up
+    assert!(writer.try_apply(Sample(op.0)).is_err());
+    for samples in 4..7 {
+        writer.swap_buffers();
+        assert!(writer.try_apply(Sample(op.0)).is_ok());
+        writer.publish();
+        assert_eq!(reader.get().samples, samples);
+    }
+    assert_eq!(
+        *reader.get(),
+        State {
+            sum: 18,
+            samples: 6
+        }
+    );
+}
@@ -0,0 +1,262 @@
+//! readers make progress no matter how often the writer publishes, see the `strategy` module docs
+//!
+//! Each test runs a writer which publishes in a tight loop for a while, and counts how many
+//! reads the reader threads finish in the meantime. Acquiring a guard never waits for the writer,
+//! so readers must get within an order of magnitude of the rate they get without a writer.
+//!
+//! The `writer_*` tests are the other way around: reader threads keep acquiring guards, so that
+//! some guard is held at all times, and new readers are created and dropped in a loop, while the
+//! writer publishes. Each swap only waits for the guards which were held when it started, so the
+//! writer must still publish at a steady rate.
+//!
+//! The tests measure throughput, so they take a few seconds and depend on the machine being
+//! otherwise idle. They're ignored by default, run them with `cargo test --test progress -- --ignored`
+
+#![cfg(all(feature = "std", not(feature = "loom")))]
+
+use std::{
+    hint::black_box,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use dbuf::{
+    interface::Strategy,
+    raw::{RawDBuf, Reader, Shared, Writer, OPTIMISTIC_READ_RETRIES},
+    strategy::{AdaptiveStrategy, HazardStrategy, TrackingStrategy},
+};
+
+/// how long the writer publishes in a loop
+const CONTENDED: Duration = Duration::from_secs(1);
+/// how long the readers run without a writer, to find the uncontended rate
+const UNCONTENDED: Duration = Duration::from_millis(250);
+/// the number of reader threads
+const READERS: usize = 2;
+/// how long the readers keep acquiring guards while the writer publishes
+const STORM: Duration = Duration::from_secs(1);
+/// the minimum number of publishes the writer must finish in `STORM`
+///
+/// Each swap may have to wait for a reader thread to be scheduled again to release its guard,
+/// so this is well below the uncontended rate, but a starved writer gets nowhere close
+const MIN_PUBLISHES: u64 = 100;
+
+/// the tests measure throughput, so they shouldn't run at the same time
+static SERIAL: Mutex<()> = Mutex::new(());
+
+type Buffer = [u64; 4];
+type SharedReader<'a, S> = Reader<&'a Shared<S, RawDBuf<Buffer>>>;
+
+/// the number of reads per second which `READERS` threads finish in `duration`,
+/// while a writer publishes in a loop if `publish` is true
+fn reads_per_sec<S>(
+    strategy: S,
+    duration: Duration,
+    publish: bool,
+    read: impl Fn(&mut SharedReader<S>) -> Buffer + Sync,
+) -> f64
+where
+    S: Strategy<ValidationError = core::convert::Infallible>,
+    S::ReaderTag: Send,
+    Shared<S, RawDBuf<Buffer>>: Sync,
+{
+    let mut shared = Shared::from_raw_parts(strategy, RawDBuf::new([0; 4], [0; 4]));
+    let mut writer = Writer::new(&mut shared);
+    let done = AtomicBool::new(false);
+    let reads = AtomicU64::new(0);
+
+    std::thread::scope(|s| {
+        for _ in 0..READERS {
+            let mut reader = writer.reader();
+            let (done, reads, read) = (&done, &reads, &read);
+            s.spawn(move || {
+                let mut count = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let [a, b, c, d] = read(&mut reader);
+                    assert!(a == b && b == c && c == d, "torn read");
+                    count += 1;
+                }
+                reads.fetch_add(count, Ordering::Relaxed);
+            });
+        }
+
+        let start = std::time::Instant::now();
+        let mut publishes = 0u64;
+        while start.elapsed() < duration {
+            if publish {
+                publishes += 1;
+                *writer.split_mut().writer = [publishes; 4];
+                writer.swap_buffers();
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        done.store(true, Ordering::Relaxed);
+
+        if publish {
+            assert!(
+                publishes > 100,
+                "the writer only published {publishes} times"
+            );
+        }
+    });
+
+    reads.load(Ordering::Relaxed) as f64 / duration.as_secs_f64()
+}
+
+/// read through a read guard
+fn guarded<S: Strategy<ValidationError = core::convert::Infallible>>(
+    reader: &mut SharedReader<S>,
+) -> Buffer {
+    *black_box(reader.get())
+}
+
+/// check that guarded reads with a publishing writer are within an order of magnitude of the uncontended rate
+fn check_guarded<S>(strategy: impl Fn() -> S)
+where
+    S: Strategy<ValidationError = core::convert::Infallible>,
+    S::ReaderTag: Send,
+    Shared<S, RawDBuf<Buffer>>: Sync,
+{
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+    let uncontended = reads_per_sec(strategy(), UNCONTENDED, false, guarded);
+    let contended = reads_per_sec(strategy(), CONTENDED, true, guarded);
+    assert!(
+        contended * 10.0 >= uncontended,
+        "{} readers starved: {contended:.0} reads/s while publishing, {uncontended:.0} reads/s without",
+        std::any::type_name::<S>(),
+    );
+}
+
+#[test]
+#[ignore = "measures throughput, run with `cargo test --test progress -- --ignored`"]
+fn hazard_readers_make_progress() {
+    check_guarded(HazardStrategy::new)
+}
+
+#[test]
+#[ignore = "measures throughput, run with `cargo test --test progress -- --ignored`"]
+fn tracking_readers_make_progress() {
+    check_guarded(TrackingStrategy::new)
+}
+
+#[test]
+#[ignore = "measures throughput, run with `cargo test --test progress -- --ignored`"]
+fn adaptive_readers_make_progress() {
+    check_guarded(AdaptiveStrategy::new)
+}
+
+#[test]
+#[ignore = "measures throughput, run with `cargo test --test progress -- --ignored`"]
+fn optimistic_readers_make_progress() {
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+    let validated = AtomicU64::new(0);
+    let optimistic = |reader: &mut SharedReader<HazardStrategy>| {
+        if let Some(copy) = reader.try_optimistic_read(0, |&buffer| buffer) {
+            validated.fetch_add(1, Ordering::Relaxed);
+            return copy;
+        }
+        // falls back to a read guard, so this can't be starved
+        reader.optimistic_read_with_retries(OPTIMISTIC_READ_RETRIES, |&buffer| buffer)
+    };
+
+    let uncontended = reads_per_sec(HazardStrategy::new(), UNCONTENDED, false, optimistic);
+    let contended = reads_per_sec(HazardStrategy::new(), CONTENDED, true, optimistic);
+    assert!(
+        contended * 10.0 >= uncontended,
+        "optimistic readers starved: {contended:.0} reads/s while publishing, {uncontended:.0} reads/s without",
+    );
+    // without retries some reads may be torn, but most still succeed
+    assert!(validated.load(Ordering::Relaxed) > 0);
+}
+
+/// the number of publishes the writer finishes in `STORM` while `READERS` threads keep acquiring guards
+///
+/// Each reader thread has two readers, and acquires a guard from one before releasing the guard of
+/// the other, so it always holds a guard. Another thread keeps creating readers and dropping them.
+fn publishes_during_storm<S>(strategy: S) -> u64
+where
+    S: Strategy<ValidationError = core::convert::Infallible>,
+    S::ReaderTag: Send,
+    Shared<S, RawDBuf<Buffer>>: Sync,
+{
+    let mut shared = Shared::from_raw_parts(strategy, RawDBuf::new([0; 4], [0; 4]));
+    let mut writer = Writer::new(&mut shared);
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        for _ in 0..READERS {
+            let (mut first, mut second) = (writer.reader(), writer.reader());
+            let done = &done;
+            s.spawn(move || {
+                let check = |buffer: &Buffer| {
+                    let [a, b, c, d] = *black_box(buffer);
+                    assert!(a == b && b == c && c == d, "torn read");
+                };
+                let mut held = first.get();
+                while !done.load(Ordering::Relaxed) {
+                    let next = second.get();
+                    check(&held);
+                    drop(held);
+                    held = first.get();
+                    check(&next);
+                }
+            });
+        }
+
+        let reader = writer.reader();
+        let done = &done;
+        s.spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                let mut new_reader = reader.clone();
+                black_box(*new_reader.get());
+            }
+        });
+
+        let start = std::time::Instant::now();
+        let mut publishes = 0;
+        while start.elapsed() < STORM {
+            publishes += 1;
+            *writer.split_mut().writer = [publishes; 4];
+            writer.swap_buffers();
+        }
+        done.store(true, Ordering::Relaxed);
+        publishes
+    })
+}
+
+/// check that the writer isn't starved by readers which always hold a guard
+fn check_writer<S>(strategy: S)
+where
+    S: Strategy<ValidationError = core::convert::Infallible>,
+    S::ReaderTag: Send,
+    Shared<S, RawDBuf<Buffer>>: Sync,
+{
+    let _serial = SERIAL.lock().unwrap_or_else(|err| err.into_inner());
+    let publishes = publishes_during_storm(strategy);
+    assert!(
+        publishes >= MIN_PUBLISHES,
+        "{} writer starved: only {publishes} publishes in {STORM:?}",
+        std::any::type_name::<S>(),
+    );
+}
+
+#[test]
+#[ignore = "measures throughput, run with `cargo test --test progress -- --ignored`"]
+fn hazard_writer_makes_progress() {
+    check_writer(HazardStrategy::new())
+}
+
+#[test]
+#[ignore = "measures throughput, run with `cargo test --test progress -- --ignored`"]
+fn tracking_writer_makes_progress() {
+    check_writer(TrackingStrategy::new())
+}
+
+#[test]
+#[ignore = "measures throughput, run with `cargo test --test progress -- --ignored`"]
+fn adaptive_writer_makes_progress() {
+    check_writer(AdaptiveStrategy::new())
+}
@@ -0,0 +1,169 @@
+//! the shared state, the strategies and the wait strategies can all be built in a const context,
+//! so a double buffer can be put in a `static`
+
+#![cfg(all(feature = "std", not(feature = "loom")))]
+
+use dbuf::{
+    interface::Strategy,
+    raw::{PaddedRawDBuf, RawDBuf, Shared, SyncShared, Writer},
+    strategy::{
+        AdaptiveStrategy, HazardStrategy, LocalHazardStrategy, LocalStrategy,
+        LocalTrackingStrategy, TrackingStrategy,
+    },
+    wait::{AdaptiveWait, DefaultWait, NoopWait, SpinWait, ThreadParker, YieldWait},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Foo {
+    id: u32,
+    name: [u8; 4],
+}
+
+const FRONT: Foo = Foo {
+    id: 1,
+    name: *b"init",
+};
+const BACK: Foo = Foo {
+    id: 1,
+    name: *b"init",
+};
+
+static HAZARD: SyncShared<Foo> = SyncShared::from_buffers(FRONT, BACK);
+static SPIN: Shared<HazardStrategy<SpinWait>, RawDBuf<Foo>> = Shared::from_raw_parts(
+    HazardStrategy::with_wait_strategy(SpinWait::new()),
+    RawDBuf::new(FRONT, BACK),
+);
+static NOOP: Shared<HazardStrategy<NoopWait>, RawDBuf<Foo>> = Shared::from_raw_parts(
+    HazardStrategy::with_wait_strategy(NoopWait::new()),
+    RawDBuf::new(FRONT, BACK),
+);
+static YIELD: Shared<HazardStrategy<YieldWait>, PaddedRawDBuf<Foo>> = Shared::from_raw_parts(
+    HazardStrategy::with_wait_strategy(YieldWait::new()),
+    PaddedRawDBuf::new(FRONT, BACK),
+);
+static PARKER: Shared<HazardStrategy<ThreadParker>, RawDBuf<Foo>> = Shared::from_raw_parts(
+    HazardStrategy::with_wait_strategy(ThreadParker::new()),
+    RawDBuf::new(FRONT, BACK),
+);
+static TRACKING: Shared<TrackingStrategy, RawDBuf<Foo>> =
+    Shared::from_raw_parts(TrackingStrategy::new(), RawDBuf::new(FRONT, BACK));
+static ADAPTIVE: Shared<AdaptiveStrategy<AdaptiveWait>, RawDBuf<Foo>> = Shared::from_raw_parts(
+    AdaptiveStrategy::with_wait_strategy(AdaptiveWait::new()),
+    RawDBuf::new(FRONT, BACK),
+);
+static ADAPTIVE_DEFAULT: Shared<AdaptiveStrategy, RawDBuf<Foo>> =
+    Shared::from_raw_parts(AdaptiveStrategy::new(), RawDBuf::new(FRONT, BACK));
+
+/// publish a change through the writer, and check that a reader sees it
+fn publish<S: Strategy>(mut writer: Writer<&'static Shared<S, RawDBuf<Foo>>>) {
+    let mut reader = writer.reader();
+    assert_eq!(*reader.get(), FRONT);
+
+    writer.split_mut().writer.id = 2;
+    assert!(writer.try_swap_buffers().is_ok());
+    assert_eq!(reader.get().id, 2);
+}
+
+#[test]
+fn statics_are_sync() {
+    fn assert_sync<T: Sync>(_: &T) {}
+
+    assert_sync(&HAZARD);
+    assert_sync(&SPIN);
+    assert_sync(&NOOP);
+    assert_sync(&YIELD);
+    assert_sync(&PARKER);
+    assert_sync(&TRACKING);
+    assert_sync(&ADAPTIVE);
+    assert_sync(&ADAPTIVE_DEFAULT);
+}
+
+#[test]
+fn local_strategies() {
+    // the local strategies aren't `Sync`, but the macros put the shared state in a `static mut`
+    publish(
+        dbuf::static_writer!(static S: Shared<LocalStrategy, RawDBuf<Foo>> =
+            Shared::from_raw_parts(LocalStrategy::new(), RawDBuf::new(FRONT, BACK))),
+    );
+    publish(dbuf::static_writer!(
+        static S: Shared<LocalHazardStrategy, RawDBuf<Foo>> =
+            Shared::from_raw_parts(LocalHazardStrategy::new(), RawDBuf::new(FRONT, BACK))
+    ));
+    publish(dbuf::static_writer!(
+        static S: Shared<LocalTrackingStrategy, RawDBuf<Foo>> =
+            Shared::from_raw_parts(LocalTrackingStrategy::new(), RawDBuf::new(FRONT, BACK))
+    ));
+}
+
+#[test]
+fn sync_strategies() {
+    publish(
+        dbuf::static_writer!(static S: SyncShared<Foo> = SyncShared::from_buffers(FRONT, BACK)),
+    );
+    publish(dbuf::static_writer!(
+        static S: Shared<HazardStrategy<SpinWait>, RawDBuf<Foo>> = Shared::from_raw_parts(
+            HazardStrategy::with_wait_strategy(SpinWait::new()),
+            RawDBuf::new(FRONT, BACK),
+        )
+    ));
+    publish(dbuf::static_writer!(
+        static S: Shared<HazardStrategy<DefaultWait>, RawDBuf<Foo>> = Shared::from_raw_parts(
+            HazardStrategy::with_allocator(dbuf::strategy::hazard::GlobalNodeAlloc),
+            RawDBuf::new(FRONT, BACK),
+        )
+    ));
+    publish(dbuf::static_writer!(
+        static S: Shared<TrackingStrategy, RawDBuf<Foo>> =
+            Shared::from_raw_parts(TrackingStrategy::new(), RawDBuf::new(FRONT, BACK))
+    ));
+    publish(dbuf::static_writer!(
+        static S: Shared<AdaptiveStrategy, RawDBuf<Foo>> =
+            Shared::from_raw_parts(AdaptiveStrategy::new(), RawDBuf::new(FRONT, BACK))
+    ));
+}
+
+#[test]
+fn static_writer_is_only_created_once() {
+    let writer = || {
+        dbuf::try_static_writer!(
+            static S: Shared<TrackingStrategy, RawDBuf<Foo>> =
+                Shared::from_raw_parts(TrackingStrategy::new(), RawDBuf::new(FRONT, BACK))
+        )
+    };
+
+    publish(writer().unwrap());
+    assert!(writer().is_none());
+}
+
+#[test]
+#[should_panic = "Tried to construct a static writer multiple times"]
+fn static_writer_panics_the_second_time() {
+    for _ in 0..2 {
+        dbuf::static_writer!(
+            static S: Shared<LocalStrategy, RawDBuf<Foo>> =
+                Shared::from_raw_parts(LocalStrategy::new(), RawDBuf::new(FRONT, BACK))
+        );
+    }
+}
+
+#[test]
+fn lazy_static_writer() {
+    // `vec!` isn't const, so the shared state is created on the first call instead
+    let create = || {
+        dbuf::try_lazy_static_writer!(
+            static S: Shared<TrackingStrategy, RawDBuf<Vec<Foo>>> =
+                Shared::from_raw_parts(TrackingStrategy::new(), RawDBuf::new(vec![FRONT], vec![BACK]))
+        )
+    };
+
+    let mut writer = create().unwrap();
+    let mut reader = writer.reader();
+    writer.split_mut().writer.push(Foo {
+        id: 2,
+        name: *b"next",
+    });
+    writer.swap_buffers();
+    assert_eq!(reader.get().len(), 2);
+
+    assert!(create().is_none());
+}
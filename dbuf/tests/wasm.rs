@@ -0,0 +1,250 @@
+//! double buffers on `wasm32-unknown-unknown`, run with `just wasm-test`
+//!
+//! Without the atomics target feature there is only one thread, so the tests check the
+//! configurations which are used there: a [`LocalStrategy`] behind the `LocalOwned` pointers,
+//! and an [`AtomicCounterStrategy`], whose writer and readers are `Send`, so they can be held
+//! across an `.await` in a future which must be `Send`.
+//!
+//! With the atomics target feature (and shared memory), a reader is moved into a web worker, and
+//! reads while the main thread publishes. The main thread of a browser isn't allowed to block,
+//! so the writer uses a [`HazardStrategy`] which only spins. The worker loads the test's own
+//! JS bindings from `import.meta.url`, so this needs the ES module output of `wasm-bindgen`,
+//! which `wasm-bindgen-test-runner` uses for browsers.
+
+#![cfg(target_arch = "wasm32")]
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use dbuf::{
+    op::OpWriter,
+    op_log::Operation,
+    ptrs::alloc::{LocalOwned, Owned},
+    raw::Writer,
+    strategy::{AtomicCounterStrategy, LocalStrategy},
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// pushes to the buffer
+struct Push(u32);
+
+impl Operation<Vec<u32>> for Push {
+    fn apply(&mut self, buffer: &mut Vec<u32>) {
+        buffer.push(self.0)
+    }
+}
+
+/// `T` may be sent to another thread
+fn assert_send<T: Send>(_: &T) {}
+
+/// a future which is pending once, so that the other tasks run before it's done
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn local_op_writer() {
+    let mut writer = OpWriter::<_, Push>::from(Writer::new(
+        LocalOwned::<LocalStrategy, _>::from_buffers(Vec::new(), Vec::new()),
+    ));
+    let mut reader = writer.reader();
+
+    for i in 0..10 {
+        writer.apply(Push(i));
+    }
+    assert!(reader.get().is_empty());
+
+    // a local strategy can't wait for readers, so publishing may fail
+    writer.publish_checked().unwrap();
+    assert_eq!(*reader.get(), (0..10).collect::<Vec<_>>());
+
+    // publishing again applies the operations to the other buffer too
+    writer.apply(Push(10));
+    writer.publish_checked().unwrap();
+    assert_eq!(*reader.get(), (0..11).collect::<Vec<_>>());
+}
+
+#[wasm_bindgen_test]
+async fn atomic_counter_across_await() {
+    let mut writer = OpWriter::<_, Push>::from(Writer::new(
+        Owned::<AtomicCounterStrategy, _>::from_buffers(Vec::new(), Vec::new()),
+    ));
+    let mut reader = writer.reader();
+    assert_send(&writer);
+    assert_send(&reader);
+
+    let task = async move {
+        for i in 0..10 {
+            writer.apply(Push(i));
+            writer.publish();
+            // let the other tasks on this thread run
+            YieldNow(false).await;
+            assert_eq!(reader.get().last(), Some(&i));
+        }
+        reader.get().len()
+    };
+    assert_send(&task);
+
+    assert_eq!(task.await, 10);
+}
+
+#[cfg(target_feature = "atomics")]
+mod worker {
+    use std::{
+        future::Future,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use dbuf::{ptrs::alloc::Owned, raw::Writer, strategy::HazardStrategy, wait::SpinWait};
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen_futures::JsFuture;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    /// the script of each worker, which instantiates this module on the same memory and runs the work
+    const WORKER_SCRIPT: &str = "
+        self.onmessage = async (event) => {
+            const [url, module, memory, work] = event.data;
+            const bindings = await import(url);
+            await bindings.default({ module_or_path: module, memory });
+            bindings.dbuf_test_worker_entry(work);
+            self.postMessage('done');
+        };
+    ";
+
+    /// the last value the writer publishes
+    const LAST: u64 = u64::MAX;
+
+    #[wasm_bindgen]
+    extern "C" {
+        /// the url of the JS bindings of this module
+        #[wasm_bindgen(thread_local_v2, js_namespace = ["import", "meta"], js_name = url)]
+        static BINDINGS_URL: String;
+    }
+
+    /// the work which was sent to a worker
+    type Work = Box<dyn FnOnce() + Send>;
+
+    /// run the work sent by [`spawn`], this is called from [`WORKER_SCRIPT`]
+    #[wasm_bindgen]
+    pub fn dbuf_test_worker_entry(work: usize) {
+        // SAFETY: `spawn` leaked this box, and each worker runs its work once
+        let work = unsafe { Box::from_raw(work as *mut Work) };
+        work()
+    }
+
+    /// start a web worker which runs `work`, the returned future finishes when the worker is done
+    fn spawn(
+        work: impl FnOnce() + Send + 'static,
+    ) -> Result<impl Future<Output = Result<(), JsValue>>, JsValue> {
+        let parts = js_sys::Array::of1(&WORKER_SCRIPT.into());
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type("text/javascript");
+        let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)?;
+        let script = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+        let options = web_sys::WorkerOptions::new();
+        options.set_type(web_sys::WorkerType::Module);
+        let worker = web_sys::Worker::new_with_options(&script, &options)?;
+
+        let finished = js_sys::Promise::new(&mut |resolve, reject| {
+            worker.set_onmessage(Some(&resolve));
+            worker.set_onerror(Some(&reject));
+        });
+
+        let work: Box<Work> = Box::new(Box::new(work));
+        let message = js_sys::Array::of4(
+            &BINDINGS_URL.with(Clone::clone).into(),
+            &wasm_bindgen::module(),
+            &wasm_bindgen::memory(),
+            &(Box::into_raw(work) as usize).into(),
+        );
+        worker.post_message(&message)?;
+
+        Ok(async move {
+            let result = JsFuture::from(finished).await;
+            worker.terminate();
+            web_sys::Url::revoke_object_url(&script)?;
+            result.map(drop)
+        })
+    }
+
+    /// give the browser a chance to run other tasks, like starting a worker
+    async fn yield_to_browser() {
+        let timeout = js_sys::Promise::new(&mut |resolve, _| {
+            web_sys::window()
+                .unwrap()
+                .set_timeout_with_callback(&resolve)
+                .unwrap();
+        });
+        JsFuture::from(timeout).await.unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn reader_in_worker() {
+        let mut writer = Writer::new(Owned::<HazardStrategy<SpinWait>, _>::from_buffers(
+            [0u64; 4], [0u64; 4],
+        ));
+        let mut reader = writer.reader();
+        let started = Arc::new(AtomicBool::new(false));
+
+        let read = spawn({
+            let started = started.clone();
+            move || {
+                started.store(true, Ordering::Relaxed);
+                let mut last = 0;
+                while last != LAST {
+                    let [a, b, c, d] = *reader.get();
+                    assert!(a == b && b == c && c == d, "torn read");
+                    assert!(a >= last, "went back from {last} to {a}");
+                    last = a;
+                }
+            }
+        })
+        .unwrap();
+
+        // the swaps only wait for the worker to release its guards, never for it to start,
+        // but the main thread has to yield for the worker to start
+        let mut value = 0;
+        let mut remaining = 1000;
+        while value != LAST {
+            value = if remaining == 0 { LAST } else { value + 1 };
+            *writer.split_mut().writer = [value; 4];
+            writer.swap_buffers();
+
+            if started.load(Ordering::Relaxed) {
+                remaining -= 1;
+            } else {
+                yield_to_browser().await;
+            }
+        }
+
+        read.await.unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    fn hazard_strategy_is_send() {
+        let writer = Writer::new(Owned::<HazardStrategy<SpinWait>, _>::from_buffers(0, 0));
+        super::assert_send(&writer);
+        super::assert_send(&writer.reader());
+    }
+}
@@ -1,6 +1,6 @@
-use dbuf::interface::DefaultOwned;
+use dbuf::interface::{DefaultOwned, Strategy};
 
-type DefaultStrategy = dbuf::strategy::HazardStrategy;
+type DefaultStrategy = dbuf::profiles::ReadMostly;
 
 pub struct PixelBuf<
     D: Dim,
@@ -12,6 +12,80 @@ pub struct PixelBuf<
         >>::StrongRefWithWeak,
     >,
     dim: D,
+    pending: PendingOps,
+    frame_index: u64,
+}
+
+const MAX_PENDING_OPS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FillOp {
+    Clear([u8; 4]),
+    FillRect {
+        x: u32,
+        y: u32,
+        w: u32,
+        h: u32,
+        rgba: [u8; 4],
+    },
+}
+
+/// the fill operations which need to be replayed onto the other buffer after the next publish
+#[derive(Debug, Clone, Copy)]
+struct PendingOps {
+    ops: [Option<FillOp>; MAX_PENDING_OPS],
+    len: usize,
+    /// too many ops were recorded, so copy the entire published buffer instead
+    overflowed: bool,
+}
+
+impl PendingOps {
+    const EMPTY: Self = Self {
+        ops: [None; MAX_PENDING_OPS],
+        len: 0,
+        overflowed: false,
+    };
+
+    fn push(&mut self, op: FillOp) {
+        if let FillOp::Clear(_) = op {
+            // a clear overwrites everything before it
+            *self = Self::EMPTY;
+        }
+
+        if self.len == MAX_PENDING_OPS {
+            self.overflowed = true;
+        } else {
+            self.ops[self.len] = Some(op);
+            self.len += 1;
+        }
+    }
+
+    fn ops(&self) -> impl Iterator<Item = FillOp> + '_ {
+        self.ops[..self.len].iter().flatten().copied()
+    }
+}
+
+impl FillOp {
+    fn apply<D: Dim>(self, dim: D, buf: &mut [u8]) {
+        match self {
+            FillOp::Clear(rgba) => {
+                for pixel in buf.chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&rgba)
+                }
+            }
+            FillOp::FillRect { x, y, w, h, rgba } => {
+                let x_end = x.saturating_add(w).min(dim.width());
+                let y_end = y.saturating_add(h).min(dim.height());
+
+                for px in x..x_end {
+                    for py in y..y_end {
+                        let index = dim.index_of(px, py);
+                        buf[index * 4..][..4].copy_from_slice(&rgba)
+                    }
+                }
+            }
+        }
+    }
 }
 
 pub unsafe trait Dim: Copy {
@@ -107,12 +181,12 @@ unsafe impl Dim for Dynamic {
 pub fn const_sized<const WIDTH: usize, const HEIGHT: usize>() -> PixelBuf<Const<WIDTH, HEIGHT>> {
     PixelBuf {
         dim: Const,
-        buf: dbuf::raw::Writer::new(dbuf::ptrs::alloc::OwnedWithWeak::new(
-            dbuf::raw::Shared::from_raw_parts(
-                DefaultStrategy::default(),
-                dbuf::raw::RawDBuf::new(Const.zeroed(), Const.zeroed()),
-            ),
-        )),
+        buf: dbuf::raw::Writer::new(
+            DefaultStrategy::default()
+                .build_with_weak(dbuf::raw::RawDBuf::new(Const.zeroed(), Const.zeroed())),
+        ),
+        pending: PendingOps::EMPTY,
+        frame_index: 0,
     }
 }
 
@@ -123,9 +197,93 @@ impl<D: Dim, S: DefaultOwned<dbuf::raw::RawDBuf<<D as Dim>::ByteBuf>>> PixelBuf<
                 strategy.build_with_weak(dbuf::raw::RawDBuf::new(dim.zeroed(), dim.zeroed())),
             ),
             dim,
+            pending: PendingOps::EMPTY,
+            frame_index: 0,
         }
     }
 
+    pub fn reader(&self) -> dbuf::raw::Reader<S::WeakRef> {
+        self.buf.reader()
+    }
+
+    /// the number of times this buffer was published
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// publish the write buffer, and then replay the recorded
+    /// clear and fill operations onto the new write buffer
+    pub fn try_publish(&mut self) -> Result<(), S::ValidationError> {
+        self.buf.try_swap_buffers()?;
+        self.frame_index += 1;
+
+        let pending = core::mem::replace(&mut self.pending, PendingOps::EMPTY);
+        let split = self.buf.split_mut();
+        let writer = split.writer.as_mut();
+
+        if pending.overflowed {
+            writer.copy_from_slice(split.reader.as_ref());
+        } else {
+            for op in pending.ops() {
+                op.apply(self.dim, writer);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn publish(&mut self)
+    where
+        S: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        match self.try_publish() {
+            Ok(()) => (),
+            Err(inf) => match inf {},
+        }
+    }
+
+    /// true if a publish now may have to wait for readers to finish reading the last frame
+    ///
+    /// this is advisory, a reader may start reading right after this returns false
+    pub fn would_publish_block(&self) -> bool {
+        self.buf.would_swap_block()
+    }
+
+    /// publish the write buffer, unless a reader is still reading the last frame
+    ///
+    /// returns false if the frame was skipped, then the write buffer is kept as is,
+    /// so the next frame can draw on top of it and publish both
+    pub fn publish_or_skip(&mut self) -> bool
+    where
+        S: Strategy<ValidationError = core::convert::Infallible>,
+    {
+        if self.would_publish_block() {
+            return false;
+        }
+
+        self.publish();
+        true
+    }
+
+    /// fill the write buffer with the given color
+    ///
+    /// this will also be applied to the other buffer after the next publish
+    pub fn clear(&mut self, rgba: [u8; 4]) {
+        self.fill(FillOp::Clear(rgba))
+    }
+
+    /// fill the given rectangle of the write buffer with the given color
+    ///
+    /// this will also be applied to the other buffer after the next publish
+    pub fn fill_rect(&mut self, x: u32, y: u32, w: u32, h: u32, rgba: [u8; 4]) {
+        self.fill(FillOp::FillRect { x, y, w, h, rgba })
+    }
+
+    fn fill(&mut self, op: FillOp) {
+        op.apply(self.dim, self.buf.split_mut().writer.as_mut());
+        self.pending.push(op);
+    }
+
     pub fn read_buf(&self) -> &[u8] {
         self.buf.split().reader.as_ref()
     }
@@ -159,3 +317,168 @@ impl<D: Dim, S: DefaultOwned<dbuf::raw::RawDBuf<<D as Dim>::ByteBuf>>> PixelBuf<
         pixel.try_into().unwrap()
     }
 }
+
+#[test]
+fn test_clear_replay() {
+    let mut buf = PixelBuf::from_raw_parts(
+        Dynamic {
+            width: 4,
+            height: 3,
+        },
+        DefaultStrategy::default(),
+    );
+
+    buf.clear([1; 4]);
+    buf.clear([2; 4]);
+    assert_eq!(buf.pending.len, 1);
+    buf.fill_rect(1, 1, 10, 1, [3; 4]);
+    buf.publish();
+
+    assert_eq!(buf.frame_index(), 1);
+    assert_eq!(buf.read_buf(), buf.write_buf());
+    assert_eq!(buf.get(0, 0), [2; 4]);
+    assert_eq!(buf.get(3, 1), [3; 4]);
+    assert_eq!(buf.get(3, 2), [2; 4]);
+
+    for i in 0..MAX_PENDING_OPS as u32 + 1 {
+        buf.fill_rect(i % 4, 0, 1, 1, [i as u8; 4]);
+    }
+    buf.publish();
+    assert_eq!(buf.read_buf(), buf.write_buf());
+}
+
+#[test]
+fn test_no_mixed_frames() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let mut buf = PixelBuf::from_raw_parts(
+        Dynamic {
+            width: 16,
+            height: 16,
+        },
+        DefaultStrategy::default(),
+    );
+    let mut reader = buf.reader();
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(Ordering::Acquire) {
+                let frame = reader.checked_get().unwrap();
+                let frame = frame.as_slice();
+                assert!(frame.chunks_exact(4).all(|pixel| pixel == &frame[..4]));
+            }
+        });
+
+        for i in 0..100u64 {
+            buf.clear(if i % 2 == 0 {
+                [0xff, 0, 0, 0xff]
+            } else {
+                [0, 0, 0xff, 0xff]
+            });
+            buf.publish();
+            assert_eq!(buf.frame_index(), i + 1);
+            // the clear was replayed onto the other buffer
+            assert_eq!(buf.read_buf(), buf.write_buf());
+        }
+
+        done.store(true, Ordering::Release);
+    });
+}
+
+#[test]
+fn test_profiles() {
+    use dbuf::profiles;
+
+    fn check<S>(strategy: S)
+    where
+        S: DefaultOwned<dbuf::raw::RawDBuf<Vec<u8>>>
+            + Strategy<ValidationError = core::convert::Infallible>,
+    {
+        let mut buf = PixelBuf::from_raw_parts(
+            Dynamic {
+                width: 2,
+                height: 2,
+            },
+            strategy,
+        );
+        buf.clear([1; 4]);
+        buf.publish();
+        assert_eq!(buf.read_buf(), buf.write_buf());
+    }
+
+    check(profiles::read_mostly::new());
+    check(profiles::low_latency_writer::new());
+    check(profiles::single_thread::new());
+    check(profiles::precise::new());
+    check(profiles::balanced::new());
+}
+
+#[test]
+fn test_publish_or_skip() {
+    let mut buf = PixelBuf::from_raw_parts(
+        Dynamic {
+            width: 2,
+            height: 2,
+        },
+        DefaultStrategy::default(),
+    );
+    let mut reader = buf.reader();
+    assert!(!buf.would_publish_block());
+
+    buf.clear([1; 4]);
+    let frame = reader.checked_get().unwrap();
+    assert!(buf.would_publish_block());
+    // publishing here would wait for `frame` forever
+    assert!(!buf.publish_or_skip());
+    assert_eq!(buf.frame_index(), 0);
+
+    // the skipped frame is published with the next one
+    drop(frame);
+    buf.fill_rect(0, 0, 1, 1, [2; 4]);
+    assert!(buf.publish_or_skip());
+    assert_eq!(buf.frame_index(), 1);
+    assert_eq!(buf.get(0, 0), [2; 4]);
+    assert_eq!(buf.get(1, 1), [1; 4]);
+    assert_eq!(buf.read_buf(), buf.write_buf());
+}
+
+#[test]
+fn test_publish_or_skip_with_busy_reader() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let mut buf = PixelBuf::from_raw_parts(
+        Dynamic {
+            width: 16,
+            height: 16,
+        },
+        DefaultStrategy::default(),
+    );
+    let mut reader = buf.reader();
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(Ordering::Acquire) {
+                let frame = reader.checked_get().unwrap();
+                let frame = frame.as_slice();
+                assert!(frame.chunks_exact(4).all(|pixel| pixel == &frame[..4]));
+            }
+        });
+
+        let mut published = 0;
+        for i in 0..1000u32 {
+            buf.clear([i as u8; 4]);
+            if buf.publish_or_skip() {
+                published += 1;
+            }
+        }
+        assert_eq!(buf.frame_index(), published);
+
+        done.store(true, Ordering::Release);
+    });
+
+    // the last frame is published once the reader is gone
+    assert!(buf.publish_or_skip());
+    assert_eq!(buf.read_buf(), buf.write_buf());
+}